@@ -0,0 +1,36 @@
+//! Draw a cube, and save a snapshot of it to cube.png.
+//!
+//! Run it with `cargo run --example cube`, with $KITTYCAD_API_TOKEN set.
+
+use color_eyre::{eyre::eyre, Result};
+use kittycad::types::Point3D;
+use kittycad_modeling_api_examples::{
+    camera, connect, shapes,
+    snapshot::{self, Output},
+};
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<()> {
+    color_eyre::install()?;
+    // Open a WebSocket to KittyCAD's modeling API, using $KITTYCAD_API_TOKEN.
+    let mut session = connect().await?;
+
+    // Draw a cube around the origin. Each of its sides is 20mm long.
+    let center = Point3D {
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+    };
+    let cube_id = shapes::draw_cube(&mut session, &center, 10.0).await?;
+
+    // Point the camera at the cube, from far enough away to see all of it.
+    let bbox = session
+        .bounding_box(&[cube_id])
+        .ok_or_else(|| eyre!("Can't tell where the cube is"))?;
+    camera::zoom_to_fit(&mut session, &bbox).await?;
+
+    // Take a snapshot, and save it.
+    snapshot::export_png(&mut session, &Output::new("cube.png")).await?;
+    println!("Saved cube.png");
+    Ok(())
+}
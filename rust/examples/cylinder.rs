@@ -0,0 +1,35 @@
+//! Draw a cylinder, by sketching a circle and extruding it, and save a snapshot of it to cylinder.png.
+//!
+//! Run it with `cargo run --example cylinder`, with $KITTYCAD_API_TOKEN set.
+
+use color_eyre::{eyre::eyre, Result};
+use kittycad::types::Point3D;
+use kittycad_modeling_api_examples::{
+    camera, connect, shapes,
+    snapshot::{self, Output},
+};
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<()> {
+    color_eyre::install()?;
+    let mut session = connect().await?;
+
+    // Sketch a circle with a radius of 10mm, flat on the ground.
+    let center = Point3D {
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+    };
+    let circle_id = shapes::sketch_circle(&mut session, &center, 10.0).await?;
+
+    // Extrude it 30mm upwards. The cylinder keeps the circle's ID.
+    shapes::extrude(&mut session, circle_id, 30.0).await?;
+
+    let bbox = session
+        .bounding_box(&[circle_id])
+        .ok_or_else(|| eyre!("Can't tell where the cylinder is"))?;
+    camera::zoom_to_fit(&mut session, &bbox).await?;
+    snapshot::export_png(&mut session, &Output::new("cylinder.png")).await?;
+    println!("Saved cylinder.png");
+    Ok(())
+}
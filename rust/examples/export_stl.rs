@@ -0,0 +1,24 @@
+//! Draw an L-shaped bracket, and export it as an STL file, bracket.stl, for 3D printing.
+//!
+//! Run it with `cargo run --example export_stl`, with $KITTYCAD_API_TOKEN set.
+
+use color_eyre::Result;
+use kittycad_modeling_api_examples::{connect, export, shapes};
+use std::path::Path;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<()> {
+    color_eyre::install()?;
+    let mut session = connect().await?;
+
+    // Legs 40mm long and 5mm thick, extruded 20mm.
+    let bracket_id = shapes::draw_l_bracket(&mut session, 40.0, 5.0, 20.0).await?;
+
+    // Ask the API to export the bracket. It sends the file back over the WebSocket,
+    // and it's saved here. The STL is then read back, to check it's a proper mesh.
+    let path = Path::new("bracket.stl");
+    let compress = false;
+    let verify = true;
+    export::export(&mut session, &[bracket_id], path, "stl", compress, verify).await?;
+    Ok(())
+}
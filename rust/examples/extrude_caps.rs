@@ -0,0 +1,72 @@
+//! Extrude the same hexagon twice: once with caps on its ends, making a solid, and once without,
+//! leaving an open tube. Saves a snapshot of each, extrude-capped.png and extrude-open.png, and
+//! exports each as an STL, to show that only the capped one is a closed mesh.
+//!
+//! Uncapped extrusions are the start of shapes which are hollowed out or lofted later. On their own
+//! they have no inside, so they have no volume, and can't be 3D printed.
+//!
+//! Run it with `cargo run --example extrude_caps`, with $KITTYCAD_API_TOKEN set.
+
+use color_eyre::{eyre::eyre, Result};
+use kittycad::types::Point3D;
+use kittycad_modeling_api_examples::{
+    camera, connect, export, shapes,
+    snapshot::{self, Output},
+    transform,
+};
+use std::{f64::consts::PI, path::Path};
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<()> {
+    color_eyre::install()?;
+    let mut session = connect().await?;
+
+    for (name, cap) in [("capped", true), ("open", false)] {
+        // A hexagon with its corners 10mm from the middle, flat on the ground.
+        let corners: Vec<_> = (0..6)
+            .map(|i| {
+                let (sin, cos) = (PI / 3.0 * i as f64).sin_cos();
+                Point3D {
+                    x: 10.0 * cos,
+                    y: 10.0 * sin,
+                    z: 0.0,
+                }
+            })
+            .collect();
+        let hexagon_id = shapes::sketch_polygon(&mut session, &corners).await?;
+
+        // Extrude it 20mm upwards. The camera looks down on it from above, so the open one
+        // shows its inside, where the top cap would be.
+        shapes::extrude_with_cap(&mut session, hexagon_id, 20.0, cap).await?;
+
+        let bbox = session
+            .bounding_box(&[hexagon_id])
+            .ok_or_else(|| eyre!("Can't tell where the hexagon is"))?;
+        camera::zoom_to_fit(&mut session, &bbox).await?;
+        let png = format!("extrude-{name}.png");
+        snapshot::export_png(&mut session, &Output::new(&png)).await?;
+        println!("Saved {png}");
+
+        // The export is read back, which counts its edges. The open tube's top and bottom edges
+        // each have only one face on them, so its mesh isn't closed.
+        let stl = format!("extrude-{name}.stl");
+        let (compress, verify) = (false, false);
+        let mesh = export::export(
+            &mut session,
+            &[hexagon_id],
+            Path::new(&stl),
+            "stl",
+            compress,
+            verify,
+        )
+        .await?;
+        if let Some(mesh) = mesh {
+            let closed = if mesh.closed { "closed" } else { "not closed" };
+            println!("{stl} is {closed}, with {} open edges", mesh.open_edges);
+        }
+
+        // Clear the scene for the next one.
+        transform::remove_objects(&mut session, &[hexagon_id]).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,70 @@
+//! Extrude a square into a block, sort its faces into the top, the bottom and the sides,
+//! then sketch a circle on the top and extrude it into a peg. Saves a snapshot to extrusion_faces.png.
+//!
+//! Run it with `cargo run --example extrusion_faces`, with $KITTYCAD_API_TOKEN set.
+
+use color_eyre::{
+    eyre::{bail, eyre},
+    Result,
+};
+use kittycad::types::Point3D;
+use kittycad_modeling_api_examples::{
+    camera, connect, shapes, sketch,
+    snapshot::{self, Output},
+};
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<()> {
+    color_eyre::install()?;
+    // Open a WebSocket to KittyCAD's modeling API, using $KITTYCAD_API_TOKEN.
+    let mut session = connect().await?;
+
+    // Extrude a 40mm square, sketched at z = 0, 10mm up.
+    let corners = [(-20.0, -20.0), (20.0, -20.0), (20.0, 20.0), (-20.0, 20.0)]
+        .map(|(x, y)| Point3D { x, y, z: 0.0 });
+    let block_id = shapes::extrude_polygon(&mut session, &corners, 10.0).await?;
+
+    // Sort the block's faces by where they are.
+    let faces = sketch::extrusion_faces(&mut session, block_id).await?;
+    for (name, face) in [("Top", &faces.top), ("Bottom", &faces.bottom)] {
+        match face {
+            Some(face) => println!("{name}: {}, at height {}", face.id, face.bbox.min.z),
+            None => println!("{name}: not found"),
+        }
+    }
+    for side in &faces.sides {
+        println!("Side: {}", side.id);
+    }
+    for (face, why) in &faces.unexpected {
+        println!("Unexpected: {}, because {why}", face.id);
+    }
+    // A block has a top, a bottom and four sides. If that's not what was found, don't guess which is which.
+    let (Some(top), Some(_)) = (&faces.top, &faces.bottom) else {
+        bail!("Could not find both of the block's caps");
+    };
+    if faces.sides.len() != 4 || !faces.unexpected.is_empty() {
+        bail!(
+            "Expected the block to have 4 sides and nothing else, but it has {} sides and {} other faces",
+            faces.sides.len(),
+            faces.unexpected.len()
+        );
+    }
+
+    // Sketch a circle in the middle of the top, and extrude it into a peg.
+    let saved_camera = camera::save(&session);
+    let plane = sketch::plane_on_face(&mut session, top).await?;
+    let mut peg = sketch::start(&mut session, &plane).await?;
+    let peg_id = peg.circle((0.0, 0.0), 8.0).await?;
+    peg.finish().await?;
+    camera::restore(&mut session, &saved_camera).await?;
+    shapes::extrude(&mut session, peg_id, 10.0).await?;
+
+    // Point the camera at the block and the peg, and take a snapshot.
+    let bbox = session
+        .bounding_box(&[block_id, peg_id])
+        .ok_or_else(|| eyre!("Can't tell where the block is"))?;
+    camera::zoom_to_fit(&mut session, &bbox).await?;
+    snapshot::export_png(&mut session, &Output::new("extrusion_faces.png")).await?;
+    println!("Saved extrusion_faces.png");
+    Ok(())
+}
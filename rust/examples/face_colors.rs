@@ -0,0 +1,63 @@
+//! Draw a cube, paint each of its faces the colour of the way it points, and save snapshots of it
+//! from two opposite corners, so every face shows: face_colors-front.png and face_colors-back.png.
+//! +X is red, +Y green and +Z blue; -X is cyan, -Y magenta and -Z yellow.
+//! Handy for checking which way a model is facing.
+//!
+//! Run it with `cargo run --example face_colors`, with $KITTYCAD_API_TOKEN set.
+
+use color_eyre::{eyre::eyre, Result};
+use kittycad::types::Point3D;
+use kittycad_modeling_api_examples::{
+    camera::{self, CameraPreset, View},
+    connect, facecolor, shapes,
+    snapshot::{self, Output},
+};
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<()> {
+    color_eyre::install()?;
+    // Open a WebSocket to KittyCAD's modeling API, using $KITTYCAD_API_TOKEN.
+    let mut session = connect().await?;
+
+    // Draw a cube around the origin. Each of its sides is 20mm long.
+    let center = Point3D {
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+    };
+    let cube_id = shapes::draw_cube(&mut session, &center, 10.0).await?;
+
+    // Work out which way each face points, and paint it.
+    let faces = facecolor::paint_faces(&mut session, cube_id).await?;
+    for painted in &faces {
+        match painted.direction {
+            Some(direction) => println!("{direction}: {}", painted.face.id),
+            None => println!("Unpainted: {}", painted.face.id),
+        }
+    }
+
+    // The usual view shows the -X, -Y and +Z faces, so look from the opposite corner too.
+    let bbox = session
+        .bounding_box(&[cube_id])
+        .ok_or_else(|| eyre!("Can't tell where the cube is"))?;
+    let opposite = CameraPreset {
+        eye: Point3D {
+            x: 1.0,
+            y: 1.0,
+            z: -1.0,
+        },
+        up: Point3D {
+            x: 0.0,
+            y: 0.0,
+            z: 1.0,
+        },
+    };
+    for (name, preset) in [("front", View::Iso.preset()), ("back", opposite)] {
+        session.set_camera_preset(preset);
+        camera::zoom_to_fit(&mut session, &bbox).await?;
+        let path = format!("face_colors-{name}.png");
+        snapshot::export_png(&mut session, &Output::new(&path)).await?;
+        println!("Saved {path}");
+    }
+    Ok(())
+}
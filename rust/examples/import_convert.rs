@@ -0,0 +1,27 @@
+//! Convert a model file to another format, by importing it into the API and exporting it again.
+//! The format to convert to is taken from the output's extension.
+//!
+//! Run it with `cargo run --example import_convert -- model.obj model.stl`,
+//! with $KITTYCAD_API_TOKEN set.
+
+use color_eyre::{eyre::bail, Result};
+use kittycad_modeling_api_examples::{connect, export, import};
+use std::path::PathBuf;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<()> {
+    color_eyre::install()?;
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let [input, out] = args.as_slice() else {
+        bail!("Usage: import_convert INPUT OUTPUT, e.g. import_convert model.obj model.stl");
+    };
+    let (input, out) = (PathBuf::from(input), PathBuf::from(out));
+    // Check the format before connecting, so a typo fails fast.
+    let format = export::extension(&out);
+    export::format_for(&out, &format)?;
+
+    let mut session = connect().await?;
+    // This imports the file (and any files it needs, like an OBJ's materials),
+    // then exports the imported object in the new format.
+    import::convert(&mut session, &input, &out, &format).await
+}
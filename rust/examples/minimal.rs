@@ -0,0 +1,110 @@
+//! The smallest complete use of KittyCAD's modeling API: draw a cube, and save a snapshot of it to
+//! minimal.png. Read this one first. It's all here, top to bottom, without the helpers the other
+//! examples use, so you can see every command that's sent.
+//!
+//! Run it with `cargo run --example minimal`, with $KITTYCAD_API_TOKEN set.
+//!
+//! It's kept deliberately small: `tests/minimal_example.rs` fails if it grows too long, or uses
+//! more than a few crates. Show off new features in the other examples instead.
+
+use color_eyre::{
+    eyre::{bail, Context},
+    Result,
+};
+use kittycad::types::{ImageFormat, ModelingCmd, OkModelingCmdResponse, PathSegment, Point3D};
+use kittycad_modeling_api_examples::session::{
+    ConnectionProfile, ModelingSession, DEFAULT_MAX_MESSAGE_BYTES,
+};
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<()> {
+    color_eyre::install()?;
+
+    // Open a WebSocket to the modeling API. Everything below is sent over it, as JSON.
+    // (The tests set $KITTYCAD_OFFLINE, to play back a recording of the API instead, like --offline.)
+    let mut session = match std::env::var_os("KITTYCAD_OFFLINE") {
+        Some(recording) => ModelingSession::replay(
+            recording.as_ref(),
+            DEFAULT_MAX_MESSAGE_BYTES,
+            ConnectionProfile::default(),
+        )?,
+        None => {
+            // Make an API client, with a token from https://zoo.dev/account/api-tokens.
+            let token =
+                std::env::var("KITTYCAD_API_TOKEN").context("You must set $KITTYCAD_API_TOKEN")?;
+            let client = kittycad::Client::new(token);
+            ModelingSession::connect(
+                &client,
+                DEFAULT_MAX_MESSAGE_BYTES,
+                ConnectionProfile::default(),
+            )
+            .await?
+        }
+    };
+
+    // The cube's bottom face is a square, 20mm across, centered under the origin.
+    let width = 10.0;
+    let corner = |x: f64, y: f64| Point3D {
+        x: x * width,
+        y: y * width,
+        z: -width,
+    };
+
+    // Start a new, empty path. Its ID is how later commands refer to it.
+    let path_id = session.send(ModelingCmd::StartPath {}).await?;
+
+    // Put the pen down at the square's first corner...
+    session
+        .send(ModelingCmd::MovePathPen {
+            path: path_id,
+            to: corner(-1.0, -1.0),
+        })
+        .await?;
+
+    // ...then draw a line to each of the other corners, and back to the first.
+    for end in [
+        corner(1.0, -1.0),
+        corner(1.0, 1.0),
+        corner(-1.0, 1.0),
+        corner(-1.0, -1.0),
+    ] {
+        session
+            .send(ModelingCmd::ExtendPath {
+                path: path_id,
+                segment: PathSegment::Line {
+                    end,
+                    relative: false,
+                },
+            })
+            .await?;
+    }
+
+    // Close the square, so it has an inside, and pull it up into a cube.
+    session.send(ModelingCmd::ClosePath { path_id }).await?;
+    session
+        .send(ModelingCmd::Extrude {
+            target: path_id,
+            distance: width * 2.0,
+            cap: true,
+        })
+        .await?;
+
+    // Ask for a picture of the scene. Commands are sent without waiting for their responses,
+    // so this is the first one we wait for: the API answers them in order, so once the snapshot
+    // arrives, the cube has been drawn (or we'd have heard why it wasn't).
+    let snapshot_id = session
+        .send(ModelingCmd::TakeSnapshot {
+            format: ImageFormat::Png,
+        })
+        .await?;
+    let OkModelingCmdResponse::TakeSnapshot { data } = session.await_response(snapshot_id).await?
+    else {
+        bail!("Expected the API to respond to the snapshot with an image");
+    };
+
+    // The image arrives as base64 in the JSON, which the kittycad crate has already decoded.
+    let png: Vec<u8> = data.contents.into();
+    std::fs::write("minimal.png", png).context("Could not save minimal.png")?;
+    println!("Saved minimal.png");
+    Ok(())
+}
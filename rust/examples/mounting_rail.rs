@@ -0,0 +1,50 @@
+//! Sketch a mounting rail on the XY plane: a long plate with a slot at each end, for screws which
+//! can slide along it, and a square window in the middle, turned 45 degrees. The slots and the
+//! window are cut out of the plate's outline before it's extruded, so they go all the way through.
+//! Saves a snapshot to mounting_rail.png.
+//!
+//! Run it with `cargo run --example mounting_rail`, with $KITTYCAD_API_TOKEN set.
+
+use color_eyre::{eyre::eyre, Result};
+use kittycad_modeling_api_examples::{
+    camera, connect, shapes,
+    sketch::{self, StandardPlane},
+    snapshot::{self, Output},
+};
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<()> {
+    color_eyre::install()?;
+    let mut session = connect().await?;
+
+    // The rail is 120mm long and 24mm wide. Its slots are 6mm wide, for M5 screws, and can move
+    // them 20mm along the rail.
+    let (length, width, thickness) = (120.0, 24.0, 4.0);
+    let plane = sketch::make_standard_plane(&mut session, StandardPlane::Xy, length).await?;
+    let mut sketch = sketch::start(&mut session, &plane).await?;
+    let rail_id = sketch
+        .rect_corners((-length / 2.0, -width / 2.0), (length / 2.0, width / 2.0))
+        .await?;
+    let mut cutouts = Vec::new();
+    for side in [-1.0, 1.0] {
+        let (inner, outer) = (side * 30.0, side * 50.0);
+        cutouts.push(sketch.slot((inner, 0.0), (outer, 0.0), 6.0).await?);
+    }
+    cutouts.push(sketch.rect_center((0.0, 0.0), 10.0, 10.0, 45.0).await?);
+    sketch.finish().await?;
+
+    // The API doesn't have 3D booleans (as of this version of the kittycad crate), so rather than
+    // subtracting the cutouts from a solid rail, they're cut out of its outline.
+    for cutout_id in cutouts {
+        shapes::add_hole(&mut session, rail_id, cutout_id).await?;
+    }
+    shapes::extrude(&mut session, rail_id, thickness).await?;
+
+    let bbox = session
+        .bounding_box(&[rail_id])
+        .ok_or_else(|| eyre!("Can't tell where the rail is"))?;
+    camera::zoom_to_fit(&mut session, &bbox).await?;
+    snapshot::export_png(&mut session, &Output::new("mounting_rail.png")).await?;
+    println!("Saved mounting_rail.png");
+    Ok(())
+}
@@ -0,0 +1,43 @@
+//! Draw a cube, and save snapshots of it from several directions: view-iso.png, view-top.png,
+//! view-front.png and view-right.png.
+//!
+//! Run it with `cargo run --example multi_view`, with $KITTYCAD_API_TOKEN set.
+
+use color_eyre::{eyre::eyre, Result};
+use kittycad::types::Point3D;
+use kittycad_modeling_api_examples::{
+    camera::{self, View},
+    connect, shapes,
+    snapshot::{self, Output},
+};
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<()> {
+    color_eyre::install()?;
+    let mut session = connect().await?;
+
+    let center = Point3D {
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+    };
+    let cube_id = shapes::draw_cube(&mut session, &center, 10.0).await?;
+    let bbox = session
+        .bounding_box(&[cube_id])
+        .ok_or_else(|| eyre!("Can't tell where the cube is"))?;
+
+    // The model only has to be drawn once. Each snapshot just moves the camera.
+    for (name, view) in [
+        ("iso", View::Iso),
+        ("top", View::Top),
+        ("front", View::Front),
+        ("right", View::Right),
+    ] {
+        session.set_camera_preset(view.preset());
+        camera::zoom_to_fit(&mut session, &bbox).await?;
+        let path = format!("view-{name}.png");
+        snapshot::export_png(&mut session, &Output::new(&path)).await?;
+        println!("Saved {path}");
+    }
+    Ok(())
+}
@@ -0,0 +1,40 @@
+//! Build a wall along an open path, like a room with a doorway in one side,
+//! by stroking the path rather than filling it. Saves a snapshot to wall.png.
+//!
+//! Run it with `cargo run --example wall`, with $KITTYCAD_API_TOKEN set.
+
+use color_eyre::{eyre::eyre, Result};
+use kittycad::types::Point3D;
+use kittycad_modeling_api_examples::{
+    camera, connect, shapes,
+    snapshot::{self, Output},
+};
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<()> {
+    color_eyre::install()?;
+    // Open a WebSocket to KittyCAD's modeling API, using $KITTYCAD_API_TOKEN.
+    let mut session = connect().await?;
+
+    // The middle of the wall, going round a 40mm by 30mm room and stopping short to leave a doorway.
+    let path = [
+        (0.0, 12.0),
+        (0.0, 0.0),
+        (40.0, 0.0),
+        (40.0, 30.0),
+        (0.0, 30.0),
+        (0.0, 18.0),
+    ]
+    .map(|(x, y)| Point3D { x, y, z: 0.0 });
+    // 2mm thick and 10mm high. The corners are square, so none are too sharp to miter.
+    let wall_id = shapes::stroke_path(&mut session, &path, 2.0, 10.0, 4.0).await?;
+
+    // Point the camera at the wall, and take a snapshot.
+    let bbox = session
+        .bounding_box(&[wall_id])
+        .ok_or_else(|| eyre!("Can't tell where the wall is"))?;
+    camera::zoom_to_fit(&mut session, &bbox).await?;
+    snapshot::export_png(&mut session, &Output::new("wall.png")).await?;
+    println!("Saved wall.png");
+    Ok(())
+}
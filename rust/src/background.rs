@@ -0,0 +1,173 @@
+use color_eyre::{eyre::bail, Report, Result};
+use image::{Rgba, RgbaImage};
+use std::str::FromStr;
+
+/// How far (per channel, out of 255) a pixel may be from the background's color and still count as background.
+/// The renderer shades the background very slightly, so this can't be zero.
+pub const TOLERANCE: u8 = 8;
+
+/// An opaque color, written as "#rrggbb", e.g. "#103050".
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl FromStr for Color {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let hex = s.trim().trim_start_matches('#');
+        if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            bail!("Color {s:?} should be 6 hex digits, like \"#103050\"");
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+        Ok(Self {
+            r: channel(0),
+            g: channel(2),
+            b: channel(4),
+        })
+    }
+}
+
+/// Repaint an image's background.
+///
+/// The API (as of this version of the kittycad crate) has no command to set the background color,
+/// so this paints over it after the snapshot is taken. The background is whatever's connected to the
+/// image's corners and is the same color as them, which works as long as the model doesn't touch a corner.
+pub fn repaint(img: &mut RgbaImage, color: Color) {
+    let (width, height) = img.dimensions();
+    if width == 0 || height == 0 {
+        return;
+    }
+    let new = Rgba([color.r, color.g, color.b, 255]);
+    let mut seen = vec![false; (width * height) as usize];
+    let corners = [
+        (0, 0),
+        (width - 1, 0),
+        (0, height - 1),
+        (width - 1, height - 1),
+    ];
+    for (x, y) in corners {
+        let old = *img.get_pixel(x, y);
+        // Flood fill from the corner, iteratively so big backgrounds don't overflow the stack.
+        let mut to_visit = vec![(x, y)];
+        while let Some((x, y)) = to_visit.pop() {
+            let i = (y * width + x) as usize;
+            if seen[i] || !similar(img.get_pixel(x, y), &old) {
+                continue;
+            }
+            seen[i] = true;
+            img.put_pixel(x, y, new);
+            if x > 0 {
+                to_visit.push((x - 1, y));
+            }
+            if x + 1 < width {
+                to_visit.push((x + 1, y));
+            }
+            if y > 0 {
+                to_visit.push((x, y - 1));
+            }
+            if y + 1 < height {
+                to_visit.push((x, y + 1));
+            }
+        }
+    }
+}
+
+/// How much of an image is the same color as its top-left corner, as a fraction of its pixels.
+/// A pixel counts if each of its channels is within `tolerance` (out of 255) of the corner's.
+/// The corner is background unless the model covers it, so this is near 1 for an empty scene.
+pub fn background_fraction(img: &RgbaImage, tolerance: u8) -> f64 {
+    let (width, height) = img.dimensions();
+    if width == 0 || height == 0 {
+        return 1.0;
+    }
+    let corner = *img.get_pixel(0, 0);
+    let background = img
+        .pixels()
+        .filter(|p| within(p, &corner, tolerance))
+        .count();
+    background as f64 / f64::from(width * height)
+}
+
+fn similar(a: &Rgba<u8>, b: &Rgba<u8>) -> bool {
+    within(a, b, TOLERANCE)
+}
+
+fn within(a: &Rgba<u8>, b: &Rgba<u8>, tolerance: u8) -> bool {
+    a.0.iter().zip(b.0).all(|(a, b)| a.abs_diff(b) <= tolerance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GREY: Rgba<u8> = Rgba([40, 44, 52, 255]);
+    const MODEL: Rgba<u8> = Rgba([200, 120, 60, 255]);
+
+    /// A 100 by 50 background, shaded a little from left to right as the renderer does, with the
+    /// model covering `model` of it.
+    fn snapshot(model: (u32, u32, u32, u32)) -> RgbaImage {
+        let (left, top, right, bottom) = model;
+        RgbaImage::from_fn(100, 50, |x, y| {
+            if (left..right).contains(&x) && (top..bottom).contains(&y) {
+                MODEL
+            } else {
+                let shade = (x / 20) as u8;
+                Rgba([GREY[0] + shade, GREY[1] + shade, GREY[2], 255])
+            }
+        })
+    }
+
+    #[test]
+    fn counts_a_shaded_background_as_all_background() {
+        assert_eq!(background_fraction(&snapshot((0, 0, 0, 0)), TOLERANCE), 1.0);
+        // Not if the shading's more than the tolerance.
+        assert!(background_fraction(&snapshot((0, 0, 0, 0)), 2) < 1.0);
+    }
+
+    #[test]
+    fn counts_the_model_as_not_background() {
+        // 40 by 25 pixels of 100 by 50.
+        let img = snapshot((30, 10, 70, 35));
+        assert_eq!(background_fraction(&img, TOLERANCE), 0.8);
+    }
+
+    #[test]
+    fn counts_an_empty_image_as_background() {
+        assert_eq!(background_fraction(&RgbaImage::new(0, 0), TOLERANCE), 1.0);
+    }
+
+    #[test]
+    fn repaints_only_the_background() {
+        let mut img = snapshot((30, 10, 70, 35));
+        let color = Color {
+            r: 255,
+            g: 255,
+            b: 255,
+        };
+        repaint(&mut img, color);
+        assert_eq!(*img.get_pixel(0, 0), Rgba([255, 255, 255, 255]));
+        assert_eq!(*img.get_pixel(99, 49), Rgba([255, 255, 255, 255]));
+        assert_eq!(*img.get_pixel(50, 20), MODEL);
+        assert_eq!(background_fraction(&img, 0), 0.8);
+    }
+
+    #[test]
+    fn parses_colors() {
+        assert_eq!(
+            "#103050".parse::<Color>().unwrap(),
+            Color {
+                r: 0x10,
+                g: 0x30,
+                b: 0x50
+            }
+        );
+        assert_eq!(
+            "#10305".parse::<Color>().unwrap_err().to_string(),
+            "Color \"#10305\" should be 6 hex digits, like \"#103050\""
+        );
+    }
+}
@@ -0,0 +1,163 @@
+use crate::session::ModelingSession;
+use color_eyre::Result;
+use kittycad::types::{ModelingCmd, PathSegment, Point3D};
+use std::time::{Duration, Instant};
+
+/// How the benchmark sends its commands.
+#[derive(Clone, Copy, Debug, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// Send one command, wait for its response, then send the next.
+    Sequential,
+    /// Send every command, then wait for all the responses.
+    Pipelined,
+    /// Like pipelined, but only flush the WebSocket once per group of commands.
+    Grouped,
+    /// Send every command in one batch message, then wait for all the responses.
+    Batched,
+}
+
+/// How the benchmark's results are printed.
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum Format {
+    Text,
+    Csv,
+    Json,
+}
+
+/// The timings from one benchmark run.
+#[derive(serde::Serialize)]
+pub struct Summary {
+    pub mode: Mode,
+    pub commands: usize,
+    pub total_ms: f64,
+    pub commands_per_sec: f64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+}
+
+/// Send `count` cheap commands (extending a scratch path with `count` lines around an ellipse), and time them.
+/// Each command's latency runs from when it was sent to when we read its response.
+/// When several commands are in flight, a response may wait a little before we read it,
+/// so pipelined and batched latencies are upper bounds.
+pub async fn run(session: &mut ModelingSession, count: usize, mode: Mode) -> Result<Summary> {
+    let path = session.new_id();
+    session
+        .send_with_id(ModelingCmd::StartPath {}, path)
+        .await?;
+    let point = |i: usize| {
+        let angle = std::f64::consts::TAU * i as f64 / count as f64;
+        Point3D {
+            x: 20.0 * angle.cos(),
+            y: 10.0 * angle.sin(),
+            z: 0.0,
+        }
+    };
+    let pen_id = session
+        .send(ModelingCmd::MovePathPen { path, to: point(0) })
+        .await?;
+    session.await_response(pen_id).await?;
+    let cmds: Vec<_> = (1..=count)
+        .map(|i| ModelingCmd::ExtendPath {
+            path,
+            segment: PathSegment::Line {
+                end: point(i),
+                relative: false,
+            },
+        })
+        .collect();
+
+    // Time with Instant, which (unlike SystemTime) never jumps if the system clock changes.
+    let start = Instant::now();
+    let mut latencies = Vec::with_capacity(count);
+    match mode {
+        Mode::Sequential => {
+            for cmd in cmds {
+                let sent = Instant::now();
+                let cmd_id = session.send(cmd).await?;
+                session.await_response(cmd_id).await?;
+                latencies.push(sent.elapsed());
+            }
+        }
+        Mode::Pipelined => {
+            let mut sent = Vec::with_capacity(count);
+            for cmd in cmds {
+                sent.push((Instant::now(), session.send(cmd).await?));
+            }
+            for (sent, cmd_id) in sent {
+                session.await_response(cmd_id).await?;
+                latencies.push(sent.elapsed());
+            }
+        }
+        Mode::Grouped => {
+            let sent = Instant::now();
+            for cmd_id in session.send_many(&cmds).await? {
+                session.await_response(cmd_id).await?;
+                latencies.push(sent.elapsed());
+            }
+        }
+        Mode::Batched => {
+            let sent = Instant::now();
+            for cmd_id in session.send_batch(cmds).await? {
+                session.await_response(cmd_id).await?;
+                latencies.push(sent.elapsed());
+            }
+        }
+    }
+    let total = start.elapsed();
+    latencies.sort();
+    Ok(Summary {
+        mode,
+        commands: count,
+        total_ms: ms(total),
+        commands_per_sec: count as f64 / total.as_secs_f64(),
+        p50_ms: ms(percentile(&latencies, 50.0)),
+        p95_ms: ms(percentile(&latencies, 95.0)),
+        p99_ms: ms(percentile(&latencies, 99.0)),
+    })
+}
+
+/// Print the summaries in the given format.
+pub fn print(summaries: &[Summary], format: Format) -> Result<()> {
+    match format {
+        Format::Text => {
+            for s in summaries {
+                println!(
+                    "{:?}: {} commands in {:.1} ms ({:.1}/s), latency p50 {:.2} ms, p95 {:.2} ms, p99 {:.2} ms",
+                    s.mode, s.commands, s.total_ms, s.commands_per_sec, s.p50_ms, s.p95_ms, s.p99_ms
+                );
+            }
+        }
+        Format::Csv => {
+            println!("mode,commands,total_ms,commands_per_sec,p50_ms,p95_ms,p99_ms");
+            for s in summaries {
+                println!(
+                    "{},{},{},{},{},{},{}",
+                    serde_json::to_value(s.mode)?.as_str().unwrap_or_default(),
+                    s.commands,
+                    s.total_ms,
+                    s.commands_per_sec,
+                    s.p50_ms,
+                    s.p95_ms,
+                    s.p99_ms
+                );
+            }
+        }
+        Format::Json => println!("{}", serde_json::to_string_pretty(summaries)?),
+    }
+    Ok(())
+}
+
+/// The latency which `p` percent of (sorted) latencies are no slower than.
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn ms(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
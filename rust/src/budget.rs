@@ -0,0 +1,141 @@
+use crate::warnings::{Code, Warning};
+use color_eyre::{
+    eyre::{bail, eyre},
+    Result,
+};
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// A step of a run which can be skipped, to finish within --time-budget.
+/// They're skipped in this order, as less and less of the budget is left.
+/// Everything else (building the model, and its snapshot) is required, so it's never skipped.
+#[derive(Clone, Copy, Debug)]
+pub enum Optional {
+    /// Snapshots from partway through a model, e.g. before it's transformed.
+    ExtraViews,
+    /// A turntable's frames after the first. The rest can be saved later with --resume-frames.
+    TurntableFrames,
+    /// The --export file.
+    Export,
+    /// Captioning a snapshot, and repainting its background.
+    TouchUp,
+}
+
+impl Optional {
+    /// What fraction of the budget must be left for the step to go ahead.
+    fn needs(self) -> f64 {
+        match self {
+            Self::ExtraViews => 0.5,
+            Self::TurntableFrames => 0.4,
+            Self::Export => 0.25,
+            Self::TouchUp => 0.1,
+        }
+    }
+}
+
+/// The run's --time-budget, and when it started.
+struct Budget {
+    total: Duration,
+    started: Instant,
+}
+
+static BUDGET: Mutex<Option<Budget>> = Mutex::new(None);
+
+/// Start the clock on the run's --time-budget.
+pub fn start(total: Duration) {
+    *BUDGET.lock().unwrap_or_else(|e| e.into_inner()) = Some(Budget {
+        total,
+        started: Instant::now(),
+    });
+}
+
+/// Whether there's enough of the budget left for an optional step. If there isn't, it's warned about,
+/// naming `what` was skipped. There always is if the run has no budget.
+pub fn allows(step: Optional, what: &str) -> Result<bool> {
+    let (total, left) = match &*BUDGET.lock().unwrap_or_else(|e| e.into_inner()) {
+        None => return Ok(true),
+        Some(budget) => (
+            budget.total,
+            budget.total.saturating_sub(budget.started.elapsed()),
+        ),
+    };
+    if left.as_secs_f64() >= total.as_secs_f64() * step.needs() {
+        return Ok(true);
+    }
+    let message = format!(
+        "Skipped {what}, because only {:.1}s of the {:.1}s time budget was left",
+        left.as_secs_f64(),
+        total.as_secs_f64()
+    );
+    Warning::new(Code::OverBudget, message)
+        .parameter("time-budget")
+        .raise()?;
+    Ok(false)
+}
+
+/// Parses a duration in seconds, minutes or hours, e.g. "90s", "1.5m" or "1h". A bare number is in seconds.
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    // The unit is the letters at the end, so a number like "1e3s" keeps its exponent.
+    let (number, unit) = s.split_at(s.trim_end_matches(|c: char| c.is_ascii_alphabetic()).len());
+    let seconds = match unit {
+        "" | "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        _ => bail!("{s} should end in s, m or h, e.g. 90s"),
+    };
+    let Ok(number) = number.trim().parse::<f64>() else {
+        bail!("{s} isn't a duration, e.g. 90s");
+    };
+    if !number.is_finite() || number <= 0.0 {
+        bail!("The duration must be positive, not {s}");
+    }
+    Duration::try_from_secs_f64(number * seconds).map_err(|_| eyre!("{s} is too long a duration"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_each_unit() {
+        for (s, seconds) in [
+            ("90", 90.0),
+            ("90s", 90.0),
+            ("1.5m", 90.0),
+            ("1h", 3600.0),
+            ("0.5s", 0.5),
+            (" 2 s", 2.0),
+        ] {
+            assert_eq!(
+                parse_duration(s).unwrap(),
+                Duration::from_secs_f64(seconds),
+                "{s}"
+            );
+        }
+    }
+
+    #[test]
+    fn keeps_exponents() {
+        assert_eq!(parse_duration("1e3s").unwrap(), Duration::from_secs(1000));
+        assert_eq!(parse_duration("1e3").unwrap(), Duration::from_secs(1000));
+        assert_eq!(parse_duration("2E1m").unwrap(), Duration::from_secs(1200));
+    }
+
+    #[test]
+    fn rejects_bad_durations() {
+        for s in [
+            "", "s", "90ms", "90 days", "-1s", "0", "inf", "NaN", "1.5.2s",
+        ] {
+            assert!(parse_duration(s).is_err(), "{s} should be rejected");
+        }
+    }
+
+    #[test]
+    fn rejects_durations_too_long_to_hold() {
+        let e = parse_duration("99999999999999999999999999").unwrap_err();
+        assert!(e.to_string().contains("too long"), "{e}");
+        assert!(parse_duration("1e300h").is_err());
+    }
+}
@@ -0,0 +1,497 @@
+use crate::{
+    sidecar,
+    sink::{self, FileSink, SinkKind},
+    snapshot::STDOUT,
+    warnings::{Code, Warning},
+    Cli, Model,
+};
+use clap::{ArgMatches, Command, CommandFactory};
+use color_eyre::{
+    eyre::{bail, WrapErr},
+    Result,
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The file in each cache entry which lists the outputs in it.
+const ENTRY: &str = "entry.json";
+
+/// Options which can't change what a run saves, so they're left out of its key.
+const IGNORED: &[&str] = &[
+    "cache_dir",
+    "no_cache",
+    "cache_max_bytes",
+    "notify_url",
+    "notify_timeout",
+    "server_info",
+    "force_tty",
+    "time_budget",
+    "deny_warnings",
+    "session_retries",
+];
+
+/// Options which name files the run writes, rather than reads, so their contents aren't part of its key.
+const OUTPUTS: &[&str] = &[
+    "output",
+    "export",
+    "manifest",
+    "save_state",
+    "emit_kcl",
+    "debug_dir",
+    "out",
+];
+
+/// Whether the run's outputs came from --cache-dir.
+#[derive(Clone, Copy, Debug, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    /// They did, so the run didn't connect to the API.
+    Hit,
+    /// They weren't there, so the run drew the model as usual.
+    Miss,
+}
+
+static STATUS: Mutex<Option<Status>> = Mutex::new(None);
+
+/// Whether the run's outputs came from the cache, if it used one.
+pub fn status() -> Option<Status> {
+    *STATUS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn set_status(status: Status) {
+    *STATUS.lock().unwrap_or_else(|e| e.into_inner()) = Some(status);
+}
+
+/// What's in a cache entry: the outputs a run saved, in order.
+/// Each is kept in the entry's directory, named after its position in the list.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Entry {
+    files: Vec<CachedFile>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CachedFile {
+    /// Where the run saved it.
+    path: String,
+    /// So a file which was changed or cut short is noticed.
+    sha256: String,
+}
+
+/// Where a run's outputs are cached, and under which key.
+pub struct Cache {
+    dir: PathBuf,
+    key: String,
+    max_bytes: u64,
+}
+
+impl Cache {
+    /// The cache for this run, if it asked for one with --cache-dir, and its outputs can be cached.
+    pub fn for_run(cli: &Cli, matches: &ArgMatches) -> Result<Option<Self>> {
+        let Some(dir) = &cli.cache_dir else {
+            return Ok(None);
+        };
+        if cli.no_cache {
+            return Ok(None);
+        }
+        let why_not = if cli.output == STDOUT {
+            Some("the snapshot goes to standard output")
+        } else if !matches!(cli.output_sink, SinkKind::File) {
+            Some("outputs aren't saved to files")
+        } else if cli.watch {
+            Some("--watch renders more than once")
+        } else if cli.manifest.is_some() || cli.save_state.is_some() || cli.emit_kcl.is_some() {
+            Some("--manifest, --save-state and --emit-kcl aren't cached")
+        } else {
+            match &cli.model {
+                Some(Model::Convert { out: Some(out), .. }) if out.as_os_str() == STDOUT => {
+                    Some("the conversion goes to standard output")
+                }
+                Some(
+                    Model::Sweep { .. }
+                    | Model::Raw { .. }
+                    | Model::Pipe { .. }
+                    | Model::Bench { .. }
+                    | Model::VerifyPrimitives { .. }
+                    | Model::Gallery { .. },
+                ) => Some("this subcommand isn't cached"),
+                _ => None,
+            }
+        };
+        if let Some(why_not) = why_not {
+            say!("Not using the cache, because {why_not}");
+            return Ok(None);
+        }
+        let mut description = format!("{}\n", env!("CARGO_PKG_VERSION"));
+        describe(&Cli::command(), matches, &mut description)?;
+        Ok(Some(Self {
+            dir: dir.clone(),
+            key: sidecar::sha256(description.as_bytes()),
+            max_bytes: cli.cache_max_bytes,
+        }))
+    }
+
+    /// Where the cache's entries are kept.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn entry_dir(&self) -> PathBuf {
+        self.dir.join(&self.key)
+    }
+
+    /// If the cache has this run's outputs, save them where the run would have, and return true.
+    /// An entry which can't be read, or whose files aren't what it says, is warned about and removed,
+    /// so the run draws the model again.
+    pub async fn restore(&self) -> Result<bool> {
+        let entry_dir = self.entry_dir();
+        if !entry_dir.exists() {
+            set_status(Status::Miss);
+            return Ok(false);
+        }
+        let files = match read_entry(&entry_dir) {
+            Ok(files) => files,
+            Err(e) => {
+                let message = format!(
+                    "Ignoring the cache entry {}: {e}: {}",
+                    entry_dir.display(),
+                    e.root_cause()
+                );
+                Warning::new(Code::Cache, message)
+                    .parameter("cache-dir")
+                    .raise()?;
+                // Another run may have removed it already.
+                let _ = fs::remove_dir_all(&entry_dir);
+                set_status(Status::Miss);
+                return Ok(false);
+            }
+        };
+        for (path, contents) in &files {
+            sink::write(&FileSink, path, contents).await?;
+        }
+        // Mark it as used, so it's pruned last.
+        if let Ok(file) = fs::File::options().write(true).open(entry_dir.join(ENTRY)) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        set_status(Status::Hit);
+        say!(
+            "Copied {} outputs from the cache ({}), so didn't connect to the API",
+            files.len(),
+            entry_dir.display()
+        );
+        Ok(true)
+    }
+
+    /// Save everything the run wrote (its outputs and their sidecars) in the cache, then prune it.
+    /// The entry is written beside where it belongs, then moved there, so other runs never see half of it.
+    /// Failures are warned about, because the run itself succeeded.
+    pub fn save(&self) -> Result<()> {
+        // If a file was written twice, the cache only needs the last version.
+        let mut paths: Vec<String> = Vec::new();
+        for written in sink::written() {
+            paths.retain(|path| *path != written.key);
+            paths.push(written.key);
+        }
+        if let Err(e) = self.try_save(paths).and_then(|()| self.prune()) {
+            let message = format!("Could not update the cache: {e}: {}", e.root_cause());
+            Warning::new(Code::Cache, message)
+                .parameter("cache-dir")
+                .raise()?;
+        }
+        Ok(())
+    }
+
+    /// Save the files at these paths as this run's entry.
+    fn try_save(&self, paths: Vec<String>) -> Result<()> {
+        let entry_dir = self.entry_dir();
+        let partial = self
+            .dir
+            .join(format!(".{}-{}.partial", self.key, uuid::Uuid::new_v4()));
+        let saved = write_entry(&partial, paths).and_then(|()| {
+            fs::rename(&partial, &entry_dir).wrap_err_with(|| {
+                format!("Could not move the new entry to {}", entry_dir.display())
+            })
+        });
+        match saved {
+            Ok(()) => {
+                say!("Saved the outputs to the cache ({})", entry_dir.display());
+                Ok(())
+            }
+            // Another run with the same options got there first, with the same outputs.
+            Err(_) if entry_dir.join(ENTRY).exists() => {
+                let _ = fs::remove_dir_all(&partial);
+                Ok(())
+            }
+            Err(e) => {
+                let _ = fs::remove_dir_all(&partial);
+                Err(e)
+            }
+        }
+    }
+
+    /// Remove the least recently used entries, until the cache is no bigger than --cache-max-bytes.
+    fn prune(&self) -> Result<()> {
+        let mut entries = Vec::new();
+        for dir_entry in fs::read_dir(&self.dir)
+            .wrap_err_with(|| format!("Could not list {}", self.dir.display()))?
+        {
+            let path = dir_entry?.path();
+            // Skip entries other runs are still writing.
+            let partial = path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+            if partial || !path.is_dir() {
+                continue;
+            }
+            let used = fs::metadata(path.join(ENTRY))
+                .and_then(|meta| meta.modified())
+                .unwrap_or(UNIX_EPOCH);
+            entries.push((used, size(&path), path));
+        }
+        entries.sort();
+        let mut total: u64 = entries.iter().map(|(_, bytes, _)| bytes).sum();
+        for (_, bytes, path) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            fs::remove_dir_all(&path)
+                .wrap_err_with(|| format!("Could not remove {}", path.display()))?;
+            say!("Removed {} from the cache, to make room", path.display());
+            total -= bytes;
+        }
+        Ok(())
+    }
+}
+
+/// Describe every option the run was given (or defaulted), and the contents of any files they name,
+/// one per line, sorted so the order they were given in doesn't matter.
+/// Files which those files refer to (e.g. a scene file's includes) aren't read.
+fn describe(command: &Command, matches: &ArgMatches, description: &mut String) -> Result<()> {
+    let mut ids: Vec<_> = command
+        .get_arguments()
+        .map(|arg| arg.get_id().as_str())
+        .collect();
+    ids.sort_unstable();
+    for id in ids {
+        if IGNORED.contains(&id) {
+            continue;
+        }
+        let Ok(Some(values)) = matches.try_get_raw(id) else {
+            continue;
+        };
+        for value in values {
+            let value = value.to_string_lossy();
+            description.push_str(&format!("{id}={value}\n"));
+            let path = Path::new(&*value);
+            if !OUTPUTS.contains(&id) && path.is_file() {
+                let contents = fs::read(path)
+                    .wrap_err_with(|| format!("Could not read {}", path.display()))?;
+                description.push_str(&format!("  {}\n", sidecar::sha256(&contents)));
+            }
+        }
+    }
+    if let Some((name, matches)) = matches.subcommand() {
+        description.push_str(&format!("{name}\n"));
+        if let Some(command) = command.find_subcommand(name) {
+            describe(command, matches, description)?;
+        }
+    }
+    Ok(())
+}
+
+/// Copy the files at these paths into a new entry.
+fn write_entry(entry_dir: &Path, paths: Vec<String>) -> Result<()> {
+    fs::create_dir_all(entry_dir)
+        .wrap_err_with(|| format!("Could not create {}", entry_dir.display()))?;
+    let mut files = Vec::new();
+    for (i, path) in paths.into_iter().enumerate() {
+        let contents = fs::read(&path).wrap_err_with(|| format!("Could not read back {path}"))?;
+        fs::write(entry_dir.join(i.to_string()), &contents)?;
+        files.push(CachedFile {
+            sha256: sidecar::sha256(&contents),
+            path,
+        });
+    }
+    fs::write(
+        entry_dir.join(ENTRY),
+        serde_json::to_vec_pretty(&Entry { files })?,
+    )?;
+    Ok(())
+}
+
+/// Read every file in an entry, checking each is what the entry says it is.
+fn read_entry(entry_dir: &Path) -> Result<Vec<(String, Vec<u8>)>> {
+    let entry_path = entry_dir.join(ENTRY);
+    let text = fs::read(&entry_path)
+        .wrap_err_with(|| format!("Could not read {}", entry_path.display()))?;
+    let entry: Entry = serde_json::from_slice(&text)
+        .wrap_err_with(|| format!("Could not parse {}", entry_path.display()))?;
+    entry
+        .files
+        .into_iter()
+        .enumerate()
+        .map(|(i, file)| {
+            let cached = entry_dir.join(i.to_string());
+            let contents = fs::read(&cached)
+                .wrap_err_with(|| format!("Could not read {}", cached.display()))?;
+            if sidecar::sha256(&contents) != file.sha256 {
+                bail!(
+                    "{} isn't what was cached for {}",
+                    cached.display(),
+                    file.path
+                );
+            }
+            Ok((file.path, contents))
+        })
+        .collect()
+}
+
+/// How many bytes are in the files in a directory.
+fn size(dir: &Path) -> u64 {
+    fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| entry.metadata().ok())
+        .map(|meta| meta.len())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{thread, time::Duration};
+
+    /// An empty directory of the test's own, with a cache in it which keeps up to `max_bytes`.
+    fn cache(test: &str, max_bytes: u64) -> (PathBuf, Cache) {
+        let dir =
+            std::env::temp_dir().join(format!("kittycad-cache-{test}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let cache = Cache {
+            dir: dir.join("cache"),
+            key: "key".to_owned(),
+            max_bytes,
+        };
+        fs::create_dir_all(&cache.dir).unwrap();
+        (dir, cache)
+    }
+
+    /// Write some outputs, as a run would, and return their paths.
+    fn outputs(dir: &Path, files: &[(&str, &str)]) -> Vec<String> {
+        files
+            .iter()
+            .map(|(name, contents)| {
+                let path = dir.join(name);
+                fs::write(&path, contents).unwrap();
+                path.display().to_string()
+            })
+            .collect()
+    }
+
+    fn restore(cache: &Cache) -> bool {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(cache.restore())
+            .unwrap()
+    }
+
+    #[test]
+    fn restores_the_outputs_it_saved() {
+        let (dir, cache) = cache("hit", u64::MAX);
+        let paths = outputs(&dir, &[("model.png", "png"), ("model.png.json", "sidecar")]);
+        cache.try_save(paths.clone()).unwrap();
+        for path in &paths {
+            fs::remove_file(path).unwrap();
+        }
+        assert!(restore(&cache));
+        assert_eq!(fs::read_to_string(&paths[0]).unwrap(), "png");
+        assert_eq!(fs::read_to_string(&paths[1]).unwrap(), "sidecar");
+    }
+
+    #[test]
+    fn misses_outputs_it_never_saved() {
+        let (dir, cache) = cache("miss", u64::MAX);
+        outputs(&dir, &[("model.png", "png")]);
+        assert!(!restore(&cache));
+        assert_eq!(fs::read_to_string(dir.join("model.png")).unwrap(), "png");
+    }
+
+    #[test]
+    fn removes_an_entry_whose_files_were_changed() {
+        let (dir, cache) = cache("changed", u64::MAX);
+        let paths = outputs(&dir, &[("model.png", "png")]);
+        cache.try_save(paths.clone()).unwrap();
+        fs::write(cache.entry_dir().join("0"), "changed").unwrap();
+        fs::remove_file(&paths[0]).unwrap();
+        assert!(!restore(&cache));
+        assert!(!cache.entry_dir().exists());
+        assert!(!Path::new(&paths[0]).exists());
+    }
+
+    #[test]
+    fn removes_an_entry_which_cant_be_read() {
+        let (dir, cache) = cache("unreadable", u64::MAX);
+        let paths = outputs(&dir, &[("model.png", "png")]);
+        cache.try_save(paths).unwrap();
+        fs::write(cache.entry_dir().join(ENTRY), "{\"files\": [").unwrap();
+        assert!(!restore(&cache));
+        assert!(!cache.entry_dir().exists());
+    }
+
+    #[test]
+    fn keeps_one_entry_when_runs_save_at_once() {
+        let (dir, cache) = cache("race", u64::MAX);
+        let paths = outputs(&dir, &[("model.png", "png")]);
+        thread::scope(|scope| {
+            let saves: Vec<_> = (0..8)
+                .map(|_| scope.spawn(|| cache.try_save(paths.clone())))
+                .collect();
+            for save in saves {
+                save.join().unwrap().unwrap();
+            }
+        });
+        let left: Vec<_> = fs::read_dir(&cache.dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(left, ["key"], "The partial entries weren't all removed");
+        assert_eq!(read_entry(&cache.entry_dir()).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn prunes_the_least_recently_used_entries() {
+        // Every entry is the same size: the one file, and its entry.json.
+        let (dir, mut cache) = cache("prune", 0);
+        let paths = outputs(&dir, &[("model.png", &"x".repeat(100))]);
+        let now = SystemTime::now();
+        for (key, age) in [("old", 30), ("older", 60), ("new", 0)] {
+            cache.key = key.to_owned();
+            cache.try_save(paths.clone()).unwrap();
+            fs::File::options()
+                .write(true)
+                .open(cache.entry_dir().join(ENTRY))
+                .unwrap()
+                .set_modified(now - Duration::from_secs(age))
+                .unwrap();
+        }
+        let entry_bytes = size(&cache.entry_dir());
+        cache.max_bytes = entry_bytes * 2;
+        cache.prune().unwrap();
+        let mut left: Vec<_> = fs::read_dir(&cache.dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        left.sort();
+        assert_eq!(left, ["new", "old"]);
+
+        cache.max_bytes = entry_bytes;
+        cache.prune().unwrap();
+        assert!(cache.dir.join("new").exists());
+        assert!(!cache.dir.join("old").exists());
+    }
+}
@@ -0,0 +1,380 @@
+use crate::{
+    geometry::{dot, normalize, BoundingBox},
+    projection::Camera,
+    session::ModelingSession,
+    timings::Phase,
+    Model,
+};
+use color_eyre::Result;
+use kittycad::types::{ModelingCmd, Point3D};
+
+/// The camera's vertical field of view, in degrees.
+/// We leave some margin on top of the API's actual field of view, so the model is never clipped.
+const FIELD_OF_VIEW_DEGREES: f64 = 40.0;
+
+/// How many points around each of a turntable's circles are checked, to find how far back the camera
+/// must stand. One every half a degree.
+const TURNTABLE_STEPS: usize = 720;
+
+/// Which way the camera looks at a model when it's framed. Zooming to fit still decides how far away it is.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CameraPreset {
+    /// Which way the camera stands from the middle of the model, e.g. (-1, -1, 1) for in front,
+    /// to the left, and above. Only its direction matters.
+    pub eye: Point3D,
+    /// Which way is up in the picture. It mustn't point the same way as `eye`.
+    pub up: Point3D,
+}
+
+impl Default for CameraPreset {
+    /// Looking down at the model from above and in front, which shows three of a cube's faces.
+    fn default() -> Self {
+        View::Iso.preset()
+    }
+}
+
+/// The views --view can ask for, instead of whichever one suits the model.
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum View {
+    /// From above, in front, and to the left, which shows three of a cube's faces.
+    Iso,
+    /// Straight down, with +Y up the picture.
+    Top,
+    /// Straight up, with +Y up the picture.
+    Bottom,
+    /// From -Y, the way models face.
+    Front,
+    /// From +Y.
+    Back,
+    /// From -X.
+    Left,
+    /// From +X.
+    Right,
+}
+
+impl View {
+    pub fn preset(self) -> CameraPreset {
+        let point = |x, y, z| Point3D { x, y, z };
+        let (eye, up) = match self {
+            View::Iso => (point(-1.0, -1.0, 1.0), point(0.0, 0.0, 1.0)),
+            View::Top => (point(0.0, 0.0, 1.0), point(0.0, 1.0, 0.0)),
+            View::Bottom => (point(0.0, 0.0, -1.0), point(0.0, 1.0, 0.0)),
+            View::Front => (point(0.0, -1.0, 0.0), point(0.0, 0.0, 1.0)),
+            View::Back => (point(0.0, 1.0, 0.0), point(0.0, 0.0, 1.0)),
+            View::Left => (point(-1.0, 0.0, 0.0), point(0.0, 0.0, 1.0)),
+            View::Right => (point(1.0, 0.0, 0.0), point(0.0, 0.0, 1.0)),
+        };
+        CameraPreset { eye, up }
+    }
+}
+
+/// Which way to look at a model: the view asked for with --view, if there was one,
+/// otherwise the model's own preset, if it has one, otherwise the usual view.
+pub(crate) fn choose(asked: Option<View>, model: &Model) -> CameraPreset {
+    asked
+        .map(View::preset)
+        .or_else(|| model.preferred_camera())
+        .unwrap_or_default()
+}
+
+/// Point the camera at the middle of the bounding box, from far enough away that the whole box is visible,
+/// from the direction of the session's camera preset.
+pub async fn zoom_to_fit(session: &mut ModelingSession, bbox: &BoundingBox) -> Result<()> {
+    let camera = framing(bbox, session.camera_preset());
+    look_at(session, bbox, camera).await
+}
+
+/// Point the camera as given, at a model in the bounding box, and remember where it's pointed.
+pub async fn look_at(
+    session: &mut ModelingSession,
+    bbox: &BoundingBox,
+    camera: Camera,
+) -> Result<()> {
+    let previous = session.enter_phase(Phase::Camera);
+    let cmd_id = session
+        .send(ModelingCmd::DefaultCameraLookAt {
+            center: camera.center.clone(),
+            up: camera.up.clone(),
+            vantage: camera.vantage.clone(),
+        })
+        .await?;
+    session.await_response(cmd_id).await?;
+    session.set_framed(bbox.clone(), camera);
+    session.enter_phase(previous);
+    Ok(())
+}
+
+/// Where the camera was pointed, so it can be pointed back there after something else moves it,
+/// e.g. sketching on a face.
+#[derive(Clone, Debug)]
+pub struct SavedCamera {
+    /// The box the camera last framed, and how, or None if the camera hadn't been pointed yet.
+    framed: Option<(BoundingBox, Camera)>,
+}
+
+/// Remember where the camera is pointed.
+/// The API (as of this version of the kittycad crate) can't say where its camera is,
+/// so this remembers where we last pointed it. Only `look_at` points it, so that's enough to point it again.
+pub fn save(session: &ModelingSession) -> SavedCamera {
+    SavedCamera {
+        framed: session.framed().cloned().zip(session.pointed().cloned()),
+    }
+}
+
+/// Point the camera back where it was when it was saved.
+/// If it hadn't been pointed yet, the API's default view can't be got back, so this forgets the framing
+/// instead, and the model is framed as usual before its snapshot.
+pub async fn restore(session: &mut ModelingSession, saved: &SavedCamera) -> Result<()> {
+    match &saved.framed {
+        Some((bbox, camera)) => look_at(session, bbox, camera.clone()).await,
+        None => {
+            session.forget_framing();
+            Ok(())
+        }
+    }
+}
+
+/// Where `zoom_to_fit` puts the camera to show the bounding box, from the preset's direction.
+/// The field of view is the one we plan with, which is a bit narrower than the API's,
+/// so points projected with it land a little further from the middle of the image than they really are.
+pub fn framing(bbox: &BoundingBox, preset: &CameraPreset) -> Camera {
+    let center = bbox.center();
+    // A sphere around the box fits in the view if it's this far away from the camera.
+    let distance = bbox.radius() / (FIELD_OF_VIEW_DEGREES.to_radians() / 2.0).sin();
+    let eye = &preset.eye;
+    let length = (eye.x * eye.x + eye.y * eye.y + eye.z * eye.z).sqrt();
+    let vantage = Point3D {
+        x: center.x + distance * eye.x / length,
+        y: center.y + distance * eye.y / length,
+        z: center.z + distance * eye.z / length,
+    };
+    Camera {
+        vantage,
+        center,
+        up: preset.up.clone(),
+        vertical_fov_degrees: FIELD_OF_VIEW_DEGREES,
+    }
+}
+
+/// Where to put the camera to show a model from every angle of a turntable, which turns the camera
+/// about the Z axis through the middle of the bounding box, starting from the preset's direction.
+/// It stands the same distance away in every frame, so the model doesn't zoom in and out as it turns,
+/// and that's far enough back for the model to fit at whichever angle it's widest.
+///
+/// As the model turns, its bounding box sweeps out a cylinder: its corners go around the axis
+/// in two circles, at the top and bottom of the box. The camera stands back far enough for every
+/// point on those circles to fit in the picture, from its direction. That's never further back than
+/// `framing` puts it, because both circles are on the sphere `framing` fits.
+/// `margin` is room to leave around the model, as a fraction of its size, e.g. 0.05 for 5%.
+pub fn turntable_framing(bbox: &BoundingBox, preset: &CameraPreset, margin: f64) -> Camera {
+    let center = bbox.center();
+    let extents = bbox.extents();
+    let scale = 1.0 + margin;
+    // How far the box's corners are from the axis, and above or below the middle.
+    let radius = (extents.x * extents.x + extents.y * extents.y).sqrt() / 2.0 * scale;
+    let half_height = extents.z / 2.0 * scale;
+    let eye = normalize(&preset.eye);
+    let distance = turntable_distance(radius, half_height, &eye);
+    let vantage = Point3D {
+        x: center.x + distance * eye.x,
+        y: center.y + distance * eye.y,
+        z: center.z + distance * eye.z,
+    };
+    Camera {
+        vantage,
+        center,
+        up: preset.up.clone(),
+        vertical_fov_degrees: FIELD_OF_VIEW_DEGREES,
+    }
+}
+
+/// How far from the middle a camera looking back along `eye` (a unit vector) must stand,
+/// to see all of two circles of `radius` about the Z axis, `half_height` above and below the middle.
+///
+/// A point `along` the way to the camera, and `across` from the line it looks along, fits in the
+/// field of view if the camera is at least `along + across / tan(fov / 2)` away.
+fn turntable_distance(radius: f64, half_height: f64, eye: &Point3D) -> f64 {
+    let tan = (FIELD_OF_VIEW_DEGREES.to_radians() / 2.0).tan();
+    let mut distance: f64 = 0.0;
+    for step in 0..TURNTABLE_STEPS {
+        let (sin, cos) = (std::f64::consts::TAU * step as f64 / TURNTABLE_STEPS as f64).sin_cos();
+        for z in [half_height, -half_height] {
+            let p = Point3D {
+                x: radius * cos,
+                y: radius * sin,
+                z,
+            };
+            let along = dot(&p, eye);
+            let across = (dot(&p, &p) - along * along).max(0.0).sqrt();
+            distance = distance.max(along + across / tan);
+        }
+    }
+    distance
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::distance;
+
+    fn point(x: f64, y: f64, z: f64) -> Point3D {
+        Point3D { x, y, z }
+    }
+
+    /// A 20mm cube, from the origin to (20, 20, 20).
+    fn cube() -> BoundingBox {
+        BoundingBox {
+            min: point(0.0, 0.0, 0.0),
+            max: point(20.0, 20.0, 20.0),
+        }
+    }
+
+    fn assert_near(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() <= expected.abs() * 1e-3,
+            "Expected {expected}, got {actual}"
+        );
+    }
+
+    /// Where the camera stands, from the middle of the box.
+    fn from_center(camera: &Camera) -> Point3D {
+        crate::geometry::sub(&camera.vantage, &camera.center)
+    }
+
+    #[test]
+    fn frames_a_box_from_far_enough_away_to_fit_a_sphere_around_it() {
+        // The sphere's radius is half the cube's diagonal, 10√3, and it fits 20° either side of
+        // the middle, so the camera's 10√3 / sin 20° away.
+        let camera = framing(&cube(), &View::Top.preset());
+        assert_eq!(camera.center, point(10.0, 10.0, 10.0));
+        assert_eq!(camera.up, point(0.0, 1.0, 0.0));
+        let expected = 10.0 * 3_f64.sqrt() / 20_f64.to_radians().sin();
+        assert_near(expected, 50.64);
+        let offset = from_center(&camera);
+        assert_eq!((offset.x, offset.y), (0.0, 0.0));
+        assert_near(offset.z, expected);
+
+        // From any direction, at the same distance.
+        let camera = framing(&cube(), &View::Iso.preset());
+        let offset = from_center(&camera);
+        assert_near(distance(&camera.vantage, &camera.center), expected);
+        assert_near(offset.x, -expected / 3_f64.sqrt());
+        assert_near(offset.y, -expected / 3_f64.sqrt());
+        assert_near(offset.z, expected / 3_f64.sqrt());
+    }
+
+    #[test]
+    fn frames_every_corner_of_a_box_in_the_picture() {
+        let bbox = BoundingBox {
+            min: point(-50.0, 3.0, -1.0),
+            max: point(10.0, 7.0, 40.0),
+        };
+        for view in [
+            View::Iso,
+            View::Top,
+            View::Bottom,
+            View::Front,
+            View::Back,
+            View::Left,
+            View::Right,
+        ] {
+            let camera = framing(&bbox, &view.preset());
+            let region = camera.screen_region(&bbox, 1.0).unwrap();
+            assert!(
+                region.left >= 0.0
+                    && region.top >= 0.0
+                    && region.right <= 1.0
+                    && region.bottom <= 1.0,
+                "{view:?} puts the box at {region:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn stands_a_turntable_back_far_enough_for_a_flat_box() {
+        // Looking straight down at a flat 20mm square, its corners sweep a circle of radius 10√2,
+        // which fits 20° either side of the middle from 10√2 / tan 20° away.
+        let flat = BoundingBox {
+            min: point(0.0, 0.0, 0.0),
+            max: point(20.0, 20.0, 0.0),
+        };
+        let camera = turntable_framing(&flat, &View::Top.preset(), 0.0);
+        assert_eq!(camera.center, point(10.0, 10.0, 0.0));
+        assert_near(
+            from_center(&camera).z,
+            10.0 * 2_f64.sqrt() / 20_f64.to_radians().tan(),
+        );
+    }
+
+    #[test]
+    fn stands_a_turntable_back_far_enough_for_a_cube() {
+        // From above, the top circle's nearest and widest point is 10 nearer than the middle.
+        let camera = turntable_framing(&cube(), &View::Top.preset(), 0.0);
+        assert_near(
+            from_center(&camera).z,
+            10.0 + 10.0 * 2_f64.sqrt() / 20_f64.to_radians().tan(),
+        );
+        // From the side, the circles are on the sphere `framing` fits, and touch its edge.
+        let side = turntable_framing(&cube(), &View::Front.preset(), 0.0);
+        let framed = framing(&cube(), &View::Front.preset());
+        assert_near(-from_center(&side).y, -from_center(&framed).y);
+        assert!(-from_center(&side).y <= -from_center(&framed).y);
+    }
+
+    #[test]
+    fn leaves_a_margin_around_a_turntable() {
+        let tight = turntable_framing(&cube(), &View::Iso.preset(), 0.0);
+        let loose = turntable_framing(&cube(), &View::Iso.preset(), 0.05);
+        assert_near(
+            distance(&loose.vantage, &loose.center),
+            1.05 * distance(&tight.vantage, &tight.center),
+        );
+    }
+
+    /// The profile model, drawn from the sample profile.
+    fn profile() -> Model {
+        let csv = concat!(env!("CARGO_MANIFEST_DIR"), "/samples/profile.csv");
+        model(&[
+            "profile",
+            &format!("--profile-csv={csv}"),
+            "--extrude-height=5",
+        ])
+    }
+
+    fn model(args: &[&str]) -> Model {
+        let args: Vec<String> = args.iter().map(|&arg| arg.to_owned()).collect();
+        crate::scene::parse_model(&args).unwrap()
+    }
+
+    #[test]
+    fn looks_at_a_model_from_its_own_preset() {
+        let plate = model(&["plate"]);
+        let preset = plate.preferred_camera().unwrap();
+        assert_ne!(preset, CameraPreset::default());
+        assert_eq!(choose(None, &plate), preset);
+        assert_eq!(choose(None, &profile()), View::Top.preset());
+    }
+
+    #[test]
+    fn looks_at_a_model_without_a_preset_from_the_usual_view() {
+        let cube = model(&["cube"]);
+        assert_eq!(cube.preferred_camera(), None);
+        assert_eq!(choose(None, &cube), View::Iso.preset());
+    }
+
+    #[test]
+    fn looks_from_the_view_asked_for_whatever_the_model_s_preset() {
+        let models = [
+            model(&["plate"]),
+            model(&["l-bracket"]),
+            model(&["bolt-circle"]),
+            profile(),
+            model(&["cube"]),
+        ];
+        for model in &models {
+            for view in [View::Iso, View::Front, View::Bottom] {
+                assert_eq!(choose(Some(view), model), view.preset(), "{view:?}");
+            }
+        }
+    }
+}
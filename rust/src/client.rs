@@ -0,0 +1,633 @@
+use crate::{
+    session::API_HOST,
+    token::TokenProvider,
+    warnings::{Code, Warning},
+};
+use color_eyre::{
+    eyre::{bail, Context},
+    Report, Result,
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use uuid::Uuid;
+
+/// How to reach the API: through which proxy, and trusting which certificates.
+/// The default is directly (or through the proxy in the environment), trusting the built-in certificates.
+#[derive(Default)]
+pub struct Network {
+    /// Requests go through this proxy if it's given, or else any proxy set in $HTTPS_PROXY or
+    /// $ALL_PROXY. Either way, hosts in $NO_PROXY are reached directly.
+    pub proxy: Option<String>,
+    /// A PEM file of root certificates to trust, as well as the built-in ones.
+    pub ca_cert: Option<PathBuf>,
+    /// Don't check the API's certificate at all.
+    pub insecure_skip_verify: bool,
+}
+
+/// An API client whose requests carry this run's ID in their user agent,
+/// so KittyCAD support can find the run in their logs.
+pub fn client(token: String, run_id: Uuid, network: &Network) -> Result<kittycad::Client> {
+    let user_agent = format!(
+        "{}/{} run/{run_id}",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION")
+    );
+    let (http, websocket) = builders(&user_agent, network)?;
+    let client = kittycad::Client::new_from_reqwest(token, http, websocket);
+    #[cfg(feature = "test-hooks")]
+    let client = {
+        let mut client = client;
+        if let Some(url) = crate::inject::api_url() {
+            client.set_base_url(url);
+        }
+        client
+    };
+    Ok(client)
+}
+
+/// The builders of an API client's HTTP client, and of the one it opens WebSockets with,
+/// reaching the API as `network` says.
+fn builders(
+    user_agent: &str,
+    network: &Network,
+) -> Result<(reqwest::ClientBuilder, reqwest::ClientBuilder)> {
+    let proxy = match &network.proxy {
+        Some(url) => {
+            check_proxy_scheme(url)?;
+            let proxy = reqwest::Proxy::all(url)
+                .with_context(|| format!("{url} isn't a usable proxy URL"))?;
+            Some(proxy.no_proxy(reqwest::NoProxy::from_env()))
+        }
+        // reqwest reads the environment's proxies itself, but skips any it can't use.
+        None => {
+            for var in ["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy"] {
+                if let Ok(url) = std::env::var(var) {
+                    check_proxy_scheme(&url).with_context(|| format!("${var} is {url}"))?;
+                }
+            }
+            None
+        }
+    };
+    let certificates = match &network.ca_cert {
+        Some(path) => read_certificates(path)?,
+        None => Vec::new(),
+    };
+    if network.insecure_skip_verify {
+        Warning::new(
+            Code::InsecureTls,
+            format!(
+                "Not verifying {API_HOST}'s TLS certificate. Anyone between here and the API \
+                 can read and change this session, including the API token."
+            ),
+        )
+        .parameter("insecure-skip-verify")
+        .raise()?;
+    }
+    // The same settings as `kittycad::Client::new`, apart from the user agent, proxy and TLS.
+    let builder = || {
+        let mut builder = reqwest::Client::builder()
+            .user_agent(user_agent)
+            .timeout(Duration::from_secs(600))
+            .connect_timeout(Duration::from_secs(60))
+            .danger_accept_invalid_certs(network.insecure_skip_verify);
+        if let Some(proxy) = &proxy {
+            builder = builder.proxy(proxy.clone());
+        }
+        for certificate in &certificates {
+            builder = builder.add_root_certificate(certificate.clone());
+        }
+        builder
+    };
+    Ok((builder(), builder().http1_only()))
+}
+
+/// Makes the run's API clients, with a token from its provider each time, so a run which reconnects
+/// can pick up a new token when the old one expires.
+pub struct Clients {
+    provider: Arc<dyn TokenProvider>,
+    run_id: Uuid,
+    network: Network,
+    /// The last client made, and the token it was made with, to use again while the token's the same.
+    last: Mutex<Option<(String, kittycad::Client)>>,
+}
+
+impl Clients {
+    pub fn new(provider: Arc<dyn TokenProvider>, run_id: Uuid, network: Network) -> Self {
+        Self {
+            provider,
+            run_id,
+            network,
+            last: Mutex::new(None),
+        }
+    }
+
+    /// A client with a current token. `refresh` asks the provider for a new token,
+    /// e.g. because the API refused the last one.
+    pub async fn client(&self, refresh: bool) -> Result<kittycad::Client> {
+        // Providers may run a command, so they're asked off the thread the sessions run on.
+        let provider = self.provider.clone();
+        let token = tokio::task::spawn_blocking(move || provider.token(refresh))
+            .await
+            .context("Could not get an API token")??;
+        let mut last = self.last.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((last_token, client)) = last.as_ref() {
+            if *last_token == token {
+                return Ok(client.clone());
+            }
+        }
+        let new_client = client(token.clone(), self.run_id, &self.network)?;
+        *last = Some((token, new_client.clone()));
+        Ok(new_client)
+    }
+}
+
+/// Only HTTP(S) proxies can be used, because reqwest is built without SOCKS support.
+fn check_proxy_scheme(url: &str) -> Result<()> {
+    if url.to_ascii_lowercase().starts_with("socks") {
+        bail!("SOCKS proxies aren't supported yet. Use an HTTP proxy, which is sent a CONNECT request");
+    }
+    Ok(())
+}
+
+/// Read every certificate in a PEM file.
+fn read_certificates(path: &Path) -> Result<Vec<reqwest::Certificate>> {
+    const BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+    let display = path.display();
+    let pem =
+        std::fs::read_to_string(path).wrap_err_with(|| format!("Could not read {display}"))?;
+    // reqwest only reads the first certificate in a PEM, so each one is read on its own.
+    let certificates = pem
+        .match_indices(BEGIN)
+        .map(|(at, _)| {
+            let rest = &pem[at + BEGIN.len()..];
+            let end = rest
+                .find(BEGIN)
+                .map_or(pem.len(), |next| at + BEGIN.len() + next);
+            reqwest::Certificate::from_pem(&pem.as_bytes()[at..end])
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .wrap_err_with(|| format!("{display} has a certificate which couldn't be read"))?;
+    if certificates.is_empty() {
+        bail!("{display} has no PEM certificates in it");
+    }
+    Ok(certificates)
+}
+
+/// The API refused to open a session because of the account or its token, rather than anything
+/// about the run, so trying again straight away won't help.
+#[derive(Debug)]
+pub struct Refused {
+    pub reason: Refusal,
+    /// What the API said, if anything.
+    pub message: Option<String>,
+}
+
+/// Why the API refused to open a session.
+#[derive(Debug, PartialEq)]
+pub enum Refusal {
+    /// Too many requests from this token. The API may say how long to wait before trying again.
+    RateLimited { retry_after: Option<Duration> },
+    /// The account has no modeling credits (minutes) left.
+    InsufficientCredits,
+    /// The token is wrong, or has expired or been revoked.
+    Unauthorized,
+    /// The token isn't allowed to use the Modeling API.
+    Forbidden,
+}
+
+impl Refused {
+    /// The exit code for a run which failed like this: 75 (EX_TEMPFAIL) if it's worth trying
+    /// again later, otherwise 77 (EX_NOPERM).
+    pub fn exit_code(&self) -> i32 {
+        match self.reason {
+            Refusal::RateLimited { .. } => 75,
+            _ => 77,
+        }
+    }
+
+    /// The refusal in an error's chain, if it was one.
+    pub fn find(e: &Report) -> Option<&Self> {
+        e.chain().find_map(|cause| cause.downcast_ref::<Self>())
+    }
+}
+
+impl std::fmt::Display for Refused {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.reason {
+            Refusal::RateLimited {
+                retry_after: Some(after),
+            } => write!(
+                f,
+                "The API is rate limiting this token. Try again in {}s",
+                after.as_secs()
+            ),
+            Refusal::RateLimited { retry_after: None } => write!(
+                f,
+                "The API is rate limiting this token. Wait a while before trying again"
+            ),
+            Refusal::InsufficientCredits => write!(
+                f,
+                "The account has run out of modeling credits. See the billing page of your KittyCAD account"
+            ),
+            Refusal::Unauthorized => write!(
+                f,
+                "The API didn't accept the token. Check $KITTYCAD_API_TOKEN is a current API token"
+            ),
+            Refusal::Forbidden => write!(f, "This token isn't allowed to use the Modeling API"),
+        }?;
+        if let Some(message) = &self.message {
+            write!(f, " (the API said: {message})")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Refused {}
+
+/// Explain an error status the API responded to the WebSocket request with.
+/// Rate limits, exhausted credits and bad tokens become a `Refused`, so they can be told apart.
+pub async fn explain_refusal(resp: reqwest::Response) -> Report {
+    let status = resp.status();
+    let headers = resp.headers().clone();
+    let body = resp.text().await.unwrap_or_default();
+    refusal(status, &headers, &body)
+}
+
+/// What a response with this status, headers and body says about why the API refused the session.
+fn refusal(
+    status: reqwest::StatusCode,
+    headers: &reqwest::header::HeaderMap,
+    body: &str,
+) -> Report {
+    let retry_after = headers
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+        .map(Duration::from_secs);
+    // The API explains errors with a JSON body, whose message is the useful part.
+    let message = match serde_json::from_str::<kittycad::types::Error>(body) {
+        Ok(error) => Some(error.message),
+        Err(_) => Some(body.trim().to_owned()).filter(|body| !body.is_empty()),
+    };
+    let reason = match status.as_u16() {
+        429 => Refusal::RateLimited { retry_after },
+        402 => Refusal::InsufficientCredits,
+        401 => Refusal::Unauthorized,
+        403 => Refusal::Forbidden,
+        _ => {
+            let said = message.map_or(String::new(), |message| format!(": {message}"));
+            return Report::msg(format!("The API responded with {status}{said}"))
+                .wrap_err("Could not open WebSocket to KittyCAD Modeling API");
+        }
+    };
+    Report::new(Refused { reason, message })
+}
+
+/// Explain why the WebSocket couldn't be opened, if it was down to the proxy or TLS.
+pub fn explain_connect_error(e: Report) -> Report {
+    let causes: Vec<String> = e.chain().map(|cause| cause.to_string()).collect();
+    // reqwest's errors for a proxy which wouldn't tunnel to the API.
+    if causes
+        .iter()
+        .any(|cause| cause == "unsuccessful tunnel" || cause == "proxy authentication required")
+    {
+        return e.wrap_err("The proxy refused to connect to the KittyCAD Modeling API");
+    }
+    // OpenSSL's and rustls' errors for a certificate which didn't verify.
+    let rejected: Vec<&String> = causes
+        .iter()
+        .filter(|cause| {
+            cause.contains("certificate verify failed")
+                || cause.contains("invalid peer certificate")
+        })
+        .collect();
+    if let Some(innermost) = rejected.last() {
+        // OpenSSL puts the reason, e.g. "(self-signed certificate)", in brackets at the end.
+        let reason = rejected
+            .iter()
+            .find_map(|cause| cause.strip_suffix(')')?.rsplit_once('('))
+            .map_or(innermost.as_str(), |(_, reason)| reason);
+        let host = API_HOST.trim_start_matches("https://");
+        return e.wrap_err(format!(
+            "{host}'s TLS certificate couldn't be verified ({reason}). If a proxy or firewall \
+             re-signs TLS connections, pass its root certificate with --ca-cert"
+        ));
+    }
+    e.wrap_err("Could not open WebSocket to KittyCAD Modeling API")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::{
+        header::{HeaderMap, HeaderValue, RETRY_AFTER},
+        StatusCode,
+    };
+
+    /// The API's refusal of a session with this status, `Retry-After` header (if any) and body.
+    fn refused(status: u16, retry_after: Option<&str>, body: &str) -> Report {
+        let mut headers = HeaderMap::new();
+        if let Some(retry_after) = retry_after {
+            headers.insert(RETRY_AFTER, HeaderValue::from_str(retry_after).unwrap());
+        }
+        refusal(StatusCode::from_u16(status).unwrap(), &headers, body)
+    }
+
+    /// Why it was refused, the exit code the run fails with, and what it says.
+    fn explained(e: &Report) -> (&Refusal, i32, String) {
+        let refused = Refused::find(e).expect("Expected a refusal");
+        (&refused.reason, refused.exit_code(), e.to_string())
+    }
+
+    /// An error body like the API's.
+    const ERROR: &str =
+        r#"{"error_code": "some_code", "message": "Says the API", "request_id": "1"}"#;
+
+    #[test]
+    fn tells_a_bad_token_apart() {
+        let e = refused(401, None, ERROR);
+        assert_eq!(
+            explained(&e),
+            (
+                &Refusal::Unauthorized,
+                77,
+                "The API didn't accept the token. Check $KITTYCAD_API_TOKEN is a current API token \
+                 (the API said: Says the API)"
+                    .to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn tells_a_token_without_access_apart() {
+        let e = refused(403, None, "");
+        assert_eq!(
+            explained(&e),
+            (
+                &Refusal::Forbidden,
+                77,
+                "This token isn't allowed to use the Modeling API".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn tells_exhausted_credits_apart() {
+        let e = refused(402, None, "  Out of minutes\n");
+        assert_eq!(
+            explained(&e),
+            (
+                &Refusal::InsufficientCredits,
+                77,
+                "The account has run out of modeling credits. See the billing page of your \
+                 KittyCAD account (the API said: Out of minutes)"
+                    .to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn tells_a_rate_limit_apart_and_says_how_long_to_wait() {
+        let e = refused(429, Some(" 30 "), ERROR);
+        assert_eq!(
+            explained(&e),
+            (
+                &Refusal::RateLimited {
+                    retry_after: Some(Duration::from_secs(30))
+                },
+                75,
+                "The API is rate limiting this token. Try again in 30s (the API said: Says the API)"
+                    .to_owned()
+            )
+        );
+        // Retry-After can be a date too, which isn't read.
+        for retry_after in [None, Some("Wed, 21 Oct 2026 07:28:00 GMT")] {
+            let e = refused(429, retry_after, "");
+            assert_eq!(
+                explained(&e),
+                (
+                    &Refusal::RateLimited { retry_after: None },
+                    75,
+                    "The API is rate limiting this token. Wait a while before trying again"
+                        .to_owned()
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn doesnt_take_server_errors_for_refusals() {
+        for (status, body, said) in [
+            (500, ERROR, "500 Internal Server Error: Says the API"),
+            (
+                503,
+                "down for maintenance",
+                "503 Service Unavailable: down for maintenance",
+            ),
+            (502, "", "502 Bad Gateway"),
+            (400, ERROR, "400 Bad Request: Says the API"),
+        ] {
+            let e = refused(status, None, body);
+            assert!(Refused::find(&e).is_none(), "{status}");
+            let chain: Vec<_> = e.chain().map(ToString::to_string).collect();
+            assert_eq!(
+                chain,
+                [
+                    "Could not open WebSocket to KittyCAD Modeling API".to_owned(),
+                    format!("The API responded with {said}")
+                ]
+            );
+        }
+    }
+
+    fn run<T>(future: impl std::future::Future<Output = T>) -> T {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    /// A proxy on localhost which refuses to tunnel anywhere, answering each CONNECT request with
+    /// this status, e.g. "403 Forbidden". Returns its address, and the head of each request it's sent.
+    fn refusing_proxy(status: &'static str) -> (String, Arc<Mutex<Vec<String>>>) {
+        use std::io::{BufRead, BufReader, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = requests.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut head = String::new();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                while reader.read_line(&mut head).unwrap() > 2 {}
+                seen.lock().unwrap().push(head);
+                write!(stream, "HTTP/1.1 {status}\r\nContent-Length: 0\r\n\r\n").unwrap();
+            }
+        });
+        (address, requests)
+    }
+
+    /// Open a WebSocket to the API as the session would, with the client `network` makes.
+    fn connect(network: &Network) -> Report {
+        let (_, websocket) = builders("tests", network).unwrap();
+        let e = run(async {
+            let client = websocket.build().unwrap();
+            let url = format!("{API_HOST}/ws/modeling/commands");
+            client.get(url).send().await.unwrap_err()
+        });
+        explain_connect_error(Report::new(e))
+    }
+
+    #[test]
+    fn tunnels_to_the_api_through_the_proxy_with_its_credentials() {
+        let (address, requests) = refusing_proxy("407 Proxy Authentication Required");
+        let e = connect(&Network {
+            proxy: Some(format!("http://alice:s3cret@{address}")),
+            ..Network::default()
+        });
+        assert_eq!(
+            e.to_string(),
+            "The proxy refused to connect to the KittyCAD Modeling API"
+        );
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        let lines: Vec<&str> = requests[0].lines().collect();
+        assert_eq!(lines[0], "CONNECT api.kittycad.io:443 HTTP/1.1");
+        assert_eq!(
+            lines[1..],
+            [
+                "Host: api.kittycad.io:443",
+                "User-Agent: tests",
+                // "alice:s3cret", in base 64.
+                "Proxy-Authorization: Basic YWxpY2U6czNjcmV0",
+                "",
+            ]
+        );
+    }
+
+    #[test]
+    fn says_when_the_proxy_wont_tunnel_to_the_api() {
+        let (address, requests) = refusing_proxy("403 Forbidden");
+        let e = connect(&Network {
+            proxy: Some(format!("http://{address}")),
+            ..Network::default()
+        });
+        assert_eq!(
+            e.to_string(),
+            "The proxy refused to connect to the KittyCAD Modeling API"
+        );
+        let requests = requests.lock().unwrap();
+        assert!(
+            !requests[0].contains("Proxy-Authorization"),
+            "{}",
+            requests[0]
+        );
+    }
+
+    #[test]
+    fn refuses_socks_proxies() {
+        let e = builders(
+            "tests",
+            &Network {
+                proxy: Some("socks5://127.0.0.1:1080".to_owned()),
+                ..Network::default()
+            },
+        )
+        .unwrap_err();
+        assert!(e
+            .to_string()
+            .starts_with("SOCKS proxies aren't supported yet"));
+    }
+
+    /// A server on localhost with a certificate for localhost and 127.0.0.1, signed by a CA of the
+    /// tests' own, tests/fixtures/tls/ca.pem, which answers every request with "200 OK".
+    /// The certificates last until 2120, and were made with:
+    /// ```text
+    /// openssl req -x509 -newkey rsa:2048 -nodes -keyout ca.key -out ca.pem \
+    ///     -not_before 20200101000000Z -not_after 21200101000000Z \
+    ///     -subj "/CN=Modeling API examples test CA" \
+    ///     -addext "basicConstraints=critical,CA:TRUE" -addext "keyUsage=critical,keyCertSign,cRLSign"
+    /// openssl req -newkey rsa:2048 -nodes -keyout localhost.key -out localhost.csr -subj "/CN=localhost"
+    /// openssl x509 -req -in localhost.csr -CA ca.pem -CAkey ca.key -set_serial 2 -out localhost.pem \
+    ///     -not_before 20200101000000Z -not_after 21200101000000Z -extfile <(printf "%s\n" \
+    ///     "subjectAltName=DNS:localhost,IP:127.0.0.1" "basicConstraints=CA:FALSE" \
+    ///     "keyUsage=critical,digitalSignature,keyEncipherment" "extendedKeyUsage=serverAuth")
+    /// ```
+    fn tls_server() -> String {
+        use std::io::{BufRead, BufReader, Write};
+        let tls = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/tls");
+        let identity = native_tls::Identity::from_pkcs8(
+            &std::fs::read(tls.join("localhost.pem")).unwrap(),
+            &std::fs::read(tls.join("localhost.key")).unwrap(),
+        )
+        .unwrap();
+        let acceptor = native_tls::TlsAcceptor::new(identity).unwrap();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!(
+            "https://localhost:{}/",
+            listener.local_addr().unwrap().port()
+        );
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                // A client which doesn't trust the certificate hangs up during the handshake.
+                let Ok(mut stream) = acceptor.accept(stream.unwrap()) else {
+                    continue;
+                };
+                let mut reader = BufReader::new(&mut stream);
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").unwrap();
+            }
+        });
+        url
+    }
+
+    /// Send a request to `url` with the client `network` makes.
+    fn get(url: &str, network: &Network) -> Result<reqwest::StatusCode> {
+        let (http, _) = builders("tests", network)?;
+        run(async {
+            let response = http.build()?.get(url).send().await?;
+            Ok(response.status())
+        })
+    }
+
+    #[test]
+    fn trusts_the_certificates_in_ca_cert() {
+        let url = tls_server();
+        let e = get(&url, &Network::default()).unwrap_err();
+        let e = explain_connect_error(e).to_string();
+        assert!(
+            e.ends_with(
+                "If a proxy or firewall re-signs TLS connections, pass its root certificate with --ca-cert"
+            ),
+            "{e}"
+        );
+        let ca_cert = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/tls/ca.pem");
+        let network = Network {
+            ca_cert: Some(ca_cert),
+            ..Network::default()
+        };
+        assert_eq!(get(&url, &network).unwrap(), reqwest::StatusCode::OK);
+    }
+
+    #[test]
+    fn fails_if_ca_cert_has_no_certificates() {
+        let key = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/tls/localhost.key");
+        let network = Network {
+            ca_cert: Some(key.clone()),
+            ..Network::default()
+        };
+        let e = builders("tests", &network).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            format!("{} has no PEM certificates in it", key.display())
+        );
+    }
+}
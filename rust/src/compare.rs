@@ -0,0 +1,177 @@
+use crate::{
+    build, gallery::NOT_SHAPES, properties::Validation, session::ModelingSession, snapshot::Output,
+    transform, Cli, Model,
+};
+use clap::{CommandFactory, FromArgMatches};
+use color_eyre::{
+    eyre::{bail, eyre, WrapErr},
+    Report, Result,
+};
+use kittycad::types::Point3D;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// The gap left between the two variants, as a fraction of the wider one's width.
+const GAP: f64 = 0.25;
+
+/// One of a model's numeric options, and the two values to draw it with side by side,
+/// e.g. "hole-diameter=5,8".
+#[derive(Clone, Debug)]
+pub struct Compare {
+    pub name: String,
+    pub values: [String; 2],
+}
+
+impl FromStr for Compare {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let usage = || eyre!("{s:?} should look like NAME=VALUE,VALUE, e.g. hole-diameter=5,8");
+        let (name, values) = s.split_once('=').ok_or_else(usage)?;
+        let Some((a, b)) = values.split_once(',') else {
+            return Err(usage());
+        };
+        if b.contains(',') {
+            bail!("--compare draws two variants, so {s:?} should have exactly two values");
+        }
+        Ok(Self {
+            name: name.trim().trim_start_matches("--").to_owned(),
+            values: [a.trim().to_owned(), b.trim().to_owned()],
+        })
+    }
+}
+
+/// How the two variants of a comparison were drawn, saved in its snapshot's sidecar.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Comparison {
+    /// The option which differs, e.g. "hole-diameter".
+    pub option: String,
+    /// The variants, from left (-X) to right (+X).
+    pub variants: Vec<Variant>,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Variant {
+    /// The option's value, as given, e.g. "5".
+    pub value: String,
+    /// How far the variant was moved along X to make room for the other.
+    pub offset_x: f64,
+}
+
+/// Parse the run's model once with each value, so an unknown or non-numeric option fails before connecting.
+/// `shape` is the model's subcommand, e.g. "plate".
+pub fn models(compare: &Compare, shape: &str) -> Result<[Model; 2]> {
+    if NOT_SHAPES.contains(&shape) {
+        bail!("--compare draws a model twice, so it can't be used with {shape}");
+    }
+    let cli = Cli::command();
+    let Some(command) = cli.find_subcommand(shape) else {
+        bail!("--compare needs a model subcommand, e.g. plate");
+    };
+    let id = compare.name.replace('-', "_");
+    let option = command
+        .get_arguments()
+        .find(|arg| arg.get_id() == id.as_str() && arg.get_long().is_some());
+    if option.is_none() {
+        bail!(
+            "{shape} has no option called --{}, so it can't be compared",
+            compare.name
+        );
+    }
+    // Parse the whole command line again, with the option added at the end, where it's one of the model's.
+    let parse = |value: &str| -> Result<Model> {
+        let args = std::env::args_os().chain([format!("--{}={value}", compare.name).into()]);
+        let matches = Cli::command()
+            .args_override_self(true)
+            .try_get_matches_from(args)
+            .wrap_err_with(|| format!("Could not draw {shape} with --{}={value}", compare.name))?;
+        let Some((_, options)) = matches.subcommand() else {
+            bail!("--compare needs a model subcommand, e.g. plate");
+        };
+        let numeric =
+            options.try_get_one::<f64>(&id).is_ok() || options.try_get_one::<u32>(&id).is_ok();
+        if !numeric {
+            bail!(
+                "{shape}'s --{} isn't a number, so it can't be compared",
+                compare.name
+            );
+        }
+        let cli = Cli::from_arg_matches(&matches)?;
+        cli.model
+            .ok_or_else(|| eyre!("--compare needs a model subcommand, e.g. plate"))
+    };
+    Ok([parse(&compare.values[0])?, parse(&compare.values[1])?])
+}
+
+/// Draw both variants of a model side by side along X, and return all their objects' IDs.
+///
+/// The second variant is drawn where the first was, to measure it, then drawn again far enough
+/// along X that their bounding boxes don't overlap. The sidecar of each output saved afterwards
+/// says which variant is which.
+pub async fn build_both(
+    session: &mut ModelingSession,
+    compare: &Compare,
+    [first, second]: [Model; 2],
+    output: &Output,
+    validation: Option<Validation>,
+) -> Result<Vec<Uuid>> {
+    let first_ids = build(session, first, output, validation).await?;
+    let first_box = session.bounding_box(&first_ids).ok_or_else(|| {
+        eyre!(
+            "Can't tell how big the --{}={} variant is, so can't draw the other beside it",
+            compare.name,
+            compare.values[0]
+        )
+    })?;
+
+    let before = session.created();
+    let measured_ids = build(session, second.clone(), output, validation).await?;
+    let second_box = session.bounding_box(&measured_ids).ok_or_else(|| {
+        eyre!(
+            "Can't tell how big the --{}={} variant is, so can't draw it beside the other",
+            compare.name,
+            compare.values[1]
+        )
+    })?;
+    let measured: Vec<_> = session
+        .created()
+        .into_iter()
+        .filter(|id| !before.contains(id))
+        .collect();
+    transform::remove_objects(session, &measured).await?;
+
+    let width = first_box.extents().x.max(second_box.extents().x);
+    let offset_x = first_box.max.x - second_box.min.x + GAP * width;
+    let origin = session.placement().clone();
+    session.set_placement(Point3D {
+        x: origin.x + offset_x,
+        ..origin.clone()
+    });
+    let second_ids = build(session, second, output, validation).await;
+    session.set_placement(origin);
+    let second_ids = second_ids?;
+
+    // Frame both variants, whichever way the models pointed the camera.
+    session.forget_framing();
+    session.set_comparison(Comparison {
+        option: compare.name.clone(),
+        variants: vec![
+            Variant {
+                value: compare.values[0].clone(),
+                offset_x: 0.0,
+            },
+            Variant {
+                value: compare.values[1].clone(),
+                offset_x,
+            },
+        ],
+    });
+    say!(
+        "Drew --{}={} on the left, and --{}={} on the right",
+        compare.name,
+        compare.values[0],
+        compare.name,
+        compare.values[1]
+    );
+    Ok(first_ids.into_iter().chain(second_ids).collect())
+}
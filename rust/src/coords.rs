@@ -0,0 +1,204 @@
+use crate::transform;
+use color_eyre::Result;
+use kittycad::types::Point3D;
+use std::sync::Mutex;
+
+/// Which axis points up, in the tool the run's points came from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Up {
+    /// Like the API, and most CAD tools.
+    #[default]
+    Z,
+    /// Like Blender's exports, and most game engines.
+    Y,
+}
+
+/// Which way the axes turn, in the tool the run's points came from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Handedness {
+    /// Like the API, glTF, and most CAD tools.
+    #[default]
+    Right,
+    /// Like Unity (Y up) and Unreal (Z up).
+    Left,
+}
+
+/// The coordinate system of the points a run was given, e.g. in CSV profiles, SVG outlines,
+/// scene files' "at", and options like --at and --translate.
+/// The API's is Z-up and right-handed, so points from anywhere else are converted before they're sent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CoordinateSystem {
+    ZUp(Handedness),
+    YUp(Handedness),
+}
+
+impl Default for CoordinateSystem {
+    /// The API's own, so nothing is converted.
+    fn default() -> Self {
+        Self::ZUp(Handedness::Right)
+    }
+}
+
+impl CoordinateSystem {
+    pub fn new(up: Up, handedness: Handedness) -> Self {
+        match up {
+            Up::Z => Self::ZUp(handedness),
+            Up::Y => Self::YUp(handedness),
+        }
+    }
+
+    /// Where a point (or which way a direction) in this coordinate system is, in the API's.
+    /// Each axis only moves to another, so lengths and angles are kept.
+    /// Left-handed points are mirrored as well, so the model isn't built inside out.
+    pub fn to_world(self, p: &Point3D) -> Point3D {
+        let (x, y, z) = (p.x, p.y, p.z);
+        let (x, y, z) = match self {
+            Self::ZUp(Handedness::Right) => (x, y, z),
+            // Unreal's X is forward and Y is right, so Y points the other way to the API's.
+            Self::ZUp(Handedness::Left) => (x, -y, z),
+            // Forward is -Z, so it becomes +Y.
+            Self::YUp(Handedness::Right) => (x, -z, y),
+            // Forward is +Z, so it becomes +Y.
+            Self::YUp(Handedness::Left) => (x, z, y),
+        };
+        Point3D { x, y, z }
+    }
+
+    /// Where a point on this coordinate system's ground, given as its two ground coordinates
+    /// (X and Y if Z is up, or X and Z if Y is up), is on the API's ground, the XY plane.
+    pub fn ground_to_world(self, (a, b): (f64, f64)) -> (f64, f64) {
+        let ground = match self {
+            Self::ZUp(_) => Point3D { x: a, y: b, z: 0.0 },
+            Self::YUp(_) => Point3D { x: a, y: 0.0, z: b },
+        };
+        let world = self.to_world(&ground);
+        (world.x, world.y)
+    }
+}
+
+/// The coordinate system the run's points are in, from --input-up and --input-handedness.
+static INPUT: Mutex<CoordinateSystem> = Mutex::new(CoordinateSystem::ZUp(Handedness::Right));
+
+/// Convert the run's points from this coordinate system, from --input-up and --input-handedness.
+pub fn set_input(system: CoordinateSystem) {
+    *INPUT.lock().unwrap_or_else(|e| e.into_inner()) = system;
+}
+
+/// The coordinate system the run's points are in.
+pub fn input() -> CoordinateSystem {
+    *INPUT.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// A point or direction as it was written, e.g. "20,0,0", in the run's input coordinate system.
+/// It can only be used by converting it with `world`, which gives a plain `Point3D`,
+/// so it can't be converted twice however many helpers it passes through.
+#[derive(Clone, Debug)]
+pub struct Given(Point3D);
+
+impl Given {
+    /// Parses three lengths, like "20,0,0" or "1in,0,0".
+    pub fn parse(s: &str) -> Result<Self> {
+        transform::parse_translation(s).map(Self)
+    }
+
+    /// Where it is in the API's coordinate system.
+    pub fn world(&self) -> Point3D {
+        input().to_world(&self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(x: f64, y: f64, z: f64) -> Point3D {
+        Point3D { x, y, z }
+    }
+
+    fn world(system: CoordinateSystem, points: &[Point3D]) -> Vec<(f64, f64, f64)> {
+        points
+            .iter()
+            .map(|p| system.to_world(p))
+            .map(|p| (p.x, p.y, p.z))
+            .collect()
+    }
+
+    /// A 10mm square on the ground, from the origin, forwards and to the right, 5mm up,
+    /// in the API's coordinate system.
+    fn square() -> Vec<(f64, f64, f64)> {
+        vec![
+            (0.0, 0.0, 5.0),
+            (10.0, 0.0, 5.0),
+            (10.0, 10.0, 5.0),
+            (0.0, 10.0, 5.0),
+        ]
+    }
+
+    #[test]
+    fn leaves_the_apis_own_points_alone() {
+        let points: Vec<_> = square()
+            .into_iter()
+            .map(|(x, y, z)| point(x, y, z))
+            .collect();
+        assert_eq!(world(CoordinateSystem::default(), &points), square());
+    }
+
+    #[test]
+    fn puts_a_y_up_square_where_the_z_up_one_is() {
+        // Forwards is -Z when Y is up and the axes are right-handed.
+        let right = [
+            point(0.0, 5.0, 0.0),
+            point(10.0, 5.0, 0.0),
+            point(10.0, 5.0, -10.0),
+            point(0.0, 5.0, -10.0),
+        ];
+        let system = CoordinateSystem::new(Up::Y, Handedness::Right);
+        assert_eq!(world(system, &right), square());
+        // And +Z when they're left-handed.
+        let left = [
+            point(0.0, 5.0, 0.0),
+            point(10.0, 5.0, 0.0),
+            point(10.0, 5.0, 10.0),
+            point(0.0, 5.0, 10.0),
+        ];
+        let system = CoordinateSystem::new(Up::Y, Handedness::Left);
+        assert_eq!(world(system, &left), square());
+    }
+
+    #[test]
+    fn puts_a_left_handed_z_up_square_where_the_right_handed_one_is() {
+        // Unreal's Y is to the right, so forwards is -Y.
+        let points = [
+            point(0.0, 0.0, 5.0),
+            point(10.0, 0.0, 5.0),
+            point(10.0, -10.0, 5.0),
+            point(0.0, -10.0, 5.0),
+        ];
+        let system = CoordinateSystem::new(Up::Z, Handedness::Left);
+        assert_eq!(world(system, &points), square());
+    }
+
+    #[test]
+    fn turns_up_into_the_apis_up() {
+        for handedness in [Handedness::Right, Handedness::Left] {
+            let up = CoordinateSystem::new(Up::Y, handedness).to_world(&point(0.0, 1.0, 0.0));
+            assert_eq!((up.x, up.y, up.z), (0.0, 0.0, 1.0), "{handedness:?}");
+            let up = CoordinateSystem::new(Up::Z, handedness).to_world(&point(0.0, 0.0, 1.0));
+            assert_eq!((up.x, up.y, up.z), (0.0, 0.0, 1.0), "{handedness:?}");
+        }
+    }
+
+    #[test]
+    fn puts_ground_points_on_the_apis_ground() {
+        let corners = |system: CoordinateSystem, ground: [(f64, f64); 4]| {
+            ground.map(|corner| system.ground_to_world(corner))
+        };
+        let square = [(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)];
+        let z_up = CoordinateSystem::new(Up::Z, Handedness::Right);
+        assert_eq!(corners(z_up, square), square);
+        // Ground points are X and Z when Y is up.
+        let y_up = CoordinateSystem::new(Up::Y, Handedness::Right);
+        let ground = [(0.0, 0.0), (10.0, 0.0), (10.0, -10.0), (0.0, -10.0)];
+        assert_eq!(corners(y_up, ground), square);
+    }
+}
@@ -0,0 +1,126 @@
+use crate::{
+    sink,
+    warnings::{Code, Warning},
+};
+use color_eyre::{eyre::WrapErr, Report, Result};
+use std::{
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use uuid::Uuid;
+
+/// Where raw responses are kept, unless --debug-dir says otherwise.
+pub const DEFAULT_DIR: &str = "debug";
+
+/// How big the debug directory may grow, in bytes, before its oldest files are deleted.
+pub const DEFAULT_MAX_BYTES: u64 = 100 << 20;
+
+/// Where to keep the raw payloads of responses which couldn't be handled, to attach to bug reports.
+#[derive(Clone, Debug)]
+pub struct DebugDir {
+    pub dir: PathBuf,
+    /// The most the files in the directory may add up to, in bytes. The oldest are deleted to make room.
+    pub max_bytes: u64,
+}
+
+impl Default for DebugDir {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from(DEFAULT_DIR),
+            max_bytes: DEFAULT_MAX_BYTES,
+        }
+    }
+}
+
+/// The API's response to a command couldn't be handled, e.g. its snapshot wasn't a valid PNG.
+/// Its raw payload was kept, if it could be, so it can be attached to a bug report.
+#[derive(Debug)]
+pub struct DecodeFailed {
+    pub cmd_id: Uuid,
+    /// What was being done with the response, e.g. "decode".
+    pub stage: &'static str,
+    /// Where the payload was kept.
+    pub saved: Option<PathBuf>,
+}
+
+impl std::fmt::Display for DecodeFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Could not handle the API's response to command {} ({} failed)",
+            self.cmd_id, self.stage
+        )?;
+        match &self.saved {
+            Some(path) => write!(
+                f,
+                ". Its raw payload is saved in {}, to attach to a bug report",
+                path.display()
+            ),
+            None => write!(f, ", and its raw payload couldn't be saved"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeFailed {}
+
+impl DebugDir {
+    /// Keep the raw payload of a response which couldn't be handled, and return `error`
+    /// wrapped in a `DecodeFailed` saying where it is.
+    /// If the payload can't be kept, that's printed, and the error is still returned.
+    pub fn keep(&self, cmd_id: Uuid, stage: &'static str, payload: &[u8], error: Report) -> Report {
+        let saved = match self.save(cmd_id, stage, payload) {
+            Ok(path) => Some(path),
+            Err(e) => {
+                let message = format!("Could not keep the API's response to {cmd_id}: {e:?}");
+                // The error being returned matters more, but if this is denied, the run fails at the end.
+                let _ = Warning::new(Code::DebugPayload, message)
+                    .cmd_id(cmd_id)
+                    .raise();
+                None
+            }
+        };
+        error.wrap_err(DecodeFailed {
+            cmd_id,
+            stage,
+            saved,
+        })
+    }
+
+    /// Save a payload as "<dir>/<milliseconds since the Unix epoch>-<cmd_id>-<stage>.bin",
+    /// then delete the directory's oldest files until it fits in `max_bytes` again.
+    fn save(&self, cmd_id: Uuid, stage: &str, payload: &[u8]) -> Result<PathBuf> {
+        std::fs::create_dir_all(&self.dir)
+            .wrap_err_with(|| format!("Could not create {}", self.dir.display()))?;
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis());
+        let path = self.dir.join(format!("{millis}-{cmd_id}-{stage}.bin"));
+        sink::write_atomically(&path, payload)
+            .wrap_err_with(|| format!("Could not save {}", path.display()))?;
+        self.prune(&path)?;
+        Ok(path)
+    }
+
+    /// Delete the oldest files until the directory fits in `max_bytes`, never deleting `keep`.
+    fn prune(&self, keep: &Path) -> Result<()> {
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_file() && entry.path() != keep {
+                files.push((metadata.modified()?, metadata.len(), entry.path()));
+            }
+        }
+        let mut total = std::fs::metadata(keep)?.len() + files.iter().map(|f| f.1).sum::<u64>();
+        files.sort();
+        for (_, size, path) in files {
+            if total <= self.max_bytes {
+                break;
+            }
+            std::fs::remove_file(&path)
+                .wrap_err_with(|| format!("Could not delete {}", path.display()))?;
+            total -= size;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,498 @@
+use crate::{
+    geometry::{distance, sub},
+    stroke,
+    template::Drawing,
+};
+use kittycad::types::{PathSegment, Point3D};
+
+/// How many points each arc or curve is drawn with, when checking a profile.
+const CURVE_STEPS: usize = 16;
+
+/// How small a profile's area may be, as a fraction of its perimeter squared, before it counts as
+/// having none. A square's area is 1/16 of its perimeter squared, so this only catches slivers.
+const MIN_AREA: f64 = 1e-9;
+
+/// A closed path which can't make a solid, e.g. because it has no area. The API either rejects these
+/// with an unhelpful error, or draws nothing and leaves a blank snapshot, so they're caught before
+/// they're sent (unless --allow-degenerate says not to).
+#[derive(Debug)]
+pub struct Degenerate(pub String);
+
+impl std::fmt::Display for Degenerate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The profile can't make a solid, because {}", self.0)
+    }
+}
+
+impl std::error::Error for Degenerate {}
+
+impl Degenerate {
+    /// Was this error (or anything that caused it) a degenerate profile?
+    pub fn caused(e: &color_eyre::Report) -> bool {
+        e.chain().any(|cause| cause.is::<Self>())
+    }
+}
+
+/// Say which model (and options) drew a degenerate profile, if that's what went wrong.
+pub fn explain(e: color_eyre::Report, drawing: &Drawing) -> color_eyre::Report {
+    if !Degenerate::caused(&e) {
+        return e;
+    }
+    e.wrap_err(format!(
+        "Can't draw {}. Check its options, or pass --allow-degenerate to send it anyway",
+        drawing.summary()
+    ))
+}
+
+/// Check a closed path (its start, and the segments drawn from there) encloses some area, isn't just a
+/// line, and doesn't cross itself. Arcs and curves are checked as the points along them.
+///
+/// Paths with tangential arcs aren't checked, because where those go depends on how the API
+/// works out the direction the path was heading.
+pub fn check(start: &Point3D, segments: &[PathSegment]) -> Result<(), Degenerate> {
+    let Some(points) = outline(start, segments) else {
+        return Ok(());
+    };
+    let perimeter: f64 = (0..points.len())
+        .map(|i| distance(&points[i], &points[(i + 1) % points.len()]))
+        .sum();
+    if perimeter == 0.0 {
+        return Err(Degenerate(
+            "all its points are in the same place".to_owned(),
+        ));
+    }
+    let normal = newell(&points);
+    let area = (normal.x.powi(2) + normal.y.powi(2) + normal.z.powi(2)).sqrt() / 2.0;
+    if area <= MIN_AREA * perimeter * perimeter && on_one_line(&points, perimeter) {
+        return Err(Degenerate("all its points are on one line".to_owned()));
+    }
+    // Look at it square-on, by dropping the axis it faces most along.
+    let flat: Vec<_> = if normal.z.abs() >= normal.x.abs() && normal.z.abs() >= normal.y.abs() {
+        points.iter().map(|p| (p.x, p.y)).collect()
+    } else if normal.y.abs() >= normal.x.abs() {
+        points.iter().map(|p| (p.z, p.x)).collect()
+    } else {
+        points.iter().map(|p| (p.y, p.z)).collect()
+    };
+    // A figure of eight's halves cancel out, so this comes before the area's checked.
+    if stroke::crosses_itself(&flat) {
+        return Err(Degenerate("it crosses itself".to_owned()));
+    }
+    if area <= MIN_AREA * perimeter * perimeter {
+        return Err(Degenerate(format!(
+            "it encloses no area (its area is {area:.3e}, and it's {perimeter:.3} around)"
+        )));
+    }
+    Ok(())
+}
+
+/// The corners of the polygon a closed path draws, with points along its arcs and curves.
+/// Points where the pen doesn't move are left out. None if it has tangential arcs.
+fn outline(start: &Point3D, segments: &[PathSegment]) -> Option<Vec<Point3D>> {
+    let mut points = vec![start.clone()];
+    let offset = |pen: &Point3D, p: &Point3D, relative: bool| {
+        if relative {
+            Point3D {
+                x: pen.x + p.x,
+                y: pen.y + p.y,
+                z: pen.z + p.z,
+            }
+        } else {
+            p.clone()
+        }
+    };
+    for segment in segments {
+        let pen = points.last().expect("a path has a start").clone();
+        match segment {
+            PathSegment::Line { end, relative } => points.push(offset(&pen, end, *relative)),
+            PathSegment::Bezier {
+                control1,
+                control2,
+                end,
+                relative,
+            } => {
+                let (c1, c2) = (
+                    offset(&pen, control1, *relative),
+                    offset(&pen, control2, *relative),
+                );
+                let end = offset(&pen, end, *relative);
+                for step in 1..=CURVE_STEPS {
+                    let t = step as f64 / CURVE_STEPS as f64;
+                    let u = 1.0 - t;
+                    let (a, b, c, d) = (u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t);
+                    points.push(Point3D {
+                        x: a * pen.x + b * c1.x + c * c2.x + d * end.x,
+                        y: a * pen.y + b * c1.y + c * c2.y + d * end.y,
+                        z: a * pen.z + b * c1.z + c * c2.z + d * end.z,
+                    });
+                }
+            }
+            PathSegment::Arc {
+                center,
+                radius,
+                angle_start,
+                angle_end,
+                relative,
+                ..
+            } => {
+                let (cx, cy) = if *relative {
+                    (pen.x + center.x, pen.y + center.y)
+                } else {
+                    (center.x, center.y)
+                };
+                // The API draws a line to where the arc starts, if the pen isn't there already.
+                for step in 0..=CURVE_STEPS {
+                    let degrees =
+                        angle_start + (angle_end - angle_start) * step as f64 / CURVE_STEPS as f64;
+                    let (sin, cos) = degrees.to_radians().sin_cos();
+                    points.push(Point3D {
+                        x: cx + radius * cos,
+                        y: cy + radius * sin,
+                        z: pen.z,
+                    });
+                }
+            }
+            PathSegment::TangentialArc { .. } | PathSegment::TangentialArcTo { .. } => return None,
+        }
+    }
+    // Points this close, for the path's size, are the same one give or take rounding, e.g. where
+    // relative segments add up to the start again.
+    let size = points
+        .iter()
+        .map(|p| distance(&points[0], p))
+        .fold(0.0, f64::max);
+    let same = |a: &Point3D, b: &Point3D| distance(a, b) <= size * 1e-9;
+    // The path is closed, so if it ends where it started, that's one corner, not two.
+    points.dedup_by(|a, b| same(a, b));
+    if points.len() > 1 && same(&points[0], points.last().unwrap()) {
+        points.pop();
+    }
+    Some(points)
+}
+
+/// The normal of a polygon by Newell's method. It's as long as twice the polygon's area,
+/// if the polygon is flat and doesn't cross itself.
+fn newell(points: &[Point3D]) -> Point3D {
+    let mut normal = Point3D {
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+    };
+    for (i, a) in points.iter().enumerate() {
+        let b = &points[(i + 1) % points.len()];
+        normal.x += (a.y - b.y) * (a.z + b.z);
+        normal.y += (a.z - b.z) * (a.x + b.x);
+        normal.z += (a.x - b.x) * (a.y + b.y);
+    }
+    normal
+}
+
+/// Whether every point is (nearly) on the line through the two furthest apart, i.e. the path
+/// only goes there and back.
+fn on_one_line(points: &[Point3D], perimeter: f64) -> bool {
+    let first = &points[0];
+    let Some(far) = points
+        .iter()
+        .max_by(|a, b| distance(first, a).total_cmp(&distance(first, b)))
+    else {
+        return true;
+    };
+    let along = sub(far, first);
+    let length = distance(far, first);
+    if length == 0.0 {
+        return true;
+    }
+    points.iter().all(|p| {
+        let d = sub(p, first);
+        // How far the point is from the line: the cross product's length, over the line's.
+        let cross = Point3D {
+            x: d.y * along.z - d.z * along.y,
+            y: d.z * along.x - d.x * along.z,
+            z: d.x * along.y - d.y * along.x,
+        };
+        let off = (cross.x.powi(2) + cross.y.powi(2) + cross.z.powi(2)).sqrt() / length;
+        off <= perimeter * 1e-9
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        geometry::{cross, normalize, scale},
+        jitter::SplitMix,
+        shapes, sketch,
+        tessellation::Quality,
+    };
+    use kittycad::types::{Angle, Point2D, UnitAngle};
+
+    fn point(x: f64, y: f64, z: f64) -> Point3D {
+        Point3D { x, y, z }
+    }
+
+    /// A random plane: a point on it, and two directions along it, square to each other.
+    fn plane(random: &mut SplitMix) -> (Point3D, Point3D, Point3D) {
+        let mut v = || random.between(-1.0, 1.0);
+        let origin = point(v() * 100.0, v() * 100.0, v() * 100.0);
+        let normal = normalize(&point(v(), v(), v()));
+        let across = normalize(&point(v(), v(), v()));
+        let u = normalize(&cross(&normal, &across));
+        (origin, u.clone(), cross(&normal, &u))
+    }
+
+    /// A polygon on a random plane, with a corner at each of these angles, at these distances from its middle.
+    fn polygon(random: &mut SplitMix, corners: &[(f64, f64)]) -> Vec<Point3D> {
+        let (origin, u, v) = plane(random);
+        corners
+            .iter()
+            .map(|&(degrees, radius)| {
+                let (sin, cos) = degrees.to_radians().sin_cos();
+                let (a, b) = (scale(&u, radius * cos), scale(&v, radius * sin));
+                point(
+                    origin.x + a.x + b.x,
+                    origin.y + a.y + b.y,
+                    origin.z + a.z + b.z,
+                )
+            })
+            .collect()
+    }
+
+    /// Random angles, from 0 up to 360 degrees, in order.
+    fn angles(random: &mut SplitMix, n: usize) -> Vec<f64> {
+        let mut angles: Vec<_> = (0..n).map(|_| random.between(0.0, 360.0)).collect();
+        angles.sort_by(f64::total_cmp);
+        angles
+    }
+
+    /// Check the closed path through these corners, drawn with absolute or relative lines.
+    fn check_corners(corners: &[Point3D], relative: bool) -> Result<(), Degenerate> {
+        let mut segments = Vec::new();
+        for pair in
+            corners
+                .windows(2)
+                .chain([[corners[corners.len() - 1].clone(), corners[0].clone()].as_slice()])
+        {
+            let end = if relative {
+                sub(&pair[1], &pair[0])
+            } else {
+                pair[1].clone()
+            };
+            segments.push(PathSegment::Line { end, relative });
+        }
+        check(&corners[0], &segments)
+    }
+
+    #[test]
+    fn accepts_convex_and_star_shaped_polygons() {
+        let mut random = SplitMix(1);
+        for _ in 0..2000 {
+            let n = 3 + random.below(30);
+            let size = 10_f64.powf(random.between(-2.0, 3.0));
+            // Corners too close together make a sliver, which is rightly caught.
+            let angles: Vec<_> = angles(&mut random, n);
+            if angles.windows(2).any(|pair| pair[1] - pair[0] < 1.0)
+                || 360.0 - angles[n - 1] + angles[0] < 1.0
+            {
+                continue;
+            }
+            // A star's corners may be nearer its middle or further away, so long as the middle is
+            // inside it, which it's only sure to be if no two neighbouring corners are half a turn apart.
+            let gaps_under_half_a_turn = angles.windows(2).all(|pair| pair[1] - pair[0] < 179.0)
+                && 360.0 - angles[n - 1] + angles[0] < 179.0;
+            let star = gaps_under_half_a_turn && random.coin();
+            let corners: Vec<_> = angles
+                .into_iter()
+                .map(|angle| {
+                    (
+                        angle,
+                        if star {
+                            size * random.between(0.3, 1.0)
+                        } else {
+                            size
+                        },
+                    )
+                })
+                .collect();
+            // The angles may all be on one side, so the polygon may be thin, but never a line.
+            let corners = polygon(&mut random, &corners);
+            let relative = random.coin();
+            if let Err(e) = check_corners(&corners, relative) {
+                panic!("{corners:?} is a simple polygon, but {e}");
+            }
+        }
+    }
+
+    #[test]
+    fn catches_polygons_which_cross_themselves() {
+        let mut random = SplitMix(2);
+        for _ in 0..2000 {
+            let angles = angles(&mut random, 4);
+            if angles.windows(2).any(|pair| pair[1] - pair[0] < 10.0)
+                || 360.0 - angles[3] + angles[0] < 10.0
+            {
+                continue;
+            }
+            // Going from each corner to the one opposite crosses the middle twice: a bow tie.
+            let order = [0, 2, 1, 3];
+            let corners: Vec<_> = order.iter().map(|&i| (angles[i], 10.0)).collect();
+            let corners = polygon(&mut random, &corners);
+            let e = check_corners(&corners, false).expect_err("a bow tie crosses itself");
+            assert_eq!(e.0, "it crosses itself", "{corners:?}");
+        }
+    }
+
+    #[test]
+    fn catches_paths_along_one_line() {
+        let mut random = SplitMix(3);
+        for _ in 0..2000 {
+            let (origin, along, _) = plane(&mut random);
+            let n = 3 + random.below(10);
+            let corners: Vec<_> = (0..n)
+                .map(|_| {
+                    let step = scale(&along, random.between(-100.0, 100.0));
+                    point(origin.x + step.x, origin.y + step.y, origin.z + step.z)
+                })
+                .collect();
+            let e = check_corners(&corners, random.coin())
+                .expect_err("a path along a line has no area");
+            assert!(
+                e.0 == "all its points are on one line" || e.0 == "it crosses itself",
+                "{corners:?}: {e}"
+            );
+        }
+        let same = vec![point(1.0, 2.0, 3.0); 4];
+        let e = check_corners(&same, false).expect_err("a path which stays put has no area");
+        assert_eq!(e.0, "all its points are in the same place");
+    }
+
+    #[test]
+    fn accepts_circles_drawn_as_one_arc() {
+        let mut random = SplitMix(4);
+        let degrees = |value| Angle {
+            unit: UnitAngle::Degrees,
+            value,
+        };
+        for _ in 0..500 {
+            let (x, y, z) = (
+                random.between(-100.0, 100.0),
+                random.between(-100.0, 100.0),
+                random.between(-100.0, 100.0),
+            );
+            let radius = 10_f64.powf(random.between(-2.0, 3.0));
+            let arc = PathSegment::Arc {
+                center: Point2D { x, y },
+                radius,
+                start: Some(degrees(0.0)),
+                end: Some(degrees(360.0)),
+                angle_start: 0.0,
+                angle_end: 360.0,
+                relative: false,
+            };
+            if let Err(e) = check(&point(x + radius, y, z), &[arc]) {
+                panic!("A circle of radius {radius} at ({x}, {y}, {z}) is fine, but {e}");
+            }
+        }
+    }
+
+    /// A size from a hundredth of a millimetre to a metre, as likely to be small as big.
+    fn size(random: &mut SplitMix) -> f64 {
+        10_f64.powf(random.between(-2.0, 3.0))
+    }
+
+    /// Check a built-in shape's profile is accepted.
+    fn assert_accepted(shape: &str, corners: &[Point3D]) {
+        if let Err(e) = check_corners(corners, false) {
+            panic!("{shape} {corners:?} is fine, but {e}");
+        }
+    }
+
+    fn flat(corners: &[(f64, f64)]) -> Vec<Point3D> {
+        corners.iter().map(|&(x, y)| point(x, y, 0.0)).collect()
+    }
+
+    #[test]
+    fn accepts_the_built_in_shapes_whatever_their_options() {
+        let mut random = SplitMix(5);
+        let qualities = [
+            Quality::Low,
+            Quality::Medium,
+            Quality::High,
+            Quality::Custom(0.003),
+        ];
+        for _ in 0..200 {
+            let center = point(
+                random.between(-100.0, 100.0),
+                random.between(-100.0, 100.0),
+                random.between(-100.0, 100.0),
+            );
+            // Cubes, and plates, which are squares and rectangles.
+            assert_accepted(
+                "A cube",
+                &shapes::square_corners(&center, size(&mut random)),
+            );
+            let (p1, p2) = (
+                (center.x, center.y),
+                (center.x + size(&mut random), center.y - size(&mut random)),
+            );
+            assert_accepted(
+                "A rectangle",
+                &flat(&sketch::rectangle_between(p1, p2).unwrap()),
+            );
+            let (width, height, rotation) = (
+                size(&mut random),
+                size(&mut random),
+                random.between(-360.0, 360.0),
+            );
+            let corners = sketch::rectangle_around(p1, width, height, rotation).unwrap();
+            assert_accepted("A turned rectangle", &flat(&corners));
+            assert_accepted(
+                "An L bracket",
+                &shapes::l_bracket_corners(size(&mut random), size(&mut random)),
+            );
+            // Holes, pins, discs and bosses, which are circles, drawn as lines at each quality.
+            let radius = size(&mut random);
+            for quality in qualities {
+                assert_accepted(
+                    &format!("A circle at {quality} quality"),
+                    &shapes::circle_corners(&center, radius, quality),
+                );
+            }
+            // Slots, drawn as lines.
+            let slot = sketch::Slot::new(
+                p1,
+                (
+                    p1.0 + random.between(-100.0, 100.0),
+                    p1.1 + random.between(-100.0, 100.0),
+                ),
+                size(&mut random),
+            )
+            .unwrap();
+            for quality in qualities {
+                assert_accepted(
+                    &format!("A slot at {quality} quality"),
+                    &flat(&slot.corners(quality)),
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn accepts_any_wall_which_can_be_stroked() {
+        let mut random = SplitMix(6);
+        let mut stroked = 0;
+        for _ in 0..2000 {
+            let n = 2 + random.below(5);
+            let path: Vec<_> = (0..n)
+                .map(|_| (random.between(-100.0, 100.0), random.between(-100.0, 100.0)))
+                .collect();
+            let (thickness, miter_limit) = (random.between(0.1, 20.0), random.between(1.0, 10.0));
+            // Paths whose walls would cross are rightly refused before they're checked.
+            let Ok(stroke) = stroke::outline(&path, thickness, miter_limit) else {
+                continue;
+            };
+            stroked += 1;
+            assert_accepted(&format!("A wall along {path:?}"), &flat(&stroke.outline));
+        }
+        assert!(stroked > 500, "Only {stroked} paths could be stroked");
+    }
+}
@@ -0,0 +1,347 @@
+use crate::{
+    coords::Given,
+    geometry::{distance, sub},
+    label, measure,
+    session::ModelingSession,
+    units::SCENE_UNIT,
+};
+use color_eyre::{
+    eyre::{bail, WrapErr},
+    Result,
+};
+use image::{Rgba, RgbaImage};
+use kittycad::types::Point3D;
+use uuid::Uuid;
+
+/// A dimension to draw on a scene's snapshot, from its file's "annotations", e.g.
+/// { "from": "0,0,0", "to": "40,0,0" } or { "edge": "<an edge's ID>", "offset": -30 }.
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct Annotation {
+    /// One end of what's measured, as "x,y,z", like a part's "at".
+    from: Option<String>,
+    /// The other end.
+    to: Option<String>,
+    /// Instead of two points, an edge to measure from one end to the other, e.g. from --inspect.
+    edge: Option<Uuid>,
+    /// How far from what's measured the dimension line is drawn, in pixels. It's drawn towards the top
+    /// of the image (or the left, for an upright line), or the other way if this is negative.
+    #[serde(default = "default_offset")]
+    offset: f64,
+}
+
+fn default_offset() -> f64 {
+    24.0
+}
+
+/// A dimension ready to draw: two points in the scene, and how far from them to draw its line.
+#[derive(Clone, Debug)]
+pub struct DimensionLine {
+    pub from: Point3D,
+    pub to: Point3D,
+    /// In pixels, as `Annotation::offset`.
+    pub offset: f64,
+}
+
+/// A dimension line's ends as they appear in a snapshot, in pixels from its top-left corner,
+/// and the text to write along it.
+#[derive(Clone, Debug)]
+pub struct Projected {
+    pub from: (f64, f64),
+    pub to: (f64, f64),
+    pub offset: f64,
+    pub text: String,
+}
+
+impl Annotation {
+    /// Check the annotation measures either two points or an edge, along one of the axes.
+    pub fn check(&self) -> Result<()> {
+        if !self.offset.is_finite() {
+            bail!(
+                "\"offset\" should be a number of pixels, not {}",
+                self.offset
+            );
+        }
+        match (&self.from, &self.to, self.edge) {
+            (Some(from), Some(to), None) => {
+                let from =
+                    Given::parse(from).wrap_err("\"from\" should be a point, e.g. \"0,0,0\"")?;
+                let to = Given::parse(to).wrap_err("\"to\" should be a point, e.g. \"40,0,0\"")?;
+                check_along_axis(&from.world(), &to.world())
+            }
+            (None, None, Some(_)) => Ok(()),
+            _ => bail!("An annotation needs either \"from\" and \"to\", or an \"edge\""),
+        }
+    }
+
+    /// Where the annotation's ends are in the scene. Points are moved like the run's models (see --at),
+    /// and edges are measured.
+    pub async fn resolve(&self, session: &mut ModelingSession) -> Result<DimensionLine> {
+        let (from, to) = match (&self.from, &self.to, self.edge) {
+            (Some(from), Some(to), None) => (
+                session.place(&Given::parse(from)?.world()),
+                session.place(&Given::parse(to)?.world()),
+            ),
+            (None, None, Some(edge)) => {
+                let ends = measure::edge_end_points(session, &[edge]).await?;
+                let Some((start, end)) = ends.into_iter().next() else {
+                    bail!("Could not measure edge {edge}");
+                };
+                check_along_axis(&start, &end)
+                    .wrap_err_with(|| format!("Can't dimension edge {edge}"))?;
+                (start, end)
+            }
+            _ => bail!("An annotation needs either \"from\" and \"to\", or an \"edge\""),
+        };
+        Ok(DimensionLine {
+            from,
+            to,
+            offset: self.offset,
+        })
+    }
+}
+
+/// Only dimensions along the X, Y or Z axis can be drawn so far.
+fn check_along_axis(from: &Point3D, to: &Point3D) -> Result<()> {
+    let d = sub(to, from);
+    let length = distance(from, to);
+    if length == 0.0 {
+        bail!(
+            "A dimension's ends must be apart, but both are at ({}, {}, {})",
+            from.x,
+            from.y,
+            from.z
+        );
+    }
+    let off_axis = [d.x.abs(), d.y.abs(), d.z.abs()]
+        .iter()
+        .filter(|&&c| c > length * 1e-6)
+        .count();
+    if off_axis > 1 {
+        bail!(
+            "Only dimensions along the X, Y or Z axis can be drawn, but ({}, {}, {}) to ({}, {}, {}) isn't",
+            from.x, from.y, from.z, to.x, to.y, to.z
+        );
+    }
+    Ok(())
+}
+
+impl DimensionLine {
+    /// How long it is, e.g. "40 mm".
+    pub fn text(&self) -> String {
+        let length = format!("{:.2}", distance(&self.from, &self.to));
+        let length = length.trim_end_matches('0').trim_end_matches('.');
+        format!("{length} {}", SCENE_UNIT)
+    }
+}
+
+/// Where the dimension lines appear in a snapshot `width` by `height` pixels, taken with the camera
+/// pointed by `zoom_to_fit`.
+///
+/// The API (as of this version of the kittycad crate) can't say where a point appears on screen,
+/// so like cropping to an object, this projects the points with the camera we pointed.
+pub fn project(
+    session: &ModelingSession,
+    lines: &[DimensionLine],
+    width: u32,
+    height: u32,
+) -> Result<Vec<Projected>> {
+    if lines.is_empty() {
+        return Ok(Vec::new());
+    }
+    let Some(camera) = session.pointed() else {
+        bail!("Can't draw dimensions, because the camera hasn't been pointed at anything yet");
+    };
+    let (w, h) = (f64::from(width), f64::from(height));
+    let to_pixels = |p: &Point3D| {
+        camera
+            .project(p, w / h)
+            .map(|(across, down)| (across * w, down * h))
+    };
+    lines
+        .iter()
+        .map(|line| {
+            let (Some(from), Some(to)) = (to_pixels(&line.from), to_pixels(&line.to)) else {
+                bail!(
+                    "Can't draw the {} dimension, because it's behind the camera",
+                    line.text()
+                );
+            };
+            Ok(Projected {
+                from,
+                to,
+                offset: line.offset,
+                text: line.text(),
+            })
+        })
+        .collect()
+}
+
+/// Draw a dimension onto an image: a line with arrowheads at both ends, offset from what's measured,
+/// lines out to it from the measured points, and its length written beside it on a patch of `paper`.
+/// Everything is drawn in `ink`, and sized for the image like captions are.
+pub fn draw(img: &mut RgbaImage, dimension: &Projected, ink: Rgba<u8>, paper: Rgba<u8>) {
+    let scale = label::scale_for(img.width());
+    let s = f64::from(scale);
+    let (a, b) = (dimension.from, dimension.to);
+    let length = (b.0 - a.0).hypot(b.1 - a.1);
+    let along = if length > 0.0 {
+        ((b.0 - a.0) / length, (b.1 - a.1) / length)
+    } else {
+        (1.0, 0.0)
+    };
+    // Square to the line, pointing up the image, or left if the line is upright.
+    let mut normal = (-along.1, along.0);
+    if normal.1 > 1e-9 || (normal.1.abs() <= 1e-9 && normal.0 > 0.0) {
+        normal = (-normal.0, -normal.1);
+    }
+    if dimension.offset < 0.0 {
+        normal = (-normal.0, -normal.1);
+    }
+    let at = |p: (f64, f64), by: f64| (p.0 + normal.0 * by, p.1 + normal.1 * by);
+    let offset = dimension.offset.abs() * s;
+
+    // Lines out from what's measured, from a little way off it to a little past the dimension line.
+    for end in [a, b] {
+        line(img, at(end, 3.0 * s), at(end, offset + 4.0 * s), scale, ink);
+    }
+    let (a, b) = (at(a, offset), at(b, offset));
+    line(img, a, b, scale, ink);
+    let (arrow, half_width) = (8.0 * s, 3.0 * s);
+    for (tip, inwards) in [(a, along), (b, (-along.0, -along.1))] {
+        let base = (tip.0 + inwards.0 * arrow, tip.1 + inwards.1 * arrow);
+        triangle(img, tip, at(base, half_width), at(base, -half_width), ink);
+    }
+
+    // The length, beside the middle of the line.
+    let (text_width, text_height) = label::text_size(&dimension.text, scale);
+    let (w, h) = (f64::from(text_width), f64::from(text_height));
+    // Far enough out that the text's box clears the line, whichever way the line runs.
+    let clear = (w * normal.0.abs() + h * normal.1.abs()) / 2.0 + 4.0 * s;
+    let middle = at(((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0), clear);
+    let left = (middle.0 - w / 2.0).round().max(0.0) as u32;
+    let top = (middle.1 - h / 2.0).round().max(0.0) as u32;
+    let pad = 2 * scale;
+    for y in top.saturating_sub(pad)..(top + text_height + pad).min(img.height()) {
+        for x in left.saturating_sub(pad)..(left + text_width + pad).min(img.width()) {
+            img.put_pixel(x, y, paper);
+        }
+    }
+    label::draw_text(img, &dimension.text, left, top, scale, ink);
+}
+
+/// Draw a straight line `thickness` pixels wide. Parts outside the image are left out.
+fn line(img: &mut RgbaImage, from: (f64, f64), to: (f64, f64), thickness: u32, ink: Rgba<u8>) {
+    let steps = ((to.0 - from.0).hypot(to.1 - from.1) * 2.0).ceil().min(1e6) as u32 + 1;
+    let half = f64::from(thickness) / 2.0;
+    for step in 0..=steps {
+        let t = f64::from(step) / f64::from(steps);
+        let (x, y) = (from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t);
+        let (left, top) = ((x - half).round(), (y - half).round());
+        for dy in 0..thickness {
+            for dx in 0..thickness {
+                put(img, left + f64::from(dx), top + f64::from(dy), ink);
+            }
+        }
+    }
+}
+
+/// Fill a triangle. Parts outside the image are left out.
+fn triangle(img: &mut RgbaImage, a: (f64, f64), b: (f64, f64), c: (f64, f64), ink: Rgba<u8>) {
+    // Which side of the line from p to q r is on.
+    let side = |p: (f64, f64), q: (f64, f64), r: (f64, f64)| {
+        (q.0 - p.0) * (r.1 - p.1) - (q.1 - p.1) * (r.0 - p.0)
+    };
+    let (left, right) = (a.0.min(b.0).min(c.0).floor(), a.0.max(b.0).max(c.0).ceil());
+    let (top, bottom) = (a.1.min(b.1).min(c.1).floor(), a.1.max(b.1).max(c.1).ceil());
+    let (left, top) = (left.max(0.0), top.max(0.0));
+    let right = right.min(f64::from(img.width()));
+    let bottom = bottom.min(f64::from(img.height()));
+    let mut y = top;
+    while y < bottom {
+        let mut x = left;
+        while x < right {
+            // Test the middle of the pixel.
+            let p = (x + 0.5, y + 0.5);
+            let sides = [side(a, b, p), side(b, c, p), side(c, a, p)];
+            if sides.iter().all(|&s| s >= 0.0) || sides.iter().all(|&s| s <= 0.0) {
+                put(img, x, y, ink);
+            }
+            x += 1.0;
+        }
+        y += 1.0;
+    }
+}
+
+/// Colour a pixel, if it's in the image.
+fn put(img: &mut RgbaImage, x: f64, y: f64, ink: Rgba<u8>) {
+    if x >= 0.0 && y >= 0.0 && x < f64::from(img.width()) && y < f64::from(img.height()) {
+        img.put_pixel(x as u32, y as u32, ink);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn annotation(annotation: serde_json::Value) -> Annotation {
+        serde_json::from_value(annotation).unwrap()
+    }
+
+    fn point(x: f64, y: f64, z: f64) -> Point3D {
+        Point3D { x, y, z }
+    }
+
+    #[test]
+    fn accepts_dimensions_along_an_axis() {
+        annotation(json!({ "from": "0,0,0", "to": "40,0,0" }))
+            .check()
+            .unwrap();
+        annotation(json!({ "from": "1in,0,0", "to": "1in,0,-2cm", "offset": -30 }))
+            .check()
+            .unwrap();
+        annotation(json!({ "edge": Uuid::from_u128(1) }))
+            .check()
+            .unwrap();
+    }
+
+    #[test]
+    fn rejects_dimensions_it_cant_draw() {
+        let error = |json| {
+            let e = annotation(json).check().unwrap_err();
+            e.chain()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(": ")
+        };
+        assert_eq!(
+            error(json!({ "from": "0,0,0", "to": "40,30,0" })),
+            "Only dimensions along the X, Y or Z axis can be drawn, but (0, 0, 0) to (40, 30, 0) isn't"
+        );
+        assert_eq!(
+            error(json!({ "from": "1,2,3", "to": "1,2,3" })),
+            "A dimension's ends must be apart, but both are at (1, 2, 3)"
+        );
+        assert_eq!(
+            error(json!({ "from": "0,0,0" })),
+            "An annotation needs either \"from\" and \"to\", or an \"edge\""
+        );
+        assert!(error(json!({ "from": "0,0", "to": "1,0,0" }))
+            .starts_with("\"from\" should be a point, e.g. \"0,0,0\""));
+    }
+
+    #[test]
+    fn writes_lengths_without_trailing_zeros() {
+        let text = |to| {
+            DimensionLine {
+                from: point(0.0, 0.0, 0.0),
+                to,
+                offset: 24.0,
+            }
+            .text()
+        };
+        assert_eq!(text(point(40.0, 0.0, 0.0)), "40 mm");
+        assert_eq!(text(point(0.0, 25.4, 0.0)), "25.4 mm");
+        assert_eq!(text(point(0.0, 0.0, -1.0 / 3.0)), "0.33 mm");
+    }
+}
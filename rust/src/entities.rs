@@ -0,0 +1,227 @@
+use crate::session::ModelingSession;
+use color_eyre::{eyre::bail, Result};
+use kittycad::types::{EntityType, ModelingCmd, OkModelingCmdResponse};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+};
+use uuid::Uuid;
+
+/// An entity in the API's scene, and everything inside it.
+/// For example, a solid contains faces, which contain edges.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct EntityTree {
+    pub id: Uuid,
+    pub entity_type: EntityType,
+    pub children: Vec<EntityTree>,
+}
+
+impl EntityTree {
+    /// Ask the API for an entity's type and children, and then their types and children, and so on.
+    pub async fn query(session: &mut ModelingSession, root: Uuid) -> Result<Self> {
+        // Walk the tree iteratively, rather than recursively, so deep trees don't need deep async stacks.
+        // Each entity we find is stored flat, with the indices of its children,
+        // and the tree gets assembled at the end.
+        let mut found: Vec<(Uuid, EntityType, Vec<usize>)> = Vec::new();
+        let mut to_visit: Vec<(Uuid, Option<usize>)> = vec![(root, None)];
+        while let Some((id, parent)) = to_visit.pop() {
+            // Send both queries before waiting for either, so we only wait for one round trip.
+            let type_cmd = session
+                .send(ModelingCmd::GetEntityType { entity_id: id })
+                .await?;
+            let children_cmd = session
+                .send(ModelingCmd::EntityGetAllChildUuids { entity_id: id })
+                .await?;
+            let OkModelingCmdResponse::GetEntityType { data } =
+                session.await_response(type_cmd).await?
+            else {
+                bail!("Expected the API to respond to GetEntityType with a type");
+            };
+            // Entities without children respond with an empty list.
+            let OkModelingCmdResponse::EntityGetAllChildUuids { data: children } =
+                session.await_response(children_cmd).await?
+            else {
+                bail!("Expected the API to respond to EntityGetAllChildUuids with a list of IDs");
+            };
+
+            let index = found.len();
+            found.push((id, data.entity_type, Vec::new()));
+            if let Some(parent) = parent {
+                found[parent].2.push(index);
+            }
+            // Visit the children in order, by pushing them onto the stack in reverse.
+            for child in children.entity_ids.into_iter().rev() {
+                to_visit.push((child, Some(index)));
+            }
+        }
+        Ok(Self::assemble(&found, 0))
+    }
+
+    fn assemble(found: &[(Uuid, EntityType, Vec<usize>)], index: usize) -> Self {
+        let (id, entity_type, children) = &found[index];
+        Self {
+            id: *id,
+            entity_type: entity_type.clone(),
+            children: children.iter().map(|&i| Self::assemble(found, i)).collect(),
+        }
+    }
+
+    /// How many entities of each type are in the tree (including the root).
+    pub fn count_by_type(&self) -> BTreeMap<String, usize> {
+        let mut counts = BTreeMap::new();
+        let mut to_visit = vec![self];
+        while let Some(entity) = to_visit.pop() {
+            *counts.entry(entity.entity_type.to_string()).or_default() += 1;
+            to_visit.extend(&entity.children);
+        }
+        counts
+    }
+
+    /// The IDs of every entity of the given type in the tree.
+    /// An entity can appear more than once (e.g. an edge belongs to two faces), but is only counted once.
+    pub fn ids_of_type(&self, entity_type: &EntityType) -> BTreeSet<Uuid> {
+        let mut ids = BTreeSet::new();
+        let mut to_visit = vec![self];
+        while let Some(entity) = to_visit.pop() {
+            if &entity.entity_type == entity_type {
+                ids.insert(entity.id);
+            }
+            to_visit.extend(&entity.children);
+        }
+        ids
+    }
+
+    /// Draw the tree as indented text, one entity per line, followed by how many of each type there are.
+    pub fn format(&self) -> String {
+        let mut out = String::new();
+        let mut to_visit = vec![(self, 0)];
+        while let Some((entity, depth)) = to_visit.pop() {
+            let indent = "  ".repeat(depth);
+            writeln!(out, "{indent}{} {}", entity.entity_type, entity.id).unwrap();
+            to_visit.extend(entity.children.iter().rev().map(|child| (child, depth + 1)));
+        }
+        let counts: Vec<_> = self
+            .count_by_type()
+            .into_iter()
+            .map(|(entity_type, count)| format!("{count} {entity_type}"))
+            .collect();
+        writeln!(out, "Total: {}", counts.join(", ")).unwrap();
+        out
+    }
+}
+
+impl ModelingSession {
+    /// How many faces does this solid have?
+    pub async fn count_faces(&mut self, object_id: Uuid) -> Result<usize> {
+        let tree = EntityTree::query(self, object_id).await?;
+        Ok(tree.ids_of_type(&EntityType::Face).len())
+    }
+
+    /// How many edges does this solid have?
+    pub async fn count_edges(&mut self, object_id: Uuid) -> Result<usize> {
+        let tree = EntityTree::query(self, object_id).await?;
+        Ok(tree.ids_of_type(&EntityType::Edge).len())
+    }
+}
+
+/// Check that a solid has as many faces and edges as it should after some operation, e.g. an extrude.
+pub async fn verify_topology(
+    session: &mut ModelingSession,
+    object_id: Uuid,
+    after: &str,
+    expected_faces: usize,
+    expected_edges: usize,
+) -> Result<()> {
+    let faces = session.count_faces(object_id).await?;
+    let edges = session.count_edges(object_id).await?;
+    say!("After {after}: {faces} faces, {edges} edges");
+    if (faces, edges) != (expected_faces, expected_edges) {
+        bail!(
+            "After {after}, expected {object_id} to have {expected_faces} faces and {expected_edges} edges, \
+             but it has {faces} faces and {edges} edges"
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    /// The responses to querying an entity of this type, with these children.
+    fn entity(entity_type: &str, children: &[Uuid]) -> [(&'static str, Value); 2] {
+        [
+            (
+                "get_entity_type",
+                json!({ "type": "get_entity_type", "data": { "entity_type": entity_type } }),
+            ),
+            (
+                "entity_get_all_child_uuids",
+                json!({ "type": "entity_get_all_child_uuids", "data": { "entity_ids": children } }),
+            ),
+        ]
+    }
+
+    fn query(test: &str, responses: &[[(&'static str, Value); 2]], root: Uuid) -> EntityTree {
+        let mut session = ModelingSession::canned(test, &responses.concat());
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(EntityTree::query(&mut session, root))
+            .unwrap()
+    }
+
+    #[test]
+    fn draws_a_solid_and_everything_in_it() {
+        let [solid, top, side, rim, shared] = [1, 2, 3, 4, 5].map(Uuid::from_u128);
+        // The entities are asked about depth first, in order.
+        let tree = query(
+            "solid",
+            &[
+                entity("solid3d", &[top, side]),
+                entity("face", &[rim, shared]),
+                entity("edge", &[]),
+                entity("edge", &[]),
+                entity("face", &[shared]),
+                entity("edge", &[]),
+            ],
+            solid,
+        );
+        assert_eq!(
+            tree.format(),
+            format!(
+                "solid3d {solid}\n  face {top}\n    edge {rim}\n    edge {shared}\n  \
+                 face {side}\n    edge {shared}\nTotal: 3 edge, 2 face, 1 solid3d\n"
+            )
+        );
+        // The edge two faces share is only one edge.
+        assert_eq!(tree.ids_of_type(&EntityType::Edge), [rim, shared].into());
+        assert_eq!(tree.ids_of_type(&EntityType::Face), [top, side].into());
+    }
+
+    #[test]
+    fn draws_an_entity_with_no_children() {
+        let edge = Uuid::from_u128(7);
+        let tree = query("leaf", &[entity("edge", &[])], edge);
+        assert!(tree.children.is_empty());
+        assert_eq!(tree.format(), format!("edge {edge}\nTotal: 1 edge\n"));
+        assert_eq!(tree.count_by_type(), [("edge".to_owned(), 1)].into());
+    }
+
+    #[test]
+    fn draws_faces_with_no_edges() {
+        let [solid, face] = [1, 2].map(Uuid::from_u128);
+        let tree = query(
+            "no-edges",
+            &[entity("solid3d", &[face]), entity("face", &[])],
+            solid,
+        );
+        assert_eq!(
+            tree.format(),
+            format!("solid3d {solid}\n  face {face}\nTotal: 1 face, 1 solid3d\n")
+        );
+        assert!(tree.ids_of_type(&EntityType::Edge).is_empty());
+    }
+}
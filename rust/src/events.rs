@@ -0,0 +1,293 @@
+use futures::task::AtomicWaker;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    task::Poll,
+    time::Duration,
+};
+use uuid::Uuid;
+
+pub use crate::{notify::RunReport, timings::Phase, warnings::Warning};
+
+/// Something which happened during a run.
+#[derive(Clone, Debug)]
+pub enum Event {
+    /// A session connected to the API.
+    Connected,
+    /// The session sent its `n`th command. `of` counts the rest of the batch it was sent in, too,
+    /// so a batch's progress goes from 1 of 50 to 50 of 50.
+    CommandSent { n: usize, of: usize },
+    /// The API responded to a command, successfully or not.
+    ResponseReceived { cmd_id: Uuid, ok: bool },
+    /// The session started spending its time on a phase, e.g. waiting for a snapshot.
+    PhaseStarted(Phase),
+    /// The session finished a phase, after this long in it.
+    PhaseFinished { phase: Phase, elapsed: Duration },
+    /// A warning, the first time it's raised.
+    Warning(Warning),
+    /// An output was saved, as `path`, and ended up at `location`, e.g. the same path, or a URL.
+    ArtifactWritten { path: String, location: String },
+    /// The run finished, successfully or not. It's always the last event.
+    Finished(Arc<RunReport>),
+}
+
+impl Event {
+    /// Whether the event may be dropped when the reader falls behind.
+    fn is_routine(&self) -> bool {
+        matches!(
+            self,
+            Self::CommandSent { .. }
+                | Self::ResponseReceived { .. }
+                | Self::PhaseStarted(_)
+                | Self::PhaseFinished { .. }
+        )
+    }
+}
+
+/// The events waiting to be read, shared by `emit` and the `Receiver`.
+struct Queue {
+    events: Mutex<Pending>,
+    waker: AtomicWaker,
+}
+
+struct Pending {
+    events: VecDeque<Event>,
+    capacity: usize,
+    /// How many routine events were dropped, because the reader fell behind.
+    dropped: usize,
+    /// Whether no more events will come, because another reader took over.
+    closed: bool,
+}
+
+/// Where events go, if anything's listening.
+static LISTENER: Mutex<Option<Arc<Queue>>> = Mutex::new(None);
+
+/// Start queueing the run's progress events, for a program embedding the library (e.g. a GUI)
+/// to follow the run without parsing its output. Read them from the `Receiver` in another task.
+///
+/// Queueing an event never waits for the reader. The queue holds `capacity` events (at least 1),
+/// and once it's full, the oldest routine event (a command sent, a response received, or a phase
+/// starting or finishing) is dropped to make room. The others (connecting, warnings, artifacts, and
+/// the run finishing) are never dropped, so the queue grows past its capacity to keep them.
+/// So a slow reader only misses routine events, and never holds up the session's WebSocket.
+///
+/// Only one reader listens at a time, so an earlier one stops receiving.
+pub fn listen(capacity: usize) -> Receiver {
+    let queue = Arc::new(Queue {
+        events: Mutex::new(Pending {
+            events: VecDeque::new(),
+            capacity: capacity.max(1),
+            dropped: 0,
+            closed: false,
+        }),
+        waker: AtomicWaker::new(),
+    });
+    let mut listener = LISTENER.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(earlier) = listener.replace(queue.clone()) {
+        earlier
+            .events
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .closed = true;
+        earlier.waker.wake();
+    }
+    Receiver { queue }
+}
+
+/// Queue an event for the reader, if there is one. Never waits.
+pub fn emit(event: Event) {
+    let Some(queue) = LISTENER.lock().unwrap_or_else(|e| e.into_inner()).clone() else {
+        return;
+    };
+    {
+        let mut pending = queue.events.lock().unwrap_or_else(|e| e.into_inner());
+        if pending.events.len() >= pending.capacity {
+            let oldest_routine = pending.events.iter().position(Event::is_routine);
+            match oldest_routine {
+                Some(i) => {
+                    pending.events.remove(i);
+                    pending.dropped += 1;
+                }
+                // Nothing can be dropped, so only a routine event is turned away.
+                None if event.is_routine() => {
+                    pending.dropped += 1;
+                    return;
+                }
+                None => {}
+            }
+        }
+        pending.events.push_back(event);
+    }
+    queue.waker.wake();
+}
+
+/// Reads the events queued since `listen` was called.
+pub struct Receiver {
+    queue: Arc<Queue>,
+}
+
+impl Receiver {
+    /// The next event, waiting for one if need be. None once another reader has taken over.
+    pub async fn recv(&mut self) -> Option<Event> {
+        futures::future::poll_fn(|cx| {
+            self.queue.waker.register(cx.waker());
+            let mut pending = self.queue.events.lock().unwrap_or_else(|e| e.into_inner());
+            match pending.events.pop_front() {
+                Some(event) => Poll::Ready(Some(event)),
+                None if pending.closed => Poll::Ready(None),
+                None => Poll::Pending,
+            }
+        })
+        .await
+    }
+
+    /// How many routine events have been dropped so far, because this reader fell behind.
+    pub fn dropped(&self) -> usize {
+        self.queue
+            .events
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::ModelingSession;
+    use kittycad::types::ModelingCmd;
+    use std::time::Instant;
+
+    /// Only one reader listens at a time, so the tests which listen take turns.
+    static LISTENING: Mutex<()> = Mutex::new(());
+
+    /// Every event queued so far, without waiting for more.
+    fn queued(receiver: &mut Receiver) -> Vec<Event> {
+        let mut events = Vec::new();
+        while let Some(event) = futures::FutureExt::now_or_never(receiver.recv()).flatten() {
+            events.push(event);
+        }
+        events
+    }
+
+    /// The `n`s of the CommandSent events among these, in order. Other tests may emit events of
+    /// other kinds while these run.
+    fn sent(events: &[Event], of: usize) -> Vec<usize> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                Event::CommandSent { n, of: batch } if *batch == of => Some(*n),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn drops_the_oldest_routine_events_for_a_reader_which_falls_behind() {
+        let _turn = LISTENING.lock().unwrap_or_else(|e| e.into_inner());
+        let mut receiver = listen(4);
+        let of = 1_000_001;
+        emit(Event::CommandSent { n: 1, of });
+        emit(Event::Connected);
+        for n in 2..=10 {
+            emit(Event::CommandSent { n, of });
+        }
+        assert!(receiver.dropped() >= 7, "{}", receiver.dropped());
+        let events = queued(&mut receiver);
+        // The newest routine events are kept, in the order they came.
+        assert_eq!(sent(&events, of), [8, 9, 10]);
+        // Connecting isn't routine, so it's kept, though it came before them.
+        let connected = events.iter().position(|e| matches!(e, Event::Connected));
+        let eighth = events
+            .iter()
+            .position(|e| matches!(e, Event::CommandSent { n: 8, of: batch } if *batch == of));
+        assert!(connected.unwrap() < eighth.unwrap());
+    }
+
+    #[test]
+    fn never_drops_events_which_arent_routine() {
+        let _turn = LISTENING.lock().unwrap_or_else(|e| e.into_inner());
+        let mut receiver = listen(2);
+        let paths: Vec<_> = (0..5).map(|i| format!("events-test-{i}.png")).collect();
+        for path in &paths {
+            emit(Event::ArtifactWritten {
+                path: path.clone(),
+                location: path.clone(),
+            });
+        }
+        // The queue's full of events which can't be dropped, so a routine one is turned away.
+        let dropped = receiver.dropped();
+        emit(Event::CommandSent {
+            n: 1,
+            of: 1_000_002,
+        });
+        assert!(receiver.dropped() > dropped);
+        let events = queued(&mut receiver);
+        let written: Vec<_> = events
+            .iter()
+            .filter_map(|event| match event {
+                Event::ArtifactWritten { path, .. } if path.starts_with("events-test-") => {
+                    Some(path.clone())
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(written, paths);
+        assert!(sent(&events, 1_000_002).is_empty());
+    }
+
+    #[test]
+    fn never_holds_up_a_session_whose_events_arent_read() {
+        let _turn = LISTENING.lock().unwrap_or_else(|e| e.into_inner());
+        let receiver = listen(4);
+        let count = 50;
+        let responses: Vec<_> = (0..count)
+            .map(|_| {
+                (
+                    "get_entity_type",
+                    serde_json::json!({ "type": "get_entity_type", "data": { "entity_type": "face" } }),
+                )
+            })
+            .collect();
+        let mut session = ModelingSession::canned("events-unread", &responses);
+        let cmds: Vec<_> = (0..count)
+            .map(|_| ModelingCmd::GetEntityType {
+                entity_id: Uuid::new_v4(),
+            })
+            .collect();
+        let started = Instant::now();
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                for cmd_id in session.send_many(&cmds).await? {
+                    session.await_response(cmd_id).await?;
+                }
+                Ok::<_, color_eyre::Report>(())
+            })
+            .unwrap();
+        // Nothing read the events, and the session finished all the same, without waiting.
+        assert!(started.elapsed() < Duration::from_secs(5));
+        // A command sent and a response received for each, but only 4 fit in the queue.
+        assert!(
+            receiver.dropped() >= 2 * count - 4,
+            "Only {} dropped",
+            receiver.dropped()
+        );
+    }
+
+    #[test]
+    fn stops_an_earlier_reader_when_another_listens() {
+        let _turn = LISTENING.lock().unwrap_or_else(|e| e.into_inner());
+        let mut earlier = listen(4);
+        let _later = listen(4);
+        // It reads what was queued for it, and then learns there's no more.
+        queued(&mut earlier);
+        let ended = futures::FutureExt::now_or_never(earlier.recv());
+        assert!(
+            matches!(ended, Some(None)),
+            "The earlier reader still waits"
+        );
+    }
+}
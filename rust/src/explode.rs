@@ -0,0 +1,183 @@
+use crate::{
+    budget::{self, Optional},
+    camera,
+    session::ModelingSession,
+    snapshot::{self, Output},
+    transform::{self, Transform},
+};
+use color_eyre::{
+    eyre::{bail, WrapErr},
+    Result,
+};
+use kittycad::types::Point3D;
+use uuid::Uuid;
+
+/// How to explode a scene's parts, from its file's "explode", e.g. { "axes": "xy", "factor": 1.5 }.
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct Explode {
+    /// Which axes the parts move along, e.g. "xy", or "z" to stack them. Defaults to "xy".
+    #[serde(default = "both_flat_axes")]
+    pub axes: String,
+    /// How far each part moves, as a multiple of how far its middle is from the middle of the scene.
+    #[serde(default = "once")]
+    pub factor: f64,
+}
+
+fn both_flat_axes() -> String {
+    "xy".to_owned()
+}
+
+fn once() -> f64 {
+    1.0
+}
+
+impl Explode {
+    /// Check the axes are some of x, y and z, each once, and the factor is positive.
+    pub fn check(&self) -> Result<()> {
+        let mut seen = String::new();
+        for axis in self.axes.to_lowercase().chars() {
+            if !"xyz".contains(axis) || seen.contains(axis) {
+                bail!(
+                    "\"axes\" should be some of x, y and z, e.g. \"xy\", not {:?}",
+                    self.axes
+                );
+            }
+            seen.push(axis);
+        }
+        if seen.is_empty() {
+            bail!("\"axes\" should be some of x, y and z, e.g. \"xy\"");
+        }
+        if !(self.factor > 0.0 && self.factor.is_finite()) {
+            bail!(
+                "\"factor\" should be a positive number, not {}",
+                self.factor
+            );
+        }
+        Ok(())
+    }
+
+    /// How far a part whose middle is at `center` moves, away from the scene's middle.
+    fn offset(&self, center: &Point3D, centroid: &Point3D) -> Point3D {
+        let axes = self.axes.to_lowercase();
+        let along = |axis, from: f64, to: f64| {
+            if axes.contains(axis) {
+                self.factor * (from - to)
+            } else {
+                0.0
+            }
+        };
+        Point3D {
+            x: along('x', center.x, centroid.x),
+            y: along('y', center.y, centroid.y),
+            z: along('z', center.z, centroid.z),
+        }
+    }
+}
+
+/// How a scene was exploded, saved in its exploded snapshot's sidecar.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Explosion {
+    pub axes: String,
+    pub factor: f64,
+    /// The middle of the scene, which the parts moved away from.
+    pub centroid: Point3D,
+    /// How far each part moved. Fixed parts aren't listed.
+    pub parts: Vec<ExplodedPart>,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct ExplodedPart {
+    pub name: String,
+    pub offset: Point3D,
+}
+
+/// One of a scene's parts, and the objects it built.
+pub struct Part {
+    pub name: String,
+    pub object_ids: Vec<Uuid>,
+    /// Whether it stays where it is when the scene is exploded.
+    pub fixed: bool,
+}
+
+/// Save a snapshot of the scene with its parts moved apart, as "<output>-exploded.png",
+/// then put the scene back how it was.
+///
+/// The API can't move objects, so each part which moves is copied to where it belongs,
+/// and the original is hidden until the snapshot has been taken.
+pub async fn snapshot(
+    session: &mut ModelingSession,
+    parts: &[Part],
+    explode: &Explode,
+    output: &Output,
+) -> Result<()> {
+    let output = output.with_suffix("-exploded");
+    if !budget::allows(Optional::ExtraViews, &output.path)? {
+        return Ok(());
+    }
+    let all_ids: Vec<Uuid> = parts.iter().flat_map(|p| p.object_ids.clone()).collect();
+    let Some(scene_box) = session.bounding_box(&all_ids) else {
+        bail!("Can't tell where every part of the scene is, so can't explode it");
+    };
+    let centroid = scene_box.center();
+
+    let saved_camera = camera::save(session);
+    let mut explosion = Explosion {
+        axes: explode.axes.clone(),
+        factor: explode.factor,
+        centroid: centroid.clone(),
+        parts: Vec::new(),
+    };
+    let mut hidden = Vec::new();
+    let mut copies = Vec::new();
+    let mut shown = Vec::new();
+    let moved = async {
+        for part in parts {
+            if part.fixed {
+                shown.extend(&part.object_ids);
+                continue;
+            }
+            let Some(part_box) = session.bounding_box(&part.object_ids) else {
+                bail!("Can't tell where part {} is, so can't move it", part.name);
+            };
+            let offset = explode.offset(&part_box.center(), &centroid);
+            let transform = Transform {
+                translate: offset.clone(),
+                ..Transform::default()
+            };
+            for &object_id in &part.object_ids {
+                let copy_id = transform::copy_transformed(session, object_id, &transform)
+                    .await
+                    .wrap_err_with(|| format!("Could not move part {}", part.name))?;
+                copies.push(copy_id);
+                transform::set_hidden(session, object_id, true).await?;
+                hidden.push(object_id);
+            }
+            explosion.parts.push(ExplodedPart {
+                name: part.name.clone(),
+                offset,
+            });
+        }
+        shown.extend(&copies);
+        session.forget_framing();
+        if let Some(bbox) = session.bounding_box(&shown) {
+            camera::zoom_to_fit(session, &bbox).await?;
+        }
+        session.set_explosion(Some(explosion));
+        let saved = snapshot::export_png(session, &output).await;
+        session.set_explosion(None);
+        saved
+    }
+    .await;
+
+    // Put the scene back, even if the snapshot failed.
+    if !copies.is_empty() {
+        transform::remove_objects(session, &copies).await?;
+    }
+    for object_id in hidden {
+        transform::set_hidden(session, object_id, false).await?;
+    }
+    camera::restore(session, &saved_camera).await?;
+    moved?;
+    say!("Saved the exploded scene to {}", output.path);
+    Ok(())
+}
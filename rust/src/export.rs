@@ -0,0 +1,442 @@
+use crate::{
+    session::ModelingSession,
+    sidecar::OutputKind,
+    snapshot::STDOUT,
+    timings::Phase,
+    units,
+    verify::{self, MeshStats},
+    warnings::{Code, Warning},
+};
+use color_eyre::{
+    eyre::{bail, WrapErr},
+    Report, Result,
+};
+use flate2::{write::GzEncoder, Compression};
+use kittycad::types::RawFile;
+use kittycad::types::{
+    Axis, AxisDirectionPair, Direction, ModelingCmd, OutputFormat, PlyStorage, StlStorage, System,
+};
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::{Component, Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use uuid::Uuid;
+
+/// Export objects to a file, in the given format (stl, obj, ply or step).
+/// Exports all objects in the scene if `object_ids` is empty.
+/// If `compress` is true, each file is gzipped, and ".gz" is added to its name.
+/// If the path is "-", the file is written to standard output, as long as the format is only one file.
+///
+/// Exports of dense models can be large, so each exported file is written straight from the
+/// decoded response, and dropped once it's written, rather than being copied first.
+/// If one can't be written, its payload is kept in the debug directory.
+///
+/// Each mesh (stl, obj or ply) is read back, and what's in it is printed and saved in its sidecar.
+/// If it's malformed or empty, that's warned about, or fails the export if `verify` is true.
+/// Returns what's in the first file, if it's a mesh.
+pub async fn export(
+    session: &mut ModelingSession,
+    object_ids: &[Uuid],
+    path: &Path,
+    extension: &str,
+    compress: bool,
+    verify: bool,
+) -> Result<Option<MeshStats>> {
+    let exports = [(path.to_owned(), extension.to_owned())];
+    let meshes = export_many(session, object_ids, &exports, compress, verify, 1).await?;
+    Ok(meshes.into_iter().next().flatten())
+}
+
+/// Export objects to several files, each a path and the format to export it as (its extension),
+/// e.g. the same model as both stl and step, as `export` does.
+///
+/// At most `concurrency` exports are asked for at once. Each export's files are saved as soon as
+/// they arrive, in whatever order that is, and dropped once they're saved. With 1, the next export
+/// isn't asked for until the last one's files are saved, so only one export's response is ever
+/// held in memory. Returns what's in the first file of each export, in the order they were given.
+pub async fn export_many(
+    session: &mut ModelingSession,
+    object_ids: &[Uuid],
+    exports: &[(PathBuf, String)],
+    compress: bool,
+    verify: bool,
+    concurrency: usize,
+) -> Result<Vec<Option<MeshStats>>> {
+    // Check every format first, so one which can't be exported fails before anything is.
+    let formats = exports
+        .iter()
+        .map(|(path, extension)| format_for(path, extension))
+        .collect::<Result<Vec<_>>>()?;
+    let previous = session.enter_phase(Phase::Export);
+    let mut formats = formats.into_iter().enumerate();
+    // Which export each command is, and when it was asked for.
+    let mut waiting: HashMap<Uuid, (usize, Instant)> = HashMap::new();
+    let mut meshes = vec![None; exports.len()];
+    loop {
+        while waiting.len() < concurrency.max(1) {
+            let Some((i, format)) = formats.next() else {
+                break;
+            };
+            let cmd_id = session
+                .send(ModelingCmd::Export {
+                    entity_ids: object_ids.to_vec(),
+                    format,
+                    source_unit: units::SCENE_UNIT,
+                })
+                .await?;
+            waiting.insert(cmd_id, (i, Instant::now()));
+        }
+        if waiting.is_empty() {
+            break;
+        }
+        let cmd_ids: Vec<_> = waiting.keys().copied().collect();
+        let (cmd_id, files) = session.await_any_export(&cmd_ids).await.wrap_err_with(|| {
+            let paths: Vec<_> = waiting
+                .values()
+                .map(|&(i, _)| exports[i].0.display().to_string())
+                .collect();
+            format!("Could not export {}", paths.join(" or "))
+        })?;
+        let (i, asked_at) = waiting
+            .remove(&cmd_id)
+            .expect("the export is one of those being waited for");
+        let (path, extension) = &exports[i];
+        let transfer = Transfer {
+            path: path.display().to_string(),
+            format: extension.clone(),
+            bytes: files.iter().map(|file| file.contents.len()).sum(),
+            received_after: asked_at.elapsed(),
+        };
+        session.enter_phase(Phase::Save);
+        meshes[i] = save(
+            session,
+            cmd_id,
+            object_ids,
+            &exports[i],
+            files,
+            compress,
+            verify,
+        )
+        .await?;
+        TRANSFERS
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(transfer);
+        session.enter_phase(Phase::Export);
+    }
+    session.enter_phase(previous);
+    Ok(meshes)
+}
+
+/// How much an export sent back, and how long it took.
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct Transfer {
+    /// Where the export was saved (its first file, if it made several).
+    pub path: String,
+    /// The format, e.g. "stl".
+    pub format: String,
+    /// The size of its files, before they were compressed (see --compress).
+    pub bytes: usize,
+    /// How long after it was asked for its files arrived.
+    #[serde(rename = "received_after_secs", serialize_with = "seconds")]
+    pub received_after: Duration,
+}
+
+fn seconds<S: serde::Serializer>(d: &Duration, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_f64(d.as_secs_f64())
+}
+
+/// Every export this run received, in the order they arrived.
+static TRANSFERS: Mutex<Vec<Transfer>> = Mutex::new(Vec::new());
+
+/// Every export this run received, how big it was, and how long it took, for the run's report.
+pub fn transfers() -> Vec<Transfer> {
+    TRANSFERS.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Save an export's files to a path, in a format (its extension), as `export` describes,
+/// and return what's in the first, if it's a mesh.
+async fn save(
+    session: &mut ModelingSession,
+    cmd_id: Uuid,
+    object_ids: &[Uuid],
+    (path, extension): &(PathBuf, String),
+    files: Vec<RawFile>,
+    compress: bool,
+    verify: bool,
+) -> Result<Option<MeshStats>> {
+    let to_stdout = path == Path::new(STDOUT);
+    if to_stdout && files.len() > 1 {
+        let names: Vec<_> = files.iter().map(|file| file.name.as_str()).collect();
+        bail!(
+            "Exporting as {extension} made {} files ({}), but only one can be written to standard output",
+            files.len(),
+            names.join(", ")
+        );
+    }
+    // Some formats export more than one file. The first one goes where it was asked for,
+    // and the rest go next to it, with the names the API gave them.
+    let mut first_mesh = None;
+    for (i, file) in files.into_iter().enumerate() {
+        let file_path = if i == 0 {
+            path.to_owned()
+        } else {
+            path.with_file_name(file_name(&file.name)?)
+        };
+        let file_path = if compress && !to_stdout {
+            let mut name = file_path.into_os_string();
+            name.push(".gz");
+            name.into()
+        } else {
+            file_path
+        };
+        let path = file_path.to_string_lossy();
+        let format = if i == 0 {
+            extension.to_owned()
+        } else {
+            self::extension(Path::new(&file.name))
+        };
+        let mesh = match verify::stats(&format, &file.contents) {
+            Ok(mesh) => mesh,
+            Err(e) if verify => {
+                let e = e.wrap_err(format!("{path} is malformed"));
+                return Err(session.keep_payload(cmd_id, "verify", &file.contents, e));
+            }
+            Err(e) => {
+                let reasons: Vec<_> = e.chain().map(ToString::to_string).collect();
+                let message = format!("{path} looks malformed: {}", reasons.join(": "));
+                Warning::new(Code::ExportMalformed, message).raise()?;
+                None
+            }
+        };
+        let contents = match write_file(session, &path, file.contents, compress).await {
+            Ok(contents) => contents,
+            Err((contents, e)) => return Err(session.keep_payload(cmd_id, "write", &contents, e)),
+        };
+        let kind = OutputKind::Export {
+            format: extension.to_owned(),
+            units: units::SCENE_UNIT,
+            compressed: compress,
+            mesh: mesh.clone(),
+        };
+        if object_ids.is_empty() {
+            session.record_output(&path, &contents, kind).await?;
+        } else {
+            session
+                .record_output_of(&path, &contents, kind, object_ids.to_vec())
+                .await?;
+        }
+        if i == 0 {
+            first_mesh = mesh.clone();
+        }
+        if let Some(mesh) = mesh {
+            say!("{path} has {mesh}");
+            let problems = mesh.problems();
+            if !problems.is_empty() {
+                let problems = problems.join(", and ");
+                if verify {
+                    bail!("{path} is broken: {problems}");
+                }
+                Warning::new(
+                    Code::ExportMalformed,
+                    format!("{path} looks broken: {problems}"),
+                )
+                .raise()?;
+            }
+        }
+    }
+    Ok(first_mesh)
+}
+
+/// A name the API gave an exported file, checked to be only a file name, not e.g. "../model.mtl" or
+/// "/etc/passwd", so the file can only be saved next to the first one.
+fn file_name(name: &str) -> Result<&str> {
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(normal)), None) if normal == name => Ok(name),
+        _ => bail!(
+            "The API named an exported file {name:?}, which isn't just a file name, so it wasn't saved"
+        ),
+    }
+}
+
+/// Save one exported file (gzipped, if `compress` is true) through the run's sink, or write it to
+/// standard output. Returns what was saved. If it can't be saved, the exported file is handed back
+/// with the error, so its payload can be kept.
+async fn write_file(
+    session: &ModelingSession,
+    path: &str,
+    contents: Vec<u8>,
+    compress: bool,
+) -> std::result::Result<Vec<u8>, (Vec<u8>, Report)> {
+    let size = contents.len();
+    if compress {
+        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+        let compressed = match gz.write_all(&contents).and_then(|()| gz.finish()) {
+            Ok(compressed) => compressed,
+            Err(e) => return Err((contents, e.into())),
+        };
+        if let Err(e) = session.write_output(path, &compressed).await {
+            return Err((contents, e));
+        }
+        say!(
+            "Exported {path} ({size} bytes, compressed to {} bytes)",
+            compressed.len()
+        );
+        Ok(compressed)
+    } else {
+        if let Err(e) = session.write_output(path, &contents).await {
+            return Err((contents, e));
+        }
+        say!("Exported {path} ({size} bytes)");
+        Ok(contents)
+    }
+}
+
+/// A file's extension, in lower case, which says which format to export it as.
+pub fn extension(path: &Path) -> String {
+    path.extension()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_lowercase()
+}
+
+/// Which format to export a file as, given its extension (or the format asked for).
+pub fn format_for(path: &Path, extension: &str) -> Result<OutputFormat> {
+    // The API's own coordinate system: Z is up, and models face along -Y.
+    let coords = System {
+        forward: AxisDirectionPair {
+            axis: Axis::Y,
+            direction: Direction::Negative,
+        },
+        up: AxisDirectionPair {
+            axis: Axis::Z,
+            direction: Direction::Positive,
+        },
+    };
+    Ok(match extension {
+        "stl" => OutputFormat::Stl {
+            coords,
+            storage: StlStorage::Binary,
+            units: units::SCENE_UNIT,
+        },
+        "obj" => OutputFormat::Obj {
+            coords,
+            units: units::SCENE_UNIT,
+        },
+        "ply" => OutputFormat::Ply {
+            coords,
+            storage: PlyStorage::BinaryLittleEndian,
+        },
+        "step" | "stp" => OutputFormat::Step { coords },
+        _ => bail!(
+            "Can't tell which format to export {} as. Use one of these extensions: stl, obj, ply, step",
+            path.display()
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{counting, session::ConnectionProfile};
+    use std::fs;
+
+    /// An empty directory of the test's own.
+    fn dir(test: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("kittycad-export-{test}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Save an export's files as a STEP file in `dir`, as if the API had just sent them.
+    fn save_to(dir: &Path, files: Vec<RawFile>) -> Result<()> {
+        let recording = dir.join("empty.jsonl");
+        fs::write(&recording, "")?;
+        let mut session =
+            ModelingSession::replay(&recording, usize::MAX, ConnectionProfile::default())?;
+        session.set_sidecars(false);
+        let export = (dir.join("model.step"), "step".to_owned());
+        tokio::runtime::Builder::new_current_thread()
+            .build()?
+            .block_on(save(
+                &mut session,
+                Uuid::nil(),
+                &[],
+                &export,
+                files,
+                false,
+                false,
+            ))?;
+        Ok(())
+    }
+
+    fn file(name: &str, contents: &[u8]) -> RawFile {
+        RawFile {
+            name: name.to_owned(),
+            contents: contents.to_vec(),
+        }
+    }
+
+    #[test]
+    fn accepts_plain_file_names() {
+        for name in ["model.mtl", "texture 1.png", ".hidden", "..dots"] {
+            assert_eq!(file_name(name).unwrap(), name);
+        }
+    }
+
+    #[test]
+    fn rejects_names_with_directories_in_them() {
+        for name in [
+            "",
+            ".",
+            "..",
+            "../model.mtl",
+            "/etc/passwd",
+            "textures/a.png",
+            "./model.mtl",
+            "model.mtl/",
+        ] {
+            assert!(file_name(name).is_err(), "{name:?} was accepted");
+        }
+    }
+
+    #[test]
+    fn saves_the_other_files_next_to_the_first() {
+        let dir = dir("next-to");
+        let files = vec![file("x.step", b"first"), file("model.mtl", b"second")];
+        save_to(&dir, files).unwrap();
+        assert_eq!(fs::read(dir.join("model.step")).unwrap(), b"first");
+        assert_eq!(fs::read(dir.join("model.mtl")).unwrap(), b"second");
+    }
+
+    #[test]
+    fn wont_save_the_other_files_anywhere_else() {
+        let dir = dir("escape");
+        let files = vec![file("x.step", b"first"), file("../escaped", b"second")];
+        let e = save_to(&dir, files).unwrap_err();
+        assert!(e.to_string().contains("isn't just a file name"), "{e:?}");
+        assert!(!dir.with_file_name("escaped").exists());
+    }
+
+    #[test]
+    fn saves_a_big_export_without_copying_it() {
+        let size = 8 << 20;
+        let contents: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+        let dir = dir("big");
+        let files = vec![file("x.step", &contents)];
+        let (saved, peak) = counting::peak_while(|| save_to(&dir, files));
+        saved.unwrap();
+        assert!(fs::read(dir.join("model.step")).unwrap() == contents);
+        // It's read back to check it was written, but only a piece at a time.
+        assert!(
+            peak < size / 8,
+            "Saving {size} bytes held {peak} more bytes at once"
+        );
+    }
+}
@@ -0,0 +1,392 @@
+use color_eyre::{
+    eyre::{bail, eyre, WrapErr},
+    Result,
+};
+use std::collections::{BTreeMap, HashMap};
+
+/// A parameter in a scene file: either a number, or arithmetic on other parameters,
+/// e.g. "plate_width - 2 * margin".
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(untagged)]
+pub enum Parameter {
+    Number(f64),
+    Expression(String),
+}
+
+/// Built-in names, which parameters can't be called.
+const CONSTANTS: [(&str, f64); 1] = [("pi", std::f64::consts::PI)];
+
+/// Arithmetic, parsed.
+#[derive(Debug)]
+enum Expr {
+    Number(f64),
+    Name(String),
+    Negate(Box<Expr>),
+    Binary(Box<Expr>, char, Box<Expr>),
+}
+
+/// Work out every parameter's value.
+/// Fails, naming the parameter, if any of them has a typo, refers to a parameter which doesn't exist,
+/// divides by zero, or depends on itself (however indirectly).
+pub fn resolve(parameters: &BTreeMap<String, Parameter>) -> Result<BTreeMap<String, f64>> {
+    let mut definitions = HashMap::new();
+    for (name, parameter) in parameters {
+        if CONSTANTS.iter().any(|(constant, _)| constant == name) {
+            bail!("Parameter {name} has the same name as a built-in constant");
+        }
+        let expr = match parameter {
+            Parameter::Number(n) => Expr::Number(*n),
+            Parameter::Expression(text) => {
+                parse(text).wrap_err_with(|| format!("Parameter {name} is invalid"))?
+            }
+        };
+        definitions.insert(name.as_str(), expr);
+    }
+    let mut resolver = Resolver {
+        definitions: &definitions,
+        values: BTreeMap::new(),
+        resolving: Vec::new(),
+    };
+    for name in parameters.keys() {
+        resolver.value_of(name)?;
+    }
+    Ok(resolver.values)
+}
+
+/// Work out the value of some arithmetic on parameters which have already been resolved.
+pub fn evaluate(text: &str, values: &BTreeMap<String, f64>) -> Result<f64> {
+    let expr = parse(text)?;
+    eval(&expr, &mut |name| {
+        values
+            .get(name)
+            .copied()
+            .ok_or_else(|| eyre!("There's no parameter called {name}"))
+    })
+}
+
+/// Resolves parameters on demand, so each is worked out after the ones it depends on.
+struct Resolver<'a> {
+    definitions: &'a HashMap<&'a str, Expr>,
+    values: BTreeMap<String, f64>,
+    /// The parameters being worked out, each depending on the one before.
+    resolving: Vec<String>,
+}
+
+impl Resolver<'_> {
+    fn value_of(&mut self, name: &str) -> Result<f64> {
+        if let Some(value) = self.values.get(name) {
+            return Ok(*value);
+        }
+        if let Some(start) = self.resolving.iter().position(|n| n == name) {
+            let mut circle = self.resolving[start..].to_vec();
+            circle.push(name.to_owned());
+            bail!(
+                "Parameter {name} depends on itself: {}",
+                circle.join(" -> ")
+            );
+        }
+        let Some(expr) = self.definitions.get(name) else {
+            match self.resolving.last() {
+                Some(user) => bail!("Parameter {user} refers to {name}, which isn't a parameter"),
+                None => bail!("There's no parameter called {name}"),
+            }
+        };
+        self.resolving.push(name.to_owned());
+        let value = eval(expr, &mut |n| self.value_of(n));
+        self.resolving.pop();
+        // Only wrap errors from this parameter's own arithmetic: ones from the parameters it depends on
+        // already name the parameter at fault.
+        let value = match value {
+            Err(e) if e.downcast_ref::<ArithmeticError>().is_some() => {
+                return Err(e).wrap_err_with(|| format!("Parameter {name} can't be worked out"))
+            }
+            value => value?,
+        };
+        self.values.insert(name.to_owned(), value);
+        Ok(value)
+    }
+}
+
+/// Arithmetic which has no answer, e.g. dividing by zero.
+#[derive(Debug)]
+struct ArithmeticError(String);
+
+impl std::fmt::Display for ArithmeticError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for ArithmeticError {}
+
+fn eval(expr: &Expr, lookup: &mut dyn FnMut(&str) -> Result<f64>) -> Result<f64> {
+    Ok(match expr {
+        Expr::Number(n) => *n,
+        Expr::Name(name) => match CONSTANTS.iter().find(|(constant, _)| constant == name) {
+            Some((_, value)) => *value,
+            None => lookup(name)?,
+        },
+        Expr::Negate(e) => -eval(e, lookup)?,
+        Expr::Binary(lhs, op, rhs) => {
+            let (a, b) = (eval(lhs, lookup)?, eval(rhs, lookup)?);
+            match op {
+                '+' => a + b,
+                '-' => a - b,
+                '*' => a * b,
+                '/' if b == 0.0 => {
+                    return Err(ArithmeticError(format!("{lhs} / {rhs} divides by zero")).into())
+                }
+                '/' => a / b,
+                _ => unreachable!("the parser only makes + - * /"),
+            }
+        }
+    })
+}
+
+impl std::fmt::Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Number(n) => write!(f, "{n}"),
+            Self::Name(name) => write!(f, "{name}"),
+            Self::Negate(e) => write!(f, "-{e}"),
+            Self::Binary(lhs, op, rhs) => write!(f, "({lhs} {op} {rhs})"),
+        }
+    }
+}
+
+/// Parse arithmetic: numbers, names, + - * / (also written − × ÷) and parentheses,
+/// with the usual precedence.
+fn parse(text: &str) -> Result<Expr> {
+    let mut parser = Parser {
+        chars: text.chars().collect(),
+        at: 0,
+    };
+    let expr = parser.sum()?;
+    parser.skip_spaces();
+    if let Some(c) = parser.peek() {
+        bail!(
+            "Unexpected '{c}' at character {} of \"{text}\"",
+            parser.at + 1
+        );
+    }
+    Ok(expr)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    at: usize,
+}
+
+impl Parser {
+    fn skip_spaces(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.at += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.at).copied()
+    }
+
+    /// The next operator, if it's one of `ops`, written the ASCII way.
+    fn operator(&mut self, ops: &[char]) -> Option<char> {
+        self.skip_spaces();
+        let op = match self.peek()? {
+            '−' => '-',
+            '×' => '*',
+            '÷' => '/',
+            c => c,
+        };
+        if !ops.contains(&op) {
+            return None;
+        }
+        self.at += 1;
+        Some(op)
+    }
+
+    /// Terms added or subtracted, e.g. "a + b * c - d".
+    fn sum(&mut self) -> Result<Expr> {
+        let mut expr = self.product()?;
+        while let Some(op) = self.operator(&['+', '-']) {
+            expr = Expr::Binary(Box::new(expr), op, Box::new(self.product()?));
+        }
+        Ok(expr)
+    }
+
+    /// Factors multiplied or divided, e.g. "b * c / d".
+    fn product(&mut self) -> Result<Expr> {
+        let mut expr = self.factor()?;
+        while let Some(op) = self.operator(&['*', '/']) {
+            expr = Expr::Binary(Box::new(expr), op, Box::new(self.factor()?));
+        }
+        Ok(expr)
+    }
+
+    /// A number, a name, a bracketed sum, or any of those negated.
+    fn factor(&mut self) -> Result<Expr> {
+        if let Some(op) = self.operator(&['+', '-']) {
+            let e = self.factor()?;
+            return Ok(if op == '-' {
+                Expr::Negate(Box::new(e))
+            } else {
+                e
+            });
+        }
+        let start = self.at;
+        match self.peek() {
+            Some('(') => {
+                self.at += 1;
+                let expr = self.sum()?;
+                self.skip_spaces();
+                if self.peek() != Some(')') {
+                    bail!("The '(' at character {} is never closed", start + 1);
+                }
+                self.at += 1;
+                Ok(expr)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                while self.peek().is_some_and(|c| c.is_ascii_digit() || c == '.') {
+                    self.at += 1;
+                }
+                let number: String = self.chars[start..self.at].iter().collect();
+                let n = number
+                    .parse()
+                    .map_err(|_| eyre!("{number} at character {} isn't a number", start + 1))?;
+                Ok(Expr::Number(n))
+            }
+            Some(c) if c.is_alphabetic() || c == '_' => {
+                while self.peek().is_some_and(|c| c.is_alphanumeric() || c == '_') {
+                    self.at += 1;
+                }
+                Ok(Expr::Name(self.chars[start..self.at].iter().collect()))
+            }
+            Some(c) => bail!(
+                "Expected a number, a name or '(' at character {}, not '{c}'",
+                start + 1
+            ),
+            None => bail!("Expected a number, a name or '(' at the end"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parameters(definitions: &[(&str, &str)]) -> BTreeMap<String, Parameter> {
+        definitions
+            .iter()
+            .map(|&(name, definition)| {
+                let parameter = match definition.parse() {
+                    Ok(n) => Parameter::Number(n),
+                    Err(_) => Parameter::Expression(definition.to_owned()),
+                };
+                (name.to_owned(), parameter)
+            })
+            .collect()
+    }
+
+    /// Everything an error says, the outermost first.
+    fn error(result: Result<impl std::fmt::Debug>) -> String {
+        let e = result.unwrap_err();
+        let reasons: Vec<_> = e.chain().map(ToString::to_string).collect();
+        reasons.join(": ")
+    }
+
+    #[test]
+    fn works_out_parameters_in_any_order() {
+        let values = resolve(&parameters(&[
+            ("inner", "outer - 2 * margin"),
+            ("margin", "2.5"),
+            ("outer", "width + 10"),
+            ("width", "30"),
+        ]))
+        .unwrap();
+        assert_eq!(values["outer"], 40.0);
+        assert_eq!(values["inner"], 35.0);
+        assert_eq!(values.len(), 4);
+    }
+
+    #[test]
+    fn follows_precedence_and_parentheses() {
+        let values = BTreeMap::from([("a".to_owned(), 2.0), ("b".to_owned(), 3.0)]);
+        for (text, expected) in [
+            ("1 + 2 * 3", 7.0),
+            ("(1 + 2) * 3", 9.0),
+            ("a - b - 1", -2.0),
+            ("12 / a / b", 2.0),
+            ("-a * -b", 6.0),
+            ("--a", 2.0),
+            ("+a", 2.0),
+            ("a × b − 1 ÷ 4", 5.75),
+            ("2 * pi", 2.0 * std::f64::consts::PI),
+            ("  .5+a  ", 2.5),
+        ] {
+            assert_eq!(evaluate(text, &values).unwrap(), expected, "{text}");
+        }
+    }
+
+    #[test]
+    fn says_which_parameter_divides_by_zero() {
+        let e = error(resolve(&parameters(&[
+            ("gap", "width - width"),
+            ("ratio", "width / gap"),
+            ("uses_ratio", "ratio + 1"),
+            ("width", "10"),
+        ])));
+        assert_eq!(
+            e,
+            "Parameter ratio can't be worked out: width / gap divides by zero"
+        );
+        let e = error(evaluate("1 / (2 - 2)", &BTreeMap::new()));
+        assert_eq!(e, "1 / (2 - 2) divides by zero");
+    }
+
+    #[test]
+    fn says_how_a_parameter_depends_on_itself() {
+        let e = error(resolve(&parameters(&[
+            ("a", "b + 1"),
+            ("b", "c * 2"),
+            ("c", "a"),
+        ])));
+        assert_eq!(e, "Parameter a depends on itself: a -> b -> c -> a");
+        let e = error(resolve(&parameters(&[("self", "self")])));
+        assert_eq!(e, "Parameter self depends on itself: self -> self");
+    }
+
+    #[test]
+    fn says_which_parameter_refers_to_a_missing_one() {
+        let e = error(resolve(&parameters(&[("a", "2 * widht"), ("width", "3")])));
+        assert_eq!(e, "Parameter a refers to widht, which isn't a parameter");
+        let e = error(evaluate("widht", &BTreeMap::new()));
+        assert_eq!(e, "There's no parameter called widht");
+    }
+
+    #[test]
+    fn says_where_arithmetic_is_wrong() {
+        for (text, expected) in [
+            ("2 +", "Expected a number, a name or '(' at the end"),
+            ("(1 + 2", "The '(' at character 1 is never closed"),
+            ("1 2", "Unexpected '2' at character 3 of \"1 2\""),
+            ("1.2.3", "1.2.3 at character 1 isn't a number"),
+            ("2 ^ 3", "Unexpected '^' at character 3 of \"2 ^ 3\""),
+            (
+                "* 2",
+                "Expected a number, a name or '(' at character 1, not '*'",
+            ),
+        ] {
+            assert_eq!(error(evaluate(text, &BTreeMap::new())), expected, "{text}");
+        }
+        let e = error(resolve(&parameters(&[("a", "1 +")])));
+        assert_eq!(
+            e,
+            "Parameter a is invalid: Expected a number, a name or '(' at the end"
+        );
+    }
+
+    #[test]
+    fn wont_redefine_constants() {
+        let e = error(resolve(&parameters(&[("pi", "3")])));
+        assert_eq!(e, "Parameter pi has the same name as a built-in constant");
+    }
+}
@@ -0,0 +1,278 @@
+use crate::{
+    geometry::BoundingBox,
+    session::ModelingSession,
+    sketch::{self, Face},
+    warnings::{Code, Warning},
+};
+use color_eyre::Result;
+use kittycad::types::{Color, ModelingCmd, Point3D};
+use uuid::Uuid;
+
+/// Which way one of a box's faces points: along or against one of the world's axes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    PlusX,
+    MinusX,
+    PlusY,
+    MinusY,
+    PlusZ,
+    MinusZ,
+}
+
+impl Direction {
+    /// The unit vector it points along.
+    pub fn normal(self) -> Point3D {
+        let point = |x, y, z| Point3D { x, y, z };
+        match self {
+            Self::PlusX => point(1.0, 0.0, 0.0),
+            Self::MinusX => point(-1.0, 0.0, 0.0),
+            Self::PlusY => point(0.0, 1.0, 0.0),
+            Self::MinusY => point(0.0, -1.0, 0.0),
+            Self::PlusZ => point(0.0, 0.0, 1.0),
+            Self::MinusZ => point(0.0, 0.0, -1.0),
+        }
+    }
+
+    /// The colour faces pointing this way are painted: red, green and blue for +X, +Y and +Z,
+    /// and paler versions of them (cyan, magenta and yellow) for -X, -Y and -Z.
+    pub fn color(self) -> Color {
+        let color = |r, g, b| Color { r, g, b, a: 1.0 };
+        match self {
+            Self::PlusX => color(0.9, 0.1, 0.1),
+            Self::MinusX => color(0.1, 0.8, 0.8),
+            Self::PlusY => color(0.1, 0.7, 0.1),
+            Self::MinusY => color(0.8, 0.1, 0.8),
+            Self::PlusZ => color(0.1, 0.2, 0.9),
+            Self::MinusZ => color(0.9, 0.8, 0.1),
+        }
+    }
+
+    /// Two axes square to the direction, with their cross product pointing along it,
+    /// so a plane with them as its X and Y axes faces this way.
+    fn axes(self) -> (Point3D, Point3D) {
+        let point = |x, y, z| Point3D { x, y, z };
+        let (x, y, z) = (
+            point(1.0, 0.0, 0.0),
+            point(0.0, 1.0, 0.0),
+            point(0.0, 0.0, 1.0),
+        );
+        match self {
+            Self::PlusX => (y, z),
+            Self::MinusX => (z, y),
+            Self::PlusY => (z, x),
+            Self::MinusY => (x, z),
+            Self::PlusZ => (x, y),
+            Self::MinusZ => (y, x),
+        }
+    }
+}
+
+impl std::fmt::Display for Direction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::PlusX => "+X",
+            Self::MinusX => "-X",
+            Self::PlusY => "+Y",
+            Self::MinusY => "-Y",
+            Self::PlusZ => "+Z",
+            Self::MinusZ => "-Z",
+        };
+        f.write_str(name)
+    }
+}
+
+/// How far apart (in scene units) two coordinates may be and still count as the same.
+const SAME: f64 = 1e-6;
+
+/// Which way a face points, judged by the box around its edges and the box around the whole solid.
+///
+/// The API (as of this version of the kittycad crate) can't tell us a face's normal, so a face counts
+/// as pointing along an axis if it's flat across that axis, and on the solid's outside along it:
+/// flat at the solid's biggest X is +X, at its smallest X is -X, and so on. Anything else, e.g. a
+/// curved face, a sloping one, or one sunk into the solid, is None.
+pub fn classify(face: &BoundingBox, solid: &BoundingBox) -> Option<Direction> {
+    let same = |a: f64, b: f64| (a - b).abs() < SAME;
+    let axes = [
+        (face.min.x, face.max.x, solid.min.x, solid.max.x),
+        (face.min.y, face.max.y, solid.min.y, solid.max.y),
+        (face.min.z, face.max.z, solid.min.z, solid.max.z),
+    ];
+    let directions = [
+        (Direction::PlusX, Direction::MinusX),
+        (Direction::PlusY, Direction::MinusY),
+        (Direction::PlusZ, Direction::MinusZ),
+    ];
+    let mut found = None;
+    for ((low, high, solid_low, solid_high), (plus, minus)) in axes.into_iter().zip(directions) {
+        if !same(low, high) {
+            continue;
+        }
+        // A face flat across two axes is really an edge, so it can't be told apart.
+        if found.is_some() {
+            return None;
+        }
+        found = if same(high, solid_high) {
+            Some(plus)
+        } else if same(low, solid_low) {
+            Some(minus)
+        } else {
+            return None;
+        };
+    }
+    found
+}
+
+/// One of a solid's faces, and which way it was found to point, if it could be told.
+pub struct PaintedFace {
+    pub face: Face,
+    pub direction: Option<Direction>,
+    /// The coloured plane laid over it, if it was painted.
+    pub plane_id: Option<Uuid>,
+}
+
+/// Paint each face of a box-like solid the colour of the way it points (see `Direction::color`),
+/// e.g. to check which way a model is facing in a snapshot.
+///
+/// The API (as of this version of the kittycad crate) can't colour a single face, so each one is
+/// covered with a coloured plane, a little outside the solid so it isn't hidden by the face.
+/// Planes are square, so they stick out past faces which aren't. Faces whose direction can't be told
+/// are left the solid's own colour, with a warning.
+pub async fn paint_faces(
+    session: &mut ModelingSession,
+    solid_id: Uuid,
+) -> Result<Vec<PaintedFace>> {
+    let faces = sketch::faces(session, solid_id).await?;
+    let Some(solid) = faces
+        .iter()
+        .map(|f| f.bbox.clone())
+        .reduce(|a, b| a.union(&b))
+    else {
+        return Ok(Vec::new());
+    };
+    let extents = solid.extents();
+    // Far enough out to be drawn in front of the face, but too close to notice.
+    let lift = extents.x.max(extents.y).max(extents.z) * 0.002;
+    let mut painted = Vec::new();
+    for face in faces {
+        let direction = classify(&face.bbox, &solid);
+        let plane_id = match direction {
+            Some(direction) => Some(paint(session, &face, direction, lift).await?),
+            None => {
+                let message = format!(
+                    "Can't tell which way face {} of solid {solid_id} points, so it's left unpainted",
+                    face.id
+                );
+                Warning::new(Code::UnclassifiedFace, message).raise()?;
+                None
+            }
+        };
+        painted.push(PaintedFace {
+            face,
+            direction,
+            plane_id,
+        });
+    }
+    Ok(painted)
+}
+
+/// Cover a face with a plane of its direction's colour, `lift` in front of it. Returns the plane's ID.
+async fn paint(
+    session: &mut ModelingSession,
+    face: &Face,
+    direction: Direction,
+    lift: f64,
+) -> Result<Uuid> {
+    let (x_axis, y_axis) = direction.axes();
+    let center = face.bbox.center();
+    let normal = direction.normal();
+    let extents = face.bbox.extents();
+    let plane_id = session.new_id();
+    // The face is already where it belongs in the scene, so the plane isn't placed again.
+    session
+        .send_with_id(
+            ModelingCmd::MakePlane {
+                clobber: false,
+                hide: Some(false),
+                origin: Point3D {
+                    x: center.x + normal.x * lift,
+                    y: center.y + normal.y * lift,
+                    z: center.z + normal.z * lift,
+                },
+                size: extents.x.max(extents.y).max(extents.z),
+                x_axis,
+                y_axis,
+            },
+            plane_id,
+        )
+        .await?;
+    session.await_response(plane_id).await?;
+    session.track(plane_id);
+    let cmd_id = session
+        .send(ModelingCmd::PlaneSetColor {
+            color: direction.color(),
+            plane_id,
+        })
+        .await?;
+    session.await_response(cmd_id).await?;
+    Ok(plane_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The box from `min` to `max`, each written as (x, y, z).
+    fn bbox(min: (f64, f64, f64), max: (f64, f64, f64)) -> BoundingBox {
+        let point = |(x, y, z)| Point3D { x, y, z };
+        BoundingBox {
+            min: point(min),
+            max: point(max),
+        }
+    }
+
+    #[test]
+    fn tells_which_way_each_face_of_a_box_points() {
+        // A box 10 by 20 by 30, so no two of its sides can be mixed up.
+        let solid = bbox((0.0, 0.0, 0.0), (10.0, 20.0, 30.0));
+        let faces = [
+            ((10.0, 0.0, 0.0), (10.0, 20.0, 30.0), Direction::PlusX),
+            ((0.0, 0.0, 0.0), (0.0, 20.0, 30.0), Direction::MinusX),
+            ((0.0, 20.0, 0.0), (10.0, 20.0, 30.0), Direction::PlusY),
+            ((0.0, 0.0, 0.0), (10.0, 0.0, 30.0), Direction::MinusY),
+            ((0.0, 0.0, 30.0), (10.0, 20.0, 30.0), Direction::PlusZ),
+            ((0.0, 0.0, 0.0), (10.0, 20.0, 0.0), Direction::MinusZ),
+        ];
+        for (min, max, direction) in faces {
+            assert_eq!(
+                classify(&bbox(min, max), &solid),
+                Some(direction),
+                "face from {min:?} to {max:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn counts_a_face_as_flat_if_it_nearly_is() {
+        let solid = bbox((0.0, 0.0, 0.0), (10.0, 10.0, 10.0));
+        let face = bbox((10.0 - SAME / 2.0, 0.0, 0.0), (10.0, 10.0, 10.0));
+        assert_eq!(classify(&face, &solid), Some(Direction::PlusX));
+    }
+
+    #[test]
+    fn cant_tell_which_way_other_faces_point() {
+        let solid = bbox((0.0, 0.0, 0.0), (10.0, 20.0, 30.0));
+        let faces = [
+            // Curved or sloping, so flat across no axis.
+            ((0.0, 0.0, 0.0), (10.0, 20.0, 30.0), "sloping"),
+            // Flat, but sunk into the solid, like the bottom of a pocket.
+            ((2.0, 2.0, 15.0), (8.0, 18.0, 15.0), "sunk"),
+            // Flat across two axes, so really an edge.
+            ((10.0, 20.0, 0.0), (10.0, 20.0, 30.0), "an edge"),
+            // Flat across all three, so really a corner.
+            ((10.0, 20.0, 30.0), (10.0, 20.0, 30.0), "a corner"),
+        ];
+        for (min, max, why) in faces {
+            assert_eq!(classify(&bbox(min, max), &solid), None, "{why}");
+        }
+    }
+}
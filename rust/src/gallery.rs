@@ -0,0 +1,184 @@
+use crate::{
+    build, camera, finish, scene,
+    session::{ConnectionLost, ModelingSession},
+    sink,
+    snapshot::Output,
+    Cli, Options,
+};
+use clap::CommandFactory;
+use color_eyre::{
+    eyre::{bail, WrapErr},
+    Result,
+};
+use std::{collections::BTreeMap, path::Path};
+
+/// Subcommands which don't draw a shape of their own, so aren't in the gallery.
+pub const NOT_SHAPES: [&str; 10] = [
+    "scene",
+    "sweep",
+    "imgdiff",
+    "scene-diff",
+    "raw",
+    "pipe",
+    "bench",
+    "verify-primitives",
+    "convert",
+    "gallery",
+];
+
+/// One shape in the gallery, as listed in its index.
+#[derive(serde::Serialize)]
+struct Entry {
+    /// The shape's subcommand, e.g. "l-bracket".
+    name: String,
+    /// The snapshot's file name, in the gallery's directory.
+    image: String,
+    /// The options the shape was drawn with, which are all their defaults, e.g. {"--count": "1"}.
+    parameters: BTreeMap<String, String>,
+    /// Why the shape couldn't be drawn, if it couldn't.
+    error: Option<String>,
+}
+
+/// Draw every shape which has a subcommand, with its default options, and save a snapshot of each
+/// to "<dir>/<name>.png". Each shape is drawn in an empty scene, and framed the same way.
+/// Also writes "<dir>/index.json" listing them (and "<dir>/index.html", if `html` is set).
+///
+/// Shapes which need an input file (e.g. a profile or an SVG) are left out.
+/// A shape which fails doesn't stop the others, but the gallery fails once they've all been tried.
+pub async fn run(
+    session: &mut ModelingSession,
+    dir: &Path,
+    html: bool,
+    output: &Output,
+    options: &Options,
+) -> Result<()> {
+    std::fs::create_dir_all(dir)
+        .wrap_err_with(|| format!("Could not create the gallery directory {}", dir.display()))?;
+    let cli = Cli::command();
+    let mut entries = Vec::new();
+    for shape in cli.get_subcommands() {
+        let frame = entries.len() + 1;
+        let name = shape.get_name().to_owned();
+        if NOT_SHAPES.contains(&name.as_str()) {
+            continue;
+        }
+        let Ok(model) = scene::parse_model(std::slice::from_ref(&name)) else {
+            say!("Leaving {name} out of the gallery, because it needs more than its defaults");
+            continue;
+        };
+        let parameters = shape
+            .get_arguments()
+            .filter_map(|arg| {
+                let long = arg.get_long()?;
+                let values: Vec<_> = arg
+                    .get_default_values()
+                    .iter()
+                    .map(|value| value.to_string_lossy())
+                    .collect();
+                (!values.is_empty()).then(|| (format!("--{long}"), values.join(",")))
+            })
+            .collect();
+        let mut shape_output = Output {
+            path: dir
+                .join(format!("{name}.png"))
+                .to_string_lossy()
+                .into_owned(),
+            export: None,
+            export_only: None,
+            crop: None,
+            ..output.clone()
+        };
+        let drawing = scene::describe(std::slice::from_ref(&name), frame)?;
+        if let Some(named) = options.outputs.name(&shape_output, &drawing)? {
+            shape_output = named;
+        }
+        let shape_output = shape_output.labelled(&drawing);
+        // The index links to each image from the gallery's directory.
+        let image = Path::new(&shape_output.path).strip_prefix(dir).map_or_else(
+            |_| shape_output.path.clone(),
+            |p| p.to_string_lossy().into_owned(),
+        );
+        // Start from an empty scene, with the camera not yet pointed, so every shape is framed
+        // the same way, from the direction which suits it.
+        scene::clear(session).await?;
+        session.set_camera_preset(camera::choose(options.view, &model));
+        let drawn = async {
+            let ids = build(session, model, &shape_output, options.validation).await?;
+            finish(session, &ids, &shape_output, options).await
+        }
+        .await;
+        let error = match drawn {
+            Ok(()) => {
+                say!("Drew {name} to {}", shape_output.path);
+                None
+            }
+            // A lost connection means nothing else can be drawn either.
+            Err(e) if ConnectionLost::caused(&e) => return Err(e),
+            Err(e) => {
+                eprintln!("{name} failed: {e:?}");
+                Some(format!("{e}: {}", e.root_cause()))
+            }
+        };
+        entries.push(Entry {
+            name,
+            image,
+            parameters,
+            error,
+        });
+    }
+    scene::clear(session).await?;
+
+    let index = dir.join("index.json");
+    let json = serde_json::to_string_pretty(&serde_json::json!({ "shapes": &entries }))?;
+    sink::write_atomically(&index, json.as_bytes())
+        .wrap_err_with(|| format!("Could not save {}", index.display()))?;
+    if html {
+        let page = dir.join("index.html");
+        sink::write_atomically(&page, index_html(&entries).as_bytes())
+            .wrap_err_with(|| format!("Could not save {}", page.display()))?;
+    }
+
+    let failures = entries.iter().filter(|e| e.error.is_some()).count();
+    say!(
+        "Drew {} of {} shapes to {}",
+        entries.len() - failures,
+        entries.len(),
+        dir.display()
+    );
+    if failures > 0 {
+        bail!("{failures} of {} shapes failed", entries.len());
+    }
+    Ok(())
+}
+
+/// A page showing every shape's snapshot, with its name and options, or why it failed.
+fn index_html(entries: &[Entry]) -> String {
+    let mut page = String::from(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Shape gallery</title></head>\n<body>\n",
+    );
+    for entry in entries {
+        let name = escape(&entry.name);
+        page.push_str(&format!("<figure>\n<figcaption><b>{name}</b>"));
+        for (option, value) in &entry.parameters {
+            page.push_str(&format!(" {} {}", escape(option), escape(value)));
+        }
+        page.push_str("</figcaption>\n");
+        match &entry.error {
+            Some(error) => page.push_str(&format!("<p>Failed: {}</p>\n", escape(error))),
+            None => page.push_str(&format!(
+                "<img src=\"{}\" alt=\"{name}\">\n",
+                escape(&entry.image)
+            )),
+        }
+        page.push_str("</figure>\n");
+    }
+    page.push_str("</body>\n</html>\n");
+    page
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,241 @@
+use kittycad::types::Point3D;
+
+/// The smallest axis-aligned box containing some geometry.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct BoundingBox {
+    pub min: Point3D,
+    pub max: Point3D,
+}
+
+impl BoundingBox {
+    /// The bounding box of some points. Returns None if there aren't any.
+    pub fn from_points<'a>(points: impl IntoIterator<Item = &'a Point3D>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        let mut bbox = Self {
+            min: first.clone(),
+            max: first.clone(),
+        };
+        for p in points {
+            bbox.min = Point3D {
+                x: bbox.min.x.min(p.x),
+                y: bbox.min.y.min(p.y),
+                z: bbox.min.z.min(p.z),
+            };
+            bbox.max = Point3D {
+                x: bbox.max.x.max(p.x),
+                y: bbox.max.y.max(p.y),
+                z: bbox.max.z.max(p.z),
+            };
+        }
+        Some(bbox)
+    }
+
+    /// The smallest box containing both boxes.
+    pub fn union(&self, other: &Self) -> Self {
+        Self::from_points([&self.min, &self.max, &other.min, &other.max]).unwrap()
+    }
+
+    /// How long the box is along each axis.
+    pub fn extents(&self) -> Point3D {
+        Point3D {
+            x: self.max.x - self.min.x,
+            y: self.max.y - self.min.y,
+            z: self.max.z - self.min.z,
+        }
+    }
+
+    /// The point in the middle of the box.
+    pub fn center(&self) -> Point3D {
+        Point3D {
+            x: (self.min.x + self.max.x) / 2.0,
+            y: (self.min.y + self.max.y) / 2.0,
+            z: (self.min.z + self.max.z) / 2.0,
+        }
+    }
+
+    /// Half the length of the box's diagonal, i.e. the radius of a sphere around the box.
+    pub fn radius(&self) -> f64 {
+        let e = self.extents();
+        (e.x * e.x + e.y * e.y + e.z * e.z).sqrt() / 2.0
+    }
+}
+
+/// How far apart two points are.
+pub fn distance(a: &Point3D, b: &Point3D) -> f64 {
+    ((a.x - b.x).powi(2) + (a.y - b.y).powi(2) + (a.z - b.z).powi(2)).sqrt()
+}
+
+/// The vector from `b` to `a`.
+pub fn sub(a: &Point3D, b: &Point3D) -> Point3D {
+    Point3D {
+        x: a.x - b.x,
+        y: a.y - b.y,
+        z: a.z - b.z,
+    }
+}
+
+pub fn dot(a: &Point3D, b: &Point3D) -> f64 {
+    a.x * b.x + a.y * b.y + a.z * b.z
+}
+
+pub fn cross(a: &Point3D, b: &Point3D) -> Point3D {
+    Point3D {
+        x: a.y * b.z - a.z * b.y,
+        y: a.z * b.x - a.x * b.z,
+        z: a.x * b.y - a.y * b.x,
+    }
+}
+
+/// A vector `factor` times as long.
+pub fn scale(a: &Point3D, factor: f64) -> Point3D {
+    Point3D {
+        x: a.x * factor,
+        y: a.y * factor,
+        z: a.z * factor,
+    }
+}
+
+/// A vector pointing the same way, with length 1.
+pub fn normalize(a: &Point3D) -> Point3D {
+    scale(a, 1.0 / dot(a, a).sqrt())
+}
+
+/// Points along a curve, given as a function from 0..=1 to points on it, close enough together that
+/// the lines between them stray no further than `tolerance` from the curve. Starts and ends with the
+/// curve's ends. Straight stretches get few points, and tight bends get many, so the lines come out
+/// more even than sampling the parameter evenly, when the curve doesn't move evenly with it.
+pub fn sample_curve(curve: impl Fn(f64) -> Point3D, tolerance: f64) -> Vec<Point3D> {
+    // Stop splitting somewhere, in case the tolerance is too small to ever meet.
+    const MAX_DEPTH: u32 = 16;
+    let mut points = vec![curve(0.0)];
+    // Split stretches of the curve in half until each is close enough to straight.
+    // The left half is visited first, so the points come out in order.
+    let mut to_visit = vec![(0.0, 1.0, 0)];
+    while let Some((t0, t1, depth)) = to_visit.pop() {
+        let (start, end) = (curve(t0), curve(t1));
+        // Checking a few points, not just the middle, catches S-bends whose middle is on the line.
+        let bent = depth < MAX_DEPTH
+            && [0.25, 0.5, 0.75]
+                .iter()
+                .any(|f| distance_to_line(&curve(t0 + f * (t1 - t0)), &start, &end) > tolerance);
+        if bent {
+            let middle = (t0 + t1) / 2.0;
+            to_visit.push((middle, t1, depth + 1));
+            to_visit.push((t0, middle, depth + 1));
+        } else {
+            points.push(end);
+        }
+    }
+    points
+}
+
+/// How far a point is from the line between two others.
+fn distance_to_line(p: &Point3D, a: &Point3D, b: &Point3D) -> f64 {
+    let (ab, ap) = (
+        [b.x - a.x, b.y - a.y, b.z - a.z],
+        [p.x - a.x, p.y - a.y, p.z - a.z],
+    );
+    let length_squared: f64 = ab.iter().map(|d| d * d).sum();
+    if length_squared == 0.0 {
+        return distance(p, a);
+    }
+    // How far along the line the nearest point is, as a fraction of its length.
+    let along =
+        (ab.iter().zip(ap).map(|(d, e)| d * e).sum::<f64>() / length_squared).clamp(0.0, 1.0);
+    let nearest = Point3D {
+        x: a.x + along * ab[0],
+        y: a.y + along * ab[1],
+        z: a.z + along * ab[2],
+    };
+    distance(p, &nearest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::TAU;
+
+    fn point(x: f64, y: f64, z: f64) -> Point3D {
+        Point3D { x, y, z }
+    }
+
+    /// A circle of radius 10 around the origin, in the XY plane.
+    fn circle(t: f64) -> Point3D {
+        point(10.0 * (t * TAU).cos(), 10.0 * (t * TAU).sin(), 0.0)
+    }
+
+    fn length(points: &[Point3D]) -> f64 {
+        points.windows(2).map(|w| distance(&w[0], &w[1])).sum()
+    }
+
+    #[test]
+    fn samples_a_line_at_its_ends() {
+        let line = |t: f64| point(1.0 + 4.0 * t, 2.0 - t, 3.0 * t);
+        assert_eq!(
+            sample_curve(line, 0.01),
+            [point(1.0, 2.0, 0.0), point(5.0, 1.0, 3.0)]
+        );
+    }
+
+    #[test]
+    fn samples_a_circle_as_long_as_it_is() {
+        let tolerance = 0.01;
+        let points = sample_curve(circle, tolerance);
+        assert_eq!(points.first(), Some(&circle(0.0)));
+        assert_eq!(points.last(), Some(&circle(1.0)));
+        // The lines are chords, so a little shorter than the circle, but not much.
+        let (sampled, circumference) = (length(&points), 10.0 * TAU);
+        assert!(sampled < circumference);
+        assert!(
+            (circumference - sampled) / circumference < 1e-3,
+            "{sampled} is too far from {circumference}"
+        );
+        // Each chord's middle strays no further than the tolerance from the circle.
+        for w in points.windows(2) {
+            let middle = scale(
+                &Point3D {
+                    x: w[0].x + w[1].x,
+                    y: w[0].y + w[1].y,
+                    z: 0.0,
+                },
+                0.5,
+            );
+            assert!(10.0 - dot(&middle, &middle).sqrt() <= tolerance);
+        }
+    }
+
+    #[test]
+    fn samples_tighter_bends_more_closely() {
+        let loose = sample_curve(circle, 0.1).len();
+        let tight = sample_curve(circle, 0.001).len();
+        assert!(tight > loose, "{tight} points isn't more than {loose}");
+    }
+
+    #[test]
+    fn samples_an_s_bend_whose_middle_is_on_its_chord() {
+        let s = |t: f64| point(10.0 * t, (t * TAU).sin(), 0.0);
+        assert!(sample_curve(s, 0.01).len() > 2);
+    }
+
+    #[test]
+    fn stops_sampling_a_curve_it_can_never_get_close_enough_to() {
+        let points = sample_curve(circle, 0.0);
+        assert_eq!(points.len(), (1 << 16) + 1);
+    }
+
+    #[test]
+    fn bounds_points() {
+        let bbox = BoundingBox::from_points(&[
+            point(1.0, -2.0, 3.0),
+            point(-1.0, 5.0, 0.0),
+            point(0.0, 0.0, 4.0),
+        ])
+        .unwrap();
+        assert_eq!(bbox.min, point(-1.0, -2.0, 0.0));
+        assert_eq!(bbox.max, point(1.0, 5.0, 4.0));
+        assert_eq!(bbox.extents(), point(2.0, 7.0, 4.0));
+        assert_eq!(bbox.center(), point(0.0, 1.5, 2.0));
+        assert_eq!(BoundingBox::from_points(&[]), None);
+    }
+}
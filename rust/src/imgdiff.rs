@@ -0,0 +1,135 @@
+use crate::sink;
+use color_eyre::{
+    eyre::{bail, WrapErr},
+    Result,
+};
+use image::{imageops, Rgba, RgbaImage};
+use std::path::Path;
+
+/// How the difference between two images is scored. Both give 0 for identical images.
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum Metric {
+    /// The mean absolute difference of every channel of every pixel, from 0 to 1.
+    Mae,
+    /// Structural dissimilarity, (1 - SSIM) / 2, from 0 to 1. It's measured on each pixel's
+    /// brightness in 8x8 blocks, so it cares more about shapes moving than colours shifting a little.
+    Dssim,
+}
+
+/// Compare two images, save a picture of where they differ if asked,
+/// and fail if they're more different than `threshold`.
+pub fn run(
+    a: &Path,
+    b: &Path,
+    out: Option<&Path>,
+    threshold: f64,
+    metric: Metric,
+    crop: bool,
+) -> Result<()> {
+    let mut a = load(a)?;
+    let mut b = load(b)?;
+    if a.dimensions() != b.dimensions() {
+        if !crop {
+            bail!(
+                "The images are different sizes ({}x{} and {}x{}). Pass --crop to compare the part they share",
+                a.width(),
+                a.height(),
+                b.width(),
+                b.height()
+            );
+        }
+        let (width, height) = (a.width().min(b.width()), a.height().min(b.height()));
+        say!("Comparing the top left {width}x{height} of each image");
+        a = imageops::crop_imm(&a, 0, 0, width, height).to_image();
+        b = imageops::crop_imm(&b, 0, 0, width, height).to_image();
+    }
+
+    let differing = a.pixels().zip(b.pixels()).filter(|(a, b)| a != b).count();
+    let (name, score) = match metric {
+        Metric::Mae => ("MAE", mae(&a, &b)),
+        Metric::Dssim => ("DSSIM", dssim(&a, &b)),
+    };
+    if let Some(out) = out {
+        let format = image::ImageFormat::from_path(out)
+            .wrap_err_with(|| format!("Can't tell which format to save {} in", out.display()))?;
+        let mut encoded = Vec::new();
+        heatmap(&a, &b)
+            .write_to(&mut std::io::Cursor::new(&mut encoded), format)
+            .wrap_err_with(|| format!("Could not encode {}", out.display()))?;
+        sink::write_atomically(out, &encoded)
+            .wrap_err_with(|| format!("Could not save {}", out.display()))?;
+        say!("Saved the differences to {}", out.display());
+    }
+    let total = a.width() as usize * a.height() as usize;
+    say!("{name}: {score:.6}, with {differing} of {total} pixels differing");
+    if score > threshold {
+        bail!("The images differ by {score:.6} ({name}), more than the threshold of {threshold}");
+    }
+    Ok(())
+}
+
+fn load(path: &Path) -> Result<RgbaImage> {
+    let img = image::open(path).wrap_err_with(|| format!("Could not read {}", path.display()))?;
+    Ok(img.to_rgba8())
+}
+
+fn mae(a: &RgbaImage, b: &RgbaImage) -> f64 {
+    let (raw_a, raw_b) = (a.as_raw(), b.as_raw());
+    if raw_a.is_empty() {
+        return 0.0;
+    }
+    let total: u64 = raw_a
+        .iter()
+        .zip(raw_b)
+        .map(|(a, b)| u64::from(a.abs_diff(*b)))
+        .sum();
+    total as f64 / (raw_a.len() as f64 * 255.0)
+}
+
+fn dssim(a: &RgbaImage, b: &RgbaImage) -> f64 {
+    const BLOCK: u32 = 8;
+    // The usual constants, which keep SSIM stable in flat, dark blocks.
+    const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+    const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+    let luma = |p: &Rgba<u8>| 0.299 * p[0] as f64 + 0.587 * p[1] as f64 + 0.114 * p[2] as f64;
+    let mut ssim_total = 0.0;
+    let mut blocks = 0;
+    for y0 in (0..a.height()).step_by(BLOCK as usize) {
+        for x0 in (0..a.width()).step_by(BLOCK as usize) {
+            let mut pairs = Vec::new();
+            for y in y0..(y0 + BLOCK).min(a.height()) {
+                for x in x0..(x0 + BLOCK).min(a.width()) {
+                    pairs.push((luma(a.get_pixel(x, y)), luma(b.get_pixel(x, y))));
+                }
+            }
+            let n = pairs.len() as f64;
+            let mean_a = pairs.iter().map(|p| p.0).sum::<f64>() / n;
+            let mean_b = pairs.iter().map(|p| p.1).sum::<f64>() / n;
+            let (mut var_a, mut var_b, mut covariance) = (0.0, 0.0, 0.0);
+            for (la, lb) in &pairs {
+                var_a += (la - mean_a).powi(2) / n;
+                var_b += (lb - mean_b).powi(2) / n;
+                covariance += (la - mean_a) * (lb - mean_b) / n;
+            }
+            ssim_total += ((2.0 * mean_a * mean_b + C1) * (2.0 * covariance + C2))
+                / ((mean_a.powi(2) + mean_b.powi(2) + C1) * (var_a + var_b + C2));
+            blocks += 1;
+        }
+    }
+    if blocks == 0 {
+        return 0.0;
+    }
+    (1.0 - ssim_total / blocks as f64) / 2.0
+}
+
+/// A faded copy of the first image, with each pixel turned redder the more the images differ there.
+fn heatmap(a: &RgbaImage, b: &RgbaImage) -> RgbaImage {
+    RgbaImage::from_fn(a.width(), a.height(), |x, y| {
+        let (pa, pb) = (a.get_pixel(x, y), b.get_pixel(x, y));
+        let difference = (0..4).map(|c| pa[c].abs_diff(pb[c])).max().unwrap_or(0) as f64 / 255.0;
+        let faded = (0.299 * pa[0] as f64 + 0.587 * pa[1] as f64 + 0.114 * pa[2] as f64) / 3.0;
+        let red = faded + difference * (255.0 - faded);
+        let other = faded * (1.0 - difference);
+        Rgba([red as u8, other as u8, other as u8, 255])
+    })
+}
@@ -0,0 +1,199 @@
+use crate::{camera, export, geometry::BoundingBox, session::ModelingSession, snapshot::STDOUT};
+use color_eyre::{
+    eyre::{bail, Context},
+    Result,
+};
+use kittycad::types::{ImportFile, ModelingCmd, OkModelingCmdResponse, Point3D};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// The extensions of files the API can import.
+/// The API works out each file's format from its extension, so there's no format to send.
+const EXTENSIONS: [&str; 9] = [
+    "stl", "obj", "mtl", "ply", "fbx", "gltf", "glb", "step", "stp",
+];
+
+/// Import a model from local files, and return the imported object's ID.
+/// Formats which span several files (e.g. OBJ with an MTL file) are imported together,
+/// and become one object.
+///
+/// The API can't tell us where an imported object is, so for STL and OBJ files,
+/// the camera is pointed at the vertices in the file. Other formats keep the default view.
+pub async fn import_files(session: &mut ModelingSession, paths: &[PathBuf]) -> Result<Uuid> {
+    let mut files = Vec::with_capacity(paths.len());
+    let mut bbox: Option<BoundingBox> = None;
+    for path in paths {
+        let extension = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        if !EXTENSIONS.contains(&extension.as_str()) {
+            bail!(
+                "Can't import {}. Use one of these extensions: {}",
+                path.display(),
+                EXTENSIONS.join(", ")
+            );
+        }
+        let data =
+            std::fs::read(path).wrap_err_with(|| format!("Could not read {}", path.display()))?;
+        if let Some(file_bbox) = mesh_bounds(&extension, &data) {
+            bbox = Some(match bbox {
+                Some(bbox) => bbox.union(&file_bbox),
+                None => file_bbox,
+            });
+        }
+        // Send just the file's name, so that files which refer to each other
+        // (like an OBJ and its MTL) can find each other.
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        files.push(ImportFile {
+            data,
+            path: name.into_owned(),
+        });
+    }
+    if files
+        .iter()
+        .all(|file| file.path.to_lowercase().ends_with(".mtl"))
+    {
+        bail!("An MTL file only describes materials, so it must be imported with its OBJ file");
+    }
+
+    let cmd_id = session.send(ModelingCmd::ImportFiles { files }).await?;
+    let OkModelingCmdResponse::ImportFiles { data } = session
+        .await_response(cmd_id)
+        .await
+        .wrap_err("Could not import the files")?
+    else {
+        bail!("Expected the API to respond to ImportFiles with the imported object's ID");
+    };
+    let object_id = data.object_id;
+    session.track(object_id);
+    say!("Imported {} file(s) as 1 object: {object_id}", paths.len());
+    if let Some(bbox) = bbox {
+        camera::zoom_to_fit(session, &bbox).await?;
+    }
+    Ok(object_id)
+}
+
+/// The box containing a mesh file's vertices, for the formats that are simple to read.
+fn mesh_bounds(extension: &str, data: &[u8]) -> Option<BoundingBox> {
+    let points = match extension {
+        "stl" => stl_points(data),
+        "obj" => text_points(data, "v"),
+        _ => Vec::new(),
+    };
+    BoundingBox::from_points(&points)
+}
+
+/// The vertices in an STL file, which is either binary or text.
+fn stl_points(data: &[u8]) -> Vec<Point3D> {
+    // A binary STL has an 80 byte header, a triangle count, then 50 bytes per triangle:
+    // a normal and three vertices (each three 32-bit floats), and two spare bytes.
+    if data.len() >= 84 {
+        let count = u32::from_le_bytes([data[80], data[81], data[82], data[83]]) as usize;
+        if data.len() == 84 + count * 50 {
+            let coord = |at: usize| {
+                f64::from(f32::from_le_bytes([
+                    data[at],
+                    data[at + 1],
+                    data[at + 2],
+                    data[at + 3],
+                ]))
+            };
+            return (0..count)
+                .flat_map(|triangle| (1..4).map(move |vertex| 84 + triangle * 50 + vertex * 12))
+                .map(|at| Point3D {
+                    x: coord(at),
+                    y: coord(at + 4),
+                    z: coord(at + 8),
+                })
+                .collect();
+        }
+    }
+    text_points(data, "vertex")
+}
+
+/// The points in a text file's lines which start with `keyword`, followed by x, y and z.
+fn text_points(data: &[u8], keyword: &str) -> Vec<Point3D> {
+    String::from_utf8_lossy(data)
+        .lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            if words.next() != Some(keyword) {
+                return None;
+            }
+            let mut coord = || words.next()?.parse().ok();
+            Some(Point3D {
+                x: coord()?,
+                y: coord()?,
+                z: coord()?,
+            })
+        })
+        .collect()
+}
+
+/// Where a conversion should be saved, and in which format, given the --to and --out options.
+/// Fails if neither says which format to convert to, or they disagree.
+pub fn conversion_output(
+    input: &Path,
+    to: &Option<String>,
+    out: &Option<PathBuf>,
+) -> Result<(PathBuf, String)> {
+    let out = match (to, out) {
+        (_, Some(out)) => out.clone(),
+        (Some(to), None) => input.with_extension(to),
+        (None, None) => bail!("Say which format to convert to, with --to or --out"),
+    };
+    let to_stdout = out.as_os_str() == STDOUT;
+    let format = match to {
+        Some(to) => {
+            if !to_stdout && !export::extension(&out).eq_ignore_ascii_case(to) {
+                bail!(
+                    "--to says to convert to {to}, but {} has a different extension",
+                    out.display()
+                );
+            }
+            to.to_lowercase()
+        }
+        None if to_stdout => bail!("Say which format to write to standard output, with --to"),
+        None => export::extension(&out),
+    };
+    if out == input {
+        bail!("Converting {} would overwrite it", input.display());
+    }
+    // Check the format before connecting, so a typo fails fast.
+    export::format_for(&out, &format)?;
+    Ok((out, format))
+}
+
+/// Convert a model file to another format, by importing it and then exporting it.
+pub async fn convert(
+    session: &mut ModelingSession,
+    input: &Path,
+    out: &Path,
+    format: &str,
+) -> Result<()> {
+    let object_id = import_files(session, &[input.to_owned()])
+        .await
+        .wrap_err_with(|| format!("Import failed, so {} wasn't converted", input.display()))?;
+    export::export(session, &[object_id], out, format, false, false)
+        .await
+        .wrap_err_with(|| {
+            format!(
+                "{} was imported, but the export failed, so it wasn't converted",
+                input.display()
+            )
+        })?;
+    let size = |path: &Path| std::fs::metadata(path).map(|m| m.len()).unwrap_or_default();
+    if out.as_os_str() == STDOUT {
+        say!("Converted {} ({} bytes)", input.display(), size(input));
+    } else {
+        say!(
+            "Converted {} ({} bytes) to {} ({} bytes)",
+            input.display(),
+            size(input),
+            out.display(),
+            size(out)
+        );
+    }
+    Ok(())
+}
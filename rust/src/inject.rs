@@ -0,0 +1,231 @@
+use crate::{budget, session, transport::TransportError};
+use color_eyre::{
+    eyre::{bail, eyre, WrapErr},
+    Result,
+};
+use kittycad::types::ErrorCode;
+use serde_json::{json, Value};
+use std::{collections::VecDeque, sync::Mutex, time::Duration};
+use tokio_tungstenite::tungstenite::{error::ProtocolError, Error as WsError, Message as WsMsg};
+
+/// The environment variable which says what to inject, in a build with the "test-hooks" feature.
+pub const VAR: &str = "KITTYCAD_INJECT";
+
+/// Failures to inject into the run's messages, so its error handling can be tested against a
+/// recording (see --offline) or the API, which usually works. Read from $KITTYCAD_INJECT, which
+/// lists them separated by commas, e.g. "corrupt=3,delay=0.5s,drop-after=10,fail=volume:internal_engine":
+///
+/// - `api=URL` talks to the API at this URL (e.g. http://127.0.0.1:8080) instead, such as a fake one
+///   a test runs, to see what the run sends it.
+/// - `corrupt=N` corrupts the Nth response from the API. A response with files in it, like a snapshot
+///   or an export, keeps its shape, but its files' contents are replaced with bytes which aren't
+///   any format. Any other response is cut in half, so it isn't JSON.
+/// - `delay=DURATION` waits this long (e.g. 2s) before reading each message from the API.
+/// - `drop-after=K` drops the connection once K responses have been received, like a network
+///   failure would. It's only dropped once, so a run which connects again carries on.
+/// - `fail=TYPE:CODE` answers every command of this type (e.g. volume) with an error with this code
+///   (e.g. internal_engine), without sending it. `fail=TYPE:CODE:N` only fails the first N.
+///   Commands sent in a batch are sent as usual. It can be given more than once.
+#[derive(Debug, Default)]
+struct Injection {
+    api: Option<String>,
+    corrupt: Option<u64>,
+    delay: Option<Duration>,
+    drop_after: Option<u64>,
+    fail: Vec<Fail>,
+    /// How many responses have been received from the API, not counting failures made up here.
+    received: u64,
+    /// Whether the connection's been dropped.
+    dropped: bool,
+    /// The made-up failures for commands which weren't sent, to be read before anything else.
+    failures: VecDeque<String>,
+}
+
+#[derive(Debug)]
+struct Fail {
+    /// The command's type, as it's sent, e.g. "volume".
+    cmd: String,
+    code: ErrorCode,
+    /// How many more times to fail it, or None for every time.
+    times: Option<u32>,
+}
+
+static INJECTION: Mutex<Option<Injection>> = Mutex::new(None);
+
+/// Read what to inject from $KITTYCAD_INJECT, if it's set.
+pub fn load() -> Result<()> {
+    let Ok(spec) = std::env::var(VAR) else {
+        return Ok(());
+    };
+    let injection = parse(&spec).wrap_err_with(|| format!("${VAR} ({spec:?}) is invalid"))?;
+    say!("Injecting failures, as ${VAR} says: {spec}");
+    *INJECTION.lock().unwrap_or_else(|e| e.into_inner()) = Some(injection);
+    Ok(())
+}
+
+fn parse(spec: &str) -> Result<Injection> {
+    let mut injection = Injection::default();
+    for item in spec
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+    {
+        let Some((name, value)) = item.split_once('=') else {
+            bail!("{item} should be a name and a value, e.g. corrupt=3");
+        };
+        let count = || {
+            value
+                .parse::<u64>()
+                .map_err(|_| eyre!("{name} should be a number, not {value}"))
+        };
+        match name {
+            "api" => injection.api = Some(value.to_owned()),
+            "corrupt" => injection.corrupt = Some(count()?),
+            "delay" => injection.delay = Some(budget::parse_duration(value)?),
+            "drop-after" => injection.drop_after = Some(count()?),
+            "fail" => {
+                let mut parts = value.split(':');
+                let (Some(cmd), Some(code)) = (parts.next(), parts.next()) else {
+                    bail!("fail should be a command type and an error code, e.g. fail=volume:internal_engine");
+                };
+                let times = match parts.next() {
+                    Some(times) => Some(
+                        times
+                            .parse()
+                            .map_err(|_| eyre!("{times} isn't a number of times to fail {cmd}"))?,
+                    ),
+                    None => None,
+                };
+                injection.fail.push(Fail {
+                    cmd: cmd.to_owned(),
+                    code: session::parse_error_code(code)?,
+                    times,
+                });
+            }
+            _ => bail!("There's nothing called {name} to inject (there's api, corrupt, delay, drop-after and fail)"),
+        }
+    }
+    Ok(injection)
+}
+
+/// A message is about to be sent. Returns true if it's a command which should fail, in which case
+/// it mustn't be sent: its failure is read next instead.
+pub fn sending(text: &str) -> bool {
+    let mut injection = INJECTION.lock().unwrap_or_else(|e| e.into_inner());
+    let Some(injection) = injection.as_mut() else {
+        return false;
+    };
+    let Ok(msg) = serde_json::from_str::<Value>(text) else {
+        return false;
+    };
+    if msg.get("type").and_then(Value::as_str) != Some("modeling_cmd_req") {
+        return false;
+    }
+    let cmd = msg.pointer("/cmd/type").and_then(Value::as_str);
+    let Some(fail) = injection
+        .fail
+        .iter_mut()
+        .find(|fail| Some(fail.cmd.as_str()) == cmd && fail.times != Some(0))
+    else {
+        return false;
+    };
+    if let Some(times) = &mut fail.times {
+        *times -= 1;
+    }
+    let failure = json!({
+        "success": false,
+        "request_id": msg.get("cmd_id"),
+        "errors": [{
+            "error_code": fail.code,
+            "message": format!("Injected by ${VAR}"),
+        }],
+    });
+    injection.failures.push_back(failure.to_string());
+    true
+}
+
+/// Where to find the API, if it isn't at its usual address.
+pub fn api_url() -> Option<String> {
+    INJECTION
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()
+        .and_then(|injection| injection.api.clone())
+}
+
+/// How long to wait before reading the next message.
+pub fn delay() -> Option<Duration> {
+    INJECTION
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()
+        .and_then(|injection| injection.delay)
+}
+
+/// The failure of a command which wasn't sent, if there is one, to read before anything from the API.
+pub fn failure() -> Option<WsMsg> {
+    INJECTION
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_mut()
+        .and_then(|injection| injection.failures.pop_front())
+        .map(WsMsg::Text)
+}
+
+/// A message's been read from the API. Returns it, or what to pretend was read instead.
+pub fn received(
+    msg: Option<Result<WsMsg, TransportError>>,
+) -> Option<Result<WsMsg, TransportError>> {
+    let mut injection = INJECTION.lock().unwrap_or_else(|e| e.into_inner());
+    let (Some(injection), Some(Ok(WsMsg::Text(text)))) = (injection.as_mut(), &msg) else {
+        return msg;
+    };
+    if injection.drop_after == Some(injection.received) && !injection.dropped {
+        injection.dropped = true;
+        let dropped = WsError::Protocol(ProtocolError::ResetWithoutClosingHandshake);
+        return Some(Err(dropped.into()));
+    }
+    injection.received += 1;
+    if injection.corrupt != Some(injection.received) {
+        return msg;
+    }
+    Some(Ok(WsMsg::Text(corrupt(text))))
+}
+
+/// Replace the contents of a response's files, or if it has none, cut it in half.
+fn corrupt(text: &str) -> String {
+    if let Ok(mut response) = serde_json::from_str::<Value>(text) {
+        if replace_contents(&mut response) {
+            return response.to_string();
+        }
+    }
+    text.chars().take(text.chars().count() / 2).collect()
+}
+
+/// Replace every file's contents in a response with "corrupted", which isn't any format.
+/// Returns whether there were any.
+fn replace_contents(value: &mut Value) -> bool {
+    match value {
+        Value::Object(fields) => {
+            let mut replaced = false;
+            for (name, field) in fields.iter_mut() {
+                if name == "contents" && field.is_string() {
+                    // "corrupted", in base64.
+                    *field = Value::from("Y29ycnVwdGVk");
+                    replaced = true;
+                } else {
+                    replaced |= replace_contents(field);
+                }
+            }
+            replaced
+        }
+        Value::Array(items) => {
+            let mut replaced = false;
+            for item in items {
+                replaced |= replace_contents(item);
+            }
+            replaced
+        }
+        _ => false,
+    }
+}
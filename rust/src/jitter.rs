@@ -0,0 +1,261 @@
+use color_eyre::{
+    eyre::{bail, WrapErr},
+    Result,
+};
+use std::collections::BTreeMap;
+
+/// How much a scene's parameter varies from one variant of the scene to the next, e.g.
+/// `{ "uniform": 2, "min": 15 }` moves it by up to 2 either way, but never below 15,
+/// and `{ "normal": 0.5 }` moves it by a normally distributed amount, usually less than 0.5.
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct Jitter {
+    /// Move it by up to this much either way, with every amount as likely as any other.
+    uniform: Option<f64>,
+    /// Move it by a normally distributed amount, with this standard deviation.
+    normal: Option<f64>,
+    /// The least it may be, once it's moved. Values below it are raised to it.
+    min: Option<f64>,
+    /// The most it may be, once it's moved. Values above it are lowered to it.
+    max: Option<f64>,
+}
+
+impl Jitter {
+    fn check(&self) -> Result<()> {
+        let amount = match (self.uniform, self.normal) {
+            (Some(amount), None) | (None, Some(amount)) => amount,
+            _ => bail!("It needs just one of \"uniform\" or \"normal\""),
+        };
+        if !amount.is_finite() || amount < 0.0 {
+            bail!("How much it varies must be a positive number, not {amount}");
+        }
+        if let (Some(min), Some(max)) = (self.min, self.max) {
+            if min > max {
+                bail!("Its min ({min}) is more than its max ({max})");
+            }
+        }
+        Ok(())
+    }
+
+    /// Move a value by a random amount, drawn from `random`, and keep it within the bounds.
+    fn apply(&self, value: f64, random: &mut SplitMix) -> f64 {
+        let moved = match (self.uniform, self.normal) {
+            (Some(spread), _) => value + spread * (2.0 * random.unit() - 1.0),
+            (_, Some(deviation)) => value + deviation * random.normal(),
+            (None, None) => value,
+        };
+        moved.clamp(
+            self.min.unwrap_or(f64::NEG_INFINITY),
+            self.max.unwrap_or(f64::INFINITY),
+        )
+    }
+}
+
+/// Which of a scene's variants to build (see --seed and --count).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Seed {
+    pub seed: u64,
+    /// The variant's number, starting at 1.
+    pub variant: u32,
+}
+
+/// A jittered variant of a scene, and the values its jittered parameters ended up with,
+/// saved in sidecars and manifests so it can be built again.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Jittered {
+    /// The seed the run was given, or picked.
+    pub seed: u64,
+    /// The variant's number, starting at 1.
+    pub variant: u32,
+    /// The jittered parameters, and their values.
+    pub parameters: BTreeMap<String, f64>,
+}
+
+/// Jitter the parameters `jitter` says to, from their values in `values`, and return their new values.
+///
+/// This only depends on its arguments: the same seed and variant always give the same values.
+/// Each parameter is moved independently of the others, so jittering another parameter too, or
+/// building more variants, doesn't change how a parameter is moved.
+pub fn apply(
+    jitter: &BTreeMap<String, Jitter>,
+    values: &BTreeMap<String, f64>,
+    seed: Seed,
+) -> Result<BTreeMap<String, f64>> {
+    let mut jittered = BTreeMap::new();
+    for (name, how) in jitter {
+        let Some(&value) = values.get(name) else {
+            bail!("There's jitter for {name}, but no parameter called {name}");
+        };
+        how.check()
+            .wrap_err_with(|| format!("The jitter for {name} is invalid"))?;
+        let mut random = SplitMix::new(seed, name);
+        jittered.insert(name.clone(), how.apply(value, &mut random));
+    }
+    Ok(jittered)
+}
+
+/// A seed for a run which wasn't given one, different every time.
+pub fn random_seed() -> u64 {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64);
+    SplitMix(nanos ^ u64::from(std::process::id())).next()
+}
+
+/// Sebastiano Vigna's SplitMix64: a small, fast random number generator whose numbers only
+/// depend on where it starts, which is all jitter needs. Tests use it too, for inputs which are
+/// random but the same every run.
+pub(crate) struct SplitMix(pub(crate) u64);
+
+impl SplitMix {
+    /// Numbers for one parameter of one variant.
+    fn new(seed: Seed, name: &str) -> Self {
+        // FNV-1a, to turn the name into a number.
+        let name = name.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, b| {
+            (hash ^ u64::from(b)).wrapping_mul(0x100_0000_01b3)
+        });
+        let variant = SplitMix(u64::from(seed.variant)).next();
+        Self(SplitMix(seed.seed ^ variant).next() ^ name)
+    }
+
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number from 0 up to (but not including) 1, every one as likely as any other.
+    pub(crate) fn unit(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1_u64 << 53) as f64
+    }
+
+    /// Heads or tails.
+    #[cfg(test)]
+    pub(crate) fn coin(&mut self) -> bool {
+        self.unit() < 0.5
+    }
+
+    /// A whole number from 0 up to (but not including) `n`.
+    #[cfg(test)]
+    pub(crate) fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    /// A number from `low` up to (but not including) `high`.
+    #[cfg(test)]
+    pub(crate) fn between(&mut self, low: f64, high: f64) -> f64 {
+        low + (high - low) * self.unit()
+    }
+
+    /// A number from the standard normal distribution, by the Box-Muller transform.
+    fn normal(&mut self) -> f64 {
+        // 1 - unit() is never 0, whose log is infinite.
+        let (u1, u2) = (1.0 - self.unit(), self.unit());
+        (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn jitter(how: serde_json::Value) -> BTreeMap<String, Jitter> {
+        serde_json::from_value(how).unwrap()
+    }
+
+    fn values(values: &[(&str, f64)]) -> BTreeMap<String, f64> {
+        values
+            .iter()
+            .map(|&(name, value)| (name.to_owned(), value))
+            .collect()
+    }
+
+    fn seed(seed: u64, variant: u32) -> Seed {
+        Seed { seed, variant }
+    }
+
+    #[test]
+    fn jitters_the_same_way_from_the_same_seed() {
+        let how = jitter(json!({ "width": { "uniform": 2 }, "height": { "normal": 0.5 } }));
+        let values = values(&[("width", 20.0), ("height", 10.0)]);
+        let first = apply(&how, &values, seed(42, 1)).unwrap();
+        assert_eq!(apply(&how, &values, seed(42, 1)).unwrap(), first);
+        assert_ne!(apply(&how, &values, seed(42, 2)).unwrap(), first);
+        assert_ne!(apply(&how, &values, seed(43, 1)).unwrap(), first);
+    }
+
+    #[test]
+    fn jitters_each_parameter_on_its_own() {
+        let values = values(&[("width", 20.0), ("height", 10.0)]);
+        let alone = apply(
+            &jitter(json!({ "width": { "uniform": 2 } })),
+            &values,
+            seed(7, 3),
+        )
+        .unwrap();
+        let both = jitter(json!({ "width": { "uniform": 2 }, "height": { "uniform": 1 } }));
+        let both = apply(&both, &values, seed(7, 3)).unwrap();
+        assert_eq!(alone["width"], both["width"]);
+        assert!(!alone.contains_key("height"));
+    }
+
+    #[test]
+    fn moves_values_by_no_more_than_the_uniform_spread() {
+        let how = jitter(json!({ "width": { "uniform": 2 } }));
+        let values = values(&[("width", 20.0)]);
+        for variant in 1..=1000 {
+            let width = apply(&how, &values, seed(1, variant)).unwrap()["width"];
+            assert!((18.0..=22.0).contains(&width), "{width}");
+        }
+    }
+
+    #[test]
+    fn keeps_values_within_their_bounds() {
+        let how = jitter(json!({
+            "width": { "uniform": 100, "min": 15, "max": 25 },
+            "height": { "normal": 100, "min": 1 },
+        }));
+        let values = values(&[("width", 20.0), ("height", 10.0)]);
+        let (mut at_min, mut at_max) = (false, false);
+        for variant in 1..=1000 {
+            let jittered = apply(&how, &values, seed(2, variant)).unwrap();
+            let width = jittered["width"];
+            assert!((15.0..=25.0).contains(&width), "{width}");
+            assert!(jittered["height"] >= 1.0, "{}", jittered["height"]);
+            at_min |= width == 15.0;
+            at_max |= width == 25.0;
+        }
+        // Spread this wide, most are clamped, to each bound.
+        assert!(at_min && at_max);
+    }
+
+    #[test]
+    fn rejects_jitter_it_cant_apply() {
+        let values = values(&[("width", 20.0)]);
+        let error = |how| {
+            let e = apply(&jitter(how), &values, seed(1, 1)).unwrap_err();
+            e.chain()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(": ")
+        };
+        assert_eq!(
+            error(json!({ "depth": { "uniform": 1 } })),
+            "There's jitter for depth, but no parameter called depth"
+        );
+        assert_eq!(
+            error(json!({ "width": { "uniform": 1, "normal": 1 } })),
+            "The jitter for width is invalid: It needs just one of \"uniform\" or \"normal\""
+        );
+        assert_eq!(
+            error(json!({ "width": { "uniform": -1 } })),
+            "The jitter for width is invalid: How much it varies must be a positive number, not -1"
+        );
+        assert_eq!(
+            error(json!({ "width": { "normal": 1, "min": 5, "max": 4 } })),
+            "The jitter for width is invalid: Its min (5) is more than its max (4)"
+        );
+    }
+}
@@ -0,0 +1,512 @@
+use crate::{recipe::Recipe, session::ModelingSession, sink};
+use color_eyre::{
+    eyre::{bail, WrapErr},
+    Result,
+};
+use kittycad::types::{PathSegment, Point3D};
+use std::{fmt::Write, path::Path};
+
+/// How far a point may be from the sketch plane, or an arc's start from the pen, and still count.
+const EPSILON: f64 = 1e-6;
+
+/// Write KCL source which builds the same solids and sketches as the session did,
+/// so the model can be carried on with in KCL.
+///
+/// This works from the recipes the session kept (the paths and extrusions we sent),
+/// so objects we don't know how to build, like imported ones, are left out, with a comment.
+pub fn emit(session: &ModelingSession) -> Result<String> {
+    let mut kcl = String::new();
+    let mut parts = 0;
+    for object_id in session.created() {
+        let Some(recipe) = session.recipe(object_id) else {
+            writeln!(
+                kcl,
+                "// {object_id} wasn't built from a sketch, so it's left out"
+            )?;
+            continue;
+        };
+        parts += 1;
+        let expression =
+            part(recipe).wrap_err_with(|| format!("Can't write {object_id} as KCL"))?;
+        writeln!(kcl, "const part{parts:03} = {expression}")?;
+    }
+    Ok(kcl)
+}
+
+/// Save the KCL for the session's objects.
+pub fn save(session: &ModelingSession, path: &Path) -> Result<()> {
+    let kcl = emit(session)?;
+    sink::write_atomically(path, kcl.as_bytes())
+        .wrap_err_with(|| format!("Could not save {}", path.display()))
+}
+
+/// A KCL expression which builds a solid (or a sketch, if it wasn't extruded).
+fn part(recipe: &Recipe) -> Result<String> {
+    let mut kcl = sketch(recipe, "\n  ")?;
+    for hole in &recipe.holes {
+        let hole = sketch(hole, " ").wrap_err("Can't write one of its holes as KCL")?;
+        write!(kcl, "\n  |> hole({hole}, %)")?;
+    }
+    if let Some(distance) = recipe.extrusion {
+        if let Some(normal) = recipe
+            .normal
+            .as_ref()
+            .filter(|n| (n.z - 1.0).abs() > EPSILON)
+        {
+            bail!(
+                "It was extruded along {},{},{}, and only extrusions straight up are translated to KCL",
+                number(normal.x),
+                number(normal.y),
+                number(normal.z)
+            );
+        }
+        if recipe.uncapped {
+            bail!("It was extruded without caps, which KCL can't do");
+        }
+        write!(kcl, "\n  |> extrude({}, %)", number(distance))?;
+    }
+    Ok(kcl)
+}
+
+/// A KCL expression which sketches a closed path. Each call is put on a new line after `separator`.
+fn sketch(recipe: &Recipe, separator: &str) -> Result<String> {
+    let start = &recipe.start;
+    // KCL sketches on a plane, so start one at the path's height, parallel to XY.
+    let plane = if start.z.abs() < EPSILON {
+        "'XY'".to_owned()
+    } else {
+        format!(
+            "{{ plane: {{ origin: {{ x: 0, y: 0, z: {} }}, x_axis: {{ x: 1, y: 0, z: 0 }}, \
+             y_axis: {{ x: 0, y: 1, z: 0 }}, z_axis: {{ x: 0, y: 0, z: 1 }} }} }}",
+            number(start.z)
+        )
+    };
+    let mut kcl = format!(
+        "startSketchOn({plane}){separator}|> startProfileAt({}, %)",
+        point(start)
+    );
+    let mut pen = start.clone();
+    for (i, segment) in recipe.segments.iter().enumerate() {
+        let call = match segment {
+            PathSegment::Line { end, relative } => {
+                let end = absolute(&pen, end, *relative);
+                check_flat(&end, start.z)?;
+                let call = if *relative {
+                    format!("line({}, %)", offset(&pen, &end))
+                } else {
+                    format!("lineTo({}, %)", point(&end))
+                };
+                pen = end;
+                call
+            }
+            PathSegment::Bezier {
+                control1,
+                control2,
+                end,
+                relative,
+            } => {
+                // KCL's curves are relative to the pen.
+                let control1 = absolute(&pen, control1, *relative);
+                let control2 = absolute(&pen, control2, *relative);
+                let end = absolute(&pen, end, *relative);
+                for p in [&control1, &control2, &end] {
+                    check_flat(p, start.z)?;
+                }
+                let call = format!(
+                    "bezierCurve({{ to: {}, control1: {}, control2: {} }}, %)",
+                    offset(&pen, &end),
+                    offset(&pen, &control1),
+                    offset(&pen, &control2)
+                );
+                pen = end;
+                call
+            }
+            PathSegment::Arc {
+                angle_end,
+                angle_start,
+                center,
+                radius,
+                relative,
+                ..
+            } => {
+                let (cx, cy) = if *relative {
+                    (pen.x + center.x, pen.y + center.y)
+                } else {
+                    (center.x, center.y)
+                };
+                // KCL's arcs start at the pen, so the pen has to be on the circle already.
+                let at = |degrees: f64| {
+                    let (sin, cos) = degrees.to_radians().sin_cos();
+                    Point3D {
+                        x: cx + radius * cos,
+                        y: cy + radius * sin,
+                        z: pen.z,
+                    }
+                };
+                let arc_start = at(*angle_start);
+                if crate::geometry::distance(&arc_start, &pen) > EPSILON * radius.max(1.0) {
+                    bail!("Segment {i} is an arc which doesn't start at the pen, which KCL can't draw");
+                }
+                let call = format!(
+                    "arc({{ angle_start: {}, angle_end: {}, radius: {} }}, %)",
+                    number(*angle_start),
+                    number(*angle_end),
+                    number(*radius)
+                );
+                pen = at(*angle_end);
+                call
+            }
+            PathSegment::TangentialArcTo { to, .. } => {
+                check_flat(to, start.z)?;
+                pen = to.clone();
+                format!("tangentialArcTo({}, %)", point(to))
+            }
+            // Where this ends depends on which way the previous segment was heading,
+            // which we'd have to work out to draw the rest of the path relative to it.
+            PathSegment::TangentialArc { .. } => {
+                bail!("Segment {i} is a tangential arc with an offset, which isn't translated to KCL yet")
+            }
+        };
+        write!(kcl, "{separator}|> {call}")?;
+    }
+    write!(kcl, "{separator}|> close(%)")?;
+    Ok(kcl)
+}
+
+/// Where a segment's point is, given where the pen was.
+fn absolute(pen: &Point3D, p: &Point3D, relative: bool) -> Point3D {
+    if relative {
+        Point3D {
+            x: pen.x + p.x,
+            y: pen.y + p.y,
+            z: pen.z + p.z,
+        }
+    } else {
+        p.clone()
+    }
+}
+
+/// KCL sketches are flat, so every point has to be on the sketch plane.
+fn check_flat(p: &Point3D, z: f64) -> Result<()> {
+    if (p.z - z).abs() > EPSILON {
+        bail!(
+            "The path leaves its sketch plane (at z = {z}) to reach z = {}, which KCL can't draw",
+            p.z
+        );
+    }
+    Ok(())
+}
+
+/// A point on the sketch plane, as KCL writes it.
+fn point(p: &Point3D) -> String {
+    format!("[{}, {}]", number(p.x), number(p.y))
+}
+
+/// The offset from the pen to a point, as KCL writes it.
+fn offset(pen: &Point3D, p: &Point3D) -> String {
+    format!("[{}, {}]", number(p.x - pen.x), number(p.y - pen.y))
+}
+
+/// A number as KCL writes it, without noise from adding up floating point offsets.
+fn number(n: f64) -> String {
+    let rounded = (n * 1e9).round() / 1e9;
+    // Avoid writing "-0".
+    format!("{}", rounded + 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kittycad::types::{Angle, Point2D, UnitAngle};
+    use uuid::Uuid;
+
+    fn point(x: f64, y: f64, z: f64) -> Point3D {
+        Point3D { x, y, z }
+    }
+
+    fn line(x: f64, y: f64, relative: bool) -> PathSegment {
+        PathSegment::Line {
+            end: point(x, y, 0.0),
+            relative,
+        }
+    }
+
+    /// The KCL for a path from the origin.
+    fn sketched(segments: Vec<PathSegment>) -> Result<String> {
+        part(&Recipe::path(point(0.0, 0.0, 0.0), segments))
+    }
+
+    /// Why a part can't be written as KCL, with the context.
+    fn error(recipe: &Recipe) -> String {
+        part(recipe)
+            .unwrap_err()
+            .chain()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>()
+            .join(": ")
+    }
+
+    #[test]
+    fn writes_lines_to_points_and_by_offsets() {
+        assert_eq!(
+            sketched(vec![
+                line(10.0, 0.0, false),
+                line(0.0, 5.0, true),
+                line(-10.0, 0.0, true)
+            ])
+            .unwrap(),
+            "startSketchOn('XY')\n  |> startProfileAt([0, 0], %)\n  |> lineTo([10, 0], %)\n  \
+             |> line([0, 5], %)\n  |> line([-10, 0], %)\n  |> close(%)"
+        );
+    }
+
+    #[test]
+    fn writes_beziers_relative_to_the_pen() {
+        let curve = PathSegment::Bezier {
+            control1: point(12.0, 2.0, 0.0),
+            control2: point(12.0, 8.0, 0.0),
+            end: point(10.0, 10.0, 0.0),
+            relative: false,
+        };
+        assert_eq!(
+            sketched(vec![line(10.0, 0.0, false), curve]).unwrap(),
+            "startSketchOn('XY')\n  |> startProfileAt([0, 0], %)\n  |> lineTo([10, 0], %)\n  \
+             |> bezierCurve({ to: [0, 10], control1: [2, 2], control2: [2, 8] }, %)\n  |> close(%)"
+        );
+        // The same curve, written relative to the pen, is the same KCL.
+        let relative = PathSegment::Bezier {
+            control1: point(2.0, 2.0, 0.0),
+            control2: point(2.0, 8.0, 0.0),
+            end: point(0.0, 10.0, 0.0),
+            relative: true,
+        };
+        assert_eq!(
+            sketched(vec![line(10.0, 0.0, false), relative]).unwrap(),
+            sketched(vec![
+                line(10.0, 0.0, false),
+                PathSegment::Bezier {
+                    control1: point(12.0, 2.0, 0.0),
+                    control2: point(12.0, 8.0, 0.0),
+                    end: point(10.0, 10.0, 0.0),
+                    relative: false,
+                }
+            ])
+            .unwrap()
+        );
+    }
+
+    fn arc(center: (f64, f64), radius: f64, from: f64, to: f64, relative: bool) -> PathSegment {
+        let degrees = |value| {
+            Some(Angle {
+                unit: UnitAngle::Degrees,
+                value,
+            })
+        };
+        PathSegment::Arc {
+            angle_start: from,
+            angle_end: to,
+            center: Point2D {
+                x: center.0,
+                y: center.1,
+            },
+            start: degrees(from),
+            end: degrees(to),
+            radius,
+            relative,
+        }
+    }
+
+    #[test]
+    fn writes_arcs_which_start_at_the_pen() {
+        // Half a circle around (5, 0), from the origin to (10, 0), then a line back.
+        let kcl = sketched(vec![
+            arc((5.0, 0.0), 5.0, 180.0, 0.0, false),
+            line(0.0, 0.0, false),
+        ]);
+        assert_eq!(
+            kcl.unwrap(),
+            "startSketchOn('XY')\n  |> startProfileAt([0, 0], %)\n  \
+             |> arc({ angle_start: 180, angle_end: 0, radius: 5 }, %)\n  |> lineTo([0, 0], %)\n  |> close(%)"
+        );
+        // A relative center is from the pen.
+        let kcl = sketched(vec![
+            line(10.0, 0.0, false),
+            arc((5.0, 0.0), 5.0, 180.0, 90.0, true),
+        ]);
+        assert!(kcl
+            .unwrap()
+            .contains("|> arc({ angle_start: 180, angle_end: 90, radius: 5 }, %)"));
+    }
+
+    #[test]
+    fn fails_on_an_arc_away_from_the_pen() {
+        let recipe = Recipe::path(
+            point(0.0, 0.0, 0.0),
+            vec![
+                line(10.0, 0.0, false),
+                arc((0.0, 0.0), 5.0, 0.0, 90.0, false),
+            ],
+        );
+        assert_eq!(
+            error(&recipe),
+            "Segment 1 is an arc which doesn't start at the pen, which KCL can't draw"
+        );
+    }
+
+    #[test]
+    fn writes_tangential_arcs_to_a_point() {
+        let kcl = sketched(vec![
+            line(10.0, 0.0, false),
+            PathSegment::TangentialArcTo {
+                angle_snap_increment: None,
+                to: point(10.0, 10.0, 0.0),
+            },
+        ]);
+        assert!(kcl
+            .unwrap()
+            .contains("\n  |> tangentialArcTo([10, 10], %)\n  |> close(%)"));
+    }
+
+    #[test]
+    fn fails_on_a_tangential_arc_by_an_angle() {
+        let recipe = Recipe::path(
+            point(0.0, 0.0, 0.0),
+            vec![
+                line(10.0, 0.0, false),
+                PathSegment::TangentialArc {
+                    offset: Angle {
+                        unit: UnitAngle::Degrees,
+                        value: 90.0,
+                    },
+                    radius: 5.0,
+                },
+            ],
+        );
+        assert_eq!(
+            error(&recipe),
+            "Segment 1 is a tangential arc with an offset, which isn't translated to KCL yet"
+        );
+    }
+
+    #[test]
+    fn sketches_on_a_plane_at_the_path_s_height() {
+        let mut recipe = Recipe::path(
+            point(0.0, 0.0, 2.5),
+            vec![line(1.0, 0.0, true), line(0.0, 1.0, true)],
+        );
+        assert!(part(&recipe).unwrap().starts_with(
+            "startSketchOn({ plane: { origin: { x: 0, y: 0, z: 2.5 }, x_axis: { x: 1, y: 0, z: 0 }, \
+             y_axis: { x: 0, y: 1, z: 0 }, z_axis: { x: 0, y: 0, z: 1 } } })\n  |> startProfileAt([0, 0], %)"
+        ));
+        recipe.segments.push(PathSegment::Line {
+            end: point(0.0, 0.0, 3.0),
+            relative: false,
+        });
+        assert_eq!(
+            error(&recipe),
+            "The path leaves its sketch plane (at z = 2.5) to reach z = 3, which KCL can't draw"
+        );
+    }
+
+    fn square(size: f64) -> Recipe {
+        Recipe::path(
+            point(0.0, 0.0, 0.0),
+            vec![
+                line(size, 0.0, true),
+                line(0.0, size, true),
+                line(-size, 0.0, true),
+            ],
+        )
+    }
+
+    #[test]
+    fn writes_extrusions_and_holes() {
+        let mut hole = square(2.0);
+        hole.start = point(4.0, 4.0, 0.0);
+        let recipe = Recipe {
+            holes: vec![hole],
+            extrusion: Some(5.0),
+            ..square(10.0)
+        };
+        assert_eq!(
+            part(&recipe).unwrap(),
+            "startSketchOn('XY')\n  |> startProfileAt([0, 0], %)\n  |> line([10, 0], %)\n  \
+             |> line([0, 10], %)\n  |> line([-10, 0], %)\n  |> close(%)\n  \
+             |> hole(startSketchOn('XY') |> startProfileAt([4, 4], %) |> line([2, 0], %) \
+             |> line([0, 2], %) |> line([-2, 0], %) |> close(%), %)\n  |> extrude(5, %)"
+        );
+    }
+
+    #[test]
+    fn fails_on_extrusions_kcl_cannot_make() {
+        let uncapped = Recipe {
+            extrusion: Some(5.0),
+            uncapped: true,
+            ..square(10.0)
+        };
+        assert_eq!(
+            error(&uncapped),
+            "It was extruded without caps, which KCL can't do"
+        );
+        let sideways = Recipe {
+            extrusion: Some(5.0),
+            normal: Some(point(0.0, -1.0, 0.0)),
+            ..square(10.0)
+        };
+        assert_eq!(
+            error(&sideways),
+            "It was extruded along 0,-1,0, and only extrusions straight up are translated to KCL"
+        );
+        let up = Recipe {
+            normal: Some(point(0.0, 0.0, 1.0)),
+            ..sideways
+        };
+        assert!(part(&up).unwrap().ends_with("|> extrude(5, %)"));
+    }
+
+    #[test]
+    fn writes_numbers_without_float_noise() {
+        assert_eq!(number(0.1 + 0.2), "0.3");
+        assert_eq!(number(-0.0), "0");
+        assert_eq!(number(-1e-12), "0");
+        assert_eq!(number(12.5), "12.5");
+    }
+
+    #[test]
+    fn writes_each_part_in_the_order_it_was_built() {
+        let mut session = ModelingSession::canned("kcl-emit", &[]);
+        let (first, imported, second) =
+            (Uuid::from_u128(1), Uuid::from_u128(2), Uuid::from_u128(3));
+        session.record_recipe(
+            first,
+            Recipe {
+                extrusion: Some(1.0),
+                ..square(1.0)
+            },
+        );
+        session.track(imported);
+        session.record_recipe(second, square(2.0));
+        let kcl = emit(&session).unwrap();
+        let lines: Vec<_> = kcl.lines().filter(|line| !line.starts_with("  ")).collect();
+        assert_eq!(
+            lines,
+            [
+                "const part001 = startSketchOn('XY')",
+                &format!("// {imported} wasn't built from a sketch, so it's left out"),
+                "const part002 = startSketchOn('XY')",
+            ]
+        );
+        session.record_recipe(
+            Uuid::from_u128(4),
+            Recipe {
+                extrusion: Some(1.0),
+                uncapped: true,
+                ..square(1.0)
+            },
+        );
+        assert_eq!(
+            emit(&session).unwrap_err().to_string(),
+            format!("Can't write {} as KCL", Uuid::from_u128(4))
+        );
+    }
+}
@@ -0,0 +1,336 @@
+use crate::{snapshot::STDOUT, template::Drawing};
+use image::{Rgba, RgbaImage};
+use std::path::Path;
+
+/// Each glyph is 5 pixels wide and 7 tall, drawn in a cell 6 wide and 9 tall, so there's a gap
+/// between letters and between lines.
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+const CELL_WIDTH: u32 = 6;
+const CELL_HEIGHT: u32 = 9;
+
+/// The space around the caption, in font pixels.
+const PADDING: u32 = 4;
+
+/// Images at least this wide (in image pixels) get a bigger font, so the caption stays readable.
+/// Each multiple of it makes the font one size bigger.
+const SCALE_STEP: u32 = 320;
+
+/// How a caption fits along the bottom of an image.
+#[derive(Debug, PartialEq)]
+pub struct Layout {
+    /// The caption, wrapped to fit the image's width.
+    pub lines: Vec<String>,
+    /// How many image pixels each font pixel takes, across and down.
+    pub scale: u32,
+    /// How many image pixels the strip the caption is drawn in adds to the bottom of the image.
+    pub height: u32,
+}
+
+/// Work out how a caption fits along the bottom of an image `width` pixels wide.
+/// Lines are wrapped between words, or in the middle of a word too long for a line by itself.
+pub fn layout(text: &str, width: u32) -> Layout {
+    let scale = scale_for(width);
+    let columns = (width / scale).saturating_sub(2 * PADDING) / CELL_WIDTH;
+    let columns = columns.max(1) as usize;
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            let mut word: Vec<char> = word.chars().collect();
+            let needed = line.chars().count() + usize::from(!line.is_empty()) + word.len();
+            if needed > columns && !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            while word.len() > columns {
+                lines.push(word.drain(..columns).collect());
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.extend(word);
+        }
+        lines.push(line);
+    }
+    let rows = lines.len() as u32;
+    Layout {
+        lines,
+        scale,
+        height: (rows * CELL_HEIGHT - (CELL_HEIGHT - GLYPH_HEIGHT) + 2 * PADDING) * scale,
+    }
+}
+
+/// The image with a strip added along its bottom, showing the caption. The strip is the color of
+/// the image's bottom-left pixel (usually the background), and the text is black or white,
+/// whichever stands out more.
+pub fn add_caption(img: &RgbaImage, text: &str) -> RgbaImage {
+    let layout = layout(text, img.width());
+    let strip = match img.height() {
+        0 => Rgba([255, 255, 255, 255]),
+        height => *img.get_pixel(0, height - 1),
+    };
+    let ink = ink_on(strip);
+    let mut out = RgbaImage::from_pixel(img.width(), img.height() + layout.height, strip);
+    image::imageops::replace(&mut out, img, 0, 0);
+    let scale = layout.scale;
+    for (row, line) in layout.lines.iter().enumerate() {
+        let top = img.height() + (PADDING + row as u32 * CELL_HEIGHT) * scale;
+        draw_text(&mut out, line, PADDING * scale, top, scale, ink);
+    }
+    out
+}
+
+/// Black or white, whichever stands out more on `background`.
+pub fn ink_on(background: Rgba<u8>) -> Rgba<u8> {
+    let [r, g, b, _] = background.0;
+    let luminance = 0.299 * f64::from(r) + 0.587 * f64::from(g) + 0.114 * f64::from(b);
+    if luminance > 127.0 {
+        Rgba([0, 0, 0, 255])
+    } else {
+        Rgba([255, 255, 255, 255])
+    }
+}
+
+/// How the font is scaled for an image `width` pixels wide, so text stays readable on big images.
+pub fn scale_for(width: u32) -> u32 {
+    (width / SCALE_STEP).max(1)
+}
+
+/// How many image pixels wide and high one line of text is, with each font pixel `scale` image pixels across.
+pub fn text_size(text: &str, scale: u32) -> (u32, u32) {
+    let columns = text.chars().count() as u32;
+    let width = (columns * CELL_WIDTH).saturating_sub(CELL_WIDTH - GLYPH_WIDTH);
+    (width * scale, GLYPH_HEIGHT * scale)
+}
+
+/// Draw one line of text in `ink`, with its top-left corner at (`left`, `top`).
+/// Pixels which would fall outside the image are left out.
+pub fn draw_text(img: &mut RgbaImage, text: &str, left: u32, top: u32, scale: u32, ink: Rgba<u8>) {
+    for (column, c) in text.chars().enumerate() {
+        let left = left + column as u32 * CELL_WIDTH * scale;
+        for (x, bits) in glyph(c).iter().enumerate() {
+            for y in 0..GLYPH_HEIGHT {
+                if bits & (1 << y) == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let px = left + x as u32 * scale + dx;
+                        let py = top + y * scale + dy;
+                        if px < img.width() && py < img.height() {
+                            img.put_pixel(px, py, ink);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// What --label-auto captions a drawing with, e.g. "plate --hole-diameter=5 (2024-05-01)".
+pub fn auto(drawing: &Drawing) -> String {
+    format!("{} ({})", drawing.summary(), today())
+}
+
+/// Today's date in UTC, e.g. "2024-05-01".
+fn today() -> String {
+    let (year, month, day) = crate::sidecar::civil_date((crate::sidecar::now() / 86_400) as i64);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// Whether a snapshot saved to this path keeps its pixels exactly, so burning a caption into it
+/// doesn't lose anything else. Snapshots saved to standard output are PNGs.
+pub fn is_lossless(path: &str) -> bool {
+    if path == STDOUT {
+        return true;
+    }
+    let ext = Path::new(path)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    matches!(
+        ext.as_str(),
+        "png" | "bmp" | "tif" | "tiff" | "tga" | "ppm" | "pgm" | "pbm" | "pam" | "qoi"
+    )
+}
+
+/// A character's columns, left to right. Each column's lowest bit is its top pixel.
+/// Characters the font doesn't have are drawn as '?'.
+fn glyph(c: char) -> &'static [u8; GLYPH_WIDTH as usize] {
+    let index = match c {
+        ' '..='~' => c as usize - ' ' as usize,
+        _ => '?' as usize - ' ' as usize,
+    };
+    &FONT[index]
+}
+
+/// A 5x7 font covering printable ASCII, from ' ' to '~'.
+#[rustfmt::skip]
+const FONT: [[u8; GLYPH_WIDTH as usize]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x00, 0x00, 0x5F, 0x00, 0x00], // '!'
+    [0x00, 0x07, 0x00, 0x07, 0x00], // '"'
+    [0x14, 0x7F, 0x14, 0x7F, 0x14], // '#'
+    [0x24, 0x2A, 0x7F, 0x2A, 0x12], // '$'
+    [0x23, 0x13, 0x08, 0x64, 0x62], // '%'
+    [0x36, 0x49, 0x55, 0x22, 0x50], // '&'
+    [0x00, 0x05, 0x03, 0x00, 0x00], // '\''
+    [0x00, 0x1C, 0x22, 0x41, 0x00], // '('
+    [0x00, 0x41, 0x22, 0x1C, 0x00], // ')'
+    [0x08, 0x2A, 0x1C, 0x2A, 0x08], // '*'
+    [0x08, 0x08, 0x3E, 0x08, 0x08], // '+'
+    [0x00, 0x50, 0x30, 0x00, 0x00], // ','
+    [0x08, 0x08, 0x08, 0x08, 0x08], // '-'
+    [0x00, 0x60, 0x60, 0x00, 0x00], // '.'
+    [0x20, 0x10, 0x08, 0x04, 0x02], // '/'
+    [0x3E, 0x51, 0x49, 0x45, 0x3E], // '0'
+    [0x00, 0x42, 0x7F, 0x40, 0x00], // '1'
+    [0x42, 0x61, 0x51, 0x49, 0x46], // '2'
+    [0x21, 0x41, 0x45, 0x4B, 0x31], // '3'
+    [0x18, 0x14, 0x12, 0x7F, 0x10], // '4'
+    [0x27, 0x45, 0x45, 0x45, 0x39], // '5'
+    [0x3C, 0x4A, 0x49, 0x49, 0x30], // '6'
+    [0x01, 0x71, 0x09, 0x05, 0x03], // '7'
+    [0x36, 0x49, 0x49, 0x49, 0x36], // '8'
+    [0x06, 0x49, 0x49, 0x29, 0x1E], // '9'
+    [0x00, 0x36, 0x36, 0x00, 0x00], // ':'
+    [0x00, 0x56, 0x36, 0x00, 0x00], // ';'
+    [0x08, 0x14, 0x22, 0x41, 0x00], // '<'
+    [0x14, 0x14, 0x14, 0x14, 0x14], // '='
+    [0x00, 0x41, 0x22, 0x14, 0x08], // '>'
+    [0x02, 0x01, 0x51, 0x09, 0x06], // '?'
+    [0x32, 0x49, 0x79, 0x41, 0x3E], // '@'
+    [0x7E, 0x11, 0x11, 0x11, 0x7E], // 'A'
+    [0x7F, 0x49, 0x49, 0x49, 0x36], // 'B'
+    [0x3E, 0x41, 0x41, 0x41, 0x22], // 'C'
+    [0x7F, 0x41, 0x41, 0x22, 0x1C], // 'D'
+    [0x7F, 0x49, 0x49, 0x49, 0x41], // 'E'
+    [0x7F, 0x09, 0x09, 0x01, 0x01], // 'F'
+    [0x3E, 0x41, 0x41, 0x51, 0x32], // 'G'
+    [0x7F, 0x08, 0x08, 0x08, 0x7F], // 'H'
+    [0x00, 0x41, 0x7F, 0x41, 0x00], // 'I'
+    [0x20, 0x40, 0x41, 0x3F, 0x01], // 'J'
+    [0x7F, 0x08, 0x14, 0x22, 0x41], // 'K'
+    [0x7F, 0x40, 0x40, 0x40, 0x40], // 'L'
+    [0x7F, 0x02, 0x04, 0x02, 0x7F], // 'M'
+    [0x7F, 0x04, 0x08, 0x10, 0x7F], // 'N'
+    [0x3E, 0x41, 0x41, 0x41, 0x3E], // 'O'
+    [0x7F, 0x09, 0x09, 0x09, 0x06], // 'P'
+    [0x3E, 0x41, 0x51, 0x21, 0x5E], // 'Q'
+    [0x7F, 0x09, 0x19, 0x29, 0x46], // 'R'
+    [0x46, 0x49, 0x49, 0x49, 0x31], // 'S'
+    [0x01, 0x01, 0x7F, 0x01, 0x01], // 'T'
+    [0x3F, 0x40, 0x40, 0x40, 0x3F], // 'U'
+    [0x1F, 0x20, 0x40, 0x20, 0x1F], // 'V'
+    [0x7F, 0x20, 0x18, 0x20, 0x7F], // 'W'
+    [0x63, 0x14, 0x08, 0x14, 0x63], // 'X'
+    [0x03, 0x04, 0x78, 0x04, 0x03], // 'Y'
+    [0x61, 0x51, 0x49, 0x45, 0x43], // 'Z'
+    [0x00, 0x7F, 0x41, 0x41, 0x00], // '['
+    [0x02, 0x04, 0x08, 0x10, 0x20], // '\\'
+    [0x00, 0x41, 0x41, 0x7F, 0x00], // ']'
+    [0x04, 0x02, 0x01, 0x02, 0x04], // '^'
+    [0x40, 0x40, 0x40, 0x40, 0x40], // '_'
+    [0x00, 0x01, 0x02, 0x04, 0x00], // '`'
+    [0x20, 0x54, 0x54, 0x54, 0x78], // 'a'
+    [0x7F, 0x48, 0x44, 0x44, 0x38], // 'b'
+    [0x38, 0x44, 0x44, 0x44, 0x20], // 'c'
+    [0x38, 0x44, 0x44, 0x48, 0x7F], // 'd'
+    [0x38, 0x54, 0x54, 0x54, 0x18], // 'e'
+    [0x08, 0x7E, 0x09, 0x01, 0x02], // 'f'
+    [0x08, 0x54, 0x54, 0x54, 0x3C], // 'g'
+    [0x7F, 0x08, 0x04, 0x04, 0x78], // 'h'
+    [0x00, 0x44, 0x7D, 0x40, 0x00], // 'i'
+    [0x20, 0x40, 0x44, 0x3D, 0x00], // 'j'
+    [0x7F, 0x10, 0x28, 0x44, 0x00], // 'k'
+    [0x00, 0x41, 0x7F, 0x40, 0x00], // 'l'
+    [0x7C, 0x04, 0x18, 0x04, 0x78], // 'm'
+    [0x7C, 0x08, 0x04, 0x04, 0x78], // 'n'
+    [0x38, 0x44, 0x44, 0x44, 0x38], // 'o'
+    [0x7C, 0x14, 0x14, 0x14, 0x08], // 'p'
+    [0x08, 0x14, 0x14, 0x18, 0x7C], // 'q'
+    [0x7C, 0x08, 0x04, 0x04, 0x08], // 'r'
+    [0x48, 0x54, 0x54, 0x54, 0x20], // 's'
+    [0x04, 0x3F, 0x44, 0x40, 0x20], // 't'
+    [0x3C, 0x40, 0x40, 0x20, 0x7C], // 'u'
+    [0x1C, 0x20, 0x40, 0x20, 0x1C], // 'v'
+    [0x3C, 0x40, 0x30, 0x40, 0x3C], // 'w'
+    [0x44, 0x28, 0x10, 0x28, 0x44], // 'x'
+    [0x0C, 0x50, 0x50, 0x50, 0x3C], // 'y'
+    [0x44, 0x64, 0x54, 0x4C, 0x44], // 'z'
+    [0x00, 0x08, 0x36, 0x41, 0x00], // '{'
+    [0x00, 0x00, 0x7F, 0x00, 0x00], // '|'
+    [0x00, 0x41, 0x36, 0x08, 0x00], // '}'
+    [0x08, 0x04, 0x08, 0x10, 0x08], // '~'
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check every line of the layout, with the padding either side, fits in `width` image pixels.
+    fn assert_fits(layout: &Layout, width: u32) {
+        for line in &layout.lines {
+            let (line_width, _) = text_size(line, layout.scale);
+            assert!(
+                line_width + 2 * PADDING * layout.scale <= width,
+                "{line:?} is {line_width} pixels wide, too wide for {width}"
+            );
+        }
+    }
+
+    #[test]
+    fn wraps_between_words() {
+        // (100 - 2 * 4) / 6 = 15 columns.
+        let layout = layout("the quick brown fox jumps over the lazy dog", 100);
+        assert_eq!(
+            layout.lines,
+            ["the quick brown", "fox jumps over", "the lazy dog"]
+        );
+        assert_eq!(layout.scale, 1);
+        assert_fits(&layout, 100);
+    }
+
+    #[test]
+    fn splits_a_word_too_long_for_a_line_by_itself() {
+        let layout = layout("a abcdefghijklmnopqrstuvwxyz0123456789 z", 100);
+        assert_eq!(
+            layout.lines,
+            ["a", "abcdefghijklmno", "pqrstuvwxyz0123", "456789 z"]
+        );
+        assert_fits(&layout, 100);
+    }
+
+    #[test]
+    fn starts_a_new_line_for_each_line_of_the_caption() {
+        let layout = layout("plate\n--hole-diameter=5", 400);
+        assert_eq!(layout.lines, ["plate", "--hole-diameter=5"]);
+    }
+
+    #[test]
+    fn fits_captions_of_any_length_into_images_of_any_width() {
+        let text = "plate --hole-diameter=5 --thickness=2.5 (2024-05-01) supercalifragilisticexpialidocious";
+        for width in [60, 100, 319, 320, 640, 1000, 1920] {
+            assert_fits(&layout(text, width), width);
+        }
+    }
+
+    #[test]
+    fn scales_the_font_up_on_wide_images() {
+        let narrow = layout("plate", 320);
+        let wide = layout("plate", 960);
+        assert_eq!((narrow.scale, wide.scale), (1, 3));
+        // One line of glyphs, and the padding above and below it.
+        assert_eq!(narrow.height, GLYPH_HEIGHT + 2 * PADDING);
+        assert_eq!(wide.height, 3 * narrow.height);
+    }
+
+    #[test]
+    fn makes_room_for_every_line() {
+        let layout = layout("the quick brown fox jumps over the lazy dog", 100);
+        assert_eq!(
+            layout.height,
+            3 * CELL_HEIGHT - (CELL_HEIGHT - GLYPH_HEIGHT) + 2 * PADDING
+        );
+    }
+}
@@ -0,0 +1,2637 @@
+//! Examples of drawing, snapshotting and exporting models with KittyCAD's modeling API.
+//!
+//! The modules here are shared by the command line (`src/main.rs`), which has every feature,
+//! and by the examples in `examples/`, which each show one thing on its own.
+//!
+//! New to the modeling API? Start with `examples/minimal.rs`, which draws a cube and saves a snapshot
+//! of it, top to bottom, sending every command itself.
+
+/// Print a line of progress, like `println!`.
+/// When the snapshot is written to standard output, progress is printed to standard error instead.
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if crate::LOG_TO_STDERR.load(std::sync::atomic::Ordering::Relaxed) {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
+}
+
+mod background;
+mod bench;
+mod budget;
+mod cache;
+pub mod camera;
+pub mod client;
+mod compare;
+mod coords;
+#[cfg(test)]
+mod counting;
+mod debug;
+mod degenerate;
+mod dimension;
+mod entities;
+pub mod events;
+mod explode;
+pub mod export;
+mod expr;
+pub mod facecolor;
+mod gallery;
+pub mod geometry;
+mod imgdiff;
+pub mod import;
+#[cfg(feature = "test-hooks")]
+mod inject;
+mod jitter;
+mod kcl;
+mod label;
+mod manifest;
+mod materials;
+mod measure;
+pub mod names;
+mod notify;
+pub mod paths;
+mod pattern;
+mod pipe;
+mod precision;
+mod preflight;
+mod primitives;
+mod probe;
+mod profile;
+mod progress;
+mod projection;
+mod properties;
+mod raw;
+mod recenter;
+mod recipe;
+mod scene;
+mod scenediff;
+mod selection;
+mod server;
+pub mod session;
+pub mod shapes;
+mod sidecar;
+mod sink;
+pub mod sketch;
+pub mod snapshot;
+mod state;
+mod stroke;
+mod svg;
+mod sweep;
+mod sweepreport;
+mod template;
+mod tessellation;
+mod timings;
+pub mod token;
+pub mod transform;
+mod transport;
+mod turntable;
+mod units;
+mod verify;
+mod warnings;
+mod watch;
+
+use background::Color;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use color_eyre::{
+    eyre::{bail, Context},
+    Result, Section,
+};
+use entities::EntityTree;
+use geometry::BoundingBox;
+use kittycad::types::{EntityType, Point3D, UnitLength};
+use materials::Density;
+use properties::Validation;
+use session::{ConnectionLost, ModelingSession, ServerUnavailable};
+use snapshot::Output;
+use std::{
+    env,
+    io::IsTerminal,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use transform::Transform;
+use uuid::Uuid;
+
+/// Whether progress is printed to standard error, because standard output is taken by an output file.
+static LOG_TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Draw a model with the KittyCAD Modeling API, measure it, and save a PNG of it.
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    /// Where should the final PNG be saved? Use "-" to write it to standard output, for piping.
+    #[arg(long, env = "IMAGE_OUTPUT_PATH", default_value = "model.png")]
+    output: String,
+
+    /// Name every output from this template instead, e.g. "renders/{shape}_{width}x{height}.{ext}",
+    /// creating any directories it needs. Placeholders are {shape}, {frame} (the drawing's position in a
+    /// sweep, scene or gallery), {ext} (e.g. png or stl), and any of the model's numeric options,
+    /// with underscores for dashes, e.g. {hole_diameter}. It must end with ".{ext}".
+    /// The run fails if two outputs get the same name.
+    #[arg(long)]
+    output_template: Option<String>,
+
+    /// Where to save outputs (snapshots, exports and their sidecars). "s3" saves them to an
+    /// S3-compatible bucket, configured by $S3_ENDPOINT, $S3_BUCKET, $S3_REGION, $S3_PREFIX and
+    /// the usual $AWS_ACCESS_KEY_ID and $AWS_SECRET_ACCESS_KEY, if this was built with the "s3" feature.
+    #[arg(long, value_enum, default_value = "file")]
+    output_sink: sink::SinkKind,
+
+    /// If saving a file fails in a way which may not happen next time (e.g. a network filesystem
+    /// timed out, or the file didn't read back as it was saved), save it again up to this many times.
+    /// Each time is warned about. Other failures, e.g. a full disk, fail the run at once.
+    #[arg(long, default_value_t = 3)]
+    write_retries: u32,
+
+    /// How long to wait before saving a file again (see --write-retries), e.g. 0.5s.
+    #[arg(long, default_value = "1s", value_parser = budget::parse_duration)]
+    write_retry_delay: Duration,
+
+    /// Write binary output to standard output even if it's a terminal.
+    #[arg(long)]
+    force_tty: bool,
+
+    /// Also export the model to this file. Its extension chooses the format: stl, obj, ply or step.
+    #[arg(long)]
+    export: Option<PathBuf>,
+
+    /// Also export the model in these formats (stl, obj, ply or step, separated by commas, or repeated),
+    /// each next to --export's file, with its own extension, e.g. --export model.stl --export-as obj,step.
+    #[arg(long, requires = "export", value_delimiter = ',')]
+    export_as: Vec<String>,
+
+    /// How many of the model's exports (see --export-as) may be waiting for the API at once. Each
+    /// export's files are saved as soon as they arrive, whichever arrives first. With 1, the next export
+    /// is only asked for once the last one's files are saved, so only one is held in memory at a time.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    export_concurrency: u32,
+
+    /// Gzip the exported files. WebSocket compression (permessage-deflate) isn't supported
+    /// by the WebSocket library, so this shrinks the files on disk, not on the wire.
+    #[arg(long, requires = "export")]
+    compress: bool,
+
+    /// Fail the run if an exported mesh (stl, obj or ply) is malformed, has no triangles, or has vertices
+    /// which are NaN. Otherwise, those are warned about. Either way, each mesh is read back,
+    /// and what's in it is printed and saved in its sidecar.
+    #[arg(long, requires = "export")]
+    verify_export: bool,
+
+    /// Only export this object, given by its ID (e.g. one saved with --save-state and carried on with
+    /// --resume) or its name: the model's, e.g. "plate", or a scene part's. A model which makes several
+    /// objects calls them e.g. "cube_1", "cube_2". Repeat it to export several objects.
+    /// By default, every object in the model is exported.
+    #[arg(long, requires = "export", value_parser = names::Handle::parse)]
+    export_only: Vec<names::Handle>,
+
+    /// Move the exported model so its bounding box is centered on the origin, or so it sits on the
+    /// XY plane, centered over the origin ("bed"), which slicers like. Snapshots show the model where
+    /// it was built, unless --recenter-snapshots is given too.
+    #[arg(long, value_enum, requires = "export")]
+    recenter: Option<recenter::Recenter>,
+
+    /// Move the model as --recenter says before the snapshots are taken, too.
+    #[arg(long, requires = "recenter")]
+    recenter_snapshots: bool,
+
+    /// Build the model here, as "x,y,z", instead of around the origin. Its paths are moved before they're
+    /// sent, so it's built in place, and any transforms the model does happen after that.
+    #[arg(long, default_value = "0,0,0", value_parser = coords::Given::parse)]
+    at: coords::Given,
+
+    /// Which axis points up in the tool the run's points came from, e.g. y for Blender's exports.
+    /// Points in files (CSV profiles, SVG outlines and scene files) and options (like --at and --translate)
+    /// are turned to match the API, whose Z axis points up. A 2D file's points are on the tool's ground:
+    /// X and Y if Z is up, or X and Z if Y is up.
+    #[arg(long, value_enum, default_value_t = coords::Up::Z)]
+    input_up: coords::Up,
+
+    /// Which way the axes turn in the tool the run's points came from, e.g. left for Unity or Unreal.
+    /// Left-handed points are mirrored, so models come out the right way round.
+    #[arg(long, value_enum, default_value_t = coords::Handedness::Right)]
+    input_handedness: coords::Handedness,
+
+    /// Which way to look at the model when framing it. By default, some models are looked at from
+    /// wherever shows them best (e.g. the L-bracket from inside the L), and the rest from the iso view.
+    #[arg(long, value_enum)]
+    view: Option<camera::View>,
+
+    /// Instead of one snapshot, save this many, turning the camera all the way around the model,
+    /// numbered from 0, e.g. "model-000.png". Progress is saved after each frame in "<output>.progress.json".
+    #[arg(long, value_parser = clap::value_parser!(u32).range(2..))]
+    turntable: Option<u32>,
+
+    /// Carry on with a turntable which stopped partway, e.g. because the connection dropped, skipping
+    /// the frames its progress file says were saved (as long as they're still there, and not empty).
+    #[arg(long, requires = "turntable")]
+    resume_frames: bool,
+
+    /// Zoom to fit each turntable frame on its own, as older versions did, instead of keeping the
+    /// camera far enough back for the model to fit at every angle. The model seems to grow and shrink
+    /// as it turns.
+    #[arg(long, requires = "turntable")]
+    per_frame_fit: bool,
+
+    /// How much room to leave around the model in turntable frames, as a fraction of its size,
+    /// e.g. 0.1 for 10%.
+    #[arg(long, default_value_t = 0.05, value_parser = turntable::parse_margin)]
+    turntable_margin: f64,
+
+    /// Draw the model twice, side by side along X, with two values of one of its numeric options,
+    /// e.g. "hole-diameter=5,8", and save one snapshot of both. The first value is on the left.
+    /// The snapshot's sidecar says which variant is which.
+    #[arg(long)]
+    compare: Option<compare::Compare>,
+
+    /// Repaint the snapshot's background this color, e.g. "#103050".
+    #[arg(long)]
+    background: Option<Color>,
+
+    /// Only save this part of each snapshot, as "x,y,width,height" in pixels from the top-left corner.
+    #[arg(long, value_parser = snapshot::PixelRect::parse, conflicts_with = "crop_to_entity")]
+    crop: Option<snapshot::PixelRect>,
+
+    /// Only save the part of each snapshot showing this object, given by its ID (e.g. a cube's ID from
+    /// --save-state) or its name, like --export-only takes.
+    /// Where it appears is worked out from the camera, so the crop is a little loose.
+    #[arg(long, value_parser = names::Handle::parse)]
+    crop_to_entity: Option<names::Handle>,
+
+    /// How many pixels to leave around the object when cropping to it.
+    #[arg(long, default_value_t = 16, requires = "crop_to_entity")]
+    crop_padding: u32,
+
+    /// Also save a snapshot at this size, e.g. "256x256:thumb.png". Repeat it for more sizes.
+    /// Each is taken of the same model, straight after the main one. The API is asked for
+    /// snapshots of that size if it can make them, and if it can't, they're cropped and scaled here.
+    #[arg(long, value_parser = snapshot::SizedSnapshot::parse)]
+    snapshot: Vec<snapshot::SizedSnapshot>,
+
+    /// Add a strip along the bottom of each snapshot, showing this caption, so it stays with
+    /// the image wherever it's copied. The model's pixels are left as they are.
+    #[arg(long, conflicts_with = "label_auto")]
+    label: Option<String>,
+
+    /// Caption each snapshot with its model and the options it was given, and the date.
+    #[arg(long)]
+    label_auto: bool,
+
+    /// Don't caption snapshots saved in formats which lose detail when they're saved again,
+    /// e.g. JPEG. They're saved without their captions, with a warning.
+    #[arg(long)]
+    no_reencode: bool,
+
+    /// If a snapshot is blank (just the background), take it again up to this many times.
+    /// 0 saves snapshots without checking them.
+    #[arg(long, default_value_t = 2)]
+    blank_retries: u32,
+
+    /// What fraction of a snapshot's pixels must be background for it to count as blank.
+    #[arg(long, default_value_t = 0.999)]
+    blank_threshold: f64,
+
+    /// How far (per channel, out of 255) a pixel may be from the background's color and still count as background.
+    #[arg(long, default_value_t = background::TOLERANCE)]
+    blank_tolerance: u8,
+
+    /// Check the measured properties against their expected values, and fail if they differ.
+    /// This makes the example double as a smoke test of the API.
+    /// Also checks that the model has as many faces and edges as it should.
+    #[arg(long, env = "VALIDATE", visible_alias = "verify")]
+    validate: bool,
+
+    /// How far (as a fraction of the expected value) may measurements deviate when validating?
+    #[arg(long, env = "VALIDATION_TOLERANCE", default_value_t = 0.01)]
+    tolerance: f64,
+
+    /// Report how heavy the model would be if it was made of this material.
+    /// One of: aluminum, steel, abs, pla.
+    #[arg(long, conflicts_with = "density")]
+    material: Option<String>,
+
+    /// Report how heavy the model would be if it was made of a material this dense,
+    /// e.g. "7850 kg:m3" or "490 lb:ft3".
+    #[arg(long)]
+    density: Option<Density>,
+
+    /// Print a tree of every entity (faces, edges, etc.) inside the model.
+    #[arg(long)]
+    inspect: bool,
+
+    /// After the run, write a JSON manifest of every object created, how it was made,
+    /// what's inside it, and which snapshots and exports show it.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "scene-manifest.json")]
+    manifest: Option<PathBuf>,
+
+    /// After the run, write KCL source which builds the same solids, to carry on with in KCL.
+    #[arg(long)]
+    emit_kcl: Option<PathBuf>,
+
+    /// After drawing the model, save the IDs of what was built (and how) to this file.
+    #[arg(long)]
+    save_state: Option<PathBuf>,
+
+    /// Instead of drawing a model, carry on with the one saved by --save-state.
+    /// The API only keeps objects for as long as their connection is open, so this fails
+    /// (and explains how to rebuild the model) unless the objects still exist.
+    #[arg(long, conflicts_with = "save_state")]
+    resume: Option<PathBuf>,
+
+    /// Before building anything, warn about anything the model (or scene) needs which the API
+    /// didn't support when it was probed, according to this file, saved by probe --save.
+    #[arg(long)]
+    capabilities: Option<PathBuf>,
+
+    /// Print which version of the API the run is talking to, and how it connects.
+    /// This is always included in the --notify-url report.
+    #[arg(long)]
+    server_info: bool,
+
+    /// Connect through this HTTP proxy, e.g. http://proxy.example.com:3128, instead of any set in
+    /// $HTTPS_PROXY or $ALL_PROXY. Hosts in $NO_PROXY are still reached directly.
+    #[arg(long)]
+    proxy: Option<String>,
+
+    /// Also trust the root certificates in this PEM file, e.g. for a proxy which re-signs TLS.
+    #[arg(long, env = "SSL_CERT_FILE")]
+    ca_cert: Option<PathBuf>,
+
+    /// Don't verify the API's TLS certificate. This lets anyone in between read the session,
+    /// including the API token, so only use it to diagnose a connection; prefer --ca-cert.
+    #[arg(long)]
+    insecure_skip_verify: bool,
+
+    /// Get the API token by running this shell command, instead of from $KITTYCAD_API_TOKEN, e.g. to ask
+    /// a service for a short-lived one. The command prints the token, or JSON like
+    /// {"token": "...", "expires_in": 3600}. It's run again when the token is about to expire, and once
+    /// more if the API refuses it.
+    #[arg(long, conflicts_with = "offline")]
+    token_command: Option<String>,
+
+    /// How long a token printed by --token-command lasts, if it doesn't say, e.g. 15m.
+    #[arg(long, default_value = "15m", value_parser = budget::parse_duration)]
+    token_lifetime: Duration,
+
+    /// Write every message sent to and received from the API to this file (one JSON object per line),
+    /// to play back later with --offline. With --offline, it's what the run sent this time, and the
+    /// responses played back to it, e.g. to see how a new version of the kittycad crate sends the
+    /// same commands. It can be the file being played back, to bring it up to date.
+    #[arg(long)]
+    record: Option<PathBuf>,
+
+    /// Don't connect to the API: play back the session recorded in this file by --record instead.
+    /// No API token is needed. The run has to send the same kinds of commands, in the same order,
+    /// as the recorded one did, or it fails saying the recording is out of date.
+    #[arg(long, conflicts_with = "server_info")]
+    offline: Option<PathBuf>,
+
+    /// How many commands may be waiting for a response at once.
+    /// Sending another command waits until the API responds to one of them.
+    #[arg(long, default_value_t = 32, value_parser = clap::value_parser!(u32).range(1..))]
+    max_in_flight: u32,
+
+    /// How many seconds a command may wait for a response before the run fails,
+    /// naming the command which got stuck.
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
+    command_deadline: u64,
+
+    /// Don't give up on the command being waited for after 60 seconds, however the commands before it are
+    /// getting on. For very long sessions, e.g. huge exports: only --command-deadline and --idle-timeout
+    /// then decide when the run fails.
+    #[arg(long)]
+    no_overall_timeout: bool,
+
+    /// How many seconds the API may send nothing at all (not even a ping) before the run fails.
+    #[arg(long, default_value_t = 60, value_parser = clap::value_parser!(u64).range(1..))]
+    idle_timeout: u64,
+
+    /// The biggest WebSocket message the run may send or receive, in bytes. Raise it for exports
+    /// too big to receive. Batches too big to send are split into smaller ones.
+    /// Messages aren't streamed: each is held in memory whole while it's decoded, and an export's files
+    /// take up to half as much again while they're read from it, so receiving one takes up to about
+    /// 1.5 times this.
+    #[arg(long, default_value_t = session::DEFAULT_MAX_MESSAGE_BYTES as u64, value_parser = clap::value_parser!(u64).range(1024..))]
+    max_ws_message_bytes: u64,
+
+    /// Which video stream to ask the API for when connecting. Snapshots don't need one, so by default
+    /// it's kept as cheap as the API allows. --server-info prints what the profile comes to.
+    #[arg(long, value_enum, default_value_t)]
+    connection_profile: session::ConnectionProfile,
+
+    /// Fail the run if the API sends a message which can't be parsed, e.g. a new kind of response
+    /// from a newer API. Otherwise it's printed and skipped, unless it responds to a command
+    /// being waited for, and the number skipped is reported at the end of the run.
+    #[arg(long)]
+    strict: bool,
+
+    /// Send profiles which can't make a solid (no area, all on one line, or crossing themselves)
+    /// anyway, e.g. to see what the API does with them. Normally they're refused before they're sent.
+    #[arg(long)]
+    allow_degenerate: bool,
+
+    /// Extrude without caps on the ends, leaving open tubes instead of solids, e.g. to hollow out
+    /// or loft later. They have no inside, so their volume and mass don't mean much, and their
+    /// exports aren't closed meshes.
+    #[arg(long)]
+    no_cap: bool,
+
+    /// Draw curves (e.g. circles, and an SVG's curves) as straight lines, at this quality: low, medium,
+    /// high, or how far the lines may stray from the curve, e.g. 0.05mm. Coarser means fewer commands
+    /// and smaller exports. By default, curves are sent to the API as curves. This overrides a scene
+    /// file's "tessellation".
+    #[arg(long, value_parser = tessellation::Quality::parse)]
+    tessellation: Option<tessellation::Quality>,
+
+    /// Fail the run if the API responds to a command before one sent earlier, naming both.
+    /// Otherwise it's warned about, and the run's summary says how often it happened.
+    #[arg(long)]
+    strict_ordering: bool,
+
+    /// Round every point's coordinates to this many decimal places before sending it, so commands are
+    /// smaller and read 10 rather than 9.999999999999998. A path which ends within rounding of where it
+    /// started still ends exactly there. The run's summary says how many bytes it saved.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(0..=15))]
+    coord_precision: Option<u32>,
+
+    /// Keep the outputs of runs in this directory, keyed by a hash of their options (and of the files the
+    /// options name), and if a run's outputs are already there, copy them into place instead of connecting.
+    /// Needs --deterministic-ids, so the cached sidecars have the IDs a new run would. Anything the run
+    /// would print, like measurements, isn't repeated, and its checks aren't run again.
+    #[arg(long, requires = "deterministic_ids")]
+    cache_dir: Option<PathBuf>,
+
+    /// Don't use --cache-dir, e.g. to check the API still draws the same thing.
+    #[arg(long)]
+    no_cache: bool,
+
+    /// How big --cache-dir may grow, in bytes. The least recently used outputs are removed to make room.
+    #[arg(long, default_value_t = 1 << 30)]
+    cache_max_bytes: u64,
+
+    /// How long the run may take, e.g. 60s or 2m. As the time runs low, optional steps are skipped, with a warning
+    /// for each: first snapshots from partway through a model, then turntable frames after the first, then the
+    /// --export file, and last of all captions and background colors. The model's snapshot is never skipped.
+    #[arg(long, value_parser = budget::parse_duration)]
+    time_budget: Option<Duration>,
+
+    /// Fail the run on warnings, instead of carrying on. Give codes (e.g. --deny-warnings=out-of-order,blank-snapshot)
+    /// to only fail on those. Either way, the run's summary lists every warning, with how often it happened.
+    #[arg(long, num_args = 0..=1, require_equals = true, value_delimiter = ',', value_enum)]
+    deny_warnings: Option<Vec<warnings::Code>>,
+
+    /// Don't save a "<file>.meta.json" sidecar next to each output, describing where it came from.
+    #[arg(long)]
+    no_sidecar: bool,
+
+    /// Remove everything the run created from the scene before closing the connection, even if
+    /// the run succeeded. If it fails, that's done anyway.
+    #[arg(long)]
+    clean_on_exit: bool,
+
+    /// Only show a scene's parts in these groups (separated by commas, or repeated). The other parts are
+    /// still built, unless they say not to be, but they're hidden from snapshots and left out of exports.
+    #[arg(long, value_delimiter = ',')]
+    only_group: Vec<String>,
+
+    /// Hide a scene's parts in these groups (separated by commas, or repeated), like --only-group.
+    #[arg(long, value_delimiter = ',')]
+    hide_group: Vec<String>,
+
+    /// After rendering a scene, keep watching its file (and the files it includes), and render it again,
+    /// in an empty scene, whenever they change. Stop with Ctrl-C.
+    #[arg(long)]
+    watch: bool,
+
+    /// If the API's snapshot or export can't be decoded or saved, keep its raw payload in this directory,
+    /// to attach to a bug report.
+    #[arg(long, default_value = debug::DEFAULT_DIR)]
+    debug_dir: PathBuf,
+
+    /// How big --debug-dir may grow, in bytes. Its oldest files are deleted to make room for new ones.
+    #[arg(long, default_value_t = debug::DEFAULT_MAX_BYTES)]
+    debug_dir_max_bytes: u64,
+
+    /// When the run finishes (or fails), POST a JSON report of how it went to this URL.
+    /// If the notification can't be delivered, that's printed, but doesn't fail the run.
+    #[arg(long)]
+    notify_url: Option<String>,
+
+    /// How many seconds to wait for --notify-url to respond.
+    #[arg(long, default_value_t = 10, requires = "notify_url")]
+    notify_timeout: u64,
+
+    /// Keep a line on stderr up to date with how the run's going: which phase it's in,
+    /// and how many commands have been sent.
+    #[arg(long)]
+    progress: bool,
+
+    /// Generate command IDs from a seed (0 unless given, e.g. --deterministic-ids=7), so that
+    /// every run with the same options sends the same IDs, and their outputs can be diffed.
+    /// Normal runs should keep the default random IDs, which are what the API expects.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "0")]
+    deterministic_ids: Option<u64>,
+
+    /// How many times to send a command again if it fails with one of the --retry-on errors.
+    /// Each retry waits twice as long as the one before, from 0.25s, up to 30s.
+    #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u32).range(..=20))]
+    retries: u32,
+
+    /// Which errors are worth retrying, separated by commas.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "internal_engine,internal_api",
+        value_parser = session::parse_error_code
+    )]
+    retry_on: Vec<kittycad::types::ErrorCode>,
+
+    /// Also retry commands which change the scene, like Extrude, not just queries.
+    /// If one of those failed part way through, retrying it could build something twice.
+    #[arg(long)]
+    retry_scene_changes: bool,
+
+    /// How many times to connect again if the API closes the session before responding to anything,
+    /// as it can for a few seconds while it's being deployed. Waits 1s before the first, then twice as long
+    /// each time, up to 30s.
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(..=20))]
+    session_retries: u32,
+
+    /// Which model to draw. Defaults to a cube.
+    #[command(subcommand)]
+    model: Option<Model>,
+}
+
+#[derive(Clone, Subcommand)]
+enum Model {
+    /// Draw one or more cubes, and measure their volume and surface area.
+    Cube {
+        /// How many cubes should be drawn? They're laid out in a row along the X axis,
+        /// and the physical properties are measured for all of them combined.
+        #[arg(long, env = "CUBE_COUNT", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+        count: u32,
+    },
+    /// Draw an L-shaped bracket, and mark its center of mass.
+    LBracket,
+    /// Sketch a square, and check the API has its vertices where we drew them.
+    /// Then extrude it into a cube.
+    PathInfo,
+    /// Draw a plate with a hole drilled through it.
+    Plate {
+        /// How wide the hole is.
+        #[arg(long, default_value_t = 10.0, value_parser = units::parse_length)]
+        hole_diameter: f64,
+        /// Where the hole's center is along X, relative to the plate's center.
+        #[arg(long, default_value_t = 0.0, value_parser = units::parse_length)]
+        hole_x: f64,
+        /// Where the hole's center is along Y, relative to the plate's center.
+        #[arg(long, default_value_t = 0.0, value_parser = units::parse_length)]
+        hole_y: f64,
+    },
+    /// Draw two cubes, and measure the gap between them and the length of an edge.
+    Measure {
+        /// How far apart the cubes' facing sides should be.
+        #[arg(long, default_value_t = 10.0, value_parser = units::parse_length)]
+        gap: f64,
+    },
+    /// Draw a cube, then move, rotate and scale it.
+    /// A snapshot from before the transform is saved next to the output, with "-before" in its name.
+    Transform {
+        /// How far to move the cube, as "x,y,z".
+        #[arg(long, default_value = "20,0,0", value_parser = coords::Given::parse)]
+        translate: coords::Given,
+        /// How far to rotate the cube about its axis, as "axis,degrees". Only the z axis is supported.
+        #[arg(long, default_value = "z,45", value_parser = transform::parse_rotation)]
+        rotate: f64,
+        /// How much to scale the cube by.
+        #[arg(long, default_value = "1.5", value_parser = transform::parse_scale)]
+        scale: f64,
+    },
+    /// Draw a pin (a small cylinder), and repeat it in a row.
+    LinearPattern {
+        /// How many pins there are, including the first one.
+        #[arg(long, default_value_t = 5)]
+        count: u32,
+        /// How far apart neighbouring pins are, as "x,y,z".
+        #[arg(long, default_value = "10,0,0", value_parser = coords::Given::parse)]
+        spacing: coords::Given,
+    },
+    /// Draw a disc with a circle of bolt holes drilled through it.
+    BoltCircle {
+        /// How many holes there are.
+        #[arg(long, default_value_t = 6, value_parser = clap::value_parser!(u32).range(1..))]
+        count: u32,
+    },
+    /// Draw a block, then sketch a circle on its top face and extrude it into a boss.
+    Boss,
+    /// Sketch a bar on the XY plane and another on the XZ plane, and extrude both, making a cross.
+    Cross {
+        /// Which standard plane to sketch the second bar on.
+        #[arg(long, value_enum, default_value_t = sketch::StandardPlane::Xz)]
+        plane: sketch::StandardPlane,
+        /// Sketch the second bar on a plane through the origin facing this way, as "x,y,z",
+        /// instead of on a standard plane.
+        #[arg(long, value_parser = coords::Given::parse, conflicts_with = "plane")]
+        normal: Option<coords::Given>,
+    },
+    /// Draw a cube, and highlight some of its edges: by default, the four vertical ones.
+    /// A snapshot with the edges highlighted is saved next to the output, with "-highlighted" in its name.
+    /// The final snapshot is taken after clearing the highlight.
+    Highlight {
+        /// Which edges to highlight: "parallel:x", "parallel:y", "parallel:z", "longer:<length>",
+        /// or "top" for the loop around the top face.
+        #[arg(long, default_value = "parallel:z", value_parser = selection::parse_edge_predicate)]
+        edges: selection::EdgePredicate,
+    },
+    /// Draw two cubes, then remove one of them.
+    /// A snapshot with both cubes is saved next to the output, with "-both" in its name.
+    Remove,
+    /// Extrude an outline read from a CSV file, with one "x,y" point per line.
+    Profile {
+        /// The CSV file. Points can be separated by commas or spaces, and a header line is allowed.
+        #[arg(long, value_parser = profile::Profile::read)]
+        profile_csv: profile::Profile,
+        /// How far to extrude the outline upwards.
+        #[arg(long, value_parser = units::parse_length)]
+        extrude_height: f64,
+        /// Multiply every coordinate in the file by this.
+        #[arg(long, default_value = "1", value_parser = transform::parse_scale)]
+        scale: f64,
+        /// Extrude half the height below the outline and half above, so the solid is centered on z = 0.
+        #[arg(long)]
+        centered: bool,
+    },
+    /// Build a wall along an open path read from a CSV file, e.g. a floor plan's wall layout.
+    /// The path is the middle of the wall, which is stroked, not filled.
+    Wall {
+        /// The CSV file, written like a profile, but left open.
+        #[arg(long, value_parser = profile::Polyline::read)]
+        path_csv: profile::Polyline,
+        /// How thick the wall is.
+        #[arg(long, default_value_t = 2.0, value_parser = units::parse_positive_length)]
+        thickness: f64,
+        /// How high the wall is.
+        #[arg(long, default_value_t = 10.0, value_parser = units::parse_positive_length)]
+        height: f64,
+        /// Multiply every coordinate in the file by this.
+        #[arg(long, default_value = "1", value_parser = transform::parse_scale)]
+        scale: f64,
+        /// Bevel corners whose miter would be longer than this many times the thickness.
+        #[arg(long, default_value_t = stroke::DEFAULT_MITER_LIMIT)]
+        miter_limit: f64,
+    },
+    /// Extrude the outline of the first <path> element in an SVG file, e.g. to turn a logo into a plaque.
+    SvgExtrude {
+        /// The SVG file. The path must be one closed outline, drawn with lines and curves
+        /// (M, L, H, V, C, S, Q, T and Z). Arcs and holes aren't supported yet.
+        #[arg(value_parser = svg::Outline::read)]
+        svg: svg::Outline,
+        /// How far to extrude the outline upwards.
+        #[arg(long, default_value_t = 5.0, value_parser = units::parse_length)]
+        height: f64,
+        /// Multiply every coordinate in the file by this.
+        #[arg(long, default_value = "1", value_parser = transform::parse_scale)]
+        scale: f64,
+        /// Flatten curves into lines which stray no further than this from the curve.
+        /// By default, curves are sent to the API as they are, unless --tessellation says how to flatten them.
+        #[arg(long, value_parser = units::parse_positive_length)]
+        flatten: Option<f64>,
+    },
+    /// Import a model from local files (STL, OBJ, PLY, FBX, glTF or STEP) instead of drawing one.
+    Import {
+        /// A file to import. Repeat it for formats which span several files, e.g. an OBJ and its MTL.
+        #[arg(long = "file", required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Don't draw a model. Instead, convert a model file to another format,
+    /// by importing it and exporting it again.
+    Convert {
+        /// The file to convert.
+        input: PathBuf,
+        /// The format to convert to: stl, obj, ply or step.
+        /// Defaults to the extension of --out.
+        #[arg(long)]
+        to: Option<String>,
+        /// Where to save the converted file. Defaults to the input's name, with --to's extension.
+        /// Use "-" to write it to standard output, along with --to.
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+    },
+    /// Build every model listed in a scene file, over one connection.
+    Scene {
+        /// The scene file, in JSON.
+        file: PathBuf,
+        /// Jitter the scene's parameters (see its "jitter") from this seed, so the same seed always
+        /// builds the same variants. Without it, a seed is picked, and printed.
+        #[arg(long)]
+        seed: Option<u64>,
+        /// Build this many variants of a jittered scene, one after another over the same connection,
+        /// each saved to outputs of its own, named after its number, e.g. "scene-variant-2.png".
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+        count: u32,
+    },
+    /// Draw a model many times over one connection, with one of its options set to each value in a range,
+    /// or several options set to every combination of their values.
+    /// Each drawing is saved next to the output, with the options and their values in its name.
+    Sweep {
+        /// The option to vary, and its values, as NAME=START..END:STEP, e.g. hole-diameter=5..20:5.
+        /// Repeat it to vary several options.
+        #[arg(long = "param", required = true)]
+        params: Vec<sweep::Param>,
+        /// The model to draw, and any of its other options, e.g. plate --hole-x 5.
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true, required = true)]
+        model: Vec<String>,
+        /// How many connections to draw over at the same time. They share one thread, so this only
+        /// overlaps waiting for the API: it helps when the API is slow to respond, not with local work
+        /// like saving snapshots.
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+        jobs: u32,
+        /// Measure each drawing, and save the measurements next to the output as "<output>-sweep.csv".
+        /// Drawings which fail are left blank. Sweeping two options also saves a heatmap, "<output>-sweep.png".
+        #[arg(long, value_enum)]
+        sweep_metric: Option<sweep::Metric>,
+    },
+    /// Don't draw a model. Instead, compare two images, e.g. snapshots of the same model from
+    /// different days, and fail if they differ by more than the threshold.
+    #[command(name = "imgdiff")]
+    ImgDiff {
+        a: PathBuf,
+        b: PathBuf,
+        /// Save a picture of where the images differ: the first image, faded, and redder where they differ.
+        #[arg(long)]
+        out: Option<PathBuf>,
+        /// The biggest score which still counts as the same.
+        #[arg(long, default_value_t = 0.01)]
+        threshold: f64,
+        /// How to score the difference.
+        #[arg(long, value_enum, default_value_t = imgdiff::Metric::Mae)]
+        metric: imgdiff::Metric,
+        /// If the images are different sizes, compare the part they share, from the top left,
+        /// instead of failing.
+        #[arg(long)]
+        crop: bool,
+    },
+    /// Don't draw a model. Instead, compare two manifests saved with --manifest, e.g. before and after
+    /// changing an option, and print which objects were added, removed or built differently,
+    /// and which outputs changed. Objects are matched by how they were made, not by their IDs.
+    #[command(name = "scene-diff")]
+    SceneDiff {
+        old: PathBuf,
+        new: PathBuf,
+        /// Print the differences as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Don't draw a model. Instead, send one modeling command written as JSON, and print the API's
+    /// response, e.g. raw '{"type": "default_camera_zoom", "magnitude": 2}'. For commands which
+    /// no subcommand sends yet.
+    Raw {
+        /// The command, as JSON, or @FILE to read it from a file. It must be a command the kittycad
+        /// crate knows, with its "type" and fields named the way the API names them.
+        cmd: String,
+    },
+    /// Build a scene from standard input, one line at a time, until it ends, then save the output.
+    /// Each line is a model written the same way as on the command line, e.g. "plate --hole-diameter 5",
+    /// or a modeling command written as JSON, like raw takes. Each adds to the scene, and once the
+    /// input goes quiet, a small preview of it is saved, for a viewer to watch.
+    Pipe {
+        /// Where to save the preview. It's replaced in one go, so a viewer never sees half an image.
+        #[arg(long, default_value = "preview.png")]
+        preview: PathBuf,
+        /// Only save a preview once this many lines have changed the scene since the last one.
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+        preview_every: u32,
+        /// Don't save previews, only the output at the end.
+        #[arg(long)]
+        no_preview: bool,
+    },
+    /// Don't draw a model. Instead, time how quickly the API responds to trivial commands.
+    Bench {
+        /// How many commands to send in each mode.
+        #[arg(long, default_value_t = 64, value_parser = clap::value_parser!(u32).range(1..))]
+        commands: u32,
+        /// How many commands the grouped mode sends between flushes.
+        #[arg(long, default_value_t = 64, value_parser = clap::value_parser!(u32).range(1..))]
+        group_size: u32,
+        /// Which modes to time. Defaults to all of them.
+        #[arg(long, value_enum)]
+        mode: Vec<bench::Mode>,
+        /// How to print the results.
+        #[arg(long, value_enum, default_value_t = bench::Format::Text)]
+        format: bench::Format,
+    },
+    /// Don't draw a model. Instead, build each shape whose volume and surface area have a formula,
+    /// measure them, and print a table comparing the two.
+    /// Fails if any measurement deviates from its formula by more than --tolerance.
+    /// Shapes the API won't measure are reported as skipped.
+    VerifyPrimitives {
+        /// Which shapes to build: box or cylinder (repeat it for both, which is the default).
+        /// Spheres, tori and cones need a revolve, which this version of the kittycad crate doesn't
+        /// have, so they're refused.
+        #[arg(long = "shape", value_name = "SHAPE", value_parser = primitives::parse, default_values = ["box", "cylinder"])]
+        shapes: Vec<primitives::Primitive>,
+    },
+    /// Don't draw a model. Instead, find out which of the features this crate uses the API supports,
+    /// e.g. arcs, holes, exports and camera controls, by sending a few cheap commands for each.
+    /// Anything built is removed again. Prints which were accepted, and why any were rejected.
+    Probe {
+        /// Print the results as JSON.
+        #[arg(long)]
+        json: bool,
+        /// Also save the results to this file, for --capabilities to check later runs against.
+        #[arg(long)]
+        save: Option<PathBuf>,
+    },
+    /// Draw every model which needs no input files, with its default options, each in an empty scene,
+    /// and save a snapshot of each to <dir>/<name>.png, with an index.json listing them.
+    /// Fails once every model has been tried, if any of them failed.
+    Gallery {
+        /// Where to save the snapshots and the index.
+        #[arg(long, default_value = "gallery")]
+        dir: PathBuf,
+        /// Also write an index.html showing every snapshot.
+        #[arg(long)]
+        html: bool,
+    },
+}
+
+impl Model {
+    /// Which way the camera should look at this model, if it looks best from somewhere
+    /// other than the usual view. --view overrides it.
+    fn preferred_camera(&self) -> Option<camera::CameraPreset> {
+        let point = |x, y, z| Point3D { x, y, z };
+        let up = point(0.0, 0.0, 1.0);
+        let (eye, up) = match self {
+            // From inside the L, where the center of mass is marked.
+            Model::LBracket => (point(1.0, 1.0, 1.0), up),
+            // From higher up than usual, so the hole shows as a hole, not a slot.
+            Model::Plate { .. } => (point(-1.0, -1.0, 2.5), up),
+            // Nearly face-on, so the holes are evenly spaced around the circle.
+            Model::BoltCircle { .. } => (point(0.0, -1.0, 3.0), up),
+            // From the front, a little above, so the pins don't hide each other.
+            Model::LinearPattern { .. } => (point(-0.3, -1.0, 0.6), up),
+            // Straight down, so the outline reads the way it was drawn.
+            Model::Profile { .. } | Model::SvgExtrude { .. } | Model::Wall { .. } => {
+                return Some(camera::View::Top.preset());
+            }
+            _ => return None,
+        };
+        Some(camera::CameraPreset { eye, up })
+    }
+}
+
+/// Options which apply to every model.
+struct Options {
+    validation: Option<Validation>,
+    density: Option<Density>,
+    inspect: bool,
+    recenter: Option<recenter::Recenter>,
+    recenter_snapshots: bool,
+    /// How many exports may be waiting for the API at once.
+    export_concurrency: usize,
+    max_in_flight: usize,
+    command_deadline: std::time::Duration,
+    /// None if there's no overall timeout.
+    response_timeout: Option<std::time::Duration>,
+    idle_timeout: std::time::Duration,
+    strict: bool,
+    strict_ordering: bool,
+    allow_degenerate: bool,
+    /// Whether extrusions are capped, making solids.
+    cap_extrusions: bool,
+    /// How finely to draw curves as lines, if the command line said.
+    tessellation: Option<tessellation::Quality>,
+    /// How many decimal places to round coordinates to, if at all.
+    coord_precision: Option<u32>,
+    max_message_bytes: usize,
+    connection_profile: session::ConnectionProfile,
+    /// Where to write down the session's messages, if anywhere.
+    record: Option<PathBuf>,
+    /// The recording to play back instead of connecting, if any.
+    offline: Option<PathBuf>,
+    sidecars: bool,
+    debug_dir: debug::DebugDir,
+    /// Where models are built, unless a scene's part says otherwise.
+    placement: Point3D,
+    /// Which of a scene's groups of parts to show.
+    groups: scene::Groups,
+    /// Which way to look at every model, if --view asked for one, rather than at each the way that suits it.
+    view: Option<camera::View>,
+    /// How many frames to save, turning the camera around each model, instead of one snapshot.
+    turntable: Option<u32>,
+    /// Whether to skip the turntable frames an earlier run saved.
+    resume_frames: bool,
+    /// How the turntable frames the model.
+    turntable_framing: turntable::Framing,
+    id_seed: Option<u64>,
+    retry: session::RetryPolicy,
+    outputs: Arc<template::OutputRegistry>,
+    /// What the run's sessions notice about the API, for its report.
+    stats: session::RunStats,
+}
+
+/// Run the command line, which `src/main.rs` does.
+pub async fn main() -> Result<()> {
+    // Install color_eyre's error reporter, which prints notes like the support IDs below.
+    color_eyre::install()?;
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    // What the model is, for naming its outputs.
+    let drawing = match matches.subcommand() {
+        Some((shape, options)) => template::Drawing::new(shape, options, 1),
+        None if cli.resume.is_some() => template::Drawing::bare("resume"),
+        None => scene::describe(&["cube".to_owned()], 1)?,
+    };
+    let converting_to_stdout = match &cli.model {
+        Some(Model::Convert { out: Some(out), .. }) => out.as_os_str() == snapshot::STDOUT,
+        _ => false,
+    };
+    let to_stdout = cli.output == snapshot::STDOUT || converting_to_stdout;
+    if to_stdout {
+        if std::io::stdout().is_terminal() && !cli.force_tty {
+            bail!(
+                "Not writing binary output to the terminal. Pipe it somewhere, or pass --force-tty"
+            );
+        }
+        LOG_TO_STDERR.store(true, Ordering::Relaxed);
+    }
+    if let Some(total) = cli.time_budget {
+        budget::start(total);
+    }
+    if let Some(codes) = cli.deny_warnings.clone() {
+        warnings::deny(codes);
+    }
+    #[cfg(feature = "test-hooks")]
+    inject::load()?;
+    sink::set_write_retry(sink::WriteRetry {
+        retries: cli.write_retries,
+        delay: cli.write_retry_delay,
+    });
+    coords::set_input(coords::CoordinateSystem::new(
+        cli.input_up,
+        cli.input_handedness,
+    ));
+    let cache = cache::Cache::for_run(&cli, &matches)?;
+    // KittyCAD support can look runs up by this ID, and by the first request ID.
+    let run_id = uuid::Uuid::new_v4();
+    say!("Run ID: {run_id}");
+    let notify_url = cli.notify_url.clone();
+    let timeout = Duration::from_secs(cli.notify_timeout);
+    let progress = cli
+        .progress
+        .then(|| tokio::spawn(progress::show(events::listen(64))));
+    let mut report = notify::RunReport {
+        args: env::args().skip(1).collect(),
+        succeeded: false,
+        error: None,
+        output: cli.output.clone(),
+        export: cli.export.as_ref().map(|path| path.display().to_string()),
+        connection_profile: cli.connection_profile,
+        started_at: sidecar::now(),
+        finished_at: 0,
+        run_id,
+        request_id: None,
+        server: None,
+        unrecognized_responses: 0,
+        session_retries: 0,
+        metrics_requests: 0,
+        coord_bytes_saved: 0,
+        response_ordering: Default::default(),
+        timings: Default::default(),
+        artifacts: Vec::new(),
+        exports: Vec::new(),
+        warnings: Vec::new(),
+        cache: None,
+    };
+    let stats = session::RunStats::default();
+    let result = run(cli, drawing, run_id, cache, stats.clone())
+        .await
+        .and_then(|()| warnings::check())
+        .with_note(|| support_ids(run_id, &stats.get()));
+    report.succeeded = result.is_ok();
+    report.error = result
+        .as_ref()
+        .err()
+        .map(|e| format!("{e}: {}", e.root_cause()));
+    report.finished_at = sidecar::now();
+    let stats = stats.get();
+    report.request_id = stats.first_request_id;
+    report.server = server::info();
+    report.unrecognized_responses = stats.unrecognized_responses;
+    report.session_retries = stats.session_retries;
+    report.metrics_requests = stats.metrics_requests;
+    report.coord_bytes_saved = precision::bytes_saved().0;
+    report.response_ordering = stats.response_ordering;
+    report.timings = timings::run_timings();
+    report.artifacts = sink::written();
+    report.exports = export::transfers();
+    report.warnings = warnings::warnings();
+    report.cache = cache::status();
+    let report = Arc::new(report);
+    events::emit(events::Event::Finished(report.clone()));
+    if let Some(progress) = progress {
+        // The progress line stops at the Finished event, so this doesn't wait long.
+        let _ = progress.await;
+    }
+    summarize(&stats);
+    if let Some(url) = notify_url {
+        notify::notify(&url, &report, timeout).await;
+    }
+    exit_if_refused(result)
+}
+
+/// If the API refused the run because of the account (e.g. it's rate limited, or out of credits),
+/// print the error and exit with the refusal's own exit code, so scripts can tell it apart.
+/// Any other result is returned, to exit as usual.
+fn exit_if_refused(result: Result<()>) -> Result<()> {
+    if let Err(e) = &result {
+        if let Some(refused) = client::Refused::find(e) {
+            eprintln!("Error: {e:?}");
+            std::process::exit(refused.exit_code());
+        }
+    }
+    result
+}
+
+/// Say where the run's time went, and how many messages from the API were skipped, if any were,
+/// because they couldn't be parsed.
+fn summarize(stats: &session::Stats) {
+    let timings = timings::run_timings();
+    if timings.total() > Duration::ZERO {
+        say!("Time spent: {timings}");
+    }
+    let skipped = stats.unrecognized_responses;
+    if skipped > 0 {
+        say!("Skipped {skipped} messages from the API which couldn't be parsed (see the warnings above)");
+    }
+    let retries = stats.session_retries;
+    if retries > 0 {
+        say!(
+            "Connected again {retries} times, because the API closed the session before responding"
+        );
+    }
+    let (saved, sent) = precision::bytes_saved();
+    if saved > 0 {
+        say!(
+            "Rounding coordinates saved {saved} bytes, {:.1}% of the commands' size",
+            100.0 * saved as f64 / (saved + sent) as f64
+        );
+    }
+    let metrics_requests = stats.metrics_requests;
+    if metrics_requests > 0 {
+        say!("Answered {metrics_requests} requests from the API for the client's metrics");
+    }
+    let ordering = stats.response_ordering;
+    if ordering.out_of_order > 0 {
+        say!(
+            "{} responses arrived out of order, overtaking at most {} earlier commands (see the warnings above)",
+            ordering.out_of_order,
+            ordering.max_distance
+        );
+    }
+    let warnings = warnings::warnings();
+    if !warnings.is_empty() {
+        say!("Warnings:");
+        for warning in &warnings {
+            let first_line = warning.message.lines().next().unwrap_or_default();
+            match warning.count {
+                1 => say!("  [{}] {first_line}", warning.code),
+                n => say!("  [{}] {first_line} ({n} times)", warning.code),
+            }
+        }
+    }
+}
+
+/// Connect to the modeling API, with the token in $KITTYCAD_API_TOKEN and the usual settings.
+/// This is how the examples connect. The command line connects in `run`, with its own options.
+///
+/// Like --offline, if $KITTYCAD_OFFLINE names a recording, it's played back instead, and no token
+/// is needed. Like --record, if $KITTYCAD_RECORD names a file, the session is recorded in it.
+pub async fn connect() -> Result<ModelingSession> {
+    let mut session = match env::var_os("KITTYCAD_OFFLINE") {
+        Some(recording) => ModelingSession::replay(
+            Path::new(&recording),
+            session::DEFAULT_MAX_MESSAGE_BYTES,
+            session::ConnectionProfile::default(),
+        )?,
+        None => {
+            let token =
+                env::var("KITTYCAD_API_TOKEN").context("You must set $KITTYCAD_API_TOKEN")?;
+            let run_id = uuid::Uuid::new_v4();
+            let client = client::client(token, run_id, &client::Network::default())?;
+            ModelingSession::connect(
+                &client,
+                session::DEFAULT_MAX_MESSAGE_BYTES,
+                session::ConnectionProfile::default(),
+            )
+            .await?
+        }
+    };
+    if let Some(recording) = env::var_os("KITTYCAD_RECORD") {
+        session.record(Path::new(&recording))?;
+    }
+    Ok(session)
+}
+
+/// Connect to the modeling API (or play back a recording of it), and set the session up
+/// with the run's options.
+///
+/// If the API refuses the token, a new one is fetched and the connection tried once more,
+/// in case the token expired sooner than its provider said it would.
+async fn open_session(clients: &client::Clients, options: &Options) -> Result<ModelingSession> {
+    let mut session = match &options.offline {
+        Some(recording) => ModelingSession::replay(
+            recording,
+            options.max_message_bytes,
+            options.connection_profile,
+        )?,
+        None => {
+            let connect = |client: kittycad::Client| async move {
+                ModelingSession::connect(
+                    &client,
+                    options.max_message_bytes,
+                    options.connection_profile,
+                )
+                .await
+            };
+            match connect(clients.client(false).await?).await {
+                Err(e)
+                    if client::Refused::find(&e)
+                        .is_some_and(|refused| refused.reason == client::Refusal::Unauthorized) =>
+                {
+                    connect(clients.client(true).await?)
+                        .await
+                        .wrap_err("The API refused the token again, after a new one was fetched")?
+                }
+                connected => connected?,
+            }
+        }
+    };
+    if let Some(path) = &options.record {
+        session.record(path)?;
+    }
+    session.set_max_in_flight(options.max_in_flight);
+    session.set_command_deadline(options.command_deadline);
+    session.set_response_timeout(options.response_timeout);
+    session.set_idle_timeout(options.idle_timeout);
+    session.set_strict(options.strict);
+    session.set_strict_ordering(options.strict_ordering);
+    session.set_allow_degenerate(options.allow_degenerate);
+    session.set_cap_extrusions(options.cap_extrusions);
+    session.set_coord_precision(options.coord_precision);
+    session.set_sidecars(options.sidecars);
+    session.set_outputs(options.outputs.clone());
+    session.share_stats(options.stats.clone());
+    session.set_debug_dir(options.debug_dir.clone());
+    session.set_placement(options.placement.clone());
+    session.set_retry_policy(options.retry.clone());
+    if let Some(seed) = options.id_seed {
+        session.set_id_seed(seed);
+    }
+    Ok(session)
+}
+
+/// The IDs to give KittyCAD support if the API misbehaved.
+fn support_ids(run_id: uuid::Uuid, stats: &session::Stats) -> String {
+    match stats.first_request_id {
+        Some(request_id) => format!("Run ID: {run_id}, first request ID: {request_id}"),
+        None => format!("Run ID: {run_id} (the API never responded to a request)"),
+    }
+}
+
+/// Draw the model the command line asked for, and save whatever it asked for.
+async fn run(
+    cli: Cli,
+    drawing: template::Drawing,
+    run_id: uuid::Uuid,
+    cache: Option<cache::Cache>,
+    stats: session::RunStats,
+) -> Result<()> {
+    // Comparing images doesn't need the API.
+    if let Some(Model::ImgDiff {
+        a,
+        b,
+        out,
+        threshold,
+        metric,
+        crop,
+    }) = &cli.model
+    {
+        return imgdiff::run(a, b, out.as_deref(), *threshold, *metric, *crop);
+    }
+    // Neither does comparing manifests.
+    if let Some(Model::SceneDiff { old, new, json }) = &cli.model {
+        return scenediff::run(old, new, *json);
+    }
+    if let Some(cache) = &cache {
+        if cache.restore().await? {
+            return Ok(());
+        }
+    }
+    // Set up the API client.
+    // Check the template before connecting, so a typo fails fast.
+    let template = cli
+        .output_template
+        .as_deref()
+        .map(str::parse::<template::Template>)
+        .transpose()?;
+    if template.is_some() && cli.output == snapshot::STDOUT {
+        bail!("--output-template names files, so it can't be used with --output -");
+    }
+    if cli.watch && !matches!(cli.model, Some(Model::Scene { .. })) {
+        bail!(
+            "--watch renders a scene file again when it changes, so it needs the scene subcommand"
+        );
+    }
+    if cli.watch && cli.output == snapshot::STDOUT {
+        bail!("--watch saves the outputs again and again, so it can't be used with --output -");
+    }
+    // Everything the command line does is made of commands and snapshots. Turntables, too,
+    // are snapshots taken one after another, not recordings of the stream.
+    cli.connection_profile.check(false)?;
+    if cli.turntable.is_some() && cli.output == snapshot::STDOUT {
+        bail!("--turntable saves many frames, so it can't be used with --output -");
+    }
+    if cli.resume_frames && !matches!(cli.output_sink, sink::SinkKind::File) {
+        bail!("--resume-frames checks the frames already saved are on disk, so it needs --output-sink file");
+    }
+    let validation = cli.validate.then_some(Validation {
+        tolerance: cli.tolerance,
+    });
+    // Look up the material before connecting, so a typo fails fast.
+    let density = match (cli.material, cli.density) {
+        (Some(material), _) => Some(materials::preset(&material)?),
+        (None, density) => density,
+    };
+
+    if cli
+        .export
+        .as_ref()
+        .is_some_and(|path| path.as_os_str() == snapshot::STDOUT)
+    {
+        bail!("--export can't tell which format to write to standard output. Use convert --to FORMAT -o - instead");
+    }
+    // Check the formats before connecting, so a typo fails fast.
+    let mut export_formats: Vec<_> = cli
+        .export
+        .iter()
+        .map(|path| export::extension(path))
+        .collect();
+    for extension in &cli.export_as {
+        if export_formats.contains(extension) {
+            bail!("--export-as {extension}: the model is already exported as {extension}");
+        }
+        if let Some(path) = &cli.export {
+            export::format_for(&path.with_extension(extension), extension)?;
+        }
+        export_formats.push(extension.clone());
+    }
+    let output = Output {
+        path: cli.output,
+        background: cli.background,
+        export: cli.export,
+        export_as: cli.export_as,
+        export_only: (!cli.export_only.is_empty()).then_some(cli.export_only),
+        compress: cli.compress,
+        verify_export: cli.verify_export,
+        crop: cli
+            .crop
+            .map(snapshot::Crop::Pixels)
+            .or(cli.crop_to_entity.map(snapshot::Crop::Object)),
+        crop_padding: cli.crop_padding,
+        blank: snapshot::BlankCheck {
+            tolerance: cli.blank_tolerance,
+            threshold: cli.blank_threshold,
+            retries: cli.blank_retries,
+        },
+        label: cli.label,
+        label_auto: cli.label_auto,
+        reencode: !cli.no_reencode,
+        size: None,
+        snapshots: cli.snapshot,
+        dimensions: Vec::new(),
+    };
+
+    let options = Options {
+        validation,
+        density,
+        inspect: cli.inspect,
+        recenter: cli.recenter,
+        recenter_snapshots: cli.recenter_snapshots,
+        export_concurrency: cli.export_concurrency as usize,
+        max_in_flight: cli.max_in_flight as usize,
+        command_deadline: std::time::Duration::from_secs(cli.command_deadline),
+        response_timeout: (!cli.no_overall_timeout).then_some(session::DEFAULT_RESPONSE_TIMEOUT),
+        idle_timeout: std::time::Duration::from_secs(cli.idle_timeout),
+        strict: cli.strict,
+        strict_ordering: cli.strict_ordering,
+        allow_degenerate: cli.allow_degenerate,
+        cap_extrusions: !cli.no_cap,
+        tessellation: cli.tessellation,
+        coord_precision: cli.coord_precision,
+        max_message_bytes: cli.max_ws_message_bytes as usize,
+        connection_profile: cli.connection_profile,
+        record: cli.record.clone(),
+        offline: cli.offline.clone(),
+        sidecars: !cli.no_sidecar,
+        debug_dir: debug::DebugDir {
+            dir: cli.debug_dir,
+            max_bytes: cli.debug_dir_max_bytes,
+        },
+        placement: cli.at.world(),
+        groups: scene::Groups {
+            only: cli.only_group,
+            hide: cli.hide_group,
+        },
+        view: cli.view,
+        turntable: cli.turntable,
+        resume_frames: cli.resume_frames,
+        turntable_framing: turntable::Framing {
+            per_frame_fit: cli.per_frame_fit,
+            margin: cli.turntable_margin,
+        },
+        id_seed: cli.deterministic_ids,
+        retry: session::RetryPolicy {
+            retries: cli.retries,
+            error_codes: cli.retry_on,
+            scene_changes: cli.retry_scene_changes,
+        },
+        outputs: Arc::new(template::OutputRegistry::new(
+            template,
+            sink::open(cli.output_sink)?,
+        )),
+        stats,
+    };
+    if cli.resume.is_some() && cli.model.is_some() {
+        bail!(
+            "--resume carries on with a saved model, so it can't be used with a model subcommand"
+        );
+    }
+    // Parse both variants before connecting, so an option which can't be compared fails fast.
+    let variants = match &cli.compare {
+        Some(compare) if cli.model.is_none() => {
+            bail!(
+                "--compare needs a model subcommand, e.g. --compare {}=... plate",
+                compare.name
+            )
+        }
+        Some(compare) => Some(compare::models(compare, &drawing.shape)?),
+        None => None,
+    };
+    let model = cli.model.unwrap_or(Model::Cube { count: 1 });
+    // Read any files before connecting, so a missing file fails fast.
+    let resume = cli.resume.as_deref().map(state::State::load).transpose()?;
+    // Every variant of the scene, if it's a scene.
+    let scenes = match &model {
+        Model::Scene { file, seed, count } => {
+            let picked = seed.unwrap_or_else(jitter::random_seed);
+            let scenes = (1..=*count)
+                .map(|variant| {
+                    let seed = jitter::Seed {
+                        seed: picked,
+                        variant,
+                    };
+                    scene::Scene::read(file, seed)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let scene = &scenes[0];
+            options.groups.check(scene)?;
+            tessellation::set_quality(options.tessellation.or(scene.tessellation));
+            match scene.jittered {
+                Some(_) if seed.is_none() => {
+                    say!("Jittered the scene with seed {picked}. Build it again with --seed {picked}")
+                }
+                Some(_) => {}
+                None if *count > 1 => bail!(
+                    "--count builds variants of a jittered scene, but {} jitters nothing",
+                    file.display()
+                ),
+                None => {}
+            }
+            if *count > 1 && cli.watch {
+                bail!(
+                    "--watch renders one scene again and again, so it can't be used with --count"
+                );
+            }
+            if *count > 1 && !output.snapshots.is_empty() {
+                bail!("--snapshot can't be used with --count, because every variant would be saved to the same files");
+            }
+            scenes
+        }
+        _ if !options.groups.only.is_empty() || !options.groups.hide.is_empty() => {
+            bail!("--only-group and --hide-group pick which of a scene's parts to show, so they need the scene subcommand")
+        }
+        _ => {
+            tessellation::set_quality(options.tessellation);
+            Vec::new()
+        }
+    };
+
+    let convert_to = match &model {
+        Model::Convert { input, to, out } => Some(import::conversion_output(input, to, out)?),
+        _ => None,
+    };
+    // Check a raw command before connecting, so a typo fails fast.
+    let raw_cmd = match &model {
+        Model::Raw { cmd } => {
+            let text = match cmd.strip_prefix('@') {
+                Some(path) => std::fs::read_to_string(path)
+                    .wrap_err_with(|| format!("Could not read the raw command from {path}"))?,
+                None => cmd.clone(),
+            };
+            Some(raw::parse(&text, &Default::default())?)
+        }
+        _ => None,
+    };
+
+    // Check everything can be saved before connecting, so a long run doesn't fail at the end.
+    let mut planned = Vec::new();
+    let snapshots = !matches!(
+        model,
+        Model::Bench { .. }
+            | Model::Convert { .. }
+            | Model::Gallery { .. }
+            | Model::Probe { .. }
+            | Model::Raw { .. }
+            | Model::VerifyPrimitives { .. }
+    );
+    if snapshots && options.outputs.writes_local_files() {
+        // Named just as they will be, later.
+        let output = options.outputs.plan(&output, &drawing)?;
+        planned.push(preflight::Planned::file("the snapshot", &output.path));
+        for (export, _) in output.exports() {
+            planned.push(preflight::Planned::file("the export", &export));
+        }
+        for sized in &output.snapshots {
+            planned.push(preflight::Planned::file("a --snapshot", &sized.path));
+        }
+    }
+    if let Some((out, _)) = &convert_to {
+        planned.push(preflight::Planned::file("the converted model", out));
+    }
+    if let Model::Gallery { dir, .. } = &model {
+        planned.push(preflight::Planned::dir("the gallery", dir));
+    }
+    if let Model::Probe {
+        save: Some(save), ..
+    } = &model
+    {
+        planned.push(preflight::Planned::file("the capabilities", save));
+    }
+    for (what, path) in [
+        ("the manifest", &cli.manifest),
+        ("the KCL", &cli.emit_kcl),
+        ("the saved state", &cli.save_state),
+        ("the recording", &cli.record),
+    ] {
+        if let Some(path) = path {
+            planned.push(preflight::Planned::file(what, path));
+        }
+    }
+    if let Some(cache) = &cache {
+        planned.push(preflight::Planned::dir("the cache", cache.dir()));
+    }
+    planned.push(preflight::Planned::dir(
+        "the debug directory",
+        &options.debug_dir.dir,
+    ));
+    preflight::check(&planned)?;
+
+    // Warn about anything the API didn't support before connecting, so it's seen before a long run.
+    if let Some(path) = &cli.capabilities {
+        let matrix = probe::Matrix::load(path)?;
+        let mut needs = match scenes.as_slice() {
+            [] => probe::needed_by(&model)
+                .into_iter()
+                .map(|capability| ("the model".to_owned(), capability))
+                .collect(),
+            scenes => {
+                // Variants mostly need the same things, which only need warning about once.
+                let mut seen = std::collections::BTreeSet::new();
+                let mut needs: Vec<_> = scenes.iter().flat_map(scene::Scene::needs).collect();
+                needs.retain(|need| seen.insert(need.clone()));
+                needs
+            }
+        };
+        if snapshots {
+            needs.push(("every snapshot".to_owned(), probe::Capability::CameraLookAt));
+        }
+        for (path, extension) in output.exports() {
+            let format = export::format_for(&path, &extension)?;
+            needs.extend(
+                probe::needed_to_export(&format)
+                    .map(|capability| ("--export".to_owned(), capability)),
+            );
+        }
+        matrix.check(path, &needs)?;
+    }
+
+    // A recording is played back without talking to the API, so it doesn't need a token.
+    let tokens: Arc<dyn token::TokenProvider> = match (&cli.offline, &cli.token_command) {
+        (Some(_), _) => Arc::new(token::StaticToken::new(String::new())),
+        (None, Some(command)) => Arc::new(token::CommandToken::new(
+            command.clone(),
+            cli.token_lifetime,
+        )),
+        (None, None) => Arc::new(token::StaticToken::from_env()?),
+    };
+    let network = client::Network {
+        proxy: cli.proxy.clone(),
+        ca_cert: cli.ca_cert.clone(),
+        insecure_skip_verify: cli.insecure_skip_verify,
+    };
+    let clients = client::Clients::new(tokens, run_id, network);
+    if cli.offline.is_none() && (cli.server_info || cli.notify_url.is_some()) {
+        let info = server::fetch(&clients.client(false).await?, cli.connection_profile).await;
+        if cli.server_info {
+            say!("{info}");
+        }
+    }
+
+    if let Model::Sweep {
+        params,
+        model,
+        jobs,
+        sweep_metric,
+    } = &model
+    {
+        if cli.manifest.is_some() || cli.save_state.is_some() || cli.emit_kcl.is_some() {
+            bail!("--manifest, --save-state and --emit-kcl can't be used with a sweep");
+        }
+        if cli.record.is_some() || cli.offline.is_some() {
+            bail!("--record and --offline can't be used with a sweep, because its drawings are built in sessions of their own");
+        }
+        if !output.snapshots.is_empty() {
+            bail!("--snapshot can't be used with a sweep, because every drawing would be saved to the same files");
+        }
+        let models = sweep::models(params, model)?;
+        let jobs = *jobs as usize;
+        return sweep::run(
+            &clients,
+            params,
+            models,
+            jobs,
+            *sweep_metric,
+            &output,
+            &options,
+        )
+        .await;
+    }
+
+    // The API sometimes closes a new session before responding to anything, e.g. for a moment while
+    // it's being deployed. Nothing was built, so connect again after a while and start over.
+    let mut session_retries = 0;
+    loop {
+        // Each attempt needs its own copy, because drawing consumes them.
+        let (model, scenes, resume, raw_cmd, convert_to, variants, output) = (
+            model.clone(),
+            scenes.clone(),
+            resume.clone(),
+            raw_cmd.clone(),
+            convert_to.clone(),
+            variants.clone(),
+            output.clone(),
+        );
+        // Establish a WebSocket connection to KittyCAD's modeling API.
+        let mut session = open_session(&clients, &options).await?;
+
+        session.set_camera_preset(camera::choose(options.view, &model));
+
+        // If anything fails, don't leave what was built so far in the scene.
+        let clean_on_exit = cli.clean_on_exit;
+        let drawn = async {
+            let session = &mut session;
+            if let Model::Bench {
+                commands,
+                group_size,
+                mode,
+                format,
+            } = model
+            {
+                let modes = if mode.is_empty() {
+                    vec![
+                        bench::Mode::Sequential,
+                        bench::Mode::Pipelined,
+                        bench::Mode::Grouped,
+                        bench::Mode::Batched,
+                    ]
+                } else {
+                    mode
+                };
+                session.set_group_size(group_size as usize);
+                let mut summaries = Vec::new();
+                for mode in modes {
+                    summaries.push(bench::run(session, commands as usize, mode).await?);
+                }
+                return bench::print(&summaries, format);
+            }
+            if let Model::Gallery { dir, html } = &model {
+                if !output.snapshots.is_empty() {
+                    bail!("--snapshot can't be used with a gallery, because every shape would be saved to the same files");
+                }
+                return gallery::run(session, dir, *html, &output, &options).await;
+            }
+            if let Model::Probe { json, save } = &model {
+                let matrix = probe::run(session).await?;
+                if let Some(save) = save {
+                    matrix.save(save)?;
+                }
+                return probe::print(&matrix, *json);
+            }
+            if let Model::VerifyPrimitives { shapes } = &model {
+                let comparisons = primitives::compare(session, shapes).await?;
+                return primitives::report(&comparisons, cli.tolerance);
+            }
+            if let Some(cmd) = raw_cmd {
+                raw::send(session, cmd).await?;
+                return Ok(());
+            }
+            if let Model::Pipe {
+                preview,
+                preview_every,
+                no_preview,
+            } = &model
+            {
+                let preview = (!no_preview).then(|| pipe::Preview {
+                    path: preview.clone(),
+                    every: *preview_every as usize,
+                });
+                let output = options
+                    .outputs
+                    .name(&output, &drawing)?
+                    .unwrap_or(output)
+                    .labelled(&drawing);
+                return pipe::run(session, preview, &output, &options).await;
+            }
+            if let (Model::Convert { input, .. }, Some((out, format))) = (&model, convert_to) {
+                return import::convert(session, input, &out, &format).await;
+            }
+            // Sweeps, scenes and galleries name each drawing's outputs themselves.
+            let output = match scenes.first() {
+                Some(scene) if scene.separate_outputs => output,
+                _ => options
+                    .outputs
+                    .name(&output, &drawing)?
+                    .unwrap_or(output)
+                    .labelled(&drawing),
+            };
+            // Say which variant is which, in the caption of a comparison.
+            let output = match &cli.compare {
+                Some(compare) if output.label_auto => Output {
+                    label: output.label.as_ref().map(|label| {
+                        format!(
+                            "{label}, comparing --{}={} (left) with {} (right)",
+                            compare.name, compare.values[0], compare.values[1]
+                        )
+                    }),
+                    ..output
+                },
+                _ => output,
+            };
+            if let Some(state) = resume {
+                state.resume(session).await?;
+                finish(session, &state.object_ids, &output, &options).await?;
+            } else if !scenes.is_empty() {
+                match &model {
+                    Model::Scene { file, .. } if cli.watch => {
+                        let scene = scenes.into_iter().next().expect("a scene has a variant");
+                        watch::scene(session, &clients, file, scene, &output, &options).await?;
+                    }
+                    _ => scene::render_variants(session, scenes, &output, &options).await?,
+                }
+            } else {
+                // First, send all commands to the API, to draw the model.
+                // Then, measure it, and download it as a PNG.
+                let object_ids = match (&cli.compare, variants) {
+                    (Some(compare), Some(variants)) => {
+                        compare::build_both(session, compare, variants, &output, validation).await
+                    }
+                    _ => build(session, model, &output, validation).await,
+                }
+                .map_err(|e| degenerate::explain(e, &drawing))?;
+                names::register_objects(session, &drawing.shape, &object_ids)?;
+                finish(session, &object_ids, &output, &options).await?;
+                if let Some(path) = &cli.save_state {
+                    state::State::new(session, &object_ids).save(path)?;
+                    say!("Saved the model's state to {}", path.display());
+                }
+            }
+            if let Some(path) = &cli.emit_kcl {
+                kcl::save(session, path)?;
+                say!("Saved the model as KCL to {}", path.display());
+            }
+            if let Some(path) = &cli.manifest {
+                manifest::Manifest::describe(session).await?.save(path)?;
+                say!("Saved the scene manifest to {}", path.display());
+            }
+            Ok(())
+        }
+        .await;
+        match &drawn {
+            Err(e) if ServerUnavailable::caused(e) && session_retries < cli.session_retries => {
+                session_retries += 1;
+                options.stats.count_session_retry();
+                // The next attempt names the same outputs again.
+                options.outputs.forget_claims();
+                let delay = session::session_retry_delay(session_retries);
+                say!(
+                    "{e}. Connecting again in {delay:?} (attempt {session_retries} of {})",
+                    cli.session_retries
+                );
+                tokio::time::sleep(delay).await;
+                continue;
+            }
+            Err(e) if ConnectionLost::caused(e) => {}
+            Err(_) => scene::clean_up(&mut session).await,
+            Ok(()) if clean_on_exit => scene::clean_up(&mut session).await,
+            Ok(()) => {}
+        }
+        // Only cache runs which went as expected, so a blank retry or a skipped step isn't kept.
+        if let (Ok(()), Some(cache)) = (&drawn, &cache) {
+            if warnings::warnings().is_empty() {
+                cache.save()?;
+            }
+        }
+        return drawn;
+    }
+}
+
+/// Send all the commands to draw a model, and return the IDs of the objects to measure and frame.
+async fn build(
+    session: &mut ModelingSession,
+    model: Model,
+    output: &Output,
+    validation: Option<Validation>,
+) -> Result<Vec<Uuid>> {
+    session.check_cancelled()?;
+    Ok(match model {
+        Model::Cube { count } => draw_cubes(session, count, validation).await?,
+        Model::LBracket => draw_l_bracket(session, validation).await?,
+        Model::PathInfo => check_path(session, validation).await?,
+        Model::Plate {
+            hole_diameter,
+            hole_x,
+            hole_y,
+        } => drill_plate(session, hole_diameter, hole_x, hole_y, validation).await?,
+        Model::Measure { gap } => measure_cubes(session, gap, validation).await?,
+        Model::Transform {
+            translate,
+            rotate,
+            scale,
+        } => {
+            let transform = Transform {
+                translate: translate.world(),
+                rotate_z_degrees: rotate,
+                scale,
+            };
+            transform_cube(session, &transform, output, validation).await?
+        }
+        Model::LinearPattern { count, spacing } => {
+            pattern_pins(session, count, &spacing.world(), validation).await?
+        }
+        Model::BoltCircle { count } => drill_bolt_circle(session, count, validation).await?,
+        Model::Boss => add_boss(session, validation).await?,
+        Model::Cross { plane, normal } => {
+            draw_cross(
+                session,
+                plane,
+                normal.map(|n| n.world()).as_ref(),
+                validation,
+            )
+            .await?
+        }
+        Model::Highlight { edges } => highlight_edges(session, &edges, output, validation).await?,
+        Model::Remove => remove_cube(session, output, validation).await?,
+        Model::Profile {
+            profile_csv,
+            extrude_height,
+            scale,
+            centered,
+        } => {
+            extrude_profile(
+                session,
+                &profile_csv,
+                extrude_height,
+                scale,
+                centered,
+                validation,
+            )
+            .await?
+        }
+        Model::Wall {
+            path_csv,
+            thickness,
+            height,
+            scale,
+            miter_limit,
+        } => {
+            draw_wall(
+                session,
+                &path_csv,
+                thickness,
+                height,
+                scale,
+                miter_limit,
+                validation,
+            )
+            .await?
+        }
+        Model::SvgExtrude {
+            svg,
+            height,
+            scale,
+            flatten,
+        } => extrude_svg(session, &svg, height, scale, flatten, validation).await?,
+        Model::Import { files } => vec![import::import_files(session, &files).await?],
+        Model::Scene { .. }
+        | Model::Sweep { .. }
+        | Model::Bench { .. }
+        | Model::VerifyPrimitives { .. }
+        | Model::Probe { .. }
+        | Model::Gallery { .. }
+        | Model::Convert { .. }
+        | Model::ImgDiff { .. }
+        | Model::SceneDiff { .. }
+        | Model::Raw { .. }
+        | Model::Pipe { .. } => {
+            bail!("Scenes, sweeps, benchmarks, primitive checks, probes, galleries, conversions, diffs, raw commands and pipes can't be part of a scene or a sweep")
+        }
+    })
+}
+
+/// Measure the built model, frame it, and save a snapshot of it (or, with --turntable, many).
+async fn finish(
+    session: &mut ModelingSession,
+    object_ids: &[Uuid],
+    output: &Output,
+    options: &Options,
+) -> Result<()> {
+    if let Some(density) = &options.density {
+        properties::report_mass(session, object_ids, density, options.validation).await?;
+    }
+    if options.inspect {
+        for &object_id in object_ids {
+            let tree = EntityTree::query(session, object_id).await?.format();
+            say!("{}", tree.trim_end());
+        }
+    }
+
+    session.check_cancelled()?;
+    let export_only = output
+        .export_only
+        .as_ref()
+        .map(|handles| {
+            handles
+                .iter()
+                .map(|handle| session.names().resolve(handle))
+                .collect::<Result<Vec<_>>>()
+        })
+        .transpose()
+        .wrap_err("Can't tell which objects --export-only means")?;
+    // Move the model before it's framed, so the snapshots show it where it's exported.
+    let moved;
+    let (object_ids, export_only) = match options.recenter {
+        Some(how) if options.recenter_snapshots => {
+            moved = recenter::move_objects(session, object_ids, how).await?;
+            // The objects to export were rebuilt with new IDs.
+            let export_only = export_only.map(|ids| {
+                ids.iter()
+                    .map(
+                        |id| match object_ids.iter().position(|object_id| object_id == id) {
+                            Some(i) => moved[i],
+                            None => *id,
+                        },
+                    )
+                    .collect()
+            });
+            (&moved[..], export_only)
+        }
+        _ => (object_ids, export_only),
+    };
+    // Frame the whole model, so it fills the picture,
+    // unless the model already pointed the camera somewhere.
+    if session.framed().is_none() {
+        if let Some(bbox) = session.bounding_box(object_ids) {
+            camera::zoom_to_fit(session, &bbox).await?;
+        }
+    }
+    match options.turntable {
+        Some(frames) => {
+            turntable::capture(
+                session,
+                object_ids,
+                output,
+                frames,
+                options.resume_frames,
+                &options.turntable_framing,
+            )
+            .await?
+        }
+        None => snapshot::export_png(session, output).await?,
+    }
+    for sized in &output.snapshots {
+        let sized_output = Output {
+            path: sized.path.clone(),
+            size: Some((sized.width, sized.height)),
+            export: None,
+            export_only: None,
+            snapshots: Vec::new(),
+            ..output.clone()
+        };
+        snapshot::export_png(session, &sized_output).await?;
+        say!(
+            "Saved a {}x{} snapshot to {}",
+            sized.width,
+            sized.height,
+            sized.path
+        );
+    }
+    let export = match &output.export {
+        Some(path) if budget::allows(budget::Optional::Export, &path.display().to_string())? => {
+            Some(path)
+        }
+        _ => None,
+    };
+    if export.is_some() {
+        session.check_cancelled()?;
+        let exports = output.exports();
+        let export_ids = export_only.as_deref().unwrap_or(object_ids);
+        // What's exported may be only part of the model, so it's recentered on its own. It's copied to
+        // be moved, and the copies are removed once they're exported, so the scene is left as it was.
+        let copies = match options.recenter {
+            Some(how) => recenter::copy_objects(session, export_ids, how).await?,
+            None => None,
+        };
+        let meshes = export::export_many(
+            session,
+            copies.as_deref().unwrap_or(export_ids),
+            &exports,
+            output.compress,
+            output.verify_export,
+            options.export_concurrency,
+        )
+        .await;
+        if let Some(copies) = &copies {
+            transform::remove_objects(session, copies).await?;
+        }
+        for ((path, _), mesh) in exports.iter().zip(meshes?) {
+            if let (Some(how), Some(mesh)) = (options.recenter, mesh) {
+                let path = path.display().to_string();
+                recenter::check_export(&path, &mesh, how, output.verify_export)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Draw a row of cubes, and check their dimensions, combined volume and surface area.
+/// Returns the cubes' IDs.
+async fn draw_cubes(
+    session: &mut ModelingSession,
+    count: u32,
+    validation: Option<Validation>,
+) -> Result<Vec<Uuid>> {
+    let width = 10.0;
+    let mut cube_ids = Vec::new();
+    for i in 0..count {
+        // Leave a gap of one cube between neighbours, so their surfaces don't touch.
+        let center = Point3D {
+            x: f64::from(i) * width * 4.0,
+            y: 0.0,
+            z: 0.0,
+        };
+        cube_ids.push(shapes::draw_cube(session, &center, width).await?);
+    }
+    // Each square spans from -width to +width, and is extruded by 2 * width,
+    // so each side of a cube is 2 * width long.
+    let side = width * 2.0;
+    let n = f64::from(count);
+    let expected_bbox = BoundingBox {
+        min: Point3D {
+            x: -width,
+            y: -width,
+            z: -width,
+        },
+        max: Point3D {
+            x: (n - 1.0) * width * 4.0 + width,
+            y: width,
+            z: width,
+        },
+    };
+    properties::report_bounding_box(session, &cube_ids, Some(&expected_bbox), validation).await?;
+    if validation.is_some() {
+        for &cube_id in &cube_ids {
+            entities::verify_topology(session, cube_id, "extruding a square", 6, 12).await?;
+        }
+    }
+    let expected_volume = n * side.powi(3);
+    properties::report_volume(session, &cube_ids, expected_volume, validation).await?;
+    let expected_area = n * 6.0 * side.powi(2);
+    properties::report_surface_area(session, &cube_ids, expected_area, validation).await?;
+    Ok(cube_ids)
+}
+
+/// Draw an L-shaped bracket, find its center of mass,
+/// and put a tiny cube there so it shows up in the snapshot.
+/// Returns the bracket's ID (but not the marker's).
+async fn draw_l_bracket(
+    session: &mut ModelingSession,
+    validation: Option<Validation>,
+) -> Result<Vec<Uuid>> {
+    let (length, thickness, depth) = (30.0, 6.0, 10.0);
+    // We have to wait for the bracket to be extruded before we can measure it.
+    let bracket_id = shapes::draw_l_bracket(session, length, thickness, depth).await?;
+    properties::report_bounding_box(session, &[bracket_id], None, validation).await?;
+    if validation.is_some() {
+        // The L's profile has 6 sides, so the extrusion has 6 side faces plus a top and bottom,
+        // and 6 edges each around the top and bottom, plus 6 vertical edges.
+        entities::verify_topology(session, bracket_id, "extruding an L", 8, 18).await?;
+    }
+
+    // The bracket is made of two rectangles: the leg along X (including the corner) and the leg along Y.
+    // Its center of mass is the area-weighted average of theirs.
+    let x_leg_area = (length + thickness) * thickness;
+    let y_leg_area = length * thickness;
+    let total_area = x_leg_area + y_leg_area;
+    let expected = Point3D {
+        x: (x_leg_area * (length - thickness) / 2.0 - y_leg_area * thickness / 2.0) / total_area,
+        y: (y_leg_area * length / 2.0 - x_leg_area * thickness / 2.0) / total_area,
+        z: depth / 2.0,
+    };
+    let expected = session.place(&expected);
+    let center_of_mass =
+        properties::report_center_of_mass(session, bracket_id, &expected, length, validation)
+            .await?;
+
+    // The API has no sphere primitive, so mark the center of mass with a tiny cube instead.
+    // An L's center of mass lies in the empty space between its legs, so the marker is visible.
+    // It was measured in the scene, so it's already placed.
+    let marker = session.unplace(&center_of_mass);
+    shapes::draw_cube(session, &marker, 1.0).await?;
+    Ok(vec![bracket_id])
+}
+
+/// Sketch a square, read its vertices back from the API, and compare them with the corners we sent.
+/// Then extrude the square into a cube, and return its ID.
+async fn check_path(
+    session: &mut ModelingSession,
+    validation: Option<Validation>,
+) -> Result<Vec<Uuid>> {
+    let width = 10.0;
+    let corners = shapes::square_corners(
+        &Point3D {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        },
+        width,
+    );
+    let path_id = shapes::sketch_polygon(session, &corners).await?;
+
+    let points = paths::query_path_points(session, path_id).await?;
+    for p in &points {
+        say!("  ({}, {}, {})", p.x, p.y, p.z);
+    }
+    // The path is closed, so it ends where it started. The API reports where it was placed.
+    let expected: Vec<_> = corners
+        .iter()
+        .chain(&corners[..1])
+        .map(|corner| session.place(corner))
+        .collect();
+    let epsilon = validation.map_or(1e-6, |v| v.tolerance * width);
+    let mismatches = paths::compare_points(&expected, &points, epsilon);
+    for mismatch in &mismatches {
+        say!("Mismatch: {mismatch}");
+    }
+    if validation.is_some() && !mismatches.is_empty() {
+        bail!(
+            "{} of the path's vertices don't match what we drew",
+            mismatches.len()
+        );
+    }
+
+    shapes::extrude(session, path_id, width * 2.0).await?;
+    Ok(vec![path_id])
+}
+
+/// Draw a plate, and drill a round hole through it.
+/// Then check, by measuring its volume, that the hole removed as much material as it should have.
+/// Returns the plate's ID.
+async fn drill_plate(
+    session: &mut ModelingSession,
+    hole_diameter: f64,
+    hole_x: f64,
+    hole_y: f64,
+    validation: Option<Validation>,
+) -> Result<Vec<Uuid>> {
+    if hole_diameter <= 0.0 {
+        bail!("The hole's diameter must be positive, but it was {hole_diameter}");
+    }
+    let (width, length, thickness) = (40.0, 30.0, 5.0);
+    let radius = hole_diameter / 2.0;
+    let corners = [
+        (-width / 2.0, -length / 2.0),
+        (width / 2.0, -length / 2.0),
+        (width / 2.0, length / 2.0),
+        (-width / 2.0, length / 2.0),
+    ]
+    .map(|(x, y)| Point3D { x, y, z: 0.0 });
+
+    // The API doesn't have 3D booleans (as of this version of the kittycad crate),
+    // so instead of subtracting a cylinder from the plate, we cut a circle out of the plate's outline
+    // before extruding it, which drills a hole all the way through.
+    let plate_id = shapes::sketch_polygon(session, &corners).await?;
+    let hole_center = Point3D {
+        x: hole_x,
+        y: hole_y,
+        z: 0.0,
+    };
+    let hole_id = shapes::sketch_circle(session, &hole_center, radius).await?;
+    shapes::add_hole(session, plate_id, hole_id).await?;
+    shapes::extrude(session, plate_id, thickness).await?;
+
+    // Did the hole actually remove any material?
+    let solid = width * length * thickness;
+    let measured = properties::volume_in_scene_units(session, &[plate_id]).await?;
+    let removed = solid - measured;
+    let inside = hole_x.abs() + radius <= width / 2.0 && hole_y.abs() + radius <= length / 2.0;
+    say!(
+        "The hole removed {removed} {}3 of material",
+        units::SCENE_UNIT
+    );
+    if removed.abs() <= solid * 1e-6 {
+        bail!(
+            "The hole at ({hole_x}, {hole_y}) didn't remove any material. \
+             The API kept the original plate, because the hole doesn't intersect it."
+        );
+    }
+    if inside {
+        let expected = tessellation::circle_area(radius) * thickness;
+        properties::check("removed volume", removed, expected, validation)?;
+    } else {
+        say!("The hole overlaps the plate's edge, so the removed volume can't be checked");
+    }
+    Ok(vec![plate_id])
+}
+
+/// Draw two cubes `gap` apart, and measure the gap between them and one of their edges.
+/// Returns the cubes' IDs.
+async fn measure_cubes(
+    session: &mut ModelingSession,
+    gap: f64,
+    validation: Option<Validation>,
+) -> Result<Vec<Uuid>> {
+    let width = 10.0;
+    let origin = Point3D {
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+    };
+    let a = shapes::draw_cube(session, &origin, width).await?;
+    let b_center = Point3D {
+        x: width * 2.0 + gap,
+        ..origin
+    };
+    let b = shapes::draw_cube(session, &b_center, width).await?;
+
+    // The cubes' centers are one cube (half of each) plus the gap apart.
+    let centers = measure::distance_between_centers(session, a, b).await?;
+    let measured_gap = centers - width * 2.0;
+    // Measurements are in scene units, which convert like any other length.
+    let inches = measured_gap * units::mm_per_length(&units::SCENE_UNIT)
+        / units::mm_per_length(&UnitLength::In);
+    say!(
+        "Gap between the cubes: {measured_gap} {} ({inches} in), expected {gap}",
+        units::SCENE_UNIT
+    );
+    properties::check("gap", measured_gap, gap, validation)?;
+
+    // Every edge of a cube is as long as its sides.
+    let tree = EntityTree::query(session, a).await?;
+    let Some(&edge_id) = tree.ids_of_type(&EntityType::Edge).first() else {
+        bail!("Cube {a} has no edges to measure");
+    };
+    let length = measure::edge_length(session, edge_id).await?;
+    say!("Edge {edge_id} is {length} {} long", units::SCENE_UNIT);
+    properties::check("edge length", length, width * 2.0, validation)?;
+    Ok(vec![a, b])
+}
+
+/// Draw a cube, take a snapshot, then transform the cube and check its volume scaled as it should.
+/// The camera stays still, framing where the cube is both before and after, so the snapshots line up.
+/// Returns the transformed cube's ID.
+async fn transform_cube(
+    session: &mut ModelingSession,
+    transform: &Transform,
+    output: &Output,
+    validation: Option<Validation>,
+) -> Result<Vec<Uuid>> {
+    let width = 10.0;
+    let origin = Point3D {
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+    };
+    let cube_id = shapes::draw_cube(session, &origin, width).await?;
+    let Some(before) = session.recipe(cube_id).map(|recipe| recipe.bounding_box()) else {
+        bail!("Cube {cube_id} wasn't recorded");
+    };
+    let after = session
+        .recipe(cube_id)
+        .map(|recipe| recipe.transformed(transform).bounding_box())
+        .unwrap_or_else(|| before.clone());
+    camera::zoom_to_fit(session, &before.union(&after)).await?;
+    let before_output = output.with_suffix("-before");
+    if budget::allows(budget::Optional::ExtraViews, &before_output.path)? {
+        snapshot::export_png(session, &before_output).await?;
+        say!(
+            "Saved the cube before transforming it to {}",
+            before_output.path
+        );
+    }
+
+    let cube_id = transform::apply_transform(session, cube_id, transform).await?;
+    properties::report_bounding_box(session, &[cube_id], Some(&after), validation).await?;
+    // Moving and rotating keep the volume the same, but scaling multiplies it by scale cubed.
+    let expected_volume = (width * 2.0 * transform.scale).powi(3);
+    properties::report_volume(session, &[cube_id], expected_volume, validation).await?;
+    Ok(vec![cube_id])
+}
+
+/// Draw a pin, repeat it `count` times `spacing` apart, and check the row ends up where it should.
+/// Returns the pins' IDs.
+async fn pattern_pins(
+    session: &mut ModelingSession,
+    count: u32,
+    spacing: &Point3D,
+    validation: Option<Validation>,
+) -> Result<Vec<Uuid>> {
+    let (radius, height) = (2.0, 8.0);
+    let origin = Point3D {
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+    };
+    let pin_id = shapes::sketch_circle(session, &origin, radius).await?;
+    shapes::extrude(session, pin_id, height).await?;
+    let pin_ids = pattern::pattern_linear(session, pin_id, count, spacing).await?;
+
+    // The row spans from the first pin to the last one, which is (count - 1) spacings along.
+    let n = f64::from(count - 1);
+    let first = BoundingBox {
+        min: Point3D {
+            x: -radius,
+            y: -radius,
+            z: 0.0,
+        },
+        max: Point3D {
+            x: radius,
+            y: radius,
+            z: height,
+        },
+    };
+    let last = BoundingBox {
+        min: Point3D {
+            x: first.min.x + spacing.x * n,
+            y: first.min.y + spacing.y * n,
+            z: first.min.z + spacing.z * n,
+        },
+        max: Point3D {
+            x: first.max.x + spacing.x * n,
+            y: first.max.y + spacing.y * n,
+            z: first.max.z + spacing.z * n,
+        },
+    };
+    properties::report_bounding_box(session, &pin_ids, Some(&first.union(&last)), validation)
+        .await?;
+    let expected_volume = f64::from(count) * tessellation::circle_area(radius) * height;
+    properties::report_volume(session, &pin_ids, expected_volume, validation).await?;
+    Ok(pin_ids)
+}
+
+/// Draw a disc, and drill `count` holes through it, evenly spaced around its rim.
+/// Then check, by measuring its volume, that every hole removed as much material as it should have.
+/// Returns the disc's ID.
+async fn drill_bolt_circle(
+    session: &mut ModelingSession,
+    count: u32,
+    validation: Option<Validation>,
+) -> Result<Vec<Uuid>> {
+    let (disc_radius, thickness, hole_radius, bolt_circle_radius) = (30.0, 5.0, 3.0, 22.0);
+    let origin = Point3D {
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+    };
+    let disc_id = shapes::sketch_circle(session, &origin, disc_radius).await?;
+
+    // Like the plate, the holes are cut out of the disc's outline before it's extruded.
+    let first_hole = Point3D {
+        x: bolt_circle_radius,
+        ..origin.clone()
+    };
+    let hole_id = shapes::sketch_circle(session, &first_hole, hole_radius).await?;
+    let up = Point3D {
+        z: 1.0,
+        ..origin.clone()
+    };
+    let hole_ids = pattern::pattern_circular(session, hole_id, count, &up, &origin).await?;
+    for hole_id in hole_ids {
+        shapes::add_hole(session, disc_id, hole_id).await?;
+    }
+    shapes::extrude(session, disc_id, thickness).await?;
+
+    let area = tessellation::circle_area(disc_radius)
+        - f64::from(count) * tessellation::circle_area(hole_radius);
+    properties::report_volume(session, &[disc_id], area * thickness, validation).await?;
+    Ok(vec![disc_id])
+}
+
+/// Extrude an outline from a CSV file, and check its height and volume. Returns the solid's ID.
+async fn extrude_profile(
+    session: &mut ModelingSession,
+    profile: &profile::Profile,
+    height: f64,
+    scale: f64,
+    centered: bool,
+    validation: Option<Validation>,
+) -> Result<Vec<Uuid>> {
+    if !(height > 0.0 && height.is_finite()) {
+        bail!("The extrude height must be a positive number, not {height}");
+    }
+    say!(
+        "Extruding the {} point outline from {}",
+        profile.points.len(),
+        profile.source
+    );
+    let solid_id = if centered {
+        let path_id = shapes::sketch_polygon(session, &profile.corners(scale)).await?;
+        shapes::extrude_centered(session, path_id, height).await?
+    } else {
+        shapes::extrude_polygon(session, &profile.corners(scale), height).await?
+    };
+    let bbox = properties::report_bounding_box(session, &[solid_id], None, validation).await?;
+    // The outline is drawn at z = 0, so the solid should start there, or straddle it.
+    let top = if centered { height / 2.0 } else { height };
+    properties::check("top", bbox.max.z, top, validation)?;
+    properties::check("z extent", bbox.extents().z, height, validation)?;
+    // This assumes the outline doesn't cross itself.
+    let expected_volume = profile.area(scale) * height;
+    properties::report_volume(session, &[solid_id], expected_volume, validation).await?;
+    Ok(vec![solid_id])
+}
+
+/// Stroke a path from a CSV file into a wall, and check its height and volume. Returns the wall's ID.
+async fn draw_wall(
+    session: &mut ModelingSession,
+    path: &profile::Polyline,
+    thickness: f64,
+    height: f64,
+    scale: f64,
+    miter_limit: f64,
+    validation: Option<Validation>,
+) -> Result<Vec<Uuid>> {
+    say!(
+        "Building a wall along the {} point path from {}",
+        path.points.len(),
+        path.source
+    );
+    let points = path.points(scale);
+    let corners: Vec<_> = points
+        .iter()
+        .map(|&(x, y)| Point3D { x, y, z: 0.0 })
+        .collect();
+    let wall_id = shapes::stroke_path(session, &corners, thickness, height, miter_limit).await?;
+    let bbox = properties::report_bounding_box(session, &[wall_id], None, validation).await?;
+    properties::check("z extent", bbox.extents().z, height, validation)?;
+    // The outline was checked not to cross itself, so its area is the wall's footprint.
+    let outline = stroke::outline(&points, thickness, miter_limit)?.outline;
+    let expected_volume = stroke::area(&outline) * height;
+    properties::report_volume(session, &[wall_id], expected_volume, validation).await?;
+    Ok(vec![wall_id])
+}
+
+/// Extrude an outline from an SVG file. Returns the solid's ID.
+async fn extrude_svg(
+    session: &mut ModelingSession,
+    outline: &svg::Outline,
+    height: f64,
+    scale: f64,
+    tolerance: Option<f64>,
+    validation: Option<Validation>,
+) -> Result<Vec<Uuid>> {
+    if !(height > 0.0 && height.is_finite()) {
+        bail!("The extrude height must be a positive number, not {height}");
+    }
+    let tolerance = tolerance.or(tessellation::quality().map(tessellation::flatten_tolerance));
+    let (start, segments) = outline.path(scale, tolerance);
+    say!(
+        "Extruding the outline from {}, which has {} segments",
+        outline.source,
+        segments.len()
+    );
+    let path_id = shapes::sketch_path(session, start, segments).await?;
+    shapes::extrude(session, path_id, height).await?;
+    properties::report_bounding_box(session, &[path_id], None, validation).await?;
+    Ok(vec![path_id])
+}
+
+/// Draw a block, find its top face, and sketch a circle on it, which is extruded upwards into a boss.
+/// Returns the block's and the boss's IDs.
+async fn add_boss(
+    session: &mut ModelingSession,
+    validation: Option<Validation>,
+) -> Result<Vec<Uuid>> {
+    let (width, height, boss_radius, boss_height) = (40.0, 10.0, 8.0, 10.0);
+    let corners = [
+        (-width / 2.0, -width / 2.0),
+        (width / 2.0, -width / 2.0),
+        (width / 2.0, width / 2.0),
+        (-width / 2.0, width / 2.0),
+    ]
+    .map(|(x, y)| Point3D { x, y, z: 0.0 });
+    let block_id = shapes::extrude_polygon(session, &corners, height).await?;
+
+    let top = sketch::find_top_face(session, block_id).await?;
+    say!(
+        "The block's top face is {}, at height {}",
+        top.id,
+        top.bbox.max.z
+    );
+    properties::check("top face height", top.bbox.max.z, height, validation)?;
+
+    // Sketch coordinates are world coordinates, so the circle sits on the face at z = height.
+    // The plane's origin is the middle of the face.
+    let saved_camera = camera::save(session);
+    let plane = sketch::plane_on_face(session, &top).await?;
+    let mut sketch = sketch::start(session, &plane)
+        .await
+        .wrap_err_with(|| format!("Could not start sketching on face {}", top.id))?;
+    let boss_id = sketch.circle((0.0, 0.0), boss_radius).await?;
+    sketch.finish().await?;
+    camera::restore(session, &saved_camera).await?;
+    shapes::extrude(session, boss_id, boss_height).await?;
+
+    // The API can't join solids (as of this version of the kittycad crate), so the boss is a separate
+    // solid standing on the block. Measure them together.
+    let ids = vec![block_id, boss_id];
+    let expected_volume =
+        width * width * height + tessellation::circle_area(boss_radius) * boss_height;
+    properties::report_volume(session, &ids, expected_volume, validation).await?;
+    Ok(ids)
+}
+
+/// Sketch a bar on the XY plane, and another on `plane` (or a plane facing `normal`),
+/// and extrude them both, so they cross. Returns the bars' IDs.
+async fn draw_cross(
+    session: &mut ModelingSession,
+    plane: sketch::StandardPlane,
+    normal: Option<&Point3D>,
+    validation: Option<Validation>,
+) -> Result<Vec<Uuid>> {
+    let (length, width, depth) = (40.0, 10.0, 10.0);
+    // Both bars are drawn in their planes' own coordinates, going anticlockwise around the planes'
+    // normals, so they're extruded along them: the flat bar upwards, and the upright one forwards (-Y).
+    let bar = [
+        (-length / 2.0, -width / 2.0),
+        (length / 2.0, -width / 2.0),
+        (length / 2.0, width / 2.0),
+        (-length / 2.0, width / 2.0),
+    ];
+    // The upright bar is the flat one turned a quarter turn in its plane, which keeps it anticlockwise.
+    let upright = bar.map(|(u, v)| (-v, u + width / 2.0));
+
+    let flat_plane =
+        sketch::make_standard_plane(session, sketch::StandardPlane::Xy, length).await?;
+    let upright_plane = match normal {
+        Some(normal) => {
+            let origin = Point3D {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            };
+            sketch::make_plane(session, origin, normal, length).await?
+        }
+        None => sketch::make_standard_plane(session, plane, length).await?,
+    };
+    let mut ids = Vec::new();
+    for (plane, corners) in [(&flat_plane, bar), (&upright_plane, upright)] {
+        let mut sketch = sketch::start(session, plane).await?;
+        let path_id = sketch.polygon(&corners).await?;
+        sketch.finish().await?;
+        shapes::extrude(session, path_id, depth).await?;
+        ids.push(path_id);
+    }
+
+    // With the upright bar on the XZ plane, the flat bar spans z = 0 to depth, and the upright one
+    // y = 0 to -depth.
+    let on_xz = normal.is_none() && matches!(plane, sketch::StandardPlane::Xz);
+    let expected_bbox = on_xz.then(|| BoundingBox {
+        min: Point3D {
+            x: -length / 2.0,
+            y: -depth,
+            z: -length / 2.0 + width / 2.0,
+        },
+        max: Point3D {
+            x: length / 2.0,
+            y: width / 2.0,
+            z: length / 2.0 + width / 2.0,
+        },
+    });
+    properties::report_bounding_box(session, &ids, expected_bbox.as_ref(), validation).await?;
+    // The bars overlap, but the API can't join them, so each is measured on its own.
+    for &id in &ids {
+        properties::report_volume(session, &[id], length * width * depth, validation).await?;
+    }
+    Ok(ids)
+}
+
+/// Draw a cube, highlight the edges which satisfy `predicate`, and snapshot it. Then clear the highlight.
+/// Returns the cube's ID.
+async fn highlight_edges(
+    session: &mut ModelingSession,
+    predicate: &selection::EdgePredicate,
+    output: &Output,
+    validation: Option<Validation>,
+) -> Result<Vec<Uuid>> {
+    let width = 10.0;
+    let origin = Point3D {
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+    };
+    let cube_id = shapes::draw_cube(session, &origin, width).await?;
+    let edge_ids = selection::select_edges(session, cube_id, predicate).await?;
+    say!("Cube {cube_id} has {} edges {predicate}", edge_ids.len());
+    // Four edges run along each axis, and four go around each face.
+    let expected = match predicate {
+        selection::EdgePredicate::ParallelTo(_) | selection::EdgePredicate::Top => Some(4),
+        selection::EdgePredicate::LongerThan(_) => None,
+    };
+    if let Some(expected) = expected.filter(|_| validation.is_some()) {
+        if edge_ids.len() != expected {
+            bail!(
+                "Expected a cube to have {expected} edges {predicate}, but found {}",
+                edge_ids.len()
+            );
+        }
+    }
+
+    selection::emphasize(session, &edge_ids).await?;
+    if let Some(bbox) = session.bounding_box(&[cube_id]) {
+        camera::zoom_to_fit(session, &bbox).await?;
+    }
+    let highlighted_output = output.with_suffix("-highlighted");
+    if budget::allows(budget::Optional::ExtraViews, &highlighted_output.path)? {
+        snapshot::export_png(session, &highlighted_output).await?;
+        say!(
+            "Saved the cube with its edges {predicate} highlighted to {}",
+            highlighted_output.path
+        );
+    }
+    selection::clear(session).await?;
+    Ok(vec![cube_id])
+}
+
+/// Draw two cubes and snapshot them, then remove one, and check the scene only has the other.
+/// The camera stays still, so the snapshots line up. Returns the remaining cube's ID.
+async fn remove_cube(
+    session: &mut ModelingSession,
+    output: &Output,
+    validation: Option<Validation>,
+) -> Result<Vec<Uuid>> {
+    let width = 10.0;
+    let origin = Point3D {
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+    };
+    let kept = shapes::draw_cube(session, &origin, width).await?;
+    let removed_center = Point3D {
+        x: width * 4.0,
+        ..origin
+    };
+    let removed = shapes::draw_cube(session, &removed_center, width).await?;
+    if let Some(bbox) = session.bounding_box(&[kept, removed]) {
+        camera::zoom_to_fit(session, &bbox).await?;
+    }
+    let both_output = output.with_suffix("-both");
+    if budget::allows(budget::Optional::ExtraViews, &both_output.path)? {
+        snapshot::export_png(session, &both_output).await?;
+        say!("Saved both cubes to {}", both_output.path);
+    }
+
+    transform::remove_objects(session, &[removed]).await?;
+    say!("Removed cube {removed}");
+    // The removed cube is gone, so the API shouldn't be able to measure it.
+    if validation.is_some() && properties::volume(session, &[removed]).await.is_ok() {
+        bail!("Removed cube {removed}, but the API can still measure its volume");
+    }
+    properties::report_volume(session, &[kept], (width * 2.0).powi(3), validation).await?;
+    Ok(vec![kept])
+}
@@ -0,0 +1,121 @@
+use crate::{
+    entities::EntityTree, jitter::Jittered, recipe::Recipe, session::ModelingSession, sink,
+    snapshot::STDOUT,
+};
+use color_eyre::{eyre::WrapErr, Result};
+use std::{collections::BTreeMap, path::Path};
+use uuid::Uuid;
+
+/// Everything a run created, and which outputs show it.
+/// Useful for scripting follow-up operations, working out which ID is which part,
+/// or comparing two runs with scene-diff.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Manifest {
+    /// The command-line arguments of the run.
+    pub args: Vec<String>,
+    pub objects: Vec<ObjectEntry>,
+    pub outputs: Vec<OutputEntry>,
+    /// What the run called its objects, e.g. "plate", and the ID each name refers to
+    /// (or last referred to, if its object was removed).
+    #[serde(default)]
+    pub names: BTreeMap<String, Uuid>,
+    /// The variants of a jittered scene the run built, and each one's jittered parameters (see --seed).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub jitter: Vec<Jittered>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct ObjectEntry {
+    pub id: Uuid,
+    /// How the object was made: "extrude", "sketch", or "other" (e.g. a plane, or a path cut out of another as a hole).
+    pub operation: String,
+    /// Whether the object was removed from the scene before the end of the run.
+    pub removed: bool,
+    /// The commands which built the object, if we know them.
+    pub recipe: Option<Recipe>,
+    /// The object's faces, edges, etc., as the API reports them.
+    /// Only known for objects which are still in the scene.
+    pub entities: Option<EntityTree>,
+    /// The outputs which show this object.
+    pub outputs: Vec<String>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct OutputEntry {
+    pub path: String,
+    /// The SHA-256 of the output file, in hex, when the manifest was saved.
+    /// Missing for outputs written to standard output.
+    #[serde(default)]
+    pub sha256: Option<String>,
+    /// The objects in this output: the ones which were in the scene when it was saved,
+    /// or for an export of only some objects, those objects.
+    pub object_ids: Vec<Uuid>,
+}
+
+impl Manifest {
+    /// Describe everything the session created, asking the API about the objects which still exist.
+    pub async fn describe(session: &mut ModelingSession) -> Result<Self> {
+        let mut objects = Vec::new();
+        for id in session.history().to_vec() {
+            let (removed, recipe) = match session.removed_recipe(id) {
+                Some(recipe) => (true, recipe.cloned()),
+                None => (false, session.recipe(id).cloned()),
+            };
+            let entities = if removed {
+                None
+            } else {
+                Some(EntityTree::query(session, id).await?)
+            };
+            let operation = match &recipe {
+                Some(Recipe {
+                    extrusion: Some(_), ..
+                }) => "extrude",
+                Some(_) => "sketch",
+                None => "other",
+            };
+            let outputs = session
+                .outputs()
+                .iter()
+                .filter(|output| output.object_ids.contains(&id))
+                .map(|output| output.path.clone())
+                .collect();
+            objects.push(ObjectEntry {
+                id,
+                operation: operation.to_owned(),
+                removed,
+                recipe,
+                entities,
+                outputs,
+            });
+        }
+        let outputs = session
+            .outputs()
+            .iter()
+            .map(|output| OutputEntry {
+                path: output.path.clone(),
+                sha256: (output.path != STDOUT).then(|| output.sha256.clone()),
+                object_ids: output.object_ids.clone(),
+            })
+            .collect();
+        Ok(Self {
+            args: std::env::args().skip(1).collect(),
+            objects,
+            outputs,
+            names: session.names().ids(),
+            jitter: session.variants().to_vec(),
+        })
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("Could not read the manifest {}", path.display()))?;
+        serde_json::from_str(&text)
+            .wrap_err_with(|| format!("Could not parse the manifest {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        sink::write_atomically(path, json.as_bytes())
+            .wrap_err_with(|| format!("Could not save the manifest to {}", path.display()))
+    }
+}
@@ -0,0 +1,132 @@
+use color_eyre::{
+    eyre::{bail, eyre, Context},
+    Result,
+};
+use kittycad::types::UnitDensity;
+use std::{fmt, str::FromStr};
+
+/// How dense some material is.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Density {
+    pub value: f64,
+    pub unit: UnitDensity,
+}
+
+impl fmt::Display for Density {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.value, self.unit)
+    }
+}
+
+/// Parses a number followed by a density unit, e.g. "7850 kg:m3" or "490lb:ft3".
+impl FromStr for Density {
+    type Err = color_eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let split_at = s
+            .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-' || c == 'e'))
+            .ok_or_else(|| eyre!("Density {s:?} needs a unit, e.g. \"{s} kg:m3\""))?;
+        let (value, unit) = s.split_at(split_at);
+        let value = value
+            .parse()
+            .with_context(|| format!("Density {s:?} must start with a number"))?;
+        let unit = unit.trim().parse().map_err(|_| {
+            eyre!(
+                "Unknown density unit {:?}, the API supports {} and {}",
+                unit.trim(),
+                UnitDensity::KgM3,
+                UnitDensity::LbFt3
+            )
+        })?;
+        Ok(Self { value, unit })
+    }
+}
+
+/// Common materials, and their densities in kg/m³.
+pub const PRESETS: [(&str, f64); 4] = [
+    ("aluminum", 2700.0),
+    ("steel", 7850.0),
+    ("abs", 1040.0),
+    ("pla", 1240.0),
+];
+
+/// Look up the density of a material by name.
+pub fn preset(name: &str) -> Result<Density> {
+    let Some((_, value)) = PRESETS
+        .iter()
+        .find(|(preset, _)| preset.eq_ignore_ascii_case(name))
+    else {
+        let names: Vec<_> = PRESETS.iter().map(|(name, _)| *name).collect();
+        bail!(
+            "Unknown material {name:?}, choose one of: {}",
+            names.join(", ")
+        );
+    };
+    Ok(Density {
+        value: *value,
+        unit: UnitDensity::KgM3,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn looks_up_presets_whatever_their_case() {
+        for (name, value) in [("steel", 7850.0), ("Aluminum", 2700.0), ("PLA", 1240.0)] {
+            let density = preset(name).unwrap();
+            assert_eq!(density.value, value, "{name}");
+            assert_eq!(density.unit, UnitDensity::KgM3);
+        }
+    }
+
+    #[test]
+    fn lists_the_presets_for_an_unknown_material() {
+        assert_eq!(
+            preset("unobtainium").unwrap_err().to_string(),
+            "Unknown material \"unobtainium\", choose one of: aluminum, steel, abs, pla"
+        );
+    }
+
+    #[test]
+    fn reads_densities_with_their_units() {
+        let density: Density = "7850 kg:m3".parse().unwrap();
+        assert_eq!(
+            density,
+            Density {
+                value: 7850.0,
+                unit: UnitDensity::KgM3
+            }
+        );
+        let density: Density = " 490lb:ft3 ".parse().unwrap();
+        assert_eq!(
+            density,
+            Density {
+                value: 490.0,
+                unit: UnitDensity::LbFt3
+            }
+        );
+        assert_eq!(density.to_string(), "490 lb:ft3");
+        let density: Density = "1.2e3 kg:m3".parse().unwrap();
+        assert_eq!(density.value, 1200.0);
+    }
+
+    #[test]
+    fn says_whats_wrong_with_a_density() {
+        for (text, expected) in [
+            ("7850", "Density \"7850\" needs a unit, e.g. \"7850 kg:m3\""),
+            (
+                "7850 g:cm3",
+                "Unknown density unit \"g:cm3\", the API supports kg:m3 and lb:ft3",
+            ),
+            (
+                "lots kg:m3",
+                "Density \"lots kg:m3\" must start with a number",
+            ),
+        ] {
+            assert_eq!(text.parse::<Density>().unwrap_err().to_string(), expected);
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use crate::{geometry, properties, session::ModelingSession};
+use color_eyre::{
+    eyre::{bail, WrapErr},
+    Result,
+};
+use kittycad::types::{ModelingCmd, OkModelingCmdResponse, Point3D};
+use uuid::Uuid;
+
+/// How far apart the centers of two objects are, in scene units.
+/// The API (as of this version of the kittycad crate) has no entity-to-entity distance query,
+/// so we measure each object's center of mass, and compare them.
+pub async fn distance_between_centers(
+    session: &mut ModelingSession,
+    a: Uuid,
+    b: Uuid,
+) -> Result<f64> {
+    let center_a = properties::center_of_mass(session, a).await?;
+    let center_b = properties::center_of_mass(session, b).await?;
+    Ok(geometry::distance(&center_a, &center_b))
+}
+
+/// How long an edge is, in scene units.
+/// This measures the straight line between its ends, so it's only right for straight edges.
+pub async fn edge_length(session: &mut ModelingSession, edge_id: Uuid) -> Result<f64> {
+    let ends = edge_end_points(session, &[edge_id]).await?;
+    let (start, end) = &ends[0];
+    Ok(geometry::distance(start, end))
+}
+
+/// Where each edge starts and ends, in the same order as the edges.
+pub async fn edge_end_points(
+    session: &mut ModelingSession,
+    edge_ids: &[Uuid],
+) -> Result<Vec<(Point3D, Point3D)>> {
+    // Ask about every edge at once, then read the responses.
+    let mut cmd_ids = Vec::with_capacity(edge_ids.len());
+    for &curve_id in edge_ids {
+        cmd_ids.push(
+            session
+                .send(ModelingCmd::CurveGetEndPoints { curve_id })
+                .await?,
+        );
+    }
+    let mut ends = Vec::with_capacity(edge_ids.len());
+    for (cmd_id, edge_id) in cmd_ids.into_iter().zip(edge_ids) {
+        let resp = session
+            .await_response(cmd_id)
+            .await
+            .wrap_err_with(|| format!("Could not measure edge {edge_id}"))?;
+        let OkModelingCmdResponse::CurveGetEndPoints { data } = resp else {
+            bail!("Expected the API to respond to CurveGetEndPoints with two points");
+        };
+        ends.push((data.start, data.end));
+    }
+    Ok(ends)
+}
@@ -0,0 +1,381 @@
+use crate::session::{ConnectionLost, ModelingSession};
+use color_eyre::{eyre::bail, Result};
+use kittycad::types::ModelingCmd;
+use std::collections::{BTreeMap, BTreeSet};
+use uuid::Uuid;
+
+/// An object, given by its ID, or by a name it was registered under, e.g. "plate" or "cube_2".
+#[derive(Clone, Debug, PartialEq)]
+pub enum Handle {
+    Id(Uuid),
+    Name(String),
+}
+
+impl Handle {
+    /// Parses an ID, or else a name.
+    pub fn parse(s: &str) -> Result<Self> {
+        if let Ok(id) = Uuid::parse_str(s) {
+            return Ok(Self::Id(id));
+        }
+        check_name(s)?;
+        Ok(Self::Name(s.to_owned()))
+    }
+}
+
+impl std::fmt::Display for Handle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Id(id) => write!(f, "{id}"),
+            Self::Name(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+/// Why a name no longer names anything in the scene.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stale {
+    /// Its object was removed from the scene.
+    Removed,
+    /// Its object was gone when the run connected again.
+    Lost,
+}
+
+#[derive(Clone, Debug)]
+struct Named {
+    id: Uuid,
+    stale: Option<Stale>,
+}
+
+/// The names the run has given its objects, so they can be referred to without copying IDs around,
+/// e.g. by --export-only, or as "${name}" in a raw command.
+///
+/// A name can't be given to two objects at once, but once its object is removed (or lost when the
+/// run reconnects), the name is stale, and can be given to something else.
+#[derive(Clone, Debug, Default)]
+pub struct NameRegistry {
+    names: BTreeMap<String, Named>,
+}
+
+impl NameRegistry {
+    /// Give an object a name. Fails if the name is already in use.
+    pub fn register(&mut self, name: &str, id: Uuid) -> Result<()> {
+        check_name(name)?;
+        if let Some(Named {
+            id: taken,
+            stale: None,
+        }) = self.names.get(name)
+        {
+            bail!("Can't give {id} the name {name}, because {taken} already has it");
+        }
+        self.names
+            .insert(name.to_owned(), Named { id, stale: None });
+        Ok(())
+    }
+
+    /// The ID of the object an ID or name refers to.
+    pub fn resolve(&self, handle: &Handle) -> Result<Uuid> {
+        match handle {
+            Handle::Id(id) => Ok(*id),
+            Handle::Name(name) => self.lookup(name),
+        }
+    }
+
+    /// The ID of the object called this. Fails (suggesting a name, if one's close) if nothing is,
+    /// or if the object it named is no longer in the scene.
+    pub fn lookup(&self, name: &str) -> Result<Uuid> {
+        match self.names.get(name) {
+            Some(Named { id, stale: None }) => Ok(*id),
+            Some(Named {
+                id,
+                stale: Some(Stale::Removed),
+            }) => bail!("{name} ({id}) has been removed from the scene"),
+            Some(Named {
+                id,
+                stale: Some(Stale::Lost),
+            }) => bail!(
+                "{name} ({id}) was lost when the run connected to the API again. \
+                 The API doesn't keep scenes between connections, so build it again"
+            ),
+            None => match self.suggest(name) {
+                Some(suggestion) => bail!("Nothing is called {name}. Did you mean {suggestion}?"),
+                None if self.names.is_empty() => {
+                    bail!("Nothing is called {name}, because nothing has been named yet")
+                }
+                None => {
+                    let known: Vec<_> = self.names.keys().map(String::as_str).collect();
+                    bail!("Nothing is called {name} (there's {})", known.join(", "))
+                }
+            },
+        }
+    }
+
+    /// The name in use which is closest to this one, if any is close enough to be a typo of it:
+    /// it only differs in case, or by a third of its letters (at least one).
+    pub fn suggest(&self, name: &str) -> Option<&str> {
+        let allowed = (name.chars().count() / 3).max(1);
+        self.names
+            .iter()
+            .filter(|(_, named)| named.stale.is_none())
+            .map(|(known, _)| {
+                let distance = if known.eq_ignore_ascii_case(name) {
+                    0
+                } else {
+                    edit_distance(known, name)
+                };
+                (distance, known)
+            })
+            .filter(|&(distance, _)| distance <= allowed)
+            .min()
+            .map(|(_, known)| known.as_str())
+    }
+
+    /// The object's been rebuilt with a new ID (e.g. to move it), so its names follow it.
+    pub fn moved(&mut self, from: Uuid, to: Uuid) {
+        for named in self.names.values_mut() {
+            if named.id == from && named.stale.is_none() {
+                named.id = to;
+            }
+        }
+    }
+
+    /// The object's been removed from the scene, so its names are stale.
+    pub fn removed(&mut self, id: Uuid) {
+        self.mark(id, Stale::Removed);
+    }
+
+    /// Everything's been removed from the scene, so every name is stale.
+    pub fn removed_all(&mut self) {
+        for named in self.names.values_mut() {
+            named.stale.get_or_insert(Stale::Removed);
+        }
+    }
+
+    /// Mark the names of an object as stale.
+    pub fn mark(&mut self, id: Uuid, why: Stale) {
+        for named in self.names.values_mut() {
+            if named.id == id && named.stale.is_none() {
+                named.stale = Some(why);
+            }
+        }
+    }
+
+    /// Every name, and the object it names (or last named, if it's stale).
+    pub fn ids(&self) -> BTreeMap<String, Uuid> {
+        self.names
+            .iter()
+            .map(|(name, named)| (name.clone(), named.id))
+            .collect()
+    }
+
+    /// The objects names still refer to.
+    fn live_ids(&self) -> BTreeSet<Uuid> {
+        self.names
+            .values()
+            .filter(|named| named.stale.is_none())
+            .map(|named| named.id)
+            .collect()
+    }
+}
+
+/// Names can be anything which can't be mistaken for an ID, and which can be written in a raw command's "${name}".
+fn check_name(name: &str) -> Result<()> {
+    if name.is_empty() {
+        bail!("A name can't be empty");
+    }
+    if name.contains(['{', '}']) {
+        bail!("The name {name} can't have braces in it");
+    }
+    if Uuid::parse_str(name).is_ok() {
+        bail!("The name {name} can't be an ID");
+    }
+    Ok(())
+}
+
+/// How many letters must be added, removed or changed to turn one string into the other.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (above + 1)
+                .min(row[j] + 1)
+                .min(diagonal + usize::from(ca != cb));
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+/// Name the objects a model made: its first is called `name`, and if it made more than one,
+/// they're also called `name_1`, `name_2`, and so on, e.g. "cube_1", "cube_2" and "cube_3".
+pub fn register_objects(session: &mut ModelingSession, name: &str, ids: &[Uuid]) -> Result<()> {
+    let Some(&first) = ids.first() else {
+        return Ok(());
+    };
+    let names = session.names_mut();
+    names.register(name, first)?;
+    if ids.len() > 1 {
+        for (i, &id) in ids.iter().enumerate() {
+            names.register(&format!("{name}_{}", i + 1), id)?;
+        }
+    }
+    Ok(())
+}
+
+/// Check every named object is still in the session's scene, e.g. after connecting again,
+/// by asking the API what kind of entity each one is. Those which aren't are marked stale.
+/// Returns the names which went stale.
+pub async fn revalidate(session: &mut ModelingSession) -> Result<Vec<String>> {
+    let mut lost = Vec::new();
+    for id in session.names().live_ids() {
+        let cmd_id = session
+            .send(ModelingCmd::GetEntityType { entity_id: id })
+            .await?;
+        match session.await_response(cmd_id).await {
+            Ok(_) => {}
+            Err(e) if ConnectionLost::caused(&e) => return Err(e),
+            Err(_) => {
+                lost.extend(
+                    session
+                        .names()
+                        .names
+                        .iter()
+                        .filter(|(_, named)| named.id == id && named.stale.is_none())
+                        .map(|(name, _)| name.clone()),
+                );
+                session.names_mut().mark(id, Stale::Lost);
+            }
+        }
+    }
+    Ok(lost)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry(names: &[(&str, u128)]) -> NameRegistry {
+        let mut registry = NameRegistry::default();
+        for &(name, id) in names {
+            registry.register(name, Uuid::from_u128(id)).unwrap();
+        }
+        registry
+    }
+
+    #[test]
+    fn looks_up_names() {
+        let names = registry(&[("plate", 1), ("cube_1", 2)]);
+        assert_eq!(names.lookup("plate").unwrap(), Uuid::from_u128(1));
+        assert_eq!(
+            names.resolve(&Handle::parse("cube_1").unwrap()).unwrap(),
+            Uuid::from_u128(2)
+        );
+        let id = Uuid::from_u128(9);
+        assert_eq!(
+            names
+                .resolve(&Handle::parse(&id.to_string()).unwrap())
+                .unwrap(),
+            id
+        );
+    }
+
+    #[test]
+    fn suggests_a_name_for_a_typo() {
+        let names = registry(&[("plate", 1), ("cube_1", 2), ("cube_2", 3)]);
+        assert_eq!(names.suggest("plat"), Some("plate"));
+        assert_eq!(names.suggest("PLATE"), Some("plate"));
+        // The nearest of two close names.
+        assert_eq!(names.suggest("cube_2x"), Some("cube_2"));
+        assert_eq!(names.suggest("bracket"), None);
+        assert_eq!(
+            names.lookup("plat").unwrap_err().to_string(),
+            "Nothing is called plat. Did you mean plate?"
+        );
+        assert_eq!(
+            names.lookup("bracket").unwrap_err().to_string(),
+            "Nothing is called bracket (there's cube_1, cube_2, plate)"
+        );
+        assert_eq!(
+            NameRegistry::default()
+                .lookup("plate")
+                .unwrap_err()
+                .to_string(),
+            "Nothing is called plate, because nothing has been named yet"
+        );
+    }
+
+    #[test]
+    fn says_why_a_stale_name_names_nothing() {
+        let mut names = registry(&[("plate", 1), ("cube", 2)]);
+        let (plate, cube) = (Uuid::from_u128(1), Uuid::from_u128(2));
+        names.mark(plate, Stale::Removed);
+        names.mark(cube, Stale::Lost);
+        assert_eq!(
+            names.lookup("plate").unwrap_err().to_string(),
+            format!("plate ({plate}) has been removed from the scene")
+        );
+        assert!(names
+            .lookup("cube")
+            .unwrap_err()
+            .to_string()
+            .starts_with(&format!(
+                "cube ({cube}) was lost when the run connected to the API again"
+            )));
+        // Stale names aren't suggested.
+        assert_eq!(names.suggest("plat"), None);
+        // A name stays stale for the reason it first went stale.
+        names.mark(plate, Stale::Lost);
+        assert!(names
+            .lookup("plate")
+            .unwrap_err()
+            .to_string()
+            .ends_with("removed from the scene"));
+    }
+
+    #[test]
+    fn gives_a_stale_name_to_something_else() {
+        let mut names = registry(&[("plate", 1)]);
+        assert_eq!(
+            names
+                .register("plate", Uuid::from_u128(2))
+                .unwrap_err()
+                .to_string(),
+            format!(
+                "Can't give {} the name plate, because {} already has it",
+                Uuid::from_u128(2),
+                Uuid::from_u128(1)
+            )
+        );
+        names.removed(Uuid::from_u128(1));
+        names.register("plate", Uuid::from_u128(2)).unwrap();
+        assert_eq!(names.lookup("plate").unwrap(), Uuid::from_u128(2));
+    }
+
+    #[test]
+    fn moves_names_with_their_objects() {
+        let mut names = registry(&[("plate", 1), ("disc", 2)]);
+        names.moved(Uuid::from_u128(1), Uuid::from_u128(3));
+        assert_eq!(names.lookup("plate").unwrap(), Uuid::from_u128(3));
+        assert_eq!(names.live_ids(), [2, 3].map(Uuid::from_u128).into());
+        names.removed_all();
+        assert!(names.live_ids().is_empty());
+    }
+
+    #[test]
+    fn rejects_names_which_could_be_mistaken() {
+        for (name, expected) in [
+            ("", "A name can't be empty".to_owned()),
+            ("a{b}", "The name a{b} can't have braces in it".to_owned()),
+            (
+                &Uuid::from_u128(1).simple().to_string(),
+                format!("The name {} can't be an ID", Uuid::from_u128(1).simple()),
+            ),
+        ] {
+            let e = NameRegistry::default().register(name, Uuid::from_u128(1));
+            assert_eq!(e.unwrap_err().to_string(), expected);
+        }
+    }
+}
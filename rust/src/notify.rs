@@ -0,0 +1,233 @@
+use color_eyre::{eyre::bail, Result};
+use std::time::Duration;
+
+/// A summary of how a run went, for whoever's waiting on it.
+#[derive(Debug, serde::Serialize)]
+pub struct RunReport {
+    /// The command-line arguments of the run.
+    pub args: Vec<String>,
+    pub succeeded: bool,
+    /// What went wrong, if the run failed.
+    pub error: Option<String>,
+    /// Where the final snapshot was (or would have been) saved.
+    pub output: String,
+    /// Where the model was (or would have been) exported, if it was asked for.
+    pub export: Option<String>,
+    /// Which stream the run's sessions asked for (see --connection-profile).
+    pub connection_profile: crate::session::ConnectionProfile,
+    /// When the run started and finished, in seconds since the Unix epoch.
+    pub started_at: u64,
+    pub finished_at: u64,
+    /// The run's ID, which its API requests carry, for KittyCAD support.
+    pub run_id: uuid::Uuid,
+    /// The first request ID the API sent back, if it responded at all.
+    pub request_id: Option<uuid::Uuid>,
+    /// Which API deployment the run used, if it got as far as asking.
+    pub server: Option<crate::server::ServerInfo>,
+    /// How many messages from the API were skipped, because they couldn't be parsed.
+    pub unrecognized_responses: usize,
+    /// How many times the run connected again, because the API closed a session before responding to anything.
+    pub session_retries: usize,
+    /// How many times the API asked the run for its client metrics, and was answered.
+    pub metrics_requests: usize,
+    /// How many bytes rounding coordinates (--coord-precision) saved.
+    pub coord_bytes_saved: usize,
+    /// How many responses arrived before the responses to commands sent earlier, and by how much.
+    pub response_ordering: crate::session::ResponseOrdering,
+    /// How long the run's sessions spent in each phase, e.g. waiting for snapshots.
+    pub timings: crate::timings::Timings,
+    /// Every output the run saved (not counting standard output), and where each ended up,
+    /// e.g. a path, or a URL in object storage.
+    pub artifacts: Vec<crate::sink::Written>,
+    /// Every export the API sent back, in the order they arrived: its format, how big it was,
+    /// and how long after it was asked for it arrived.
+    pub exports: Vec<crate::export::Transfer>,
+    /// Every warning, once each, with how many times it happened.
+    pub warnings: Vec<crate::warnings::Warning>,
+    /// Whether the outputs came from --cache-dir, if the run used it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache: Option<crate::cache::Status>,
+}
+
+/// POST the report to a URL as JSON, trying once more if that fails.
+/// Failures are printed, not returned, because they don't change how the run went.
+pub async fn notify(url: &str, report: &RunReport, timeout: Duration) {
+    let client = match reqwest::Client::builder().timeout(timeout).build() {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("Could not notify {url}: {e}");
+            return;
+        }
+    };
+    for attempt in 1..=2 {
+        match post(&client, url, report).await {
+            Ok(()) => {
+                say!("Notified {url}");
+                return;
+            }
+            Err(e) => eprintln!("Could not notify {url} (attempt {attempt} of 2): {e}"),
+        }
+    }
+}
+
+async fn post(client: &reqwest::Client, url: &str, report: &RunReport) -> Result<()> {
+    let response = client.post(url).json(report).send().await?;
+    if !response.status().is_success() {
+        bail!("it responded with {}", response.status());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        sync::{Arc, Mutex},
+    };
+
+    /// A request the listener was sent: its request line, its headers (names in lower case), and its body.
+    struct Request {
+        line: String,
+        headers: Vec<(String, String)>,
+        body: String,
+    }
+
+    impl Request {
+        fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, value)| value.as_str())
+        }
+    }
+
+    /// Listen on localhost, answering each request with the next of these statuses, e.g. "200 OK".
+    /// Returns the URL to notify, and the requests it's been sent.
+    fn listen(statuses: &[&'static str]) -> (String, Arc<Mutex<Vec<Request>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/runs", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = requests.clone();
+        let statuses = statuses.to_vec();
+        std::thread::spawn(move || {
+            for (stream, status) in listener.incoming().zip(statuses) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let mut headers = Vec::new();
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    let Some((name, value)) = header.trim_end().split_once(':') else {
+                        break;
+                    };
+                    headers.push((name.to_lowercase(), value.trim().to_owned()));
+                }
+                let length = headers
+                    .iter()
+                    .find(|(name, _)| name == "content-length")
+                    .map_or(0, |(_, value)| value.parse().unwrap());
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                seen.lock().unwrap().push(Request {
+                    line: line.trim_end().to_owned(),
+                    headers,
+                    body: String::from_utf8(body).unwrap(),
+                });
+                write!(
+                    stream,
+                    "HTTP/1.1 {status}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                )
+                .unwrap();
+            }
+        });
+        (url, requests)
+    }
+
+    fn report() -> RunReport {
+        RunReport {
+            args: vec!["--output=model.png".to_owned(), "cube".to_owned()],
+            succeeded: false,
+            error: Some("The API closed the connection".to_owned()),
+            output: "model.png".to_owned(),
+            export: None,
+            connection_profile: Default::default(),
+            started_at: 1_700_000_000,
+            finished_at: 1_700_000_042,
+            run_id: uuid::Uuid::from_u128(7),
+            request_id: None,
+            server: None,
+            unrecognized_responses: 1,
+            session_retries: 2,
+            metrics_requests: 0,
+            coord_bytes_saved: 0,
+            response_ordering: Default::default(),
+            timings: Default::default(),
+            artifacts: Vec::new(),
+            exports: Vec::new(),
+            warnings: Vec::new(),
+            cache: None,
+        }
+    }
+
+    fn run<T>(future: impl std::future::Future<Output = T>) -> T {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn posts_the_report_as_json() {
+        let (url, requests) = listen(&["204 No Content"]);
+        run(notify(&url, &report(), Duration::from_secs(5)));
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        let request = &requests[0];
+        assert_eq!(request.line, "POST /runs HTTP/1.1");
+        assert_eq!(request.header("content-type"), Some("application/json"));
+        let body: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+        assert_eq!(
+            body["args"],
+            serde_json::json!(["--output=model.png", "cube"])
+        );
+        assert_eq!(body["succeeded"], false);
+        assert_eq!(body["error"], "The API closed the connection");
+        assert_eq!(body["output"], "model.png");
+        assert_eq!(body["export"], serde_json::Value::Null);
+        assert_eq!(body["connection_profile"], "snapshot-only");
+        assert_eq!(body["finished_at"], 1_700_000_042);
+        assert_eq!(body["run_id"], uuid::Uuid::from_u128(7).to_string());
+        assert_eq!(body["session_retries"], 2);
+        // Left out unless the run used the cache.
+        assert!(body.get("cache").is_none());
+    }
+
+    #[test]
+    fn tries_once_more_if_the_url_refuses_the_report() {
+        let (url, requests) = listen(&["503 Service Unavailable", "200 OK"]);
+        run(notify(&url, &report(), Duration::from_secs(5)));
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].body, requests[1].body);
+    }
+
+    #[test]
+    fn gives_up_after_trying_twice() {
+        let (url, requests) = listen(&["500 Internal Server Error"; 3]);
+        run(notify(&url, &report(), Duration::from_secs(5)));
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn fails_to_post_when_the_url_refuses_the_report() {
+        let (url, _) = listen(&["404 Not Found"]);
+        let client = reqwest::Client::new();
+        let e = run(post(&client, &url, &report())).unwrap_err();
+        assert_eq!(e.to_string(), "it responded with 404 Not Found");
+    }
+}
@@ -0,0 +1,85 @@
+use crate::{geometry, session::ModelingSession};
+use color_eyre::{eyre::bail, Result};
+use kittycad::types::{ModelingCmd, OkModelingCmdResponse, PathCommand, Point3D};
+use uuid::Uuid;
+
+/// Ask the API where the vertices of a path are, in order.
+/// For a closed path, the last vertex is the same as the first.
+pub async fn query_path_points(
+    session: &mut ModelingSession,
+    path_id: Uuid,
+) -> Result<Vec<Point3D>> {
+    // How many vertices does the API think the path has?
+    let cmd_id = session
+        .send(ModelingCmd::PathGetVertexUuids { path_id })
+        .await?;
+    let OkModelingCmdResponse::PathGetVertexUuids { data: vertices } =
+        session.await_response(cmd_id).await?
+    else {
+        bail!("Expected the API to respond to PathGetVertexUuids with a list of vertices");
+    };
+    say!(
+        "The API says path {path_id} has {} vertices",
+        vertices.vertex_ids.len()
+    );
+
+    // Each line in the path is a curve, with the same ID as the command which drew it.
+    let cmd_id = session.send(ModelingCmd::PathGetInfo { path_id }).await?;
+    let OkModelingCmdResponse::PathGetInfo { data: info } = session.await_response(cmd_id).await?
+    else {
+        bail!("Expected the API to respond to PathGetInfo with the path's segments");
+    };
+    let curve_ids: Vec<_> = info
+        .segments
+        .into_iter()
+        .filter(|segment| !matches!(segment.command, PathCommand::MoveTo))
+        .filter_map(|segment| segment.command_id)
+        .collect();
+
+    // Ask for every curve's end points at once, then read the responses.
+    let mut cmd_ids = Vec::with_capacity(curve_ids.len());
+    for curve_id in curve_ids {
+        cmd_ids.push(
+            session
+                .send(ModelingCmd::CurveGetEndPoints { curve_id })
+                .await?,
+        );
+    }
+    let mut points = Vec::with_capacity(cmd_ids.len() + 1);
+    for cmd_id in cmd_ids {
+        let OkModelingCmdResponse::CurveGetEndPoints { data } =
+            session.await_response(cmd_id).await?
+        else {
+            bail!("Expected the API to respond to CurveGetEndPoints with two points");
+        };
+        // Each curve starts where the previous one ended, so only the first curve's start is new.
+        if points.is_empty() {
+            points.push(data.start);
+        }
+        points.push(data.end);
+    }
+    Ok(points)
+}
+
+/// Compare the points we drew with the points the API reported.
+/// Returns a description of every mismatch, so an empty list means they all matched.
+pub fn compare_points(expected: &[Point3D], actual: &[Point3D], epsilon: f64) -> Vec<String> {
+    let mut mismatches = Vec::new();
+    if expected.len() != actual.len() {
+        mismatches.push(format!(
+            "expected {} vertices, but the API reported {}",
+            expected.len(),
+            actual.len()
+        ));
+    }
+    for (i, (e, a)) in expected.iter().zip(actual).enumerate() {
+        let distance = geometry::distance(e, a);
+        if distance > epsilon {
+            mismatches.push(format!(
+                "vertex {i}: expected ({}, {}, {}), but the API reported ({}, {}, {}), {distance} away",
+                e.x, e.y, e.z, a.x, a.y, a.z
+            ));
+        }
+    }
+    mismatches
+}
@@ -0,0 +1,298 @@
+use crate::{
+    session::ModelingSession,
+    transform::{self, Transform},
+};
+use color_eyre::{eyre::bail, Result};
+use kittycad::types::Point3D;
+use uuid::Uuid;
+
+/// Repeat an object in a row, each copy `spacing` further along than the previous one.
+/// `count` includes the original, so a count of 1 leaves the object on its own.
+/// The spacing can point in any direction, including backwards along an axis.
+/// Returns the IDs of every object in the row, starting with the original.
+///
+/// The API (as of this version of the kittycad crate) has no pattern command,
+/// so each copy is built separately from the original's recipe.
+pub async fn pattern_linear(
+    session: &mut ModelingSession,
+    object_id: Uuid,
+    count: u32,
+    spacing: &Point3D,
+) -> Result<Vec<Uuid>> {
+    if count == 0 {
+        bail!("A pattern needs at least one instance, including the original");
+    }
+    if count > 1 && spacing.x == 0.0 && spacing.y == 0.0 && spacing.z == 0.0 {
+        bail!("A pattern's spacing can't be zero, because every copy would be in the same place");
+    }
+    let mut ids = vec![object_id];
+    for i in 1..count {
+        let n = f64::from(i);
+        let step = Transform {
+            translate: Point3D {
+                x: spacing.x * n,
+                y: spacing.y * n,
+                z: spacing.z * n,
+            },
+            ..Default::default()
+        };
+        ids.push(transform::copy_transformed(session, object_id, &step).await?);
+    }
+    Ok(ids)
+}
+
+/// Repeat an object `count` times, evenly spaced around an axis, like the holes in a bolt circle.
+/// The axis runs through `center`, in the direction `axis`, which must be along Z
+/// (our profiles are flat on the XY plane, so they can only be rotated about Z).
+/// `count` includes the original, which stays where it is.
+/// Returns the IDs of every object in the circle, starting with the original and going counter-clockwise.
+///
+/// Like `pattern_linear`, the copies are built separately from the original's recipe.
+pub async fn pattern_circular(
+    session: &mut ModelingSession,
+    object_id: Uuid,
+    count: u32,
+    axis: &Point3D,
+    center: &Point3D,
+) -> Result<Vec<Uuid>> {
+    if count == 0 {
+        bail!("A pattern needs at least one instance, including the original");
+    }
+    if axis.z == 0.0 || axis.x != 0.0 || axis.y != 0.0 {
+        bail!(
+            "Can only pattern around the z axis, not ({}, {}, {})",
+            axis.x,
+            axis.y,
+            axis.z
+        );
+    }
+    // Going around a downwards axis goes clockwise, when seen from above.
+    let direction = axis.z.signum();
+    let mut ids = vec![object_id];
+    for i in 1..count {
+        // Work out each angle from its index, rather than adding up steps,
+        // so rounding errors don't build up around the circle.
+        let degrees = direction * 360.0 * f64::from(i) / f64::from(count);
+        ids.push(
+            transform::copy_transformed(session, object_id, &rotate_about(center, degrees)).await?,
+        );
+    }
+    Ok(ids)
+}
+
+/// Rotate about a vertical axis through `center`, rather than through the origin.
+fn rotate_about(center: &Point3D, degrees: f64) -> Transform {
+    let rotation = Transform {
+        rotate_z_degrees: degrees,
+        ..Default::default()
+    };
+    // Rotating about the origin moves the center, so move it back to where it was.
+    let moved = rotation.apply(center);
+    Transform {
+        translate: Point3D {
+            x: center.x - moved.x,
+            y: center.y - moved.y,
+            z: 0.0,
+        },
+        ..rotation
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recipe::Recipe;
+    use kittycad::types::PathSegment;
+    use serde_json::json;
+
+    fn point(x: f64, y: f64, z: f64) -> Point3D {
+        Point3D { x, y, z }
+    }
+
+    /// A session answering the commands which build `copies` extruded triangles, like `pin`.
+    fn session(test: &str, copies: usize) -> ModelingSession {
+        let build = [
+            "start_path",
+            "move_path_pen",
+            "extend_path",
+            "extend_path",
+            "close_path",
+            "extrude",
+        ];
+        let responses: Vec<_> = (0..copies)
+            .flat_map(|_| build.map(|cmd| (cmd, json!({ "type": "empty" }))))
+            .collect();
+        ModelingSession::canned(test, &responses)
+    }
+
+    /// A pin the session knows how to build again: a triangle at (1, 2, 0), extruded 3mm.
+    fn pin(session: &mut ModelingSession) -> Uuid {
+        let id = Uuid::new_v4();
+        let line = |x, y| PathSegment::Line {
+            end: point(x, y, 0.0),
+            relative: true,
+        };
+        let recipe = Recipe {
+            extrusion: Some(3.0),
+            ..Recipe::path(point(1.0, 2.0, 0.0), vec![line(1.0, 0.0), line(0.0, 1.0)])
+        };
+        session.record_recipe(id, recipe);
+        id
+    }
+
+    fn run<T>(future: impl std::future::Future<Output = T>) -> T {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    /// Where each object in a pattern starts.
+    fn starts(session: &ModelingSession, ids: &[Uuid]) -> Vec<(f64, f64, f64)> {
+        ids.iter()
+            .map(|id| {
+                let start = &session.recipe(*id).unwrap().start;
+                (start.x, start.y, start.z)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fails_on_a_count_of_zero() {
+        let mut session = session("pattern-zero", 0);
+        let pin = pin(&mut session);
+        let e = run(pattern_linear(&mut session, pin, 0, &point(5.0, 0.0, 0.0))).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "A pattern needs at least one instance, including the original"
+        );
+        let e = run(pattern_circular(
+            &mut session,
+            pin,
+            0,
+            &point(0.0, 0.0, 1.0),
+            &point(0.0, 0.0, 0.0),
+        ))
+        .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "A pattern needs at least one instance, including the original"
+        );
+    }
+
+    #[test]
+    fn leaves_the_original_on_its_own_for_a_count_of_one() {
+        // Nothing's sent, so nothing needs an answer, however it's spaced.
+        let mut session = session("pattern-one", 0);
+        let pin = pin(&mut session);
+        for spacing in [point(5.0, 0.0, 0.0), point(0.0, 0.0, 0.0)] {
+            let ids = run(pattern_linear(&mut session, pin, 1, &spacing)).unwrap();
+            assert_eq!(ids, [pin]);
+        }
+        assert_eq!(session.created(), [pin]);
+    }
+
+    #[test]
+    fn fails_on_a_spacing_of_zero() {
+        let mut session = session("pattern-no-spacing", 0);
+        let pin = pin(&mut session);
+        let e = run(pattern_linear(&mut session, pin, 2, &point(0.0, 0.0, 0.0))).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "A pattern's spacing can't be zero, because every copy would be in the same place"
+        );
+        assert_eq!(session.created(), [pin]);
+    }
+
+    #[test]
+    fn spaces_copies_along_the_spacing() {
+        let mut session = session("pattern-forwards", 3);
+        let pin = pin(&mut session);
+        let ids = run(pattern_linear(&mut session, pin, 4, &point(5.0, 0.0, 0.5))).unwrap();
+        assert_eq!(ids[0], pin);
+        assert_eq!(session.created(), ids);
+        assert_eq!(
+            starts(&session, &ids),
+            [
+                (1.0, 2.0, 0.0),
+                (6.0, 2.0, 0.5),
+                (11.0, 2.0, 1.0),
+                (16.0, 2.0, 1.5)
+            ]
+        );
+        // The copies are the same shape as the original.
+        for id in &ids {
+            let recipe = session.recipe(*id).unwrap();
+            assert_eq!(recipe.extrusion, Some(3.0));
+            assert_eq!(recipe.segments.len(), 2);
+        }
+    }
+
+    #[test]
+    fn goes_backwards_along_a_negative_spacing() {
+        let mut session = session("pattern-backwards", 2);
+        let pin = pin(&mut session);
+        let ids = run(pattern_linear(
+            &mut session,
+            pin,
+            3,
+            &point(-4.0, -1.5, 0.0),
+        ))
+        .unwrap();
+        assert_eq!(
+            starts(&session, &ids),
+            [(1.0, 2.0, 0.0), (-3.0, 0.5, 0.0), (-7.0, -1.0, 0.0)]
+        );
+    }
+
+    #[test]
+    fn fails_to_copy_what_it_does_not_know_how_to_build() {
+        let mut session = session("pattern-unknown", 0);
+        let imported = Uuid::new_v4();
+        let e = run(pattern_linear(
+            &mut session,
+            imported,
+            2,
+            &point(5.0, 0.0, 0.0),
+        ))
+        .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            format!("Can't transform {imported}, because we don't know how it was built")
+        );
+    }
+
+    #[test]
+    fn spaces_copies_evenly_around_the_axis() {
+        let mut session = session("pattern-circle", 3);
+        let pin = pin(&mut session);
+        let center = point(1.0, 0.0, 0.0);
+        let ids = run(pattern_circular(
+            &mut session,
+            pin,
+            4,
+            &point(0.0, 0.0, 1.0),
+            &center,
+        ))
+        .unwrap();
+        // (1, 2) is 2 above the center, so a quarter turn anticlockwise at a time goes left, down and right.
+        let starts: Vec<_> = starts(&session, &ids)
+            .into_iter()
+            .map(|(x, y, _)| ((x * 1e9).round() / 1e9, (y * 1e9).round() / 1e9))
+            .collect();
+        assert_eq!(starts, [(1.0, 2.0), (-1.0, 0.0), (1.0, -2.0), (3.0, 0.0)]);
+        let e = run(pattern_circular(
+            &mut session,
+            pin,
+            4,
+            &point(1.0, 0.0, 0.0),
+            &center,
+        ))
+        .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Can only pattern around the z axis, not (1, 0, 0)"
+        );
+    }
+}
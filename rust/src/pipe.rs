@@ -0,0 +1,189 @@
+use crate::{
+    build, camera, finish, names, raw, scene,
+    session::{ConnectionLost, ModelingSession},
+    sink,
+    snapshot::{self, Output},
+    Options,
+};
+use color_eyre::{eyre::WrapErr, Result};
+use futures::{channel::mpsc, StreamExt};
+use kittycad::types::ImageFormat;
+use std::{
+    io::{BufRead, IsTerminal, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use uuid::Uuid;
+
+/// How long the input must be quiet before a preview is taken, so a burst of commands
+/// (e.g. a script piped in) only triggers one.
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// How wide or tall previews may be, in pixels. The API can't take a smaller snapshot than the
+/// session's, so previews are scaled down here, to keep them quick to write and to reload.
+const PREVIEW_SIZE: u32 = 320;
+
+/// How to keep a preview of the scene up to date, while it's being built.
+pub struct Preview {
+    /// Where to save it. It's always replaced in one go, so a viewer watching it never sees half an image.
+    pub path: PathBuf,
+    /// How many changes to wait for between previews.
+    pub every: usize,
+}
+
+/// Decides when to take a preview, so a burst of changes only triggers one.
+struct Debounce {
+    every: usize,
+    /// How many changes have been made since the last preview.
+    pending: usize,
+}
+
+impl Debounce {
+    fn new(every: usize) -> Self {
+        Self { every, pending: 0 }
+    }
+
+    fn changed(&mut self) {
+        self.pending += 1;
+    }
+
+    /// Whether a preview is due, now the input has gone quiet.
+    fn due(&self) -> bool {
+        self.pending >= self.every.max(1)
+    }
+
+    fn taken(&mut self) {
+        self.pending = 0;
+    }
+}
+
+/// Build a scene one line of standard input at a time, until it ends, then save the output as usual.
+///
+/// Each line is a model written the same way as on the command line (e.g. "plate --hole-diameter 5"),
+/// split at whitespace, or a modeling command written as JSON, like the raw subcommand takes.
+/// Everything stays in the scene, so each line adds to what's there. Models are numbered from 1,
+/// and raw commands can refer to a model's first object as "${1}", "${2}", and so on
+/// (or to the second object of the first model, if it made several, as "${1_2}").
+/// A line which fails is printed, and the next one carries on from there.
+/// Blank lines, and lines starting with "#", are skipped.
+pub async fn run(
+    session: &mut ModelingSession,
+    preview: Option<Preview>,
+    output: &Output,
+    options: &Options,
+) -> Result<()> {
+    let interactive = std::io::stdin().is_terminal();
+    let mut lines = read_lines();
+    let mut debounce = Debounce::new(preview.as_ref().map_or(1, |p| p.every));
+    let mut object_ids = Vec::new();
+    let mut built = 0;
+    let mut line_number = 0;
+    loop {
+        if interactive {
+            eprint!("> ");
+            std::io::stderr().flush()?;
+        }
+        // Wait a moment for the next line, and take a preview if none comes.
+        let line = match &preview {
+            Some(preview) if debounce.due() => {
+                match tokio::time::timeout(DEBOUNCE, lines.next()).await {
+                    Ok(line) => line,
+                    Err(_) => {
+                        save_preview(session, &object_ids, &preview.path).await?;
+                        debounce.taken();
+                        lines.next().await
+                    }
+                }
+            }
+            _ => lines.next().await,
+        };
+        let Some(line) = line else {
+            break;
+        };
+        let line = line.wrap_err("Could not read standard input")?;
+        line_number += 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match step(session, line, output, options).await {
+            Ok(ids) => {
+                if !ids.is_empty() {
+                    built += 1;
+                    say!("#{built}: made {} object(s)", ids.len());
+                    names::register_objects(session, &built.to_string(), &ids)?;
+                }
+                object_ids.extend(ids);
+                debounce.changed();
+            }
+            Err(e) if ConnectionLost::caused(&e) => return Err(e),
+            Err(e) => eprintln!("Line {line_number} failed: {e:?}"),
+        }
+    }
+    if interactive {
+        eprintln!();
+    }
+
+    if let Some(preview) = &preview {
+        if debounce.pending > 0 {
+            save_preview(session, &object_ids, &preview.path).await?;
+        }
+    }
+    // Frame everything, not just what was there at the last preview.
+    session.forget_framing();
+    finish(session, &object_ids, output, options).await
+}
+
+/// Build one line's model, or send its raw command, and return the IDs of the objects it made.
+async fn step(
+    session: &mut ModelingSession,
+    line: &str,
+    output: &Output,
+    options: &Options,
+) -> Result<Vec<Uuid>> {
+    if line.starts_with('{') {
+        let cmd = raw::parse(line, session.names())?;
+        raw::send(session, cmd).await?;
+        // Whatever it did, it may have changed the picture.
+        return Ok(Vec::new());
+    }
+    let args: Vec<String> = line.split_whitespace().map(str::to_owned).collect();
+    let model = scene::parse_model(&args)?;
+    build(session, model, output, options.validation).await
+}
+
+/// Read standard input on its own thread, one line at a time, because reading it blocks.
+fn read_lines() -> mpsc::UnboundedReceiver<std::io::Result<String>> {
+    let (tx, rx) = mpsc::unbounded();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            if tx.unbounded_send(line).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+/// Frame everything built so far, and save a small snapshot of it.
+async fn save_preview(
+    session: &mut ModelingSession,
+    object_ids: &[Uuid],
+    path: &Path,
+) -> Result<()> {
+    session.forget_framing();
+    if let Some(bbox) = session.bounding_box(object_ids) {
+        camera::zoom_to_fit(session, &bbox).await?;
+    }
+    let snapshot = snapshot::capture_snapshot(session, ImageFormat::Png).await?;
+    let img = image::load_from_memory(&snapshot.contents)?;
+    let img = img.thumbnail(PREVIEW_SIZE, PREVIEW_SIZE);
+    let mut png = Vec::new();
+    img.write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .wrap_err("Could not encode the preview")?;
+    // It's replaced in one go, so whatever is showing it never reads half of it.
+    sink::write_atomically(path, &png)
+        .wrap_err_with(|| format!("Could not save the preview to {}", path.display()))?;
+    say!("Updated the preview at {}", path.display());
+    Ok(())
+}
@@ -0,0 +1,288 @@
+use color_eyre::{eyre::WrapErr, Result};
+use kittycad::types::{ModelingCmd, PathSegment, Point3D};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use uuid::Uuid;
+
+/// How many bytes rounding coordinates (see --coord-precision) saved, across every session in the run.
+static BYTES_SAVED: AtomicUsize = AtomicUsize::new(0);
+
+/// How many bytes the rounded commands took, across every session in the run.
+static BYTES_SENT: AtomicUsize = AtomicUsize::new(0);
+
+/// How many bytes rounding coordinates saved in this run, and how many the rounded commands still took.
+pub fn bytes_saved() -> (usize, usize) {
+    (
+        BYTES_SAVED.load(Ordering::Relaxed),
+        BYTES_SENT.load(Ordering::Relaxed),
+    )
+}
+
+/// Rounds the coordinates of every point in a session's commands to a number of decimal places,
+/// from --coord-precision, so the JSON sent is shorter, and reads 10 rather than 9.999999999999998.
+pub struct Quantizer {
+    places: u32,
+    /// Where each path's pen was last moved to, rounded, so a segment which ends back there still does.
+    starts: HashMap<Uuid, Point3D>,
+}
+
+impl Quantizer {
+    pub fn new(places: u32) -> Self {
+        Self {
+            places,
+            starts: HashMap::new(),
+        }
+    }
+
+    /// The command, with every point's coordinates rounded.
+    /// A segment which ended within rounding of where its path started ends exactly there,
+    /// so closed paths still close.
+    pub fn apply(&mut self, cmd: &ModelingCmd) -> Result<ModelingCmd> {
+        let mut value = serde_json::to_value(cmd)?;
+        let before = value.to_string().len();
+        round_points(&mut value, self.places);
+        let mut rounded: ModelingCmd = serde_json::from_value(value)
+            .wrap_err("Could not round the coordinates in a command")?;
+        match &mut rounded {
+            ModelingCmd::MovePathPen { path, to } => {
+                self.starts.insert(*path, to.clone());
+            }
+            ModelingCmd::ExtendPath {
+                path,
+                segment:
+                    PathSegment::Line {
+                        end,
+                        relative: false,
+                    }
+                    | PathSegment::Bezier {
+                        end,
+                        relative: false,
+                        ..
+                    },
+            } => {
+                let quantum = 10f64.powi(-(self.places as i32));
+                if let Some(start) = self.starts.get(path) {
+                    // Both are rounded, so they're a whole number of quanta apart, give or take
+                    // floating point error: within rounding is at most one.
+                    let close = |a: f64, b: f64| (a - b).abs() <= quantum * 1.5;
+                    if close(end.x, start.x) && close(end.y, start.y) && close(end.z, start.z) {
+                        *end = start.clone();
+                    }
+                }
+            }
+            _ => {}
+        }
+        let after = serde_json::to_string(&rounded)?.len();
+        BYTES_SAVED.fetch_add(before.saturating_sub(after), Ordering::Relaxed);
+        BYTES_SENT.fetch_add(after, Ordering::Relaxed);
+        Ok(rounded)
+    }
+}
+
+/// Round every point (anything with numbers for x, y and z, and nothing else) in a command's JSON.
+fn round_points(value: &mut Value, places: u32) {
+    match value {
+        Value::Object(object) => {
+            let is_point = object.len() == 3
+                && ["x", "y", "z"]
+                    .iter()
+                    .all(|axis| object.get(*axis).is_some_and(Value::is_f64));
+            if is_point {
+                for coordinate in object.values_mut() {
+                    if let Some(rounded) = coordinate
+                        .as_f64()
+                        .and_then(|c| serde_json::Number::from_f64(round(c, places)))
+                    {
+                        *coordinate = Value::Number(rounded);
+                    }
+                }
+                return;
+            }
+            for child in object.values_mut() {
+                round_points(child, places);
+            }
+        }
+        Value::Array(values) => {
+            for child in values {
+                round_points(child, places);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Round to a number of decimal places. A number too big to round that finely is left as it is,
+/// so rounding never turns a number into infinity or NaN, or moves it further than rounding would.
+pub fn round(value: f64, places: u32) -> f64 {
+    let scale = 10f64.powi(places as i32);
+    // Past 2^52, every f64 is a whole number, so there's nothing to round, but dividing by the
+    // scale again could still move it.
+    if (value * scale).abs() >= 2f64.powi(52) {
+        return value;
+    }
+    let rounded = (value * scale).round() / scale;
+    if rounded.is_finite() {
+        // Adding zero turns -0.0 into 0.0, so it's sent as 0.0.
+        rounded + 0.0
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jitter::SplitMix;
+
+    fn point(x: f64, y: f64, z: f64) -> Point3D {
+        Point3D { x, y, z }
+    }
+
+    fn line(path: Uuid, end: Point3D, relative: bool) -> ModelingCmd {
+        ModelingCmd::ExtendPath {
+            path,
+            segment: PathSegment::Line { end, relative },
+        }
+    }
+
+    /// Where a segment ends.
+    fn end(cmd: &ModelingCmd) -> &Point3D {
+        match cmd {
+            ModelingCmd::ExtendPath {
+                segment: PathSegment::Line { end, .. },
+                ..
+            } => end,
+            other => panic!("{other:?} isn't a line"),
+        }
+    }
+
+    #[test]
+    fn rounds_to_the_places_asked_for() {
+        assert_eq!(round(9.999999999999998, 6), 10.0);
+        assert_eq!(round(1.23456, 2), 1.23);
+        assert_eq!(round(-1.235001, 2), -1.24);
+        assert_eq!(round(7.5, 0), 8.0);
+        // Not -0.0, which would be sent as such.
+        assert!(round(-0.000001, 3).is_sign_positive());
+    }
+
+    #[test]
+    fn never_rounds_to_infinity_or_nan() {
+        assert_eq!(round(1e300, 15), 1e300);
+        assert_eq!(round(f64::MAX, 6), f64::MAX);
+        // Whole already, so it's left exactly as it is.
+        assert_eq!(round(6.055939600382877e206, 6), 6.055939600382877e206);
+        assert_eq!(round(2e15, 3), 2e15);
+        let mut random = SplitMix(1);
+        for _ in 0..10_000 {
+            let value =
+                10_f64.powf(random.between(-10.0, 308.0)) * if random.coin() { -1.0 } else { 1.0 };
+            let places = random.below(16) as u32;
+            let rounded = round(value, places);
+            assert!(
+                rounded.is_finite(),
+                "{value} to {places} places is {rounded}"
+            );
+            // No further than half a place, and what the nearest f64 to that is.
+            let furthest = 0.5 * 10_f64.powi(-(places as i32)) + 2.0 * value.abs() * f64::EPSILON;
+            assert!(
+                (rounded - value).abs() <= furthest,
+                "{value} to {places} places is {rounded}"
+            );
+        }
+    }
+
+    #[test]
+    fn rounds_every_point_in_a_command() {
+        let path = Uuid::from_u128(1);
+        let mut quantizer = Quantizer::new(3);
+        let cmd = ModelingCmd::ExtendPath {
+            path,
+            segment: PathSegment::Bezier {
+                control1: point(0.12345, 1.0, 2.0),
+                control2: point(3.0, 4.00049, 5.0),
+                end: point(6.0, 7.0, 8.9999),
+                relative: false,
+            },
+        };
+        let ModelingCmd::ExtendPath {
+            segment:
+                PathSegment::Bezier {
+                    control1,
+                    control2,
+                    end,
+                    ..
+                },
+            ..
+        } = quantizer.apply(&cmd).unwrap()
+        else {
+            panic!("The command changed kind");
+        };
+        assert_eq!(control1, point(0.123, 1.0, 2.0));
+        assert_eq!(control2, point(3.0, 4.0, 5.0));
+        assert_eq!(end, point(6.0, 7.0, 9.0));
+    }
+
+    #[test]
+    fn keeps_closed_paths_closed() {
+        let mut random = SplitMix(2);
+        for i in 0..1000 {
+            let path = Uuid::from_u128(i);
+            let places = random.below(7) as u32;
+            let mut quantizer = Quantizer::new(places);
+            let mut v = || random.between(-1000.0, 1000.0);
+            let start = point(v(), v(), v());
+            let moved = quantizer
+                .apply(&ModelingCmd::MovePathPen {
+                    path,
+                    to: start.clone(),
+                })
+                .unwrap();
+            let ModelingCmd::MovePathPen { to, .. } = moved else {
+                panic!("The command changed kind");
+            };
+            for _ in 0..3 {
+                quantizer
+                    .apply(&line(path, point(v(), v(), v()), false))
+                    .unwrap();
+            }
+            // Back to the start, give or take rounding in how it was worked out.
+            let quantum = 10_f64.powi(-(places as i32));
+            let mut nudge = || random.between(-0.4, 0.4) * quantum;
+            let back = point(start.x + nudge(), start.y + nudge(), start.z + nudge());
+            let closed = quantizer.apply(&line(path, back, false)).unwrap();
+            assert_eq!(end(&closed), &to, "{places} places");
+            assert!([to.x, to.y, to.z].iter().all(|c| c.is_finite()));
+        }
+    }
+
+    #[test]
+    fn only_snaps_segments_which_end_at_their_paths_start() {
+        let (path, other) = (Uuid::from_u128(1), Uuid::from_u128(2));
+        let mut quantizer = Quantizer::new(2);
+        quantizer
+            .apply(&ModelingCmd::MovePathPen {
+                path,
+                to: point(1.0, 1.0, 0.0),
+            })
+            .unwrap();
+        // Further than a hundredth away.
+        let near = quantizer
+            .apply(&line(path, point(1.02, 1.0, 0.0), false))
+            .unwrap();
+        assert_eq!(end(&near), &point(1.02, 1.0, 0.0));
+        // Relative to the pen, so not a point in the scene.
+        let relative = quantizer
+            .apply(&line(path, point(1.001, 1.0, 0.0), true))
+            .unwrap();
+        assert_eq!(end(&relative), &point(1.0, 1.0, 0.0));
+        // Another path's.
+        let elsewhere = quantizer
+            .apply(&line(other, point(1.004, 1.0, 0.0), false))
+            .unwrap();
+        assert_eq!(end(&elsewhere), &point(1.0, 1.0, 0.0));
+    }
+}
@@ -0,0 +1,220 @@
+use crate::snapshot::STDOUT;
+use color_eyre::{eyre::bail, Result};
+use std::{
+    collections::BTreeMap,
+    fs::OpenOptions,
+    path::{Path, PathBuf},
+};
+
+/// Something the run will save, to be checked before it connects.
+pub struct Planned {
+    /// What it is, e.g. "the snapshot", for saying what's wrong.
+    what: String,
+    path: PathBuf,
+    /// Whether the path is a directory the run saves files in, rather than a file.
+    is_dir: bool,
+}
+
+impl Planned {
+    pub fn file(what: impl Into<String>, path: impl Into<PathBuf>) -> Self {
+        Self {
+            what: what.into(),
+            path: path.into(),
+            is_dir: false,
+        }
+    }
+
+    pub fn dir(what: impl Into<String>, path: impl Into<PathBuf>) -> Self {
+        Self {
+            what: what.into(),
+            path: path.into(),
+            is_dir: true,
+        }
+    }
+
+    /// The directory the run will write in.
+    fn dir_path(&self) -> PathBuf {
+        if self.is_dir {
+            return self.path.clone();
+        }
+        match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_owned(),
+            _ => PathBuf::from("."),
+        }
+    }
+}
+
+/// Check everything the run will save can be saved, before it does any network work, so a long run
+/// doesn't fail at the very end because a directory can't be written to.
+///
+/// Each directory must exist, or be possible to make inside the nearest one which does, and a file
+/// must be possible to write in it (and remove again). Nothing is left behind: directories which
+/// don't exist yet are checked by writing in the one they'd be made in.
+/// Fails listing every problem, so they can all be fixed at once.
+pub fn check(planned: &[Planned]) -> Result<()> {
+    let mut problems = Vec::new();
+    // Each directory is only probed once, however many outputs go in it.
+    let mut dirs: BTreeMap<PathBuf, Vec<&Planned>> = BTreeMap::new();
+    for planned in planned {
+        if planned.path.as_os_str() == STDOUT {
+            continue;
+        }
+        if !planned.is_dir && planned.path.is_dir() {
+            problems.push(format!(
+                "{} ({}) is a directory, not a file",
+                planned.what,
+                planned.path.display()
+            ));
+            continue;
+        }
+        dirs.entry(planned.dir_path()).or_default().push(planned);
+    }
+    for (dir, planned) in dirs {
+        if let Err(problem) = check_dir(&dir) {
+            let whats: Vec<_> = planned
+                .iter()
+                .map(|p| format!("{} ({})", p.what, p.path.display()))
+                .collect();
+            problems.push(format!("{} can't be saved: {problem}", whats.join(", ")));
+        }
+    }
+    if !problems.is_empty() {
+        bail!(
+            "Not starting, because the run couldn't save everything it's asked to:\n  {}",
+            problems.join("\n  ")
+        );
+    }
+    Ok(())
+}
+
+/// Check a file can be written in a directory, or the nearest one above it which exists.
+fn check_dir(dir: &Path) -> std::result::Result<(), String> {
+    let mut existing = dir;
+    while !existing.exists() {
+        match existing.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => existing = parent,
+            // A relative path's first directory is made in the current one.
+            _ => {
+                existing = Path::new(".");
+                break;
+            }
+        }
+    }
+    if !existing.is_dir() {
+        return Err(format!("{} isn't a directory", existing.display()));
+    }
+    let probe = existing.join(format!(".preflight-{}", std::process::id()));
+    let written = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&probe)
+        .and_then(|_| std::fs::remove_file(&probe));
+    match written {
+        Ok(()) => Ok(()),
+        Err(e) if existing == dir => Err(format!("{} can't be written to: {e}", dir.display())),
+        Err(e) => Err(format!(
+            "{} doesn't exist, and can't be made in {}: {e}",
+            dir.display(),
+            existing.display()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// An empty directory of the test's own.
+    fn dir(test: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("kittycad-preflight-{test}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn error(e: color_eyre::Report) -> String {
+        e.chain()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>()
+            .join(": ")
+    }
+
+    #[test]
+    fn passes_when_everything_can_be_saved() {
+        let dir = dir("passes");
+        check(&[
+            Planned::file("the snapshot", dir.join("model.png")),
+            Planned::file("the export", dir.join("model.stl")),
+            Planned::dir("the gallery", dir.join("not/made/yet")),
+            Planned::file("the snapshot", STDOUT),
+        ])
+        .unwrap();
+        // Nothing's left behind: not the probe, nor the directories the gallery will be made in.
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn fails_when_a_file_would_be_saved_in_a_file() {
+        let dir = dir("file-in-file");
+        fs::write(dir.join("model"), "").unwrap();
+        let saved = dir.join("model/deeper/model.png");
+        let e = check(&[Planned::file("the snapshot", &saved)]).unwrap_err();
+        assert_eq!(
+            error(e),
+            format!(
+                "Not starting, because the run couldn't save everything it's asked to:\n  \
+                 the snapshot ({}) can't be saved: {} isn't a directory",
+                saved.display(),
+                dir.join("model").display()
+            )
+        );
+    }
+
+    #[test]
+    fn fails_when_a_file_would_be_saved_over_a_directory() {
+        let dir = dir("file-over-dir");
+        let e = check(&[Planned::file("the export", &dir)]).unwrap_err();
+        assert!(error(e).ends_with(&format!(
+            "the export ({}) is a directory, not a file",
+            dir.display()
+        )));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn lists_every_directory_which_cant_be_written_to() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = dir("read-only");
+        let read_only = dir.join("read-only");
+        fs::create_dir(&read_only).unwrap();
+        fs::set_permissions(&read_only, fs::Permissions::from_mode(0o555)).unwrap();
+        if fs::write(read_only.join("probe"), "").is_ok() {
+            // Running as root, which can write anywhere.
+            return;
+        }
+        let e = check(&[
+            Planned::file("the snapshot", read_only.join("model.png")),
+            Planned::file("the export", read_only.join("model.stl")),
+            Planned::dir("the gallery", read_only.join("gallery")),
+            Planned::file("the recording", dir.join("trace.jsonl")),
+        ])
+        .unwrap_err();
+        let e = error(e);
+        let lines: Vec<_> = e.lines().collect();
+        assert_eq!(lines.len(), 3, "{e}");
+        assert!(lines[1].starts_with(&format!(
+            "  the snapshot ({}), the export ({}) can't be saved: {} can't be written to: ",
+            read_only.join("model.png").display(),
+            read_only.join("model.stl").display(),
+            read_only.display()
+        )));
+        assert!(lines[2].starts_with(&format!(
+            "  the gallery ({gallery}) can't be saved: {gallery} doesn't exist, and can't be made in {}: ",
+            read_only.display(),
+            gallery = read_only.join("gallery").display()
+        )));
+        fs::set_permissions(&read_only, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+}
@@ -0,0 +1,236 @@
+use crate::{
+    properties,
+    session::{ConnectionLost, ModelingSession},
+    shapes,
+};
+use color_eyre::{
+    eyre::{bail, eyre},
+    Result,
+};
+use kittycad::types::{Point3D, UnitArea};
+use std::f64::consts::PI;
+use uuid::Uuid;
+
+/// A shape whose volume and surface area have a formula, to check the API's measurements against.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Primitive {
+    Box,
+    Cylinder,
+    Sphere,
+    Torus,
+    Cone,
+}
+
+/// Parse a primitive for --shape, failing for those which can't be built (see `Primitive::can_build`),
+/// so asking for one fails before connecting, instead of being skipped.
+pub fn parse(s: &str) -> Result<Primitive> {
+    let primitive = <Primitive as clap::ValueEnum>::from_str(s, true).map_err(|_| {
+        eyre!("{s:?} isn't a primitive. Use one of these: box, cylinder, sphere, torus, cone")
+    })?;
+    if !primitive.can_build() {
+        bail!(
+            "Unsupported primitive {s}: spheres, tori and cones need a revolve, which this version of \
+             the kittycad crate doesn't have, so only box and cylinder can be verified"
+        );
+    }
+    Ok(primitive)
+}
+
+impl Primitive {
+    /// Whether the API (as of this version of the kittycad crate) can build it. Spheres, tori and
+    /// cones need a revolve, which it doesn't have, so only their formulas are here, for when it does.
+    fn can_build(self) -> bool {
+        matches!(self, Self::Box | Self::Cylinder)
+    }
+
+    /// The shape's volume and surface area, in cubic and square scene units.
+    fn analytic(self) -> (f64, f64) {
+        match self {
+            Self::Box => {
+                let (x, y, z) = BOX;
+                (x * y * z, 2.0 * (x * y + y * z + z * x))
+            }
+            Self::Cylinder => {
+                let (r, h) = CYLINDER;
+                (PI * r * r * h, 2.0 * PI * r * (r + h))
+            }
+            Self::Sphere => (4.0 / 3.0 * PI * SPHERE.powi(3), 4.0 * PI * SPHERE.powi(2)),
+            Self::Torus => {
+                let (major, minor) = TORUS;
+                (
+                    2.0 * PI * PI * major * minor * minor,
+                    4.0 * PI * PI * major * minor,
+                )
+            }
+            Self::Cone => {
+                let (r, h) = CONE;
+                (PI * r * r * h / 3.0, PI * r * (r + r.hypot(h)))
+            }
+        }
+    }
+
+    /// Build the shape, and return its ID. Fails for shapes which can't be built (see `can_build`).
+    async fn build(self, session: &mut ModelingSession) -> Result<Uuid> {
+        let origin = Point3D {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        };
+        Ok(match self {
+            Self::Box => {
+                let (x, y, z) = BOX;
+                let corners =
+                    [(0.0, 0.0), (x, 0.0), (x, y), (0.0, y)].map(|(x, y)| Point3D { x, y, z: 0.0 });
+                shapes::extrude_polygon(session, &corners, z).await?
+            }
+            Self::Cylinder => {
+                let (r, h) = CYLINDER;
+                let path_id = shapes::sketch_exact_circle(session, &origin, r).await?;
+                shapes::extrude(session, path_id, h).await?;
+                path_id
+            }
+            Self::Sphere | Self::Torus | Self::Cone => bail!("Unsupported primitive {self:?}"),
+        })
+    }
+}
+
+/// The box's length, width and height.
+const BOX: (f64, f64, f64) = (10.0, 20.0, 30.0);
+/// The cylinder's radius and height.
+const CYLINDER: (f64, f64) = (10.0, 20.0);
+/// The sphere's radius.
+const SPHERE: f64 = 10.0;
+/// The torus's major radius (to the middle of its tube) and minor radius (of its tube).
+const TORUS: (f64, f64) = (20.0, 5.0);
+/// The cone's base radius and height.
+const CONE: (f64, f64) = (10.0, 20.0);
+
+/// How one primitive's measurements compared with its formulas.
+pub struct Comparison {
+    pub primitive: Primitive,
+    /// The expected and measured volume and surface area, or why they weren't measured.
+    pub measured: std::result::Result<Measured, String>,
+}
+
+/// A primitive's volume and surface area, each as (from its formula, as the API measured it).
+pub struct Measured {
+    pub volume: (f64, f64),
+    pub area: (f64, f64),
+}
+
+impl Measured {
+    /// How far the volume and area measurements are from the formulas, as fractions of the formulas.
+    fn errors(&self) -> (f64, f64) {
+        let error = |(expected, measured): (f64, f64)| (measured - expected).abs() / expected;
+        (error(self.volume), error(self.area))
+    }
+}
+
+/// Build each primitive, and measure its volume and surface area.
+/// Primitives whose measurements the API refuses are skipped.
+pub async fn compare(
+    session: &mut ModelingSession,
+    primitives: &[Primitive],
+) -> Result<Vec<Comparison>> {
+    let mut comparisons = Vec::new();
+    for &primitive in primitives {
+        let measured = match measure(session, primitive).await {
+            Ok(measured) => Ok(measured),
+            // A lost connection means nothing else can be measured either.
+            Err(e) if ConnectionLost::caused(&e) => return Err(e),
+            Err(e) => Err(format!("{e}: {}", e.root_cause())),
+        };
+        comparisons.push(Comparison {
+            primitive,
+            measured,
+        });
+    }
+    Ok(comparisons)
+}
+
+async fn measure(session: &mut ModelingSession, primitive: Primitive) -> Result<Measured> {
+    let object_id = primitive.build(session).await?;
+    let (volume, area) = primitive.analytic();
+    let measured_volume = properties::volume_in_scene_units(session, &[object_id]).await?;
+    // Our scene is measured in millimeters, so mm² are square scene units.
+    let measured_area = properties::surface_area(session, &[object_id], UnitArea::Mm2).await?;
+    Ok(Measured {
+        volume: (volume, measured_volume),
+        area: (area, measured_area),
+    })
+}
+
+/// Print a table of the comparisons, and fail if any measurement is further than `tolerance`
+/// (as a fraction) from its formula. Skipped primitives don't fail.
+pub fn report(comparisons: &[Comparison], tolerance: f64) -> Result<()> {
+    println!(
+        "{:<9} {:>12} {:>12} {:>8} {:>12} {:>12} {:>8}",
+        "shape", "volume", "measured", "error", "area", "measured", "error"
+    );
+    let mut failed = Vec::new();
+    for Comparison {
+        primitive,
+        measured,
+    } in comparisons
+    {
+        let name = format!("{primitive:?}").to_lowercase();
+        let measured = match measured {
+            Ok(measured) => measured,
+            Err(reason) => {
+                println!("{name:<9} skipped: {reason}");
+                continue;
+            }
+        };
+        let (volume_error, area_error) = measured.errors();
+        println!(
+            "{name:<9} {:>12.3} {:>12.3} {:>7.3}% {:>12.3} {:>12.3} {:>7.3}%",
+            measured.volume.0,
+            measured.volume.1,
+            volume_error * 100.0,
+            measured.area.0,
+            measured.area.1,
+            area_error * 100.0
+        );
+        if volume_error > tolerance || area_error > tolerance {
+            failed.push(name);
+        }
+    }
+    if !failed.is_empty() {
+        bail!(
+            "The measurements of {} deviate from their formulas by more than the tolerance of {:.2}%",
+            failed.join(", "),
+            tolerance * 100.0
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_primitives_it_can_build() {
+        assert_eq!(parse("box").unwrap(), Primitive::Box);
+        assert_eq!(parse("Cylinder").unwrap(), Primitive::Cylinder);
+    }
+
+    #[test]
+    fn refuses_primitives_it_cant_build() {
+        for shape in ["sphere", "torus", "cone"] {
+            let e = parse(shape).unwrap_err().to_string();
+            assert!(
+                e.starts_with(&format!("Unsupported primitive {shape}: ")),
+                "{e}"
+            );
+        }
+    }
+
+    #[test]
+    fn refuses_shapes_which_arent_primitives() {
+        assert_eq!(
+            parse("pyramid").unwrap_err().to_string(),
+            "\"pyramid\" isn't a primitive. Use one of these: box, cylinder, sphere, torus, cone"
+        );
+    }
+}
@@ -0,0 +1,547 @@
+use crate::{
+    export,
+    names::NameRegistry,
+    raw,
+    session::{ConnectionLost, ModelingSession},
+    shapes, sink, svg, tessellation, transform,
+    transport::RecordingOutOfDate,
+    units,
+    warnings::{Code, Warning},
+    Model,
+};
+use color_eyre::{eyre::WrapErr, Result};
+use kittycad::types::{Angle, ModelingCmd, OutputFormat, PathSegment, Point3D, UnitAngle};
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use uuid::Uuid;
+
+/// A feature of the API which the crate uses, or would, if the kittycad crate could send it.
+/// Different deployments of the API support different ones, so `probe` finds out which.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Capability {
+    /// Paths with arcs in them, e.g. circles.
+    Arcs,
+    /// Paths with arcs which carry on from the segment before them.
+    TangentialArcs,
+    /// Paths with Bézier curves in them, e.g. outlines from SVG files.
+    Beziers,
+    /// Cutting one closed path out of another, e.g. a plate's hole.
+    Holes,
+    /// Adding, subtracting and intersecting solids.
+    Booleans,
+    /// Rounding a solid's edges.
+    Fillet,
+    /// Hollowing a solid out.
+    Shell,
+    /// Joining profiles into a solid.
+    Loft,
+    /// Sweeping a profile along a path.
+    Sweep,
+    ExportStl,
+    ExportObj,
+    ExportPly,
+    ExportStep,
+    /// Pointing the camera at a point, which every snapshot does.
+    CameraLookAt,
+    /// Moving the camera towards what it's looking at.
+    CameraZoom,
+}
+
+impl Capability {
+    pub const ALL: [Self; 15] = [
+        Self::Arcs,
+        Self::TangentialArcs,
+        Self::Beziers,
+        Self::Holes,
+        Self::Booleans,
+        Self::Fillet,
+        Self::Shell,
+        Self::Loft,
+        Self::Sweep,
+        Self::ExportStl,
+        Self::ExportObj,
+        Self::ExportPly,
+        Self::ExportStep,
+        Self::CameraLookAt,
+        Self::CameraZoom,
+    ];
+
+    /// Why it can't be probed, if it can't.
+    fn unsendable(self) -> Option<&'static str> {
+        match self {
+            Self::Booleans | Self::Fillet | Self::Shell | Self::Loft | Self::Sweep => {
+                Some("this version of the kittycad crate has no command for it")
+            }
+            _ => None,
+        }
+    }
+
+    /// The extension to export as, if it's an export format.
+    fn export_extension(self) -> Option<&'static str> {
+        match self {
+            Self::ExportStl => Some("stl"),
+            Self::ExportObj => Some("obj"),
+            Self::ExportPly => Some("ply"),
+            Self::ExportStep => Some("step"),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Capability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = serde_json::to_value(self).map_err(|_| std::fmt::Error)?;
+        f.write_str(name.as_str().unwrap_or_default())
+    }
+}
+
+/// Whether the API supports a capability, as far as its probe could tell.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+pub enum Support {
+    /// The API accepted every command in the probe.
+    Supported,
+    /// The API refused one of the probe's commands, for this reason.
+    Rejected { reason: String },
+    /// The probe wasn't sent, for this reason.
+    Untried { reason: String },
+}
+
+/// Which capabilities the API supports, from `probe`. It can be saved, and read back with
+/// --capabilities, to warn before a run that it needs something the API doesn't support.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Matrix {
+    /// When the API was probed, in seconds since the Unix epoch.
+    pub probed_at: u64,
+    pub capabilities: BTreeMap<Capability, Support>,
+}
+
+impl Matrix {
+    /// Collect each capability's support into a matrix, probed now.
+    pub fn assemble(supports: impl IntoIterator<Item = (Capability, Support)>) -> Self {
+        let probed_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        Self {
+            probed_at,
+            capabilities: supports.into_iter().collect(),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        sink::write_atomically(path, json.as_bytes())
+            .wrap_err_with(|| format!("Could not save the capabilities to {}", path.display()))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("Could not read capabilities file {}", path.display()))?;
+        serde_json::from_str(&text)
+            .wrap_err_with(|| format!("Could not parse capabilities file {}", path.display()))
+    }
+
+    /// Warn about each capability which is needed (by whatever's named alongside it), but which
+    /// the API didn't accept when this matrix, from `path`, was probed. Capabilities it doesn't
+    /// mention, e.g. because it was probed by an older version of the crate, aren't warned about.
+    pub fn check(&self, path: &Path, needs: &[(String, Capability)]) -> Result<()> {
+        let mut needed: BTreeMap<Capability, Vec<&str>> = BTreeMap::new();
+        for (needer, capability) in needs {
+            let needers = needed.entry(*capability).or_default();
+            if !needers.contains(&needer.as_str()) {
+                needers.push(needer);
+            }
+        }
+        for (capability, needers) in needed {
+            let why = match self.capabilities.get(&capability) {
+                None | Some(Support::Supported) => continue,
+                Some(Support::Rejected { reason }) => format!("the API rejected it ({reason})"),
+                Some(Support::Untried { reason }) => format!("it couldn't be tried ({reason})"),
+            };
+            let need = if needers.len() == 1 { "needs" } else { "need" };
+            Warning::new(
+                Code::Unsupported,
+                format!(
+                    "The API may not support {capability}, which {} {need}: when {} was probed, {why}",
+                    needers.join(", "),
+                    path.display()
+                ),
+            )
+            .parameter("--capabilities")
+            .raise()?;
+        }
+        Ok(())
+    }
+}
+
+/// Probe each capability, with a few cheap commands which change nothing, or which build something
+/// small and then remove it. Fails if the connection is lost, since nothing after that can be tried.
+pub async fn run(session: &mut ModelingSession) -> Result<Matrix> {
+    // The exports share one small cube, built when the first is probed.
+    let mut solid = None;
+    let mut supports = Vec::new();
+    for capability in Capability::ALL {
+        let support = probe(session, capability, &mut solid).await?;
+        supports.push((capability, support));
+    }
+    if let Some(Ok(solid)) = solid {
+        transform::remove_objects(session, &[solid]).await?;
+    }
+    Ok(Matrix::assemble(supports))
+}
+
+/// Probe one capability. `solid` is the cube exports are probed with, or why it couldn't be built.
+async fn probe(
+    session: &mut ModelingSession,
+    capability: Capability,
+    solid: &mut Option<std::result::Result<Uuid, String>>,
+) -> Result<Support> {
+    if let Some(reason) = capability.unsendable() {
+        return Ok(Support::Untried {
+            reason: reason.to_owned(),
+        });
+    }
+    let origin = Point3D {
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+    };
+    let point = |x, y| Point3D { x, y, z: 0.0 };
+    let tried = match capability {
+        Capability::Arcs => {
+            let path_id = shapes::sketch_exact_circle(session, &origin, 5.0).await;
+            remove(session, path_id).await
+        }
+        Capability::TangentialArcs => {
+            let segments = vec![
+                PathSegment::Line {
+                    end: point(10.0, 0.0),
+                    relative: false,
+                },
+                PathSegment::TangentialArc {
+                    offset: Angle {
+                        unit: UnitAngle::Degrees,
+                        value: 180.0,
+                    },
+                    radius: 5.0,
+                },
+            ];
+            let path_id = shapes::sketch_path(session, origin, segments).await;
+            remove(session, path_id).await
+        }
+        Capability::Beziers => {
+            let segments = vec![PathSegment::Bezier {
+                control1: point(5.0, 10.0),
+                control2: point(15.0, 10.0),
+                end: point(20.0, 0.0),
+                relative: false,
+            }];
+            let path_id = shapes::sketch_path(session, origin, segments).await;
+            remove(session, path_id).await
+        }
+        Capability::Holes => cut_hole(session, &origin).await,
+        Capability::CameraLookAt => {
+            let cmd = ModelingCmd::DefaultCameraLookAt {
+                center: origin,
+                up: Point3D {
+                    x: 0.0,
+                    y: 0.0,
+                    z: 1.0,
+                },
+                vantage: Point3D {
+                    x: 100.0,
+                    y: -100.0,
+                    z: 100.0,
+                },
+            };
+            send(session, cmd).await
+        }
+        // Moving no distance at all leaves the camera where it is.
+        Capability::CameraZoom => {
+            send(session, ModelingCmd::DefaultCameraZoom { magnitude: 0.0 }).await
+        }
+        Capability::Booleans
+        | Capability::Fillet
+        | Capability::Shell
+        | Capability::Loft
+        | Capability::Sweep => unreachable!("{capability} can't be sent, so it isn't probed"),
+        Capability::ExportStl
+        | Capability::ExportObj
+        | Capability::ExportPly
+        | Capability::ExportStep => {
+            let Some(extension) = capability.export_extension() else {
+                unreachable!("{capability} is an export format");
+            };
+            if solid.is_none() {
+                let corners = shapes::square_corners(&origin, 5.0);
+                *solid = Some(
+                    match shapes::extrude_polygon(session, &corners, 10.0).await {
+                        Ok(id) => Ok(id),
+                        Err(e) if says_nothing(&e) => return Err(e),
+                        Err(e) => Err(reason(&e)),
+                    },
+                );
+            }
+            match solid {
+                Some(Ok(solid)) => export_as(session, *solid, extension).await,
+                Some(Err(why)) => {
+                    return Ok(Support::Untried {
+                        reason: format!("couldn't build a cube to export: {why}"),
+                    })
+                }
+                None => unreachable!("the cube was just built"),
+            }
+        }
+    };
+    judge(tried)
+}
+
+/// What a probe's outcome says about its capability: it's supported if every command was accepted,
+/// and rejected if any was refused.
+fn judge(tried: Result<()>) -> Result<Support> {
+    match tried {
+        Ok(()) => Ok(Support::Supported),
+        Err(e) if says_nothing(&e) => Err(e),
+        Err(e) => Ok(Support::Rejected { reason: reason(&e) }),
+    }
+}
+
+/// Whether an error says nothing about what the API supports, so the probe fails instead:
+/// the connection was lost, or the run went its own way from the recording it's replaying.
+fn says_nothing(e: &color_eyre::Report) -> bool {
+    ConnectionLost::caused(e) || RecordingOutOfDate::caused(e)
+}
+
+/// Every cause of an error, e.g. the API's reason for refusing a command, on one line.
+fn reason(e: &color_eyre::Report) -> String {
+    let reasons: Vec<_> = e.chain().map(ToString::to_string).collect();
+    reasons.join(": ")
+}
+
+async fn send(session: &mut ModelingSession, cmd: ModelingCmd) -> Result<()> {
+    let cmd_id = session.send(cmd).await?;
+    session.await_response(cmd_id).await?;
+    Ok(())
+}
+
+/// Remove what a probe built, if it built it, keeping the probe's own error if it didn't.
+async fn remove(session: &mut ModelingSession, built: Result<Uuid>) -> Result<()> {
+    transform::remove_objects(session, &[built?]).await
+}
+
+/// Cut a small square out of a bigger one, then remove them both.
+async fn cut_hole(session: &mut ModelingSession, center: &Point3D) -> Result<()> {
+    let outer = shapes::sketch_polygon(session, &shapes::square_corners(center, 10.0)).await?;
+    let inner = match shapes::sketch_polygon(session, &shapes::square_corners(center, 5.0)).await {
+        Ok(inner) => inner,
+        Err(e) if says_nothing(&e) => return Err(e),
+        Err(e) => return remove(session, Ok(outer)).await.and(Err(e)),
+    };
+    let cut = shapes::add_hole(session, outer, inner).await;
+    if cut.as_ref().is_err_and(says_nothing) {
+        return cut;
+    }
+    let removed = transform::remove_objects(session, &[inner, outer]).await;
+    cut.and(removed)
+}
+
+/// Export the solid, without saving what the API sends back.
+async fn export_as(session: &mut ModelingSession, solid: Uuid, extension: &str) -> Result<()> {
+    let format = export::format_for(Path::new(extension), extension)?;
+    let cmd_id = session
+        .send(ModelingCmd::Export {
+            entity_ids: vec![solid],
+            format,
+            source_unit: units::SCENE_UNIT,
+        })
+        .await?;
+    session.await_export(cmd_id).await?;
+    Ok(())
+}
+
+/// Print which capabilities are supported, as a table, or as JSON.
+pub fn print(matrix: &Matrix, json: bool) -> Result<()> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(matrix)?);
+        return Ok(());
+    }
+    println!("{:<16} support", "capability");
+    for (capability, support) in &matrix.capabilities {
+        let capability = capability.to_string();
+        match support {
+            Support::Supported => println!("{capability:<16} supported"),
+            Support::Rejected { reason } => println!("{capability:<16} rejected: {reason}"),
+            Support::Untried { reason } => println!("{capability:<16} untried: {reason}"),
+        }
+    }
+    Ok(())
+}
+
+/// The capabilities a model needs to be built. Snapshots and exports need more, which the caller
+/// adds, since they depend on the run's options rather than on the model.
+pub fn needed_by(model: &Model) -> Vec<Capability> {
+    // With --tessellation, curves are drawn as straight lines instead.
+    let curves = tessellation::quality().is_none();
+    let mut needs = Vec::new();
+    match model {
+        Model::Plate { .. } | Model::BoltCircle { .. } => {
+            needs.push(Capability::Holes);
+            if curves {
+                needs.push(Capability::Arcs);
+            }
+        }
+        Model::LinearPattern { .. } | Model::Boss if curves => needs.push(Capability::Arcs),
+        Model::SvgExtrude { svg, flatten, .. }
+            if curves
+                && flatten.is_none()
+                && svg
+                    .pieces
+                    .iter()
+                    .any(|piece| matches!(piece, svg::Piece::Cubic(..))) =>
+        {
+            needs.push(Capability::Beziers)
+        }
+        _ => {}
+    }
+    needs
+}
+
+/// The capability a modeling command needs, if it's one which is probed.
+pub fn needed_by_cmd(cmd: &ModelingCmd) -> Option<Capability> {
+    match cmd {
+        ModelingCmd::ExtendPath { segment, .. } => match segment {
+            PathSegment::Line { .. } => None,
+            PathSegment::Arc { .. } => Some(Capability::Arcs),
+            PathSegment::Bezier { .. } => Some(Capability::Beziers),
+            PathSegment::TangentialArc { .. } | PathSegment::TangentialArcTo { .. } => {
+                Some(Capability::TangentialArcs)
+            }
+        },
+        ModelingCmd::Solid2DAddHole { .. } => Some(Capability::Holes),
+        ModelingCmd::Export { format, .. } => needed_to_export(format),
+        ModelingCmd::DefaultCameraLookAt { .. } => Some(Capability::CameraLookAt),
+        ModelingCmd::DefaultCameraZoom { .. } => Some(Capability::CameraZoom),
+        _ => None,
+    }
+}
+
+/// The capability a raw command needs, if it's one which is probed. Any "${name}" in it is
+/// taken to be some object, since which one doesn't matter.
+pub fn needed_by_raw(text: &str) -> Option<Capability> {
+    let mut names = NameRegistry::default();
+    for reference in raw::references(text) {
+        // A name used twice is already registered.
+        let _ = names.register(reference, Uuid::nil());
+    }
+    raw::parse(text, &names)
+        .ok()
+        .and_then(|cmd| needed_by_cmd(&cmd))
+}
+
+/// The capability exporting to this format needs, if it's one which is probed.
+pub fn needed_to_export(format: &OutputFormat) -> Option<Capability> {
+    match format {
+        OutputFormat::Stl { .. } => Some(Capability::ExportStl),
+        OutputFormat::Obj { .. } => Some(Capability::ExportObj),
+        OutputFormat::Ply { .. } => Some(Capability::ExportPly),
+        OutputFormat::Step { .. } => Some(Capability::ExportStep),
+        OutputFormat::Fbx { .. } | OutputFormat::Gltf { .. } => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::ConnectionProfile;
+
+    fn run<T>(future: impl std::future::Future<Output = T>) -> T {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    fn replaying(path: &Path) -> ModelingSession {
+        ModelingSession::replay(path, usize::MAX, ConnectionProfile::default()).unwrap()
+    }
+
+    /// probe.jsonl is a probe of an API which refuses tangential arcs, holes and STEP exports.
+    fn recording() -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/recordings/probe.jsonl")
+    }
+
+    /// Check a capability's support is `status`, e.g. "rejected", with `why` in its reason.
+    fn check(matrix: &Matrix, capability: Capability, status: &str, why: &str) {
+        let (actual, reason) = match &matrix.capabilities[&capability] {
+            Support::Supported => ("supported", ""),
+            Support::Rejected { reason } => ("rejected", reason.as_str()),
+            Support::Untried { reason } => ("untried", reason.as_str()),
+        };
+        assert_eq!(actual, status, "{capability}: {reason}");
+        assert!(reason.contains(why), "{capability}: {reason}");
+    }
+
+    #[test]
+    fn finds_which_capabilities_the_api_supports() {
+        let mut session = replaying(&recording());
+        let matrix = run(super::run(&mut session)).unwrap();
+        let unsendable = "this version of the kittycad crate has no command for it";
+        let expected = [
+            (Capability::Arcs, "supported", ""),
+            (
+                Capability::TangentialArcs,
+                "rejected",
+                "bad_request: Tangential arcs aren't supported",
+            ),
+            (Capability::Beziers, "supported", ""),
+            (
+                Capability::Holes,
+                "rejected",
+                "bad_request: A hole can't be cut in this",
+            ),
+            (Capability::Booleans, "untried", unsendable),
+            (Capability::Fillet, "untried", unsendable),
+            (Capability::Shell, "untried", unsendable),
+            (Capability::Loft, "untried", unsendable),
+            (Capability::Sweep, "untried", unsendable),
+            (Capability::ExportStl, "supported", ""),
+            (Capability::ExportObj, "supported", ""),
+            (Capability::ExportPly, "supported", ""),
+            (
+                Capability::ExportStep,
+                "rejected",
+                "bad_request: STEP export isn't supported",
+            ),
+            (Capability::CameraLookAt, "supported", ""),
+            (Capability::CameraZoom, "supported", ""),
+        ];
+        assert_eq!(matrix.capabilities.len(), expected.len());
+        for (capability, status, why) in expected {
+            check(&matrix, capability, status, why);
+        }
+    }
+
+    #[test]
+    fn fails_rather_than_judging_what_it_never_heard_back_about() {
+        // The API closes the connection partway through probing holes.
+        let text = std::fs::read_to_string(recording()).unwrap();
+        let lines: Vec<_> = text.lines().collect();
+        let path = std::env::temp_dir().join(format!(
+            "kittycad-probe-cut-short-{}.jsonl",
+            std::process::id()
+        ));
+        let closed =
+            r#"{"closed": {"code": 1001, "reason": "The server is restarting for a deploy"}}"#;
+        std::fs::write(&path, [&lines[..40], &[closed]].concat().join("\n")).unwrap();
+        let mut session = replaying(&path);
+        let e = run(super::run(&mut session)).unwrap_err();
+        assert!(ConnectionLost::caused(&e), "{e:#}");
+    }
+}
@@ -0,0 +1,387 @@
+use crate::coords;
+use color_eyre::{
+    eyre::{bail, eyre},
+    Result,
+};
+use kittycad::types::Point3D;
+
+/// A closed outline on the XY plane, read from a CSV file.
+#[derive(Clone, Debug)]
+pub struct Profile {
+    /// The file it was read from.
+    pub source: String,
+    /// The outline's corners, in order. The outline is closed from the last back to the first.
+    pub points: Vec<(f64, f64)>,
+}
+
+impl Profile {
+    /// Read a profile from a CSV file.
+    /// Each line is one point, as "x,y" or "x y". A header line is allowed, as are blank lines.
+    pub fn read(path: &str) -> Result<Self> {
+        // Argument errors only show the outermost message, so the cause goes in it.
+        let text =
+            std::fs::read_to_string(path).map_err(|e| eyre!("Could not read {path}: {e}"))?;
+        let points = parse_points(&text).map_err(|e| eyre!("{path}: {e}"))?;
+        Ok(Self {
+            source: path.to_owned(),
+            points,
+        })
+    }
+
+    /// The profile's corners, scaled, at height zero.
+    /// The file's points are on the ground of the run's input coordinate system, so they're turned to match the API's.
+    pub fn corners(&self, scale: f64) -> Vec<Point3D> {
+        let input = coords::input();
+        self.points
+            .iter()
+            .map(|&point| {
+                let (x, y) = input.ground_to_world(point);
+                Point3D {
+                    x: x * scale,
+                    y: y * scale,
+                    z: 0.0,
+                }
+            })
+            .collect()
+    }
+
+    /// The area inside the outline, after scaling.
+    pub fn area(&self, scale: f64) -> f64 {
+        // The shoelace formula.
+        let n = self.points.len();
+        let twice_area: f64 = (0..n)
+            .map(|i| {
+                let (x0, y0) = self.points[i];
+                let (x1, y1) = self.points[(i + 1) % n];
+                x0 * y1 - x1 * y0
+            })
+            .sum();
+        (twice_area / 2.0).abs() * scale * scale
+    }
+}
+
+/// An open path on the XY plane, read from a CSV file, e.g. the middle of the walls in a floor plan.
+#[derive(Clone, Debug)]
+pub struct Polyline {
+    /// The file it was read from.
+    pub source: String,
+    /// The path's points, in order. It isn't closed.
+    pub points: Vec<(f64, f64)>,
+}
+
+impl Polyline {
+    /// Read a path from a CSV file, written like a profile.
+    pub fn read(path: &str) -> Result<Self> {
+        // Argument errors only show the outermost message, so the cause goes in it.
+        let text =
+            std::fs::read_to_string(path).map_err(|e| eyre!("Could not read {path}: {e}"))?;
+        let points = read_points(&text).map_err(|e| eyre!("{path}: {e}"))?;
+        if points.len() < 2 {
+            bail!(
+                "{path}: A path needs at least 2 points, but only {} were found",
+                points.len()
+            );
+        }
+        Ok(Self {
+            source: path.to_owned(),
+            points,
+        })
+    }
+
+    /// The path's points, scaled, turned to match the API's coordinate system like a profile's corners.
+    pub fn points(&self, scale: f64) -> Vec<(f64, f64)> {
+        let input = coords::input();
+        self.points
+            .iter()
+            .map(|&point| {
+                let (x, y) = input.ground_to_world(point);
+                (x * scale, y * scale)
+            })
+            .collect()
+    }
+}
+
+/// Parse the points in a CSV profile. Errors say which line was wrong.
+fn parse_points(text: &str) -> Result<Vec<(f64, f64)>> {
+    let mut points = read_points(text)?;
+    // The path gets closed anyway, so an outline which repeats its first point doesn't need to.
+    if points.len() > 1 && points.first() == points.last() {
+        points.pop();
+    }
+    if points.len() < 3 {
+        bail!(
+            "A profile needs at least 3 points, but only {} were found",
+            points.len()
+        );
+    }
+    Ok(points)
+}
+
+/// Parse the points in a CSV file, one per line. Errors say which line was wrong.
+fn read_points(text: &str) -> Result<Vec<(f64, f64)>> {
+    let mut points = Vec::new();
+    let mut seen_line = false;
+    for (i, line) in text.lines().enumerate() {
+        let line_number = i + 1;
+        let fields: Vec<_> = line
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|field| !field.is_empty())
+            .collect();
+        if fields.is_empty() {
+            continue;
+        }
+        let is_first_line = !seen_line;
+        seen_line = true;
+        let numbers: Result<Vec<f64>, _> = fields.iter().map(|field| field.parse()).collect();
+        let numbers = match numbers {
+            Ok(numbers) => numbers,
+            // The first line can be a header, like "x,y".
+            Err(_) if is_first_line => continue,
+            Err(e) => bail!("Line {line_number}: {line:?} should be two numbers, x and y ({e})"),
+        };
+        let [x, y] = numbers[..] else {
+            bail!(
+                "Line {line_number}: expected two numbers, x and y, but found {}",
+                numbers.len()
+            );
+        };
+        if !x.is_finite() || !y.is_finite() {
+            bail!("Line {line_number}: {line:?} isn't a finite point");
+        }
+        points.push((x, y));
+    }
+    Ok(points)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jitter::SplitMix;
+
+    /// Write points as a CSV file might, with separators, a header and blank lines picked at random.
+    fn write(random: &mut SplitMix, points: &[(f64, f64)]) -> String {
+        let separators = [",", ", ", " ", "\t", " , "];
+        let mut text = String::new();
+        if random.coin() {
+            text.push_str("x,y\n");
+        }
+        for &(x, y) in points {
+            if random.next().is_multiple_of(4) {
+                text.push_str("  \n");
+            }
+            let separator = separators[random.below(separators.len())];
+            text.push_str(&format!("{x}{separator}{y}\n"));
+        }
+        if random.coin() {
+            text.pop();
+        }
+        text
+    }
+
+    fn random_points(random: &mut SplitMix, n: usize) -> Vec<(f64, f64)> {
+        (0..n)
+            .map(|_| {
+                let size = 10_f64.powf(random.between(-3.0, 6.0));
+                (random.between(-size, size), random.between(-size, size))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reads_back_the_points_it_was_given() {
+        let mut random = SplitMix(1);
+        for _ in 0..2000 {
+            let n = 3 + random.below(20);
+            let points = random_points(&mut random, n);
+            let mut written = points.clone();
+            // Closing the outline by repeating the first point makes no difference.
+            if random.coin() {
+                written.push(points[0]);
+            }
+            let text = write(&mut random, &written);
+            assert_eq!(parse_points(&text).unwrap(), points, "{text}");
+        }
+    }
+
+    #[test]
+    fn says_which_line_is_wrong() {
+        let mut random = SplitMix(2);
+        let bad = ["1", "1,2,3", "one,2", "1,inf", "NaN 2", "1;2"];
+        for _ in 0..2000 {
+            let n = 3 + random.below(10);
+            let points = random_points(&mut random, n);
+            let mut lines: Vec<String> = points.iter().map(|(x, y)| format!("{x},{y}")).collect();
+            // After the first line, which could be a header.
+            let at = 1 + random.below(lines.len());
+            let line = bad[random.below(bad.len())];
+            lines.insert(at, line.to_owned());
+            let e = parse_points(&lines.join("\n")).unwrap_err().to_string();
+            assert!(
+                e.starts_with(&format!("Line {}:", at + 1)),
+                "{line:?} on line {} gave: {e}",
+                at + 1
+            );
+        }
+    }
+
+    #[test]
+    fn never_panics_on_junk() {
+        let mut random = SplitMix(3);
+        let pieces = [
+            "1", "-2.5", "1e3", ",", " ", "\n", "x", "y", "nan", "inf", "é", "\r\n", "\t", "--",
+        ];
+        for _ in 0..5000 {
+            let text: String = (0..random.next() % 30)
+                .map(|_| pieces[random.below(pieces.len())])
+                .collect();
+            if let Ok(points) = parse_points(&text) {
+                assert!(points.len() >= 3);
+                assert!(points.iter().all(|(x, y)| x.is_finite() && y.is_finite()));
+            }
+        }
+    }
+
+    /// The message of a profile which doesn't parse.
+    fn error(text: &str) -> String {
+        parse_points(text).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn reads_commas_spaces_and_tabs() {
+        let expected = vec![(0.0, 0.0), (30.0, 0.0), (30.0, 10.0)];
+        for text in [
+            "0,0\n30,0\n30,10",
+            "0 0\n30 0\n30 10\n",
+            "0\t0\n30\t0\n30\t10",
+            "0, 0\r\n30 ,0\r\n  30,10  \r\n",
+            "\n\n0,0\n\n30,0\n   \n30,10\n\n",
+        ] {
+            assert_eq!(parse_points(text).unwrap(), expected, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn reads_numbers_as_rust_writes_them() {
+        assert_eq!(
+            parse_points("-1.5,+2\n1e3,-2.5E-1\n.5,7.").unwrap(),
+            [(-1.5, 2.0), (1000.0, -0.25), (0.5, 7.0)]
+        );
+    }
+
+    #[test]
+    fn skips_a_header_only_on_the_first_line() {
+        assert_eq!(
+            parse_points("x,y\n0,0\n1,0\n1,1").unwrap(),
+            [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0)]
+        );
+        // Blank lines before the header don't count.
+        assert_eq!(parse_points("\n  \nX Y\n0,0\n1,0\n1,1").unwrap().len(), 3);
+        assert_eq!(
+            error("0,0\nx,y\n1,0\n1,1"),
+            "Line 2: \"x,y\" should be two numbers, x and y (invalid float literal)"
+        );
+    }
+
+    #[test]
+    fn drops_a_last_point_which_closes_the_outline() {
+        assert_eq!(
+            parse_points("0,0\n1,0\n1,1\n0,0").unwrap(),
+            [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0)]
+        );
+        // Only if it's the same as the first.
+        assert_eq!(parse_points("0,0\n1,0\n1,1\n0,1").unwrap().len(), 4);
+    }
+
+    #[test]
+    fn needs_at_least_three_points() {
+        assert_eq!(
+            error(""),
+            "A profile needs at least 3 points, but only 0 were found"
+        );
+        assert_eq!(
+            error("x,y\n0,0\n1,0"),
+            "A profile needs at least 3 points, but only 2 were found"
+        );
+        // A triangle written closed is still a triangle, but a closed line isn't.
+        assert_eq!(parse_points("0,0\n1,0\n1,1\n0,0").unwrap().len(), 3);
+        assert_eq!(
+            error("0,0\n1,0\n0,0"),
+            "A profile needs at least 3 points, but only 2 were found"
+        );
+    }
+
+    #[test]
+    fn says_what_is_wrong_with_a_line() {
+        assert_eq!(
+            error("0,0\n1,0\n1"),
+            "Line 3: expected two numbers, x and y, but found 1"
+        );
+        assert_eq!(
+            error("0,0\n1,0\n1,1,1"),
+            "Line 3: expected two numbers, x and y, but found 3"
+        );
+        assert_eq!(
+            error("0,0\n\n1;0\n1,1"),
+            "Line 3: \"1;0\" should be two numbers, x and y (invalid float literal)"
+        );
+        assert_eq!(
+            error("0,0\n1,NaN\n1,1"),
+            "Line 2: \"1,NaN\" isn't a finite point"
+        );
+        assert_eq!(
+            error("0,0\n1,0\n-inf 1"),
+            "Line 3: \"-inf 1\" isn't a finite point"
+        );
+    }
+
+    #[test]
+    fn reads_the_sample_profile() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/samples/profile.csv");
+        let profile = Profile::read(path).unwrap();
+        assert_eq!(profile.source, path);
+        assert_eq!(profile.points.len(), 8);
+        assert_eq!(profile.points[0], (0.0, 0.0));
+        assert_eq!(profile.points[7], (0.0, 10.0));
+        // A T: a 30x10 bar with a 10x15 stem on top.
+        assert_eq!(profile.area(1.0), 450.0);
+        assert_eq!(profile.area(2.0), 1800.0);
+    }
+
+    #[test]
+    fn works_out_the_area_whichever_way_round_the_outline_goes() {
+        let square = |points: &[(f64, f64)]| Profile {
+            source: "square.csv".to_owned(),
+            points: points.to_vec(),
+        };
+        let anticlockwise = square(&[(0.0, 0.0), (4.0, 0.0), (4.0, 4.0), (0.0, 4.0)]);
+        let clockwise = square(&[(0.0, 0.0), (0.0, 4.0), (4.0, 4.0), (4.0, 0.0)]);
+        assert_eq!(anticlockwise.area(1.0), 16.0);
+        assert_eq!(clockwise.area(1.0), 16.0);
+        assert_eq!(clockwise.area(0.5), 4.0);
+    }
+
+    #[test]
+    fn says_which_file_is_wrong() {
+        let path =
+            std::env::temp_dir().join(format!("kittycad-profile-{}.csv", std::process::id()));
+        let path_str = path.display().to_string();
+        std::fs::write(&path, "x,y\n0,0\n1,zero\n").unwrap();
+        assert_eq!(
+            Profile::read(&path_str).unwrap_err().to_string(),
+            format!("{path_str}: Line 3: \"1,zero\" should be two numbers, x and y (invalid float literal)")
+        );
+        std::fs::write(&path, "0,0\n").unwrap();
+        assert_eq!(
+            Polyline::read(&path_str).unwrap_err().to_string(),
+            format!("{path_str}: A path needs at least 2 points, but only 1 were found")
+        );
+        // A path only needs two points, and isn't closed, so a repeated point stays.
+        std::fs::write(&path, "0,0\n5,0\n0,0\n").unwrap();
+        assert_eq!(Polyline::read(&path_str).unwrap().points.len(), 3);
+        let _ = std::fs::remove_file(&path);
+        assert!(Profile::read(&path_str)
+            .unwrap_err()
+            .to_string()
+            .starts_with(&format!("Could not read {path_str}: ")));
+    }
+}
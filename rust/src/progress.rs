@@ -0,0 +1,95 @@
+use crate::events::{Event, Phase, Receiver};
+use std::io::{IsTerminal, Write};
+
+/// Show how the run's going on stderr (for --progress), until it finishes.
+///
+/// On a terminal, one line is kept up to date. Otherwise, e.g. when stderr is a log file,
+/// a line is printed each time the run moves on to another phase, so the log isn't flooded.
+pub async fn show(mut events: Receiver) {
+    let terminal = std::io::stderr().is_terminal();
+    let mut status = Status::default();
+    while let Some(event) = events.recv().await {
+        let moved_on = status.update(&event);
+        let finished = matches!(event, Event::Finished(_));
+        let mut stderr = std::io::stderr().lock();
+        if terminal {
+            // Back to the start of the line, and clear whatever was left of the last one.
+            let _ = write!(stderr, "\r{status}\x1b[K");
+            if finished {
+                let _ = writeln!(stderr);
+            }
+            let _ = stderr.flush();
+        } else if moved_on || finished {
+            let _ = writeln!(stderr, "{status}");
+        }
+        if finished {
+            break;
+        }
+    }
+}
+
+/// What's shown: what the run's doing, and how far it's got.
+#[derive(Default)]
+struct Status {
+    phase: Option<Phase>,
+    sent: usize,
+    of: usize,
+    failed: usize,
+    warnings: usize,
+    saved: usize,
+    /// Whether the run succeeded, once it's finished.
+    succeeded: Option<bool>,
+}
+
+impl Status {
+    /// Take an event into account. Returns whether the run moved on to another phase.
+    fn update(&mut self, event: &Event) -> bool {
+        match event {
+            Event::Connected => {}
+            Event::CommandSent { n, of } => {
+                self.sent = *n;
+                self.of = *of;
+            }
+            Event::ResponseReceived { ok, .. } => {
+                if !ok {
+                    self.failed += 1;
+                }
+            }
+            Event::PhaseStarted(phase) => {
+                let moved_on = self.phase != Some(*phase);
+                self.phase = Some(*phase);
+                return moved_on;
+            }
+            Event::PhaseFinished { .. } => {}
+            Event::Warning(_) => self.warnings += 1,
+            Event::ArtifactWritten { .. } => self.saved += 1,
+            Event::Finished(report) => self.succeeded = Some(report.succeeded),
+        }
+        false
+    }
+}
+
+/// E.g. "[build] sent 12 of 50 commands, 1 failed, 2 warnings, saved 1 file".
+impl std::fmt::Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.succeeded, self.phase) {
+            (Some(true), _) => write!(f, "[done]")?,
+            (Some(false), _) => write!(f, "[failed]")?,
+            (None, Some(phase)) => write!(f, "[{}]", format!("{phase:?}").to_lowercase())?,
+            (None, None) => write!(f, "[starting]")?,
+        }
+        write!(f, " sent {} of {} commands", self.sent, self.of)?;
+        if self.failed > 0 {
+            write!(f, ", {} failed", self.failed)?;
+        }
+        if self.warnings > 0 {
+            let s = if self.warnings == 1 { "" } else { "s" };
+            write!(f, ", {} warning{s}", self.warnings)?;
+        }
+        if self.saved > 0 {
+            let s = if self.saved == 1 { "" } else { "s" };
+            write!(f, ", saved {} file{s}", self.saved)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,176 @@
+use crate::geometry::{cross, dot, normalize, sub, BoundingBox};
+use kittycad::types::Point3D;
+
+/// A perspective camera, as set up by `DefaultCameraLookAt`.
+#[derive(Clone, Debug)]
+pub struct Camera {
+    /// Where the camera stands.
+    pub vantage: Point3D,
+    /// The point it looks at, which ends up in the middle of the image.
+    pub center: Point3D,
+    /// Which way is up in the image.
+    pub up: Point3D,
+    /// How much of the scene the image shows, from its top edge to its bottom edge.
+    pub vertical_fov_degrees: f64,
+}
+
+/// Part of an image, as fractions of its width and height from its top-left corner.
+/// Parts which fall outside the image are less than 0 or more than 1.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Region {
+    pub left: f64,
+    pub top: f64,
+    pub right: f64,
+    pub bottom: f64,
+}
+
+impl Camera {
+    /// Where a point appears in an image `aspect` times as wide as it is tall, as fractions of
+    /// its width and height from its top-left corner. Returns None if the point is behind the camera.
+    pub fn project(&self, p: &Point3D, aspect: f64) -> Option<(f64, f64)> {
+        let forward = normalize(&sub(&self.center, &self.vantage));
+        let right = normalize(&cross(&forward, &self.up));
+        // The image's up, which is square to where the camera looks, unlike `self.up`.
+        let up = cross(&right, &forward);
+
+        let offset = sub(p, &self.vantage);
+        let depth = dot(&offset, &forward);
+        if depth <= 0.0 {
+            return None;
+        }
+        // At this depth, the image spans from -half_height to half_height vertically.
+        let half_height = depth * (self.vertical_fov_degrees.to_radians() / 2.0).tan();
+        let x = dot(&offset, &right) / (half_height * aspect);
+        let y = dot(&offset, &up) / half_height;
+        Some(((x + 1.0) / 2.0, (1.0 - y) / 2.0))
+    }
+
+    /// The part of the image a box appears in. Returns None if any of it is behind the camera.
+    pub fn screen_region(&self, bbox: &BoundingBox, aspect: f64) -> Option<Region> {
+        let (min, max) = (&bbox.min, &bbox.max);
+        let mut region: Option<Region> = None;
+        for x in [min.x, max.x] {
+            for y in [min.y, max.y] {
+                for z in [min.z, max.z] {
+                    let (across, down) = self.project(&Point3D { x, y, z }, aspect)?;
+                    region = Some(match region {
+                        None => Region {
+                            left: across,
+                            top: down,
+                            right: across,
+                            bottom: down,
+                        },
+                        Some(r) => Region {
+                            left: r.left.min(across),
+                            top: r.top.min(down),
+                            right: r.right.max(across),
+                            bottom: r.bottom.max(down),
+                        },
+                    });
+                }
+            }
+        }
+        region
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(x: f64, y: f64, z: f64) -> Point3D {
+        Point3D { x, y, z }
+    }
+
+    /// Ten units up the z axis, looking down at the origin with y up, seeing 90° from top to bottom,
+    /// so at depth d the image spans d either side of the middle.
+    fn camera() -> Camera {
+        Camera {
+            vantage: point(0.0, 0.0, 10.0),
+            center: point(0.0, 0.0, 0.0),
+            up: point(0.0, 1.0, 0.0),
+            vertical_fov_degrees: 90.0,
+        }
+    }
+
+    fn assert_near((across, down): (f64, f64), expected: (f64, f64)) {
+        assert!(
+            (across - expected.0).abs() < 1e-9 && (down - expected.1).abs() < 1e-9,
+            "Expected {expected:?}, got {:?}",
+            (across, down)
+        );
+    }
+
+    #[test]
+    fn projects_the_center_to_the_middle() {
+        assert_near(
+            camera().project(&point(0.0, 0.0, 0.0), 1.0).unwrap(),
+            (0.5, 0.5),
+        );
+    }
+
+    #[test]
+    fn projects_points_off_center() {
+        let camera = camera();
+        // Half way to the right edge, and half way to the top edge.
+        assert_near(
+            camera.project(&point(5.0, 0.0, 0.0), 1.0).unwrap(),
+            (0.75, 0.5),
+        );
+        assert_near(
+            camera.project(&point(0.0, 5.0, 0.0), 1.0).unwrap(),
+            (0.5, 0.25),
+        );
+        // An image twice as wide shows twice as much across.
+        assert_near(
+            camera.project(&point(5.0, 0.0, 0.0), 2.0).unwrap(),
+            (0.625, 0.5),
+        );
+        // Further away, it's nearer the middle.
+        assert_near(
+            camera.project(&point(5.0, 0.0, -10.0), 1.0).unwrap(),
+            (0.625, 0.5),
+        );
+        // Outside the image is outside 0..=1.
+        assert_near(
+            camera.project(&point(-20.0, 0.0, 0.0), 1.0).unwrap(),
+            (-0.5, 0.5),
+        );
+    }
+
+    #[test]
+    fn cant_project_points_behind_the_camera() {
+        assert_eq!(camera().project(&point(0.0, 0.0, 10.0), 1.0), None);
+        assert_eq!(camera().project(&point(1.0, 1.0, 20.0), 1.0), None);
+    }
+
+    #[test]
+    fn finds_where_a_box_appears() {
+        // The near face of a 10 unit cube is 5 units away, so it fills the image.
+        let bbox = BoundingBox {
+            min: point(-5.0, -5.0, -5.0),
+            max: point(5.0, 5.0, 5.0),
+        };
+        let region = camera().screen_region(&bbox, 1.0).unwrap();
+        assert_near((region.left, region.top), (0.0, 0.0));
+        assert_near((region.right, region.bottom), (1.0, 1.0));
+
+        // One in the top-right quarter, at the center's depth.
+        let bbox = BoundingBox {
+            min: point(0.0, 0.0, 0.0),
+            max: point(10.0, 10.0, 0.0),
+        };
+        let region = camera().screen_region(&bbox, 1.0).unwrap();
+        assert_near((region.left, region.top), (0.5, 0.0));
+        assert_near((region.right, region.bottom), (1.0, 0.5));
+    }
+
+    #[test]
+    fn cant_find_a_box_partly_behind_the_camera() {
+        let bbox = BoundingBox {
+            min: point(-1.0, -1.0, 0.0),
+            max: point(1.0, 1.0, 15.0),
+        };
+        assert_eq!(camera().screen_region(&bbox, 1.0), None);
+    }
+}
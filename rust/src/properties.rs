@@ -0,0 +1,357 @@
+use crate::{
+    entities::EntityTree,
+    geometry::{self, BoundingBox},
+    materials::Density,
+    measure,
+    recipe::Recipe,
+    session::ModelingSession,
+    units::{self, SCENE_UNIT},
+    warnings::{Code, Warning},
+};
+use color_eyre::{
+    eyre::{bail, eyre, WrapErr},
+    Result,
+};
+use kittycad::types::{
+    EntityType, Mass, ModelingCmd, OkModelingCmdResponse, Point3D, UnitArea, UnitMass, UnitVolume,
+    Volume,
+};
+use uuid::Uuid;
+
+/// Should the reported physical properties be checked against their expected values?
+/// If so, how far (as a fraction of the expected value) may they deviate?
+#[derive(Clone, Copy, Debug)]
+pub struct Validation {
+    pub tolerance: f64,
+}
+
+/// Ask the API for the combined volume of some objects, and print it next to the volume we expected.
+/// `expected` is measured in cubic scene units.
+pub async fn report_volume(
+    session: &mut ModelingSession,
+    object_ids: &[Uuid],
+    expected: f64,
+    validation: Option<Validation>,
+) -> Result<()> {
+    let data = volume(session, object_ids).await?;
+
+    // The API tells us which unit it measured the volume in, so convert our expectation to match.
+    let expected = units::volume_from_scene_units(expected, &data.output_unit);
+    let unit = data.output_unit;
+    say!(
+        "Volume: {} {unit} (expected {expected} {unit})",
+        data.volume
+    );
+    check("volume", data.volume, expected, validation)
+}
+
+/// Ask the API for the combined volume of some objects, in cm³.
+pub async fn volume(session: &mut ModelingSession, object_ids: &[Uuid]) -> Result<Volume> {
+    warn_if_uncapped(session, object_ids, "volume")?;
+    let cmd_id = session
+        .send(ModelingCmd::Volume {
+            entity_ids: object_ids.to_vec(),
+            output_unit: UnitVolume::Cm3,
+            source_unit: SCENE_UNIT,
+        })
+        .await?;
+    let OkModelingCmdResponse::Volume { data } = session.await_response(cmd_id).await? else {
+        bail!("Expected the API to respond to the Volume command with a volume");
+    };
+    Ok(data)
+}
+
+/// Ask the API for the combined volume of some objects, in cubic scene units.
+pub async fn volume_in_scene_units(
+    session: &mut ModelingSession,
+    object_ids: &[Uuid],
+) -> Result<f64> {
+    let data = volume(session, object_ids).await?;
+    Ok(data.volume / units::volume_from_scene_units(1.0, &data.output_unit))
+}
+
+/// Ask the API for the combined surface area of some objects, and print it next to the area we expected.
+/// `expected` is measured in square scene units.
+pub async fn report_surface_area(
+    session: &mut ModelingSession,
+    object_ids: &[Uuid],
+    expected: f64,
+    validation: Option<Validation>,
+) -> Result<()> {
+    // Our scene is measured in millimeters, so ask for the area in mm² first.
+    let area_mm2 = surface_area(session, object_ids, UnitArea::Mm2).await?;
+    say!("Surface area: {area_mm2} mm2 (expected {expected} mm2)");
+    check("surface area", area_mm2, expected, validation)?;
+
+    // The API can also convert the area into a different unit than the scene uses.
+    // It should agree with converting the mm² value ourselves.
+    let area_cm2 = surface_area(session, object_ids, UnitArea::Cm2).await?;
+    let converted = area_mm2 / units::square_mm_per_area(&UnitArea::Cm2);
+    say!("Surface area: {area_cm2} cm2 (converted from mm2: {converted} cm2)");
+    check("surface area in cm2", area_cm2, converted, validation)
+}
+
+/// Ask the API for the combined surface area of some objects, in the given unit.
+pub async fn surface_area(
+    session: &mut ModelingSession,
+    object_ids: &[Uuid],
+    output_unit: UnitArea,
+) -> Result<f64> {
+    let cmd_id = session
+        .send(ModelingCmd::SurfaceArea {
+            entity_ids: object_ids.to_vec(),
+            output_unit: output_unit.clone(),
+            source_unit: SCENE_UNIT,
+        })
+        .await?;
+    let OkModelingCmdResponse::SurfaceArea { data } = session.await_response(cmd_id).await? else {
+        bail!("Expected the API to respond to the SurfaceArea command with an area");
+    };
+    if data.output_unit != output_unit {
+        bail!(
+            "Asked the API for a surface area in {output_unit}, but it responded in {}",
+            data.output_unit
+        );
+    }
+    Ok(data.surface_area)
+}
+
+/// Ask the API for the center of mass of an object, and print it next to the point we expected.
+/// Returns the center of mass, measured in scene units.
+/// `size` is roughly how big the object is; validation compares the error to it.
+pub async fn report_center_of_mass(
+    session: &mut ModelingSession,
+    object_id: Uuid,
+    expected: &Point3D,
+    size: f64,
+    validation: Option<Validation>,
+) -> Result<Point3D> {
+    let c = center_of_mass(session, object_id).await?;
+    let unit = SCENE_UNIT;
+    say!(
+        "Center of mass: ({}, {}, {}) {unit} (expected ({}, {}, {}) {unit})",
+        c.x,
+        c.y,
+        c.z,
+        expected.x,
+        expected.y,
+        expected.z
+    );
+    let error = geometry::distance(&c, expected);
+    // A point can't be compared as a fraction of itself, so compare the error to the object's size.
+    if let Some(Validation { tolerance }) = validation {
+        if error > tolerance * size {
+            bail!(
+                "The measured center of mass is {error} {unit} from the expected point, more than {:.2}% of the object's size",
+                tolerance * 100.0
+            );
+        }
+    }
+    Ok(c)
+}
+
+/// Ask the API for the center of mass of an object, measured in scene units.
+pub async fn center_of_mass(session: &mut ModelingSession, object_id: Uuid) -> Result<Point3D> {
+    warn_if_uncapped(session, &[object_id], "center of mass")?;
+    let cmd_id = session
+        .send(ModelingCmd::CenterOfMass {
+            entity_ids: vec![object_id],
+            output_unit: SCENE_UNIT,
+            source_unit: SCENE_UNIT,
+        })
+        .await?;
+    // Only closed solids have a center of mass, so this is where open or flat geometry fails.
+    let resp = session.await_response(cmd_id).await.wrap_err_with(|| {
+        format!("Could not find the center of mass of {object_id}. Is it a closed solid?")
+    })?;
+    let OkModelingCmdResponse::CenterOfMass { data } = resp else {
+        bail!("Expected the API to respond to the CenterOfMass command with a point");
+    };
+    // We asked for scene units, but the API tells us which unit it used, so convert just in case.
+    let scale = units::mm_per_length(&data.output_unit) / units::mm_per_length(&SCENE_UNIT);
+    let c = data.center_of_mass;
+    Ok(Point3D {
+        x: c.x * scale,
+        y: c.y * scale,
+        z: c.z * scale,
+    })
+}
+
+/// Ask the API how heavy some objects would be if they were made of a material with the given density,
+/// and print a small report. Then ask the API to work backwards from that mass to the density,
+/// which should give us the density we started with.
+pub async fn report_mass(
+    session: &mut ModelingSession,
+    object_ids: &[Uuid],
+    density: &Density,
+    validation: Option<Validation>,
+) -> Result<()> {
+    let volume = volume(session, object_ids).await?;
+
+    let mass = mass_as_measured(session, object_ids, density).await?;
+    let grams = mass.mass * units::grams_per_mass(&mass.output_unit);
+    let pounds = grams / units::grams_per_mass(&UnitMass::Lb);
+
+    say!("Volume:  {} {}", volume.volume, volume.output_unit);
+    say!("Density: {density}");
+    say!("Mass:    {grams} g ({pounds} lb)");
+
+    let cmd_id = session
+        .send(ModelingCmd::Density {
+            entity_ids: object_ids.to_vec(),
+            material_mass: mass.mass,
+            material_mass_unit: mass.output_unit,
+            output_unit: density.unit.clone(),
+            source_unit: SCENE_UNIT,
+        })
+        .await?;
+    let OkModelingCmdResponse::Density { data } = session.await_response(cmd_id).await? else {
+        bail!("Expected the API to respond to the Density command with a density");
+    };
+    let expected = density.value * units::kg_m3_per_density(&density.unit)
+        / units::kg_m3_per_density(&data.output_unit);
+    say!(
+        "Density computed back from the mass: {} {}",
+        data.density,
+        data.output_unit
+    );
+    check("density", data.density, expected, validation)
+}
+
+/// Ask the API how heavy some objects would be if they were made of a material with the given density, in grams.
+pub async fn mass(
+    session: &mut ModelingSession,
+    object_ids: &[Uuid],
+    density: &Density,
+) -> Result<f64> {
+    let mass = mass_as_measured(session, object_ids, density).await?;
+    Ok(mass.mass * units::grams_per_mass(&mass.output_unit))
+}
+
+/// Ask the API for the mass, in whichever unit it measured it in.
+async fn mass_as_measured(
+    session: &mut ModelingSession,
+    object_ids: &[Uuid],
+    density: &Density,
+) -> Result<Mass> {
+    warn_if_uncapped(session, object_ids, "mass")?;
+    let cmd_id = session
+        .send(ModelingCmd::Mass {
+            entity_ids: object_ids.to_vec(),
+            material_density: density.value,
+            material_density_unit: density.unit.clone(),
+            output_unit: UnitMass::G,
+            source_unit: SCENE_UNIT,
+        })
+        .await?;
+    let OkModelingCmdResponse::Mass { data } = session.await_response(cmd_id).await? else {
+        bail!("Expected the API to respond to the Mass command with a mass");
+    };
+    Ok(data)
+}
+
+/// Warn about any of the objects which were extruded without caps. They aren't closed solids,
+/// so they have no inside, and so nothing to measure the `what` (e.g. volume) of.
+fn warn_if_uncapped(session: &ModelingSession, object_ids: &[Uuid], what: &str) -> Result<()> {
+    for &object_id in object_ids {
+        if !session
+            .recipe(object_id)
+            .is_some_and(|recipe| recipe.uncapped)
+        {
+            continue;
+        }
+        let warning = Warning::new(
+            Code::Uncapped,
+            format!(
+                "{object_id} was extruded without caps, so it isn't a closed solid, \
+                 and its {what} doesn't mean much"
+            ),
+        );
+        let warning = if session.caps_extrusions() {
+            warning
+        } else {
+            warning.parameter("--no-cap")
+        };
+        warning.raise()?;
+    }
+    Ok(())
+}
+
+/// Print the box containing some objects, and return it.
+/// Usually that's the box worked out from what was drawn (see `ModelingSession::bounding_box`),
+/// and it says so. If validation was requested, and the objects only have straight edges, it's
+/// measured instead, from the ends of their edges, and checked against `expected` or, if we don't
+/// know what the box should be, against what was drawn. A curved edge can bulge past its ends,
+/// so objects with curved edges can't be measured that way.
+pub async fn report_bounding_box(
+    session: &mut ModelingSession,
+    object_ids: &[Uuid],
+    expected: Option<&BoundingBox>,
+    validation: Option<Validation>,
+) -> Result<BoundingBox> {
+    let Some(drawn) = session.bounding_box(object_ids) else {
+        bail!("Don't know the bounding box of {object_ids:?}");
+    };
+    let measurable = validation.is_some()
+        && object_ids.iter().all(|&object_id| {
+            session
+                .recipe(object_id)
+                .is_some_and(Recipe::is_straight_edged)
+        });
+    let (bbox, how) = if measurable {
+        let measured = measure_bounding_box(session, object_ids).await?;
+        (measured, "measured from its edges")
+    } else {
+        (drawn.clone(), "as drawn, not measured")
+    };
+    let (min, max, e) = (&bbox.min, &bbox.max, bbox.extents());
+    let unit = SCENE_UNIT;
+    say!("Bounding box ({unit}, {how}):");
+    say!("  x: {} to {} (extent {})", min.x, max.x, e.x);
+    say!("  y: {} to {} (extent {})", min.y, max.y, e.y);
+    say!("  z: {} to {} (extent {})", min.z, max.z, e.z);
+    if let Some(expected) = expected.or(measurable.then_some(&drawn)) {
+        let expected_extents = expected.extents();
+        check("x extent", e.x, expected_extents.x, validation)?;
+        check("y extent", e.y, expected_extents.y, validation)?;
+        check("z extent", e.z, expected_extents.z, validation)?;
+    }
+    Ok(bbox)
+}
+
+/// Ask the API where the ends of the objects' edges are, and return the box containing them.
+/// It's only the box containing the objects if their edges are straight.
+pub async fn measure_bounding_box(
+    session: &mut ModelingSession,
+    object_ids: &[Uuid],
+) -> Result<BoundingBox> {
+    let mut ends = Vec::new();
+    for &object_id in object_ids {
+        let tree = EntityTree::query(session, object_id).await?;
+        let edge_ids: Vec<_> = tree.ids_of_type(&EntityType::Edge).into_iter().collect();
+        ends.extend(measure::edge_end_points(session, &edge_ids).await?);
+    }
+    BoundingBox::from_points(ends.iter().flat_map(|(start, end)| [start, end]))
+        .ok_or_else(|| eyre!("The API says {object_ids:?} have no edges to measure"))
+}
+
+/// If validation was requested, fail if the measured value is too far from the expected value.
+pub fn check(
+    property: &str,
+    measured: f64,
+    expected: f64,
+    validation: Option<Validation>,
+) -> Result<()> {
+    let Some(Validation { tolerance }) = validation else {
+        return Ok(());
+    };
+    let deviation = (measured - expected).abs() / expected.abs();
+    if deviation > tolerance {
+        bail!(
+            "The measured {property} ({measured}) deviates from the expected {property} ({expected}) by {:.2}%, more than the tolerance of {:.2}%",
+            deviation * 100.0,
+            tolerance * 100.0
+        );
+    }
+    Ok(())
+}
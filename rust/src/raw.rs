@@ -0,0 +1,183 @@
+use crate::{
+    names::{Handle, NameRegistry},
+    session::ModelingSession,
+    sidecar::KITTYCAD_VERSION,
+};
+use color_eyre::{
+    eyre::{bail, eyre, WrapErr},
+    Result,
+};
+use kittycad::types::{ModelingCmd, OkModelingCmdResponse};
+use uuid::Uuid;
+
+/// Parse a modeling command written as JSON, e.g. {"type": "default_camera_zoom", "magnitude": 2},
+/// after replacing each "${name}" with the ID of the object called that, e.g. a scene's part.
+/// An ID can be given as "${id}" too, to check it's written correctly.
+///
+/// Any command the kittycad crate knows can be sent this way, even ones no other subcommand uses yet.
+pub fn parse(text: &str, names: &NameRegistry) -> Result<ModelingCmd> {
+    let text = substitute(text, names)?;
+    let json: serde_json::Value =
+        serde_json::from_str(&text).wrap_err("The raw command isn't valid JSON")?;
+    let kind = json
+        .get("type")
+        .and_then(|kind| kind.as_str())
+        .ok_or_else(|| eyre!("The raw command needs a \"type\", e.g. \"default_camera_zoom\""))?
+        .to_owned();
+    serde_json::from_value(json).map_err(|e| {
+        if e.to_string().contains("unknown variant") {
+            eyre!("{kind} isn't a command version {KITTYCAD_VERSION} of the kittycad crate knows: {e}")
+        } else {
+            eyre!("The raw {kind} command is invalid: {e}")
+        }
+    })
+}
+
+/// The names (or IDs) a raw command refers to as "${name}".
+pub fn references(text: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        let Some(len) = rest[start + 2..].find('}') else {
+            break;
+        };
+        names.push(&rest[start + 2..start + 2 + len]);
+        rest = &rest[start + 2 + len + 1..];
+    }
+    names
+}
+
+/// Replace each "${name}" with the ID it names.
+fn substitute(text: &str, names: &NameRegistry) -> Result<String> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        out.push_str(&rest[..start]);
+        let Some(len) = rest[start + 2..].find('}') else {
+            bail!("The \"${{\" in the raw command is never closed");
+        };
+        let name = &rest[start + 2..start + 2 + len];
+        let id = Handle::parse(name)
+            .and_then(|handle| names.resolve(&handle))
+            .wrap_err_with(|| format!("The raw command refers to ${{{name}}}"))?;
+        out.push_str(&id.to_string());
+        rest = &rest[start + 2 + len + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Send a command, wait for the API's response, and print it as JSON.
+/// Returns the command's ID, which is also the ID of anything it created.
+pub async fn send(session: &mut ModelingSession, cmd: ModelingCmd) -> Result<Uuid> {
+    let cmd_id = session.send(cmd).await?;
+    let response: OkModelingCmdResponse = session.await_response(cmd_id).await?;
+    say!("{}", serde_json::to_string_pretty(&response)?);
+    Ok(cmd_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names() -> NameRegistry {
+        let mut names = NameRegistry::default();
+        names.register("plate", Uuid::from_u128(1)).unwrap();
+        names
+    }
+
+    fn error(e: color_eyre::Report) -> String {
+        e.chain()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(": ")
+    }
+
+    #[test]
+    fn replaces_a_name_with_its_objects_id() {
+        let text = r#"{"type": "object_visible", "object_id": "${plate}", "hidden": true}"#;
+        assert_eq!(
+            substitute(text, &names()).unwrap(),
+            r#"{"type": "object_visible", "object_id": "00000000-0000-0000-0000-000000000001", "hidden": true}"#
+        );
+        let ModelingCmd::ObjectVisible { object_id, hidden } = parse(text, &names()).unwrap()
+        else {
+            panic!("Expected an object_visible command");
+        };
+        assert_eq!((object_id, hidden), (Uuid::from_u128(1), true));
+    }
+
+    #[test]
+    fn keeps_ids_given_as_ids() {
+        let id = Uuid::from_u128(7);
+        assert_eq!(
+            substitute(&format!("${{{id}}}"), &names()).unwrap(),
+            id.to_string()
+        );
+    }
+
+    #[test]
+    fn refuses_a_name_nothing_has() {
+        let e = error(substitute(r#"{"object_id": "${plates}"}"#, &names()).unwrap_err());
+        assert_eq!(
+            e,
+            "The raw command refers to ${plates}: Nothing is called plates. Did you mean plate?"
+        );
+    }
+
+    #[test]
+    fn leaves_dollars_and_braces_which_arent_references_alone() {
+        let text = r#"{"name": "$5 {each}", "id": "${plate}"}"#;
+        assert_eq!(
+            substitute(text, &names()).unwrap(),
+            r#"{"name": "$5 {each}", "id": "00000000-0000-0000-0000-000000000001"}"#
+        );
+        assert_eq!(references(text), ["plate"]);
+    }
+
+    #[test]
+    fn refuses_a_reference_which_is_never_closed() {
+        let e = error(substitute(r#""object_id": "${plate""#, &names()).unwrap_err());
+        assert_eq!(e, "The \"${\" in the raw command is never closed");
+    }
+
+    #[test]
+    fn refuses_invalid_json() {
+        let e = error(
+            parse(
+                r#"{"type": "default_camera_zoom", "magnitude": }"#,
+                &names(),
+            )
+            .unwrap_err(),
+        );
+        assert!(
+            e.starts_with("The raw command isn't valid JSON: expected value"),
+            "{e}"
+        );
+    }
+
+    #[test]
+    fn names_the_crates_version_for_a_command_it_doesnt_know() {
+        let e = error(parse(r#"{"type": "teleport_me"}"#, &names()).unwrap_err());
+        assert!(
+            e.starts_with(&format!(
+                "teleport_me isn't a command version {KITTYCAD_VERSION} of the kittycad crate knows: unknown variant `teleport_me`"
+            )),
+            "{e}"
+        );
+    }
+
+    #[test]
+    fn says_whats_wrong_with_a_command_it_knows() {
+        let e = error(parse(r#"{"type": "default_camera_zoom"}"#, &names()).unwrap_err());
+        assert_eq!(
+            e,
+            "The raw default_camera_zoom command is invalid: missing field `magnitude`"
+        );
+        let e = error(parse(r#"{"magnitude": 2}"#, &names()).unwrap_err());
+        assert_eq!(
+            e,
+            "The raw command needs a \"type\", e.g. \"default_camera_zoom\""
+        );
+    }
+}
@@ -0,0 +1,135 @@
+use crate::{
+    geometry::BoundingBox,
+    session::ModelingSession,
+    transform::{self, Transform},
+    verify::MeshStats,
+    warnings::{Code, Warning},
+};
+use color_eyre::{eyre::bail, Result};
+use kittycad::types::Point3D;
+use uuid::Uuid;
+
+/// Where --recenter moves a model to before it's exported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Recenter {
+    /// The middle of the model's bounding box goes to the origin.
+    Origin,
+    /// The model sits on the XY plane, centered over the origin, like on a 3D printer's bed.
+    Bed,
+}
+
+impl Recenter {
+    /// Where a box's reference point goes: its middle, or the middle of its bottom face for a bed.
+    fn reference(self, bbox: &BoundingBox) -> Point3D {
+        let center = bbox.center();
+        match self {
+            Self::Origin => center,
+            Self::Bed => Point3D {
+                z: bbox.min.z,
+                ..center
+            },
+        }
+    }
+
+    /// How far a model in this box is moved.
+    pub fn offset(self, bbox: &BoundingBox) -> Point3D {
+        let reference = self.reference(bbox);
+        Point3D {
+            x: -reference.x,
+            y: -reference.y,
+            z: -reference.z,
+        }
+    }
+
+    /// How far a box is from where it should be, along whichever axis is furthest out.
+    fn off_by(self, bbox: &BoundingBox) -> f64 {
+        let reference = self.reference(bbox);
+        reference
+            .x
+            .abs()
+            .max(reference.y.abs())
+            .max(reference.z.abs())
+    }
+}
+
+impl std::fmt::Display for Recenter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Origin => "centered on the origin",
+            Self::Bed => "sitting on the XY plane",
+        })
+    }
+}
+
+/// Move objects so that, together, they're where `how` says, and return their new IDs, in the same order.
+///
+/// Like other transforms, this rebuilds the objects from their recipes, so the objects
+/// (e.g. imported models) must have been built here.
+pub async fn move_objects(
+    session: &mut ModelingSession,
+    object_ids: &[Uuid],
+    how: Recenter,
+) -> Result<Vec<Uuid>> {
+    let transform = transform_for(session, object_ids, how)?;
+    let mut moved = Vec::new();
+    for &object_id in object_ids {
+        moved.push(transform::apply_transform(session, object_id, &transform).await?);
+    }
+    Ok(moved)
+}
+
+/// Build copies of objects moved so that, together, they're where `how` says, leaving the objects
+/// where they are. Returns the copies' IDs, or None if the objects are already there.
+pub async fn copy_objects(
+    session: &mut ModelingSession,
+    object_ids: &[Uuid],
+    how: Recenter,
+) -> Result<Option<Vec<Uuid>>> {
+    let transform = transform_for(session, object_ids, how)?;
+    let t = &transform.translate;
+    if t.x == 0.0 && t.y == 0.0 && t.z == 0.0 {
+        return Ok(None);
+    }
+    let mut copies = Vec::new();
+    for &object_id in object_ids {
+        copies.push(transform::copy_transformed(session, object_id, &transform).await?);
+    }
+    Ok(Some(copies))
+}
+
+/// The move which puts the objects where `how` says.
+fn transform_for(
+    session: &ModelingSession,
+    object_ids: &[Uuid],
+    how: Recenter,
+) -> Result<Transform> {
+    // The API (as of this version of the kittycad crate) can't measure an object's bounding box,
+    // so it's worked out from how the objects were built.
+    let Some(bbox) = session.bounding_box(object_ids) else {
+        bail!("Can't recenter the model, because we don't know how all of it was built");
+    };
+    Ok(Transform {
+        translate: how.offset(&bbox),
+        ..Transform::default()
+    })
+}
+
+/// Check an exported mesh ended up where `how` says, within a thousandth of its size (or a micron,
+/// whichever's bigger). If it didn't, that's warned about, or fails the export if `verify` is true.
+pub fn check_export(path: &str, mesh: &MeshStats, how: Recenter, verify: bool) -> Result<()> {
+    let Some(bbox) = &mesh.bounding_box else {
+        return Ok(());
+    };
+    let tolerance = (bbox.radius() * 2e-3).max(1e-3);
+    let off_by = how.off_by(bbox);
+    if off_by <= tolerance {
+        return Ok(());
+    }
+    let message = format!("{path} should be {how}, but it's {off_by:.4} out");
+    if verify {
+        bail!(message);
+    }
+    Warning::new(Code::OffCenter, message)
+        .parameter("recenter")
+        .raise()
+}
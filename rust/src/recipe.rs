@@ -0,0 +1,229 @@
+use crate::{
+    geometry::{self, BoundingBox},
+    transform::Transform,
+};
+use kittycad::types::{Angle, PathSegment, Point3D, UnitAngle};
+
+/// The commands that built a solid (or a sketch that hasn't been extruded yet),
+/// kept so that the same shape can be built again, e.g. somewhere else.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Recipe {
+    /// Where the path's pen started.
+    pub start: Point3D,
+    /// The segments drawn from there. The path was closed after the last one.
+    pub segments: Vec<PathSegment>,
+    /// Closed paths which were cut out of this one before it was extruded.
+    /// Holes are simple paths: they have no holes or extrusion of their own.
+    pub holes: Vec<Recipe>,
+    /// How far the path was extruded, if it was.
+    pub extrusion: Option<f64>,
+    /// Whether it was extruded without caps on its ends, so it's open, not a solid.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub uncapped: bool,
+    /// Which way the path was extruded, as a unit vector, if not straight up (+Z),
+    /// e.g. because it was sketched on an upright plane.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub normal: Option<Point3D>,
+}
+
+impl Recipe {
+    /// A closed path which hasn't been extruded.
+    pub fn path(start: Point3D, segments: Vec<PathSegment>) -> Self {
+        Self {
+            start,
+            segments,
+            holes: Vec::new(),
+            extrusion: None,
+            uncapped: false,
+            normal: None,
+        }
+    }
+
+    /// The box containing the shape.
+    /// This is worked out from the points we sent, because the API (as of this version of the
+    /// kittycad crate) has no command to measure it. Curves are assumed to stay within their
+    /// control points (or, for arcs, their whole circle), so the box may be a bit too big.
+    pub fn bounding_box(&self) -> BoundingBox {
+        let mut points = vec![self.start.clone()];
+        for segment in &self.segments {
+            let pen = points.last().unwrap().clone();
+            match segment {
+                PathSegment::Line { end, relative } => points.push(offset(&pen, end, *relative)),
+                PathSegment::Bezier {
+                    control1,
+                    control2,
+                    end,
+                    relative,
+                } => {
+                    points.push(offset(&pen, control1, *relative));
+                    points.push(offset(&pen, control2, *relative));
+                    points.push(offset(&pen, end, *relative));
+                }
+                PathSegment::Arc { center, radius, .. } => {
+                    for (dx, dy) in [(-1.0, -1.0), (1.0, 1.0)] {
+                        points.push(Point3D {
+                            x: center.x + dx * radius,
+                            y: center.y + dy * radius,
+                            z: pen.z,
+                        });
+                    }
+                }
+                PathSegment::TangentialArc { radius, .. } => {
+                    for (dx, dy) in [(-2.0, -2.0), (2.0, 2.0)] {
+                        points.push(Point3D {
+                            x: pen.x + dx * radius,
+                            y: pen.y + dy * radius,
+                            z: pen.z,
+                        });
+                    }
+                }
+                PathSegment::TangentialArcTo { to, .. } => points.push(to.clone()),
+            }
+        }
+        let path = BoundingBox::from_points(&points).expect("a path has a start");
+        match (self.extrusion, &self.normal) {
+            (Some(distance), None) => BoundingBox {
+                max: Point3D {
+                    z: path.max.z + distance,
+                    ..path.max
+                },
+                min: path.min,
+            },
+            // The solid spans from the path to a copy of it moved along the normal.
+            (Some(distance), Some(normal)) => {
+                let moved = geometry::scale(normal, distance);
+                let shift = |p: &Point3D| Point3D {
+                    x: p.x + moved.x,
+                    y: p.y + moved.y,
+                    z: p.z + moved.z,
+                };
+                path.union(&BoundingBox {
+                    min: shift(&path.min),
+                    max: shift(&path.max),
+                })
+            }
+            (None, _) => path,
+        }
+    }
+
+    /// Whether all the path's segments are straight lines, so each of the shape's edges stays
+    /// between its ends.
+    pub fn is_straight_edged(&self) -> bool {
+        self.segments
+            .iter()
+            .all(|segment| matches!(segment, PathSegment::Line { .. }))
+    }
+
+    /// The same shape, moved, rotated and scaled.
+    pub fn transformed(&self, transform: &Transform) -> Self {
+        Self {
+            start: transform.apply(&self.start),
+            segments: self
+                .segments
+                .iter()
+                .map(|segment| transform_segment(segment, transform))
+                .collect(),
+            holes: self
+                .holes
+                .iter()
+                .map(|hole| hole.transformed(transform))
+                .collect(),
+            extrusion: self.extrusion.map(|distance| distance * transform.scale),
+            uncapped: self.uncapped,
+            normal: self
+                .normal
+                .as_ref()
+                .map(|normal| geometry::normalize(&transform.apply_to_offset(normal))),
+        }
+    }
+}
+
+/// Where a segment's point is, given where the pen was.
+fn offset(pen: &Point3D, p: &Point3D, relative: bool) -> Point3D {
+    if relative {
+        Point3D {
+            x: pen.x + p.x,
+            y: pen.y + p.y,
+            z: pen.z + p.z,
+        }
+    } else {
+        p.clone()
+    }
+}
+
+fn transform_segment(segment: &PathSegment, transform: &Transform) -> PathSegment {
+    // Relative points are offsets from the pen, so they rotate and scale, but don't move.
+    let point = |p: &Point3D, relative: bool| {
+        if relative {
+            transform.apply_to_offset(p)
+        } else {
+            transform.apply(p)
+        }
+    };
+    let rotate = |angle: &Angle| Angle {
+        value: angle.value
+            + match angle.unit {
+                UnitAngle::Degrees => transform.rotate_z_degrees,
+                UnitAngle::Radians => transform.rotate_z_degrees.to_radians(),
+            },
+        unit: angle.unit.clone(),
+    };
+    match segment {
+        PathSegment::Line { end, relative } => PathSegment::Line {
+            end: point(end, *relative),
+            relative: *relative,
+        },
+        PathSegment::Bezier {
+            control1,
+            control2,
+            end,
+            relative,
+        } => PathSegment::Bezier {
+            control1: point(control1, *relative),
+            control2: point(control2, *relative),
+            end: point(end, *relative),
+            relative: *relative,
+        },
+        PathSegment::Arc {
+            angle_end,
+            angle_start,
+            center,
+            end,
+            radius,
+            relative,
+            start,
+        } => {
+            let center = point(
+                &Point3D {
+                    x: center.x,
+                    y: center.y,
+                    z: 0.0,
+                },
+                *relative,
+            );
+            PathSegment::Arc {
+                angle_end: angle_end + transform.rotate_z_degrees,
+                angle_start: angle_start + transform.rotate_z_degrees,
+                center: kittycad::types::Point2D {
+                    x: center.x,
+                    y: center.y,
+                },
+                end: end.as_ref().map(rotate),
+                radius: radius * transform.scale,
+                relative: *relative,
+                start: start.as_ref().map(rotate),
+            }
+        }
+        PathSegment::TangentialArc { offset, radius } => PathSegment::TangentialArc {
+            offset: offset.clone(),
+            radius: radius * transform.scale,
+        },
+        PathSegment::TangentialArcTo {
+            angle_snap_increment,
+            to,
+        } => PathSegment::TangentialArcTo {
+            angle_snap_increment: angle_snap_increment.clone(),
+            to: transform.apply(to),
+        },
+    }
+}
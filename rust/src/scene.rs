@@ -0,0 +1,995 @@
+use crate::{
+    build, camera, coords,
+    dimension::Annotation,
+    explode::{self, Explode},
+    expr, finish,
+    jitter::{self, Jitter, Jittered, Seed},
+    names::{self, Handle, NameRegistry},
+    probe, raw,
+    session::ModelingSession,
+    snapshot::Output,
+    template::Drawing,
+    tessellation, transform,
+    warnings::{Code, Warning},
+    Model, Options,
+};
+use clap::{CommandFactory, Parser};
+use color_eyre::{
+    eyre::{bail, eyre, WrapErr},
+    Result,
+};
+use kittycad::types::Point3D;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use uuid::Uuid;
+
+/// A scene file: several models, built one after another over the same connection.
+/// Every part's model has been parsed, so a typo in any part fails before anything is built.
+#[derive(Clone)]
+pub struct Scene {
+    /// The models to build, in order.
+    pub parts: Vec<ScenePart>,
+    pub separate_outputs: bool,
+    /// If set, a snapshot of the scene with its parts moved apart is saved too.
+    pub explode: Option<Explode>,
+    /// Dimensions drawn on the scene's snapshot.
+    pub annotations: Vec<Annotation>,
+    /// The scene file, and every file it includes, to watch for changes.
+    pub files: Vec<PathBuf>,
+    /// How finely to draw curves as lines, if the scene says (and the command line doesn't).
+    pub tessellation: Option<tessellation::Quality>,
+    /// Which variant of the scene this is, to read it again the same way.
+    pub seed: Seed,
+    /// How its parameters were jittered, if the scene jitters any.
+    pub jittered: Option<Jittered>,
+}
+
+/// One of a scene's models, parsed.
+#[derive(Clone)]
+pub struct ScenePart {
+    /// What to call the part's output.
+    pub name: String,
+    pub step: Step,
+    /// What to name the part's outputs after, with --output-template.
+    pub drawing: Drawing,
+    /// Whether the part is included in --export's file.
+    pub export: bool,
+    /// Where to build the part, if not where the run builds models (the origin, unless --at says otherwise).
+    pub at: Option<Point3D>,
+    /// Whether the part stays where it is when the scene is exploded.
+    pub fixed: bool,
+    pub group: Option<String>,
+    /// Whether the part is shown, unless its group is hidden.
+    pub visible: bool,
+    /// Whether the part is built even when it's hidden.
+    pub build: bool,
+}
+
+/// What a scene's part does.
+#[derive(Clone)]
+pub enum Step {
+    /// Build a model.
+    Model(Model),
+    /// Send a modeling command written as JSON, which may refer to earlier parts as "${name}".
+    /// The command was checked when the scene was read, but earlier parts' IDs are only known once
+    /// they're built, so it's parsed again then.
+    Raw(String),
+}
+
+/// A scene file, as written.
+///
+/// For example:
+/// ```json
+/// {
+///     "explode": { "axes": "xy", "factor": 1.5 },
+///     "tessellation": "medium",
+///     "annotations": [{ "from": "0,0,0", "to": "50,0,0" }],
+///     "include": ["parts/fasteners.json"],
+///     "parameters": { "size": 20, "hole": "size / 4" },
+///     "jitter": { "size": { "uniform": 2, "min": 19 } },
+///     "library": {
+///         "washer": { "model": ["plate", "--hole-diameter", "=bore"], "parameters": { "bore": 5 } }
+///     },
+///     "parts": [
+///         { "name": "cubes", "model": ["cube", "--count", "3"], "group": "stock" },
+///         { "name": "bracket", "model": ["l-bracket"], "export": false, "fixed": true },
+///         { "name": "plate", "model": ["plate", "--hole-diameter", "=hole * 2"], "at": "50,0,0" },
+///         { "name": "small", "use": "washer", "at": "0,50,0", "group": "fasteners" },
+///         { "name": "big", "use": "washer", "with": { "bore": "hole * 2" }, "at": "50,50,0", "group": "fasteners" },
+///         { "name": "jig", "model": ["cube"], "visible": false, "build": false },
+///         { "raw_cmd": { "type": "object_visible", "object_id": "${bracket}", "hidden": true } }
+///     ]
+/// }
+/// ```
+#[derive(serde::Deserialize)]
+struct SceneFile {
+    /// The models to build, in order. Files which are only included, not built, have none.
+    #[serde(default)]
+    parts: Vec<Part>,
+    /// Other scene files, whose library parts and parameters this one can use, relative to this one.
+    /// They're read in order, and then this file's own, so if two define the same name, the later one wins.
+    /// Included files can include others in turn, but not themselves.
+    #[serde(default)]
+    include: Vec<PathBuf>,
+    /// Parts defined once, with default parameters, which any number of the scene's parts can build.
+    #[serde(default)]
+    library: BTreeMap<String, LibraryPart>,
+    /// Numbers the parts' models can use. Each is a number, or arithmetic on other parameters
+    /// (+ - * / and parentheses, and the constant pi).
+    #[serde(default)]
+    parameters: BTreeMap<String, expr::Parameter>,
+    /// How much to vary parameters from one variant of the scene to the next, e.g. to build many slightly
+    /// different models with --count. Each is moved by a random amount, which only depends on --seed, so
+    /// the same seed always builds the same variants. Parameters worked out from a jittered one follow it.
+    #[serde(default)]
+    jitter: BTreeMap<String, Jitter>,
+    /// If true, each part is built in an empty scene, and saved to its own output,
+    /// named after the part. Otherwise, all the parts are built together and saved to one output.
+    #[serde(default)]
+    separate_outputs: bool,
+    /// If set, a snapshot of the scene with its parts moved apart along these axes, away from the middle of
+    /// the scene, is saved too, as "<output>-exploded.png". Then the parts are put back.
+    /// Needs the parts to be built together.
+    explode: Option<Explode>,
+    /// Dimensions to draw on the scene's snapshot, each between two points along an axis, or along an edge,
+    /// with its length written beside it. Needs the parts to be built together.
+    #[serde(default)]
+    annotations: Vec<Annotation>,
+    /// Draw curves as straight lines, at this quality, like --tessellation (which wins, if it's given):
+    /// "low", "medium", "high", or how far the lines may stray from the curve, e.g. "0.05mm".
+    tessellation: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct Part {
+    /// What to call the part's output, and its objects (e.g. for --export-only).
+    /// Defaults to the part's position in the list, starting at 1.
+    name: Option<String>,
+    /// The model to build, written the same way as on the command line, e.g. ["plate", "--hole-diameter", "5"].
+    /// Arguments starting with "=" are arithmetic on the scene's parameters, e.g. "=hole * 2".
+    model: Option<Vec<String>>,
+    /// Instead of a model, a modeling command to send, written as JSON the way the API takes it,
+    /// for commands which no subcommand sends yet. Anywhere in it, "${name}" is replaced with the ID
+    /// of an earlier part (its first object, for a model), or "${name_2}" with the second object of a
+    /// model which made several. The API's response is printed.
+    raw_cmd: Option<serde_json::Value>,
+    /// Instead of a model, the name of a part in the scene's library to build.
+    #[serde(rename = "use")]
+    library_part: Option<String>,
+    /// Parameters to use for this part only, instead of the library part's defaults or the scene's parameters.
+    #[serde(default)]
+    with: BTreeMap<String, expr::Parameter>,
+    /// Whether the part is included in --export's file. Set it to false for helper geometry.
+    #[serde(default = "included")]
+    export: bool,
+    /// Where to build the part, as "x,y,z", like --at. Its paths are moved before they're sent,
+    /// so the part is built in place.
+    at: Option<String>,
+    /// Whether the part stays where it is when the scene is exploded, e.g. a base plate.
+    #[serde(default)]
+    fixed: bool,
+    /// Which group the part is in, so --only-group and --hide-group can show or hide it with others, like a layer.
+    group: Option<String>,
+    /// Whether the part is shown. Hidden parts are still built, so their IDs are known, but they're hidden
+    /// from snapshots and left out of exports.
+    #[serde(default = "included")]
+    visible: bool,
+    /// Set it to false to skip building the part whenever it's hidden, e.g. to save time.
+    #[serde(default = "included")]
+    build: bool,
+}
+
+fn included() -> bool {
+    true
+}
+
+/// A part in a scene's library.
+#[derive(serde::Deserialize)]
+struct LibraryPart {
+    /// The model to build, written like a part's. Arguments starting with "=" can use the library part's
+    /// parameters, as well as the scene's.
+    model: Vec<String>,
+    /// Default values for the library part's own parameters, which each part using it can change with "with".
+    #[serde(default)]
+    parameters: BTreeMap<String, expr::Parameter>,
+}
+
+/// The library parts and parameters of a scene file, merged with those of every file it includes.
+#[derive(Default)]
+struct Definitions {
+    library: BTreeMap<String, LibraryPart>,
+    parameters: BTreeMap<String, expr::Parameter>,
+    jitter: BTreeMap<String, Jitter>,
+    /// Every file which was included, however indirectly.
+    included: Vec<PathBuf>,
+}
+
+impl Definitions {
+    /// Merge in a scene file's includes, and then its own definitions, so later definitions replace earlier ones.
+    /// `chain` is the files being read, starting with the scene file, to catch files which include themselves.
+    fn merge(&mut self, path: &Path, file: &mut SceneFile, chain: &mut Vec<PathBuf>) -> Result<()> {
+        let dir = path.parent().unwrap_or(Path::new(""));
+        for include in &file.include {
+            let included = dir.join(include);
+            let canonical = included.canonicalize().wrap_err_with(|| {
+                format!(
+                    "Could not find {}, included by {}",
+                    included.display(),
+                    path.display()
+                )
+            })?;
+            if chain.contains(&canonical) {
+                let files: Vec<_> = chain
+                    .iter()
+                    .chain([&canonical])
+                    .map(|p| p.display().to_string())
+                    .collect();
+                bail!(
+                    "Scene files include each other in a loop: {}",
+                    files.join(" includes ")
+                );
+            }
+            chain.push(canonical);
+            self.included.push(included.clone());
+            let merged = read_file(&included).and_then(|mut file| {
+                if !file.parts.is_empty() {
+                    bail!("Included files can only define library parts and parameters, not parts");
+                }
+                self.merge(&included, &mut file, chain)
+            });
+            chain.pop();
+            merged.wrap_err_with(|| {
+                format!("In {}, included by {}", included.display(), path.display())
+            })?;
+        }
+        self.library.append(&mut file.library);
+        self.parameters.append(&mut file.parameters);
+        self.jitter.append(&mut file.jitter);
+        Ok(())
+    }
+}
+
+fn read_file(path: &Path) -> Result<SceneFile> {
+    let text = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("Could not read scene file {}", path.display()))?;
+    serde_json::from_str(&text)
+        .wrap_err_with(|| format!("Could not parse scene file {}", path.display()))
+}
+
+/// Parses a part's model like the command line does, so parts get the same defaults and validation.
+#[derive(Parser)]
+#[command(no_binary_name = true)]
+struct PartArgs {
+    #[command(subcommand)]
+    model: Model,
+}
+
+/// Parse a model written the same way as on the command line, e.g. ["plate", "--hole-diameter", "5"].
+pub fn parse_model(args: &[String]) -> Result<Model> {
+    Ok(PartArgs::try_parse_from(args)?.model)
+}
+
+/// Describe a model written the same way as on the command line, for naming its outputs.
+/// `frame` is its position in a sweep, scene or gallery, starting at 1.
+pub fn describe(args: &[String], frame: usize) -> Result<Drawing> {
+    let matches = PartArgs::command().try_get_matches_from(args)?;
+    let (shape, options) = matches
+        .subcommand()
+        .ok_or_else(|| eyre!("{args:?} doesn't name a model"))?;
+    Ok(Drawing::new(shape, options, frame))
+}
+
+impl Scene {
+    /// Read a scene file, and jitter its parameters as it says for this variant of it.
+    pub fn read(path: &Path, seed: Seed) -> Result<Self> {
+        let mut file = read_file(path)?;
+        if file.parts.is_empty() {
+            bail!("Scene file {} has no parts", path.display());
+        }
+        if let Some(explode) = &file.explode {
+            if file.separate_outputs {
+                bail!(
+                    "Scene file {} can't explode parts which are built separately",
+                    path.display()
+                );
+            }
+            explode.check().wrap_err_with(|| {
+                format!("Scene file {} has an invalid \"explode\"", path.display())
+            })?;
+        }
+        if !file.annotations.is_empty() && file.separate_outputs {
+            bail!(
+                "Scene file {} can't annotate parts which are built separately",
+                path.display()
+            );
+        }
+        for (i, annotation) in file.annotations.iter().enumerate() {
+            annotation.check().wrap_err_with(|| {
+                format!(
+                    "Scene file {} has an invalid annotation (number {})",
+                    path.display(),
+                    i + 1
+                )
+            })?;
+        }
+        let tessellation = file
+            .tessellation
+            .as_deref()
+            .map(tessellation::Quality::parse)
+            .transpose()
+            .wrap_err_with(|| {
+                format!(
+                    "Scene file {} has an invalid \"tessellation\"",
+                    path.display()
+                )
+            })?;
+        let mut definitions = Definitions::default();
+        let canonical = path
+            .canonicalize()
+            .wrap_err_with(|| format!("Could not find scene file {}", path.display()))?;
+        definitions.merge(path, &mut file, &mut vec![canonical])?;
+        // Check them even if no part uses them, so typos show up.
+        let values = expr::resolve(&definitions.parameters)
+            .wrap_err_with(|| format!("Scene file {} has invalid parameters", path.display()))?;
+        let jittered = if definitions.jitter.is_empty() {
+            None
+        } else {
+            let parameters =
+                jitter::apply(&definitions.jitter, &values, seed).wrap_err_with(|| {
+                    format!("Scene file {} has an invalid \"jitter\"", path.display())
+                })?;
+            for (name, &value) in &parameters {
+                definitions
+                    .parameters
+                    .insert(name.clone(), expr::Parameter::Number(value));
+            }
+            Some(Jittered {
+                seed: seed.seed,
+                variant: seed.variant,
+                parameters,
+            })
+        };
+        // Raw commands may only refer to the parts before them.
+        let mut earlier = NameRegistry::default();
+        // Models' objects are called "name_1", "name_2" and so on too, if there's more than one,
+        // but how many there'll be isn't known until they're built.
+        let mut earlier_models = BTreeSet::new();
+        let parts = file
+            .parts
+            .into_iter()
+            .enumerate()
+            .map(|(i, part)| {
+                let name = part.name.unwrap_or_else(|| (i + 1).to_string());
+                if earlier.ids().contains_key(&name) {
+                    bail!(
+                        "There are two parts called {name}, but each part needs a name of its own"
+                    );
+                }
+                let (model, defaults) = match (part.model, part.raw_cmd, part.library_part) {
+                    (Some(model), None, None) => (model, None),
+                    (None, None, Some(library_part)) => {
+                        let Some(defined) = definitions.library.get(&library_part) else {
+                            bail!("Part {name} uses {library_part}, which isn't in the library");
+                        };
+                        (defined.model.clone(), Some(&defined.parameters))
+                    }
+                    (None, Some(cmd), None) => {
+                        if !part.with.is_empty() {
+                            bail!("Part {name} is a raw_cmd, which has no parameters to set");
+                        }
+                        let cmd = cmd.to_string();
+                        for reference in raw::references(&cmd) {
+                            let numbered = reference.rsplit_once('_').is_some_and(|(model, n)| {
+                                earlier_models.contains(model) && n.parse::<usize>().is_ok()
+                            });
+                            if numbered && earlier.lookup(reference).is_err() {
+                                earlier.register(reference, Uuid::nil())?;
+                            }
+                        }
+                        raw::parse(&cmd, &earlier)
+                            .wrap_err_with(|| format!("Part {name} has an invalid raw_cmd"))?;
+                        earlier
+                            .register(&name, Uuid::nil())
+                            .wrap_err_with(|| format!("Part {name} has an invalid name"))?;
+                        return ScenePart {
+                            name,
+                            step: Step::Raw(cmd),
+                            drawing: Drawing::bare("raw"),
+                            export: part.export,
+                            at: None,
+                            fixed: part.fixed,
+                            group: part.group,
+                            visible: part.visible,
+                            build: part.build,
+                        }
+                        .placed(part.at.as_deref());
+                    }
+                    _ => bail!("Part {name} needs just one of a model, a raw_cmd, or a \"use\""),
+                };
+                // The part's own parameters replace its library part's defaults, which replace the scene's.
+                let mut parameters = definitions.parameters.clone();
+                parameters.extend(defaults.cloned().unwrap_or_default());
+                parameters.extend(part.with);
+                let parameters = expr::resolve(&parameters)
+                    .wrap_err_with(|| format!("Part {name} has invalid parameters"))?;
+                let args = substitute(&model, &parameters)
+                    .wrap_err_with(|| format!("Part {name} has an invalid argument"))?;
+                let model = parse_model(&args)
+                    .wrap_err_with(|| format!("Part {name} has an invalid model"))?;
+                let drawing = describe(&args, i + 1)?;
+                earlier
+                    .register(&name, Uuid::nil())
+                    .wrap_err_with(|| format!("Part {name} has an invalid name"))?;
+                earlier_models.insert(name.clone());
+                ScenePart {
+                    name,
+                    step: Step::Model(model),
+                    drawing,
+                    export: part.export,
+                    at: None,
+                    fixed: part.fixed,
+                    group: part.group,
+                    visible: part.visible,
+                    build: part.build,
+                }
+                .placed(part.at.as_deref())
+            })
+            .collect::<Result<_>>()?;
+        let mut files = vec![path.to_owned()];
+        files.append(&mut definitions.included);
+        Ok(Self {
+            parts,
+            separate_outputs: file.separate_outputs,
+            explode: file.explode,
+            annotations: file.annotations,
+            files,
+            tessellation,
+            seed,
+            jittered,
+        })
+    }
+
+    /// The capabilities the scene's parts need, and which part needs each, to check against a probe.
+    pub fn needs(&self) -> Vec<(String, probe::Capability)> {
+        let mut needs = Vec::new();
+        for part in &self.parts {
+            let capabilities = match &part.step {
+                Step::Model(model) => probe::needed_by(model),
+                Step::Raw(cmd) => probe::needed_by_raw(cmd).into_iter().collect(),
+            };
+            needs.extend(
+                capabilities
+                    .into_iter()
+                    .map(|capability| (format!("part {}", part.name), capability)),
+            );
+        }
+        needs
+    }
+}
+
+impl ScenePart {
+    /// Build the part at `at`, written as "x,y,z", if it's given.
+    fn placed(self, at: Option<&str>) -> Result<Self> {
+        let at = at
+            .map(coords::Given::parse)
+            .transpose()
+            .wrap_err_with(|| format!("Part {} has an invalid \"at\"", self.name))?
+            .map(|at| at.world());
+        Ok(Self { at, ..self })
+    }
+}
+
+/// Replace each argument starting with "=" with the value of the arithmetic after it.
+fn substitute(args: &[String], parameters: &BTreeMap<String, f64>) -> Result<Vec<String>> {
+    args.iter()
+        .map(|arg| match arg.strip_prefix('=') {
+            Some(text) => {
+                let value = expr::evaluate(text, parameters)
+                    .wrap_err_with(|| format!("Could not work out \"{arg}\""))?;
+                Ok(value.to_string())
+            }
+            None => Ok(arg.clone()),
+        })
+        .collect()
+}
+
+/// Which of a scene's groups of parts to show, from --only-group and --hide-group.
+#[derive(Clone, Debug, Default)]
+pub struct Groups {
+    /// If any are given, only parts in these groups are shown.
+    pub only: Vec<String>,
+    /// Parts in these groups are hidden.
+    pub hide: Vec<String>,
+}
+
+impl Groups {
+    /// Check every group named is one of the scene's, so a typo isn't silently ignored.
+    pub fn check(&self, scene: &Scene) -> Result<()> {
+        let known: BTreeSet<&str> = scene
+            .parts
+            .iter()
+            .filter_map(|p| p.group.as_deref())
+            .collect();
+        for (flag, groups) in [("--only-group", &self.only), ("--hide-group", &self.hide)] {
+            for group in groups {
+                if !known.contains(group.as_str()) {
+                    let known: Vec<_> = known.iter().copied().collect();
+                    bail!(
+                        "{flag} {group}: the scene has no group called {group:?}. Its groups are: {}",
+                        if known.is_empty() { "(none)".to_owned() } else { known.join(", ") }
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether a part is shown: it's visible, and its group isn't hidden.
+    fn shows(&self, part: &ScenePart) -> bool {
+        let in_any = |groups: &[String]| part.group.as_ref().is_some_and(|g| groups.contains(g));
+        part.visible && (self.only.is_empty() || in_any(&self.only)) && !in_any(&self.hide)
+    }
+}
+
+/// Build every part of a scene, and save the output (or outputs).
+/// Parts which aren't shown (see `Groups`) are built, unless they say not to, but are hidden from
+/// snapshots and left out of exports. When each part is saved separately, they're skipped instead.
+pub async fn render(
+    session: &mut ModelingSession,
+    scene: Scene,
+    output: &Output,
+    options: &Options,
+) -> Result<()> {
+    if let Some(jittered) = &scene.jittered {
+        session.start_variant(jittered.clone());
+    }
+    let mut object_ids = Vec::new();
+    let mut export_ids = Vec::new();
+    // Name every part's outputs first, so names which clash fail before anything is built.
+    let mut part_outputs = Vec::new();
+    if scene.separate_outputs {
+        // Raw commands have no outputs of their own.
+        for part in scene
+            .parts
+            .iter()
+            .filter(|p| matches!(p.step, Step::Model(_)) && options.groups.shows(p))
+        {
+            part_outputs.push(
+                match options.outputs.name(output, &part.drawing)? {
+                    Some(part_output) => part_output,
+                    None => output.with_suffix(&format!("-{}", part.name)),
+                }
+                .labelled(&part.drawing),
+            );
+        }
+    }
+    let mut part_outputs = part_outputs.into_iter();
+    // Each part built together, and its objects, to explode the scene.
+    let mut together = Vec::new();
+    // The objects of parts which were built but aren't shown.
+    let mut hidden = Vec::new();
+    for part in scene.parts {
+        let shown = options.groups.shows(&part);
+        let ScenePart {
+            name,
+            step,
+            export,
+            at,
+            fixed,
+            build: build_hidden,
+            ..
+        } = part;
+        // Raw commands have no objects to hide, so they're always sent.
+        if !shown && matches!(step, Step::Model(_)) && (scene.separate_outputs || !build_hidden) {
+            say!("Skipped part {name}, because it's hidden");
+            continue;
+        }
+        session.set_placement(at.unwrap_or_else(|| options.placement.clone()));
+        let model = match step {
+            Step::Model(model) => model,
+            Step::Raw(cmd) => {
+                let cmd = raw::parse(&cmd, session.names())
+                    .wrap_err_with(|| format!("Part {name} has an invalid raw_cmd"))?;
+                say!("Part {name}'s response:");
+                let id = raw::send(session, cmd).await?;
+                session.names_mut().register(&name, id)?;
+                continue;
+            }
+        };
+        if !scene.separate_outputs {
+            let ids = build(session, model, output, options.validation).await?;
+            names::register_objects(session, &name, &ids)?;
+            if !shown {
+                hidden.extend(ids);
+                continue;
+            }
+            if export {
+                export_ids.extend(&ids);
+            }
+            object_ids.extend(&ids);
+            together.push(explode::Part {
+                name,
+                object_ids: ids,
+                fixed,
+            });
+            continue;
+        }
+        let mut part_output = part_outputs
+            .next()
+            .expect("every separate part's output was named");
+        if !export {
+            part_output.export = None;
+        }
+        // Every part would be saved to the same files.
+        part_output.snapshots.clear();
+        session.set_camera_preset(camera::choose(options.view, &model));
+        let ids = build(session, model, &part_output, options.validation).await?;
+        names::register_objects(session, &name, &ids)?;
+        finish(session, &ids, &part_output, options).await?;
+        say!("Saved part {name} to {}", part_output.path);
+        clear(session).await?;
+    }
+    session.set_placement(options.placement.clone());
+    for &object_id in &hidden {
+        transform::set_hidden(session, object_id, true).await?;
+    }
+    if !hidden.is_empty() {
+        say!("Hid {} objects of parts which aren't shown", hidden.len());
+    }
+    if !scene.separate_outputs {
+        // Objects picked on the command line take precedence over the parts' own choices.
+        let mut output = Output {
+            export_only: output
+                .export_only
+                .clone()
+                .or(Some(export_ids.into_iter().map(Handle::Id).collect())),
+            ..output.clone()
+        };
+        // The API exports everything when asked for nothing, so don't ask.
+        if output.export_only.as_ref().is_some_and(Vec::is_empty) {
+            output.export = None;
+        }
+        for annotation in &scene.annotations {
+            output.dimensions.push(annotation.resolve(session).await?);
+        }
+        finish(session, &object_ids, &output, options).await?;
+        if let Some(explode) = &scene.explode {
+            explode::snapshot(session, &together, explode, &output).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Build each of a scene's variants (see --count) in turn, each in an empty scene, and save each to
+/// outputs of its own, named after its number, e.g. "scene-variant-2.png". A single variant is
+/// saved to the outputs as they are.
+pub async fn render_variants(
+    session: &mut ModelingSession,
+    scenes: Vec<Scene>,
+    output: &Output,
+    options: &Options,
+) -> Result<()> {
+    let count = scenes.len();
+    for scene in scenes {
+        if count == 1 {
+            return render(session, scene, output, options).await;
+        }
+        let variant = scene.seed.variant;
+        clear(session).await?;
+        let output = output.with_suffix(&format!("-variant-{variant}"));
+        render(session, scene, &output, options).await?;
+        say!("Built variant {variant} of {count}");
+    }
+    Ok(())
+}
+
+/// Remove everything we've created from the scene, so the next model starts from scratch.
+pub async fn clear(session: &mut ModelingSession) -> Result<()> {
+    let created = session.created();
+    if !created.is_empty() {
+        transform::remove_objects(session, &created).await?;
+    }
+    // Raw commands' names don't name objects, but they're gone with everything else.
+    session.names_mut().removed_all();
+    session.forget_framing();
+    Ok(())
+}
+
+/// How long cleaning up may take, so a broken connection can't hold up the end of the run.
+const CLEAN_UP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Remove everything we've created which is still in the scene, one object at a time, newest first,
+/// e.g. after a build failed partway, so it isn't left in the way of whatever the connection draws next.
+/// This is best effort: objects which can't be removed are printed, not returned as an error,
+/// and it gives up after `CLEAN_UP_TIMEOUT`.
+pub async fn clean_up(session: &mut ModelingSession) {
+    let created = session.created();
+    if created.is_empty() {
+        return;
+    }
+    let mut removed = 0;
+    let removing = async {
+        for &object_id in created.iter().rev() {
+            match transform::remove_objects(session, &[object_id]).await {
+                Ok(()) => {
+                    say!("Cleaned up {object_id}");
+                    removed += 1;
+                }
+                Err(e) => {
+                    let message =
+                        format!("Could not clean up {object_id}: {e}: {}", e.root_cause());
+                    // Cleaning up is best effort, but if this is denied, the run fails at the end.
+                    let _ = Warning::new(Code::CleanUp, message).raise();
+                }
+            }
+        }
+    };
+    if tokio::time::timeout(CLEAN_UP_TIMEOUT, removing)
+        .await
+        .is_err()
+    {
+        let message = format!("Gave up cleaning up after {CLEAN_UP_TIMEOUT:?}");
+        let _ = Warning::new(Code::CleanUp, message).raise();
+    }
+    say!("Cleaned up {removed} of {} objects", created.len());
+    session.forget_framing();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jitter::SplitMix;
+    use serde_json::json;
+
+    const SEED: Seed = Seed {
+        seed: 0,
+        variant: 1,
+    };
+
+    /// A file of the test's own to write a scene to.
+    fn scene_file(test: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("kittycad-scene-{test}-{}.json", std::process::id()))
+    }
+
+    /// What a made-up part should read back as.
+    enum Expected {
+        Cubes(u32),
+        Plate { hole_diameter: f64 },
+    }
+
+    /// A scene of cubes and plates, whose sizes come from parameters, some of them worked out from others.
+    fn random_scene(
+        random: &mut SplitMix,
+    ) -> (serde_json::Value, Vec<(String, Expected, Option<Point3D>)>) {
+        let mut parameters = serde_json::Map::new();
+        let mut parts = Vec::new();
+        let mut expected = Vec::new();
+        for i in 0..1 + random.next() % 6 {
+            let name = format!("part{i}_{}", random.next() % 1000);
+            let (model, part) = if random.coin() {
+                let count = 1 + (random.next() % 9) as u32;
+                parameters.insert(format!("count{i}"), json!(count));
+                (
+                    json!(["cube", "--count", format!("=count{i}")]),
+                    Expected::Cubes(count),
+                )
+            } else {
+                let hole = random.between(1.0, 10.0);
+                parameters.insert(format!("hole{i}"), json!(hole));
+                parameters.insert(format!("bore{i}"), json!(format!("hole{i} * 2")));
+                (
+                    json!(["plate", "--hole-diameter", format!("=bore{i}")]),
+                    Expected::Plate {
+                        hole_diameter: hole * 2.0,
+                    },
+                )
+            };
+            let mut part_json = json!({ "name": name, "model": model });
+            let at = (random.coin()).then(|| Point3D {
+                x: (random.next() % 200) as f64 - 100.0,
+                y: (random.next() % 200) as f64 - 100.0,
+                z: (random.next() % 200) as f64 - 100.0,
+            });
+            if let Some(at) = &at {
+                part_json["at"] = json!(format!("{},{},{}", at.x, at.y, at.z));
+            }
+            parts.push(part_json);
+            expected.push((name, part, at));
+        }
+        (
+            json!({ "parameters": parameters, "parts": parts }),
+            expected,
+        )
+    }
+
+    #[test]
+    fn reads_back_the_parts_it_was_given() {
+        let mut random = SplitMix(1);
+        let path = scene_file("parts");
+        for _ in 0..200 {
+            let (scene, expected) = random_scene(&mut random);
+            std::fs::write(&path, scene.to_string()).unwrap();
+            let read = Scene::read(&path, SEED).unwrap();
+            assert_eq!(read.parts.len(), expected.len(), "{scene}");
+            for (part, (name, model, at)) in read.parts.iter().zip(&expected) {
+                assert_eq!(&part.name, name);
+                match (&part.step, model) {
+                    (Step::Model(Model::Cube { count }), Expected::Cubes(expected)) => {
+                        assert_eq!(count, expected, "{scene}");
+                    }
+                    (
+                        Step::Model(Model::Plate { hole_diameter, .. }),
+                        Expected::Plate {
+                            hole_diameter: expected,
+                        },
+                    ) => assert!((hole_diameter - expected).abs() < 1e-9, "{scene}"),
+                    _ => panic!("Part {name} was read as the wrong model: {scene}"),
+                }
+                match (&part.at, at) {
+                    (Some(read), Some(at)) => {
+                        assert_eq!((read.x, read.y, read.z), (at.x, at.y, at.z), "{scene}");
+                    }
+                    (None, None) => {}
+                    _ => panic!("Part {name} was read at {:?}: {scene}", part.at),
+                }
+            }
+        }
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn never_panics_on_a_cut_off_scene() {
+        let mut random = SplitMix(2);
+        let path = scene_file("cut-off");
+        for _ in 0..20 {
+            let (scene, _) = random_scene(&mut random);
+            let text = scene.to_string();
+            for end in (0..text.len()).filter(|&end| text.is_char_boundary(end)) {
+                std::fs::write(&path, &text[..end]).unwrap();
+                assert!(Scene::read(&path, SEED).is_err(), "{}", &text[..end]);
+            }
+        }
+        let _ = std::fs::remove_file(path);
+    }
+
+    /// A scene file in tests/fixtures/scenes.
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/scenes")
+            .join(name)
+    }
+
+    /// Each part's name, and what it builds: the cubes' count, or the plate's hole diameter, and where.
+    fn built(scene: &Scene) -> Vec<(&str, String)> {
+        scene
+            .parts
+            .iter()
+            .map(|part| {
+                let mut model = match &part.step {
+                    Step::Model(Model::Cube { count }) => format!("{count} cubes"),
+                    Step::Model(Model::Plate { hole_diameter, .. }) => {
+                        format!("a plate with a {hole_diameter}mm hole")
+                    }
+                    _ => panic!("Part {} was read as the wrong model", part.name),
+                };
+                if let Some(at) = &part.at {
+                    model += &format!(" at {},{},{}", at.x, at.y, at.z);
+                }
+                (part.name.as_str(), model)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn uses_the_last_definition_of_each_name() {
+        let scene = Scene::read(&fixture("assembly.json"), SEED).unwrap();
+        assert_eq!(
+            built(&scene),
+            [
+                // metric.json's washer replaces the one it includes from fasteners.json, and the library
+                // part's default bore beats the scene's.
+                ("washer", "a plate with a 5mm hole".to_owned()),
+                // "with" beats both, and works from metric.json's size, not fasteners.json's.
+                (
+                    "wide-washer",
+                    "a plate with a 10mm hole at 0,50,0".to_owned()
+                ),
+                // The scene's own nut replaces the included one.
+                ("nuts", "2 cubes".to_owned()),
+                ("more-nuts", "3 cubes".to_owned()),
+                // Only fasteners.json defines the spacer, but it's sized by metric.json's parameters.
+                ("spacer", "a plate with a 4mm hole".to_owned()),
+                ("plate", "a plate with a 5mm hole".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_included_definitions_as_if_they_were_written_in_the_scene() {
+        let included = Scene::read(&fixture("assembly.json"), SEED).unwrap();
+        let flattened = Scene::read(&fixture("flattened.json"), SEED).unwrap();
+        assert_eq!(built(&included), built(&flattened));
+        for (included, flattened) in included.parts.iter().zip(&flattened.parts) {
+            assert_eq!(
+                format!("{:?}", included.drawing),
+                format!("{:?}", flattened.drawing)
+            );
+        }
+    }
+
+    #[test]
+    fn finds_included_files_next_to_the_file_including_them() {
+        let scene = Scene::read(&fixture("assembly.json"), SEED).unwrap();
+        assert_eq!(
+            scene.files,
+            [
+                fixture("assembly.json"),
+                fixture("parts/metric.json"),
+                // Included by metric.json, from parts/.
+                fixture("parts/fasteners.json"),
+            ]
+        );
+    }
+
+    /// Why reading a scene failed, with the context of each file.
+    fn error(name: &str) -> String {
+        let Err(e) = Scene::read(&fixture(name), SEED) else {
+            panic!("{name} was read");
+        };
+        e.chain()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>()
+            .join(": ")
+    }
+
+    #[test]
+    fn names_every_file_in_an_include_loop() {
+        let canonical = |name: &str| fixture(name).canonicalize().unwrap().display().to_string();
+        let (scene, first, second) = (
+            canonical("loop/scene.json"),
+            canonical("loop/first.json"),
+            canonical("loop/second.json"),
+        );
+        let first_included = fixture("loop/first.json").display().to_string();
+        let e = error("loop/scene.json");
+        assert!(
+            e.ends_with(&format!(
+                "Scene files include each other in a loop: {scene} includes {first} includes {second} includes {first}"
+            )),
+            "{e}"
+        );
+        assert!(
+            e.starts_with(&format!("In {first_included}, included by ")),
+            "{e}"
+        );
+    }
+
+    #[test]
+    fn names_the_file_including_a_missing_one() {
+        let e = error("missing.json");
+        assert!(
+            e.starts_with(&format!(
+                "Could not find {}, included by {}",
+                fixture("parts/imperial.json").display(),
+                fixture("missing.json").display()
+            )),
+            "{e}"
+        );
+    }
+
+    #[test]
+    fn only_builds_the_parts_of_the_scene_itself() {
+        let e = error("includes-parts.json");
+        assert_eq!(
+            e,
+            format!(
+                "In {}, included by {}: Included files can only define library parts and parameters, not parts",
+                fixture("assembly.json").display(),
+                fixture("includes-parts.json").display()
+            )
+        );
+    }
+}
@@ -0,0 +1,453 @@
+use crate::{
+    entities::EntityTree,
+    manifest::{Manifest, ObjectEntry},
+};
+use color_eyre::Result;
+use serde_json::Value;
+use std::{collections::BTreeMap, path::Path};
+
+/// What changed between two runs' manifests.
+///
+/// Every run gives its objects new IDs, so objects are matched by their role instead: how they were
+/// made, and where they come in the run among objects made the same way, e.g. "extrude 2".
+/// Outputs are matched by their paths.
+#[derive(Debug, Default, serde::Serialize)]
+pub struct SceneDiff {
+    /// The runs' command-line arguments, if they differ.
+    pub args: Option<Change>,
+    /// Roles only the new run has.
+    pub added: Vec<String>,
+    /// Roles only the old run has.
+    pub removed: Vec<String>,
+    /// Objects both runs have, but which were built differently, or came out differently.
+    pub changed: Vec<ObjectChange>,
+    /// Outputs which only one run saved, or which have different contents.
+    pub outputs: Vec<OutputChange>,
+}
+
+/// Something which was one thing in the old run, and another in the new one.
+/// Null means it wasn't there.
+#[derive(Debug, PartialEq, serde::Serialize)]
+pub struct Change {
+    pub old: Value,
+    pub new: Value,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct ObjectChange {
+    /// E.g. "extrude 2".
+    pub role: String,
+    /// Each part of the object which differs, e.g. {"recipe.extrusion": {"old": 20, "new": 30}}.
+    /// Entities are counted by type, e.g. "entities.face".
+    pub fields: BTreeMap<String, Change>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct OutputChange {
+    pub path: String,
+    pub status: OutputStatus,
+    /// The SHA-256 of the output in each run, null if the run didn't save it (or it wasn't hashed).
+    pub sha256: Change,
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputStatus {
+    /// Only the new run saved it.
+    Added,
+    /// Only the old run saved it.
+    Removed,
+    /// Both runs saved it, but its contents differ.
+    Changed,
+}
+
+impl SceneDiff {
+    pub fn is_empty(&self) -> bool {
+        self.args.is_none()
+            && self.added.is_empty()
+            && self.removed.is_empty()
+            && self.changed.is_empty()
+            && self.outputs.is_empty()
+    }
+}
+
+/// Compare two manifests, and print what changed, as text or as JSON.
+pub fn run(old: &Path, new: &Path, json: bool) -> Result<()> {
+    let diff = compare(&Manifest::read(old)?, &Manifest::read(new)?);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&diff)?);
+    } else if diff.is_empty() {
+        println!("No differences");
+    } else {
+        print!("{diff}");
+    }
+    Ok(())
+}
+
+/// Work out what changed between two manifests.
+pub fn compare(old: &Manifest, new: &Manifest) -> SceneDiff {
+    let mut diff = SceneDiff::default();
+    if old.args != new.args {
+        diff.args = Some(Change {
+            old: old.args.join(" ").into(),
+            new: new.args.join(" ").into(),
+        });
+    }
+
+    let (old_objects, new_objects) = (by_role(&old.objects), by_role(&new.objects));
+    for (role, old_object) in &old_objects {
+        match new_objects.get(role) {
+            Some(new_object) => {
+                let fields = changed_fields(&describe(old_object), &describe(new_object));
+                if !fields.is_empty() {
+                    diff.changed.push(ObjectChange {
+                        role: role.clone(),
+                        fields,
+                    });
+                }
+            }
+            None => diff.removed.push(role.clone()),
+        }
+    }
+    diff.added = new_objects
+        .keys()
+        .filter(|role| !old_objects.contains_key(*role))
+        .cloned()
+        .collect();
+
+    let hashes = |manifest: &Manifest| -> BTreeMap<String, Value> {
+        manifest
+            .outputs
+            .iter()
+            .map(|output| (output.path.clone(), output.sha256.clone().into()))
+            .collect()
+    };
+    let (old_hashes, mut new_hashes) = (hashes(old), hashes(new));
+    for (path, old_hash) in old_hashes {
+        let (status, new_hash) = match new_hashes.remove(&path) {
+            Some(new_hash) if new_hash == old_hash => continue,
+            Some(new_hash) => (OutputStatus::Changed, new_hash),
+            None => (OutputStatus::Removed, Value::Null),
+        };
+        diff.outputs.push(OutputChange {
+            path,
+            status,
+            sha256: Change {
+                old: old_hash,
+                new: new_hash,
+            },
+        });
+    }
+    for (path, new_hash) in new_hashes {
+        diff.outputs.push(OutputChange {
+            path,
+            status: OutputStatus::Added,
+            sha256: Change {
+                old: Value::Null,
+                new: new_hash,
+            },
+        });
+    }
+    diff
+}
+
+/// Each object, named by how it was made and where it comes among objects made the same way,
+/// e.g. "extrude 2" is the second object which was extruded.
+fn by_role(objects: &[ObjectEntry]) -> BTreeMap<String, &ObjectEntry> {
+    let mut counts = BTreeMap::new();
+    objects
+        .iter()
+        .map(|object| {
+            let count = counts.entry(object.operation.as_str()).or_insert(0);
+            *count += 1;
+            (format!("{} {count}", object.operation), object)
+        })
+        .collect()
+}
+
+/// An object's comparable parts, without its IDs (which every run changes), as dotted names
+/// and values, e.g. "recipe.extrusion" and 20.
+fn describe(object: &ObjectEntry) -> BTreeMap<String, Value> {
+    let mut fields = BTreeMap::new();
+    fields.insert("removed".to_owned(), object.removed.into());
+    if let Some(recipe) = &object.recipe {
+        if let Ok(Value::Object(recipe)) = serde_json::to_value(recipe) {
+            for (name, value) in recipe {
+                fields.insert(format!("recipe.{name}"), value);
+            }
+        }
+    }
+    if let Some(tree) = &object.entities {
+        let mut counts = BTreeMap::new();
+        count_entities(tree, &mut counts);
+        for (entity_type, count) in counts {
+            fields.insert(format!("entities.{entity_type}"), count.into());
+        }
+    }
+    fields
+}
+
+/// Count the entities in a tree of each type, e.g. {"face": 6, "edge": 12}.
+fn count_entities(tree: &EntityTree, counts: &mut BTreeMap<String, u64>) {
+    let entity_type = match serde_json::to_value(&tree.entity_type) {
+        Ok(Value::String(name)) => name,
+        _ => format!("{:?}", tree.entity_type),
+    };
+    *counts.entry(entity_type).or_default() += 1;
+    for child in &tree.children {
+        count_entities(child, counts);
+    }
+}
+
+fn changed_fields(
+    old: &BTreeMap<String, Value>,
+    new: &BTreeMap<String, Value>,
+) -> BTreeMap<String, Change> {
+    old.keys()
+        .chain(new.keys())
+        .filter_map(|name| {
+            let change = Change {
+                old: old.get(name).cloned().unwrap_or(Value::Null),
+                new: new.get(name).cloned().unwrap_or(Value::Null),
+            };
+            (change.old != change.new).then(|| (name.clone(), change))
+        })
+        .collect()
+}
+
+/// Long values, like a path's segments, are cut short, so the diff stays readable.
+const MAX_VALUE_CHARS: usize = 80;
+
+impl std::fmt::Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let show = |value: &Value| {
+            let text = match value {
+                Value::Null => "(none)".to_owned(),
+                Value::String(s) => s.clone(),
+                value => value.to_string(),
+            };
+            if text.chars().count() > MAX_VALUE_CHARS {
+                let short: String = text.chars().take(MAX_VALUE_CHARS).collect();
+                format!("{short}...")
+            } else {
+                text
+            }
+        };
+        write!(f, "{} -> {}", show(&self.old), show(&self.new))
+    }
+}
+
+/// E.g.
+/// ```text
+/// Arguments: plate --hole-diameter 5 -> plate --hole-diameter 6
+/// + extrude 3
+/// - sketch 2
+/// ~ extrude 1
+///     recipe.extrusion: 20 -> 30
+///     entities.face: 6 -> 7
+/// ~ model.png: 3f2a... -> 9b1c...
+/// ```
+impl std::fmt::Display for SceneDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(args) = &self.args {
+            writeln!(f, "Arguments: {args}")?;
+        }
+        for role in &self.added {
+            writeln!(f, "+ {role}")?;
+        }
+        for role in &self.removed {
+            writeln!(f, "- {role}")?;
+        }
+        for object in &self.changed {
+            writeln!(f, "~ {}", object.role)?;
+            for (name, change) in &object.fields {
+                writeln!(f, "    {name}: {change}")?;
+            }
+        }
+        for output in &self.outputs {
+            match output.status {
+                OutputStatus::Added => writeln!(f, "+ {}", output.path)?,
+                OutputStatus::Removed => writeln!(f, "- {}", output.path)?,
+                OutputStatus::Changed => writeln!(f, "~ {}: {}", output.path, output.sha256)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    /// An entity tree of a solid with this many faces, each with no edges.
+    fn solid(faces: usize) -> Value {
+        let face = |i| json!({ "id": Uuid::from_u128(100 + i as u128), "entity_type": "face", "children": [] });
+        json!({
+            "id": Uuid::new_v4(),
+            "entity_type": "solid3d",
+            "children": (0..faces).map(face).collect::<Vec<_>>(),
+        })
+    }
+
+    /// A square extruded this far.
+    fn extruded(height: f64) -> Value {
+        json!({
+            "start": { "x": 0.0, "y": 0.0, "z": 0.0 },
+            "segments": [
+                { "type": "line", "end": { "x": 10.0, "y": 0.0, "z": 0.0 }, "relative": false },
+                { "type": "line", "end": { "x": 10.0, "y": 10.0, "z": 0.0 }, "relative": false },
+                { "type": "line", "end": { "x": 0.0, "y": 0.0, "z": 0.0 }, "relative": false },
+            ],
+            "holes": [],
+            "extrusion": height,
+        })
+    }
+
+    /// A manifest of a run with these arguments, objects and outputs. Every object gets a new ID,
+    /// as it would in a new run.
+    fn manifest(
+        args: &str,
+        objects: &[(&str, Value, Value)],
+        outputs: &[(&str, &str)],
+    ) -> Manifest {
+        let objects: Vec<_> = objects
+            .iter()
+            .map(|(operation, recipe, entities)| {
+                json!({
+                    "id": Uuid::new_v4(),
+                    "operation": operation,
+                    "removed": false,
+                    "recipe": recipe,
+                    "entities": entities,
+                    "outputs": [],
+                })
+            })
+            .collect();
+        let outputs: Vec<_> = outputs
+            .iter()
+            .map(|(path, sha256)| json!({ "path": path, "sha256": sha256, "object_ids": [] }))
+            .collect();
+        serde_json::from_value(json!({
+            "args": args.split(' ').collect::<Vec<_>>(),
+            "objects": objects,
+            "outputs": outputs,
+        }))
+        .unwrap()
+    }
+
+    fn plate(height: f64, faces: usize) -> Manifest {
+        manifest(
+            "plate --hole-diameter 5",
+            &[("extrude", extruded(height), solid(faces))],
+            &[("model.png", "aa")],
+        )
+    }
+
+    #[test]
+    fn finds_no_differences_between_the_same_runs() {
+        let diff = compare(&plate(20.0, 6), &plate(20.0, 6));
+        assert!(diff.is_empty(), "{diff:?}");
+        assert_eq!(diff.to_string(), "");
+    }
+
+    #[test]
+    fn finds_changed_arguments() {
+        let new = manifest("plate --hole-diameter 6", &[], &[]);
+        let diff = compare(&manifest("plate --hole-diameter 5", &[], &[]), &new);
+        assert_eq!(
+            diff.args,
+            Some(Change {
+                old: "plate --hole-diameter 5".into(),
+                new: "plate --hole-diameter 6".into()
+            })
+        );
+        assert_eq!(
+            diff.to_string(),
+            "Arguments: plate --hole-diameter 5 -> plate --hole-diameter 6\n"
+        );
+    }
+
+    #[test]
+    fn finds_added_and_removed_objects_by_role() {
+        let old = manifest(
+            "cube",
+            &[
+                ("extrude", extruded(20.0), Value::Null),
+                ("sketch", Value::Null, Value::Null),
+            ],
+            &[],
+        );
+        let new = manifest(
+            "cube",
+            &[
+                ("extrude", extruded(20.0), Value::Null),
+                ("extrude", extruded(20.0), Value::Null),
+            ],
+            &[],
+        );
+        let diff = compare(&old, &new);
+        assert_eq!(diff.added, ["extrude 2"]);
+        assert_eq!(diff.removed, ["sketch 1"]);
+        assert!(diff.changed.is_empty());
+        assert_eq!(diff.to_string(), "+ extrude 2\n- sketch 1\n");
+    }
+
+    #[test]
+    fn finds_objects_built_or_coming_out_differently() {
+        let diff = compare(&plate(20.0, 6), &plate(30.0, 7));
+        assert_eq!(diff.changed.len(), 1);
+        let changed = &diff.changed[0];
+        assert_eq!(changed.role, "extrude 1");
+        assert_eq!(
+            changed.fields.keys().collect::<Vec<_>>(),
+            ["entities.face", "recipe.extrusion"]
+        );
+        assert_eq!(
+            diff.to_string(),
+            "~ extrude 1\n    entities.face: 6 -> 7\n    recipe.extrusion: 20.0 -> 30.0\n"
+        );
+    }
+
+    #[test]
+    fn finds_changed_added_and_removed_outputs() {
+        let old = manifest("cube", &[], &[("model.png", "aa"), ("model.stl", "bb")]);
+        let new = manifest("cube", &[], &[("model.png", "cc"), ("model.step", "dd")]);
+        let diff = compare(&old, &new);
+        let outputs: Vec<_> = diff
+            .outputs
+            .iter()
+            .map(|output| {
+                (
+                    output.path.as_str(),
+                    serde_json::to_value(&output.status).unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            outputs,
+            [
+                ("model.png", json!("changed")),
+                ("model.stl", json!("removed")),
+                ("model.step", json!("added")),
+            ]
+        );
+        assert_eq!(
+            diff.to_string(),
+            "~ model.png: aa -> cc\n- model.stl\n+ model.step\n"
+        );
+    }
+
+    #[test]
+    fn cuts_long_values_short() {
+        let change = Change {
+            old: "x".repeat(100).into(),
+            new: Value::Null,
+        };
+        assert_eq!(
+            change.to_string(),
+            format!("{}... -> (none)", "x".repeat(80))
+        );
+    }
+}
@@ -0,0 +1,282 @@
+use crate::{entities::EntityTree, geometry::BoundingBox, measure, session::ModelingSession};
+use color_eyre::{
+    eyre::{bail, eyre},
+    Result,
+};
+use kittycad::types::{EntityType, ModelingCmd, OkModelingCmdResponse, Point3D};
+use uuid::Uuid;
+
+/// Which edges of a solid to pick, judged by where their ends are.
+#[derive(Clone, Debug)]
+pub enum EdgePredicate {
+    /// Edges which run along this axis: "x", "y" or "z".
+    ParallelTo(char),
+    /// Edges whose ends are further apart than this.
+    LongerThan(f64),
+    /// Edges with both ends at the top of the solid, e.g. the loop around a box's top face.
+    Top,
+}
+
+impl EdgePredicate {
+    /// Does the edge from `start` to `end` satisfy the predicate?
+    /// `bounds` is the bounding box of all the solid's edges.
+    pub fn matches(&self, start: &Point3D, end: &Point3D, bounds: &BoundingBox) -> bool {
+        let epsilon = 1e-6;
+        match self {
+            Self::ParallelTo(axis) => {
+                let d = [end.x - start.x, end.y - start.y, end.z - start.z];
+                let along = match axis {
+                    'x' => 0,
+                    'y' => 1,
+                    _ => 2,
+                };
+                d[along].abs() > epsilon && (0..3).all(|i| i == along || d[i].abs() < epsilon)
+            }
+            Self::LongerThan(length) => crate::geometry::distance(start, end) > *length,
+            Self::Top => {
+                (start.z - bounds.max.z).abs() < epsilon && (end.z - bounds.max.z).abs() < epsilon
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for EdgePredicate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ParallelTo(axis) => write!(f, "parallel to {axis}"),
+            Self::LongerThan(length) => write!(f, "longer than {length}"),
+            Self::Top => write!(f, "on the top face"),
+        }
+    }
+}
+
+/// Parses "parallel:x", "parallel:y", "parallel:z", "longer:<length>" or "top".
+pub fn parse_edge_predicate(s: &str) -> Result<EdgePredicate> {
+    let (name, arg) = s.split_once(':').unwrap_or((s, ""));
+    match (name.trim(), arg.trim()) {
+        ("parallel", axis @ ("x" | "y" | "z")) => {
+            Ok(EdgePredicate::ParallelTo(axis.chars().next().unwrap()))
+        }
+        ("longer", length) => {
+            let length = length
+                .parse()
+                .map_err(|_| eyre!("Expected \"longer:<length>\", e.g. \"longer:5\", got {s:?}"))?;
+            Ok(EdgePredicate::LongerThan(length))
+        }
+        ("top", "") => Ok(EdgePredicate::Top),
+        _ => bail!(
+            "Expected \"parallel:x\", \"parallel:y\", \"parallel:z\", \"longer:<length>\" or \"top\", got {s:?}"
+        ),
+    }
+}
+
+/// The edges of a solid which satisfy `predicate`.
+/// Picking no edges at all is an error, since it's usually a mistake in the predicate.
+pub async fn select_edges(
+    session: &mut ModelingSession,
+    solid_id: Uuid,
+    predicate: &EdgePredicate,
+) -> Result<Vec<Uuid>> {
+    let tree = EntityTree::query(session, solid_id).await?;
+    let edge_ids: Vec<_> = tree.ids_of_type(&EntityType::Edge).into_iter().collect();
+    let ends = measure::edge_end_points(session, &edge_ids).await?;
+    let Some(bounds) = BoundingBox::from_points(ends.iter().flat_map(|(start, end)| [start, end]))
+    else {
+        bail!("Solid {solid_id} has no edges to pick from");
+    };
+    let picked: Vec<_> = edge_ids
+        .into_iter()
+        .zip(&ends)
+        .filter(|(_, (start, end))| predicate.matches(start, end, &bounds))
+        .map(|(edge_id, _)| edge_id)
+        .collect();
+    if picked.is_empty() {
+        bail!(
+            "None of solid {solid_id}'s {} edges are {predicate}",
+            ends.len()
+        );
+    }
+    Ok(picked)
+}
+
+/// Highlight and select some entities, so they stand out in snapshots.
+/// Then check the API agrees about what's selected.
+pub async fn emphasize(session: &mut ModelingSession, entity_ids: &[Uuid]) -> Result<()> {
+    let highlight_id = session
+        .send(ModelingCmd::HighlightSetEntities {
+            entities: entity_ids.to_vec(),
+        })
+        .await?;
+    let select_id = session
+        .send(ModelingCmd::SelectAdd {
+            entities: entity_ids.to_vec(),
+        })
+        .await?;
+    session.await_response(highlight_id).await?;
+    session.await_response(select_id).await?;
+
+    let selected = selected(session).await?;
+    let missing: Vec<_> = entity_ids
+        .iter()
+        .filter(|id| !selected.contains(id))
+        .map(Uuid::to_string)
+        .collect();
+    if !missing.is_empty() {
+        bail!(
+            "Asked the API to select {} entities, but these weren't selected: {}",
+            entity_ids.len(),
+            missing.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Unhighlight and deselect everything.
+pub async fn clear(session: &mut ModelingSession) -> Result<()> {
+    let highlight_id = session
+        .send(ModelingCmd::HighlightSetEntities {
+            entities: Vec::new(),
+        })
+        .await?;
+    let select_id = session.send(ModelingCmd::SelectClear {}).await?;
+    session.await_response(highlight_id).await?;
+    session.await_response(select_id).await?;
+
+    let selected = selected(session).await?;
+    if !selected.is_empty() {
+        bail!(
+            "Cleared the selection, but the API still has {} entities selected",
+            selected.len()
+        );
+    }
+    Ok(())
+}
+
+/// Which entities the API has selected.
+async fn selected(session: &mut ModelingSession) -> Result<Vec<Uuid>> {
+    let cmd_id = session.send(ModelingCmd::SelectGet {}).await?;
+    let OkModelingCmdResponse::SelectGet { data } = session.await_response(cmd_id).await? else {
+        bail!("Expected the API to respond to SelectGet with a list of IDs");
+    };
+    Ok(data.entity_ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn point(x: f64, y: f64, z: f64) -> Point3D {
+        Point3D { x, y, z }
+    }
+
+    /// A 10 by 20 by 30 box's bounds.
+    fn bounds() -> BoundingBox {
+        BoundingBox {
+            min: point(0.0, 0.0, 0.0),
+            max: point(10.0, 20.0, 30.0),
+        }
+    }
+
+    #[test]
+    fn parses_each_predicate() {
+        for (text, expected) in [
+            ("parallel:x", "parallel to x"),
+            (" parallel : z ", "parallel to z"),
+            ("longer:5", "longer than 5"),
+            ("longer:2.5", "longer than 2.5"),
+            ("top", "on the top face"),
+        ] {
+            assert_eq!(parse_edge_predicate(text).unwrap().to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn says_what_a_predicate_should_look_like() {
+        for text in ["parallel:w", "parallel", "top:1", "bottom", ""] {
+            let e = parse_edge_predicate(text).unwrap_err().to_string();
+            assert!(e.starts_with("Expected \"parallel:x\""), "{text}: {e}");
+        }
+        assert_eq!(
+            parse_edge_predicate("longer:far").unwrap_err().to_string(),
+            "Expected \"longer:<length>\", e.g. \"longer:5\", got \"longer:far\""
+        );
+    }
+
+    #[test]
+    fn matches_edges_along_an_axis() {
+        let along_y = (point(10.0, 0.0, 30.0), point(10.0, 20.0, 30.0));
+        let diagonal = (point(0.0, 0.0, 0.0), point(10.0, 20.0, 0.0));
+        let y = EdgePredicate::ParallelTo('y');
+        assert!(y.matches(&along_y.0, &along_y.1, &bounds()));
+        // Either way along it.
+        assert!(y.matches(&along_y.1, &along_y.0, &bounds()));
+        assert!(!y.matches(&diagonal.0, &diagonal.1, &bounds()));
+        assert!(!EdgePredicate::ParallelTo('x').matches(&along_y.0, &along_y.1, &bounds()));
+        // A point has no direction.
+        assert!(!y.matches(&along_y.0, &along_y.0, &bounds()));
+    }
+
+    #[test]
+    fn matches_edges_longer_than_a_length() {
+        let (start, end) = (point(0.0, 0.0, 0.0), point(3.0, 4.0, 0.0));
+        assert!(EdgePredicate::LongerThan(4.9).matches(&start, &end, &bounds()));
+        assert!(!EdgePredicate::LongerThan(5.0).matches(&start, &end, &bounds()));
+    }
+
+    #[test]
+    fn matches_edges_at_the_top() {
+        let top = EdgePredicate::Top;
+        assert!(top.matches(&point(0.0, 0.0, 30.0), &point(10.0, 0.0, 30.0), &bounds()));
+        assert!(!top.matches(&point(0.0, 0.0, 0.0), &point(0.0, 0.0, 30.0), &bounds()));
+        assert!(!top.matches(&point(0.0, 0.0, 0.0), &point(10.0, 0.0, 0.0), &bounds()));
+    }
+
+    #[test]
+    fn fails_if_no_edges_match() {
+        let [solid, face, a, b] = [1, 2, 3, 4].map(Uuid::from_u128);
+        let entity = |entity_type: &str, children: &[Uuid]| -> [(&str, Value); 2] {
+            [
+                (
+                    "get_entity_type",
+                    json!({ "type": "get_entity_type", "data": { "entity_type": entity_type } }),
+                ),
+                (
+                    "entity_get_all_child_uuids",
+                    json!({ "type": "entity_get_all_child_uuids", "data": { "entity_ids": children } }),
+                ),
+            ]
+        };
+        let ends = |start: Point3D, end: Point3D| {
+            (
+                "curve_get_end_points",
+                json!({ "type": "curve_get_end_points", "data": { "start": start, "end": end } }),
+            )
+        };
+        let responses = [
+            entity("solid3d", &[face]).as_slice(),
+            &entity("face", &[a, b]),
+            &entity("edge", &[]),
+            &entity("edge", &[]),
+            &[
+                ends(point(0.0, 0.0, 0.0), point(10.0, 0.0, 0.0)),
+                ends(point(10.0, 0.0, 0.0), point(10.0, 20.0, 0.0)),
+            ],
+        ]
+        .concat();
+        let mut session = ModelingSession::canned("no-edges-match", &responses);
+        let picked = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(select_edges(
+                &mut session,
+                solid,
+                &EdgePredicate::ParallelTo('z'),
+            ));
+        assert_eq!(
+            picked.unwrap_err().to_string(),
+            format!("None of solid {solid}'s 2 edges are parallel to z")
+        );
+    }
+}
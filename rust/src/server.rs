@@ -0,0 +1,72 @@
+use crate::session::{ConnectionProfile, StreamSettings};
+use std::sync::OnceLock;
+
+/// What the run was talking to, fetched once at startup, so reports say which deployment they're about.
+static SERVER_INFO: OnceLock<ServerInfo> = OnceLock::new();
+
+/// Which API deployment the run used, and how it connected.
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct ServerInfo {
+    /// The API's version, from its OpenAPI schema, if it could be fetched.
+    pub api_version: Option<String>,
+    /// The git hash the server was built from. Only KittyCAD employees are allowed to see this.
+    pub git_hash: Option<String>,
+    /// The version of the kittycad crate the example was built with.
+    pub kittycad_version: &'static str,
+    /// Which stream the run's sessions ask for (see --connection-profile), and what that comes to.
+    pub connection_profile: ConnectionProfile,
+    pub stream: StreamSettings,
+}
+
+/// Ask the API which version it's running, and remember it for the run's report.
+/// Failures are printed, not returned, because they don't stop the model being drawn.
+/// The WebSocket doesn't say which version it is: its first messages are only ever for WebRTC.
+pub async fn fetch(client: &kittycad::Client, profile: ConnectionProfile) -> ServerInfo {
+    let api_version = match client.meta().get_schema().await {
+        Ok(schema) => schema["info"]["version"].as_str().map(str::to_owned),
+        Err(e) => {
+            eprintln!("Could not fetch the API's version: {e}");
+            None
+        }
+    };
+    // Everyone else is refused, which isn't worth mentioning.
+    let git_hash = client
+        .meta()
+        .get_metadata()
+        .await
+        .ok()
+        .map(|metadata| metadata.git_hash);
+    let info = ServerInfo {
+        api_version,
+        git_hash,
+        kittycad_version: crate::sidecar::KITTYCAD_VERSION,
+        connection_profile: profile,
+        stream: profile.stream(),
+    };
+    // This is only fetched once a run.
+    let _ = SERVER_INFO.set(info.clone());
+    info
+}
+
+/// What `fetch` found, if it's been called.
+pub fn info() -> Option<ServerInfo> {
+    SERVER_INFO.get().cloned()
+}
+
+impl std::fmt::Display for ServerInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let unknown = || "unknown".to_owned();
+        write!(
+            f,
+            "API version {}, server build {}, kittycad crate {}, {} profile: {}x{} stream at {} fps, WebRTC {}",
+            self.api_version.clone().unwrap_or_else(unknown),
+            self.git_hash.clone().unwrap_or_else(unknown),
+            self.kittycad_version,
+            self.connection_profile,
+            self.stream.video_res_width,
+            self.stream.video_res_height,
+            self.stream.fps,
+            if self.stream.webrtc { "on" } else { "off" }
+        )
+    }
+}
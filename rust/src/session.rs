@@ -0,0 +1,2349 @@
+use crate::{
+    camera::CameraPreset,
+    client,
+    compare::Comparison,
+    debug::DebugDir,
+    events::{self, Event},
+    explode::Explosion,
+    geometry::{self, BoundingBox},
+    jitter::Jittered,
+    names::NameRegistry,
+    precision::Quantizer,
+    projection::Camera,
+    recipe::Recipe,
+    sidecar::{self, OutputKind, Sidecar},
+    sink::FileSink,
+    snapshot::STDOUT,
+    template::OutputRegistry,
+    timings::{self, Phase, Timings},
+    transport::{Recording, Replay, Transport, TransportError},
+    warnings::{Code, Warning},
+};
+use color_eyre::{
+    eyre::{bail, eyre},
+    Report, Result,
+};
+use futures::StreamExt;
+use kittycad::types::{
+    ClientMetrics, ErrorCode, FailureWebSocketResponse, ModelingCmd, ModelingCmdReq,
+    OkModelingCmdResponse, OkWebSocketResponseData, Point3D, RawFile, SuccessWebSocketResponse,
+    WebSocketRequest,
+};
+use serde::de::IgnoredAny;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::time::timeout;
+use tokio_tungstenite::tungstenite::{protocol::WebSocketConfig, Message as WsMsg};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+/// The API the kittycad crate's client talks to.
+pub const API_HOST: &str = "https://api.kittycad.io";
+
+/// Which video stream a session asks for when it connects (see --connection-profile).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConnectionProfile {
+    /// Only commands and snapshots, which don't depend on the stream, so it's kept as cheap for the API
+    /// as it'll allow: one frame a second, and no WebRTC.
+    #[default]
+    SnapshotOnly,
+    /// A 30 fps stream over WebRTC, for watching the model as it's drawn. Nothing here watches it yet,
+    /// so it only costs the API more.
+    Streaming,
+}
+
+impl ConnectionProfile {
+    /// The stream to ask for. They're reported with the server's details, in case the API behaves
+    /// differently with them.
+    pub fn stream(self) -> StreamSettings {
+        match self {
+            Self::SnapshotOnly => StreamSettings {
+                fps: 1,
+                unlocked_framerate: false,
+                video_res_width: 640,
+                video_res_height: 480,
+                webrtc: false,
+            },
+            Self::Streaming => StreamSettings {
+                fps: 30,
+                unlocked_framerate: false,
+                video_res_width: 640,
+                video_res_height: 480,
+                webrtc: true,
+            },
+        }
+    }
+
+    /// Check the profile suits what the run does with its session. A run which watches video needs
+    /// the streaming profile, and one which doesn't is better off without it.
+    pub fn check(self, watches_video: bool) -> Result<()> {
+        match (self, watches_video) {
+            (Self::SnapshotOnly, true) => {
+                bail!("Watching the video stream needs --connection-profile streaming")
+            }
+            (Self::Streaming, false) => Warning::new(
+                Code::UnusedStream,
+                "The streaming profile asks the API for video which nothing in this run watches, \
+                 so --connection-profile snapshot-only would cost it less",
+            )
+            .parameter("connection-profile")
+            .raise(),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl std::fmt::Display for ConnectionProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value = clap::ValueEnum::to_possible_value(self).expect("no profiles are skipped");
+        f.write_str(value.get_name())
+    }
+}
+
+/// The WebSocket connection's parameters.
+#[derive(Clone, Copy, Debug, serde::Serialize)]
+pub struct StreamSettings {
+    pub fps: u32,
+    pub unlocked_framerate: bool,
+    pub video_res_width: u32,
+    pub video_res_height: u32,
+    pub webrtc: bool,
+}
+
+/// How many commands `send_many` queues up before flushing them to the WebSocket, by default.
+const DEFAULT_GROUP_SIZE: usize = 64;
+
+/// How many commands may be waiting for a response at once, by default.
+const DEFAULT_MAX_IN_FLIGHT: usize = 32;
+
+/// How long to wait before the first retry of a failed command. Each retry after that waits twice as long,
+/// up to `MAX_RETRY_BACKOFF`.
+const RETRY_BACKOFF: Duration = Duration::from_millis(250);
+
+/// The longest to wait before retrying a failed command, however many times it's failed.
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(30);
+
+/// How long a command may wait for a response, by default, before the session reports it as stuck.
+const DEFAULT_COMMAND_DEADLINE: Duration = Duration::from_secs(10);
+
+/// How long to wait for the API to respond to the command being awaited, however the commands
+/// before it are getting on, by default. This is a backstop: stuck commands are normally caught by their deadline.
+pub const DEFAULT_RESPONSE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long the WebSocket may be silent (no messages of any kind, not even pings) by default,
+/// before the session decides the connection is dead.
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The biggest WebSocket message the session sends or receives by default, in bytes.
+/// This is the WebSocket library's own default for messages.
+pub const DEFAULT_MAX_MESSAGE_BYTES: usize = 64 << 20;
+
+/// What a run's sessions noticed about the API, for the run's report. Every session of a run (e.g. each
+/// of a sweep's jobs, and each connection made again) shares one, so it adds up across all of them.
+#[derive(Clone, Debug, Default)]
+pub struct RunStats(Arc<Mutex<Stats>>);
+
+/// What `RunStats` counted, as of when it was read.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Stats {
+    /// The first request ID the API sent back, to give KittyCAD support.
+    pub first_request_id: Option<Uuid>,
+    /// How many messages from the API were skipped, because they couldn't be parsed,
+    /// e.g. new kinds of response from an API newer than this version of the kittycad crate.
+    pub unrecognized_responses: usize,
+    /// How many times the run connected again, because the API closed a session before responding
+    /// to anything (see `ServerUnavailable`).
+    pub session_retries: usize,
+    /// How many times the API asked for the client's metrics (see `answer_metrics_request`).
+    pub metrics_requests: usize,
+    /// How out of order the API's responses arrived.
+    pub response_ordering: ResponseOrdering,
+}
+
+impl RunStats {
+    pub fn get(&self) -> Stats {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn update(&self, change: impl FnOnce(&mut Stats)) {
+        change(&mut self.0.lock().unwrap_or_else(|e| e.into_inner()));
+    }
+
+    /// Count another session the run had to connect again for.
+    pub fn count_session_retry(&self) {
+        self.update(|stats| stats.session_retries += 1);
+    }
+}
+
+/// The longest to wait before connecting again (see `session_retry_delay`).
+const MAX_SESSION_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// How long to wait before connecting again for the `attempt`th time (counting from 1): 1s,
+/// then twice as long each time, up to 30s.
+pub fn session_retry_delay(attempt: u32) -> Duration {
+    2u64.checked_pow(attempt.saturating_sub(1))
+        .map_or(MAX_SESSION_RETRY_DELAY, |secs| {
+            Duration::from_secs(secs).min(MAX_SESSION_RETRY_DELAY)
+        })
+}
+
+/// How out of order the API's responses arrived, across every session in the run.
+#[derive(Clone, Copy, Debug, Default, serde::Serialize)]
+pub struct ResponseOrdering {
+    /// How many responses arrived while a command sent before theirs was still waiting for its own.
+    pub out_of_order: usize,
+    /// The most earlier commands any one response overtook.
+    pub max_distance: usize,
+}
+
+/// How much of a message which couldn't be parsed is printed, in characters.
+const UNRECOGNIZED_PREVIEW: usize = 500;
+
+/// A WebSocket connection to the KittyCAD Modeling API.
+/// Commands are sent with `send`, and their responses can be awaited with `await_response`.
+pub struct ModelingSession {
+    transport: Transport,
+    /// Responses which arrived from the API before anybody asked for them,
+    /// keyed by the ID of the command they respond to.
+    unclaimed: HashMap<Uuid, OkWebSocketResponseData>,
+    /// Every object we've created, in the order we created them, including ones since removed.
+    history: Vec<Uuid>,
+    /// Every path and solid we've created which is still in the scene, and how we built it.
+    recipes: HashMap<Uuid, Recipe>,
+    /// Objects which have been removed from the scene, and how they were built (if we know).
+    removed: HashMap<Uuid, Option<Recipe>>,
+    /// Every output we've saved, and which objects were in the scene at the time.
+    outputs: Vec<SavedOutput>,
+    /// What the objects are called, so they can be referred to by name.
+    names: NameRegistry,
+    /// Where outputs are saved, and what they're called.
+    registry: Arc<OutputRegistry>,
+    /// The box the camera was last pointed at, if it's been moved.
+    framed: Option<BoundingBox>,
+    /// How the camera was pointed at it.
+    pointed: Option<Camera>,
+    /// Which way the camera looks at whatever it frames.
+    camera_preset: CameraPreset,
+    /// How many commands `send_many` queues up before flushing them.
+    group_size: usize,
+    /// Commands which haven't been responded to yet, in the order they were sent.
+    in_flight: Vec<InFlight>,
+    /// How long a command may wait for a response before the session gives up on it.
+    command_deadline: Duration,
+    /// How long to wait for the command being awaited, however the others are getting on.
+    /// None waits as long as commands meet their deadlines and the WebSocket isn't idle.
+    response_timeout: Option<Duration>,
+    /// How long the WebSocket may go without sending anything before the connection counts as lost.
+    idle_timeout: Duration,
+    /// How many commands may be in flight at once. Sending another waits until one is responded to.
+    max_in_flight: usize,
+    /// When the connection was opened, in seconds since the Unix epoch.
+    started_at: u64,
+    /// How many modeling commands have been sent.
+    commands_sent: usize,
+    /// Whether to save a sidecar describing each output next to it.
+    sidecars: bool,
+    /// Where to keep the raw payloads of responses which couldn't be handled.
+    debug_dir: DebugDir,
+    /// How far new paths and planes are moved from where the model draws them, before they're sent.
+    placement: Point3D,
+    /// Which variant of the model is which, if two were drawn side by side to compare them.
+    comparison: Option<Comparison>,
+    /// How the scene's parts were moved apart, while a snapshot of it exploded is being saved.
+    explosion: Option<Explosion>,
+    /// The jittered variants of a scene built so far, the one being built last.
+    variants: Vec<Jittered>,
+    /// How long the session has spent in each phase, not counting the current one.
+    timings: Timings,
+    /// What the session is spending its time on now, and since when.
+    phase: (Phase, Instant),
+    /// Whether a message from the API which can't be parsed fails the session, instead of being skipped.
+    strict: bool,
+    /// Whether a response arriving before the response to a command sent earlier fails the session,
+    /// instead of being warned about.
+    strict_ordering: bool,
+    /// Whether profiles which can't make a solid are sent anyway, instead of being refused.
+    allow_degenerate: bool,
+    /// Whether extrusions are closed with caps on their ends, making solids.
+    cap_extrusions: bool,
+    /// The biggest WebSocket message (or frame) the session sends or receives, in bytes.
+    max_message_bytes: usize,
+    /// The video stream the session asked for when it connected.
+    stream: StreamSettings,
+    /// Where new command IDs come from.
+    ids: IdGenerator,
+    /// Which failed commands to send again.
+    retry: RetryPolicy,
+    /// Commands which may be sent again if they fail, and how many times they've been retried,
+    /// keyed by the ID they were last sent with.
+    retryable: HashMap<Uuid, (ModelingCmd, u32)>,
+    /// The ID each retried command was first sent with, keyed by the ID it was last sent with.
+    /// Its response is handed over under its first ID, so callers don't need to know it was retried.
+    retried: HashMap<Uuid, Uuid>,
+    /// Whether the API has responded to any command yet, so losing the connection before then
+    /// can be told apart (see `ServerUnavailable`).
+    responded: bool,
+    /// Rounds the coordinates in commands before they're sent, if --coord-precision was given.
+    quantizer: Option<Quantizer>,
+    /// Whether reconfiguring the stream changes the size of the API's snapshots, once it's been tried.
+    stream_sizes_snapshots: Option<bool>,
+    /// Cancels whatever the session is waiting for, e.g. from a GUI's cancel button (see `set_cancellation`).
+    cancellation: Option<CancellationToken>,
+    /// What the session noticed about the API, added up with the rest of the run's sessions (see `share_stats`).
+    stats: RunStats,
+    /// Commands which were still waiting for a response when the session was cancelled.
+    /// Their responses are ignored if they arrive later.
+    abandoned: Vec<Uuid>,
+}
+
+/// An output the session saved.
+pub struct SavedOutput {
+    /// Where it was saved, as the run named it, e.g. "plate.png", or "-" for standard output.
+    pub path: String,
+    /// The objects which were in it.
+    pub object_ids: Vec<Uuid>,
+    /// The SHA-256 of what was saved, in hex.
+    pub sha256: String,
+}
+
+/// A command which is waiting for a response.
+struct InFlight {
+    cmd_id: Uuid,
+    /// The command's type, e.g. "extrude".
+    name: String,
+    /// Which command this was in the session, counting from 1.
+    position: usize,
+    sent_at: Instant,
+}
+
+/// Which failed commands to send again, and how many times.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// How many times to resend a command before giving up. Zero turns retries off.
+    pub retries: u32,
+    /// Which errors are worth retrying, e.g. the engine failing, rather than a bad command.
+    pub error_codes: Vec<ErrorCode>,
+    /// Whether to retry commands which change the scene (like Extrude), as well as queries.
+    /// If one of those failed part way through, sending it again could build something twice.
+    pub scene_changes: bool,
+}
+
+impl RetryPolicy {
+    /// Does this policy allow the command to be sent again if it fails?
+    fn allows(&self, cmd: &ModelingCmd) -> bool {
+        self.retries > 0 && (self.scene_changes || leaves_scene_alone(cmd))
+    }
+}
+
+/// Parses an API error code, e.g. "internal_engine".
+pub fn parse_error_code(s: &str) -> Result<ErrorCode> {
+    s.trim()
+        .parse()
+        .map_err(|_| eyre!("{s:?} isn't an API error code, like internal_engine or bad_request"))
+}
+
+/// How long to wait before a command's `attempt`th retry (counting from 1).
+fn retry_backoff(attempt: u32) -> Duration {
+    2u32.checked_pow(attempt.saturating_sub(1))
+        .and_then(|factor| RETRY_BACKOFF.checked_mul(factor))
+        .map_or(MAX_RETRY_BACKOFF, |backoff| backoff.min(MAX_RETRY_BACKOFF))
+}
+
+/// Does this command leave the scene's geometry alone, so sending it twice does no harm?
+fn leaves_scene_alone(cmd: &ModelingCmd) -> bool {
+    matches!(
+        cmd,
+        ModelingCmd::Volume { .. }
+            | ModelingCmd::Mass { .. }
+            | ModelingCmd::Density { .. }
+            | ModelingCmd::SurfaceArea { .. }
+            | ModelingCmd::CenterOfMass { .. }
+            | ModelingCmd::TakeSnapshot { .. }
+            | ModelingCmd::Export { .. }
+            | ModelingCmd::GetEntityType { .. }
+            | ModelingCmd::EntityGetParentId { .. }
+            | ModelingCmd::EntityGetNumChildren { .. }
+            | ModelingCmd::EntityGetChildUuid { .. }
+            | ModelingCmd::EntityGetAllChildUuids { .. }
+            | ModelingCmd::PathGetInfo { .. }
+            | ModelingCmd::PathGetVertexUuids { .. }
+            | ModelingCmd::PathGetCurveUuidsForVertices { .. }
+            | ModelingCmd::CurveGetEndPoints { .. }
+            | ModelingCmd::CurveGetType { .. }
+            | ModelingCmd::CurveGetControlPoints { .. }
+            | ModelingCmd::SelectGet { .. }
+            | ModelingCmd::SelectClear { .. }
+            | ModelingCmd::HighlightSetEntities { .. }
+            | ModelingCmd::DefaultCameraLookAt { .. }
+            | ModelingCmd::DefaultCameraZoom { .. }
+    )
+}
+
+/// Where new command (and so entity) IDs come from.
+enum IdGenerator {
+    /// Random IDs, which are what the API expects.
+    Random,
+    /// IDs which are the same every run, for diffing what two runs sent.
+    /// This is a SplitMix64 generator, whose state starts at the seed.
+    Seeded(u64),
+}
+
+impl IdGenerator {
+    fn next(&mut self) -> Uuid {
+        match self {
+            Self::Random => Uuid::new_v4(),
+            Self::Seeded(state) => {
+                let mut half = || {
+                    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+                    let mut z = *state;
+                    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+                    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+                    z ^ (z >> 31)
+                };
+                let bytes = (u128::from(half()) << 64 | u128::from(half())).to_be_bytes();
+                uuid::Builder::from_random_bytes(bytes).into_uuid()
+            }
+        }
+    }
+}
+
+impl ModelingSession {
+    /// Establish a WebSocket connection to KittyCAD's modeling API, asking for the profile's stream.
+    /// Messages (and their frames) bigger than `max_message_bytes` can't be sent or received.
+    pub async fn connect(
+        client: &kittycad::Client,
+        max_message_bytes: usize,
+        profile: ConnectionProfile,
+    ) -> Result<Self> {
+        let connecting_since = Instant::now();
+        let stream = profile.stream();
+        let ws = match client
+            .modeling()
+            .commands_ws(
+                Some(stream.fps),
+                Some(stream.unlocked_framerate),
+                Some(stream.video_res_height),
+                Some(stream.video_res_width),
+                Some(stream.webrtc),
+            )
+            .await
+        {
+            Ok(ws) => ws,
+            Err(kittycad::types::error::Error::UnexpectedResponse(resp)) => {
+                return Err(client::explain_refusal(resp).await)
+            }
+            Err(e) => return Err(client::explain_connect_error(color_eyre::Report::new(e))),
+        };
+
+        // Now that we have a WebSocket connection, we can split it into two ends:
+        // one for writing to and one for reading from.
+        // The API may send a whole export as one frame, so frames get the same limit as messages.
+        let config = WebSocketConfig {
+            max_message_size: Some(max_message_bytes),
+            max_frame_size: Some(max_message_bytes),
+            ..WebSocketConfig::default()
+        };
+        let (write, read) = tokio_tungstenite::WebSocketStream::from_raw_socket(
+            ws,
+            tokio_tungstenite::tungstenite::protocol::Role::Client,
+            Some(config),
+        )
+        .await
+        .split();
+        let transport = Transport::WebSocket {
+            write,
+            read,
+            recording: None,
+        };
+        Ok(Self::new(
+            transport,
+            connecting_since,
+            max_message_bytes,
+            stream,
+        ))
+    }
+
+    /// Play back a recording made by --record, instead of connecting to the API.
+    /// The run has to send the same kinds of commands, in the same order, as the recorded one did.
+    /// Recorded messages bigger than `max_message_bytes` can't be received, as if they'd come over the WebSocket.
+    pub fn replay(
+        recording: &Path,
+        max_message_bytes: usize,
+        profile: ConnectionProfile,
+    ) -> Result<Self> {
+        let connecting_since = Instant::now();
+        let transport = Transport::Replay(Replay::open(recording, max_message_bytes)?);
+        Ok(Self::new(
+            transport,
+            connecting_since,
+            max_message_bytes,
+            profile.stream(),
+        ))
+    }
+
+    /// A session which has just connected, with the usual settings.
+    fn new(
+        transport: Transport,
+        connecting_since: Instant,
+        max_message_bytes: usize,
+        stream: StreamSettings,
+    ) -> Self {
+        // Building starts the moment connecting stops, so no time falls between the two.
+        let connected_at = Instant::now();
+        events::emit(Event::Connected);
+        events::emit(Event::PhaseFinished {
+            phase: Phase::Connect,
+            elapsed: connected_at - connecting_since,
+        });
+        events::emit(Event::PhaseStarted(Phase::Build));
+        Self {
+            transport,
+            unclaimed: HashMap::new(),
+            history: Vec::new(),
+            recipes: HashMap::new(),
+            removed: HashMap::new(),
+            outputs: Vec::new(),
+            names: NameRegistry::default(),
+            registry: Arc::new(OutputRegistry::new(None, Arc::new(FileSink))),
+            framed: None,
+            pointed: None,
+            camera_preset: CameraPreset::default(),
+            group_size: DEFAULT_GROUP_SIZE,
+            in_flight: Vec::new(),
+            command_deadline: DEFAULT_COMMAND_DEADLINE,
+            response_timeout: Some(DEFAULT_RESPONSE_TIMEOUT),
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+            started_at: sidecar::now(),
+            commands_sent: 0,
+            sidecars: true,
+            debug_dir: DebugDir::default(),
+            placement: Point3D {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            comparison: None,
+            explosion: None,
+            variants: Vec::new(),
+            timings: Timings {
+                connect: connected_at - connecting_since,
+                ..Timings::default()
+            },
+            phase: (Phase::Build, connected_at),
+            strict: false,
+            strict_ordering: false,
+            allow_degenerate: false,
+            cap_extrusions: true,
+            max_message_bytes,
+            stream,
+            ids: IdGenerator::Random,
+            retry: RetryPolicy {
+                retries: 0,
+                error_codes: Vec::new(),
+                scene_changes: false,
+            },
+            retryable: HashMap::new(),
+            retried: HashMap::new(),
+            responded: false,
+            quantizer: None,
+            stream_sizes_snapshots: None,
+            cancellation: None,
+            stats: RunStats::default(),
+            abandoned: Vec::new(),
+        }
+    }
+
+    /// Write every message sent and received from now on to this file, for --offline to play back.
+    pub fn record(&mut self, path: &Path) -> Result<()> {
+        self.transport.record(Recording::create(path)?);
+        Ok(())
+    }
+
+    /// Send a modeling command to the API.
+    /// Returns the command's ID, which its response will be tagged with.
+    /// If it fails, it may be sent again, depending on the retry policy.
+    pub async fn send(&mut self, cmd: ModelingCmd) -> Result<Uuid> {
+        let cmd_id = self.new_id();
+        let retry = self.retry.allows(&cmd).then(|| cmd.clone());
+        self.send_with_id(cmd, cmd_id).await?;
+        if let Some(cmd) = retry {
+            self.retryable.insert(cmd_id, (cmd, 0));
+        }
+        Ok(cmd_id)
+    }
+
+    /// Send a modeling command to the API, using an ID chosen by the caller.
+    /// This is useful for commands which create something, because the new
+    /// entity will have the same ID as the command which created it.
+    /// Commands sent this way aren't retried, because that would give them a different ID.
+    pub async fn send_with_id(&mut self, cmd: ModelingCmd, cmd_id: Uuid) -> Result<()> {
+        // All messages to the KittyCAD Modeling API will be sent over the WebSocket as Text.
+        // The text will contain JSON representing a `ModelingCmdReq`.
+        let cmd = self.rounded(cmd)?;
+        let msg = serde_json::to_string(&WebSocketRequest::ModelingCmdReq { cmd, cmd_id })?;
+        self.check_size(&msg)?;
+        let names = cmd_names(&msg);
+        self.wait_for_room(1).await?;
+        self.transport.send(msg).await.map_err(|e| self.failed(e))?;
+        self.start_waiting(&[cmd_id], names);
+        Ok(())
+    }
+
+    /// Send several modeling commands, in order, flushing the WebSocket once per group of commands
+    /// rather than once per command. Returns their IDs, in order.
+    pub async fn send_many(&mut self, cmds: &[ModelingCmd]) -> Result<Vec<Uuid>> {
+        let mut cmd_ids = Vec::with_capacity(cmds.len());
+        for group in cmds.chunks(self.group_size) {
+            for cmd in group {
+                let cmd_id = self.new_id();
+                let msg = serde_json::to_string(&WebSocketRequest::ModelingCmdReq {
+                    cmd: self.rounded(cmd.clone())?,
+                    cmd_id,
+                })?;
+                self.check_size(&msg)?;
+                let names = cmd_names(&msg);
+                self.wait_for_room(1).await?;
+                self.transport.feed(msg).await.map_err(|e| self.failed(e))?;
+                self.start_waiting(&[cmd_id], names);
+                if self.retry.allows(cmd) {
+                    self.retryable.insert(cmd_id, (cmd.clone(), 0));
+                }
+                cmd_ids.push(cmd_id);
+            }
+            self.transport.flush().await.map_err(|e| self.failed(e))?;
+        }
+        Ok(cmd_ids)
+    }
+
+    /// Change how many commands `send_many` queues up before flushing them.
+    pub fn set_group_size(&mut self, group_size: usize) {
+        self.group_size = group_size.max(1);
+    }
+
+    /// A new ID for a command, or for something a command will create.
+    pub fn new_id(&mut self) -> Uuid {
+        self.ids.next()
+    }
+
+    /// Make IDs the same every run, starting from this seed, instead of random.
+    /// This makes it possible to diff what two runs sent, but the API expects random IDs,
+    /// so don't use it for normal runs.
+    pub fn set_id_seed(&mut self, seed: u64) {
+        self.ids = IdGenerator::Seeded(seed);
+    }
+
+    /// Change which failed commands are sent again.
+    pub fn set_retry_policy(&mut self, retry: RetryPolicy) {
+        self.retry = retry;
+    }
+
+    /// Change how long a command may wait for a response before the session gives up on it.
+    pub fn set_command_deadline(&mut self, deadline: Duration) {
+        self.command_deadline = deadline;
+    }
+
+    /// Change how long to wait for the command being awaited, however the others are getting on.
+    /// None turns the overall timeout off, for sessions with legitimately slow commands (e.g. huge
+    /// exports), so only the command deadline and the idle timeout decide when to give up.
+    pub fn set_response_timeout(&mut self, response_timeout: Option<Duration>) {
+        self.response_timeout = response_timeout;
+    }
+
+    /// Change how long the WebSocket may be silent before the connection counts as lost.
+    pub fn set_idle_timeout(&mut self, idle_timeout: Duration) {
+        self.idle_timeout = idle_timeout;
+    }
+
+    /// Change whether a message from the API which can't be parsed fails the session.
+    /// Otherwise it's printed and skipped, unless it responds to a command we're waiting for.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Change whether a response arriving out of order fails the session.
+    /// Otherwise it's warned about, and counted in the run's summary.
+    pub fn set_strict_ordering(&mut self, strict_ordering: bool) {
+        self.strict_ordering = strict_ordering;
+    }
+
+    /// Change whether profiles which can't make a solid are sent anyway, instead of being refused.
+    pub fn set_allow_degenerate(&mut self, allow_degenerate: bool) {
+        self.allow_degenerate = allow_degenerate;
+    }
+
+    /// Whether profiles which can't make a solid are sent anyway.
+    pub fn allows_degenerate(&self) -> bool {
+        self.allow_degenerate
+    }
+
+    /// Change whether `shapes::extrude` closes the ends of what it extrudes with caps.
+    /// Without them, an extrusion is an open tube, not a solid.
+    pub fn set_cap_extrusions(&mut self, cap_extrusions: bool) {
+        self.cap_extrusions = cap_extrusions;
+    }
+
+    /// Whether `shapes::extrude` closes the ends of what it extrudes with caps.
+    pub fn caps_extrusions(&self) -> bool {
+        self.cap_extrusions
+    }
+
+    /// Round every point's coordinates in the commands sent from now on to this many decimal places,
+    /// or None to send them as they are.
+    pub fn set_coord_precision(&mut self, places: Option<u32>) {
+        self.quantizer = places.map(Quantizer::new);
+    }
+
+    /// The command as it should be sent, with its coordinates rounded if the session rounds them.
+    fn rounded(&mut self, cmd: ModelingCmd) -> Result<ModelingCmd> {
+        match &mut self.quantizer {
+            Some(quantizer) => quantizer.apply(&cmd),
+            None => Ok(cmd),
+        }
+    }
+
+    /// Change how many commands may be waiting for a response at once.
+    pub fn set_max_in_flight(&mut self, max_in_flight: usize) {
+        self.max_in_flight = max_in_flight.max(1);
+    }
+
+    /// Send several modeling commands in one message.
+    /// Returns their IDs, in order. Each command gets its own response.
+    /// If the message would be too big to send, the commands are split into several batches,
+    /// sent one after another. A command failing then only stops the rest of its own batch.
+    pub async fn send_batch(&mut self, cmds: Vec<ModelingCmd>) -> Result<Vec<Uuid>> {
+        let mut requests = Vec::with_capacity(cmds.len());
+        for cmd in cmds {
+            let cmd_id = self.new_id();
+            // Retries are sent on their own, not as part of a batch.
+            if self.retry.allows(&cmd) {
+                self.retryable.insert(cmd_id, (cmd.clone(), 0));
+            }
+            let cmd = self.rounded(cmd)?;
+            requests.push(ModelingCmdReq { cmd, cmd_id });
+        }
+        let cmd_ids: Vec<_> = requests.iter().map(|req| req.cmd_id).collect();
+        // Batches still to send, the next one last.
+        let mut batches = vec![requests];
+        while let Some(requests) = batches.pop() {
+            let batch_ids: Vec<_> = requests.iter().map(|req| req.cmd_id).collect();
+            let request = WebSocketRequest::ModelingCmdBatchReq { requests };
+            let msg = serde_json::to_string(&request)?;
+            if msg.len() > self.max_message_bytes && batch_ids.len() > 1 {
+                let WebSocketRequest::ModelingCmdBatchReq { mut requests } = request else {
+                    unreachable!("it was made a batch request just above");
+                };
+                let second_half = requests.split_off(requests.len() / 2);
+                say!(
+                    "A batch of {} commands is {} bytes, more than the limit of {}, so it's being split in two",
+                    batch_ids.len(),
+                    msg.len(),
+                    self.max_message_bytes
+                );
+                batches.push(second_half);
+                batches.push(requests);
+                continue;
+            }
+            self.check_size(&msg)?;
+            let names = cmd_names(&msg);
+            self.wait_for_room(batch_ids.len()).await?;
+            self.transport.send(msg).await.map_err(|e| self.failed(e))?;
+            self.start_waiting(&batch_ids, names);
+        }
+        Ok(cmd_ids)
+    }
+
+    /// Fail if a message is too big to send.
+    fn check_size(&self, msg: &str) -> Result<()> {
+        if msg.len() > self.max_message_bytes {
+            return Err(MessageTooBig {
+                sending: true,
+                size: msg.len(),
+                limit: self.max_message_bytes,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Remember how a path or solid we created was built.
+    /// The API (as of this version of the kittycad crate) can't tell us where a solid is,
+    /// or transform it, so we work those out from the commands we sent.
+    pub fn record_recipe(&mut self, object_id: Uuid, recipe: Recipe) {
+        self.track(object_id);
+        self.recipes.insert(object_id, recipe);
+    }
+
+    /// Remember that we created an object, so it can be cleaned up later.
+    pub fn track(&mut self, object_id: Uuid) {
+        if !self.history.contains(&object_id) {
+            self.history.push(object_id);
+        }
+    }
+
+    /// Every object we've created which is still in the scene, in the order we created them.
+    pub fn created(&self) -> Vec<Uuid> {
+        self.history
+            .iter()
+            .filter(|id| !self.removed.contains_key(id))
+            .copied()
+            .collect()
+    }
+
+    /// Every object we've created, in the order we created them, including ones since removed.
+    pub fn history(&self) -> &[Uuid] {
+        &self.history
+    }
+
+    /// Stop tracking an object, because it's been removed from the scene.
+    pub fn removed(&mut self, object_id: Uuid) {
+        let recipe = self.recipes.remove(&object_id);
+        self.removed.insert(object_id, recipe);
+        self.names.removed(object_id);
+    }
+
+    /// What the session's objects are called.
+    pub fn names(&self) -> &NameRegistry {
+        &self.names
+    }
+
+    pub fn names_mut(&mut self) -> &mut NameRegistry {
+        &mut self.names
+    }
+
+    /// Has this object been removed from the scene?
+    /// If so, returns how it was built (if we know).
+    pub fn removed_recipe(&self, object_id: Uuid) -> Option<Option<&Recipe>> {
+        self.removed.get(&object_id).map(Option::as_ref)
+    }
+
+    /// Save an output's contents through the run's sink (e.g. to a file), or to standard output
+    /// if its path is "-". Returns where it ended up. Call `record_output` once it's saved.
+    pub async fn write_output(&self, path: &str, contents: &[u8]) -> Result<String> {
+        self.registry.write(path, contents).await
+    }
+
+    /// Remember that an output (a snapshot or an export) was saved, with everything currently in
+    /// the scene in it. Also saves a sidecar describing it, unless sidecars are turned off
+    /// or it went to standard output.
+    pub async fn record_output(
+        &mut self,
+        path: &str,
+        contents: &[u8],
+        kind: OutputKind,
+    ) -> Result<()> {
+        let object_ids = self.created();
+        self.record_output_of(path, contents, kind, object_ids)
+            .await
+    }
+
+    /// Remember that an output was saved with only some objects in it, e.g. an export of part of the scene.
+    pub async fn record_output_of(
+        &mut self,
+        path: &str,
+        contents: &[u8],
+        kind: OutputKind,
+        object_ids: Vec<Uuid>,
+    ) -> Result<()> {
+        self.outputs.push(SavedOutput {
+            path: path.to_owned(),
+            object_ids,
+            sha256: sidecar::sha256(contents),
+        });
+        if self.sidecars && path != STDOUT {
+            let sidecar = Sidecar::new(self, path, contents, kind);
+            self.write_output(&sidecar.path(), &sidecar.to_json()?)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Start spending time on a phase, e.g. waiting for a snapshot, and return the phase it was on before,
+    /// to go back to afterwards.
+    pub fn enter_phase(&mut self, phase: Phase) -> Phase {
+        // The new phase starts the moment the last one stops, so every moment is counted once.
+        let now = Instant::now();
+        let (previous, since) = std::mem::replace(&mut self.phase, (phase, now));
+        self.timings.add(previous, now - since);
+        if phase != previous {
+            events::emit(Event::PhaseFinished {
+                phase: previous,
+                elapsed: now - since,
+            });
+            events::emit(Event::PhaseStarted(phase));
+        }
+        previous
+    }
+
+    /// Count time spent encoding on another thread while the session got on with something else.
+    pub fn add_overlapped(&mut self, time: Duration) {
+        self.timings.overlapped += time;
+    }
+
+    /// How long the session has spent in each phase so far.
+    pub fn timings(&self) -> Timings {
+        let mut timings = self.timings.clone();
+        let (phase, since) = self.phase;
+        timings.add(phase, since.elapsed());
+        timings
+    }
+
+    /// Turn the sidecars saved next to each output on or off.
+    pub fn set_sidecars(&mut self, sidecars: bool) {
+        self.sidecars = sidecars;
+    }
+
+    /// Add what this session notices about the API to `stats`, which the run's other sessions share.
+    pub fn share_stats(&mut self, stats: RunStats) {
+        self.stats = stats;
+    }
+
+    /// What this session, and any others sharing its `RunStats`, have noticed about the API so far.
+    pub fn stats(&self) -> Stats {
+        self.stats.get()
+    }
+
+    /// Set where outputs are saved, and what they're called, shared by every session in the run.
+    pub fn set_outputs(&mut self, registry: Arc<OutputRegistry>) {
+        self.registry = registry;
+    }
+
+    /// Set where to keep the raw payloads of responses which couldn't be handled.
+    pub fn set_debug_dir(&mut self, debug_dir: DebugDir) {
+        self.debug_dir = debug_dir;
+    }
+
+    /// Keep the raw payload of a response which couldn't be handled in the debug directory,
+    /// and return the error, saying where it is.
+    pub fn keep_payload(
+        &self,
+        cmd_id: Uuid,
+        stage: &'static str,
+        payload: &[u8],
+        error: Report,
+    ) -> Report {
+        self.debug_dir.keep(cmd_id, stage, payload, error)
+    }
+
+    /// Move every path and plane sketched from now on by this much, so the model is built where it
+    /// belongs, e.g. to assemble a scene, rather than around the origin.
+    pub fn set_placement(&mut self, at: Point3D) {
+        self.placement = at;
+    }
+
+    /// Remember which variant of the model is which, to describe the outputs saved from now on.
+    pub fn set_comparison(&mut self, comparison: Comparison) {
+        self.comparison = Some(comparison);
+    }
+
+    /// Which variant of the model is which, if two were drawn side by side to compare them.
+    pub fn comparison(&self) -> Option<&Comparison> {
+        self.comparison.as_ref()
+    }
+
+    /// Remember how the scene's parts were moved apart, to describe the outputs saved until it's put back.
+    pub fn set_explosion(&mut self, explosion: Option<Explosion>) {
+        self.explosion = explosion;
+    }
+
+    pub fn explosion(&self) -> Option<&Explosion> {
+        self.explosion.as_ref()
+    }
+
+    /// Remember which jittered variant of a scene is being built, to describe the outputs saved from now on.
+    /// Building the same variant again (e.g. with --watch) replaces it.
+    pub fn start_variant(&mut self, jittered: Jittered) {
+        self.variants
+            .retain(|v| (v.seed, v.variant) != (jittered.seed, jittered.variant));
+        self.variants.push(jittered);
+    }
+
+    /// The jittered variant of a scene being built, if it's jittered.
+    pub fn variant(&self) -> Option<&Jittered> {
+        self.variants.last()
+    }
+
+    /// Every jittered variant of a scene built so far, in order.
+    pub fn variants(&self) -> &[Jittered] {
+        &self.variants
+    }
+
+    /// How far new paths and planes are moved from where the model draws them.
+    pub fn placement(&self) -> &Point3D {
+        &self.placement
+    }
+
+    /// Where a point the model draws ends up in the scene.
+    pub fn place(&self, p: &Point3D) -> Point3D {
+        Point3D {
+            x: p.x + self.placement.x,
+            y: p.y + self.placement.y,
+            z: p.z + self.placement.z,
+        }
+    }
+
+    /// Where the model would have to draw a point for it to end up here in the scene,
+    /// e.g. to draw on something measured from the scene.
+    pub fn unplace(&self, p: &Point3D) -> Point3D {
+        geometry::sub(p, &self.placement)
+    }
+
+    /// When the connection was opened, in seconds since the Unix epoch.
+    pub fn started_at(&self) -> u64 {
+        self.started_at
+    }
+
+    /// How many modeling commands have been sent.
+    pub fn commands_sent(&self) -> usize {
+        self.commands_sent
+    }
+
+    /// Every output we've saved, and which objects were in the scene at the time.
+    pub fn outputs(&self) -> &[SavedOutput] {
+        &self.outputs
+    }
+
+    /// How a path or solid we created was built.
+    pub fn recipe(&self, object_id: Uuid) -> Option<&Recipe> {
+        self.recipes.get(&object_id)
+    }
+
+    /// How a path or solid we created was built, so that it can be updated (e.g. after extruding it).
+    pub fn recipe_mut(&mut self, object_id: Uuid) -> Option<&mut Recipe> {
+        self.recipes.get_mut(&object_id)
+    }
+
+    /// Forget how an object was built, e.g. because it's become part of another object.
+    /// Returns how it was built.
+    pub fn forget(&mut self, object_id: Uuid) -> Option<Recipe> {
+        self.recipes.remove(&object_id)
+    }
+
+    /// The box containing all the given solids, as drawn: it's worked out from how they were built
+    /// (see `Recipe::bounding_box`), not measured by the API.
+    /// Returns None if there aren't any, or we don't know where one of them is.
+    pub fn bounding_box(&self, object_ids: &[Uuid]) -> Option<BoundingBox> {
+        let mut boxes = object_ids
+            .iter()
+            .map(|id| self.recipes.get(id).map(Recipe::bounding_box));
+        let first = boxes.next()??;
+        boxes.try_fold(first, |acc, bbox| Some(acc.union(&bbox?)))
+    }
+
+    /// Remember that the camera was pointed at this box, and how.
+    pub fn set_framed(&mut self, bbox: BoundingBox, camera: Camera) {
+        self.framed = Some(bbox);
+        self.pointed = Some(camera);
+    }
+
+    /// Set which way the camera looks at whatever it frames from now on, e.g. the model's preset.
+    pub fn set_camera_preset(&mut self, preset: CameraPreset) {
+        self.camera_preset = preset;
+    }
+
+    pub fn camera_preset(&self) -> &CameraPreset {
+        &self.camera_preset
+    }
+
+    /// The video stream the session asked for when it connected.
+    pub fn stream(&self) -> StreamSettings {
+        self.stream
+    }
+
+    /// Whether reconfiguring the stream changes the size of the API's snapshots, or None if it hasn't been tried.
+    pub fn stream_sizes_snapshots(&self) -> Option<bool> {
+        self.stream_sizes_snapshots
+    }
+
+    /// Remember whether reconfiguring the stream changed the size of a snapshot.
+    pub fn set_stream_sizes_snapshots(&mut self, sizes: bool) {
+        self.stream_sizes_snapshots = Some(sizes);
+    }
+
+    /// Stop waiting for the API, and fail with `Cancelled`, as soon as this token is cancelled,
+    /// e.g. by another task. The session can still be used afterwards.
+    ///
+    /// A token only cancels once. After its `Cancelled` error, the session stops watching it,
+    /// so anything cleaning up after the error (e.g. leaving sketch mode) can still talk to the API.
+    /// Give the session a new token before starting something else which should be cancellable.
+    pub fn set_cancellation(&mut self, token: Option<CancellationToken>) {
+        self.cancellation = token;
+    }
+
+    /// The commands which were still waiting for a response when the session was cancelled, oldest first.
+    pub fn abandoned(&self) -> &[Uuid] {
+        &self.abandoned
+    }
+
+    /// Fail with `Cancelled` if the session's token has been cancelled.
+    /// Long operations call this between their steps, e.g. before each turntable frame.
+    pub fn check_cancelled(&mut self) -> Result<()> {
+        if self
+            .cancellation
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+        {
+            return Err(self.cancel());
+        }
+        Ok(())
+    }
+
+    /// Stop watching the token, and stop waiting for every command still in flight,
+    /// so the session is ready for the next command.
+    fn cancel(&mut self) -> Report {
+        self.cancellation = None;
+        let abandoned: Vec<Uuid> = self.in_flight.drain(..).map(|c| c.cmd_id).collect();
+        for cmd_id in &abandoned {
+            self.retryable.remove(cmd_id);
+        }
+        // A retried command is waited for under its latest ID, which was just abandoned.
+        self.retried.retain(|latest, _| !abandoned.contains(latest));
+        self.abandoned.extend(&abandoned);
+        Cancelled {
+            phase: self.phase.0,
+            abandoned,
+        }
+        .into()
+    }
+
+    /// Wait for something from the API, unless the session is cancelled first, in which case this is None.
+    async fn unless_cancelled<T>(
+        token: Option<CancellationToken>,
+        waiting: impl std::future::Future<Output = T>,
+    ) -> Option<T> {
+        let Some(token) = token else {
+            return Some(waiting.await);
+        };
+        tokio::select! {
+            biased;
+            _ = token.cancelled() => None,
+            done = waiting => Some(done),
+        }
+    }
+
+    /// Forget where the camera was pointed, e.g. because the scene was cleared, or sketch mode turned it.
+    pub fn forget_framing(&mut self) {
+        self.framed = None;
+        self.pointed = None;
+    }
+
+    /// The box the camera was last pointed at, or None if it hasn't been moved yet.
+    pub fn framed(&self) -> Option<&BoundingBox> {
+        self.framed.as_ref()
+    }
+
+    /// Where the camera was last pointed, e.g. to work out where things appear in snapshots,
+    /// or None if it hasn't been moved yet.
+    pub fn pointed(&self) -> Option<&Camera> {
+        self.pointed.as_ref()
+    }
+
+    /// Read WebSocket messages until we receive the response to the given command.
+    /// Responses to other commands are kept, so they can be awaited later.
+    pub async fn await_response(&mut self, cmd_id: Uuid) -> Result<OkModelingCmdResponse> {
+        match self.await_ws_response(cmd_id).await? {
+            OkWebSocketResponseData::Modeling { modeling_response } => Ok(modeling_response),
+            _ => bail!("Expected the API to respond to command {cmd_id} with a modeling response"),
+        }
+    }
+
+    /// Read WebSocket messages until we receive the files exported by the given Export command.
+    pub async fn await_export(&mut self, cmd_id: Uuid) -> Result<Vec<RawFile>> {
+        let (_, files) = self.await_any_export(&[cmd_id]).await?;
+        Ok(files)
+    }
+
+    /// Read WebSocket messages until we receive the files exported by any of these Export commands,
+    /// whichever the API responds to first. Returns which one it was, and its files.
+    /// The others' responses are kept until they're waited for.
+    pub async fn await_any_export(&mut self, cmd_ids: &[Uuid]) -> Result<(Uuid, Vec<RawFile>)> {
+        match self.await_any_ws_response(cmd_ids).await? {
+            (cmd_id, OkWebSocketResponseData::Export { files }) => Ok((cmd_id, files)),
+            (cmd_id, _) => {
+                bail!("Expected the API to respond to command {cmd_id} with exported files")
+            }
+        }
+    }
+
+    async fn await_ws_response(&mut self, cmd_id: Uuid) -> Result<OkWebSocketResponseData> {
+        let (_, response) = self.await_any_ws_response(&[cmd_id]).await?;
+        Ok(response)
+    }
+
+    async fn await_any_ws_response(
+        &mut self,
+        cmd_ids: &[Uuid],
+    ) -> Result<(Uuid, OkWebSocketResponseData)> {
+        let response_timeout = self.response_timeout;
+        let cancellation = self.cancellation.clone();
+        let response = async {
+            loop {
+                for &cmd_id in cmd_ids {
+                    if let Some(resp) = self.unclaimed.remove(&cmd_id) {
+                        return Ok((cmd_id, resp));
+                    }
+                }
+                self.receive_watched().await?;
+            }
+        };
+        let response = async {
+            let Some(response_timeout) = response_timeout else {
+                return response.await;
+            };
+            timeout(response_timeout, response).await.map_err(|_| {
+                let cmd_ids: Vec<_> = cmd_ids.iter().map(Uuid::to_string).collect();
+                eyre!(
+                    "Timed out waiting for the API to respond to command {}",
+                    cmd_ids.join(" or ")
+                )
+            })?
+        };
+        match Self::unless_cancelled(cancellation, response).await {
+            Some(response) => response,
+            None => Err(self.cancel()),
+        }
+    }
+
+    /// Wait until there's room in the window to send this many more commands.
+    /// Commands which are bigger than the whole window are sent once nothing else is in flight.
+    async fn wait_for_room(&mut self, cmds: usize) -> Result<()> {
+        if self.in_flight.is_empty() || self.in_flight.len() + cmds <= self.max_in_flight {
+            return Ok(());
+        }
+        // Anything queued by `send_many` has to reach the API before it can be responded to.
+        self.transport.flush().await.map_err(|e| self.failed(e))?;
+        let response_timeout = self.response_timeout;
+        let cancellation = self.cancellation.clone();
+        let room = async {
+            while !self.in_flight.is_empty() && self.in_flight.len() + cmds > self.max_in_flight {
+                self.receive_watched().await?;
+            }
+            Ok(())
+        };
+        let room = async {
+            let Some(response_timeout) = response_timeout else {
+                return room.await.map_err(Some);
+            };
+            match timeout(response_timeout, room).await {
+                Ok(room) => room.map_err(Some),
+                // Which commands are stuck is only known once `self` is free again.
+                Err(_) => Err(None),
+            }
+        };
+        match Self::unless_cancelled(cancellation, room).await {
+            Some(Ok(())) => Ok(()),
+            Some(Err(Some(e))) => Err(e),
+            Some(Err(None)) => bail!(
+                "Waited {}s to send another command, but {} commands are still waiting for a \
+                response (the oldest is {}). The API may have dropped them.",
+                response_timeout.unwrap_or_default().as_secs(),
+                self.in_flight.len(),
+                self.in_flight[0].cmd_id
+            ),
+            None => Err(self.cancel()),
+        }
+    }
+
+    /// Remember that commands were sent, and are now waiting for responses.
+    fn start_waiting(&mut self, cmd_ids: &[Uuid], names: Vec<String>) {
+        let sent_at = Instant::now();
+        let mut names = names.into_iter();
+        let of = self.commands_sent + cmd_ids.len();
+        for &cmd_id in cmd_ids {
+            self.commands_sent += 1;
+            events::emit(Event::CommandSent {
+                n: self.commands_sent,
+                of,
+            });
+            self.in_flight.push(InFlight {
+                cmd_id,
+                name: names.next().unwrap_or_else(|| "unknown".to_owned()),
+                position: self.commands_sent,
+                sent_at,
+            });
+        }
+    }
+
+    /// Stop waiting for a command, now its response has arrived.
+    /// Commands wait in the order they were sent, so any waiting before it were sent earlier,
+    /// and their responses have been overtaken.
+    fn responded(&mut self, request_id: Uuid) -> Result<()> {
+        let Some(distance) = self.in_flight.iter().position(|c| c.cmd_id == request_id) else {
+            return Ok(());
+        };
+        let early = self.in_flight.remove(distance);
+        if distance == 0 {
+            return Ok(());
+        }
+        self.stats.update(|stats| {
+            let ordering = &mut stats.response_ordering;
+            ordering.out_of_order += 1;
+            ordering.max_distance = ordering.max_distance.max(distance);
+        });
+        let overtaken = &self.in_flight[0];
+        let e = OutOfOrder {
+            early: (early.position, early.name, early.cmd_id),
+            overtaken: (overtaken.position, overtaken.name.clone(), overtaken.cmd_id),
+            distance,
+        };
+        if self.strict_ordering {
+            return Err(e.into());
+        }
+        Warning::new(Code::OutOfOrder, e.to_string())
+            .cmd_id(e.early.2)
+            .raise()
+    }
+
+    /// Like `receive`, but fails if the oldest command still waiting for a response passes its
+    /// deadline first, naming the stuck command.
+    async fn receive_watched(&mut self) -> Result<()> {
+        let Some(oldest) = self.in_flight.first() else {
+            return self.receive().await;
+        };
+        let left = self
+            .command_deadline
+            .saturating_sub(oldest.sent_at.elapsed());
+        match timeout(left, self.receive()).await {
+            Ok(received) => received,
+            Err(_) => {
+                let stuck = &self.in_flight[0];
+                let completed = self.commands_sent - self.in_flight.len();
+                bail!(
+                    "Command #{} ({}, {}) has waited more than {}s for a response, so it seems stuck. \
+                    {completed} of the {} commands sent had been responded to.",
+                    stuck.position,
+                    stuck.name,
+                    stuck.cmd_id,
+                    self.command_deadline.as_secs_f64(),
+                    self.commands_sent
+                )
+            }
+        }
+    }
+
+    /// Send a failed command again, with a new ID, after waiting a little longer each time.
+    async fn resend(&mut self, cmd: ModelingCmd, first_id: Uuid, attempt: u32) -> Result<()> {
+        let backoff = retry_backoff(attempt);
+        let cmd_id = self.new_id();
+        say!(
+            "Command {first_id} failed, so it's being sent again as {cmd_id} in {}ms (retry {attempt} of {})",
+            backoff.as_millis(),
+            self.retry.retries
+        );
+        tokio::time::sleep(backoff).await;
+        let msg = serde_json::to_string(&WebSocketRequest::ModelingCmdReq {
+            cmd: self.rounded(cmd.clone())?,
+            cmd_id,
+        })?;
+        self.check_size(&msg)?;
+        let names = cmd_names(&msg);
+        self.transport.send(msg).await.map_err(|e| self.failed(e))?;
+        self.start_waiting(&[cmd_id], names);
+        self.retryable.insert(cmd_id, (cmd, attempt));
+        self.retried.insert(cmd_id, first_id);
+        Ok(())
+    }
+
+    /// Answer the API's request for the client's metrics. This client doesn't play the API's video stream,
+    /// so every WebRTC counter is zero.
+    async fn answer_metrics_request(&mut self) -> Result<()> {
+        let metrics = ClientMetrics {
+            rtc_frames_decoded: 0,
+            rtc_frames_dropped: 0,
+            rtc_frames_per_second: 0,
+            rtc_frames_received: 0,
+            rtc_freeze_count: 0,
+            rtc_jitter_sec: 0.0,
+            rtc_keyframes_decoded: 0,
+            rtc_total_freezes_duration_sec: 0.0,
+        };
+        let msg = serde_json::to_string(&WebSocketRequest::MetricsResponse { metrics })?;
+        self.transport.send(msg).await.map_err(|e| self.failed(e))?;
+        self.stats.update(|stats| stats.metrics_requests += 1);
+        Ok(())
+    }
+
+    /// The error for losing the connection. If the API hadn't successfully responded to anything yet,
+    /// it's a `ServerUnavailable`, so the run can connect again.
+    fn lost(&self, e: impl Into<Report>) -> Report {
+        let e = e.into();
+        if self.responded {
+            return e;
+        }
+        ServerUnavailable { how: e.to_string() }.into()
+    }
+
+    /// The error for a message which couldn't be sent or received.
+    fn failed(&self, e: TransportError) -> Report {
+        match e {
+            TransportError::WebSocket(e) => self.lost(ConnectionLost::from(*e)),
+            TransportError::TooBig { size, limit } => MessageTooBig {
+                sending: false,
+                size,
+                limit,
+            }
+            .into(),
+            TransportError::OutOfDate(e) => e.into(),
+            TransportError::Record(e) => e,
+        }
+    }
+
+    /// Read WebSocket messages until one of them responds to a command,
+    /// and keep the response until somebody awaits it.
+    async fn receive(&mut self) -> Result<()> {
+        loop {
+            let idle_timeout = self.idle_timeout;
+            let Ok(msg) = timeout(idle_timeout, self.transport.next()).await else {
+                return Err(ConnectionLost(format!(
+                    "The API sent nothing at all for {}s, not even a ping",
+                    idle_timeout.as_secs()
+                ))
+                .into());
+            };
+            let Some(msg) = msg else {
+                return Err(self.lost(ConnectionLost(
+                    "The WebSocket closed before the API responded".to_owned(),
+                )));
+            };
+            // We're looking for a WebSocket response with text.
+            let msg = msg.map_err(|e| self.failed(e))?;
+            let text = match msg {
+                WsMsg::Text(text) => text,
+                // The WebSocket library queues a Pong with the same payload as soon as it reads
+                // a Ping, but only sends it with the next write, so send it now.
+                WsMsg::Ping(_) => {
+                    self.transport.flush().await.map_err(|e| self.failed(e))?;
+                    continue;
+                }
+                WsMsg::Close(frame) => {
+                    return Err(self.lost(match frame {
+                        Some(frame) => SessionClosed {
+                            code: frame.code.into(),
+                            reason: frame.reason.into_owned(),
+                        },
+                        None => SessionClosed {
+                            code: 1005,
+                            reason: String::new(),
+                        },
+                    }))
+                }
+                // Ignore any other type of WebSocket messages.
+                _ => continue,
+            };
+            // What did the WebSocket response contain?
+            // It should either match the KittyCAD successful response schema, or the failed response schema.
+            // Exports can be big, so the text is dropped as soon as it's decoded,
+            // and the decoded files are handed over without being copied.
+            let decoded = match decode_websocket_text(&text) {
+                Ok(decoded) => decoded,
+                Err(unrecognized) => {
+                    // Skipping a response to a command we're waiting for would leave it stuck.
+                    let awaited = unrecognized
+                        .request_id
+                        .filter(|id| self.in_flight.iter().any(|c| c.cmd_id == *id));
+                    if let Some(request_id) = awaited {
+                        self.in_flight.retain(|c| c.cmd_id != request_id);
+                        self.retryable.remove(&request_id);
+                        self.retried.remove(&request_id);
+                    }
+                    if self.strict || awaited.is_some() {
+                        return Err(unrecognized.into());
+                    }
+                    self.stats.update(|stats| stats.unrecognized_responses += 1);
+                    let message = format!("{unrecognized}\n(Skipped it, and carried on.)");
+                    Warning::new(Code::UnrecognizedResponse, message).raise()?;
+                    continue;
+                }
+            };
+            drop(text);
+            self.responded |= decoded.is_ok();
+            let request_id = match &decoded {
+                Ok((request_id, _)) => *request_id,
+                Err(failure) => failure.request_id,
+            };
+            if let Some(request_id) = request_id {
+                let mut first = false;
+                self.stats.update(|stats| {
+                    first = stats.first_request_id.is_none();
+                    stats.first_request_id.get_or_insert(request_id);
+                });
+                if first {
+                    say!("First request ID: {request_id}");
+                }
+            }
+            // The API asks for these now and then, on its own, to check the client is healthy.
+            // They don't answer a command, so they're answered here and don't go in `unclaimed`.
+            if let Ok((_, OkWebSocketResponseData::MetricsRequest {})) = &decoded {
+                self.answer_metrics_request().await?;
+                continue;
+            }
+            // Nothing's waiting for these any more, so whatever they say doesn't matter.
+            if request_id.is_some_and(|id| self.abandoned.contains(&id)) {
+                continue;
+            }
+            match decoded {
+                // Success!
+                Ok((Some(request_id), resp)) => {
+                    events::emit(Event::ResponseReceived {
+                        cmd_id: request_id,
+                        ok: true,
+                    });
+                    self.responded(request_id)?;
+                    self.retryable.remove(&request_id);
+                    let cmd_id = self.retried.remove(&request_id).unwrap_or(request_id);
+                    self.unclaimed.insert(cmd_id, resp);
+                    return Ok(());
+                }
+                // Success, but not a response to any command
+                Ok((None, _)) => {}
+                // Failure. Commands are run in order, so if an earlier command failed,
+                // the ones after it probably can't succeed either.
+                Err(failure) => {
+                    let Some(request_id) = failure.request_id else {
+                        bail!(
+                            "KittyCAD API responded with an error: {}",
+                            describe_failure(&failure)
+                        )
+                    };
+                    events::emit(Event::ResponseReceived {
+                        cmd_id: request_id,
+                        ok: false,
+                    });
+                    self.responded(request_id)?;
+                    let first_id = self.retried.remove(&request_id).unwrap_or(request_id);
+                    let retry = self.retryable.remove(&request_id);
+                    let worth_retrying = failure
+                        .errors
+                        .iter()
+                        .all(|e| self.retry.error_codes.contains(&e.error_code));
+                    match retry {
+                        Some((cmd, attempts)) if worth_retrying && attempts < self.retry.retries => {
+                            self.resend(cmd, first_id, attempts + 1).await?;
+                            return Ok(());
+                        }
+                        Some((_, attempts)) if worth_retrying => bail!(
+                            "KittyCAD API responded with an error, even after {attempts} retries: {}",
+                            describe_failure(&failure)
+                        ),
+                        _ => bail!(
+                            "KittyCAD API responded with an error: {}",
+                            describe_failure(&failure)
+                        ),
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Drop for ModelingSession {
+    fn drop(&mut self) {
+        timings::record(&self.timings());
+    }
+}
+
+/// The WebSocket connection to the API failed, so the session can't be used any more.
+/// Other errors (e.g. the API rejecting a command) leave the session usable.
+#[derive(Debug)]
+pub struct ConnectionLost(pub String);
+
+impl std::fmt::Display for ConnectionLost {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Lost the connection to the API: {}", self.0)
+    }
+}
+
+impl std::error::Error for ConnectionLost {}
+
+impl From<tokio_tungstenite::tungstenite::Error> for ConnectionLost {
+    fn from(e: tokio_tungstenite::tungstenite::Error) -> Self {
+        Self(e.to_string())
+    }
+}
+
+impl ConnectionLost {
+    /// Was this error (or anything that caused it) a lost connection?
+    /// That includes the API closing the connection.
+    pub fn caused(e: &color_eyre::Report) -> bool {
+        e.chain().any(|cause| {
+            cause.is::<Self>()
+                || cause.is::<SessionClosed>()
+                || cause.is::<ServerUnavailable>()
+                || cause
+                    .downcast_ref::<MessageTooBig>()
+                    .is_some_and(|e| !e.sending)
+        })
+    }
+}
+
+/// The session's cancellation token was cancelled (see `ModelingSession::set_cancellation`),
+/// so it stopped waiting for the API. The session can still be used.
+#[derive(Debug)]
+pub struct Cancelled {
+    /// What the session was doing.
+    pub phase: Phase,
+    /// The commands which were still waiting for a response, and now never will be.
+    pub abandoned: Vec<Uuid>,
+}
+
+impl Cancelled {
+    /// Was this error (or anything that caused it) a cancellation?
+    pub fn caused(e: &color_eyre::Report) -> bool {
+        e.chain().any(|cause| cause.is::<Self>())
+    }
+}
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Cancelled during the {:?} phase", self.phase)?;
+        match self.abandoned.len() {
+            0 => Ok(()),
+            1 => write!(f, ", abandoning 1 command which was waiting for a response"),
+            n => write!(
+                f,
+                ", abandoning {n} commands which were waiting for a response"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// The API responded to a command before a command sent earlier, which was still waiting.
+#[derive(Debug)]
+pub struct OutOfOrder {
+    /// The command whose response arrived early: where it came in the session, its type, and its ID.
+    pub early: (usize, String, Uuid),
+    /// The oldest command it overtook, the same way.
+    pub overtaken: (usize, String, Uuid),
+    /// How many commands sent before it were still waiting.
+    pub distance: usize,
+}
+
+impl std::fmt::Display for OutOfOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (early, overtaken) = (&self.early, &self.overtaken);
+        write!(
+            f,
+            "The API responded to command #{} ({}, {}) before command #{} ({}, {}), which was sent first. \
+            It overtook {} command{}",
+            early.0,
+            early.1,
+            early.2,
+            overtaken.0,
+            overtaken.1,
+            overtaken.2,
+            self.distance,
+            if self.distance == 1 { "" } else { "s" }
+        )
+    }
+}
+
+impl std::error::Error for OutOfOrder {}
+
+/// A WebSocket message was bigger than the session's limit.
+/// Receiving one loses the connection, because the rest of the message can't be skipped.
+/// Sending one is refused before anything is sent, so the session can carry on.
+#[derive(Debug)]
+pub struct MessageTooBig {
+    /// Whether we were sending the message, rather than receiving it.
+    pub sending: bool,
+    /// How big the message (or, when receiving, the part of it read so far) was, in bytes.
+    pub size: usize,
+    /// The limit, in bytes.
+    pub limit: usize,
+}
+
+impl std::fmt::Display for MessageTooBig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (what, tip) = if self.sending {
+            (
+                "Can't send a message",
+                "Send fewer commands at once, or raise the limit",
+            )
+        } else {
+            (
+                "The API sent a message",
+                "Raise the limit to receive it, e.g. for a big export",
+            )
+        };
+        write!(
+            f,
+            "{what} of {} bytes, which is more than the WebSocket message size limit of {} bytes. {tip}",
+            self.size, self.limit
+        )
+    }
+}
+
+impl std::error::Error for MessageTooBig {}
+
+/// The API closed the WebSocket connection, e.g. because it's being redeployed.
+#[derive(Debug)]
+pub struct SessionClosed {
+    /// The WebSocket close code, e.g. 1001 if the server is going away.
+    /// 1005 means the API didn't send one.
+    pub code: u16,
+    /// Why the API closed the connection, if it said.
+    pub reason: String,
+}
+
+impl std::fmt::Display for SessionClosed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The API closed the connection (code {})", self.code)?;
+        if !self.reason.is_empty() {
+            write!(f, ": {}", self.reason)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for SessionClosed {}
+
+/// The API closed the connection before successfully responding to anything, as it does for a
+/// few seconds while it's being deployed. Nothing was built, so it's worth connecting again.
+#[derive(Debug)]
+pub struct ServerUnavailable {
+    /// How the connection ended, e.g. the close code and reason, or the error writing to it.
+    pub how: String,
+}
+
+impl ServerUnavailable {
+    /// Was this error (or anything that caused it) the API being unavailable?
+    pub fn caused(e: &color_eyre::Report) -> bool {
+        e.chain().any(|cause| cause.is::<Self>())
+    }
+}
+
+impl std::fmt::Display for ServerUnavailable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The API closed the session before responding to anything, so it's probably unavailable \
+             for a moment, e.g. while it's being deployed ({})",
+            self.how
+        )
+    }
+}
+
+impl std::error::Error for ServerUnavailable {}
+
+/// Given the text from a WebSocket, deserialize its JSON.
+/// Returns OK if the WebSocket's JSON represents a successful response.
+/// Returns an error if the WebSocket's JSON represented a failure response.
+/// Returns an unrecognized response if the JSON is neither, e.g. because the API is newer than the kittycad crate.
+///
+/// This isn't streamed: the message's whole text has been received before it's decoded. An export's
+/// files are sent as lists of numbers, about 4 bytes of text a byte, so an export of N bytes takes
+/// about 4N for its message, and up to 2N more while its files are decoded from it: about 6N at once.
+/// --max-ws-message-bytes limits the message, and so that peak.
+fn decode_websocket_text(
+    text: &str,
+) -> std::result::Result<
+    std::result::Result<(Option<Uuid>, OkWebSocketResponseData), FailureWebSocketResponse>,
+    UnrecognizedResponse,
+> {
+    let unrecognized = |request_id, problem| UnrecognizedResponse {
+        request_id,
+        problem,
+        payload: preview(text),
+    };
+    // Only read the fields which say which schema it is, and skip the rest, which for an export is
+    // its files: as a `serde_json::Value`, each of their bytes would take dozens.
+    #[derive(Default, serde::Deserialize)]
+    struct Outline {
+        success: Option<serde_json::Value>,
+        request_id: Option<serde_json::Value>,
+        resp: Option<OutlineResp>,
+    }
+    #[derive(serde::Deserialize)]
+    struct OutlineResp {
+        #[serde(rename = "type")]
+        kind: Option<serde_json::Value>,
+    }
+    // A tagged enum like `OkWebSocketResponseData` is buffered whole, dozens of bytes a byte,
+    // before its variant is read, so an export is read into a plain struct of its own instead.
+    #[derive(serde::Deserialize)]
+    struct Export {
+        request_id: Option<Uuid>,
+        resp: ExportResp,
+    }
+    #[derive(serde::Deserialize)]
+    struct ExportResp {
+        data: ExportData,
+    }
+    #[derive(serde::Deserialize)]
+    struct ExportData {
+        files: Vec<RawFile>,
+    }
+    let outline = match serde_json::from_str::<Outline>(text) {
+        Ok(outline) => outline,
+        Err(e) if e.is_data() => Outline::default(),
+        Err(e) => return Err(unrecognized(None, format!("not JSON: {e}"))),
+    };
+    let request_id = outline
+        .request_id
+        .as_ref()
+        .and_then(|id| id.as_str())
+        .and_then(|id| Uuid::parse_str(id).ok());
+    // Which fields the response schemas don't have, which are the likeliest reason it didn't parse.
+    let unknown_fields = || -> String {
+        const KNOWN: [&str; 4] = ["success", "request_id", "resp", "errors"];
+        let fields = serde_json::from_str::<BTreeMap<String, IgnoredAny>>(text).unwrap_or_default();
+        let unknown: Vec<_> = fields
+            .keys()
+            .filter(|field| !KNOWN.contains(&field.as_str()))
+            .map(|field| format!("`{field}`"))
+            .collect();
+        match unknown.as_slice() {
+            [] => String::new(),
+            _ => format!(" (unknown fields: {})", unknown.join(", ")),
+        }
+    };
+    // Decide which schema to parse with by the `success` field, rather than trying both,
+    // so the error says what's wrong with the response instead of just that neither matched.
+    match outline
+        .success
+        .as_ref()
+        .and_then(|success| success.as_bool())
+    {
+        Some(true)
+            if outline
+                .resp
+                .and_then(|resp| resp.kind)
+                .is_some_and(|kind| kind == "export") =>
+        {
+            let e: Export = serde_json::from_str(text)
+                .map_err(|e| unrecognized(request_id, format!("{e}{}", unknown_fields())))?;
+            let files = e.resp.data.files;
+            Ok(Ok((
+                e.request_id,
+                OkWebSocketResponseData::Export { files },
+            )))
+        }
+        Some(true) => {
+            let s: SuccessWebSocketResponse = serde_json::from_str(text)
+                .map_err(|e| unrecognized(request_id, format!("{e}{}", unknown_fields())))?;
+            Ok(Ok((s.request_id, s.resp)))
+        }
+        Some(false) => {
+            let f: FailureWebSocketResponse = serde_json::from_str(text)
+                .map_err(|e| unrecognized(request_id, format!("{e}{}", unknown_fields())))?;
+            Ok(Err(f))
+        }
+        None => Err(unrecognized(
+            request_id,
+            format!(
+                "it has no true or false `success` field{}",
+                unknown_fields()
+            ),
+        )),
+    }
+}
+
+/// The start of a message, for printing, with "..." if it's been cut short.
+fn preview(text: &str) -> String {
+    match text.char_indices().nth(UNRECOGNIZED_PREVIEW) {
+        Some((at, _)) => format!("{}...", &text[..at]),
+        None => text.to_owned(),
+    }
+}
+
+/// A message from the API which is neither a successful response nor a failure response,
+/// as far as this version of the kittycad crate knows.
+#[derive(Debug)]
+pub struct UnrecognizedResponse {
+    /// Which command it responds to, if it says and that much could be read.
+    pub request_id: Option<Uuid>,
+    /// Why it couldn't be parsed.
+    pub problem: String,
+    /// The start of the message.
+    pub payload: String,
+}
+
+impl std::fmt::Display for UnrecognizedResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The API sent a response which couldn't be parsed")?;
+        if let Some(request_id) = self.request_id {
+            write!(f, " (command {request_id})")?;
+        }
+        write!(f, ": {}\n{}", self.problem, self.payload)
+    }
+}
+
+impl std::error::Error for UnrecognizedResponse {}
+
+/// Summarize a failure response in a human-readable way,
+/// e.g. "bad_request: Cannot compute the volume of an open path (command 1234...)".
+fn describe_failure(failure: &FailureWebSocketResponse) -> String {
+    let errors: Vec<_> = failure
+        .errors
+        .iter()
+        .map(|e| format!("{}: {}", e.error_code, e.message))
+        .collect();
+    match failure.request_id {
+        Some(cmd_id) => format!("{} (command {cmd_id})", errors.join("; ")),
+        None => errors.join("; "),
+    }
+}
+
+/// The types of the modeling commands in a request, e.g. "extrude", in order.
+/// These are read from the JSON we're about to send, because serializing the commands again
+/// just to name them could be slow for commands carrying files.
+fn cmd_names(msg: &str) -> Vec<String> {
+    const PREFIX: &str = "\"cmd\":{\"type\":\"";
+    msg.match_indices(PREFIX)
+        .filter_map(|(at, _)| {
+            let rest = &msg[at + PREFIX.len()..];
+            Some(rest[..rest.find('"')?].to_owned())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+impl ModelingSession {
+    /// A session for unit tests, which answers each command sent to it with the next of these
+    /// responses: the type of command it answers (e.g. "get_entity_type"), and its
+    /// `modeling_response`. A command of any other type fails, as it would playing back a
+    /// recording which is out of date.
+    pub(crate) fn canned(test: &str, responses: &[(&str, serde_json::Value)]) -> Self {
+        use serde_json::json;
+        use std::fmt::Write;
+        let mut recording = String::new();
+        for (cmd, response) in responses {
+            // Random, so it's never the same as an ID in a response, which would be swapped for
+            // the ID the command was sent with.
+            let cmd_id = Uuid::new_v4();
+            let sent = json!({
+                "sent": { "type": "modeling_cmd_req", "cmd": { "type": cmd }, "cmd_id": cmd_id },
+            });
+            let received = json!({
+                "received": {
+                    "success": true,
+                    "request_id": cmd_id,
+                    "resp": { "type": "modeling", "data": { "modeling_response": response } },
+                },
+            });
+            writeln!(recording, "{sent}\n{received}").unwrap();
+        }
+        let path = std::env::temp_dir().join(format!(
+            "kittycad-canned-{test}-{}.jsonl",
+            std::process::id()
+        ));
+        std::fs::write(&path, recording).unwrap();
+        Self::replay(&path, usize::MAX, ConnectionProfile::default()).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{counting, warnings};
+
+    #[test]
+    fn waits_twice_as_long_before_each_retry_up_to_a_limit() {
+        let waits: Vec<_> = (1..=9).map(retry_backoff).collect();
+        let millis = [250, 500, 1000, 2000, 4000, 8000, 16000, 30000, 30000];
+        assert_eq!(waits, millis.map(Duration::from_millis));
+        // However many retries are allowed, the wait never overflows.
+        assert_eq!(retry_backoff(33), MAX_RETRY_BACKOFF);
+        assert_eq!(retry_backoff(u32::MAX), MAX_RETRY_BACKOFF);
+    }
+
+    #[test]
+    fn waits_twice_as_long_before_each_new_session_up_to_a_limit() {
+        let waits: Vec<_> = (1..=7).map(session_retry_delay).collect();
+        let secs = [1, 2, 4, 8, 16, 30, 30];
+        assert_eq!(waits, secs.map(Duration::from_secs));
+        assert_eq!(session_retry_delay(65), MAX_SESSION_RETRY_DELAY);
+        assert_eq!(session_retry_delay(u32::MAX), MAX_SESSION_RETRY_DELAY);
+    }
+
+    #[test]
+    fn counts_every_moment_of_a_session_in_exactly_one_phase() {
+        let mut session = ModelingSession::canned("phases", &[]);
+        let phases = [
+            Phase::Camera,
+            Phase::Snapshot,
+            Phase::Save,
+            Phase::Snapshot,
+            Phase::Build,
+            Phase::Export,
+            Phase::Export,
+            Phase::Save,
+        ];
+        let before = Instant::now();
+        let first = session.timings();
+        let after_first = Instant::now();
+        for phase in phases {
+            session.enter_phase(phase);
+            std::thread::sleep(Duration::from_millis(2));
+        }
+        let before_last = Instant::now();
+        let last = session.timings();
+        let after = Instant::now();
+        // However long the phases took, they add up to the time between the two looks.
+        let spent = last.total() - first.total();
+        assert!(spent <= after - before, "{spent:?} > {:?}", after - before);
+        assert!(
+            spent >= before_last - after_first,
+            "{spent:?} < {:?}",
+            before_last - after_first
+        );
+        for phase in [Phase::Camera, Phase::Snapshot, Phase::Save, Phase::Export] {
+            assert!(
+                phase_time(&last, phase) >= Duration::from_millis(2),
+                "{phase:?}: {last}"
+            );
+        }
+    }
+
+    /// How long some timings say was spent on a phase.
+    fn phase_time(timings: &Timings, phase: Phase) -> Duration {
+        match phase {
+            Phase::Connect => timings.connect,
+            Phase::Build => timings.build,
+            Phase::Camera => timings.camera,
+            Phase::Snapshot => timings.snapshot,
+            Phase::Save => timings.save,
+            Phase::Export => timings.export,
+        }
+    }
+
+    #[test]
+    fn decodes_a_big_export_holding_it_only_once_more() {
+        // 4MB, sent as the API sends it: a list of numbers, about 4 bytes of text a byte.
+        let size = 4 << 20;
+        let contents: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+        let response = serde_json::json!({
+            "success": true,
+            "request_id": Uuid::nil(),
+            "resp": {
+                "type": "export",
+                "data": { "files": [RawFile { name: "model.stl".to_owned(), contents: contents.clone() }] },
+            },
+        });
+        let text = response.to_string();
+        drop(response);
+        let (decoded, peak) = counting::peak_while(|| decode_websocket_text(&text));
+        let Ok(Ok((_, OkWebSocketResponseData::Export { files }))) = decoded else {
+            panic!("Expected the response to decode to an export");
+        };
+        assert!(files[0].contents == contents);
+        // The file's bytes, which may have had up to twice the room while they were read.
+        assert!(
+            peak <= size * 2,
+            "Decoding a {} byte response with {size} bytes in it held {peak} bytes at once",
+            text.len()
+        );
+    }
+
+    /// Why a message couldn't be decoded, and which command it said it was for.
+    fn unrecognized(text: &str) -> (String, Option<Uuid>) {
+        match decode_websocket_text(text) {
+            Err(e) => (e.problem, e.request_id),
+            Ok(_) => panic!("Expected {text} not to decode"),
+        }
+    }
+
+    #[test]
+    fn names_an_unknown_type_of_response_and_the_command_it_was_for() {
+        let id = Uuid::new_v4();
+        let text = format!(
+            r#"{{"success": true, "request_id": "{id}", "resp": {{"type": "hologram", "data": {{}}}}}}"#
+        );
+        let (problem, request_id) = unrecognized(&text);
+        assert!(problem.contains("unknown variant `hologram`"), "{problem}");
+        assert_eq!(request_id, Some(id));
+    }
+
+    #[test]
+    fn names_a_missing_field() {
+        let (problem, request_id) =
+            unrecognized(r#"{"success": true, "resp": {"type": "modeling"}}"#);
+        assert!(problem.contains("missing field `data`"), "{problem}");
+        assert_eq!(request_id, None);
+        let (problem, _) = unrecognized(r#"{"success": false, "request_id": null}"#);
+        assert!(problem.contains("missing field `errors`"), "{problem}");
+    }
+
+    #[test]
+    fn names_fields_the_schemas_dont_have_when_success_is_missing() {
+        let (problem, _) = unrecognized(r#"{"status": "ok", "resp": {}, "when": 3}"#);
+        assert_eq!(
+            problem,
+            "it has no true or false `success` field (unknown fields: `status`, `when`)"
+        );
+        let (problem, _) = unrecognized(r#"{"success": "yes"}"#);
+        assert_eq!(problem, "it has no true or false `success` field");
+    }
+
+    #[test]
+    fn says_when_a_message_isnt_json() {
+        let (problem, request_id) = unrecognized("Service Unavailable");
+        assert!(problem.starts_with("not JSON: "), "{problem}");
+        assert_eq!(request_id, None);
+        // An outline which doesn't fit is still JSON, and is read as far as it can be.
+        let (problem, _) = unrecognized(r#"{"success": true, "resp": "export"}"#);
+        assert!(!problem.starts_with("not JSON"), "{problem}");
+    }
+
+    #[test]
+    fn reads_an_export_without_its_files_as_unrecognized() {
+        let id = Uuid::new_v4();
+        let text = format!(
+            r#"{{"success": true, "request_id": "{id}", "resp": {{"type": "export", "data": {{}}}}}}"#
+        );
+        let (problem, request_id) = unrecognized(&text);
+        assert!(problem.contains("missing field `files`"), "{problem}");
+        assert_eq!(request_id, Some(id));
+    }
+
+    #[test]
+    fn reads_small_exports_and_failures_as_the_schemas_say() {
+        let id = Uuid::new_v4();
+        let export = format!(
+            r#"{{"success": true, "request_id": "{id}", "resp": {{"type": "export", "data": {{"files": [{{"name": "a.stl", "contents": [115, 111, 108, 105, 100]}}]}}}}}}"#
+        );
+        let Ok(Ok((Some(request_id), OkWebSocketResponseData::Export { files }))) =
+            decode_websocket_text(&export)
+        else {
+            panic!("Expected {export} to decode to an export");
+        };
+        assert_eq!(request_id, id);
+        assert_eq!(
+            (files[0].name.as_str(), files[0].contents.as_slice()),
+            ("a.stl", &b"solid"[..])
+        );
+        let failure = format!(
+            r#"{{"success": false, "request_id": "{id}", "errors": [{{"error_code": "bad_request", "message": "no"}}]}}"#
+        );
+        let Ok(Err(failure)) = decode_websocket_text(&failure) else {
+            panic!("Expected {failure} to decode to a failure");
+        };
+        assert_eq!(failure.request_id, Some(id));
+        assert_eq!(failure.errors[0].message, "no");
+    }
+
+    #[test]
+    fn adds_up_what_sessions_sharing_stats_notice() {
+        let stats = RunStats::default();
+        for _ in 0..2 {
+            let mut session = replaying("unrecognized.jsonl", usize::MAX);
+            session.share_stats(stats.clone());
+            run(entity_types(&mut session, 1)).unwrap();
+        }
+        stats.count_session_retry();
+        let stats = stats.get();
+        assert_eq!(stats.unrecognized_responses, 6);
+        assert_eq!(stats.session_retries, 1);
+        // A session which doesn't share them keeps its own.
+        let mut session = replaying("unrecognized.jsonl", usize::MAX);
+        run(entity_types(&mut session, 1)).unwrap();
+        assert_eq!(session.stats().unrecognized_responses, 3);
+    }
+
+    #[test]
+    fn skips_responses_it_cant_read_which_nothing_is_waiting_for() {
+        let mut session = replaying("unrecognized.jsonl", usize::MAX);
+        let found = run(entity_types(&mut session, 1)).unwrap();
+        assert_eq!(found, ["solid3d"]);
+        assert_eq!(session.stats().unrecognized_responses, 3);
+        let skipped: Vec<_> = warnings::warnings()
+            .into_iter()
+            .filter(|w| w.code == Code::UnrecognizedResponse)
+            .map(|w| w.message)
+            .collect();
+        for problem in [
+            "unknown variant `hologram`",
+            "missing field `data`",
+            "unknown fields: `status`",
+        ] {
+            assert!(
+                skipped
+                    .iter()
+                    .any(|m| m.contains(problem) && m.ends_with("(Skipped it, and carried on.)")),
+                "No warning about {problem} in {skipped:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn fails_on_the_first_response_it_cant_read_when_strict() {
+        let mut session = replaying("unrecognized.jsonl", usize::MAX);
+        session.set_strict(true);
+        let e = error(run(entity_types(&mut session, 1)).unwrap_err());
+        assert!(
+            e.starts_with("The API sent a response which couldn't be parsed (command 0e9c5b61-")
+                && e.contains("unknown variant `hologram`"),
+            "{e}"
+        );
+    }
+
+    #[test]
+    fn fails_on_a_response_it_cant_read_to_a_command_being_waited_for() {
+        let mut session = replaying("unrecognized-awaited.jsonl", usize::MAX);
+        let e = error(run(entity_types(&mut session, 1)).unwrap_err());
+        assert!(
+            e.starts_with("The API sent a response which couldn't be parsed (command ")
+                && e.contains("unknown variant `hologram`"),
+            "{e}"
+        );
+    }
+
+    /// A session playing back one of the recordings in tests/recordings.
+    fn replaying(name: &str, max_message_bytes: usize) -> ModelingSession {
+        let recording = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/recordings")
+            .join(name);
+        ModelingSession::replay(&recording, max_message_bytes, ConnectionProfile::default())
+            .unwrap()
+    }
+
+    fn run<T>(future: impl std::future::Future<Output = T>) -> T {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    /// Ask what each of `count` entities is, all at once, and wait for the answers in order.
+    async fn entity_types(session: &mut ModelingSession, count: usize) -> Result<Vec<String>> {
+        let cmds: Vec<_> = (0..count)
+            .map(|_| ModelingCmd::GetEntityType {
+                entity_id: Uuid::new_v4(),
+            })
+            .collect();
+        let mut found = Vec::new();
+        for cmd_id in session.send_many(&cmds).await? {
+            let response = serde_json::to_value(session.await_response(cmd_id).await?)?;
+            found.push(response["data"]["entity_type"].as_str().unwrap().to_owned());
+        }
+        Ok(found)
+    }
+
+    /// What a session sent (s) and received (r), in order, from its recording.
+    fn order(trace: &Path) -> String {
+        std::fs::read_to_string(trace)
+            .unwrap()
+            .lines()
+            .map(|line| {
+                if line.starts_with(r#"{"sent""#) {
+                    's'
+                } else {
+                    'r'
+                }
+            })
+            .collect()
+    }
+
+    fn error(e: Report) -> String {
+        e.chain()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(": ")
+    }
+
+    #[test]
+    fn waits_for_room_in_the_window_before_sending_more() {
+        let trace = std::env::temp_dir().join(format!(
+            "kittycad-window-trace-{}.jsonl",
+            std::process::id()
+        ));
+        let mut session = replaying("window.jsonl", usize::MAX);
+        session.set_max_in_flight(2);
+        session.record(&trace).unwrap();
+        let found = run(entity_types(&mut session, 4)).unwrap();
+        assert_eq!(found, ["solid3d", "face", "edge", "plane"]);
+        // The third and fourth commands each waited for a response before they were sent.
+        assert_eq!(order(&trace), "ssrsrsrr");
+    }
+
+    #[test]
+    fn sends_everything_at_once_in_a_window_big_enough() {
+        let trace = std::env::temp_dir().join(format!(
+            "kittycad-wide-window-trace-{}.jsonl",
+            std::process::id()
+        ));
+        let mut session = replaying("window.jsonl", usize::MAX);
+        session.record(&trace).unwrap();
+        run(entity_types(&mut session, 4)).unwrap();
+        assert_eq!(order(&trace), "ssssrrrr");
+    }
+
+    #[test]
+    fn refuses_a_response_bigger_than_the_message_size_limit() {
+        let export = |max_message_bytes| {
+            let mut session = replaying("oversized.jsonl", max_message_bytes);
+            run(async {
+                let cmd_id = session
+                    .send(ModelingCmd::Export {
+                        entity_ids: vec![Uuid::new_v4()],
+                        format: crate::export::format_for(Path::new("model.stl"), "stl")?,
+                        source_unit: crate::units::SCENE_UNIT,
+                    })
+                    .await?;
+                session.await_export(cmd_id).await
+            })
+        };
+        let e = error(export(1024).unwrap_err());
+        assert!(
+            e.starts_with("The API sent a message of ")
+                && e.contains("more than the WebSocket message size limit of 1024 bytes"),
+            "{e}"
+        );
+        let files = export(4096).unwrap();
+        assert_eq!(files[0].contents.len(), 600);
+    }
+
+    #[test]
+    fn carries_on_past_a_response_which_overtakes_an_earlier_command() {
+        let mut session = replaying("out-of-order.jsonl", usize::MAX);
+        let found = run(entity_types(&mut session, 2)).unwrap();
+        assert_eq!(found, ["solid3d", "face"]);
+    }
+
+    #[test]
+    fn names_both_commands_when_a_response_overtakes_another_with_strict_ordering() {
+        let mut session = replaying("out-of-order.jsonl", usize::MAX);
+        session.set_strict_ordering(true);
+        let e = error(run(entity_types(&mut session, 2)).unwrap_err());
+        assert!(
+            e.starts_with("The API responded to command #2 (get_entity_type, ")
+                && e.contains(") before command #1 (get_entity_type, ")
+                && e.ends_with("), which was sent first. It overtook 1 command"),
+            "{e}"
+        );
+    }
+
+    #[test]
+    fn carries_on_with_the_next_command_after_being_cancelled() {
+        let mut session = replaying("cancelled.jsonl", usize::MAX);
+        let token = CancellationToken::new();
+        session.set_cancellation(Some(token.clone()));
+        let first = ModelingCmd::GetEntityType {
+            entity_id: Uuid::new_v4(),
+        };
+        let (cancelled, found) = run(async {
+            let cmd_id = session.send(first).await?;
+            token.cancel();
+            let cancelled = session.await_response(cmd_id).await.unwrap_err();
+            assert_eq!(session.abandoned(), [cmd_id]);
+            // The first command's response arrives now, and is ignored.
+            let found = entity_types(&mut session, 1).await?;
+            Ok::<_, Report>((cancelled, found))
+        })
+        .unwrap();
+        assert!(Cancelled::caused(&cancelled));
+        assert_eq!(
+            error(cancelled),
+            "Cancelled during the Build phase, abandoning 1 command which was waiting for a response"
+        );
+        assert_eq!(found, ["face"]);
+    }
+}
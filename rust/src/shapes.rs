@@ -0,0 +1,339 @@
+use crate::{
+    degenerate,
+    recipe::Recipe,
+    session::ModelingSession,
+    stroke, tessellation,
+    transform::{self, Transform},
+    warnings::{Code, Warning},
+};
+use color_eyre::{
+    eyre::{bail, WrapErr},
+    Result,
+};
+use kittycad::types::{Angle, ModelingCmd, PathSegment, Point2D, Point3D, UnitAngle};
+use uuid::Uuid;
+
+/// Draw a cube around the given center, and return its ID.
+/// Each side of the cube is 2 * width long.
+pub async fn draw_cube(
+    session: &mut ModelingSession,
+    center: &Point3D,
+    width: f64,
+) -> Result<Uuid> {
+    // The bottom face of the cube is a square, which we'll extrude upwards.
+    let corners = square_corners(center, width);
+    extrude_polygon(session, &corners, width * 2.0).await
+}
+
+/// The corners of the square at the bottom of a cube around the given center.
+pub fn square_corners(center: &Point3D, width: f64) -> [Point3D; 4] {
+    let z = center.z - width;
+    [
+        (center.x - width, center.y - width),
+        (center.x + width, center.y - width),
+        (center.x + width, center.y + width),
+        (center.x - width, center.y + width),
+    ]
+    .map(|(x, y)| Point3D { x, y, z })
+}
+
+/// Draw an L-shaped bracket, and return its ID.
+/// Its two legs are `length` long and `thickness` thick, and it's extruded by `depth`.
+/// The bracket's inner corner is at the origin, so its center of mass is not.
+pub async fn draw_l_bracket(
+    session: &mut ModelingSession,
+    length: f64,
+    thickness: f64,
+    depth: f64,
+) -> Result<Uuid> {
+    let corners = l_bracket_corners(length, thickness);
+    extrude_polygon(session, &corners, depth).await
+}
+
+/// The corners of an L-shaped bracket's profile, anticlockwise from the outside of its corner.
+pub fn l_bracket_corners(length: f64, thickness: f64) -> [Point3D; 6] {
+    [
+        (-thickness, -thickness),
+        (length, -thickness),
+        (length, 0.0),
+        (0.0, 0.0),
+        (0.0, length),
+        (-thickness, length),
+    ]
+    .map(|(x, y)| Point3D { x, y, z: 0.0 })
+}
+
+/// Draw a closed polygon through the given corners, extrude it by `distance`, and return the solid's ID.
+/// The corners should all have the same Z coordinate, and be counter-clockwise when seen from above,
+/// so that the polygon is extruded upwards.
+/// When this returns, the API has finished extruding, so the solid is ready to use.
+pub async fn extrude_polygon(
+    session: &mut ModelingSession,
+    corners: &[Point3D],
+    distance: f64,
+) -> Result<Uuid> {
+    let path_id = sketch_polygon(session, corners).await?;
+    extrude(session, path_id, distance).await?;
+    Ok(path_id)
+}
+
+/// Draw a wall `thickness` thick and `height` high along an open path, e.g. a wall layout,
+/// and return the solid's ID. The path is stroked, not filled: its outline is worked out here,
+/// offset half the thickness to each side, with mitered corners and square ends, then extruded.
+/// Corners too sharp for `miter_limit` (see `stroke::outline`) are bevelled, with a warning.
+/// The points should all have the same Z coordinate.
+pub async fn stroke_path(
+    session: &mut ModelingSession,
+    path: &[Point3D],
+    thickness: f64,
+    height: f64,
+    miter_limit: f64,
+) -> Result<Uuid> {
+    let Some(z) = path.first().map(|p| p.z) else {
+        bail!("Can't stroke a path with no points");
+    };
+    let points: Vec<_> = path.iter().map(|p| (p.x, p.y)).collect();
+    let stroke = stroke::outline(&points, thickness, miter_limit)?;
+    for (corner, degrees) in &stroke.bevelled {
+        let message = format!(
+            "The path turns by {degrees:.0} degrees at point {corner}, which is too sharp to miter \
+             with a miter limit of {miter_limit}, so the corner was bevelled"
+        );
+        Warning::new(Code::Bevelled, message)
+            .parameter("miter-limit")
+            .raise()?;
+    }
+    let corners: Vec<_> = stroke
+        .outline
+        .into_iter()
+        .map(|(x, y)| Point3D { x, y, z })
+        .collect();
+    extrude_polygon(session, &corners, height).await
+}
+
+/// Draw a closed polygon through the given corners, and return the path's ID.
+pub async fn sketch_polygon(session: &mut ModelingSession, corners: &[Point3D]) -> Result<Uuid> {
+    // Extend the path from the first corner to each other corner, and back to the start.
+    let segments = corners[1..]
+        .iter()
+        .chain(&corners[..1])
+        .map(|point| PathSegment::Line {
+            end: point.clone(),
+            relative: false,
+        })
+        .collect();
+    sketch_path(session, corners[0].clone(), segments).await
+}
+
+/// Draw a circle, flat on the XY plane (at the given center's height), and return the path's ID.
+/// If the run draws curves as lines (see --tessellation), it's a regular polygon with its corners
+/// on the circle, starting at 0 degrees.
+pub async fn sketch_circle(
+    session: &mut ModelingSession,
+    center: &Point3D,
+    radius: f64,
+) -> Result<Uuid> {
+    let Some(quality) = tessellation::quality() else {
+        return sketch_exact_circle(session, center, radius).await;
+    };
+    sketch_polygon(session, &circle_corners(center, radius, quality)).await
+}
+
+/// The corners of the regular polygon a circle is drawn as at this quality, starting at 0 degrees.
+pub fn circle_corners(
+    center: &Point3D,
+    radius: f64,
+    quality: tessellation::Quality,
+) -> Vec<Point3D> {
+    let n = tessellation::segments_for_radius(radius, quality);
+    (0..n)
+        .map(|i| {
+            let (sin, cos) = (2.0 * std::f64::consts::PI * i as f64 / n as f64).sin_cos();
+            Point3D {
+                x: center.x + radius * cos,
+                y: center.y + radius * sin,
+                z: center.z,
+            }
+        })
+        .collect()
+}
+
+/// Draw a circle as one arc, however the run draws curves, e.g. to measure the API's own circles.
+pub async fn sketch_exact_circle(
+    session: &mut ModelingSession,
+    center: &Point3D,
+    radius: f64,
+) -> Result<Uuid> {
+    // An arc starts wherever the pen is, so put the pen on the circle, at 0 degrees.
+    let start = Point3D {
+        x: center.x + radius,
+        ..center.clone()
+    };
+    let degrees = |value| Angle {
+        unit: UnitAngle::Degrees,
+        value,
+    };
+    let arc = PathSegment::Arc {
+        center: Point2D {
+            x: center.x,
+            y: center.y,
+        },
+        radius,
+        start: Some(degrees(0.0)),
+        end: Some(degrees(360.0)),
+        // These are deprecated in favour of `start` and `end`, but still required.
+        angle_start: 0.0,
+        angle_end: 360.0,
+        relative: false,
+    };
+    sketch_path(session, start, vec![arc]).await
+}
+
+/// Start a path at `start`, draw the given segments, close it, and return the path's ID.
+/// The path is moved by the session's placement before it's sent.
+pub async fn sketch_path(
+    session: &mut ModelingSession,
+    start: Point3D,
+    segments: Vec<PathSegment>,
+) -> Result<Uuid> {
+    let placement = session.placement().clone();
+    if placement.x == 0.0 && placement.y == 0.0 && placement.z == 0.0 {
+        return sketch_path_in_scene(session, start, segments).await;
+    }
+    let placed = Recipe::path(start, segments).transformed(&Transform {
+        translate: placement,
+        ..Transform::default()
+    });
+    sketch_path_in_scene(session, placed.start, placed.segments).await
+}
+
+/// Like `sketch_path`, but the points are already where they belong in the scene,
+/// so the placement doesn't move them, e.g. when rebuilding a recipe.
+async fn sketch_path_in_scene(
+    session: &mut ModelingSession,
+    start: Point3D,
+    segments: Vec<PathSegment>,
+) -> Result<Uuid> {
+    if !session.allows_degenerate() {
+        degenerate::check(&start, &segments)?;
+    }
+    // Create a new empty path.
+    let path_id = session.new_id();
+    session
+        .send_with_id(ModelingCmd::StartPath {}, path_id)
+        .await?;
+    // Send the rest of the path's commands together, so the WebSocket is only flushed once.
+    let mut cmds = vec![ModelingCmd::MovePathPen {
+        path: path_id,
+        to: start.clone(),
+    }];
+    cmds.extend(segments.iter().map(|segment| ModelingCmd::ExtendPath {
+        path: path_id,
+        segment: segment.clone(),
+    }));
+    cmds.push(ModelingCmd::ClosePath { path_id });
+    let cmd_ids = session.send_many(&cmds).await?;
+    let close_id = *cmd_ids.last().unwrap();
+    session.await_response(close_id).await?;
+
+    session.record_recipe(path_id, Recipe::path(start, segments));
+    Ok(path_id)
+}
+
+/// Cut a closed path out of another closed path, before it's extruded.
+/// Extruding the outer path then leaves a hole all the way through the solid.
+pub async fn add_hole(session: &mut ModelingSession, path_id: Uuid, hole_id: Uuid) -> Result<()> {
+    let cmd_id = session
+        .send(ModelingCmd::Solid2DAddHole {
+            hole_id,
+            object_id: path_id,
+        })
+        .await?;
+    session
+        .await_response(cmd_id)
+        .await
+        .wrap_err_with(|| format!("Could not cut hole {hole_id} out of {path_id}"))?;
+
+    // The hole is part of the outer path now.
+    if let Some(hole) = session.forget(hole_id) {
+        if let Some(recipe) = session.recipe_mut(path_id) {
+            recipe.holes.push(hole);
+        }
+    }
+    Ok(())
+}
+
+/// Extrude a closed path upwards by `distance`, turning it into a solid.
+/// The extruded solid keeps the ID of the path it was made from.
+/// Its ends are capped unless the session says otherwise (see --no-cap).
+pub async fn extrude(session: &mut ModelingSession, path_id: Uuid, distance: f64) -> Result<()> {
+    let cap = session.caps_extrusions();
+    extrude_with_cap(session, path_id, distance, cap).await
+}
+
+/// Extrude a closed path upwards by `distance`, with or without caps on its ends.
+///
+/// Capped, the extrusion is a closed solid. Uncapped, it's only the sides the path sweeps out,
+/// like a tube, open at both ends. That's the start of a shape which is hollowed out or lofted
+/// later, but it has no inside, so it has no volume, and its exports aren't watertight.
+pub async fn extrude_with_cap(
+    session: &mut ModelingSession,
+    path_id: Uuid,
+    distance: f64,
+    cap: bool,
+) -> Result<()> {
+    let extrude_id = session
+        .send(ModelingCmd::Extrude {
+            cap,
+            distance,
+            target: path_id,
+        })
+        .await?;
+    session.await_response(extrude_id).await?;
+
+    if let Some(recipe) = session.recipe_mut(path_id) {
+        recipe.extrusion = Some(distance);
+        recipe.uncapped = !cap;
+    }
+    Ok(())
+}
+
+/// Extrude a closed path by `distance`, half above the plane it was sketched on and half below,
+/// and return the solid's ID.
+///
+/// The API (as of this version of the kittycad crate) only extrudes upwards, and can't join two
+/// solids. So instead, this sketches the path again half the distance lower, removes the original,
+/// and extrudes the copy. That means the solid gets a new ID.
+pub async fn extrude_centered(
+    session: &mut ModelingSession,
+    path_id: Uuid,
+    distance: f64,
+) -> Result<Uuid> {
+    let lower = Transform {
+        translate: Point3D {
+            x: 0.0,
+            y: 0.0,
+            z: -distance / 2.0,
+        },
+        ..Transform::default()
+    };
+    let lowered_id = transform::apply_transform(session, path_id, &lower).await?;
+    extrude(session, lowered_id, distance).await?;
+    Ok(lowered_id)
+}
+
+/// Build a new object from a recipe, and return its ID.
+/// Recipes are where their objects were in the scene, so the placement doesn't move them.
+pub async fn build(session: &mut ModelingSession, recipe: &Recipe) -> Result<Uuid> {
+    let path_id =
+        sketch_path_in_scene(session, recipe.start.clone(), recipe.segments.clone()).await?;
+    for hole in &recipe.holes {
+        let hole_id =
+            sketch_path_in_scene(session, hole.start.clone(), hole.segments.clone()).await?;
+        add_hole(session, path_id, hole_id).await?;
+    }
+    if let Some(distance) = recipe.extrusion {
+        extrude_with_cap(session, path_id, distance, !recipe.uncapped).await?;
+    }
+    Ok(path_id)
+}
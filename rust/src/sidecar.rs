@@ -0,0 +1,326 @@
+use crate::{
+    compare::Comparison, explode::Explosion, jitter::Jittered, session::ModelingSession,
+    snapshot::Sizing, tessellation, timings::Timings, verify::MeshStats,
+};
+use color_eyre::Result;
+use kittycad::types::UnitLength;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The version of the kittycad crate in Cargo.toml.
+pub const KITTYCAD_VERSION: &str = "0.2.37";
+
+/// Where an output file came from, saved next to it as "<file>.meta.json".
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct Sidecar {
+    /// The output file.
+    pub artifact: String,
+    /// The SHA-256 of the output file, in hex.
+    pub sha256: String,
+    /// The command-line arguments of the run, which name the model and its options.
+    pub args: Vec<String>,
+    pub api_host: String,
+    /// When the session connected to the API, in seconds since the Unix epoch.
+    pub session_started_at: u64,
+    /// When the output file was saved, in seconds since the Unix epoch.
+    pub saved_at: u64,
+    /// How many modeling commands the session had sent when the output was saved.
+    pub commands_sent: usize,
+    pub kittycad_version: String,
+    /// How long the session had spent in each phase when the output was saved.
+    pub timings: Timings,
+    /// Which variant is which, if the output compares two variants of the model side by side.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comparison: Option<Comparison>,
+    /// How far each part was moved, if the output is a snapshot of an exploded scene.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exploded: Option<Explosion>,
+    /// How finely curves were drawn as lines, if they were (see --tessellation).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tessellation: Option<tessellation::Used>,
+    /// Which variant of a jittered scene the output shows, and its jittered parameters (see --seed).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jitter: Option<Jittered>,
+    #[serde(flatten)]
+    pub kind: OutputKind,
+}
+
+/// What kind of output a file is.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum OutputKind {
+    /// A snapshot of the scene.
+    Image {
+        width: u32,
+        height: u32,
+        /// How it was made the size it was asked for, if it was asked for one (see --snapshot).
+        #[serde(skip_serializing_if = "Option::is_none")]
+        sizing: Option<Sizing>,
+    },
+    /// A model exported from the scene.
+    Export {
+        /// The format, e.g. "stl".
+        format: String,
+        units: UnitLength,
+        /// Whether the file was gzipped.
+        compressed: bool,
+        /// What the exported mesh contains, if it's a format which can be read back.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        mesh: Option<MeshStats>,
+    },
+}
+
+impl Sidecar {
+    /// Describe an output file the session has just saved, with these contents.
+    pub fn new(session: &ModelingSession, path: &str, contents: &[u8], kind: OutputKind) -> Self {
+        Self {
+            artifact: path.to_owned(),
+            sha256: sha256(contents),
+            args: std::env::args().skip(1).collect(),
+            api_host: crate::session::API_HOST.to_owned(),
+            session_started_at: session.started_at(),
+            saved_at: now(),
+            commands_sent: session.commands_sent(),
+            kittycad_version: KITTYCAD_VERSION.to_owned(),
+            timings: session.timings(),
+            comparison: session.comparison().cloned(),
+            exploded: session.explosion().cloned(),
+            tessellation: tessellation::used(),
+            jitter: session.variant().cloned(),
+            kind,
+        }
+    }
+
+    /// Where the sidecar goes: next to its output file, as "<file>.meta.json".
+    pub fn path(&self) -> String {
+        format!("{}.meta.json", self.artifact)
+    }
+
+    pub fn to_json(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec_pretty(self)?)
+    }
+}
+
+/// The SHA-256 of a file's contents, in hex.
+pub fn sha256(contents: &[u8]) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA256, contents);
+    digest.as_ref().iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// The time, in seconds since the Unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// The year, month and day, in the proleptic Gregorian calendar, a number of days after the Unix epoch.
+/// This is Howard Hinnant's days-to-civil algorithm.
+pub fn civil_date(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        compare::Variant, explode::ExplodedPart, geometry::BoundingBox, tessellation::Quality,
+    };
+    use kittycad::types::Point3D;
+    use serde_json::{json, Value};
+    use std::time::Duration;
+
+    fn point(x: f64, y: f64, z: f64) -> Point3D {
+        Point3D { x, y, z }
+    }
+
+    /// A sidecar with nothing left out.
+    fn sidecar(kind: OutputKind) -> Sidecar {
+        Sidecar {
+            artifact: "out/plate.png".to_owned(),
+            sha256: sha256(b"plate"),
+            args: vec!["plate".to_owned(), "--hole-diameter=2.5".to_owned()],
+            api_host: "api.zoo.dev".to_owned(),
+            session_started_at: 1_700_000_000,
+            saved_at: 1_700_000_042,
+            commands_sent: 17,
+            kittycad_version: KITTYCAD_VERSION.to_owned(),
+            timings: Timings {
+                connect: Duration::from_millis(250),
+                build: Duration::from_millis(1500),
+                snapshot: Duration::from_micros(125),
+                overlapped: Duration::from_secs(3),
+                ..Timings::default()
+            },
+            comparison: Some(Comparison {
+                option: "hole-diameter".to_owned(),
+                variants: vec![
+                    Variant {
+                        value: "2.5".to_owned(),
+                        offset_x: -60.0,
+                    },
+                    Variant {
+                        value: "5".to_owned(),
+                        offset_x: 60.0,
+                    },
+                ],
+            }),
+            exploded: Some(Explosion {
+                axes: "xz".to_owned(),
+                factor: 1.5,
+                centroid: point(0.0, 0.0, 10.0),
+                parts: vec![ExplodedPart {
+                    name: "lid".to_owned(),
+                    offset: point(0.0, 0.0, 12.5),
+                }],
+            }),
+            tessellation: Some(tessellation::Used {
+                quality: Quality::Custom(0.05),
+                chord_tolerance: 0.05,
+            }),
+            jitter: Some(Jittered {
+                seed: 7,
+                variant: 2,
+                parameters: [("size".to_owned(), 20.25)].into(),
+            }),
+            kind,
+        }
+    }
+
+    fn image() -> OutputKind {
+        OutputKind::Image {
+            width: 800,
+            height: 600,
+            sizing: Some(Sizing::Resized {
+                from_width: 1024,
+                from_height: 768,
+            }),
+        }
+    }
+
+    fn export() -> OutputKind {
+        OutputKind::Export {
+            format: "stl".to_owned(),
+            units: UnitLength::Mm,
+            compressed: true,
+            mesh: Some(MeshStats {
+                triangles: 12,
+                vertices: 8,
+                bounding_box: Some(BoundingBox {
+                    min: point(-10.0, -10.0, -10.0),
+                    max: point(10.0, 10.0, 10.0),
+                }),
+                bad_vertices: 0,
+                open_edges: 0,
+                non_manifold_edges: 0,
+                closed: true,
+            }),
+        }
+    }
+
+    /// Write a sidecar as JSON, and read it back.
+    fn round_trip(sidecar: &Sidecar) -> (Value, Sidecar) {
+        let json = sidecar.to_json().unwrap();
+        let read: Sidecar = serde_json::from_slice(&json).unwrap();
+        (serde_json::from_slice(&json).unwrap(), read)
+    }
+
+    #[test]
+    fn reads_back_what_it_writes() {
+        for sidecar in [sidecar(image()), sidecar(export())] {
+            let (written, read) = round_trip(&sidecar);
+            assert_eq!(serde_json::to_value(&read).unwrap(), written);
+            assert_eq!(read.timings.build, Duration::from_millis(1500));
+            assert_eq!(read.timings.snapshot, Duration::from_micros(125));
+            assert_eq!(read.jitter, sidecar.jitter);
+        }
+    }
+
+    #[test]
+    fn says_what_kind_of_output_it_describes() {
+        let (image, _) = round_trip(&sidecar(image()));
+        assert_eq!(image["kind"], "image");
+        assert_eq!(image["width"], 800);
+        assert_eq!(
+            image["sizing"],
+            json!({ "method": "resized", "from_width": 1024, "from_height": 768 })
+        );
+        let (export, read) = round_trip(&sidecar(export()));
+        assert_eq!(export["kind"], "export");
+        assert_eq!(export["units"], "mm");
+        assert_eq!(export["mesh"]["bounding_box"]["max"]["z"], 10.0);
+        let OutputKind::Export { mesh, .. } = read.kind else {
+            panic!("An export was read back as {:?}", read.kind);
+        };
+        assert!(mesh.unwrap().closed);
+    }
+
+    #[test]
+    fn saves_timings_in_seconds() {
+        let (written, _) = round_trip(&sidecar(image()));
+        assert_eq!(written["timings"]["connect"], 0.25);
+        assert_eq!(written["timings"]["overlapped"], 3.0);
+        assert_eq!(written["timings"]["export"], 0.0);
+    }
+
+    #[test]
+    fn leaves_out_what_the_output_has_nothing_to_say_about() {
+        let sidecar = Sidecar {
+            comparison: None,
+            exploded: None,
+            tessellation: None,
+            jitter: None,
+            ..sidecar(OutputKind::Image {
+                width: 800,
+                height: 600,
+                sizing: None,
+            })
+        };
+        let (written, read) = round_trip(&sidecar);
+        for left_out in ["comparison", "exploded", "tessellation", "jitter", "sizing"] {
+            assert!(
+                written.get(left_out).is_none(),
+                "{left_out} is in {written}"
+            );
+        }
+        assert!(read.comparison.is_none() && read.exploded.is_none());
+        assert!(read.tessellation.is_none() && read.jitter.is_none());
+        assert!(matches!(read.kind, OutputKind::Image { sizing: None, .. }));
+    }
+
+    #[test]
+    fn goes_next_to_its_output() {
+        assert_eq!(sidecar(image()).path(), "out/plate.png.meta.json");
+    }
+
+    #[test]
+    fn hashes_with_sha256() {
+        // From FIPS 180-2's examples.
+        assert_eq!(
+            sha256(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            sha256(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn works_out_dates_from_days_since_the_epoch() {
+        assert_eq!(civil_date(0), (1970, 1, 1));
+        assert_eq!(civil_date(-1), (1969, 12, 31));
+        assert_eq!(civil_date(11_016), (2000, 2, 29));
+        assert_eq!(civil_date(11_017), (2000, 3, 1));
+        assert_eq!(civil_date(19_723), (2024, 1, 1));
+        assert_eq!(civil_date(-719_468), (0, 3, 1));
+    }
+}
@@ -0,0 +1,879 @@
+use crate::{
+    entities::EntityTree,
+    geometry::{cross, dot, normalize, scale, sub, BoundingBox},
+    measure,
+    session::ModelingSession,
+    shapes, tessellation,
+    warnings::{Code, Warning},
+};
+use color_eyre::{
+    eyre::{bail, WrapErr},
+    Result,
+};
+use kittycad::types::{Angle, EntityType, ModelingCmd, PathSegment, Point2D, Point3D, UnitAngle};
+use uuid::Uuid;
+
+/// A face of a solid. Flat, horizontal ones can be sketched on.
+#[derive(Clone, Debug)]
+pub struct Face {
+    pub id: Uuid,
+    /// The box around the face's edges. If the face is flat and horizontal, its min and max Z are the same.
+    pub bbox: BoundingBox,
+}
+
+/// Find the highest flat, horizontal face of a solid, e.g. the top of an extruded block.
+///
+/// The API (as of this version of the kittycad crate) can't tell us which way a face points,
+/// so we look at every face's edges, and pick the face whose edges all lie at the greatest height.
+pub async fn find_top_face(session: &mut ModelingSession, solid_id: Uuid) -> Result<Face> {
+    let mut top: Option<Face> = None;
+    for face in faces(session, solid_id).await? {
+        let flat = face.bbox.extents().z.abs() < FLAT;
+        if flat && top.as_ref().is_none_or(|t| face.bbox.max.z > t.bbox.max.z) {
+            top = Some(face);
+        }
+    }
+    let Some(top) = top else {
+        bail!("Solid {solid_id} has no flat, horizontal faces to sketch on");
+    };
+    Ok(top)
+}
+
+/// How far apart (in scene units) two heights may be and still count as the same, e.g. for a face to be flat.
+const FLAT: f64 = 1e-6;
+
+/// The faces of a solid extruded straight up from a horizontal path, like `shapes::extrude` makes,
+/// sorted by which part of the extrusion they are.
+#[derive(Debug, Default)]
+pub struct ExtrusionFaces {
+    /// The cap at the end the path was extruded to.
+    pub top: Option<Face>,
+    /// The cap where the path was sketched.
+    pub bottom: Option<Face>,
+    /// The faces swept out by the path's segments, from one cap to the other.
+    pub sides: Vec<Face>,
+    /// Faces which aren't any of those, and why, e.g. a second flat face at the top.
+    pub unexpected: Vec<(Face, String)>,
+}
+
+/// Sort an extruded solid's faces into its caps and its sides.
+///
+/// The API (as of this version of the kittycad crate) can't say which faces an extrusion made, so like
+/// `find_top_face`, this goes by where each face's edges are: the caps are flat, at the top and bottom of
+/// the solid, and the sides reach from one to the other. Faces which fit none of those are listed as
+/// unexpected, rather than guessed at, so callers can tell the solid isn't a plain extrusion.
+pub async fn extrusion_faces(
+    session: &mut ModelingSession,
+    solid_id: Uuid,
+) -> Result<ExtrusionFaces> {
+    let faces = faces(session, solid_id).await?;
+    let Some(solid) = faces
+        .iter()
+        .map(|f| f.bbox.clone())
+        .reduce(|a, b| a.union(&b))
+    else {
+        bail!("Solid {solid_id} has no faces");
+    };
+    let same = |a: f64, b: f64| (a - b).abs() < FLAT;
+    let mut sorted = ExtrusionFaces::default();
+    for face in faces {
+        let (low, high) = (face.bbox.min.z, face.bbox.max.z);
+        let flat = same(low, high);
+        if flat && same(high, solid.max.z) && sorted.top.is_none() {
+            sorted.top = Some(face);
+        } else if flat && same(low, solid.min.z) && sorted.bottom.is_none() {
+            sorted.bottom = Some(face);
+        } else if !flat && same(low, solid.min.z) && same(high, solid.max.z) {
+            sorted.sides.push(face);
+        } else {
+            let why = if flat && (same(low, solid.min.z) || same(low, solid.max.z)) {
+                format!("it's a second flat face at height {low}, where a cap was already found")
+            } else if flat {
+                format!("it's flat, but at height {low}, between the caps")
+            } else {
+                format!(
+                    "it reaches from height {low} to {high}, not from one cap ({}) to the other ({})",
+                    solid.min.z, solid.max.z
+                )
+            };
+            sorted.unexpected.push((face, why));
+        }
+    }
+    Ok(sorted)
+}
+
+/// Every face of a solid, with the box around its edges. Faces without edges are left out.
+pub async fn faces(session: &mut ModelingSession, solid_id: Uuid) -> Result<Vec<Face>> {
+    let tree = EntityTree::query(session, solid_id).await?;
+    let mut faces = Vec::new();
+    for face in tree.ids_of_type(&EntityType::Face) {
+        let Some(face_tree) = find(&tree, face) else {
+            continue;
+        };
+        let edges: Vec<_> = face_tree
+            .ids_of_type(&EntityType::Edge)
+            .into_iter()
+            .collect();
+        let ends = measure::edge_end_points(session, &edges).await?;
+        if let Some(bbox) =
+            BoundingBox::from_points(ends.iter().flat_map(|(start, end)| [start, end]))
+        {
+            faces.push(Face { id: face, bbox });
+        }
+    }
+    Ok(faces)
+}
+
+/// A construction plane, which paths can be sketched on. It's hidden, so it doesn't show in snapshots.
+#[derive(Clone, Debug)]
+pub struct Plane {
+    pub id: Uuid,
+    pub origin: Point3D,
+    /// Which way the plane's own X and Y axes point in the world. Both are unit length.
+    pub x_axis: Point3D,
+    pub y_axis: Point3D,
+    /// Which way the plane faces: square to both axes, following the right-hand rule from X to Y.
+    pub normal: Point3D,
+}
+
+impl Plane {
+    /// The point `u` along the plane's X axis and `v` along its Y axis from its origin, in world coordinates.
+    pub fn point(&self, u: f64, v: f64) -> Point3D {
+        let (o, x, y) = (&self.origin, &self.x_axis, &self.y_axis);
+        Point3D {
+            x: o.x + u * x.x + v * y.x,
+            y: o.y + u * x.y + v * y.y,
+            z: o.z + u * x.z + v * y.z,
+        }
+    }
+}
+
+/// One of the planes through the origin which contain two of the world's axes.
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum StandardPlane {
+    /// Faces up (+Z).
+    Xy,
+    /// Faces forwards (-Y), like KCL's XZ plane.
+    Xz,
+    /// Faces right (+X).
+    Yz,
+}
+
+/// Make one of the standard planes. `size` is how wide the plane is drawn, if it's shown.
+pub async fn make_standard_plane(
+    session: &mut ModelingSession,
+    plane: StandardPlane,
+    size: f64,
+) -> Result<Plane> {
+    let axis = |x, y, z| Point3D { x, y, z };
+    let (x_axis, y_axis) = match plane {
+        StandardPlane::Xy => (axis(1.0, 0.0, 0.0), axis(0.0, 1.0, 0.0)),
+        StandardPlane::Xz => (axis(1.0, 0.0, 0.0), axis(0.0, 0.0, 1.0)),
+        StandardPlane::Yz => (axis(0.0, 1.0, 0.0), axis(0.0, 0.0, 1.0)),
+    };
+    let origin = axis(0.0, 0.0, 0.0);
+    make_plane_with_axes(session, origin, x_axis, y_axis, size).await
+}
+
+/// Make a plane through `origin`, facing `normal`. Its X axis is picked to be square to the normal.
+/// A normal which isn't unit length is scaled to be, with a warning, since it's usually a slip.
+pub async fn make_plane(
+    session: &mut ModelingSession,
+    origin: Point3D,
+    normal: &Point3D,
+    size: f64,
+) -> Result<Plane> {
+    let length = dot(normal, normal).sqrt();
+    if !(length > 0.0 && length.is_finite()) {
+        bail!(
+            "A plane's normal must have a length, but ({}, {}, {}) doesn't",
+            normal.x,
+            normal.y,
+            normal.z
+        );
+    }
+    if (length - 1.0).abs() > 1e-6 {
+        let message = format!(
+            "The plane's normal ({}, {}, {}) isn't unit length, so it's been scaled to be",
+            normal.x, normal.y, normal.z
+        );
+        Warning::new(Code::PlaneNormal, message).raise()?;
+    }
+    let normal = normalize(normal);
+    // Start from whichever world axis is least like the normal, and remove the part of it along the normal.
+    let [nx, ny, nz] = [normal.x.abs(), normal.y.abs(), normal.z.abs()];
+    let seed = if nx <= ny && nx <= nz {
+        Point3D {
+            x: 1.0,
+            y: 0.0,
+            z: 0.0,
+        }
+    } else if ny <= nz {
+        Point3D {
+            x: 0.0,
+            y: 1.0,
+            z: 0.0,
+        }
+    } else {
+        Point3D {
+            x: 0.0,
+            y: 0.0,
+            z: 1.0,
+        }
+    };
+    let along = dot(&seed, &normal);
+    let x_axis = normalize(&sub(&seed, &scale(&normal, along)));
+    let y_axis = cross(&normal, &x_axis);
+    make_plane_with_axes(session, origin, x_axis, y_axis, size).await
+}
+
+/// Make a plane with the given (unit length, square) axes.
+/// Its origin is moved by the session's placement, like a path's points.
+async fn make_plane_with_axes(
+    session: &mut ModelingSession,
+    origin: Point3D,
+    x_axis: Point3D,
+    y_axis: Point3D,
+    size: f64,
+) -> Result<Plane> {
+    // Planes are placed like paths, so sketches on them land where the model meant.
+    let origin = session.place(&origin);
+    let id = session.new_id();
+    session
+        .send_with_id(
+            ModelingCmd::MakePlane {
+                clobber: false,
+                hide: Some(true),
+                origin: origin.clone(),
+                size,
+                x_axis: x_axis.clone(),
+                y_axis: y_axis.clone(),
+            },
+            id,
+        )
+        .await?;
+    session.await_response(id).await?;
+    session.track(id);
+    let normal = cross(&x_axis, &y_axis);
+    Ok(Plane {
+        id,
+        origin,
+        x_axis,
+        y_axis,
+        normal,
+    })
+}
+
+/// Make a plane lying on a face, to sketch on.
+///
+/// The face is horizontal, so a point on it at height h has z = h, and its x and y are the same as
+/// anywhere else in the scene. That's why the plane's axes are just the world's X and Y.
+/// Its origin is the middle of the face.
+pub async fn plane_on_face(session: &mut ModelingSession, face: &Face) -> Result<Plane> {
+    let extents = face.bbox.extents();
+    // The face is already where it belongs in the scene, so it mustn't be placed again.
+    let center = session.unplace(&face.bbox.center());
+    make_plane_with_axes(
+        session,
+        center,
+        Point3D {
+            x: 1.0,
+            y: 0.0,
+            z: 0.0,
+        },
+        Point3D {
+            x: 0.0,
+            y: 1.0,
+            z: 0.0,
+        },
+        extents.x.max(extents.y),
+    )
+    .await
+}
+
+/// The API in sketch mode on a plane. Paths are sketched through it, in the plane's own coordinates,
+/// and `finish` takes the API out of sketch mode again.
+///
+/// If sketching a path fails, sketch mode is left before the error is returned, so the session is never
+/// left sketching by a failed command. Dropping a sketch which wasn't finished can't send anything,
+/// so it only warns.
+pub struct Sketch<'a> {
+    session: &'a mut ModelingSession,
+    plane: Plane,
+    /// Whether the API has been asked to leave sketch mode.
+    finished: bool,
+}
+
+/// Put the API into sketch mode on a plane.
+///
+/// Sketch mode changes how the camera behaves, but not how paths are drawn. Path points are still in
+/// world coordinates, not in the plane's own 2D coordinates, so the sketch's methods convert them.
+pub async fn start<'a>(session: &'a mut ModelingSession, plane: &Plane) -> Result<Sketch<'a>> {
+    let cmd_id = session
+        .send(ModelingCmd::SketchModeEnable {
+            animated: false,
+            disable_camera_with_plane: None,
+            ortho: false,
+            plane_id: plane.id,
+        })
+        .await?;
+    session
+        .await_response(cmd_id)
+        .await
+        .wrap_err_with(|| format!("Could not start sketching on plane {}", plane.id))?;
+    // Sketch mode turns the camera to face the plane, so it's no longer where we pointed it.
+    session.forget_framing();
+    Ok(Sketch {
+        session,
+        plane: plane.clone(),
+        finished: false,
+    })
+}
+
+impl Sketch<'_> {
+    /// Sketch a closed polygon, with its corners given in the plane's own coordinates,
+    /// and return the path's ID. The corners should go anticlockwise around the plane's normal,
+    /// so that extruding the path moves it along the normal.
+    pub async fn polygon(&mut self, corners: &[(f64, f64)]) -> Result<Uuid> {
+        // The plane was placed when it was made, so undo that, or the path would be placed twice.
+        let corners: Vec<_> = corners
+            .iter()
+            .map(|&(u, v)| self.session.unplace(&self.plane.point(u, v)))
+            .collect();
+        let sketched = shapes::sketch_polygon(self.session, &corners).await;
+        let path_id = self.exit_on_error(sketched).await?;
+        if let Some(recipe) = self.session.recipe_mut(path_id) {
+            recipe.normal = Some(self.plane.normal.clone());
+        }
+        Ok(path_id)
+    }
+
+    /// Sketch a circle around a point given in the plane's own coordinates, and return the path's ID.
+    /// Arcs are always drawn flat on the XY plane, so the plane must be horizontal.
+    pub async fn circle(&mut self, center: (f64, f64), radius: f64) -> Result<Uuid> {
+        let sketched = match self.check_horizontal("Circles") {
+            Ok(()) => {
+                let center = self.session.unplace(&self.plane.point(center.0, center.1));
+                shapes::sketch_circle(self.session, &center, radius).await
+            }
+            Err(e) => Err(e),
+        };
+        self.exit_on_error(sketched).await
+    }
+
+    /// Sketch a `width` by `height` rectangle around a point given in the plane's own coordinates,
+    /// turned `rotation` degrees anticlockwise about its middle, and return the path's ID.
+    pub async fn rect_center(
+        &mut self,
+        center: (f64, f64),
+        width: f64,
+        height: f64,
+        rotation: f64,
+    ) -> Result<Uuid> {
+        let corners = rectangle_around(center, width, height, rotation);
+        let corners = self.exit_on_error(corners).await?;
+        self.polygon(&corners).await
+    }
+
+    /// Sketch a rectangle with two opposite corners at the given points, in the plane's own
+    /// coordinates, and its sides along the plane's axes. Return the path's ID.
+    pub async fn rect_corners(&mut self, p1: (f64, f64), p2: (f64, f64)) -> Result<Uuid> {
+        let corners = rectangle_between(p1, p2);
+        let corners = self.exit_on_error(corners).await?;
+        self.polygon(&corners).await
+    }
+
+    /// Sketch a slot from `p1` to `p2`, given in the plane's own coordinates, and return the path's ID.
+    /// It's `width` wide, with straight sides and round ends, like the path a `width` wide drill
+    /// bit cuts moving from one point to the other.
+    ///
+    /// Its ends are arcs, which are always drawn flat on the XY plane, so the plane must be horizontal,
+    /// unless the run draws curves as lines (see --tessellation).
+    pub async fn slot(&mut self, p1: (f64, f64), p2: (f64, f64), width: f64) -> Result<Uuid> {
+        let slot = self.exit_on_error(Slot::new(p1, p2, width)).await?;
+        if let Some(quality) = tessellation::quality() {
+            return self.polygon(&slot.corners(quality)).await;
+        }
+        let sketched = match self.check_horizontal("Slots") {
+            Ok(()) => {
+                let (start, segments) = self.slot_path(&slot);
+                shapes::sketch_path(self.session, start, segments).await
+            }
+            Err(e) => Err(e),
+        };
+        let path_id = self.exit_on_error(sketched).await?;
+        if let Some(recipe) = self.session.recipe_mut(path_id) {
+            recipe.normal = Some(self.plane.normal.clone());
+        }
+        Ok(path_id)
+    }
+
+    /// The slot's path, in world coordinates: where it starts, and its sides and ends.
+    fn slot_path(&self, slot: &Slot) -> (Point3D, Vec<PathSegment>) {
+        // The plane was placed when it was made, so undo that, or the path would be placed twice.
+        let world = |(u, v): (f64, f64)| self.session.unplace(&self.plane.point(u, v));
+        // Turning anticlockwise around the plane's normal is turning clockwise seen from above,
+        // if the plane faces down.
+        let turn = 180.0_f64.copysign(self.plane.normal.z);
+        let mut segments = Vec::new();
+        for (center, degrees) in [(slot.p2, slot.right), (slot.p1, slot.right + 180.0)] {
+            segments.push(PathSegment::Line {
+                end: world(slot.point(center, degrees)),
+                relative: false,
+            });
+            // The angle the arc starts at, measured in the world, not the plane.
+            let (sin, cos) = degrees.to_radians().sin_cos();
+            let toward = self.plane.point(cos, sin);
+            let start = (toward.y - self.plane.origin.y)
+                .atan2(toward.x - self.plane.origin.x)
+                .to_degrees();
+            let center = world(center);
+            let angle = |value| Angle {
+                unit: UnitAngle::Degrees,
+                value,
+            };
+            segments.push(PathSegment::Arc {
+                center: Point2D {
+                    x: center.x,
+                    y: center.y,
+                },
+                radius: slot.radius,
+                start: Some(angle(start)),
+                end: Some(angle(start + turn)),
+                // These are deprecated in favour of `start` and `end`, but still required.
+                angle_start: start,
+                angle_end: start + turn,
+                relative: false,
+            });
+        }
+        (world(slot.point(slot.p1, slot.right)), segments)
+    }
+
+    /// Stop sketching, and go back to the normal 3D camera.
+    pub async fn finish(mut self) -> Result<()> {
+        self.finished = true;
+        exit_sketch_mode(self.session).await
+    }
+
+    /// Arcs are always drawn flat on the XY plane, so `what` can only be sketched on horizontal planes.
+    fn check_horizontal(&self, what: &str) -> Result<()> {
+        if (self.plane.normal.z.abs() - 1.0).abs() > 1e-9 {
+            bail!(
+                "{what} can only be sketched on horizontal planes, and plane {} isn't one",
+                self.plane.id
+            );
+        }
+        Ok(())
+    }
+
+    /// If sketching failed, leave sketch mode before passing the error on.
+    async fn exit_on_error<T>(&mut self, result: Result<T>) -> Result<T> {
+        if result.is_err() && !self.finished {
+            self.finished = true;
+            // The first error says what went wrong, so one from leaving sketch mode is only a warning.
+            if let Err(e) = exit_sketch_mode(self.session).await {
+                let message = format!("Could not leave sketch mode after sketching failed: {e}");
+                let _ = Warning::new(Code::SketchMode, message).raise();
+            }
+        }
+        result
+    }
+}
+
+impl Drop for Sketch<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            let message = format!(
+                "Sketching on plane {} was never finished, so the API is still in sketch mode",
+                self.plane.id
+            );
+            // Dropping can't fail, but if this is denied, the run fails at the end.
+            let _ = Warning::new(Code::SketchMode, message).raise();
+        }
+    }
+}
+
+/// The corners of a `width` by `height` rectangle around `center`, turned `rotation` degrees
+/// anticlockwise about its middle. They go anticlockwise, starting from the one which is at the
+/// bottom left before the rectangle is turned.
+pub fn rectangle_around(
+    center: (f64, f64),
+    width: f64,
+    height: f64,
+    rotation: f64,
+) -> Result<[(f64, f64); 4]> {
+    if !(width > 0.0 && height > 0.0 && width.is_finite() && height.is_finite()) {
+        bail!("A rectangle must be a positive width and height, not {width} by {height}");
+    }
+    let (sin, cos) = rotation.to_radians().sin_cos();
+    let (w, h) = (width / 2.0, height / 2.0);
+    Ok([(-w, -h), (w, -h), (w, h), (-w, h)]
+        .map(|(u, v)| (center.0 + u * cos - v * sin, center.1 + u * sin + v * cos)))
+}
+
+/// The corners of the rectangle with two opposite corners at `p1` and `p2`, and its sides along
+/// the axes. They go anticlockwise, starting from the bottom left, whichever corners were given.
+pub fn rectangle_between(p1: (f64, f64), p2: (f64, f64)) -> Result<[(f64, f64); 4]> {
+    let (left, right) = (p1.0.min(p2.0), p1.0.max(p2.0));
+    let (bottom, top) = (p1.1.min(p2.1), p1.1.max(p2.1));
+    if left == right || bottom == top {
+        bail!(
+            "A rectangle's opposite corners must be apart in both directions, but ({}, {}) and ({}, {}) aren't",
+            p1.0,
+            p1.1,
+            p2.0,
+            p2.1
+        );
+    }
+    Ok([(left, bottom), (right, bottom), (right, top), (left, top)])
+}
+
+/// A slot's outline, in a plane's own coordinates. It goes anticlockwise: along one straight side
+/// from `p1` to `p2`, round the end at `p2`, back along the other side, and round the end at `p1`.
+pub(crate) struct Slot {
+    p1: (f64, f64),
+    p2: (f64, f64),
+    /// Half the slot's width, which is the radius of its ends.
+    radius: f64,
+    /// Which way is to the right, going from `p1` to `p2`, in degrees anticlockwise from the X axis.
+    right: f64,
+}
+
+impl Slot {
+    pub(crate) fn new(p1: (f64, f64), p2: (f64, f64), width: f64) -> Result<Self> {
+        if !(width > 0.0 && width.is_finite()) {
+            bail!("A slot's width must be positive, not {width}");
+        }
+        let (du, dv) = (p2.0 - p1.0, p2.1 - p1.1);
+        if du == 0.0 && dv == 0.0 {
+            bail!(
+                "A slot's ends must be apart, but both are at ({}, {}). Sketch a circle instead",
+                p1.0,
+                p1.1
+            );
+        }
+        Ok(Self {
+            p1,
+            p2,
+            radius: width / 2.0,
+            right: (-du).atan2(dv).to_degrees(),
+        })
+    }
+
+    /// The point on the slot's edge around one of its ends, `degrees` anticlockwise from the X axis.
+    fn point(&self, center: (f64, f64), degrees: f64) -> (f64, f64) {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        (center.0 + self.radius * cos, center.1 + self.radius * sin)
+    }
+
+    /// The slot as a polygon, with each end drawn as half of a circle drawn as lines (see --tessellation).
+    pub(crate) fn corners(&self, quality: tessellation::Quality) -> Vec<(f64, f64)> {
+        let n = tessellation::segments_for_radius(self.radius, quality).div_ceil(2);
+        [(self.p2, self.right), (self.p1, self.right + 180.0)]
+            .into_iter()
+            .flat_map(|(center, start)| {
+                (0..=n).map(move |i| self.point(center, start + 180.0 * i as f64 / n as f64))
+            })
+            .collect()
+    }
+}
+
+/// Take the API out of sketch mode.
+async fn exit_sketch_mode(session: &mut ModelingSession) -> Result<()> {
+    let cmd_id = session.send(ModelingCmd::SketchModeDisable {}).await?;
+    session.await_response(cmd_id).await?;
+    Ok(())
+}
+
+/// Find an entity somewhere in a tree.
+fn find(tree: &EntityTree, id: Uuid) -> Option<&EntityTree> {
+    let mut to_visit = vec![tree];
+    while let Some(entity) = to_visit.pop() {
+        if entity.id == id {
+            return Some(entity);
+        }
+        to_visit.extend(&entity.children);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use std::path::Path;
+
+    fn point(x: f64, y: f64, z: f64) -> Point3D {
+        Point3D { x, y, z }
+    }
+
+    /// A plane through `origin`, with these axes.
+    fn plane(origin: Point3D, x_axis: Point3D, y_axis: Point3D) -> Plane {
+        Plane {
+            id: Uuid::from_u128(1),
+            normal: cross(&x_axis, &y_axis),
+            origin,
+            x_axis,
+            y_axis,
+        }
+    }
+
+    fn xy() -> Plane {
+        plane(
+            point(0.0, 0.0, 0.0),
+            point(1.0, 0.0, 0.0),
+            point(0.0, 1.0, 0.0),
+        )
+    }
+
+    /// A plane 5mm up, facing down, so its Y axis points towards -Y.
+    fn facing_down() -> Plane {
+        plane(
+            point(0.0, 0.0, 5.0),
+            point(1.0, 0.0, 0.0),
+            point(0.0, -1.0, 0.0),
+        )
+    }
+
+    /// Like KCL's XZ plane, facing forwards (-Y).
+    fn xz() -> Plane {
+        plane(
+            point(0.0, 0.0, 0.0),
+            point(1.0, 0.0, 0.0),
+            point(0.0, 0.0, 1.0),
+        )
+    }
+
+    fn run<T>(future: impl std::future::Future<Output = T>) -> T {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn leaves_sketch_mode_when_a_command_in_the_sketch_fails() {
+        let recording =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/recordings/sketch-failed.jsonl");
+        let trace = std::env::temp_dir().join(format!(
+            "kittycad-sketch-failed-trace-{}.jsonl",
+            std::process::id()
+        ));
+        let mut session = ModelingSession::replay(
+            &recording,
+            usize::MAX,
+            crate::session::ConnectionProfile::default(),
+        )
+        .unwrap();
+        session.record(&trace).unwrap();
+        let e = run(async {
+            let mut sketch = start(&mut session, &xy()).await?;
+            sketch
+                .polygon(&[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)])
+                .await?;
+            sketch.finish().await
+        })
+        .unwrap_err();
+        assert!(
+            format!("{e:#}").contains("The path crosses itself"),
+            "{e:#}"
+        );
+        let sent: Vec<String> = std::fs::read_to_string(&trace)
+            .unwrap()
+            .lines()
+            .filter_map(|line| {
+                let entry: Value = serde_json::from_str(line).unwrap();
+                Some(entry.get("sent")?["cmd"]["type"].as_str()?.to_owned())
+            })
+            .collect();
+        assert_eq!(
+            sent,
+            [
+                "sketch_mode_enable",
+                "start_path",
+                "move_path_pen",
+                "extend_path",
+                "extend_path",
+                "extend_path",
+                "extend_path",
+                "close_path",
+                "sketch_mode_disable"
+            ]
+        );
+        // It was left, so there's nothing to warn about.
+        assert!(!crate::warnings::warnings()
+            .iter()
+            .any(|w| w.code == Code::SketchMode
+                && w.message.contains(&Uuid::from_u128(1).to_string())));
+    }
+
+    /// Sketch something on a plane, and return the path the session recorded, as JSON.
+    /// `segments` is how many segments the path should have, to answer the commands sketching it.
+    fn sketched(
+        test: &str,
+        plane: Plane,
+        segments: usize,
+        draw: impl FnOnce(&mut Sketch<'_>) -> Result<Uuid>,
+    ) -> Value {
+        let empty = || json!({ "type": "empty" });
+        let mut responses = vec![("start_path", empty()), ("move_path_pen", empty())];
+        responses.extend((0..segments).map(|_| ("extend_path", empty())));
+        responses.push(("close_path", empty()));
+        let mut session = ModelingSession::canned(test, &responses);
+        let mut sketch = Sketch {
+            session: &mut session,
+            plane,
+            // It's never started, so there's no sketch mode to leave.
+            finished: true,
+        };
+        let path_id = draw(&mut sketch).unwrap();
+        drop(sketch);
+        rounded(serde_json::to_value(session.recipe(path_id).unwrap()).unwrap())
+    }
+
+    /// Round every number to 9 decimal places, so the golden files don't depend on how the
+    /// platform rounds sines and cosines.
+    fn rounded(value: Value) -> Value {
+        match value {
+            Value::Number(n) => json!(((n.as_f64().unwrap() * 1e9).round() / 1e9) + 0.0),
+            Value::Array(values) => Value::Array(values.into_iter().map(rounded).collect()),
+            Value::Object(fields) => {
+                Value::Object(fields.into_iter().map(|(k, v)| (k, rounded(v))).collect())
+            }
+            value => value,
+        }
+    }
+
+    /// Compare `value` with tests/fixtures/sketch/<name>.json. If a change is meant, write the
+    /// files again with `UPDATE_FIXTURES=1 cargo test --lib sketch::`, and check their diff.
+    fn golden(name: &str, value: &Value) {
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/sketch")
+            .join(format!("{name}.json"));
+        if std::env::var_os("UPDATE_FIXTURES").is_some_and(|update| update == "1") {
+            std::fs::create_dir_all(fixture.parent().unwrap()).unwrap();
+            let json = serde_json::to_string_pretty(value).unwrap();
+            std::fs::write(&fixture, json + "\n").unwrap();
+            return;
+        }
+        let Ok(expected) = std::fs::read_to_string(&fixture) else {
+            panic!(
+                "There's no {}. Write it with UPDATE_FIXTURES=1",
+                fixture.display()
+            );
+        };
+        let expected: Value = serde_json::from_str(&expected).unwrap();
+        assert_eq!(
+            value,
+            &expected,
+            "{name} differs from {}. If that's meant, write it again with UPDATE_FIXTURES=1",
+            fixture.display()
+        );
+    }
+
+    #[test]
+    fn sketches_rectangles_around_a_center() {
+        let square = sketched("rect-center", xy(), 4, |sketch| {
+            run(sketch.rect_center((10.0, 5.0), 40.0, 20.0, 0.0))
+        });
+        golden("rect-center", &square);
+        let turned = sketched("rect-center-turned", xy(), 4, |sketch| {
+            run(sketch.rect_center((10.0, 5.0), 40.0, 20.0, 30.0))
+        });
+        golden("rect-center-turned", &turned);
+        let upright = sketched("rect-center-upright", xz(), 4, |sketch| {
+            run(sketch.rect_center((10.0, 5.0), 40.0, 20.0, 90.0))
+        });
+        golden("rect-center-upright", &upright);
+    }
+
+    #[test]
+    fn turns_rectangles_about_their_middle() {
+        for rotation in [0.0, 30.0, 90.0, 137.5, -45.0] {
+            let corners = rectangle_around((10.0, 5.0), 40.0, 20.0, rotation).unwrap();
+            let middle = corners
+                .iter()
+                .fold((0.0, 0.0), |(u, v), &(cu, cv)| (u + cu / 4.0, v + cv / 4.0));
+            assert!((middle.0 - 10.0).abs() < 1e-9 && (middle.1 - 5.0).abs() < 1e-9);
+            let side = |a: (f64, f64), b: (f64, f64)| (a.0 - b.0).hypot(a.1 - b.1);
+            assert!((side(corners[0], corners[1]) - 40.0).abs() < 1e-9);
+            assert!((side(corners[1], corners[2]) - 20.0).abs() < 1e-9);
+        }
+        assert_eq!(
+            rectangle_around((0.0, 0.0), 0.0, 20.0, 0.0)
+                .unwrap_err()
+                .to_string(),
+            "A rectangle must be a positive width and height, not 0 by 20"
+        );
+    }
+
+    #[test]
+    fn sketches_rectangles_between_corners() {
+        let rectangle = sketched("rect-corners", xy(), 4, |sketch| {
+            run(sketch.rect_corners((30.0, -5.0), (-10.0, 15.0)))
+        });
+        golden("rect-corners", &rectangle);
+        // Whichever two opposite corners are given, it's the same rectangle.
+        for (p1, p2) in [
+            ((-10.0, -5.0), (30.0, 15.0)),
+            ((30.0, 15.0), (-10.0, -5.0)),
+            ((-10.0, 15.0), (30.0, -5.0)),
+        ] {
+            assert_eq!(
+                rectangle_between(p1, p2).unwrap(),
+                rectangle_between((30.0, -5.0), (-10.0, 15.0)).unwrap()
+            );
+        }
+        assert_eq!(
+            rectangle_between((1.0, 2.0), (1.0, 5.0))
+                .unwrap_err()
+                .to_string(),
+            "A rectangle's opposite corners must be apart in both directions, but (1, 2) and (1, 5) aren't"
+        );
+    }
+
+    #[test]
+    fn sketches_slots_with_round_ends() {
+        let slot = sketched("slot", xy(), 4, |sketch| {
+            run(sketch.slot((0.0, 0.0), (30.0, 0.0), 8.0))
+        });
+        golden("slot", &slot);
+        let diagonal = sketched("slot-diagonal", xy(), 4, |sketch| {
+            run(sketch.slot((5.0, 5.0), (-15.0, 25.0), 6.0))
+        });
+        golden("slot-diagonal", &diagonal);
+        // Facing down, anticlockwise around the plane's normal is clockwise seen from above.
+        let down = sketched("slot-facing-down", facing_down(), 4, |sketch| {
+            run(sketch.slot((0.0, 0.0), (30.0, 0.0), 8.0))
+        });
+        golden("slot-facing-down", &down);
+    }
+
+    #[test]
+    fn draws_slots_as_lines_when_asked() {
+        let slot = Slot::new((0.0, 0.0), (30.0, 0.0), 8.0).unwrap();
+        let corners = slot.corners(tessellation::Quality::Low);
+        golden("slot-low", &rounded(json!(corners)));
+    }
+
+    #[test]
+    fn needs_a_slot_to_have_length_and_width() {
+        assert_eq!(
+            Slot::new((3.0, 4.0), (3.0, 4.0), 8.0)
+                .err()
+                .unwrap()
+                .to_string(),
+            "A slot's ends must be apart, but both are at (3, 4). Sketch a circle instead"
+        );
+        for width in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert_eq!(
+                Slot::new((0.0, 0.0), (1.0, 0.0), width)
+                    .err()
+                    .unwrap()
+                    .to_string(),
+                format!("A slot's width must be positive, not {width}")
+            );
+        }
+    }
+}
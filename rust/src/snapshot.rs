@@ -0,0 +1,1241 @@
+use crate::{
+    background::Color,
+    budget, camera,
+    dimension::{self, DimensionLine, Projected},
+    label,
+    names::Handle,
+    session::{ConnectionLost, ModelingSession},
+    sidecar::OutputKind,
+    template::Drawing,
+    timings::Phase,
+    warnings::{Code, Warning},
+};
+use color_eyre::{
+    eyre::{bail, eyre, Context},
+    Result,
+};
+use kittycad::types::{ImageFormat, ModelingCmd, OkModelingCmdResponse};
+use std::{
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+use uuid::Uuid;
+
+/// The output path which means standard output, rather than a file.
+pub const STDOUT: &str = "-";
+
+/// Where to save a snapshot, and how to touch it up first.
+#[derive(Clone, Debug)]
+pub struct Output {
+    pub path: String,
+    /// If set, the snapshot's background is repainted this color.
+    pub background: Option<Color>,
+    /// If set, the finished model is also exported to this file.
+    pub export: Option<PathBuf>,
+    /// Other formats to export the model in too, e.g. "step", each next to `export`, with its extension.
+    pub export_as: Vec<String>,
+    /// If set, only these objects are exported, rather than every object in the model.
+    pub export_only: Option<Vec<Handle>>,
+    /// Whether to gzip exported files.
+    pub compress: bool,
+    /// Whether to fail if an exported mesh is empty or malformed, rather than warn.
+    pub verify_export: bool,
+    /// If set, only this part of the snapshot is saved.
+    pub crop: Option<Crop>,
+    /// How many pixels to leave around an object when cropping to it.
+    pub crop_padding: u32,
+    /// How to tell a blank snapshot (just the background) from a real one, and how often to retry.
+    pub blank: BlankCheck,
+    /// If set, this caption is burned into a strip added along the bottom of the snapshot.
+    pub label: Option<String>,
+    /// Whether to caption each drawing with its model and options (and the date), rather than with `label`.
+    pub label_auto: bool,
+    /// Whether captions may be burned into snapshots saved in formats which lose detail, e.g. JPEG.
+    /// If not, those snapshots are saved without their captions.
+    pub reencode: bool,
+    /// If set, the snapshot is made exactly this many pixels wide and high (before any caption),
+    /// e.g. for one of the run's --snapshot outputs.
+    pub size: Option<(u32, u32)>,
+    /// More snapshots to save, each at its own size, from --snapshot.
+    /// They're taken straight after this one, of the same model, from the same camera.
+    pub snapshots: Vec<SizedSnapshot>,
+    /// Dimensions to draw on the snapshot, e.g. from a scene's "annotations".
+    /// They're only drawn on this view, so outputs made from it with `with_suffix` don't have them.
+    pub dimensions: Vec<DimensionLine>,
+}
+
+/// An extra snapshot of each model, at its own size, from --snapshot, e.g. "256x256:thumb.png".
+#[derive(Clone, Debug)]
+pub struct SizedSnapshot {
+    pub width: u32,
+    pub height: u32,
+    pub path: String,
+}
+
+impl SizedSnapshot {
+    /// Parses "WIDTHxHEIGHT:PATH", e.g. "1920x1080:hero.png".
+    pub fn parse(s: &str) -> Result<Self> {
+        let usage = || format!("{s:?} should look like WIDTHxHEIGHT:PATH, e.g. 1920x1080:hero.png");
+        let (size, path) = s.split_once(':').ok_or_else(|| eyre!(usage()))?;
+        let (width, height) = size.split_once('x').ok_or_else(|| eyre!(usage()))?;
+        let parse = |n: &str| n.trim().parse::<u32>().ok().filter(|&n| n > 0);
+        let (Some(width), Some(height)) = (parse(width), parse(height)) else {
+            bail!("{}, with a width and height of at least 1 pixel", usage());
+        };
+        if path.is_empty() || path == STDOUT {
+            bail!(
+                "{}. Only the main snapshot can go to standard output",
+                usage()
+            );
+        }
+        Ok(Self {
+            width,
+            height,
+            path: path.to_owned(),
+        })
+    }
+}
+
+/// How a snapshot was made the size it was asked for, saved in its sidecar.
+#[derive(Clone, Copy, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum Sizing {
+    /// The API's snapshot was already the right size, because its stream was reconfigured to it
+    /// (or happened to be that size).
+    Captured { stream_reconfigured: bool },
+    /// The API's snapshot was another size, so it was cropped to the right shape and scaled here.
+    Resized { from_width: u32, from_height: u32 },
+}
+
+/// How to tell when a snapshot was taken before the scene finished drawing, so it's just the background.
+#[derive(Clone, Copy, Debug)]
+pub struct BlankCheck {
+    /// How far (per channel, out of 255) a pixel may be from the background's color and still count as background.
+    pub tolerance: u8,
+    /// What fraction of the pixels must be background for the snapshot to count as blank.
+    pub threshold: f64,
+    /// How many more snapshots to take if one is blank, before giving up.
+    /// 0 saves the first snapshot without checking it.
+    pub retries: u32,
+}
+
+impl BlankCheck {
+    /// Is this snapshot just the background?
+    pub fn is_blank(&self, img: &image::RgbaImage) -> bool {
+        crate::background::background_fraction(img, self.tolerance) >= self.threshold
+    }
+}
+
+/// Every snapshot the API sent was blank, even after retrying.
+#[derive(Debug)]
+pub struct BlankSnapshot {
+    pub attempts: u32,
+}
+
+impl std::fmt::Display for BlankSnapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The API's snapshot was blank (just the background) {} times in a row",
+            self.attempts
+        )
+    }
+}
+
+impl std::error::Error for BlankSnapshot {}
+
+/// Which part of a snapshot to keep.
+#[derive(Clone, Debug)]
+pub enum Crop {
+    /// A rectangle of pixels.
+    Pixels(PixelRect),
+    /// Wherever this object appears in the snapshot.
+    Object(Handle),
+}
+
+/// A rectangle of pixels, measured from the image's top-left corner.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PixelRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl PixelRect {
+    /// Parses "x,y,width,height", e.g. "100,50,200,150".
+    pub fn parse(s: &str) -> Result<Self> {
+        let numbers = s
+            .split(',')
+            .map(|n| n.trim().parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("{s:?} should be four whole numbers, like 100,50,200,150"))?;
+        let [x, y, width, height] = numbers[..] else {
+            bail!("{s:?} should be four whole numbers, like 100,50,200,150");
+        };
+        Ok(Self {
+            x,
+            y,
+            width,
+            height,
+        })
+    }
+
+    /// The part of the rectangle between these edges which is inside an image this big,
+    /// or None if none of it is.
+    fn clamped(
+        left: i64,
+        top: i64,
+        right: i64,
+        bottom: i64,
+        width: u32,
+        height: u32,
+    ) -> Option<Self> {
+        let (left, right) = (left.max(0), right.min(i64::from(width)));
+        let (top, bottom) = (top.max(0), bottom.min(i64::from(height)));
+        if left >= right || top >= bottom {
+            return None;
+        }
+        // All of these are within 0..=width or 0..=height now, so they fit.
+        Some(Self {
+            x: left as u32,
+            y: top as u32,
+            width: (right - left) as u32,
+            height: (bottom - top) as u32,
+        })
+    }
+}
+
+/// The pixels to keep from a snapshot `width` by `height` pixels big.
+fn crop_rect(
+    session: &ModelingSession,
+    crop: &Crop,
+    padding: u32,
+    width: u32,
+    height: u32,
+) -> Result<PixelRect> {
+    match crop {
+        Crop::Pixels(rect) => PixelRect::clamped(
+            i64::from(rect.x),
+            i64::from(rect.y),
+            i64::from(rect.x) + i64::from(rect.width),
+            i64::from(rect.y) + i64::from(rect.height),
+            width,
+            height,
+        )
+        .ok_or_else(|| {
+            let PixelRect {
+                x,
+                y,
+                width: w,
+                height: h,
+            } = rect;
+            eyre!("The crop {x},{y},{w},{h} is outside the {width}x{height} snapshot")
+        }),
+        Crop::Object(handle) => {
+            let id = session.names().resolve(handle)?;
+            // The API (as of this version of the kittycad crate) can't say where an object appears
+            // on screen, so we project its bounding box with the camera we pointed.
+            let Some(bbox) = session.bounding_box(&[id]) else {
+                bail!("Can't crop to {handle}, because it isn't an object drawn in this run");
+            };
+            let Some(camera) = session.pointed() else {
+                bail!("Can't crop to {handle}, because the camera hasn't been pointed at anything yet");
+            };
+            let (w, h) = (f64::from(width), f64::from(height));
+            let Some(region) = camera.screen_region(&bbox, w / h) else {
+                bail!("Can't crop to {handle}, because some of it is behind the camera");
+            };
+            let padding = i64::from(padding);
+            PixelRect::clamped(
+                (region.left * w).floor() as i64 - padding,
+                (region.top * h).floor() as i64 - padding,
+                (region.right * w).ceil() as i64 + padding,
+                (region.bottom * h).ceil() as i64 + padding,
+                width,
+                height,
+            )
+            .ok_or_else(|| eyre!("Can't crop to {handle}, because it isn't in the snapshot"))
+        }
+    }
+}
+
+impl Output {
+    /// Save the snapshot to `path`, with the command line's defaults: no export, no caption, and no cropping.
+    pub fn new(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            background: None,
+            export: None,
+            export_as: Vec::new(),
+            export_only: None,
+            compress: false,
+            verify_export: false,
+            crop: None,
+            crop_padding: 16,
+            blank: BlankCheck {
+                tolerance: crate::background::TOLERANCE,
+                threshold: 0.999,
+                retries: 2,
+            },
+            label: None,
+            label_auto: false,
+            reencode: true,
+            size: None,
+            snapshots: Vec::new(),
+            dimensions: Vec::new(),
+        }
+    }
+
+    /// The files the model is exported to, if it is, and the format of each (its extension):
+    /// `export`, and then one next to it for each of `export_as`.
+    pub fn exports(&self) -> Vec<(PathBuf, String)> {
+        let Some(export) = &self.export else {
+            return Vec::new();
+        };
+        let mut exports = vec![(export.clone(), crate::export::extension(export))];
+        for extension in &self.export_as {
+            exports.push((export.with_extension(extension), extension.clone()));
+        }
+        exports
+    }
+
+    /// The same output, but with a suffix added to the files' names, before their extensions,
+    /// e.g. "model.png" becomes "model-before.png".
+    /// Only the final snapshot goes to standard output, so the others are saved as "model-before.png" etc.
+    pub fn with_suffix(&self, suffix: &str) -> Self {
+        let path = if self.path == STDOUT {
+            Path::new("model.png")
+        } else {
+            Path::new(&self.path)
+        };
+        Self {
+            path: with_suffix(path, suffix).to_string_lossy().into_owned(),
+            export: self.export.as_deref().map(|path| with_suffix(path, suffix)),
+            dimensions: Vec::new(),
+            ..self.clone()
+        }
+    }
+
+    /// The output for a drawing, captioned with its model and options if `label_auto` is set.
+    pub fn labelled(self, drawing: &Drawing) -> Self {
+        if !self.label_auto {
+            return self;
+        }
+        Self {
+            label: Some(label::auto(drawing)),
+            ..self
+        }
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{stem}{suffix}.{}", ext.to_string_lossy()),
+        None => format!("{stem}{suffix}"),
+    };
+    path.with_file_name(name)
+}
+
+/// Export the model as a PNG, and wait until we receive it from the API.
+/// Then save it to the local filesystem.
+///
+/// Sometimes the API takes the snapshot before it's finished drawing the scene, so it's blank.
+/// If so, this points the camera again, waits a moment, and takes another, as `output.blank` allows.
+/// If the snapshot can't be decoded, it's saved as the API sent it, with a warning, unless it needed
+/// touching up. If it can't be touched up or saved, its payload is kept in the debug directory.
+///
+/// A snapshot with a size is taken with the API's stream reconfigured to that size, if that's known
+/// (or not yet known not) to change the size of snapshots. Otherwise it's scaled here.
+pub async fn export_png(session: &mut ModelingSession, output: &Output) -> Result<()> {
+    let previous = session.enter_phase(Phase::Save);
+    let captured = capture_png(session, output).await?;
+    let image = captured.encode();
+    captured.save(session, image).await?;
+    session.enter_phase(previous);
+    Ok(())
+}
+
+/// A snapshot taken for an output, and how it'll be touched up, waiting to be encoded and saved.
+/// Encoding doesn't need the session, so it can be done on another thread, e.g. by turntables.
+pub struct CapturedPng {
+    path: String,
+    snapshot: Snapshot,
+    touch_up: TouchUp,
+    /// The size it'll be once it's touched up.
+    width: u32,
+    height: u32,
+    sizing: Option<Sizing>,
+}
+
+impl CapturedPng {
+    /// Touch the snapshot up and encode it, as `encode_image` does.
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        encode_image(&self.snapshot.contents, &self.path, &self.touch_up)
+    }
+
+    /// Save the encoded image. If it couldn't be encoded, because the snapshot couldn't be decoded,
+    /// the snapshot is saved as the API sent it instead, with a warning, to a path with its format's
+    /// extension. Unless it needed touching up, which needs its pixels: then it fails, and the
+    /// snapshot's payload is kept in the debug directory.
+    pub async fn save(self, session: &mut ModelingSession, image: Result<Vec<u8>>) -> Result<()> {
+        let (path, image) = match image {
+            Err(e) if self.touch_up.is_empty() => {
+                let raw = raw_path(&self.path, &self.snapshot.contents);
+                let message = format!(
+                    "Could not decode the snapshot for {} ({}), so it was saved as the API sent it, to {raw}",
+                    self.path,
+                    e.root_cause()
+                );
+                Warning::new(Code::SnapshotFormat, message)
+                    .cmd_id(self.snapshot.cmd_id)
+                    .raise()?;
+                (raw, Ok(self.snapshot.contents.clone()))
+            }
+            Err(e) => {
+                let steps = self.touch_up.steps().join(", ");
+                let e = e.wrap_err(format!(
+                    "Could not touch up {} ({steps}), which needs its pixels",
+                    self.path
+                ));
+                (self.path.clone(), Err(e))
+            }
+            image => (self.path.clone(), image),
+        };
+        let saved = match image {
+            Ok(image) => session.write_output(&path, &image).await.map(|_| image),
+            Err(e) => Err(e),
+        };
+        let image = match saved {
+            Ok(image) => image,
+            Err(e) => {
+                return Err(session.keep_payload(
+                    self.snapshot.cmd_id,
+                    "save",
+                    &self.snapshot.contents,
+                    e,
+                ))
+            }
+        };
+        session
+            .record_output(
+                &path,
+                &image,
+                OutputKind::Image {
+                    width: self.width,
+                    height: self.height,
+                    sizing: self.sizing,
+                },
+            )
+            .await
+    }
+}
+
+/// Take the snapshot for an output, as `export_png` does, and work out how to touch it up,
+/// but leave encoding and saving it to the caller.
+pub async fn capture_png(session: &mut ModelingSession, output: &Output) -> Result<CapturedPng> {
+    let reconfigured = match output.size {
+        Some((width, height)) if session.stream_sizes_snapshots() != Some(false) => {
+            reconfigure_stream(session, width, height).await?
+        }
+        _ => false,
+    };
+    let mut attempts = 1;
+    let captured: Result<Snapshot> = async {
+        loop {
+            let snapshot = capture_snapshot(session, ImageFormat::Png).await?;
+            if output.blank.retries == 0 {
+                return Ok(snapshot);
+            }
+            match decode(&snapshot.contents) {
+                Ok(img) if !output.blank.is_blank(&img) => return Ok(snapshot),
+                Ok(_) => {}
+                // It may still be saved as it is, if it doesn't need touching up.
+                Err(e) => {
+                    let message = format!(
+                        "Could not check whether the snapshot for {} is blank, because it couldn't be decoded ({})",
+                        output.path,
+                        e.root_cause()
+                    );
+                    Warning::new(Code::SnapshotFormat, message)
+                        .cmd_id(snapshot.cmd_id)
+                        .raise()?;
+                    return Ok(snapshot);
+                }
+            }
+            if attempts > output.blank.retries {
+                return Err(BlankSnapshot { attempts }.into());
+            }
+            Warning::new(
+                Code::BlankSnapshot,
+                "The snapshot was blank, so taking it again",
+            )
+            .raise()?;
+            attempts += 1;
+            session.enter_phase(Phase::Snapshot);
+            tokio::time::sleep(BLANK_RETRY_DELAY).await;
+            session.check_cancelled()?;
+            session.enter_phase(Phase::Save);
+            if let (Some(framed), Some(camera)) =
+                (session.framed().cloned(), session.pointed().cloned())
+            {
+                camera::look_at(session, &framed, camera).await?;
+            }
+        }
+    }
+    .await;
+    // Put the stream back how it was, even if the snapshot failed.
+    if reconfigured {
+        let stream = session.stream();
+        reconfigure_stream(session, stream.video_res_width, stream.video_res_height).await?;
+    }
+    let snapshot = captured?;
+    let (mut width, mut height) = image_size(&snapshot.contents);
+    if reconfigured {
+        session.set_stream_sizes_snapshots(Some((width, height)) == output.size);
+    }
+    let dimensions = match &output.dimensions {
+        lines if lines.is_empty() => Vec::new(),
+        _ if !output.reencode && !label::is_lossless(&output.path) => {
+            let message = format!(
+                "Not drawing dimensions on {}, because --no-reencode is set, and its format loses detail",
+                output.path
+            );
+            Warning::new(Code::CaptionSkipped, message)
+                .parameter("annotations")
+                .raise()?;
+            Vec::new()
+        }
+        lines => dimension::project(session, lines, width, height)?,
+    };
+    let crop = match &output.crop {
+        Some(crop) => Some(crop_rect(
+            session,
+            crop,
+            output.crop_padding,
+            width,
+            height,
+        )?),
+        None => None,
+    };
+    if let Some(rect) = crop {
+        (width, height) = (rect.width, rect.height);
+    }
+    let sizing = match output.size {
+        Some(size) if size == (width, height) => Some(Sizing::Captured {
+            stream_reconfigured: reconfigured,
+        }),
+        Some((to_width, to_height)) => {
+            if to_width > width || to_height > height {
+                let message = format!(
+                    "{} is {to_width}x{to_height}, but the API's snapshot was only {width}x{height}, so it was scaled up",
+                    output.path
+                );
+                Warning::new(Code::ScaledUp, message)
+                    .parameter("snapshot")
+                    .raise()?;
+            }
+            let sizing = Sizing::Resized {
+                from_width: width,
+                from_height: height,
+            };
+            (width, height) = (to_width, to_height);
+            Some(sizing)
+        }
+        None => None,
+    };
+    let mut label = match &output.label {
+        Some(_) if !output.reencode && !label::is_lossless(&output.path) => {
+            let message = format!(
+                "Not captioning {}, because --no-reencode is set, and its format loses detail",
+                output.path
+            );
+            Warning::new(Code::CaptionSkipped, message)
+                .parameter("label")
+                .raise()?;
+            None
+        }
+        label => label.clone(),
+    };
+    let mut background = output.background;
+    let what = format!("captioning and repainting {}", output.path);
+    if (background.is_some() || label.is_some())
+        && !budget::allows(budget::Optional::TouchUp, &what)?
+    {
+        (background, label) = (None, None);
+    }
+    if let Some(text) = &label {
+        height += label::layout(text, width).height;
+    }
+    Ok(CapturedPng {
+        path: output.path.clone(),
+        snapshot,
+        touch_up: TouchUp {
+            background,
+            crop,
+            size: output.size,
+            label,
+            dimensions,
+        },
+        width,
+        height,
+        sizing,
+    })
+}
+
+/// Ask the API to make its stream this size, which may make its snapshots this size too.
+/// Returns whether it did, or false if the API refused.
+async fn reconfigure_stream(
+    session: &mut ModelingSession,
+    width: u32,
+    height: u32,
+) -> Result<bool> {
+    let cmd_id = session
+        .send(ModelingCmd::ReconfigureStream {
+            fps: session.stream().fps,
+            width,
+            height,
+        })
+        .await?;
+    match session.await_response(cmd_id).await {
+        Ok(_) => Ok(true),
+        Err(e) if ConnectionLost::caused(&e) => Err(e),
+        // Snapshots will be scaled here instead, so there's no need to ask again.
+        Err(_) => {
+            session.set_stream_sizes_snapshots(false);
+            Ok(false)
+        }
+    }
+}
+
+/// Crop an image to the shape of the size asked for, keeping its middle, then scale it to that size.
+pub fn fit(img: &image::RgbaImage, width: u32, height: u32) -> image::RgbaImage {
+    let (from_width, from_height) = img.dimensions();
+    // Compare the shapes without dividing, so nothing is lost to rounding.
+    let (crop_width, crop_height) =
+        if u64::from(from_width) * u64::from(height) > u64::from(width) * u64::from(from_height) {
+            let crop_width = u64::from(from_height) * u64::from(width) / u64::from(height);
+            (crop_width.max(1) as u32, from_height)
+        } else {
+            let crop_height = u64::from(from_width) * u64::from(height) / u64::from(width);
+            (from_width, crop_height.max(1) as u32)
+        };
+    let cropped = image::imageops::crop_imm(
+        img,
+        (from_width - crop_width) / 2,
+        (from_height - crop_height) / 2,
+        crop_width,
+        crop_height,
+    )
+    .to_image();
+    image::imageops::resize(
+        &cropped,
+        width,
+        height,
+        image::imageops::FilterType::Lanczos3,
+    )
+}
+
+/// How long to wait before taking another snapshot, after a blank one.
+const BLANK_RETRY_DELAY: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// A snapshot, as the API sent it.
+pub struct Snapshot {
+    /// The ID of the command which took it, to find its payload by if it can't be handled.
+    pub cmd_id: Uuid,
+    /// The image file's bytes.
+    pub contents: Vec<u8>,
+}
+
+/// Ask the API for a snapshot of the scene as it's framed now.
+/// Nothing is decoded or saved, so callers can do what they like with it, e.g. `encode_image`.
+/// If it's an image in another format, it's returned with a warning, because it can still be saved
+/// as it is. If it isn't an image at all, its payload is kept in the debug directory.
+pub async fn capture_snapshot(
+    session: &mut ModelingSession,
+    format: ImageFormat,
+) -> Result<Snapshot> {
+    let previous = session.enter_phase(Phase::Snapshot);
+    let snapshot_id = session
+        .send(ModelingCmd::TakeSnapshot {
+            format: format.clone(),
+        })
+        .await?;
+    let OkModelingCmdResponse::TakeSnapshot { data } = session.await_response(snapshot_id).await?
+    else {
+        bail!("Expected the API to respond to TakeSnapshot with an image");
+    };
+    session.enter_phase(previous);
+    let contents: Vec<u8> = data.contents.into();
+    let signature = match format {
+        ImageFormat::Png => PNG_SIGNATURE,
+        ImageFormat::Jpeg => JPEG_SIGNATURE,
+    };
+    if !contents.starts_with(signature) {
+        let Ok(found) = image::guess_format(&contents) else {
+            let e = eyre!("Expected the API's snapshot to be a {format}, but it isn't");
+            return Err(session.keep_payload(snapshot_id, "signature", &contents, e));
+        };
+        let message = format!("Asked the API for a {format} snapshot, but it sent a {found:?}");
+        Warning::new(Code::SnapshotFormat, message)
+            .cmd_id(snapshot_id)
+            .raise()?;
+    }
+    Ok(Snapshot {
+        cmd_id: snapshot_id,
+        contents,
+    })
+}
+
+/// Decode an image file, in whichever format it's in.
+fn decode(contents: &[u8]) -> Result<image::RgbaImage> {
+    let img = image::io::Reader::new(Cursor::new(contents)).with_guessed_format()?;
+    Ok(img.decode()?.into_rgba8())
+}
+
+/// Every PNG file starts with these bytes.
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Every JPEG file starts with these bytes.
+const JPEG_SIGNATURE: &[u8] = b"\xff\xd8\xff";
+
+/// Where to save an image file as it is, instead of at `path`: at `path`, unless its extension is
+/// another format's, when it's changed to the file's format's (which is told by its first few bytes),
+/// e.g. "model.webp" instead of "model.png".
+fn raw_path(path: &str, contents: &[u8]) -> String {
+    let Ok(format) = image::guess_format(contents) else {
+        return path.to_owned();
+    };
+    let extension = Path::new(path).extension().unwrap_or_default();
+    let fits = format
+        .extensions_str()
+        .iter()
+        .any(|ext| extension.eq_ignore_ascii_case(ext));
+    match format.extensions_str().first() {
+        Some(ext) if !fits && path != STDOUT => Path::new(path)
+            .with_extension(ext)
+            .to_string_lossy()
+            .into_owned(),
+        _ => path.to_owned(),
+    }
+}
+
+/// How big an image is, from its header, or 0x0 if that can't be read.
+fn image_size(contents: &[u8]) -> (u32, u32) {
+    if contents.starts_with(PNG_SIGNATURE) {
+        return png_size(contents);
+    }
+    image::io::Reader::new(Cursor::new(contents))
+        .with_guessed_format()
+        .ok()
+        .and_then(|reader| reader.into_dimensions().ok())
+        .unwrap_or((0, 0))
+}
+
+/// How big a PNG is, from its header, which comes straight after the signature.
+fn png_size(contents: &[u8]) -> (u32, u32) {
+    let number = |at: usize| {
+        contents
+            .get(at..at + 4)
+            .map_or(0, |b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    };
+    (number(16), number(20))
+}
+
+/// How to touch up a snapshot before it's saved.
+#[derive(Clone, Debug, Default)]
+pub struct TouchUp {
+    /// If set, the background is repainted this color.
+    pub background: Option<Color>,
+    /// If set, only these pixels are kept.
+    pub crop: Option<PixelRect>,
+    /// If set, the image is made this size with `fit`, after cropping, if it isn't already.
+    pub size: Option<(u32, u32)>,
+    /// If set, this caption is drawn in a strip added along the bottom, after cropping.
+    pub label: Option<String>,
+    /// Dimensions drawn on the image before it's cropped, in its pixels.
+    pub dimensions: Vec<Projected>,
+}
+
+impl TouchUp {
+    /// What it does to a snapshot, e.g. "crop it", each of which needs the snapshot's pixels.
+    fn steps(&self) -> Vec<&'static str> {
+        [
+            (self.background.is_some(), "repaint its background"),
+            (!self.dimensions.is_empty(), "draw dimensions on it"),
+            (self.crop.is_some(), "crop it"),
+            (self.size.is_some(), "make it the size asked for"),
+            (self.label.is_some(), "caption it"),
+        ]
+        .into_iter()
+        .filter_map(|(does, step)| does.then_some(step))
+        .collect()
+    }
+
+    /// Whether it leaves a snapshot as it is (apart from its format).
+    fn is_empty(&self) -> bool {
+        self.steps().is_empty()
+    }
+}
+
+/// Touch up an image file from the API (e.g. from `capture_snapshot`), and encode it to be saved to a path.
+/// The image crate picks the format from the path's extension.
+/// If it's a PNG which doesn't need touching up or converting, it's kept exactly as the API sent it.
+/// Snapshots saved to standard output are always PNGs.
+pub fn encode_image(contents: &[u8], path: &str, touch_up: &TouchUp) -> Result<Vec<u8>> {
+    let to_stdout = path == STDOUT;
+    let to_png = to_stdout
+        || Path::new(path)
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("png"));
+    let sized = touch_up
+        .size
+        .is_none_or(|size| contents.starts_with(PNG_SIGNATURE) && png_size(contents) == size);
+    if to_png
+        && contents.starts_with(PNG_SIGNATURE)
+        && touch_up.background.is_none()
+        && touch_up.crop.is_none()
+        && sized
+        && touch_up.label.is_none()
+        && touch_up.dimensions.is_empty()
+    {
+        return Ok(contents.to_vec());
+    }
+
+    // Otherwise, decode the pixels.
+    let mut img = decode(contents)?;
+    // Repaint before cropping, because the crop's corners may be the model, not the background.
+    if let Some(color) = touch_up.background {
+        crate::background::repaint(&mut img, color);
+    }
+    // Drawn on the background's colour, which is the top-left corner's, unless the model covers it.
+    if !touch_up.dimensions.is_empty() {
+        let paper = touch_up
+            .background
+            .map(|color| image::Rgba([color.r, color.g, color.b, 255]))
+            .unwrap_or(*img.get_pixel(0, 0));
+        for d in &touch_up.dimensions {
+            dimension::draw(&mut img, d, label::ink_on(paper), paper);
+        }
+    }
+    if let Some(rect) = touch_up.crop {
+        img = image::imageops::crop_imm(&img, rect.x, rect.y, rect.width, rect.height).to_image();
+    }
+    if let Some((width, height)) = touch_up.size {
+        if img.dimensions() != (width, height) {
+            img = fit(&img, width, height);
+        }
+    }
+    if let Some(text) = &touch_up.label {
+        img = label::add_caption(&img, text);
+    }
+    let format = if to_png {
+        image::ImageFormat::Png
+    } else {
+        image::ImageFormat::from_path(path)
+            .wrap_err_with(|| format!("Could not tell which format to save {path} as"))?
+    };
+    let mut encoded = Cursor::new(Vec::new());
+    img.write_to(&mut encoded, format)
+        .wrap_err_with(|| format!("Could not save {path}"))?;
+    Ok(encoded.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::ConnectionProfile;
+
+    fn rect(x: u32, y: u32, width: u32, height: u32) -> PixelRect {
+        PixelRect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn tells_a_blank_snapshot_from_one_of_a_model() {
+        let blank = Output::new("model.png").blank;
+        let background = image::Rgba([40, 44, 52, 255]);
+        let mut img = image::RgbaImage::from_pixel(100, 50, background);
+        assert!(blank.is_blank(&img));
+        // Even a small model, 10 by 10 pixels of 100 by 50, isn't blank.
+        for x in 45..55 {
+            for y in 20..30 {
+                img.put_pixel(x, y, image::Rgba([200, 120, 60, 255]));
+            }
+        }
+        assert!(!blank.is_blank(&img));
+    }
+
+    #[test]
+    fn keeps_a_crop_inside_the_image() {
+        assert_eq!(
+            PixelRect::clamped(10, 20, 30, 40, 100, 50),
+            Some(rect(10, 20, 20, 20))
+        );
+        // Off the top-left, or the bottom-right, it's cut at the image's edges.
+        assert_eq!(
+            PixelRect::clamped(-16, -16, 30, 40, 100, 50),
+            Some(rect(0, 0, 30, 40))
+        );
+        assert_eq!(
+            PixelRect::clamped(90, 40, 116, 66, 100, 50),
+            Some(rect(90, 40, 10, 10))
+        );
+        assert_eq!(
+            PixelRect::clamped(-5, -5, 105, 55, 100, 50),
+            Some(rect(0, 0, 100, 50))
+        );
+    }
+
+    #[test]
+    fn has_no_crop_outside_the_image() {
+        assert_eq!(PixelRect::clamped(100, 0, 120, 50, 100, 50), None);
+        assert_eq!(PixelRect::clamped(-20, -20, 0, 0, 100, 50), None);
+        assert_eq!(PixelRect::clamped(10, 10, 10, 20, 100, 50), None);
+    }
+
+    #[test]
+    fn crops_pixels_to_the_snapshot() {
+        let session = ModelingSession::canned("crop-pixels", &[]);
+        let crop = |rect| crop_rect(&session, &Crop::Pixels(rect), 16, 100, 50);
+        assert_eq!(crop(rect(80, 30, 40, 40)).unwrap(), rect(80, 30, 20, 20));
+        // The padding is only for objects.
+        assert_eq!(crop(rect(0, 0, 10, 10)).unwrap(), rect(0, 0, 10, 10));
+        assert_eq!(
+            crop(rect(100, 0, 10, 10)).unwrap_err().to_string(),
+            "The crop 100,0,10,10 is outside the 100x50 snapshot"
+        );
+    }
+
+    /// A 40x20 PNG with 16 bits a channel, a gradient so it isn't blank.
+    fn png_16_bit() -> Vec<u8> {
+        let img = image::ImageBuffer::from_fn(40, 20, |x, y| {
+            image::Rgba([x as u16 * 1600, y as u16 * 3200, 40_000, u16::MAX])
+        });
+        let mut png = Cursor::new(Vec::new());
+        image::DynamicImage::ImageRgba16(img)
+            .write_to(&mut png, image::ImageFormat::Png)
+            .unwrap();
+        let png = png.into_inner();
+        // Its header says so: bit depth 16, colour type 6 (RGBA).
+        assert_eq!(png[24..26], [16, 6]);
+        png
+    }
+
+    /// A PNG cut off halfway through its pixels, with its header intact.
+    fn png_truncated() -> Vec<u8> {
+        let mut png = png_16_bit();
+        png.truncate(png.len() / 2);
+        png
+    }
+
+    /// A snapshot waiting to be saved to `path`, in a directory of the test's own.
+    fn captured(test: &str, path: &str, contents: Vec<u8>, touch_up: TouchUp) -> CapturedPng {
+        let dir =
+            std::env::temp_dir().join(format!("kittycad-snapshot-{test}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let (width, height) = image_size(&contents);
+        CapturedPng {
+            path: dir.join(path).display().to_string(),
+            snapshot: Snapshot {
+                cmd_id: Uuid::new_v4(),
+                contents,
+            },
+            touch_up,
+            width,
+            height,
+            sizing: None,
+        }
+    }
+
+    /// Save a snapshot as `export_png` does, with its debug directory next to it.
+    fn save(captured: CapturedPng) -> (Result<()>, PathBuf) {
+        let dir = Path::new(&captured.path).parent().unwrap().to_owned();
+        let name = dir.file_name().unwrap().to_string_lossy();
+        let mut session = ModelingSession::canned(&name, &[]);
+        session.set_sidecars(false);
+        session.set_debug_dir(crate::debug::DebugDir {
+            dir: dir.join("debug"),
+            max_bytes: 1 << 20,
+        });
+        let image = captured.encode();
+        let saved = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(captured.save(&mut session, image));
+        (saved, dir)
+    }
+
+    /// The payloads kept in a test's debug directory.
+    fn kept(dir: &Path) -> Vec<Vec<u8>> {
+        std::fs::read_dir(dir.join("debug"))
+            .map(|entries| {
+                entries
+                    .map(|entry| std::fs::read(entry.unwrap().path()).unwrap())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn label() -> TouchUp {
+        TouchUp {
+            label: Some("plate".to_owned()),
+            ..TouchUp::default()
+        }
+    }
+
+    #[test]
+    fn reads_the_size_of_a_snapshot_from_its_header() {
+        assert_eq!(image_size(&png_16_bit()), (40, 20));
+        assert_eq!(image_size(&png_truncated()), (40, 20));
+        assert_eq!(image_size(b"not an image"), (0, 0));
+    }
+
+    #[test]
+    fn saves_a_16_bit_png_as_it_was_sent() {
+        let png = png_16_bit();
+        let captured = captured("16-bit", "model.png", png.clone(), TouchUp::default());
+        let path = captured.path.clone();
+        let (saved, dir) = save(captured);
+        saved.unwrap();
+        assert_eq!(std::fs::read(path).unwrap(), png);
+        assert!(kept(&dir).is_empty());
+    }
+
+    #[test]
+    fn touches_up_a_16_bit_png() {
+        let captured = captured("16-bit-label", "model.png", png_16_bit(), label());
+        let path = captured.path.clone();
+        let (saved, _) = save(captured);
+        saved.unwrap();
+        let saved = decode(&std::fs::read(path).unwrap()).unwrap();
+        // The caption's strip is added along the bottom.
+        assert_eq!(saved.width(), 40);
+        assert!(saved.height() > 20);
+        assert_eq!(saved.get_pixel(39, 0).0, [243, 0, 156, 255]);
+    }
+
+    #[test]
+    fn saves_a_truncated_png_as_it_was_sent_with_a_warning() {
+        let png = png_truncated();
+        // Saving it as a JPEG needs its pixels, which aren't all there.
+        let captured = captured("truncated", "model.jpg", png.clone(), TouchUp::default());
+        let path = captured.path.clone();
+        assert!(captured.encode().is_err());
+        let (saved, dir) = save(captured);
+        saved.unwrap();
+        // As a PNG, which is what it is.
+        let raw = dir.join("model.png");
+        assert_eq!(std::fs::read(&raw).unwrap(), png);
+        assert!(!Path::new(&path).exists());
+        assert!(kept(&dir).is_empty());
+        let warned = format!("so it was saved as the API sent it, to {}", raw.display());
+        let warning = crate::warnings::warnings()
+            .into_iter()
+            .find(|w| w.message.ends_with(&warned))
+            .expect("No warning about the truncated PNG");
+        assert_eq!(warning.code, Code::SnapshotFormat);
+        assert!(warning
+            .message
+            .starts_with(&format!("Could not decode the snapshot for {path} (")));
+    }
+
+    #[test]
+    fn fails_to_touch_up_a_truncated_png_and_keeps_it() {
+        let png = png_truncated();
+        let touch_up = TouchUp {
+            crop: Some(rect(0, 0, 10, 10)),
+            ..label()
+        };
+        let captured = captured("truncated-label", "model.png", png.clone(), touch_up);
+        let path = captured.path.clone();
+        let (saved, dir) = save(captured);
+        let e = saved.unwrap_err();
+        let chain: Vec<_> = e.chain().map(|e| e.to_string()).collect();
+        assert!(
+            chain.contains(&format!(
+                "Could not touch up {path} (crop it, caption it), which needs its pixels"
+            )),
+            "{chain:?}"
+        );
+        assert!(!Path::new(&path).exists());
+        assert_eq!(kept(&dir), [png]);
+    }
+
+    #[test]
+    fn saves_raw_snapshots_with_their_format_s_extension() {
+        let png = png_truncated();
+        assert_eq!(raw_path("out/model.png", &png), "out/model.png");
+        assert_eq!(raw_path("out/model.PNG", &png), "out/model.PNG");
+        assert_eq!(raw_path("out/model.jpg", &png), "out/model.png");
+        assert_eq!(raw_path("out/model", &png), "out/model.png");
+        assert_eq!(
+            raw_path("out/model.png", b"RIFF\0\0\0\0WEBPVP8 "),
+            "out/model.webp"
+        );
+        assert_eq!(raw_path(STDOUT, b"RIFF\0\0\0\0WEBPVP8 "), STDOUT);
+        // Not an image the image crate knows, so there's no better extension.
+        assert_eq!(raw_path("out/model.png", b"not an image"), "out/model.png");
+    }
+
+    #[test]
+    fn saves_images_in_the_format_of_their_extension() {
+        let png = png_16_bit();
+        let formats = [
+            ("model.png", image::ImageFormat::Png),
+            ("model.PNG", image::ImageFormat::Png),
+            ("model.jpg", image::ImageFormat::Jpeg),
+            ("model.jpeg", image::ImageFormat::Jpeg),
+            ("model.bmp", image::ImageFormat::Bmp),
+            ("model.tiff", image::ImageFormat::Tiff),
+            (STDOUT, image::ImageFormat::Png),
+        ];
+        for (path, format) in formats {
+            let encoded = encode_image(&png, path, &label()).unwrap();
+            assert_eq!(image::guess_format(&encoded).unwrap(), format, "{path}");
+        }
+    }
+
+    #[test]
+    fn fails_to_save_an_image_with_an_extension_it_doesnt_know() {
+        let e = encode_image(&png_16_bit(), "model.txt", &label()).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Could not tell which format to save model.txt as"
+        );
+        let e = encode_image(&png_16_bit(), "model", &label()).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Could not tell which format to save model as"
+        );
+    }
+
+    #[test]
+    fn captures_a_snapshot_whose_response_comes_after_an_export_s() {
+        let recording = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/recordings/snapshot-after-export.jsonl");
+        let mut session =
+            ModelingSession::replay(&recording, usize::MAX, ConnectionProfile::default()).unwrap();
+        let (snapshot, files) = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let export_id = session
+                    .send(ModelingCmd::Export {
+                        entity_ids: Vec::new(),
+                        format: crate::export::format_for(Path::new("model.stl"), "stl")?,
+                        source_unit: crate::units::SCENE_UNIT,
+                    })
+                    .await?;
+                let snapshot = capture_snapshot(&mut session, ImageFormat::Png).await?;
+                // The export's response came first, and was kept for whoever waits for it.
+                let files = session.await_export(export_id).await?;
+                Result::<_>::Ok((snapshot, files))
+            })
+            .unwrap();
+        assert!(snapshot.contents.starts_with(PNG_SIGNATURE));
+        assert_eq!(image_size(&snapshot.contents), (64, 64));
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].name, "output.stl");
+    }
+
+    #[test]
+    fn parses_a_snapshot_s_size_and_path() {
+        let snapshot = SizedSnapshot::parse("1920x1080:hero.png").unwrap();
+        assert_eq!((snapshot.width, snapshot.height), (1920, 1080));
+        assert_eq!(snapshot.path, "hero.png");
+        // Only the first colon ends the size, so the path may have its own.
+        let snapshot = SizedSnapshot::parse("256x256:C:\\thumbs\\cube.png").unwrap();
+        assert_eq!((snapshot.width, snapshot.height), (256, 256));
+        assert_eq!(snapshot.path, "C:\\thumbs\\cube.png");
+    }
+
+    #[test]
+    fn refuses_a_snapshot_which_doesnt_look_like_width_x_height_path() {
+        for malformed in [
+            "hero.png",
+            "1920:hero.png",
+            "1920x:hero.png",
+            "x1080:hero.png",
+            "wide x tall:hero.png",
+            "-1920x1080:hero.png",
+            "0x1080:hero.png",
+            "1920x1080:",
+        ] {
+            let e = SizedSnapshot::parse(malformed).unwrap_err().to_string();
+            assert!(
+                e.contains("should look like WIDTHxHEIGHT:PATH"),
+                "{malformed}: {e}"
+            );
+        }
+        let e = SizedSnapshot::parse("1920x1080:-").unwrap_err().to_string();
+        assert!(
+            e.contains("Only the main snapshot can go to standard output"),
+            "{e}"
+        );
+    }
+
+    #[test]
+    fn refuses_a_snapshot_size_with_units() {
+        for suffixed in [
+            "1920pxx1080px:hero.png",
+            "1920x1080px:hero.png",
+            "10cmx10cm:hero.png",
+        ] {
+            let e = SizedSnapshot::parse(suffixed).unwrap_err().to_string();
+            assert!(
+                e.contains("with a width and height of at least 1 pixel"),
+                "{suffixed}: {e}"
+            );
+        }
+    }
+
+    /// An image whose middle `middle` columns (or rows, if `across` is false) are white, and the rest black.
+    fn striped(width: u32, height: u32, middle: u32, across: bool) -> image::RgbaImage {
+        image::RgbaImage::from_fn(width, height, |x, y| {
+            let (at, length) = if across { (x, width) } else { (y, height) };
+            let edge = (length - middle) / 2;
+            if (edge..edge + middle).contains(&at) {
+                image::Rgba([255, 255, 255, 255])
+            } else {
+                image::Rgba([0, 0, 0, 255])
+            }
+        })
+    }
+
+    #[test]
+    fn crops_the_sides_of_an_image_wider_than_the_size_asked_for() {
+        let fitted = fit(&striped(200, 100, 100, true), 50, 50);
+        assert_eq!(fitted.dimensions(), (50, 50));
+        assert!(fitted.pixels().all(|p| p.0 == [255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn crops_the_top_and_bottom_of_an_image_taller_than_the_size_asked_for() {
+        let fitted = fit(&striped(100, 400, 200, false), 30, 60);
+        assert_eq!(fitted.dimensions(), (30, 60));
+        assert!(fitted.pixels().all(|p| p.0 == [255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn scales_an_image_already_the_shape_asked_for_without_cropping_it() {
+        // Half of it is white, so half the scaled image should be too, had nothing been cropped.
+        let fitted = fit(&striped(640, 480, 320, true), 320, 240);
+        assert_eq!(fitted.dimensions(), (320, 240));
+        let white = fitted.pixels().filter(|p| p.0[0] > 127).count();
+        assert_eq!(white, 160 * 240);
+        // Scaling up works too.
+        assert_eq!(
+            fit(&striped(64, 48, 32, true), 1280, 960).dimensions(),
+            (1280, 960)
+        );
+    }
+}
@@ -0,0 +1,91 @@
+use crate::{names, recipe::Recipe, session::ModelingSession, sink};
+use color_eyre::{
+    eyre::{bail, WrapErr},
+    Result,
+};
+use kittycad::types::ModelingCmd;
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use uuid::Uuid;
+
+/// What one run built, saved so that a later run can try to carry on from it.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct State {
+    /// When the state was saved, in seconds since the Unix epoch.
+    pub saved_at: u64,
+    /// The command-line arguments which built the model, so it can be rebuilt.
+    pub args: Vec<String>,
+    /// The objects to measure and frame.
+    pub object_ids: Vec<Uuid>,
+    /// How each object was built.
+    pub recipes: BTreeMap<Uuid, Recipe>,
+    /// What the objects were called, so they can still be referred to by name.
+    #[serde(default)]
+    pub names: BTreeMap<String, Uuid>,
+}
+
+impl State {
+    /// Record what the session built.
+    pub fn new(session: &ModelingSession, object_ids: &[Uuid]) -> Self {
+        let saved_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let recipes = object_ids
+            .iter()
+            .filter_map(|&id| Some((id, session.recipe(id)?.clone())))
+            .collect();
+        Self {
+            saved_at,
+            args: std::env::args().skip(1).collect(),
+            object_ids: object_ids.to_vec(),
+            recipes,
+            names: session.names().ids(),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        sink::write_atomically(path, json.as_bytes())
+            .wrap_err_with(|| format!("Could not save state to {}", path.display()))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("Could not read state file {}", path.display()))?;
+        serde_json::from_str(&text)
+            .wrap_err_with(|| format!("Could not parse state file {}", path.display()))
+    }
+
+    /// Check every saved object still exists in the session's scene, and start tracking them again.
+    /// Each WebSocket connection to the API (as of this version of the kittycad crate) starts with
+    /// an empty scene, so expect this to fail with an explanation.
+    pub async fn resume(&self, session: &mut ModelingSession) -> Result<()> {
+        for &object_id in &self.object_ids {
+            let cmd_id = session
+                .send(ModelingCmd::GetEntityType {
+                    entity_id: object_id,
+                })
+                .await?;
+            if let Err(e) = session.await_response(cmd_id).await {
+                bail!(
+                    "Object {object_id} from the saved state no longer exists ({e}). \
+                     The API doesn't keep scenes between connections, \
+                     so rebuild the model by running again with: {}",
+                    self.args.join(" ")
+                );
+            }
+        }
+        for (&id, recipe) in &self.recipes {
+            session.record_recipe(id, recipe.clone());
+        }
+        for (name, &id) in &self.names {
+            session.names_mut().register(name, id)?;
+        }
+        // Names may refer to objects which weren't saved, e.g. ones which were removed.
+        names::revalidate(session).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,268 @@
+use color_eyre::{eyre::bail, Result};
+
+/// The miter limit SVG uses by default: a corner is bevelled once its miter would be more than
+/// 4 times as long as the stroke is wide, i.e. once it turns by more than about 151 degrees.
+pub const DEFAULT_MITER_LIMIT: f64 = 4.0;
+
+/// The outline of an open path drawn with a thick pen, ready to extrude.
+pub struct Stroke {
+    /// The outline's corners, counter-clockwise. It's closed from the last back to the first.
+    pub outline: Vec<(f64, f64)>,
+    /// The path's corners (counting its points from 0) which were too sharp for the miter limit,
+    /// so were cut off square (bevelled), and by how many degrees the path turns at each.
+    pub bevelled: Vec<(usize, f64)>,
+}
+
+/// Offset an open path by half of `width` on each side, and join the two sides into one outline.
+/// Corners are mitered, unless the miter would be longer than `miter_limit` times `width`,
+/// in which case they're bevelled. The ends are square, reaching half of `width` past the path's ends.
+/// Fails if the path has fewer than 2 points, repeats a point, turns straight back on itself,
+/// or its outline would cross itself (e.g. because a segment is shorter than the wall is thick).
+pub fn outline(path: &[(f64, f64)], width: f64, miter_limit: f64) -> Result<Stroke> {
+    if path.len() < 2 {
+        bail!(
+            "A path needs at least 2 points, but only {} were given",
+            path.len()
+        );
+    }
+    if !(width > 0.0 && width.is_finite()) {
+        bail!("The stroke's width must be a positive number, not {width}");
+    }
+    if miter_limit.is_nan() || miter_limit < 1.0 {
+        bail!("The miter limit must be at least 1, not {miter_limit}");
+    }
+    let half = width / 2.0;
+    // Each segment's direction, and the normal on its left.
+    let mut directions = Vec::new();
+    for (i, pair) in path.windows(2).enumerate() {
+        let (dx, dy) = (pair[1].0 - pair[0].0, pair[1].1 - pair[0].1);
+        let length = dx.hypot(dy);
+        if length < 1e-9 {
+            bail!("Points {i} and {} of the path are in the same place", i + 1);
+        }
+        directions.push((dx / length, dy / length));
+    }
+    let left = |(dx, dy): (f64, f64)| (-dy, dx);
+    let at = |(x, y): (f64, f64), (nx, ny): (f64, f64), by: f64| (x + nx * by, y + ny * by);
+
+    // Each side's corners, from the start of the path to its end.
+    let mut sides: [Vec<(f64, f64)>; 2] = [Vec::new(), Vec::new()];
+    let mut bevelled = Vec::new();
+    let (first, last) = (directions[0], directions[directions.len() - 1]);
+    let start = at(path[0], first, -half);
+    let end = at(path[path.len() - 1], last, half);
+    for (side, sign) in sides.iter_mut().zip([1.0, -1.0]) {
+        side.push(at(start, left(first), sign * half));
+    }
+    for i in 1..path.len() - 1 {
+        let (before, after) = (directions[i - 1], directions[i]);
+        let (n0, n1) = (left(before), left(after));
+        let cos = n0.0 * n1.0 + n0.1 * n1.1;
+        if cos < -1.0 + 1e-9 {
+            bail!("The path turns straight back on itself at point {i}");
+        }
+        // Where the two offset lines meet, as a multiple of the half width.
+        let miter = ((n0.0 + n1.0) / (1.0 + cos), (n0.1 + n1.1) / (1.0 + cos));
+        let ratio = miter.0.hypot(miter.1);
+        // Turning left puts the outside of the corner on the right.
+        let turns_left = before.0 * after.1 - before.1 * after.0 > 0.0;
+        let bevel = ratio > miter_limit;
+        if bevel {
+            bevelled.push((i, cos.clamp(-1.0, 1.0).acos().to_degrees()));
+        }
+        for (side, sign) in sides.iter_mut().zip([1.0, -1.0]) {
+            let outside = (sign < 0.0) == turns_left;
+            if bevel && outside {
+                side.push(at(path[i], n0, sign * half));
+                side.push(at(path[i], n1, sign * half));
+            } else {
+                side.push(at(path[i], miter, sign * half));
+            }
+        }
+    }
+    for (side, sign) in sides.iter_mut().zip([1.0, -1.0]) {
+        side.push(at(end, left(last), sign * half));
+    }
+
+    // Along the right side, then back along the left, is counter-clockwise.
+    let [left_side, right_side] = sides;
+    let mut outline = right_side;
+    outline.extend(left_side.into_iter().rev());
+    if crosses_itself(&outline) {
+        bail!(
+            "The stroked path's outline crosses itself. The path mustn't cross itself, \
+             and its segments must be longer than the stroke is wide near sharp corners"
+        );
+    }
+    Ok(Stroke { outline, bevelled })
+}
+
+/// The area inside a polygon which doesn't cross itself.
+pub fn area(polygon: &[(f64, f64)]) -> f64 {
+    // The shoelace formula.
+    let n = polygon.len();
+    let twice_area: f64 = (0..n)
+        .map(|i| {
+            let (x0, y0) = polygon[i];
+            let (x1, y1) = polygon[(i + 1) % n];
+            x0 * y1 - x1 * y0
+        })
+        .sum();
+    (twice_area / 2.0).abs()
+}
+
+/// Whether any two of a closed polygon's edges cross, other than neighbours meeting at their corner.
+pub fn crosses_itself(polygon: &[(f64, f64)]) -> bool {
+    let n = polygon.len();
+    let edge = |i: usize| (polygon[i], polygon[(i + 1) % n]);
+    (0..n).any(|i| {
+        (i + 2..n)
+            // The last edge meets the first at the first corner.
+            .filter(|&j| !(i == 0 && j == n - 1))
+            .any(|j| segments_cross(edge(i), edge(j)))
+    })
+}
+
+/// Whether two line segments touch or cross.
+fn segments_cross(a: ((f64, f64), (f64, f64)), b: ((f64, f64), (f64, f64))) -> bool {
+    // Which side of the line through p and q r is on: positive for left, negative for right.
+    let side = |p: (f64, f64), q: (f64, f64), r: (f64, f64)| {
+        (q.0 - p.0) * (r.1 - p.1) - (q.1 - p.1) * (r.0 - p.0)
+    };
+    let (d1, d2) = (side(b.0, b.1, a.0), side(b.0, b.1, a.1));
+    let (d3, d4) = (side(a.0, a.1, b.0), side(a.0, a.1, b.1));
+    // Segments on the same line only cross if they overlap.
+    (d1 * d2 <= 0.0) && (d3 * d4 <= 0.0) && !(d1 == 0.0 && d2 == 0.0 && !overlap(a, b))
+}
+
+/// Whether two segments on the same line overlap.
+fn overlap(a: ((f64, f64), (f64, f64)), b: ((f64, f64), (f64, f64))) -> bool {
+    let range = |p: f64, q: f64| (p.min(q), p.max(q));
+    let (ax, bx) = (range(a.0 .0, a.1 .0), range(b.0 .0, b.1 .0));
+    let (ay, by) = (range(a.0 .1, a.1 .1), range(b.0 .1, b.1 .1));
+    ax.0 <= bx.1 && bx.0 <= ax.1 && ay.0 <= by.1 && by.0 <= ay.1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jitter::SplitMix;
+
+    /// A path wandering from the origin, turning by up to `max_turn` degrees at each corner.
+    fn wander(random: &mut SplitMix, max_turn: f64) -> Vec<(f64, f64)> {
+        let mut heading = random.between(0.0, 360.0);
+        let mut path = vec![(0.0, 0.0)];
+        for _ in 0..(2 + random.next() % 5) {
+            let (x, y) = *path.last().unwrap();
+            let length = random.between(1.0, 50.0);
+            let (sin, cos) = heading.to_radians().sin_cos();
+            path.push((x + length * cos, y + length * sin));
+            heading += random.between(-max_turn, max_turn);
+        }
+        path
+    }
+
+    fn length(path: &[(f64, f64)]) -> f64 {
+        path.windows(2)
+            .map(|pair| (pair[1].0 - pair[0].0).hypot(pair[1].1 - pair[0].1))
+            .sum()
+    }
+
+    #[test]
+    fn mitered_outlines_are_as_big_as_the_path_is_long() {
+        let mut random = SplitMix(1);
+        let mut outlined = 0;
+        for _ in 0..2000 {
+            let path = wander(&mut random, 150.0);
+            let width = random.between(0.1, 2.0);
+            // Paths which cross themselves, or corners too sharp for their segments, can't be outlined.
+            let Ok(stroke) = outline(&path, width, f64::INFINITY) else {
+                continue;
+            };
+            outlined += 1;
+            assert!(stroke.bevelled.is_empty());
+            assert_eq!(stroke.outline.len(), 2 * path.len());
+            assert!(!crosses_itself(&stroke.outline));
+            // The square ends reach half the width past each end of the path.
+            let expected = width * (length(&path) + width);
+            let area = area(&stroke.outline);
+            assert!(
+                (area - expected).abs() <= expected * 1e-9,
+                "The outline of {path:?}, {width} wide, has an area of {area}, not {expected}"
+            );
+        }
+        assert!(
+            outlined > 1000,
+            "Only {outlined} of the paths could be outlined"
+        );
+    }
+
+    #[test]
+    fn only_corners_past_the_miter_limit_are_bevelled() {
+        let mut random = SplitMix(2);
+        for _ in 0..2000 {
+            let path = wander(&mut random, 175.0);
+            let width = random.between(0.1, 2.0);
+            let Ok(stroke) = outline(&path, width, DEFAULT_MITER_LIMIT) else {
+                continue;
+            };
+            // A bevel adds a corner, and cuts off some of the miter.
+            assert_eq!(stroke.outline.len(), 2 * path.len() + stroke.bevelled.len());
+            assert!(area(&stroke.outline) <= width * (length(&path) + width) * (1.0 + 1e-9));
+            for &(corner, degrees) in &stroke.bevelled {
+                assert!(
+                    (1..path.len() - 1).contains(&corner) && degrees > 151.0,
+                    "Corner {corner} of {path:?} was bevelled, though it only turns {degrees} degrees"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn mirrored_paths_have_mirrored_outlines() {
+        let mut random = SplitMix(3);
+        for _ in 0..500 {
+            let path = wander(&mut random, 175.0);
+            let mirrored: Vec<_> = path.iter().map(|&(x, y)| (x, -y)).collect();
+            let width = random.between(0.1, 2.0);
+            match (
+                outline(&path, width, DEFAULT_MITER_LIMIT),
+                outline(&mirrored, width, DEFAULT_MITER_LIMIT),
+            ) {
+                (Ok(stroke), Ok(mirrored)) => {
+                    let (a, b) = (area(&stroke.outline), area(&mirrored.outline));
+                    assert!((a - b).abs() <= a * 1e-9, "{path:?}: {a} and {b}");
+                    assert_eq!(stroke.bevelled.len(), mirrored.bevelled.len());
+                }
+                (Err(_), Err(_)) => {}
+                (stroke, mirrored) => panic!(
+                    "{path:?} could{} be outlined, but its mirror image could{}",
+                    if stroke.is_ok() { "" } else { "n't" },
+                    if mirrored.is_ok() { "" } else { "n't" }
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_bad_paths_and_widths_without_panicking() {
+        let mut random = SplitMix(4);
+        let widths = [0.0, -1.0, f64::NAN, f64::INFINITY, 1e-12, 1.0, 1e12];
+        let limits = [f64::NAN, 0.5, 1.0, DEFAULT_MITER_LIMIT, f64::INFINITY];
+        for _ in 0..2000 {
+            // Few distinct points, so they repeat and go back on themselves.
+            let path: Vec<_> = (0..random.next() % 6)
+                .map(|_| ((random.next() % 3) as f64, (random.next() % 3) as f64))
+                .collect();
+            let width = widths[random.below(widths.len())];
+            let limit = limits[random.below(limits.len())];
+            if let Ok(stroke) = outline(&path, width, limit) {
+                assert!(stroke
+                    .outline
+                    .iter()
+                    .all(|&(x, y)| x.is_finite() && y.is_finite()));
+                assert!(!crosses_itself(&stroke.outline));
+            }
+        }
+    }
+}
@@ -0,0 +1,338 @@
+use crate::{coords, geometry};
+use color_eyre::{
+    eyre::{bail, eyre},
+    Result,
+};
+use kittycad::types::{PathSegment, Point3D};
+
+/// A closed outline read from an SVG path, on the XY plane.
+#[derive(Clone, Debug)]
+pub struct Outline {
+    /// The file it was read from.
+    pub source: String,
+    /// Where the outline starts.
+    pub start: (f64, f64),
+    /// The outline's pieces, in order. The outline is closed from the end of the last one.
+    pub pieces: Vec<Piece>,
+}
+
+/// A piece of an outline, in absolute coordinates.
+#[derive(Clone, Debug)]
+pub enum Piece {
+    Line((f64, f64)),
+    Cubic((f64, f64), (f64, f64), (f64, f64)),
+}
+
+impl Outline {
+    /// Read the first <path> element's outline from an SVG file.
+    pub fn read(path: &str) -> Result<Self> {
+        // Argument errors only show the outermost message, so the cause goes in it.
+        let text =
+            std::fs::read_to_string(path).map_err(|e| eyre!("Could not read {path}: {e}"))?;
+        let data = first_path_data(&text).map_err(|e| eyre!("{path}: {e}"))?;
+        let (start, pieces) = parse_path_data(data).map_err(|e| eyre!("{path}: {e}"))?;
+        Ok(Self {
+            source: path.to_owned(),
+            start,
+            pieces,
+        })
+    }
+
+    /// The outline's path, scaled, at height zero.
+    /// SVG's Y axis points down the page, so it's flipped, to keep the outline the right way up.
+    /// Then it's on the ground of the run's input coordinate system, so it's turned to match the API's.
+    /// If `tolerance` is given, curves are flattened into lines no further than that from the curve.
+    pub fn path(&self, scale: f64, tolerance: Option<f64>) -> (Point3D, Vec<PathSegment>) {
+        let input = coords::input();
+        let point = |(x, y): (f64, f64)| {
+            let (x, y) = input.ground_to_world((x, -y));
+            Point3D {
+                x: x * scale,
+                y: y * scale,
+                z: 0.0,
+            }
+        };
+        let mut segments = Vec::new();
+        let mut pen = self.start;
+        for piece in &self.pieces {
+            match (piece, tolerance) {
+                (Piece::Line(end), _) => {
+                    segments.push(PathSegment::Line {
+                        end: point(*end),
+                        relative: false,
+                    });
+                    pen = *end;
+                }
+                (Piece::Cubic(c1, c2, end), None) => {
+                    segments.push(PathSegment::Bezier {
+                        control1: point(*c1),
+                        control2: point(*c2),
+                        end: point(*end),
+                        relative: false,
+                    });
+                    pen = *end;
+                }
+                (Piece::Cubic(c1, c2, end), Some(tolerance)) => {
+                    for p in flatten([pen, *c1, *c2, *end], tolerance / scale) {
+                        segments.push(PathSegment::Line {
+                            end: point(p),
+                            relative: false,
+                        });
+                    }
+                    pen = *end;
+                }
+            }
+        }
+        (point(self.start), segments)
+    }
+}
+
+/// Split a cubic Bézier curve into enough lines that none of them strays further than
+/// `tolerance` from the curve. Returns the end of each line.
+fn flatten(curve: [(f64, f64); 4], tolerance: f64) -> Vec<(f64, f64)> {
+    let [p0, p1, p2, p3] = curve;
+    let bezier = |t: f64| {
+        let u = 1.0 - t;
+        let (a, b, c, d) = (u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t);
+        Point3D {
+            x: a * p0.0 + b * p1.0 + c * p2.0 + d * p3.0,
+            y: a * p0.1 + b * p1.1 + c * p2.1 + d * p3.1,
+            z: 0.0,
+        }
+    };
+    // The first point is the pen, where the curve starts.
+    geometry::sample_curve(bezier, tolerance)
+        .into_iter()
+        .skip(1)
+        .map(|p| (p.x, p.y))
+        .collect()
+}
+
+/// Find the `d` attribute of the first <path> element.
+fn first_path_data(svg: &str) -> Result<&str> {
+    let mut rest = svg;
+    let element = loop {
+        let Some(at) = rest.find("<path") else {
+            bail!("There's no <path> element");
+        };
+        rest = &rest[at + "<path".len()..];
+        // Skip elements whose names only start with "path".
+        if rest.starts_with(|c: char| c.is_whitespace()) {
+            break &rest[..rest.find('>').unwrap_or(rest.len())];
+        }
+    };
+    if attribute(element, "transform").is_some() {
+        bail!("Transforms on the <path> element are not yet supported");
+    }
+    attribute(element, "d").ok_or_else(|| eyre!("The first <path> element has no d attribute"))
+}
+
+/// The value of an attribute in an element's opening tag.
+fn attribute<'a>(element: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = element;
+    loop {
+        let at = rest.find(name)?;
+        let preceded_by_space = rest[..at].ends_with(|c: char| c.is_whitespace());
+        rest = &rest[at + name.len()..];
+        let after = rest.trim_start();
+        if !preceded_by_space || !after.starts_with('=') {
+            continue;
+        }
+        let value = after[1..].trim_start();
+        let quote = value.chars().next()?;
+        if quote != '"' && quote != '\'' {
+            return None;
+        }
+        let value = &value[1..];
+        return Some(&value[..value.find(quote)?]);
+    }
+}
+
+/// Parse SVG path data, e.g. "M 0 0 L 10 0 C 10 5 5 10 0 10 Z", into one closed outline.
+/// Supports the M, L, H, V, C, S, Q, T and Z commands, in absolute and relative forms.
+fn parse_path_data(data: &str) -> Result<((f64, f64), Vec<Piece>)> {
+    let tokens = tokenize(data)?;
+    let mut tokens = tokens.iter().peekable();
+    let mut start = None;
+    let mut pen = (0.0, 0.0);
+    let mut pieces = Vec::new();
+    // The last control point of the previous curve, for S and T, which reflect it.
+    let mut last_cubic_control = None;
+    let mut last_quadratic_control = None;
+    let mut closed = false;
+    let mut command = None;
+
+    while let Some(token) = tokens.peek() {
+        if let Token::Command(c) = token {
+            command = Some(*c);
+            tokens.next();
+        }
+        let Some(c) = command else {
+            bail!("Path data must start with a command, like M");
+        };
+        if closed {
+            bail!("Paths with more than one subpath are not yet supported");
+        }
+        let relative = c.is_ascii_lowercase();
+        let mut number = || match tokens.next() {
+            Some(Token::Number(n)) => Ok(*n),
+            _ => Err(eyre!("The {c} command needs more numbers")),
+        };
+        let mut point = |pen: (f64, f64)| -> Result<(f64, f64)> {
+            let (x, y) = (number()?, number()?);
+            Ok(if relative {
+                (pen.0 + x, pen.1 + y)
+            } else {
+                (x, y)
+            })
+        };
+        let (cubic_control, quadratic_control) = match c.to_ascii_uppercase() {
+            'M' => {
+                if start.is_some() {
+                    bail!("Paths with more than one subpath are not yet supported");
+                }
+                pen = point(pen)?;
+                start = Some(pen);
+                // Any more points after a move are lines.
+                command = Some(if relative { 'l' } else { 'L' });
+                (None, None)
+            }
+            'L' => {
+                pen = point(pen)?;
+                pieces.push(Piece::Line(pen));
+                (None, None)
+            }
+            'H' => {
+                let x = number()?;
+                pen = (if relative { pen.0 + x } else { x }, pen.1);
+                pieces.push(Piece::Line(pen));
+                (None, None)
+            }
+            'V' => {
+                let y = number()?;
+                pen = (pen.0, if relative { pen.1 + y } else { y });
+                pieces.push(Piece::Line(pen));
+                (None, None)
+            }
+            'C' => {
+                let c1 = point(pen)?;
+                let c2 = point(pen)?;
+                let end = point(pen)?;
+                pieces.push(Piece::Cubic(c1, c2, end));
+                pen = end;
+                (Some(c2), None)
+            }
+            'S' => {
+                let c1 = reflect(last_cubic_control, pen);
+                let c2 = point(pen)?;
+                let end = point(pen)?;
+                pieces.push(Piece::Cubic(c1, c2, end));
+                pen = end;
+                (Some(c2), None)
+            }
+            'Q' | 'T' => {
+                let q = if c.eq_ignore_ascii_case(&'Q') {
+                    point(pen)?
+                } else {
+                    reflect(last_quadratic_control, pen)
+                };
+                let end = point(pen)?;
+                // A quadratic curve is a cubic curve whose control points are 2/3 of the way
+                // from each end to the quadratic's control point.
+                let two_thirds = |from: (f64, f64)| {
+                    (
+                        from.0 + 2.0 / 3.0 * (q.0 - from.0),
+                        from.1 + 2.0 / 3.0 * (q.1 - from.1),
+                    )
+                };
+                pieces.push(Piece::Cubic(two_thirds(pen), two_thirds(end), end));
+                pen = end;
+                (None, Some(q))
+            }
+            'Z' => {
+                closed = true;
+                command = None;
+                (None, None)
+            }
+            'A' => bail!("Arcs (the {c} command) are not yet supported"),
+            _ => bail!("{c:?} isn't a path command"),
+        };
+        last_cubic_control = cubic_control;
+        last_quadratic_control = quadratic_control;
+        if start.is_none() {
+            bail!("Path data must start with a move, like M 0 0");
+        }
+    }
+
+    let Some(start) = start else {
+        bail!("The path is empty");
+    };
+    // The path gets closed anyway, so a line back to the start isn't needed.
+    if let Some(Piece::Line(end)) = pieces.last() {
+        if *end == start {
+            pieces.pop();
+        }
+    }
+    if pieces.len() < 2 {
+        bail!("The path needs at least two pieces to enclose any area");
+    }
+    Ok((start, pieces))
+}
+
+/// The first control point of a smooth curve: the previous curve's last control point,
+/// reflected through the pen. If the previous piece wasn't the same kind of curve, it's the pen.
+fn reflect(control: Option<(f64, f64)>, pen: (f64, f64)) -> (f64, f64) {
+    match control {
+        Some((x, y)) => (2.0 * pen.0 - x, 2.0 * pen.1 - y),
+        None => pen,
+    }
+}
+
+enum Token {
+    Command(char),
+    Number(f64),
+}
+
+/// Split path data into commands and numbers.
+/// Numbers can be separated by spaces or commas, or not at all, e.g. "10-5" or "0.5.5".
+fn tokenize(data: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let chars: Vec<char> = data.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() || c == ',' {
+            i += 1;
+        } else if c.is_ascii_alphabetic() && c != 'e' && c != 'E' {
+            tokens.push(Token::Command(c));
+            i += 1;
+        } else {
+            let start = i;
+            let mut seen_point = false;
+            if chars[i] == '-' || chars[i] == '+' {
+                i += 1;
+            }
+            while i < chars.len() {
+                match chars[i] {
+                    '0'..='9' => {}
+                    '.' if !seen_point => seen_point = true,
+                    'e' | 'E' => {
+                        // An exponent can have its own sign.
+                        if matches!(chars.get(i + 1), Some('-' | '+')) {
+                            i += 1;
+                        }
+                        seen_point = true;
+                    }
+                    _ => break,
+                }
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            let n = text
+                .parse()
+                .map_err(|_| eyre!("{text:?} in the path data isn't a number"))?;
+            tokens.push(Token::Number(n));
+        }
+    }
+    Ok(tokens)
+}
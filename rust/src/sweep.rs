@@ -0,0 +1,412 @@
+use crate::{
+    build, camera,
+    client::{Clients, Refusal, Refused},
+    finish, open_session, properties, scene,
+    session::{ConnectionLost, ModelingSession},
+    snapshot::Output,
+    sweepreport,
+    template::Drawing,
+    Model, Options,
+};
+use color_eyre::{
+    eyre::{bail, eyre, WrapErr},
+    Report, Result,
+};
+use futures::future::join_all;
+use std::{
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+/// How many times to reconnect, if the connection drops in the middle of a sweep.
+const MAX_RECONNECTS: u32 = 3;
+
+/// One of a model's options, and the values to draw the model with, e.g. "hole-diameter=5..20:5".
+#[derive(Clone, Debug)]
+pub struct Param {
+    pub name: String,
+    pub values: Vec<f64>,
+}
+
+impl FromStr for Param {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let usage =
+            || format!("{s:?} should look like NAME=START..END:STEP, e.g. hole-diameter=5..20:5");
+        let (name, range) = s.split_once('=').ok_or_else(|| eyre!(usage()))?;
+        let (range, step) = range.split_once(':').ok_or_else(|| eyre!(usage()))?;
+        let (start, end) = range.split_once("..").ok_or_else(|| eyre!(usage()))?;
+        let parse = |n: &str| n.trim().parse::<f64>().wrap_err_with(usage);
+        let (start, end, step) = (parse(start)?, parse(end)?, parse(step)?);
+        if !(step > 0.0 && step.is_finite()) {
+            bail!("The step in {s:?} must be positive");
+        }
+        if end < start {
+            bail!("The end of {s:?} must not be before its start");
+        }
+        // Work out each value from its index, so rounding errors don't build up.
+        // The end is included, if the steps land on it (give or take rounding).
+        let steps = ((end - start) / step + 1e-9).floor() as usize;
+        let values = (0..=steps).map(|i| start + i as f64 * step).collect();
+        Ok(Self {
+            name: name.trim().trim_start_matches("--").to_owned(),
+            values,
+        })
+    }
+}
+
+/// What to measure in each drawing of a sweep, for its report (see `report`), from --sweep-metric.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Metric {
+    /// The model's volume, in cm3.
+    Volume,
+    /// The model's mass, in grams, made of the material given with --density.
+    Mass,
+    /// How long the drawing took, in seconds, including reconnecting.
+    Duration,
+}
+
+impl Metric {
+    /// What the report's column is called, with its unit.
+    pub fn column(self) -> &'static str {
+        match self {
+            Metric::Volume => "volume_cm3",
+            Metric::Mass => "mass_g",
+            Metric::Duration => "duration_s",
+        }
+    }
+}
+
+/// Every combination of the parameters' values, the last parameter changing fastest.
+fn combinations(params: &[Param]) -> Vec<Vec<f64>> {
+    params.iter().fold(vec![Vec::new()], |combinations, param| {
+        combinations
+            .iter()
+            .flat_map(|values| {
+                param.values.iter().map(move |&value| {
+                    let mut values = values.clone();
+                    values.push(value);
+                    values
+                })
+            })
+            .collect()
+    })
+}
+
+/// The options a drawing was given, e.g. "--hole-diameter=5 --thickness=2".
+fn describe(params: &[Param], values: &[f64]) -> String {
+    let options: Vec<_> = params
+        .iter()
+        .zip(values)
+        .map(|(param, value)| format!("--{}={value}", param.name))
+        .collect();
+    options.join(" ")
+}
+
+/// Parse the model once for every combination of the parameters' values,
+/// so a bad value fails before anything is built.
+/// Returns each combination of values with its model, and what to name its outputs after.
+pub fn models(params: &[Param], model: &[String]) -> Result<Vec<(Vec<f64>, Model, Drawing)>> {
+    for (i, param) in params.iter().enumerate() {
+        if params[..i].iter().any(|earlier| earlier.name == param.name) {
+            bail!("--param {} was given more than once", param.name);
+        }
+    }
+    combinations(params)
+        .into_iter()
+        .enumerate()
+        .map(|(i, values)| {
+            let mut args = model.to_vec();
+            args.extend(
+                params
+                    .iter()
+                    .zip(&values)
+                    .map(|(param, value)| format!("--{}={value}", param.name)),
+            );
+            let model = scene::parse_model(&args).wrap_err_with(|| {
+                format!(
+                    "Could not draw {} with {}",
+                    args[0],
+                    describe(params, &values)
+                )
+            })?;
+            let drawing = scene::describe(&args, i + 1)?;
+            Ok((values, model, drawing))
+        })
+        .collect()
+}
+
+/// Draw the model once for every combination of values, clearing the scene in between.
+/// Each drawing is saved to its own output, named after its values.
+/// With a metric, each drawing is measured, and the measurements are saved in a report once they're all done.
+///
+/// The drawings are shared out between `jobs` connections, which take turns on one thread: while one
+/// waits for the API, another can send its commands or save what it got back. So only their waiting
+/// overlaps, not their own work (like decoding and saving snapshots), which is done one at a time.
+/// A drawing which fails doesn't stop the others. If a connection drops, its job reconnects
+/// and carries on from the drawing that failed.
+pub async fn run(
+    clients: &Clients,
+    params: &[Param],
+    models: Vec<(Vec<f64>, Model, Drawing)>,
+    jobs: usize,
+    metric: Option<Metric>,
+    output: &Output,
+    options: &Options,
+) -> Result<()> {
+    if metric == Some(Metric::Mass) && options.density.is_none() {
+        bail!("--sweep-metric mass needs --density, to know what the model is made of");
+    }
+    let start = Instant::now();
+    let total = models.len();
+    let combinations: Vec<_> = models.iter().map(|(values, ..)| values.clone()).collect();
+    // Name every drawing's outputs first, so names which clash fail before anything is drawn.
+    let mut named = Vec::with_capacity(total);
+    for (values, model, drawing) in models {
+        let iteration_output = match options.outputs.name(output, &drawing)? {
+            Some(iteration_output) => iteration_output,
+            None => {
+                let suffix: String = params
+                    .iter()
+                    .zip(&values)
+                    .map(|(param, value)| format!("-{}{value}", param.name))
+                    .collect();
+                output.with_suffix(&suffix)
+            }
+        }
+        .labelled(&drawing);
+        named.push((values, model, iteration_output));
+    }
+    // Deal the drawings out to the jobs in turn, so they finish at about the same time.
+    let mut queues: Vec<_> = (0..jobs.min(total)).map(|_| Vec::new()).collect();
+    let queue_count = queues.len();
+    for (i, model) in named.into_iter().enumerate() {
+        queues[i % queue_count].push(model);
+    }
+    let reports = join_all(
+        queues
+            .into_iter()
+            .enumerate()
+            .map(|(i, queue)| run_job(i + 1, clients, params, queue, metric, options)),
+    )
+    .await;
+
+    let wall_clock = start.elapsed();
+    let mut failures = 0;
+    for report in &reports {
+        say!(
+            "Job {}: drew {}, {} failed, busy for {:.2?}",
+            report.job,
+            report.drawn,
+            report.failed.len(),
+            report.busy
+        );
+        for (values, e) in &report.failed {
+            eprintln!("{} failed: {e:?}", describe(params, values));
+        }
+        failures += report.failed.len();
+    }
+    // The jobs' busy times overlap, and each includes waiting for the others' turns on the thread,
+    // so they don't add up to how long one job would take, and no speedup is worked out from them.
+    say!(
+        "Drew {} of {total} variations in {wall_clock:.2?} with {} jobs",
+        total - failures,
+        reports.len(),
+    );
+    if let Some(metric) = metric {
+        // Drawings which failed have no measurement, so they're left as gaps.
+        let measured: Vec<_> = reports.iter().flat_map(|r| &r.measured).collect();
+        let measurements = combinations
+            .into_iter()
+            .map(|values| {
+                let measurement = measured
+                    .iter()
+                    .find(|(measured_values, _)| *measured_values == values)
+                    .map(|(_, measurement)| *measurement);
+                (values, measurement)
+            })
+            .collect();
+        let report = sweepreport::Report {
+            params: params.to_vec(),
+            metric,
+            measurements,
+        };
+        report.save(output, options).await?;
+    }
+    if failures > 0 {
+        bail!("{failures} of {total} variations failed");
+    }
+    Ok(())
+}
+
+/// What one job drew.
+struct JobReport {
+    job: usize,
+    drawn: usize,
+    failed: Vec<(Vec<f64>, Report)>,
+    /// What each drawing measured, if the sweep has a metric.
+    measured: Vec<(Vec<f64>, f64)>,
+    /// How long the job spent drawing, including reconnecting, and waiting for other jobs' turns.
+    busy: Duration,
+}
+
+/// Draw each model in the queue, over one connection (reconnecting if it drops).
+async fn run_job(
+    job: usize,
+    clients: &Clients,
+    params: &[Param],
+    queue: Vec<(Vec<f64>, Model, Output)>,
+    metric: Option<Metric>,
+    options: &Options,
+) -> JobReport {
+    let mut report = JobReport {
+        job,
+        drawn: 0,
+        failed: Vec::new(),
+        measured: Vec::new(),
+        busy: Duration::ZERO,
+    };
+    let mut session = None;
+    let mut queue = queue.into_iter();
+    while let Some((values, model, iteration_output)) = queue.next() {
+        let iteration_start = Instant::now();
+        let mut reconnects = 0;
+        let result = loop {
+            // Each attempt needs its own copy, because building consumes the model.
+            let attempt = draw_on(
+                clients,
+                &mut session,
+                model.clone(),
+                &iteration_output,
+                metric,
+                options,
+            )
+            .await;
+            match attempt {
+                // Honor the API's hint about how long to back off for, if it gave one.
+                Err(e) if rate_limited(&e).is_some() && reconnects < MAX_RECONNECTS => {
+                    reconnects += 1;
+                    let delay = rate_limited(&e)
+                        .flatten()
+                        .unwrap_or(Duration::from_secs(1 << (reconnects - 1)));
+                    eprintln!(
+                        "Job {job}: {e}. Connecting again in {delay:?} (attempt {reconnects} of {MAX_RECONNECTS})"
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) if ConnectionLost::caused(&e) && reconnects < MAX_RECONNECTS => {
+                    reconnects += 1;
+                    let delay = Duration::from_secs(1 << (reconnects - 1));
+                    eprintln!(
+                        "Job {job}: {e}. Reconnecting in {delay:?} (attempt {reconnects} of {MAX_RECONNECTS})"
+                    );
+                    // A new connection starts with an empty scene, so there's nothing to clear.
+                    session = None;
+                    tokio::time::sleep(delay).await;
+                }
+                result => break result,
+            }
+        };
+        let elapsed = iteration_start.elapsed();
+        report.busy += elapsed;
+        match result {
+            Ok(measurement) => {
+                say!(
+                    "Job {job}: {}: saved {} in {elapsed:.2?}",
+                    describe(params, &values),
+                    iteration_output.path
+                );
+                report.drawn += 1;
+                let measurement = match metric {
+                    Some(Metric::Duration) => Some(elapsed.as_secs_f64()),
+                    _ => measurement,
+                };
+                if let Some(measurement) = measurement {
+                    report.measured.push((values, measurement));
+                }
+            }
+            // Nothing else can be drawn with a token the API won't accept, so don't keep asking.
+            Err(e) if Refused::find(&e).is_some() && rate_limited(&e).is_none() => {
+                report.failed.push((values, e));
+                for (values, ..) in queue.by_ref() {
+                    let e = eyre!("Not drawn, because the API refused the job's connection");
+                    report.failed.push((values, e));
+                }
+            }
+            Err(e) => report.failed.push((values, e)),
+        }
+    }
+    report
+}
+
+/// If the API refused to connect because of a rate limit, how long it said to wait, if it did.
+fn rate_limited(e: &Report) -> Option<Option<Duration>> {
+    match Refused::find(e)?.reason {
+        Refusal::RateLimited { retry_after } => Some(retry_after),
+        _ => None,
+    }
+}
+
+/// Draw a model on the job's connection, connecting first if it doesn't have one yet.
+/// Returns what it measured, if the metric is measured on the model.
+async fn draw_on(
+    clients: &Clients,
+    session: &mut Option<ModelingSession>,
+    model: Model,
+    output: &Output,
+    metric: Option<Metric>,
+    options: &Options,
+) -> Result<Option<f64>> {
+    let session = match session {
+        Some(session) => session,
+        None => {
+            // Refusals are passed on as they are, so the job can tell them apart.
+            let new_session =
+                open_session(clients, options)
+                    .await
+                    .map_err(|e| match Refused::find(&e) {
+                        Some(_) => e,
+                        None => ConnectionLost(format!("{e}: {}", e.root_cause())).into(),
+                    })?;
+            session.insert(new_session)
+        }
+    };
+    draw(session, model, output, metric, options).await
+}
+
+async fn draw(
+    session: &mut ModelingSession,
+    model: Model,
+    output: &Output,
+    metric: Option<Metric>,
+    options: &Options,
+) -> Result<Option<f64>> {
+    session.set_camera_preset(camera::choose(options.view, &model));
+    let drawn = async {
+        let ids = build(session, model, output, options.validation).await?;
+        finish(session, &ids, output, options).await?;
+        Ok(match (metric, &options.density) {
+            (Some(Metric::Volume), _) => Some(properties::volume(session, &ids).await?.volume),
+            (Some(Metric::Mass), Some(density)) => {
+                Some(properties::mass(session, &ids, density).await?)
+            }
+            _ => None,
+        })
+    }
+    .await;
+    match drawn {
+        Ok(measurement) => {
+            scene::clear(session).await?;
+            Ok(measurement)
+        }
+        // The connection is reused for the next model, so don't leave half of this one behind.
+        // A lost connection is replaced by a new one, which starts with an empty scene.
+        Err(e) => {
+            if !ConnectionLost::caused(&e) {
+                scene::clean_up(session).await;
+            }
+            Err(e)
+        }
+    }
+}
@@ -0,0 +1,342 @@
+use crate::{
+    sidecar,
+    snapshot::Output,
+    sweep::{Metric, Param},
+    Options,
+};
+use color_eyre::{eyre::WrapErr, Result};
+use image::{Rgba, RgbaImage};
+use std::io::Cursor;
+
+/// How big the heatmap is along its longer side, roughly, in pixels.
+const HEATMAP_SIZE: u32 = 512;
+
+/// What a sweep measured in each of its drawings, from --sweep-metric.
+pub struct Report {
+    pub params: Vec<Param>,
+    pub metric: Metric,
+    /// Every combination of the parameters' values, in the order they were swept,
+    /// and what was measured, or None if drawing it failed.
+    pub measurements: Vec<(Vec<f64>, Option<f64>)>,
+}
+
+/// Where the heatmap's axes and colours came from, saved next to it as "<heatmap>.meta.json".
+#[derive(serde::Serialize)]
+struct HeatmapSidecar {
+    artifact: String,
+    sha256: String,
+    /// The measurement, with its unit, e.g. "volume_cm3".
+    metric: &'static str,
+    /// Left to right.
+    x_axis: Axis,
+    /// Bottom to top.
+    y_axis: Axis,
+    /// The measurements coloured darkest and brightest.
+    min: Option<f64>,
+    max: Option<f64>,
+    colors: &'static str,
+}
+
+#[derive(serde::Serialize)]
+struct Axis {
+    param: String,
+    values: Vec<f64>,
+}
+
+impl Report {
+    /// Save the measurements as "<output>-sweep.csv", and, if two parameters were swept,
+    /// as a heatmap, "<output>-sweep.png", with its axes described in its sidecar.
+    pub async fn save(&self, output: &Output, options: &Options) -> Result<()> {
+        let output = output.with_suffix("-sweep");
+        let csv_path = output_path(&output, "csv");
+        let saved = options
+            .outputs
+            .write(&csv_path, self.csv().as_bytes())
+            .await?;
+        say!("Saved the sweep's measurements to {saved}");
+
+        let [x, y] = &self.params[..] else {
+            return Ok(());
+        };
+        let grid = self.grid();
+        let png_path = output_path(&output, "png");
+        let mut png = Vec::new();
+        heatmap(&grid)
+            .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+            .wrap_err("Could not encode the sweep's heatmap")?;
+        let saved = options.outputs.write(&png_path, &png).await?;
+        say!(
+            "Saved a heatmap of the sweep's measurements to {saved}, with --{} across and --{} up",
+            x.name,
+            y.name
+        );
+        if options.sidecars {
+            let (min, max) = range(&grid).unzip();
+            let sidecar = HeatmapSidecar {
+                artifact: png_path.clone(),
+                sha256: sidecar::sha256(&png),
+                metric: self.metric.column(),
+                x_axis: Axis {
+                    param: x.name.clone(),
+                    values: x.values.clone(),
+                },
+                y_axis: Axis {
+                    param: y.name.clone(),
+                    values: y.values.clone(),
+                },
+                min,
+                max,
+                colors: "Dark purple for the smallest measurement, through blue and green, to yellow for the biggest. \
+                         Drawings which failed are left transparent.",
+            };
+            options
+                .outputs
+                .write(
+                    &format!("{png_path}.meta.json"),
+                    &serde_json::to_vec_pretty(&sidecar)?,
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// One row per drawing: its parameters' values, then what it measured, or nothing if it failed.
+    fn csv(&self) -> String {
+        let mut header: Vec<String> = self.params.iter().map(|p| p.name.clone()).collect();
+        header.push(self.metric.column().to_owned());
+        let mut text = header.join(",") + "\n";
+        for (values, measurement) in &self.measurements {
+            let mut row: Vec<String> = values.iter().map(f64::to_string).collect();
+            row.push(measurement.map(|m| m.to_string()).unwrap_or_default());
+            text.push_str(&row.join(","));
+            text.push('\n');
+        }
+        text
+    }
+
+    /// The measurements of a sweep of two parameters, indexed by the first's value, then the second's.
+    fn grid(&self) -> Vec<Vec<Option<f64>>> {
+        let (x, y) = (&self.params[0].values, &self.params[1].values);
+        let mut grid = vec![vec![None; y.len()]; x.len()];
+        for (values, measurement) in &self.measurements {
+            let column = x.iter().position(|v| *v == values[0]);
+            let row = y.iter().position(|v| *v == values[1]);
+            if let (Some(column), Some(row)) = (column, row) {
+                grid[column][row] = *measurement;
+            }
+        }
+        grid
+    }
+}
+
+/// Where one of the report's files goes, named after the output.
+fn output_path(output: &Output, extension: &str) -> String {
+    std::path::Path::new(&output.path)
+        .with_extension(extension)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// The smallest and biggest measurement, if there are any.
+fn range(grid: &[Vec<Option<f64>>]) -> Option<(f64, f64)> {
+    grid.iter()
+        .flatten()
+        .flatten()
+        .fold(None, |range, &m| match range {
+            None => Some((m, m)),
+            Some((min, max)) => Some((m.min(min), m.max(max))),
+        })
+}
+
+/// Draw a grid of measurements as squares of colour, the first index across and the second up.
+/// Missing measurements are left transparent.
+fn heatmap(grid: &[Vec<Option<f64>>]) -> RgbaImage {
+    let columns = grid.len() as u32;
+    let rows = grid.first().map_or(0, Vec::len) as u32;
+    let cell = (HEATMAP_SIZE / columns.max(rows).max(1)).max(1);
+    let range = range(grid);
+    RgbaImage::from_fn(columns * cell, rows * cell, |px, py| {
+        let (column, row) = ((px / cell) as usize, (rows - 1 - py / cell) as usize);
+        match (grid[column][row], range) {
+            (Some(measurement), Some((min, max))) => {
+                // Every measurement is the same, so none is bigger than another.
+                let t = if max > min {
+                    (measurement - min) / (max - min)
+                } else {
+                    0.5
+                };
+                color(t)
+            }
+            _ => Rgba([0, 0, 0, 0]),
+        }
+    })
+}
+
+/// The colour for a measurement, given as how far it is from the smallest (0) to the biggest (1):
+/// dark purple, through blue and green, to yellow, like the viridis colour map.
+fn color(t: f64) -> Rgba<u8> {
+    const STOPS: [[f64; 3]; 5] = [
+        [68.0, 1.0, 84.0],
+        [59.0, 82.0, 139.0],
+        [33.0, 145.0, 140.0],
+        [94.0, 201.0, 98.0],
+        [253.0, 231.0, 37.0],
+    ];
+    let t = if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) };
+    let at = t * (STOPS.len() - 1) as f64;
+    let i = (at.floor() as usize).min(STOPS.len() - 2);
+    let f = at - i as f64;
+    let mix = |c: usize| (STOPS[i][c] + (STOPS[i + 1][c] - STOPS[i][c]) * f).round() as u8;
+    Rgba([mix(0), mix(1), mix(2), 255])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn param(name: &str, values: &[f64]) -> Param {
+        Param {
+            name: name.to_owned(),
+            values: values.to_vec(),
+        }
+    }
+
+    /// A sweep of hole-diameter 1 and 2 by length 10, 20 and 30, measuring the volume.
+    /// Drawing (2, 20) failed.
+    fn two_params() -> Report {
+        let measured = |x: f64, y: f64| (x != 2.0 || y != 20.0).then_some(x * 100.0 + y);
+        let mut measurements = Vec::new();
+        for x in [1.0, 2.0] {
+            for y in [10.0, 20.0, 30.0] {
+                measurements.push((vec![x, y], measured(x, y)));
+            }
+        }
+        Report {
+            params: vec![
+                param("hole-diameter", &[1.0, 2.0]),
+                param("length", &[10.0, 20.0, 30.0]),
+            ],
+            metric: Metric::Volume,
+            measurements,
+        }
+    }
+
+    #[test]
+    fn writes_a_row_per_drawing() {
+        let report = Report {
+            params: vec![param("hole-diameter", &[2.5, 5.0, 7.5])],
+            metric: Metric::Mass,
+            measurements: vec![
+                (vec![2.5], Some(12.25)),
+                (vec![5.0], Some(11.0)),
+                (vec![7.5], Some(9.5)),
+            ],
+        };
+        assert_eq!(
+            report.csv(),
+            "hole-diameter,mass_g\n2.5,12.25\n5,11\n7.5,9.5\n"
+        );
+    }
+
+    #[test]
+    fn leaves_failed_drawings_blank_not_zero() {
+        assert_eq!(
+            two_params().csv(),
+            "hole-diameter,length,volume_cm3\n\
+             1,10,110\n1,20,120\n1,30,130\n2,10,210\n2,20,\n2,30,230\n"
+        );
+        let report = Report {
+            params: vec![param("count", &[1.0, 2.0])],
+            metric: Metric::Duration,
+            measurements: vec![(vec![1.0], None), (vec![2.0], Some(0.0))],
+        };
+        assert_eq!(report.csv(), "count,duration_s\n1,\n2,0\n");
+    }
+
+    #[test]
+    fn puts_each_measurement_in_its_cell() {
+        assert_eq!(
+            two_params().grid(),
+            [
+                [Some(110.0), Some(120.0), Some(130.0)],
+                [Some(210.0), None, Some(230.0)]
+            ]
+        );
+        assert_eq!(range(&two_params().grid()), Some((110.0, 230.0)));
+        assert_eq!(range(&[vec![None, None]]), None);
+    }
+
+    #[test]
+    fn colors_from_dark_purple_to_yellow() {
+        assert_eq!(color(0.0), Rgba([68, 1, 84, 255]));
+        assert_eq!(color(0.25), Rgba([59, 82, 139, 255]));
+        assert_eq!(color(0.5), Rgba([33, 145, 140, 255]));
+        assert_eq!(color(0.75), Rgba([94, 201, 98, 255]));
+        assert_eq!(color(1.0), Rgba([253, 231, 37, 255]));
+        // Halfway between the first two stops.
+        assert_eq!(color(0.125), Rgba([64, 42, 112, 255]));
+    }
+
+    #[test]
+    fn colors_what_is_out_of_range_as_the_nearest_end() {
+        assert_eq!(color(-1.0), color(0.0));
+        assert_eq!(color(2.0), color(1.0));
+        assert_eq!(color(f64::NAN), color(0.0));
+        assert_eq!(color(f64::INFINITY), color(1.0));
+    }
+
+    /// The colour of each cell of a heatmap, the first index across and the second up.
+    fn cells(image: &RgbaImage, columns: u32, rows: u32) -> Vec<Vec<Rgba<u8>>> {
+        let cell = image.width() / columns;
+        assert_eq!(image.height(), rows * cell);
+        (0..columns)
+            .map(|column| {
+                (0..rows)
+                    .map(|row| {
+                        let (left, top) = (column * cell, (rows - 1 - row) * cell);
+                        let pixel = *image.get_pixel(left, top);
+                        // Each cell is one colour all over.
+                        assert_eq!(*image.get_pixel(left + cell - 1, top + cell - 1), pixel);
+                        pixel
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn draws_a_heatmap_of_the_grid() {
+        let grid = two_params().grid();
+        let image = heatmap(&grid);
+        // 3 rows fit 170 pixel cells into 512.
+        assert_eq!((image.width(), image.height()), (340, 510));
+        let t = |m: f64| color((m - 110.0) / 120.0);
+        assert_eq!(
+            cells(&image, 2, 3),
+            [
+                [t(110.0), t(120.0), t(130.0)],
+                [t(210.0), Rgba([0, 0, 0, 0]), t(230.0)]
+            ]
+        );
+        assert_eq!(cells(&image, 2, 3)[0][0], color(0.0));
+        assert_eq!(cells(&image, 2, 3)[1][2], color(1.0));
+    }
+
+    #[test]
+    fn colors_a_grid_of_one_measurement_in_the_middle() {
+        let grid = vec![vec![Some(5.0), Some(5.0)], vec![None, Some(5.0)]];
+        let image = heatmap(&grid);
+        let middle = color(0.5);
+        assert_eq!(
+            cells(&image, 2, 2),
+            [[middle, middle], [Rgba([0, 0, 0, 0]), middle]]
+        );
+    }
+
+    #[test]
+    fn leaves_a_grid_of_failures_transparent() {
+        let image = heatmap(&[vec![None], vec![None], vec![None]]);
+        assert_eq!((image.width(), image.height()), (510, 170));
+        assert!(image.pixels().all(|pixel| pixel.0[3] == 0));
+    }
+}
@@ -0,0 +1,437 @@
+use crate::{
+    export,
+    gallery::NOT_SHAPES,
+    sink::{self, OutputSink},
+    snapshot::Output,
+    Cli,
+};
+use clap::{parser::ValueSource, ArgMatches, CommandFactory};
+use color_eyre::{eyre::bail, Result};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+/// Placeholders every drawing has values for.
+const FIXED: [&str; 3] = ["shape", "frame", "ext"];
+
+/// What to call a drawing's output files, e.g. "renders/{shape}_{width}x{height}.{ext}".
+///
+/// Placeholders are:
+/// - {shape}: the model's subcommand, e.g. "plate".
+/// - {frame}: the drawing's position in a sweep, scene or gallery, starting at 1 (1 for a single model).
+/// - {ext}: the file's extension, e.g. "png" for the snapshot and "stl" for an export.
+/// - any of a model's numeric options, with underscores for dashes, e.g. {hole_diameter}.
+///   It's written the way it was given (or defaulted), e.g. "2.5" or "10mm".
+///
+/// Write "{{" and "}}" for literal braces. Templates end with ".{ext}".
+#[derive(Clone, Debug)]
+pub struct Template {
+    text: String,
+    pieces: Vec<Piece>,
+}
+
+#[derive(Clone, Debug)]
+enum Piece {
+    Text(String),
+    Placeholder(String),
+}
+
+impl FromStr for Template {
+    type Err = color_eyre::Report;
+
+    /// Parse a template, failing if it names a placeholder which no drawing could fill in.
+    fn from_str(text: &str) -> Result<Self> {
+        let valid = placeholders();
+        let mut pieces = Vec::new();
+        let mut literal = String::new();
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    let mut closed = false;
+                    for c in chars.by_ref() {
+                        if c == '}' {
+                            closed = true;
+                            break;
+                        }
+                        name.push(c);
+                    }
+                    if !closed {
+                        bail!("The '{{' before \"{name}\" in the output template is never closed");
+                    }
+                    if !valid.contains(&name) {
+                        bail!(
+                            "The output template's {{{name}}} isn't a placeholder. Valid ones are: {}",
+                            valid
+                                .iter()
+                                .map(|name| format!("{{{name}}}"))
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        );
+                    }
+                    if !literal.is_empty() {
+                        pieces.push(Piece::Text(std::mem::take(&mut literal)));
+                    }
+                    pieces.push(Piece::Placeholder(name));
+                }
+                '}' => bail!("The output template has a '}}' which doesn't close a placeholder; write \"}}}}\" for a literal one"),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            pieces.push(Piece::Text(literal));
+        }
+        // Otherwise a dot in a parameter, e.g. "2.5", would be taken for the start of the extension.
+        let ends_with_ext = match &pieces[..] {
+            [.., Piece::Text(before), Piece::Placeholder(last)] => {
+                before.ends_with('.') && last == "ext"
+            }
+            _ => false,
+        };
+        if !ends_with_ext {
+            bail!("The output template {text:?} must end with \".{{ext}}\", so each file's extension is its format's");
+        }
+        Ok(Self {
+            text: text.to_owned(),
+            pieces,
+        })
+    }
+}
+
+/// Every placeholder a template can use: the fixed ones, and every shape's options.
+fn placeholders() -> BTreeSet<String> {
+    let cli = Cli::command();
+    let options = cli
+        .get_subcommands()
+        .filter(|shape| !NOT_SHAPES.contains(&shape.get_name()))
+        .flat_map(|shape| shape.get_arguments())
+        .filter(|arg| arg.get_long().is_some())
+        .map(|arg| arg.get_id().to_string());
+    FIXED
+        .iter()
+        .map(|&name| name.to_owned())
+        .chain(options)
+        .collect()
+}
+
+impl Template {
+    /// Fill in the placeholders for one of a drawing's files.
+    pub fn render(&self, drawing: &Drawing, ext: &str) -> Result<PathBuf> {
+        let mut path = String::new();
+        for piece in &self.pieces {
+            match piece {
+                Piece::Text(text) => path.push_str(text),
+                Piece::Placeholder(name) => match name.as_str() {
+                    "shape" => path.push_str(&drawing.shape),
+                    "frame" => path.push_str(&drawing.frame.to_string()),
+                    "ext" => path.push_str(ext),
+                    name => match drawing.parameters.get(name) {
+                        Some(value) => path.push_str(value),
+                        None => bail!(
+                            "The output template uses {{{name}}}, but {} has no numeric option called {}",
+                            drawing.shape,
+                            name.replace('_', "-")
+                        ),
+                    },
+                },
+            }
+        }
+        Ok(PathBuf::from(path))
+    }
+}
+
+/// One model to draw, as far as naming its outputs goes.
+#[derive(Clone, Debug)]
+pub struct Drawing {
+    /// The model's subcommand, e.g. "plate".
+    pub shape: String,
+    /// Its position in a sweep, scene or gallery, starting at 1.
+    pub frame: usize,
+    /// Each numeric option, as written, e.g. {"hole_diameter": "5"}.
+    parameters: BTreeMap<String, String>,
+    /// The numeric options which weren't left at their defaults, to tell drawings of the same shape apart.
+    given: Vec<String>,
+}
+
+impl Drawing {
+    /// Describe a model from the options it was parsed from.
+    pub fn new(shape: &str, matches: &ArgMatches, frame: usize) -> Self {
+        let mut parameters = BTreeMap::new();
+        let mut given = Vec::new();
+        for id in matches.ids() {
+            let id = id.as_str();
+            let Ok(Some(mut values)) = matches.try_get_raw(id) else {
+                continue;
+            };
+            let (Some(value), None) = (values.next(), values.next()) else {
+                continue;
+            };
+            let value = value.to_string_lossy().into_owned();
+            // Lengths may have units, e.g. "10mm", and are as numeric as plain numbers.
+            if crate::units::Dimension::from_str(&value).is_err() {
+                continue;
+            }
+            if matches.value_source(id) != Some(ValueSource::DefaultValue) {
+                given.push(format!("--{}={value}", id.replace('_', "-")));
+            }
+            parameters.insert(id.to_owned(), value);
+        }
+        Self {
+            shape: shape.to_owned(),
+            frame,
+            parameters,
+            given,
+        }
+    }
+
+    /// The model's subcommand, and the options which weren't left at their defaults,
+    /// e.g. "plate --hole-diameter=5".
+    pub fn summary(&self) -> String {
+        std::iter::once(self.shape.as_str())
+            .chain(self.given.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// A model with no options worth naming it after.
+    pub fn bare(shape: &str) -> Self {
+        Self {
+            shape: shape.to_owned(),
+            frame: 1,
+            parameters: BTreeMap::new(),
+            given: Vec::new(),
+        }
+    }
+}
+
+/// E.g. "drawing 2 (plate --hole-diameter=5)".
+impl std::fmt::Display for Drawing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "drawing {} ({})", self.frame, self.summary())
+    }
+}
+
+/// Names every drawing's outputs from the run's --output-template, and remembers which drawing each
+/// file came from, so no two drawings write the same file. Outputs are saved through the run's sink,
+/// e.g. to files, or to object storage.
+pub struct OutputRegistry {
+    template: Option<Template>,
+    sink: Arc<dyn OutputSink>,
+    /// Each file named so far, and what's being saved to it.
+    claimed: Mutex<HashMap<PathBuf, String>>,
+}
+
+impl OutputRegistry {
+    pub fn new(template: Option<Template>, sink: Arc<dyn OutputSink>) -> Self {
+        Self {
+            template,
+            sink,
+            claimed: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Name a drawing's snapshot (and export, if there is one) from the template.
+    /// Returns None if there's no template, so the caller names them as usual.
+    /// Fails if another drawing in the run already has a file of the same name.
+    pub fn name(&self, output: &Output, drawing: &Drawing) -> Result<Option<Output>> {
+        let Some(named) = self.render(output, drawing)? else {
+            return Ok(None);
+        };
+        self.claim(Path::new(&named.path), format!("the snapshot of {drawing}"))?;
+        if let Some(export) = &named.export {
+            self.claim(export, format!("the export of {drawing}"))?;
+        }
+        Ok(Some(named))
+    }
+
+    /// Where a drawing's outputs will be saved, as `name` will name them, without claiming the files.
+    pub fn plan(&self, output: &Output, drawing: &Drawing) -> Result<Output> {
+        Ok(self
+            .render(output, drawing)?
+            .unwrap_or_else(|| output.clone()))
+    }
+
+    /// Whether outputs are saved as files on the local disk.
+    pub fn writes_local_files(&self) -> bool {
+        self.sink.writes_local_files()
+    }
+
+    fn render(&self, output: &Output, drawing: &Drawing) -> Result<Option<Output>> {
+        let Some(template) = &self.template else {
+            return Ok(None);
+        };
+        let image_ext = match export::extension(Path::new(&output.path)) {
+            ext if ext.is_empty() => "png".to_owned(),
+            ext => ext,
+        };
+        let path = template.render(drawing, &image_ext)?;
+        let export = match &output.export {
+            Some(export) => Some(template.render(drawing, &export::extension(export))?),
+            None => None,
+        };
+        Ok(Some(Output {
+            path: path.to_string_lossy().into_owned(),
+            export,
+            ..output.clone()
+        }))
+    }
+
+    /// Save an output's contents (a snapshot, an export, or a sidecar) through the run's sink,
+    /// or to standard output if its path is "-". Returns where it ended up.
+    pub async fn write(&self, path: &str, contents: &[u8]) -> Result<String> {
+        sink::write(&*self.sink, path, contents).await
+    }
+
+    /// Forget every file named so far, when the run starts drawing over again.
+    pub fn forget_claims(&self) {
+        self.claimed
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+
+    fn claim(&self, path: &Path, producer: String) -> Result<()> {
+        let mut claimed = self.claimed.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(earlier) = claimed.get(path) {
+            bail!(
+                "Both {earlier} and {producer} would be saved to {}, according to the output template {:?}",
+                path.display(),
+                self.template.as_ref().map_or("", |t| t.text.as_str())
+            );
+        }
+        claimed.insert(path.to_owned(), producer);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::FileSink;
+
+    /// A drawing of a model written as on the command line, e.g. "plate --hole-diameter=2.5".
+    fn drawing(model: &str, frame: usize) -> Drawing {
+        let args: Vec<_> = model.split(' ').map(str::to_owned).collect();
+        crate::scene::describe(&args, frame).unwrap()
+    }
+
+    fn render(template: &str, model: &str) -> Result<PathBuf> {
+        template
+            .parse::<Template>()?
+            .render(&drawing(model, 3), "png")
+    }
+
+    #[test]
+    fn fills_in_placeholders() {
+        assert_eq!(
+            render("renders/{shape}_{frame}.{ext}", "cube").unwrap(),
+            Path::new("renders/cube_3.png")
+        );
+        assert_eq!(
+            render(
+                "{shape}_{hole_x}_{hole_y}.{ext}",
+                "plate --hole-x=-5 --hole-y=7mm"
+            )
+            .unwrap(),
+            Path::new("plate_-5_7mm.png")
+        );
+        // Options left at their defaults are written as they default.
+        assert_eq!(
+            render("{hole_diameter}.{ext}", "plate").unwrap(),
+            Path::new("10.png")
+        );
+    }
+
+    #[test]
+    fn keeps_the_dots_in_parameters() {
+        let path = render("{shape}-{hole_diameter}.{ext}", "plate --hole-diameter=2.5").unwrap();
+        assert_eq!(path, Path::new("plate-2.5.png"));
+        assert_eq!(path.extension().unwrap(), "png");
+        assert_eq!(path.file_stem().unwrap(), "plate-2.5");
+        // Other formats are named by changing the extension, which leaves the parameter alone.
+        assert_eq!(path.with_extension("step"), Path::new("plate-2.5.step"));
+    }
+
+    #[test]
+    fn wont_end_with_anything_but_the_extension() {
+        for template in [
+            "{shape}-{hole_diameter}",
+            "{shape}.png",
+            "{shape}{ext}",
+            "{ext}.{shape}",
+        ] {
+            let e = template.parse::<Template>().unwrap_err().to_string();
+            assert_eq!(
+                e,
+                format!("The output template {template:?} must end with \".{{ext}}\", so each file's extension is its format's")
+            );
+        }
+    }
+
+    #[test]
+    fn writes_doubled_braces_as_braces() {
+        assert_eq!(
+            render("{{{shape}}}.{ext}", "cube").unwrap(),
+            Path::new("{cube}.png")
+        );
+        assert_eq!(render("a}}b.{ext}", "cube").unwrap(), Path::new("a}b.png"));
+    }
+
+    #[test]
+    fn rejects_placeholders_no_drawing_has() {
+        let e = "{shape}_{colour}.{ext}"
+            .parse::<Template>()
+            .unwrap_err()
+            .to_string();
+        assert!(
+            e.starts_with("The output template's {colour} isn't a placeholder. Valid ones are: "),
+            "{e}"
+        );
+        assert!(e.contains("{hole_diameter}"), "{e}");
+        assert_eq!(
+            "{shape}.{ext".parse::<Template>().unwrap_err().to_string(),
+            "The '{' before \"ext\" in the output template is never closed"
+        );
+        assert!("{shape}}.{ext}".parse::<Template>().is_err());
+        // Another shape's option.
+        assert_eq!(
+            render("{gap}.{ext}", "plate").unwrap_err().to_string(),
+            "The output template uses {gap}, but plate has no numeric option called gap"
+        );
+    }
+
+    #[test]
+    fn names_both_drawings_which_would_be_saved_to_the_same_file() {
+        let template = "{shape}.{ext}".parse().unwrap();
+        let registry = OutputRegistry::new(Some(template), Arc::new(FileSink));
+        let output = Output::new("model.png");
+        registry
+            .name(&output, &drawing("plate --hole-diameter=5", 1))
+            .unwrap();
+        let e = registry
+            .name(&output, &drawing("plate --hole-diameter=6", 2))
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            e,
+            "Both the snapshot of drawing 1 (plate --hole-diameter=5) and the snapshot of drawing 2 \
+             (plate --hole-diameter=6) would be saved to plate.png, according to the output template \"{shape}.{ext}\""
+        );
+        registry.forget_claims();
+        registry
+            .name(&output, &drawing("plate --hole-diameter=6", 2))
+            .unwrap();
+    }
+}
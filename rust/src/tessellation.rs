@@ -0,0 +1,223 @@
+use crate::units;
+use color_eyre::{eyre::eyre, Result};
+use std::{f64::consts::PI, sync::Mutex};
+
+/// The fewest straight sides a circle is drawn with, however coarse the quality.
+const MIN_SEGMENTS: usize = 6;
+
+/// The most straight sides a circle is drawn with, however fine the quality.
+const MAX_SEGMENTS: usize = 1024;
+
+/// How finely curves are drawn as straight lines, from --tessellation or a scene file's "tessellation".
+/// Coarser is fewer commands and smaller exports; finer is rounder.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Quality {
+    Low,
+    Medium,
+    High,
+    /// Lines may stray this far (in the scene's units) from the curve.
+    Custom(f64),
+}
+
+impl Quality {
+    /// Parses "low", "medium", "high", or a length, e.g. "0.05mm", for lines to stray no further than.
+    pub fn parse(s: &str) -> Result<Self> {
+        match s {
+            "low" => Ok(Self::Low),
+            "medium" => Ok(Self::Medium),
+            "high" => Ok(Self::High),
+            _ => units::parse_positive_length(s)
+                .map(Self::Custom)
+                .map_err(|e| {
+                    eyre!("The tessellation must be low, medium, high, or a length, not {s} ({e})")
+                }),
+        }
+    }
+
+    /// How far a straight line may stray from the curve it stands for, in the scene's units.
+    pub fn chord_tolerance(self) -> f64 {
+        match self {
+            Self::Low => 0.5,
+            Self::Medium => 0.1,
+            Self::High => 0.01,
+            Self::Custom(tolerance) => tolerance,
+        }
+    }
+}
+
+impl std::fmt::Display for Quality {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Low => write!(f, "low"),
+            Self::Medium => write!(f, "medium"),
+            Self::High => write!(f, "high"),
+            Self::Custom(tolerance) => write!(f, "{tolerance}"),
+        }
+    }
+}
+
+/// The quality the run draws curves at, with what it comes to, for outputs' sidecars.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Used {
+    pub quality: Quality,
+    pub chord_tolerance: f64,
+}
+
+/// How finely the run draws curves, if it was asked to draw them as lines.
+static QUALITY: Mutex<Option<Quality>> = Mutex::new(None);
+
+/// Draw curves as straight lines, at this quality, or None to send them to the API as curves,
+/// where it can draw them.
+pub fn set_quality(quality: Option<Quality>) {
+    *QUALITY.lock().unwrap_or_else(|e| e.into_inner()) = quality;
+}
+
+/// How finely the run draws curves, or None if curves are sent as they are.
+pub fn quality() -> Option<Quality> {
+    *QUALITY.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// The run's quality, for outputs' sidecars, so a render can be made again the same way.
+pub fn used() -> Option<Used> {
+    quality().map(|quality| Used {
+        quality,
+        chord_tolerance: quality.chord_tolerance(),
+    })
+}
+
+/// How many straight sides to draw a circle of this radius with, so that none strays further from it
+/// than the quality allows. E.g. a circle of radius 10 gets 10 sides at low quality,
+/// 23 at medium, and 71 at high.
+pub fn segments_for_radius(radius: f64, quality: Quality) -> usize {
+    let tolerance = quality.chord_tolerance();
+    if tolerance >= radius {
+        return MIN_SEGMENTS;
+    }
+    // A side's middle is r * (1 - cos(angle / 2)) inside the circle.
+    let angle = 2.0 * (1.0 - tolerance / radius).acos();
+    ((2.0 * PI / angle).ceil() as usize).clamp(MIN_SEGMENTS, MAX_SEGMENTS)
+}
+
+/// How far the lines a curve is flattened into may stray from it.
+pub fn flatten_tolerance(quality: Quality) -> f64 {
+    quality.chord_tolerance()
+}
+
+/// The area of a circle as the run draws it: exactly, or as a polygon, if curves are drawn as lines.
+pub fn circle_area(radius: f64) -> f64 {
+    match quality() {
+        None => PI * radius * radius,
+        Some(quality) => {
+            let n = segments_for_radius(radius, quality) as f64;
+            n / 2.0 * radius * radius * (2.0 * PI / n).sin()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jitter::SplitMix;
+
+    const QUALITIES: [Quality; 4] = [
+        Quality::Low,
+        Quality::Medium,
+        Quality::High,
+        Quality::Custom(0.003),
+    ];
+
+    /// Radii from a thousandth of a millimetre to ten metres, as likely to be small as big.
+    fn radii(seed: u64) -> Vec<f64> {
+        let mut random = SplitMix(seed);
+        (0..2000)
+            .map(|_| 10_f64.powf(random.between(-3.0, 4.0)))
+            .collect()
+    }
+
+    /// How far the middle of each side of a circle drawn with `n` sides is inside it.
+    fn sagitta(radius: f64, n: usize) -> f64 {
+        radius * (1.0 - (PI / n as f64).cos())
+    }
+
+    #[test]
+    fn draws_circles_with_as_many_sides_as_worked_out_by_hand() {
+        for (radius, quality, sides) in [
+            (10.0, Quality::Low, 10),
+            (10.0, Quality::Medium, 23),
+            (10.0, Quality::High, 71),
+            // The same shape ten times bigger, with ten times the tolerance.
+            (100.0, Quality::Medium, 71),
+            (5.0, Quality::Custom(0.003), 91),
+            (1000.0, Quality::High, 703),
+            // Three sides would do, but that's too few to look like a circle.
+            (1.0, Quality::Low, MIN_SEGMENTS),
+            // Smaller than the tolerance.
+            (0.4, Quality::Low, MIN_SEGMENTS),
+            // 2222 sides would be needed.
+            (10000.0, Quality::High, MAX_SEGMENTS),
+        ] {
+            assert_eq!(
+                segments_for_radius(radius, quality),
+                sides,
+                "A circle of radius {radius} at {quality:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn circles_stray_no_further_than_the_quality_allows() {
+        for radius in radii(1) {
+            for quality in QUALITIES {
+                let n = segments_for_radius(radius, quality);
+                assert!((MIN_SEGMENTS..=MAX_SEGMENTS).contains(&n));
+                let tolerance = quality.chord_tolerance();
+                assert!(
+                    n == MAX_SEGMENTS || sagitta(radius, n) <= tolerance * (1.0 + 1e-9),
+                    "A circle of radius {radius} drawn with {n} sides strays {} from it, more than {tolerance}",
+                    sagitta(radius, n)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn circles_have_no_more_sides_than_they_need() {
+        for radius in radii(2) {
+            for quality in QUALITIES {
+                let n = segments_for_radius(radius, quality);
+                let tolerance = quality.chord_tolerance();
+                assert!(
+                    n == MIN_SEGMENTS || sagitta(radius, n - 1) > tolerance * (1.0 - 1e-9),
+                    "A circle of radius {radius} would be close enough with {} sides, not {n}",
+                    n - 1
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn bigger_circles_and_finer_qualities_get_more_sides() {
+        let mut radii = radii(3);
+        radii.sort_by(f64::total_cmp);
+        for pair in radii.windows(2) {
+            for quality in QUALITIES {
+                assert!(
+                    segments_for_radius(pair[0], quality) <= segments_for_radius(pair[1], quality),
+                    "A circle of radius {} gets more sides than one of radius {} at {quality:?}",
+                    pair[0],
+                    pair[1]
+                );
+            }
+            let counts: Vec<_> = [Quality::Low, Quality::Medium, Quality::High]
+                .into_iter()
+                .map(|quality| segments_for_radius(pair[0], quality))
+                .collect();
+            assert!(
+                counts.windows(2).all(|pair| pair[0] <= pair[1]),
+                "A circle of radius {} gets {counts:?} sides at low, medium and high quality",
+                pair[0]
+            );
+        }
+    }
+}
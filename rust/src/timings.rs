@@ -0,0 +1,255 @@
+use std::{sync::Mutex, time::Duration};
+
+/// What a session is spending its time on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    /// Opening the WebSocket connection.
+    Connect,
+    /// Drawing the model, and anything else no other phase covers.
+    Build,
+    /// Pointing the camera.
+    Camera,
+    /// Waiting for the API's snapshot.
+    Snapshot,
+    /// Decoding, touching up and saving outputs locally.
+    Save,
+    /// Waiting for the API's export.
+    Export,
+}
+
+/// How much wall-clock time was spent in each phase.
+/// Each moment of a session is in exactly one phase, so the phases add up to the session's whole duration.
+/// They're saved in seconds.
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct Timings {
+    #[serde(serialize_with = "seconds", deserialize_with = "from_seconds")]
+    pub connect: Duration,
+    #[serde(serialize_with = "seconds", deserialize_with = "from_seconds")]
+    pub build: Duration,
+    #[serde(serialize_with = "seconds", deserialize_with = "from_seconds")]
+    pub camera: Duration,
+    #[serde(serialize_with = "seconds", deserialize_with = "from_seconds")]
+    pub snapshot: Duration,
+    #[serde(serialize_with = "seconds", deserialize_with = "from_seconds")]
+    pub save: Duration,
+    #[serde(serialize_with = "seconds", deserialize_with = "from_seconds")]
+    pub export: Duration,
+    /// How long images spent being encoded on other threads (e.g. a turntable's frames), while the session
+    /// got on with something else. That time isn't a phase of its own, so it isn't in the total.
+    #[serde(serialize_with = "seconds", deserialize_with = "from_seconds")]
+    pub overlapped: Duration,
+}
+
+fn seconds<S: serde::Serializer>(d: &Duration, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_f64(d.as_secs_f64())
+}
+
+fn from_seconds<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+    let seconds: f64 = serde::Deserialize::deserialize(d)?;
+    Duration::try_from_secs_f64(seconds).map_err(serde::de::Error::custom)
+}
+
+impl Timings {
+    pub fn add(&mut self, phase: Phase, time: Duration) {
+        *match phase {
+            Phase::Connect => &mut self.connect,
+            Phase::Build => &mut self.build,
+            Phase::Camera => &mut self.camera,
+            Phase::Snapshot => &mut self.snapshot,
+            Phase::Save => &mut self.save,
+            Phase::Export => &mut self.export,
+        } += time;
+    }
+
+    /// Add another session's timings to these.
+    pub fn add_all(&mut self, other: &Self) {
+        for (phase, time) in other.phases() {
+            self.add(phase, time);
+        }
+        self.overlapped += other.overlapped;
+    }
+
+    fn phases(&self) -> [(Phase, Duration); 6] {
+        [
+            (Phase::Connect, self.connect),
+            (Phase::Build, self.build),
+            (Phase::Camera, self.camera),
+            (Phase::Snapshot, self.snapshot),
+            (Phase::Save, self.save),
+            (Phase::Export, self.export),
+        ]
+    }
+
+    pub fn total(&self) -> Duration {
+        self.phases().iter().map(|(_, time)| *time).sum()
+    }
+}
+
+/// E.g. "connect 0.41s, build 1.20s, camera 0.05s, snapshot 0.80s, save 0.02s, export 0.00s (2.48s in all)",
+/// and how long encoding overlapped with the rest, if it did.
+impl std::fmt::Display for Timings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let phases: Vec<_> = self
+            .phases()
+            .iter()
+            .map(|(phase, time)| {
+                let name = format!("{phase:?}").to_lowercase();
+                format!("{name} {:.2}s", time.as_secs_f64())
+            })
+            .collect();
+        write!(
+            f,
+            "{} ({:.2}s in all)",
+            phases.join(", "),
+            self.total().as_secs_f64()
+        )?;
+        if self.overlapped > Duration::ZERO {
+            write!(
+                f,
+                ", and {:.2}s of encoding overlapped with it",
+                self.overlapped.as_secs_f64()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Every finished session's timings, added up. Sweeps run sessions at the same time,
+/// so their total can be more than the run took.
+static RUN_TIMINGS: Mutex<Timings> = Mutex::new(Timings {
+    connect: Duration::ZERO,
+    build: Duration::ZERO,
+    camera: Duration::ZERO,
+    snapshot: Duration::ZERO,
+    save: Duration::ZERO,
+    export: Duration::ZERO,
+    overlapped: Duration::ZERO,
+});
+
+/// Add a finished session's timings to the run's.
+pub fn record(timings: &Timings) {
+    RUN_TIMINGS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .add_all(timings);
+}
+
+/// Every finished session's timings, added up.
+pub fn run_timings() -> Timings {
+    RUN_TIMINGS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jitter::SplitMix;
+
+    const PHASES: [Phase; 6] = [
+        Phase::Connect,
+        Phase::Build,
+        Phase::Camera,
+        Phase::Snapshot,
+        Phase::Save,
+        Phase::Export,
+    ];
+
+    /// Timings of a made-up session, with time added to its phases a bit at a time, and how long it took.
+    fn random_timings(random: &mut SplitMix) -> (Timings, Duration) {
+        let mut timings = Timings::default();
+        let mut took = Duration::ZERO;
+        for _ in 0..random.below(50) {
+            let time = Duration::from_nanos(random.next() % 5_000_000_000);
+            timings.add(PHASES[random.below(PHASES.len())], time);
+            took += time;
+        }
+        timings.overlapped = Duration::from_nanos(random.next() % 5_000_000_000);
+        (timings, took)
+    }
+
+    #[test]
+    fn adds_up_to_the_time_spent_in_every_phase() {
+        let mut random = SplitMix(1);
+        for _ in 0..1000 {
+            let (timings, took) = random_timings(&mut random);
+            // Time spent encoding alongside the phases isn't more time.
+            assert_eq!(timings.total(), took, "{timings:?}");
+        }
+    }
+
+    #[test]
+    fn saves_phases_which_add_up_to_the_total() {
+        let mut random = SplitMix(2);
+        for _ in 0..1000 {
+            let (timings, took) = random_timings(&mut random);
+            let saved = serde_json::to_value(&timings).unwrap();
+            let sum: f64 = PHASES
+                .iter()
+                .map(|phase| saved[format!("{phase:?}").to_lowercase()].as_f64().unwrap())
+                .sum();
+            // Within rounding a nanosecond's worth of seconds, for each phase.
+            assert!((sum - took.as_secs_f64()).abs() < 1e-8, "{saved}");
+        }
+    }
+
+    #[test]
+    fn reads_back_what_it_saves() {
+        let mut random = SplitMix(3);
+        for _ in 0..1000 {
+            let (timings, _) = random_timings(&mut random);
+            let json = serde_json::to_string(&timings).unwrap();
+            let read: Timings = serde_json::from_str(&json).unwrap();
+            for phase in PHASES {
+                let (saved, read) = (phase_time(&timings, phase), phase_time(&read, phase));
+                assert!(saved.abs_diff(read) <= Duration::from_nanos(1), "{json}");
+            }
+        }
+        assert!(serde_json::from_str::<Timings>(r#"{ "connect": -1 }"#).is_err());
+    }
+
+    fn phase_time(timings: &Timings, phase: Phase) -> Duration {
+        timings
+            .phases()
+            .into_iter()
+            .find(|(p, _)| *p == phase)
+            .unwrap()
+            .1
+    }
+
+    #[test]
+    fn adds_sessions_timings_together() {
+        let mut random = SplitMix(4);
+        let (a, a_took) = random_timings(&mut random);
+        let (b, b_took) = random_timings(&mut random);
+        let mut both = a.clone();
+        both.add_all(&b);
+        assert_eq!(both.total(), a_took + b_took);
+        assert_eq!(both.overlapped, a.overlapped + b.overlapped);
+        for phase in PHASES {
+            assert_eq!(
+                phase_time(&both, phase),
+                phase_time(&a, phase) + phase_time(&b, phase)
+            );
+        }
+    }
+
+    #[test]
+    fn says_how_long_each_phase_took() {
+        let mut timings = Timings::default();
+        timings.add(Phase::Connect, Duration::from_millis(410));
+        timings.add(Phase::Build, Duration::from_millis(1200));
+        timings.add(Phase::Snapshot, Duration::from_millis(800));
+        timings.add(Phase::Camera, Duration::from_millis(50));
+        timings.add(Phase::Save, Duration::from_millis(20));
+        assert_eq!(
+            timings.to_string(),
+            "connect 0.41s, build 1.20s, camera 0.05s, snapshot 0.80s, save 0.02s, export 0.00s (2.48s in all)"
+        );
+        timings.overlapped = Duration::from_millis(300);
+        assert!(timings
+            .to_string()
+            .ends_with("(2.48s in all), and 0.30s of encoding overlapped with it"));
+    }
+}
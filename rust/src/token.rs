@@ -0,0 +1,266 @@
+use color_eyre::{
+    eyre::{bail, Context},
+    Result,
+};
+use std::{
+    process::{Command, Stdio},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// How long before a token expires to fetch a new one, so a connection isn't opened with a token
+/// which runs out as it's being used.
+const REFRESH_AHEAD: Duration = Duration::from_secs(60);
+
+/// Where the run gets its API token from. It's asked each time the run connects (or reconnects),
+/// so a long run can swap in a new token when the old one expires.
+///
+/// Tokens are secrets: providers mustn't print them, or put them in their errors.
+pub trait TokenProvider: Send + Sync {
+    /// The token to connect with. `refresh` asks for a new one, even if the last one should still
+    /// be good, e.g. because the API just refused it.
+    fn token(&self, refresh: bool) -> Result<String>;
+}
+
+/// The same token every time, e.g. the one in $KITTYCAD_API_TOKEN.
+pub struct StaticToken(String);
+
+impl StaticToken {
+    pub fn new(token: String) -> Self {
+        Self(token)
+    }
+
+    /// The token in $KITTYCAD_API_TOKEN.
+    pub fn from_env() -> Result<Self> {
+        let token =
+            std::env::var("KITTYCAD_API_TOKEN").context("You must set $KITTYCAD_API_TOKEN")?;
+        Ok(Self(token))
+    }
+}
+
+impl TokenProvider for StaticToken {
+    fn token(&self, _refresh: bool) -> Result<String> {
+        Ok(self.0.clone())
+    }
+}
+
+/// A token printed by a command (from --token-command), e.g. one which asks an internal service
+/// for a short-lived token. The token is kept until it's about to expire.
+///
+/// The command prints either the token on its own, which is kept for `lifetime`, or a JSON object
+/// like {"token": "...", "expires_in": 3600}, which says how many seconds the token lasts.
+pub struct CommandToken {
+    command: String,
+    lifetime: Duration,
+    cached: Mutex<Option<Cached>>,
+}
+
+struct Cached {
+    token: String,
+    /// When the token expires, or None if it lasts longer than the run could.
+    expires: Option<Instant>,
+}
+
+#[derive(serde::Deserialize)]
+struct Printed {
+    token: String,
+    expires_in: Option<f64>,
+}
+
+impl CommandToken {
+    pub fn new(command: String, lifetime: Duration) -> Self {
+        Self {
+            command,
+            lifetime,
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// Run the command, and read the token (and how long it lasts) from what it prints.
+    fn fetch(&self) -> Result<(String, Duration)> {
+        let mut shell = if cfg!(windows) {
+            let mut shell = Command::new("cmd");
+            shell.arg("/C");
+            shell
+        } else {
+            let mut shell = Command::new("sh");
+            shell.arg("-c");
+            shell
+        };
+        let output = shell
+            .arg(&self.command)
+            .stdin(Stdio::null())
+            .stderr(Stdio::inherit())
+            .output()
+            .wrap_err_with(|| format!("Could not run the --token-command ({})", self.command))?;
+        // What it printed is never put in an error, in case it was a token after all.
+        if !output.status.success() {
+            bail!(
+                "The --token-command ({}) failed ({})",
+                self.command,
+                output.status
+            );
+        }
+        let Ok(printed) = String::from_utf8(output.stdout) else {
+            bail!(
+                "The --token-command ({}) printed something which isn't text",
+                self.command
+            );
+        };
+        let printed = printed.trim();
+        let (token, lifetime) = if printed.starts_with('{') {
+            let Ok(printed) = serde_json::from_str::<Printed>(printed) else {
+                bail!(
+                    "The --token-command ({}) printed JSON without a \"token\" (or with an \"expires_in\" which isn't a number of seconds)",
+                    self.command
+                );
+            };
+            let lifetime = match printed.expires_in {
+                // A lifetime too long to hold is as good as forever.
+                Some(seconds) if seconds.is_finite() && seconds >= 0.0 => {
+                    Duration::try_from_secs_f64(seconds).unwrap_or(Duration::MAX)
+                }
+                Some(_) => bail!(
+                    "The --token-command ({}) printed an \"expires_in\" which isn't a number of seconds",
+                    self.command
+                ),
+                None => self.lifetime,
+            };
+            (printed.token.trim().to_owned(), lifetime)
+        } else {
+            (printed.to_owned(), self.lifetime)
+        };
+        if token.is_empty() || token.contains(char::is_whitespace) {
+            bail!(
+                "The --token-command ({}) should print a token, or JSON with a \"token\", and nothing else",
+                self.command
+            );
+        }
+        Ok((token, lifetime))
+    }
+}
+
+impl TokenProvider for CommandToken {
+    fn token(&self, refresh: bool) -> Result<String> {
+        let mut cached = self.cached.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(cached) = cached.as_ref() {
+            let fresh = cached
+                .expires
+                .is_none_or(|expires| Instant::now() + REFRESH_AHEAD < expires);
+            if !refresh && fresh {
+                return Ok(cached.token.clone());
+            }
+        }
+        let (token, lifetime) = self.fetch()?;
+        if cached.is_some() {
+            say!(
+                "Fetched a new API token with the --token-command, because the last one {}",
+                if refresh {
+                    "was refused"
+                } else {
+                    "was about to expire"
+                }
+            );
+        }
+        *cached = Some(Cached {
+            token: token.clone(),
+            expires: Instant::now().checked_add(lifetime),
+        });
+        Ok(token)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A command which counts how often it's run, in a directory of the test's own, and prints
+    /// `printed` with "{n}" replaced by the count. So "secret-{n}" prints "secret-1" the first
+    /// time, without the command having "secret-1" in it to turn up in errors.
+    fn counting(test: &str, printed: &str) -> (PathBuf, String) {
+        let dir =
+            std::env::temp_dir().join(format!("kittycad-token-{test}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let count = dir.join("count");
+        let command = format!(
+            "n=$(( $(cat '{count}' 2>/dev/null || echo 0) + 1 )); echo $n > '{count}'; echo '{printed}' | sed \"s/{{n}}/$n/\"",
+            count = count.display(),
+        );
+        (count, command)
+    }
+
+    fn runs(count: &PathBuf) -> u32 {
+        std::fs::read_to_string(count).map_or(0, |n| n.trim().parse().unwrap())
+    }
+
+    #[test]
+    fn keeps_a_token_until_it_expires() {
+        let (count, command) = counting("cached", "token-{n}");
+        let provider = CommandToken::new(command, Duration::from_secs(3600));
+        assert_eq!(provider.token(false).unwrap(), "token-1");
+        assert_eq!(provider.token(false).unwrap(), "token-1");
+        assert_eq!(runs(&count), 1);
+    }
+
+    #[test]
+    fn fetches_a_new_token_when_asked_to() {
+        let (count, command) = counting("refresh", "token-{n}");
+        let provider = CommandToken::new(command, Duration::from_secs(3600));
+        assert_eq!(provider.token(false).unwrap(), "token-1");
+        assert_eq!(provider.token(true).unwrap(), "token-2");
+        assert_eq!(provider.token(false).unwrap(), "token-2");
+        assert_eq!(runs(&count), 2);
+    }
+
+    #[test]
+    fn fetches_a_new_token_before_the_last_one_expires() {
+        // It lasts less than REFRESH_AHEAD, so it's never good enough to keep.
+        let (count, command) = counting("expiring", r#"{"token": "token-{n}", "expires_in": 30}"#);
+        let provider = CommandToken::new(command, Duration::from_secs(3600));
+        assert_eq!(provider.token(false).unwrap(), "token-1");
+        assert_eq!(provider.token(false).unwrap(), "token-2");
+        assert_eq!(runs(&count), 2);
+    }
+
+    #[test]
+    fn keeps_tokens_which_last_too_long_to_hold_forever() {
+        let (count, command) = counting("forever", r#"{"token": "token-{n}", "expires_in": 1e30}"#);
+        let provider = CommandToken::new(command, Duration::from_secs(3600));
+        assert_eq!(provider.token(false).unwrap(), "token-1");
+        assert_eq!(provider.token(false).unwrap(), "token-1");
+        assert_eq!(runs(&count), 1);
+
+        let (count, command) = counting("long-lifetime", "token-{n}");
+        let provider = CommandToken::new(command, Duration::MAX);
+        assert_eq!(provider.token(false).unwrap(), "token-1");
+        assert_eq!(provider.token(false).unwrap(), "token-1");
+        assert_eq!(runs(&count), 1);
+    }
+
+    #[test]
+    fn rejects_bad_output_without_repeating_it() {
+        for printed in [
+            "secret-{n} and more",
+            r#"{"token": "secret-{n}", "expires_in": -1}"#,
+            r#"{"token": "secret-{n}", "expires_in": "soon"}"#,
+            r#"{"secret": "secret-{n}"}"#,
+        ] {
+            let (_, command) = counting("bad", printed);
+            let e = CommandToken::new(command, Duration::from_secs(60))
+                .token(false)
+                .unwrap_err();
+            assert!(!format!("{e:?}").contains("secret-1"), "{e:?}");
+        }
+    }
+
+    #[test]
+    fn fails_if_the_command_fails() {
+        let provider =
+            CommandToken::new("echo secret-1; exit 3".to_owned(), Duration::from_secs(60));
+        let e = provider.token(false).unwrap_err();
+        assert!(e.to_string().contains("failed"), "{e}");
+        assert!(!format!("{e:?}").contains("secret-1 "), "{e:?}");
+    }
+}
@@ -0,0 +1,157 @@
+use crate::{session::ModelingSession, shapes, units};
+use color_eyre::{
+    eyre::{bail, eyre, Context},
+    Result,
+};
+use kittycad::types::{ModelingCmd, Point3D};
+use uuid::Uuid;
+
+/// Scale, then rotate about the Z axis, then move.
+/// Scaling and rotation are both about the origin.
+#[derive(Clone, Debug)]
+pub struct Transform {
+    pub translate: Point3D,
+    pub rotate_z_degrees: f64,
+    pub scale: f64,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translate: Point3D {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            rotate_z_degrees: 0.0,
+            scale: 1.0,
+        }
+    }
+}
+
+impl Transform {
+    /// Where a point ends up after the transform.
+    pub fn apply(&self, p: &Point3D) -> Point3D {
+        let p = self.apply_to_offset(p);
+        Point3D {
+            x: p.x + self.translate.x,
+            y: p.y + self.translate.y,
+            z: p.z + self.translate.z,
+        }
+    }
+
+    /// How an offset between two points changes after the transform.
+    /// Offsets are scaled and rotated, but moving doesn't change them.
+    pub fn apply_to_offset(&self, p: &Point3D) -> Point3D {
+        let (sin, cos) = self.rotate_z_degrees.to_radians().sin_cos();
+        Point3D {
+            x: self.scale * (p.x * cos - p.y * sin),
+            y: self.scale * (p.x * sin + p.y * cos),
+            z: self.scale * p.z,
+        }
+    }
+}
+
+/// Parses "x,y,z", e.g. "20,0,0".
+pub fn parse_translation(s: &str) -> Result<Point3D> {
+    let coords = s
+        .split(',')
+        .map(units::parse_length)
+        .collect::<Result<Vec<_>>>()
+        .with_context(|| format!("{s:?} should be three lengths, like 20,0,0 or 1in,0,0"))?;
+    let [x, y, z] = coords[..] else {
+        bail!("{s:?} should be three lengths, like 20,0,0 or 1in,0,0");
+    };
+    Ok(Point3D { x, y, z })
+}
+
+/// Parses "axis,degrees", e.g. "z,45".
+/// Only the Z axis is supported, because our profiles are flat on the XY plane,
+/// and rotating them about any other axis would tip them out of it.
+pub fn parse_rotation(s: &str) -> Result<f64> {
+    let (axis, degrees) = s
+        .split_once(',')
+        .ok_or_else(|| eyre!("{s:?} should be an axis and an angle in degrees, like z,45"))?;
+    if !axis.trim().eq_ignore_ascii_case("z") {
+        bail!("Can only rotate about the z axis, not {:?}", axis.trim());
+    }
+    degrees
+        .trim()
+        .parse()
+        .with_context(|| format!("{degrees:?} should be an angle in degrees"))
+}
+
+/// Parses a scale factor, which must be positive.
+pub fn parse_scale(s: &str) -> Result<f64> {
+    let scale: f64 = s
+        .trim()
+        .parse()
+        .with_context(|| format!("{s:?} should be a number"))?;
+    if !(scale > 0.0 && scale.is_finite()) {
+        bail!("The scale must be a positive number, not {scale}");
+    }
+    Ok(scale)
+}
+
+/// Move, rotate and scale an object, and return its new ID.
+///
+/// The API (as of this version of the kittycad crate) has no commands to transform an object once
+/// it's been built. So instead, this builds a transformed copy from the object's recipe (the commands
+/// we used to build it), and then removes the original. That means the object gets a new ID.
+pub async fn apply_transform(
+    session: &mut ModelingSession,
+    object_id: Uuid,
+    transform: &Transform,
+) -> Result<Uuid> {
+    let new_id = copy_transformed(session, object_id, transform).await?;
+    // It's the same object, moved, so it keeps its names.
+    session.names_mut().moved(object_id, new_id);
+    remove_objects(session, &[object_id]).await?;
+    Ok(new_id)
+}
+
+/// Build a moved, rotated and scaled copy of an object, leaving the original where it is,
+/// and return the copy's ID.
+pub async fn copy_transformed(
+    session: &mut ModelingSession,
+    object_id: Uuid,
+    transform: &Transform,
+) -> Result<Uuid> {
+    let Some(recipe) = session
+        .recipe(object_id)
+        .map(|recipe| recipe.transformed(transform))
+    else {
+        bail!("Can't transform {object_id}, because we don't know how it was built");
+    };
+    shapes::build(session, &recipe).await
+}
+
+/// Hide an object from snapshots, or show it again. Hidden objects are still in the scene.
+pub async fn set_hidden(
+    session: &mut ModelingSession,
+    object_id: Uuid,
+    hidden: bool,
+) -> Result<()> {
+    let cmd_id = session
+        .send(ModelingCmd::ObjectVisible { hidden, object_id })
+        .await?;
+    session.await_response(cmd_id).await?;
+    Ok(())
+}
+
+/// Remove objects from the scene.
+pub async fn remove_objects(session: &mut ModelingSession, object_ids: &[Uuid]) -> Result<()> {
+    let cmd_id = session
+        .send(ModelingCmd::RemoveSceneObjects {
+            object_ids: object_ids.to_vec(),
+        })
+        .await?;
+    session.await_response(cmd_id).await.wrap_err_with(|| {
+        let ids: Vec<_> = object_ids.iter().map(Uuid::to_string).collect();
+        format!("Could not remove {} from the scene", ids.join(", "))
+    })?;
+    for object_id in object_ids {
+        session.removed(*object_id);
+    }
+    Ok(())
+}
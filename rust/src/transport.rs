@@ -0,0 +1,681 @@
+use color_eyre::{eyre::WrapErr, Report, Result};
+use futures::{
+    stream::{SplitSink, SplitStream},
+    SinkExt, StreamExt,
+};
+use reqwest::Upgraded;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+use tokio_tungstenite::{
+    tungstenite::{
+        error::CapacityError,
+        protocol::{frame::coding::CloseCode, CloseFrame},
+        Error as WsError, Message as WsMsg,
+    },
+    WebSocketStream,
+};
+
+/// How a session's messages reach the API: over a WebSocket, or (for --offline) from a recording of one.
+pub enum Transport {
+    WebSocket {
+        write: SplitSink<WebSocketStream<Upgraded>, WsMsg>,
+        read: SplitStream<WebSocketStream<Upgraded>>,
+        /// Where the messages are written down as they're sent and received, for --record.
+        recording: Option<Recording>,
+    },
+    Replay(Replay),
+}
+
+/// Why a message couldn't be sent or received.
+#[derive(Debug)]
+pub enum TransportError {
+    /// The WebSocket failed, e.g. because the connection was lost.
+    WebSocket(Box<WsError>),
+    /// A message from the API was bigger than the limit on messages.
+    TooBig { size: usize, limit: usize },
+    /// The run didn't go the way the recording it's replaying did.
+    OutOfDate(RecordingOutOfDate),
+    /// The message couldn't be written down in the recording.
+    Record(Report),
+}
+
+impl From<WsError> for TransportError {
+    fn from(e: WsError) -> Self {
+        match e {
+            WsError::Capacity(CapacityError::MessageTooLong { size, max_size }) => Self::TooBig {
+                size,
+                limit: max_size,
+            },
+            e => Self::WebSocket(Box::new(e)),
+        }
+    }
+}
+
+impl Transport {
+    /// Write down every message sent and received from now on.
+    pub fn record(&mut self, new: Recording) {
+        match self {
+            Self::WebSocket { recording, .. } => *recording = Some(new),
+            Self::Replay(replay) => replay.recording = Some(new),
+        }
+    }
+
+    /// Send a message straight away.
+    pub async fn send(&mut self, text: String) -> Result<(), TransportError> {
+        #[cfg(feature = "test-hooks")]
+        if crate::inject::sending(&text) {
+            return Ok(());
+        }
+        match self {
+            Self::WebSocket {
+                write, recording, ..
+            } => {
+                if let Some(recording) = recording {
+                    recording.write(Entry::sent(&text))?;
+                }
+                write
+                    .send(WsMsg::Text(text))
+                    .await
+                    .map_err(TransportError::from)
+            }
+            Self::Replay(replay) => replay.send(&text),
+        }
+    }
+
+    /// Queue a message, to be sent by the next `flush` (or `send`).
+    pub async fn feed(&mut self, text: String) -> Result<(), TransportError> {
+        #[cfg(feature = "test-hooks")]
+        if crate::inject::sending(&text) {
+            return Ok(());
+        }
+        match self {
+            Self::WebSocket {
+                write, recording, ..
+            } => {
+                if let Some(recording) = recording {
+                    recording.write(Entry::sent(&text))?;
+                }
+                write
+                    .feed(WsMsg::Text(text))
+                    .await
+                    .map_err(TransportError::from)
+            }
+            Self::Replay(replay) => replay.send(&text),
+        }
+    }
+
+    /// Send whatever's queued.
+    pub async fn flush(&mut self) -> Result<(), TransportError> {
+        match self {
+            Self::WebSocket { write, .. } => write.flush().await.map_err(TransportError::from),
+            Self::Replay(_) => Ok(()),
+        }
+    }
+
+    /// The next message from the API, or None if the connection closed.
+    pub async fn next(&mut self) -> Option<Result<WsMsg, TransportError>> {
+        #[cfg(feature = "test-hooks")]
+        {
+            if let Some(delay) = crate::inject::delay() {
+                tokio::time::sleep(delay).await;
+            }
+            if let Some(failure) = crate::inject::failure() {
+                return Some(Ok(failure));
+            }
+            let msg = self.read().await;
+            crate::inject::received(msg)
+        }
+        #[cfg(not(feature = "test-hooks"))]
+        self.read().await
+    }
+
+    /// Read the next message from the API.
+    async fn read(&mut self) -> Option<Result<WsMsg, TransportError>> {
+        match self {
+            Self::WebSocket {
+                read, recording, ..
+            } => {
+                let msg = read.next().await?;
+                if let (Some(recording), Ok(msg)) = (recording, &msg) {
+                    if let Some(Err(e)) = Entry::read(msg).map(|entry| recording.write(entry)) {
+                        return Some(Err(e));
+                    }
+                }
+                Some(msg.map_err(TransportError::from))
+            }
+            Self::Replay(replay) => match replay.next() {
+                Ok(Some(msg)) => Some(Ok(msg)),
+                // The recorded API never answered, so this one doesn't either, until the session gives up.
+                Ok(None) => std::future::pending().await,
+                Err(e) => Some(Err(e)),
+            },
+        }
+    }
+}
+
+/// One message in a recording, one per line.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Entry {
+    Sent(Value),
+    Received(Value),
+    /// The API pinged the connection, with this payload.
+    Ping(String),
+    /// The API closed the connection. 1005 means it didn't send a close code.
+    Closed {
+        code: u16,
+        reason: String,
+    },
+}
+
+impl Entry {
+    fn sent(text: &str) -> Self {
+        Self::Sent(parse(text))
+    }
+
+    fn received(text: &str) -> Self {
+        Self::Received(parse(text))
+    }
+
+    /// How a message read from the API is written down, or None for the kinds the session ignores.
+    fn read(msg: &WsMsg) -> Option<Self> {
+        match msg {
+            WsMsg::Text(text) => Some(Self::received(text)),
+            WsMsg::Ping(payload) => Some(Self::Ping(String::from_utf8_lossy(payload).into_owned())),
+            WsMsg::Close(Some(frame)) => Some(Self::Closed {
+                code: frame.code.into(),
+                reason: frame.reason.to_string(),
+            }),
+            WsMsg::Close(None) => Some(Self::Closed {
+                code: 1005,
+                reason: String::new(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// A message's JSON, so the recording can be read (and diffed) like the messages were.
+/// Anything which isn't JSON is kept as a string.
+fn parse(text: &str) -> Value {
+    serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.to_owned()))
+}
+
+/// A message's text again, from its JSON.
+fn unparse(value: Value) -> String {
+    match value {
+        Value::String(text) => text,
+        value => value.to_string(),
+    }
+}
+
+/// Every message a session sends and receives, written to a file (for --record) as they go.
+pub struct Recording {
+    path: PathBuf,
+    file: BufWriter<File>,
+}
+
+impl Recording {
+    /// Start a recording in this file, replacing anything already in it.
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = File::create(path)
+            .wrap_err_with(|| format!("Could not start a recording in {}", path.display()))?;
+        Ok(Self {
+            path: path.to_owned(),
+            file: BufWriter::new(file),
+        })
+    }
+
+    /// Write a message down. Each is flushed straight away, so a run which fails still leaves
+    /// everything up to the failure in the recording.
+    fn write(&mut self, entry: Entry) -> Result<(), TransportError> {
+        serde_json::to_writer(&mut self.file, &entry)
+            .map_err(std::io::Error::from)
+            .and_then(|()| writeln!(self.file))
+            .and_then(|()| self.file.flush())
+            .wrap_err_with(|| {
+                format!(
+                    "Could not write to the recording in {}",
+                    self.path.display()
+                )
+            })
+            .map_err(TransportError::Record)
+    }
+}
+
+/// Plays back a recording made by --record, instead of talking to the API.
+///
+/// Each command the run sends is matched to the next one in the recording, by what kind of command
+/// it is, and the responses the API sent after it are played back. Their IDs are changed to the ones
+/// this run used, so a run with different (e.g. random) IDs still matches. The run may send its
+/// commands sooner or later than the recorded one did, as long as it sends the same ones.
+///
+/// Pings and Close frames are played back where the API sent them, and responses bigger than the
+/// limit on messages fail as they would on a WebSocket. If the recording ends with commands the API
+/// never answered, the replay doesn't answer them either, so the run gives up on them as it did then.
+pub struct Replay {
+    path: PathBuf,
+    /// What's left of the recording, with the line each entry's on.
+    entries: VecDeque<(usize, Entry)>,
+    /// Messages which can be read, because the commands they respond to have been sent.
+    ready: VecDeque<WsMsg>,
+    /// The ID each command had in the recording, and the ID this run sent it with.
+    ids: HashMap<String, String>,
+    /// The IDs, in the recording, of the commands which have been sent.
+    sent: HashSet<String>,
+    /// The IDs, in the recording, of the commands which have been sent, but not responded to yet.
+    unanswered: HashSet<String>,
+    /// The biggest message which can be received, in bytes.
+    max_message_bytes: usize,
+    /// Where this run's messages are written down, for --record.
+    recording: Option<Recording>,
+}
+
+impl Replay {
+    /// Messages bigger than `max_message_bytes` can't be received, as on a WebSocket.
+    pub fn open(path: &Path, max_message_bytes: usize) -> Result<Self> {
+        let file = File::open(path)
+            .wrap_err_with(|| format!("Could not open the recording {}", path.display()))?;
+        let mut entries = VecDeque::new();
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line =
+                line.wrap_err_with(|| format!("Could not read the recording {}", path.display()))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry = serde_json::from_str(&line).wrap_err_with(|| {
+                format!(
+                    "Line {} of {} isn't a recorded message. Was it made by --record?",
+                    i + 1,
+                    path.display()
+                )
+            })?;
+            entries.push_back((i + 1, entry));
+        }
+        let mut replay = Self {
+            path: path.to_owned(),
+            entries,
+            ready: VecDeque::new(),
+            ids: HashMap::new(),
+            sent: HashSet::new(),
+            unanswered: HashSet::new(),
+            max_message_bytes,
+            recording: None,
+        };
+        // Anything the API sent before the first command (e.g. asking for the client's metrics).
+        replay.play_responses();
+        Ok(replay)
+    }
+
+    /// Match a message the run sends to the next one in the recording.
+    fn send(&mut self, text: &str) -> Result<(), TransportError> {
+        let sent = parse(text);
+        // The recorded run read these before sending this, but this run didn't wait for them.
+        self.play_responses();
+        let (line, recorded) = match self.entries.pop_front() {
+            Some((line, Entry::Sent(recorded))) => (line, recorded),
+            Some(_) => unreachable!("the responses were all played above"),
+            None => {
+                return Err(self.out_of_date(
+                    0,
+                    format!("the run sent {}, but the recording ends", describe(&sent)),
+                ))
+            }
+        };
+        if describe(&recorded) != describe(&sent) {
+            return Err(self.out_of_date(
+                line,
+                format!(
+                    "the run sent {}, but the recorded run sent {}",
+                    describe(&sent),
+                    describe(&recorded)
+                ),
+            ));
+        }
+        for (recorded_id, sent_id) in cmd_ids(&recorded).into_iter().zip(cmd_ids(&sent)) {
+            self.sent.insert(recorded_id.clone());
+            self.unanswered.insert(recorded_id.clone());
+            if recorded_id != sent_id {
+                self.ids.insert(recorded_id, sent_id);
+            }
+        }
+        if let Some(recording) = &mut self.recording {
+            recording.write(Entry::Sent(sent))?;
+        }
+        self.play_responses();
+        Ok(())
+    }
+
+    /// Get the messages the API sent before the next command ready to be read,
+    /// with the IDs this run used.
+    fn play_responses(&mut self) {
+        while matches!(self.entries.front(), Some((_, entry)) if !matches!(entry, Entry::Sent(_))) {
+            let Some((_, entry)) = self.entries.pop_front() else {
+                unreachable!("there was an entry at the front just above");
+            };
+            let msg = self.play(entry);
+            self.ready.push_back(msg);
+        }
+    }
+
+    /// A recorded message from the API, as this run receives it.
+    fn play(&mut self, entry: Entry) -> WsMsg {
+        match entry {
+            Entry::Received(value) => {
+                let text = unparse(value);
+                self.unanswered.retain(|id| !text.contains(id.as_str()));
+                WsMsg::Text(self.with_sent_ids(text))
+            }
+            Entry::Ping(payload) => WsMsg::Ping(payload.into_bytes()),
+            Entry::Closed { code: 1005, .. } => WsMsg::Close(None),
+            Entry::Closed { code, reason } => WsMsg::Close(Some(CloseFrame {
+                code: CloseCode::from(code),
+                reason: reason.into(),
+            })),
+            Entry::Sent(_) => unreachable!("only messages from the API are played"),
+        }
+    }
+
+    /// A recorded response's text, with the IDs this run used.
+    fn with_sent_ids(&self, mut text: String) -> String {
+        for (recorded_id, sent_id) in &self.ids {
+            if text.contains(recorded_id.as_str()) {
+                text = text.replace(recorded_id.as_str(), sent_id);
+            }
+        }
+        text
+    }
+
+    /// The next recorded message, if the commands it responds to have been sent,
+    /// or None if the API never sent another.
+    fn next(&mut self) -> Result<Option<WsMsg>, TransportError> {
+        let Some(msg) = self.next_response()? else {
+            return Ok(None);
+        };
+        if let WsMsg::Text(text) = &msg {
+            if text.len() > self.max_message_bytes {
+                return Err(TransportError::TooBig {
+                    size: text.len(),
+                    limit: self.max_message_bytes,
+                });
+            }
+        }
+        if let (Some(recording), Some(entry)) = (&mut self.recording, Entry::read(&msg)) {
+            recording.write(entry)?;
+        }
+        Ok(Some(msg))
+    }
+
+    fn next_response(&mut self) -> Result<Option<WsMsg>, TransportError> {
+        if let Some(msg) = self.ready.pop_front() {
+            return Ok(Some(msg));
+        }
+        // The recorded run sent more commands before reading this one's response,
+        // but it's already been sent this time, so it needn't wait.
+        let early = self.entries.iter().position(|(_, entry)| match entry {
+            Entry::Received(value) => value
+                .get("request_id")
+                .and_then(Value::as_str)
+                .is_some_and(|id| self.sent.contains(id)),
+            _ => false,
+        });
+        if let Some((_, entry)) = early.and_then(|i| self.entries.remove(i)) {
+            return Ok(Some(self.play(entry)));
+        }
+        if self.entries.is_empty() && !self.unanswered.is_empty() {
+            return Ok(None);
+        }
+        Err(match self.entries.front() {
+            Some((line, Entry::Sent(recorded))) => self.out_of_date(
+                *line,
+                format!(
+                    "the run waited for a response, but the recorded run sent {} first",
+                    describe(recorded)
+                ),
+            ),
+            _ => self.out_of_date(
+                0,
+                "the run waited for a response, but the recording ends".to_owned(),
+            ),
+        })
+    }
+
+    fn out_of_date(&self, line: usize, what: String) -> TransportError {
+        TransportError::OutOfDate(RecordingOutOfDate {
+            path: self.path.clone(),
+            line,
+            what,
+        })
+    }
+}
+
+/// What kind of message this is, e.g. "a command (start_path)", or "a batch of 3 commands (...)".
+fn describe(msg: &Value) -> String {
+    let kind = |v: &Value| {
+        v.get("type")
+            .and_then(Value::as_str)
+            .unwrap_or("unknown")
+            .to_owned()
+    };
+    match kind(msg).as_str() {
+        "modeling_cmd_req" => format!(
+            "a command ({})",
+            msg.get("cmd").map(kind).unwrap_or_default()
+        ),
+        "modeling_cmd_batch_req" => {
+            let kinds: Vec<_> = msg
+                .get("requests")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(|request| request.get("cmd").map(kind))
+                .collect();
+            format!("a batch of {} commands ({})", kinds.len(), kinds.join(", "))
+        }
+        other => format!("a message ({other})"),
+    }
+}
+
+/// The IDs of the commands in a message, in order.
+fn cmd_ids(msg: &Value) -> Vec<String> {
+    let requests = match msg.get("requests").and_then(Value::as_array) {
+        Some(requests) => requests.iter().collect(),
+        None => vec![msg],
+    };
+    requests
+        .into_iter()
+        .filter_map(|request| request.get("cmd_id").and_then(Value::as_str))
+        .map(str::to_owned)
+        .collect()
+}
+
+/// The run sent different commands from the recording it's replaying (with --offline),
+/// e.g. because the model's changed since it was recorded.
+#[derive(Debug)]
+pub struct RecordingOutOfDate {
+    path: PathBuf,
+    /// The line of the recording where the run went its own way, or 0 at the end of the recording.
+    line: usize,
+    what: String,
+}
+
+impl std::fmt::Display for RecordingOutOfDate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The recording {} is out of date: ", self.path.display())?;
+        if self.line > 0 {
+            write!(f, "at line {}, ", self.line)?;
+        }
+        write!(
+            f,
+            "{}. Record it again (with --record) against the API",
+            self.what
+        )
+    }
+}
+
+impl std::error::Error for RecordingOutOfDate {}
+
+impl RecordingOutOfDate {
+    /// Was this error (or anything that caused it) the run going its own way from its recording?
+    pub fn caused(e: &Report) -> bool {
+        e.chain().any(|cause| cause.is::<Self>())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// A replay of a recording with these lines in it.
+    fn replay(test: &str, lines: &[Value], max_message_bytes: usize) -> Replay {
+        let path = std::env::temp_dir().join(format!(
+            "kittycad-replay-{test}-{}.jsonl",
+            std::process::id()
+        ));
+        let recording: String = lines.iter().map(|line| format!("{line}\n")).collect();
+        std::fs::write(&path, recording).unwrap();
+        Replay::open(&path, max_message_bytes).unwrap()
+    }
+
+    fn command(cmd_id: &str) -> Value {
+        json!({ "type": "modeling_cmd_req", "cmd": { "type": "volume" }, "cmd_id": cmd_id })
+    }
+
+    fn response(request_id: &str) -> Value {
+        json!({
+            "success": true,
+            "request_id": request_id,
+            "resp": { "type": "modeling", "data": { "modeling_response": { "type": "empty" } } },
+        })
+    }
+
+    fn text(msg: Option<WsMsg>) -> Value {
+        let Some(WsMsg::Text(text)) = msg else {
+            panic!("Expected a text message, not {msg:?}");
+        };
+        serde_json::from_str(&text).unwrap()
+    }
+
+    fn going_away() -> WsMsg {
+        WsMsg::Close(Some(CloseFrame {
+            code: CloseCode::Away,
+            reason: "The server is restarting for a deploy".into(),
+        }))
+    }
+
+    #[test]
+    fn plays_pings_and_closes_back_where_the_api_sent_them() {
+        let mut replay = replay(
+            "pings",
+            &[
+                json!({ "sent": command("recorded") }),
+                json!({ "ping": "heartbeat" }),
+                json!({ "received": response("recorded") }),
+                json!({ "closed": { "code": 1001, "reason": "The server is restarting for a deploy" } }),
+            ],
+            usize::MAX,
+        );
+        replay.send(&command("sent").to_string()).unwrap();
+        assert_eq!(
+            replay.next().unwrap(),
+            Some(WsMsg::Ping(b"heartbeat".to_vec()))
+        );
+        assert_eq!(text(replay.next().unwrap()), response("sent"));
+        assert_eq!(replay.next().unwrap(), Some(going_away()));
+    }
+
+    #[test]
+    fn plays_a_close_without_a_code_back_without_a_frame() {
+        let mut replay = replay(
+            "close",
+            &[json!({ "closed": { "code": 1005, "reason": "" } })],
+            usize::MAX,
+        );
+        assert_eq!(replay.next().unwrap(), Some(WsMsg::Close(None)));
+    }
+
+    #[test]
+    fn writes_down_pings_and_closes_as_they_are_read() {
+        let entry = |msg: WsMsg| serde_json::to_value(Entry::read(&msg)).unwrap();
+        assert_eq!(
+            entry(WsMsg::Ping(b"heartbeat".to_vec())),
+            json!({ "ping": "heartbeat" })
+        );
+        assert_eq!(
+            entry(going_away()),
+            json!({ "closed": { "code": 1001, "reason": "The server is restarting for a deploy" } })
+        );
+        assert_eq!(
+            entry(WsMsg::Close(None)),
+            json!({ "closed": { "code": 1005, "reason": "" } })
+        );
+        assert_eq!(entry(WsMsg::Pong(Vec::new())), Value::Null);
+    }
+
+    #[test]
+    fn goes_quiet_when_the_api_never_answered_a_command() {
+        let mut replay = replay(
+            "quiet",
+            &[
+                json!({ "sent": command("first") }),
+                json!({ "received": response("first") }),
+                json!({ "sent": command("stuck") }),
+            ],
+            usize::MAX,
+        );
+        replay.send(&command("first").to_string()).unwrap();
+        assert_eq!(text(replay.next().unwrap()), response("first"));
+        replay.send(&command("stuck").to_string()).unwrap();
+        assert_eq!(replay.next().unwrap(), None);
+    }
+
+    #[test]
+    fn is_out_of_date_when_the_run_waits_for_more_than_the_api_sent() {
+        let mut replay = replay(
+            "ends",
+            &[
+                json!({ "sent": command("recorded") }),
+                json!({ "received": response("recorded") }),
+            ],
+            usize::MAX,
+        );
+        replay.send(&command("sent").to_string()).unwrap();
+        replay.next().unwrap();
+        let Err(TransportError::OutOfDate(e)) = replay.next() else {
+            panic!("Expected the recording to be out of date");
+        };
+        assert_eq!(
+            e.to_string(),
+            format!(
+                "The recording {} is out of date: the run waited for a response, but the recording \
+                ends. Record it again (with --record) against the API",
+                replay.path.display()
+            )
+        );
+    }
+
+    #[test]
+    fn refuses_a_response_bigger_than_the_limit() {
+        let size = response("sent").to_string().len();
+        let mut replay = replay(
+            "big",
+            &[
+                json!({ "sent": command("recorded") }),
+                json!({ "received": response("recorded") }),
+            ],
+            size - 1,
+        );
+        replay.send(&command("sent").to_string()).unwrap();
+        assert!(matches!(
+            replay.next(),
+            Err(TransportError::TooBig { size: s, limit }) if s == size && limit == size - 1
+        ));
+    }
+}
@@ -0,0 +1,458 @@
+use crate::{
+    budget::{self, Optional},
+    camera::{self, CameraPreset},
+    session::ModelingSession,
+    sink,
+    snapshot::{self, CapturedPng, Output},
+    timings::Phase,
+};
+use color_eyre::{
+    eyre::{bail, eyre, WrapErr},
+    Result,
+};
+use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
+use kittycad::types::Point3D;
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    time::{Duration, Instant},
+};
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+/// How many frames may be captured but not yet saved. Once there are this many, capturing waits for
+/// the oldest to be saved, so a slow encoder holds up the camera rather than frames piling up in memory.
+const MAX_UNSAVED_FRAMES: usize = 4;
+
+/// How far a turntable has got, saved after every frame as "<output>.progress.json",
+/// so a run which dies partway can carry on from where it stopped with --resume-frames.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Progress {
+    /// How many frames the whole turntable has.
+    frames: u32,
+    /// Every frame saved so far, in order.
+    completed: Vec<Frame>,
+}
+
+/// One saved frame, and where the camera was for it.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Frame {
+    /// Counting from 0.
+    index: u32,
+    /// How far the camera had turned around the model, from where the first frame looked from.
+    angle_degrees: f64,
+    /// Which way the camera stood from the middle of the model, and which way was up.
+    eye: Point3D,
+    up: Point3D,
+    path: String,
+}
+
+impl Progress {
+    fn path(output: &Output) -> String {
+        format!("{}.progress.json", output.path)
+    }
+
+    fn load(path: &str) -> Result<Option<Self>> {
+        if !Path::new(path).exists() {
+            return Ok(None);
+        }
+        let text = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("Could not read the turntable's progress from {path}"))?;
+        let progress = serde_json::from_str(&text)
+            .wrap_err_with(|| format!("Could not parse the turntable's progress in {path}"))?;
+        Ok(Some(progress))
+    }
+
+    fn save(&self, path: &str) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        sink::write_atomically(Path::new(path), json.as_bytes())
+            .wrap_err_with(|| format!("Could not save the turntable's progress to {path}"))
+    }
+}
+
+/// How the camera frames the model as it turns.
+#[derive(Clone, Debug)]
+pub struct Framing {
+    /// Zoom to fit each frame on its own, rather than standing back far enough for every frame.
+    pub per_frame_fit: bool,
+    /// Room to leave around the model, as a fraction of its size.
+    pub margin: f64,
+}
+
+/// Parses --turntable-margin, which mustn't be negative.
+pub fn parse_margin(s: &str) -> Result<f64> {
+    let margin: f64 = s
+        .trim()
+        .parse()
+        .wrap_err_with(|| format!("{s:?} should be a number, e.g. 0.05 for 5%"))?;
+    if !(margin >= 0.0 && margin.is_finite()) {
+        bail!("The margin can't be negative, but it's {margin}");
+    }
+    Ok(margin)
+}
+
+/// Save `frames` snapshots of the objects, turning the camera around them (about Z) a little further
+/// each time, starting from the session's camera preset. Each frame is saved to the output,
+/// with its number added to its name, e.g. "model-007.png".
+///
+/// The camera stays the same distance away, far enough back for the model to fit at every angle,
+/// with `framing`'s margin around it (see `camera::turntable_framing`). With `framing.per_frame_fit`,
+/// each frame is zoomed to fit on its own instead.
+///
+/// Progress is saved after each frame. If `resume` is true, and an earlier run's progress is there,
+/// frames it saved are skipped, as long as their files are still there and aren't empty,
+/// and the turntable carries on from the first one which isn't.
+pub async fn capture(
+    session: &mut ModelingSession,
+    object_ids: &[Uuid],
+    output: &Output,
+    frames: u32,
+    resume: bool,
+    framing: &Framing,
+) -> Result<()> {
+    let Some(bbox) = session.bounding_box(object_ids) else {
+        bail!("Can't tell where the model is, so can't turn the camera around it");
+    };
+    let progress_path = Progress::path(output);
+    let mut progress = Progress {
+        frames,
+        completed: Vec::new(),
+    };
+    if resume {
+        if let Some(saved) = Progress::load(&progress_path)? {
+            if saved.frames != frames {
+                bail!(
+                    "{progress_path} is the progress of a {}-frame turntable, but this one has {frames} frames",
+                    saved.frames
+                );
+            }
+            // Only skip the frames saved before the first one which is missing,
+            // so the frames which are kept are all in a row.
+            for frame in saved.completed {
+                if frame.index != progress.completed.len() as u32 || !is_saved(&frame.path) {
+                    break;
+                }
+                progress.completed.push(frame);
+            }
+            say!(
+                "Resuming the turntable from frame {} of {frames}",
+                progress.completed.len() + 1
+            );
+        }
+    }
+
+    // Frames are encoded on other threads while the next ones are captured,
+    // and saved in order as they're ready.
+    let first = progress.completed.len() as u32;
+    let mut encoding = InOrder::new(first);
+    let start = session.camera_preset().clone();
+    for index in first..frames {
+        if let Err(e) = session.check_cancelled() {
+            let saved = save_encoded(session, &mut encoding, &mut progress, &progress_path).await;
+            session.set_camera_preset(start);
+            session.forget_framing();
+            saved?;
+            return Err(e.wrap_err(format!(
+                "Saved {index} of {frames} frames of the turntable. Use --resume-frames to save the rest"
+            )));
+        }
+        // Always save at least one frame, so there's something to look at.
+        let what = format!("turntable frames {} to {frames}", index + 1);
+        if index > 0 && !budget::allows(Optional::TurntableFrames, &what)? {
+            save_encoded(session, &mut encoding, &mut progress, &progress_path).await?;
+            session.set_camera_preset(start);
+            session.forget_framing();
+            say!("Saved {index} of {frames} frames of the turntable. Use --resume-frames to save the rest");
+            return Ok(());
+        }
+        // Make room for the frame.
+        while encoding.len() >= MAX_UNSAVED_FRAMES {
+            let (frame, captured, image) = wait_for_next(session, &mut encoding).await?;
+            save_frame(
+                session,
+                frame,
+                captured,
+                image,
+                &mut progress,
+                &progress_path,
+            )
+            .await?;
+        }
+        let angle_degrees = 360.0 * f64::from(index) / f64::from(frames);
+        let preset = CameraPreset {
+            eye: turn(&start.eye, angle_degrees),
+            up: turn(&start.up, angle_degrees),
+        };
+        session.set_camera_preset(preset.clone());
+        if framing.per_frame_fit {
+            camera::zoom_to_fit(session, &bbox).await?;
+        } else {
+            let camera = camera::turntable_framing(&bbox, &preset, framing.margin);
+            camera::look_at(session, &bbox, camera).await?;
+        }
+        let frame_output = output.with_suffix(&format!("-{index:03}"));
+        let previous = session.enter_phase(Phase::Save);
+        let captured = snapshot::capture_png(session, &frame_output).await?;
+        session.enter_phase(previous);
+        let frame = Frame {
+            index,
+            angle_degrees,
+            eye: preset.eye,
+            up: preset.up,
+            path: frame_output.path,
+        };
+        encoding.spawn(move || {
+            let image = captured.encode();
+            (frame, captured, image)
+        });
+        // Save whatever's already been encoded, without waiting.
+        while let Some(ready) = encoding.next_ready() {
+            let (frame, captured, image) = ready?;
+            save_frame(
+                session,
+                frame,
+                captured,
+                image,
+                &mut progress,
+                &progress_path,
+            )
+            .await?;
+        }
+    }
+    save_encoded(session, &mut encoding, &mut progress, &progress_path).await?;
+    session.set_camera_preset(start);
+    session.forget_framing();
+    say!("Saved all {frames} frames of the turntable");
+    // It's finished, so a later run starts a new one.
+    std::fs::remove_file(&progress_path)
+        .wrap_err_with(|| format!("Could not remove {progress_path}"))?;
+    Ok(())
+}
+
+/// A frame, its snapshot, and the snapshot encoded, if it could be.
+type Encoded = (Frame, CapturedPng, Result<Vec<u8>>);
+
+/// Wait for the next frame in order to be encoded, counting the wait as saving.
+async fn wait_for_next(
+    session: &mut ModelingSession,
+    encoding: &mut InOrder<Encoded>,
+) -> Result<Encoded> {
+    let previous = session.enter_phase(Phase::Save);
+    let next = encoding.next().await;
+    session.enter_phase(previous);
+    next?.ok_or_else(|| eyre!("There are no frames being encoded"))
+}
+
+/// Save a frame which has been encoded, and the turntable's progress.
+async fn save_frame(
+    session: &mut ModelingSession,
+    frame: Frame,
+    captured: CapturedPng,
+    image: Result<Vec<u8>>,
+    progress: &mut Progress,
+    progress_path: &str,
+) -> Result<()> {
+    let previous = session.enter_phase(Phase::Save);
+    captured.save(session, image).await?;
+    session.enter_phase(previous);
+    progress.completed.push(frame);
+    progress.save(progress_path)
+}
+
+/// Wait for every frame being encoded, and save them, in order. Then count how much of the encoding
+/// happened while the session was busy with something else.
+async fn save_encoded(
+    session: &mut ModelingSession,
+    encoding: &mut InOrder<Encoded>,
+    progress: &mut Progress,
+    progress_path: &str,
+) -> Result<()> {
+    while !encoding.is_empty() {
+        let (frame, captured, image) = wait_for_next(session, encoding).await?;
+        save_frame(session, frame, captured, image, progress, progress_path).await?;
+    }
+    session.add_overlapped(encoding.overlapped());
+    Ok(())
+}
+
+/// Jobs run on other threads, whose results are handed back in the order the jobs were started,
+/// however their threads finish.
+struct InOrder<T> {
+    running: FuturesUnordered<JoinHandle<(u32, T, Duration)>>,
+    /// Results which finished before one started earlier, by the index they were started with.
+    finished: BTreeMap<u32, T>,
+    /// The index of the next job to start, and of the next result to hand back.
+    next_started: u32,
+    next_handed_back: u32,
+    /// How long the jobs took, all told, and how long was spent waiting for them.
+    busy: Duration,
+    waited: Duration,
+}
+
+impl<T: Send + 'static> InOrder<T> {
+    /// Number the jobs from `first`.
+    fn new(first: u32) -> Self {
+        Self {
+            running: FuturesUnordered::new(),
+            finished: BTreeMap::new(),
+            next_started: first,
+            next_handed_back: first,
+            busy: Duration::ZERO,
+            waited: Duration::ZERO,
+        }
+    }
+
+    /// How many jobs have been started whose results haven't been handed back yet.
+    fn len(&self) -> usize {
+        (self.next_started - self.next_handed_back) as usize
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Start a job on a thread of its own (from tokio's pool for blocking work).
+    fn spawn(&mut self, job: impl FnOnce() -> T + Send + 'static) {
+        let index = self.next_started;
+        self.next_started += 1;
+        self.running.push(tokio::task::spawn_blocking(move || {
+            let started = Instant::now();
+            let result = job();
+            (index, result, started.elapsed())
+        }));
+    }
+
+    /// The next result in order, if it's ready now.
+    fn next_ready(&mut self) -> Option<Result<T>> {
+        loop {
+            if let Some(result) = self.finished.remove(&self.next_handed_back) {
+                self.next_handed_back += 1;
+                return Some(Ok(result));
+            }
+            match self.running.next().now_or_never() {
+                Some(Some(finished)) => {
+                    if let Err(e) = self.finish(finished) {
+                        return Some(Err(e));
+                    }
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    /// The next result in order, waiting for it if need be. None if no jobs are left.
+    async fn next(&mut self) -> Result<Option<T>> {
+        let waiting_since = Instant::now();
+        let next = loop {
+            if let Some(result) = self.finished.remove(&self.next_handed_back) {
+                self.next_handed_back += 1;
+                break Some(result);
+            }
+            match self.running.next().await {
+                Some(finished) => self.finish(finished)?,
+                None => break None,
+            }
+        };
+        self.waited += waiting_since.elapsed();
+        Ok(next)
+    }
+
+    /// Put a finished job's result aside until it's its turn.
+    fn finish(
+        &mut self,
+        finished: std::result::Result<(u32, T, Duration), tokio::task::JoinError>,
+    ) -> Result<()> {
+        let (index, result, took) = finished.wrap_err("A frame couldn't be encoded")?;
+        self.busy += took;
+        self.finished.insert(index, result);
+        Ok(())
+    }
+
+    /// How much of the time the jobs took wasn't spent waiting for them.
+    fn overlapped(&self) -> Duration {
+        self.busy.saturating_sub(self.waited)
+    }
+}
+
+/// Is a frame's file there, with something in it?
+fn is_saved(path: &str) -> bool {
+    std::fs::metadata(path).is_ok_and(|meta| meta.len() > 0)
+}
+
+/// Turn a direction around the Z axis, anticlockwise looking down.
+fn turn(p: &Point3D, degrees: f64) -> Point3D {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    Point3D {
+        x: p.x * cos - p.y * sin,
+        y: p.x * sin + p.y * cos,
+        z: p.z,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    fn run<T>(future: impl std::future::Future<Output = T>) -> T {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn hands_results_back_in_the_order_the_jobs_started() {
+        let handed_back = run(async {
+            let mut jobs = InOrder::new(3);
+            // The later a job starts, the sooner it finishes.
+            for delay in [40, 5, 30, 0, 20, 10, 35, 15] {
+                let index = jobs.next_started;
+                jobs.spawn(move || {
+                    std::thread::sleep(Duration::from_millis(delay));
+                    index
+                });
+            }
+            let mut handed_back = Vec::new();
+            while let Some(index) = jobs.next().await.unwrap() {
+                handed_back.push(index);
+            }
+            assert!(jobs.is_empty());
+            handed_back
+        });
+        assert_eq!(handed_back, (3..11).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn finished_jobs_count_as_unsaved_until_the_ones_before_them_are_handed_back() {
+        run(async {
+            let mut jobs = InOrder::new(0);
+            let (release, released) = mpsc::channel::<()>();
+            jobs.spawn(move || {
+                released.recv().unwrap();
+                0
+            });
+            for index in 1..MAX_UNSAVED_FRAMES as u32 {
+                jobs.spawn(move || index);
+            }
+            while jobs.finished.len() < MAX_UNSAVED_FRAMES - 1 {
+                assert!(jobs.next_ready().is_none());
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+            // Everything after the first is ready, but nothing can be saved before it is,
+            // so there's no room for another frame until the first is encoded.
+            assert!(jobs.next_ready().is_none());
+            assert_eq!(jobs.len(), MAX_UNSAVED_FRAMES);
+
+            release.send(()).unwrap();
+            assert_eq!(jobs.next().await.unwrap(), Some(0));
+            assert_eq!(jobs.len(), MAX_UNSAVED_FRAMES - 1);
+            for index in 1..MAX_UNSAVED_FRAMES as u32 {
+                assert_eq!(jobs.next_ready().unwrap().unwrap(), index);
+            }
+            assert!(jobs.is_empty());
+            assert!(jobs.next_ready().is_none());
+        });
+    }
+}
@@ -0,0 +1,256 @@
+use color_eyre::{
+    eyre::{bail, eyre},
+    Report, Result,
+};
+use kittycad::types::{UnitArea, UnitDensity, UnitLength, UnitMass, UnitVolume};
+use std::str::FromStr;
+
+/// The unit that coordinates sent to the API are measured in.
+/// The API itself is unitless, so queries like Volume ask which unit we meant.
+pub const SCENE_UNIT: UnitLength = UnitLength::Mm;
+
+/// How many millimeters are in one of the given unit.
+pub fn mm_per_length(unit: &UnitLength) -> f64 {
+    match unit {
+        UnitLength::Mm => 1.0,
+        UnitLength::Cm => 10.0,
+        UnitLength::M => 1000.0,
+        UnitLength::In => 25.4,
+        UnitLength::Ft => 304.8,
+        UnitLength::Yd => 914.4,
+    }
+}
+
+/// The suffixes a length can be written with, e.g. "25mm".
+const LENGTH_SUFFIXES: &str = "mm, cm, m, in, ft or yd";
+
+/// A length, in the unit it was written in, e.g. "25mm", "2.5cm" or "1in".
+/// A bare number is in scene units.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dimension {
+    pub value: f64,
+    pub unit: UnitLength,
+}
+
+impl FromStr for Dimension {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let usage = || {
+            eyre!("{s:?} should be a length: a number, optionally followed by {LENGTH_SUFFIXES}, e.g. 25mm")
+        };
+        let s = s.trim();
+        let number_len = s.trim_end_matches(|c: char| c.is_ascii_alphabetic()).len();
+        let (number, suffix) = s.split_at(number_len);
+        let value: f64 = number.trim().parse().map_err(|_| usage())?;
+        if !value.is_finite() {
+            return Err(usage());
+        }
+        let unit = match suffix {
+            "" => SCENE_UNIT,
+            suffix => suffix.parse().map_err(|_| usage())?,
+        };
+        Ok(Self { value, unit })
+    }
+}
+
+impl Dimension {
+    /// The length in scene units, which is what coordinates sent to the API are in.
+    pub fn in_scene_units(&self) -> f64 {
+        if self.unit == SCENE_UNIT {
+            return self.value;
+        }
+        self.value * mm_per_length(&self.unit) / mm_per_length(&SCENE_UNIT)
+    }
+}
+
+/// Parses a length, e.g. "25mm" or "1in", into scene units. A bare number is already in scene units,
+/// so different options can be given in different units.
+pub fn parse_length(s: &str) -> Result<f64> {
+    Ok(s.parse::<Dimension>()?.in_scene_units())
+}
+
+/// Parses a length like `parse_length`, which must be more than zero, e.g. a tolerance.
+pub fn parse_positive_length(s: &str) -> Result<f64> {
+    let length = parse_length(s)?;
+    if length <= 0.0 {
+        bail!("The length must be positive, not {s}");
+    }
+    Ok(length)
+}
+
+/// How many square millimeters are in one of the given unit.
+pub fn square_mm_per_area(unit: &UnitArea) -> f64 {
+    match unit {
+        UnitArea::Mm2 => 1.0,
+        UnitArea::Cm2 => 1e2,
+        UnitArea::Dm2 => 1e4,
+        UnitArea::M2 => 1e6,
+        UnitArea::Km2 => 1e12,
+        UnitArea::In2 => 25.4f64.powi(2),
+        UnitArea::Ft2 => 304.8f64.powi(2),
+        UnitArea::Yd2 => 914.4f64.powi(2),
+    }
+}
+
+/// How many cubic millimeters are in one of the given unit.
+pub fn cubic_mm_per_volume(unit: &UnitVolume) -> f64 {
+    match unit {
+        UnitVolume::Cm3 | UnitVolume::Ml => 1e3,
+        UnitVolume::L => 1e6,
+        UnitVolume::M3 => 1e9,
+        UnitVolume::In3 => 25.4f64.powi(3),
+        UnitVolume::Ft3 => 304.8f64.powi(3),
+        UnitVolume::Yd3 => 914.4f64.powi(3),
+        UnitVolume::Usfloz => 29_573.529_562_5,
+        UnitVolume::Usgal => 3_785_411.784,
+    }
+}
+
+/// Convert a volume measured in cubic scene units into the given unit.
+pub fn volume_from_scene_units(volume: f64, unit: &UnitVolume) -> f64 {
+    volume * mm_per_length(&SCENE_UNIT).powi(3) / cubic_mm_per_volume(unit)
+}
+
+/// How many grams are in one of the given unit.
+pub fn grams_per_mass(unit: &UnitMass) -> f64 {
+    match unit {
+        UnitMass::G => 1.0,
+        UnitMass::Kg => 1000.0,
+        UnitMass::Lb => 453.592_37,
+    }
+}
+
+/// How many kg/m³ are in one of the given unit.
+pub fn kg_m3_per_density(unit: &UnitDensity) -> f64 {
+    match unit {
+        UnitDensity::KgM3 => 1.0,
+        // One pound per cubic foot, converted to kilograms per cubic meter.
+        UnitDensity::LbFt3 => 0.453_592_37 / 0.304_8f64.powi(3),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() <= 1e-9 * b.abs(), "{a} isn't {b}");
+    }
+
+    #[test]
+    fn parses_lengths_in_each_unit() {
+        for (text, mm) in [
+            ("25mm", 25.0),
+            ("2.5cm", 25.0),
+            ("1in", 25.4),
+            ("0.5ft", 152.4),
+            ("2yd", 1828.8),
+            ("0.25m", 250.0),
+            ("-5mm", -5.0),
+            (" 3 cm ", 30.0),
+            ("1e2mm", 100.0),
+        ] {
+            // Exactly, for the common factors.
+            assert_eq!(parse_length(text).unwrap(), mm, "{text}");
+        }
+    }
+
+    #[test]
+    fn parses_a_bare_number_in_scene_units() {
+        assert_eq!(parse_length("7").unwrap(), 7.0);
+        assert_eq!(
+            "7".parse::<Dimension>().unwrap(),
+            Dimension {
+                value: 7.0,
+                unit: SCENE_UNIT
+            }
+        );
+        assert_eq!(
+            "2in".parse::<Dimension>().unwrap(),
+            Dimension {
+                value: 2.0,
+                unit: UnitLength::In
+            }
+        );
+    }
+
+    #[test]
+    fn says_which_suffixes_a_length_can_have() {
+        for text in ["5km", "five", "mm", "", "inf", "NaN", "5 m m"] {
+            assert_eq!(
+                parse_length(text).unwrap_err().to_string(),
+                format!("{text:?} should be a length: a number, optionally followed by mm, cm, m, in, ft or yd, e.g. 25mm"),
+            );
+        }
+    }
+
+    #[test]
+    fn parses_positive_lengths() {
+        assert_eq!(parse_positive_length("0.1mm").unwrap(), 0.1);
+        for text in ["0", "-1in"] {
+            assert_eq!(
+                parse_positive_length(text).unwrap_err().to_string(),
+                format!("The length must be positive, not {text}")
+            );
+        }
+    }
+
+    #[test]
+    fn converts_areas_and_volumes_as_the_lengths_they_are_made_of() {
+        for (area, length) in [
+            (UnitArea::Mm2, UnitLength::Mm),
+            (UnitArea::Cm2, UnitLength::Cm),
+            (UnitArea::M2, UnitLength::M),
+            (UnitArea::In2, UnitLength::In),
+            (UnitArea::Ft2, UnitLength::Ft),
+            (UnitArea::Yd2, UnitLength::Yd),
+        ] {
+            assert_close(square_mm_per_area(&area), mm_per_length(&length).powi(2));
+        }
+        for (volume, length) in [
+            (UnitVolume::Cm3, UnitLength::Cm),
+            (UnitVolume::M3, UnitLength::M),
+            (UnitVolume::In3, UnitLength::In),
+            (UnitVolume::Ft3, UnitLength::Ft),
+            (UnitVolume::Yd3, UnitLength::Yd),
+        ] {
+            assert_close(cubic_mm_per_volume(&volume), mm_per_length(&length).powi(3));
+        }
+        assert_eq!(
+            cubic_mm_per_volume(&UnitVolume::Ml),
+            cubic_mm_per_volume(&UnitVolume::Cm3)
+        );
+        // A US gallon is 231 cubic inches, and 128 fluid ounces.
+        assert_close(
+            cubic_mm_per_volume(&UnitVolume::Usgal),
+            231.0 * 25.4f64.powi(3),
+        );
+        assert_close(
+            cubic_mm_per_volume(&UnitVolume::Usgal),
+            128.0 * cubic_mm_per_volume(&UnitVolume::Usfloz),
+        );
+        assert_eq!(volume_from_scene_units(2e6, &UnitVolume::L), 2.0);
+    }
+
+    #[test]
+    fn converts_masses_to_grams() {
+        assert_eq!(grams_per_mass(&UnitMass::G), 1.0);
+        assert_eq!(grams_per_mass(&UnitMass::Kg), 1000.0);
+        // A pound is defined as exactly 0.45359237 kg.
+        assert_close(grams_per_mass(&UnitMass::Lb), 453.59237);
+    }
+
+    #[test]
+    fn converts_densities_to_kg_per_cubic_meter() {
+        assert_eq!(kg_m3_per_density(&UnitDensity::KgM3), 1.0);
+        // 1 lb/ft³ is 16.018463... kg/m³.
+        assert_close(
+            kg_m3_per_density(&UnitDensity::LbFt3),
+            16.018_463_373_960_14,
+        );
+        // Water is 62.43 lb/ft³, or about 1000 kg/m³.
+        let water = 62.427_96 * kg_m3_per_density(&UnitDensity::LbFt3);
+        assert!((water - 1000.0).abs() < 0.01, "{water}");
+    }
+}
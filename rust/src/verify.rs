@@ -0,0 +1,683 @@
+use crate::geometry::BoundingBox;
+use color_eyre::{
+    eyre::{bail, eyre, WrapErr},
+    Result,
+};
+use kittycad::types::Point3D;
+use std::collections::HashMap;
+
+/// What an exported mesh contains, found by reading it back.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct MeshStats {
+    /// How many triangles it has. Polygons with more sides count as the triangles they'd be split into.
+    pub triangles: u64,
+    /// How many distinct vertices it has.
+    pub vertices: u64,
+    /// The smallest box containing every vertex which is a real point. None if there aren't any.
+    pub bounding_box: Option<BoundingBox>,
+    /// How many vertices have a coordinate which is NaN or infinite.
+    pub bad_vertices: u64,
+    /// How many edges only have one face on them, i.e. border a hole, so the mesh isn't watertight.
+    pub open_edges: u64,
+    /// How many edges have more than two faces on them, so the mesh isn't manifold.
+    pub non_manifold_edges: u64,
+    /// Whether the mesh is closed: every edge has exactly two faces on it, so it has an inside,
+    /// and a volume. An extrusion without caps isn't.
+    pub closed: bool,
+}
+
+impl MeshStats {
+    /// What's obviously wrong with the mesh, if anything: it's empty, or some of its vertices aren't points.
+    /// Open and non-manifold edges are only hints, because some models have them on purpose.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.triangles == 0 {
+            problems.push("it has no triangles".to_owned());
+        }
+        if self.bad_vertices > 0 {
+            problems.push(format!(
+                "{} of its vertices are NaN or infinite",
+                self.bad_vertices
+            ));
+        }
+        problems
+    }
+}
+
+impl std::fmt::Display for MeshStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} triangles, {} vertices",
+            self.triangles, self.vertices
+        )?;
+        if let Some(BoundingBox { min, max }) = &self.bounding_box {
+            write!(
+                f,
+                ", from ({:.3}, {:.3}, {:.3}) to ({:.3}, {:.3}, {:.3})",
+                min.x, min.y, min.z, max.x, max.y, max.z
+            )?;
+        }
+        if self.closed {
+            write!(f, ", closed")?;
+        } else {
+            write!(f, ", not closed")?;
+        }
+        if self.open_edges > 0 {
+            write!(f, ", {} open edges", self.open_edges)?;
+        }
+        if self.non_manifold_edges > 0 {
+            write!(f, ", {} non-manifold edges", self.non_manifold_edges)?;
+        }
+        Ok(())
+    }
+}
+
+/// Read back an exported file, given its format (e.g. "stl"), and describe the mesh in it.
+/// Returns None for formats which aren't meshes (e.g. step), or aren't read back yet.
+/// Fails if the file is malformed, e.g. cut short.
+pub fn stats(format: &str, contents: &[u8]) -> Result<Option<MeshStats>> {
+    let mesh = match format {
+        "stl" => read_stl(contents)?,
+        "obj" => read_obj(contents)?,
+        "ply" => read_ply(contents)?,
+        _ => return Ok(None),
+    };
+    Ok(Some(mesh.stats()))
+}
+
+/// A mesh's vertices and faces, as far as counting them goes.
+#[derive(Default)]
+struct Mesh {
+    vertices: Vec<[f64; 3]>,
+    triangles: u64,
+    /// How many faces each edge (its vertices' indices, smallest first) is on.
+    edges: HashMap<(usize, usize), u32>,
+}
+
+impl Mesh {
+    fn add_vertex(&mut self, vertex: [f64; 3]) {
+        self.vertices.push(vertex);
+    }
+
+    /// Add a face, given its vertices' indices, in order around it.
+    fn add_face(&mut self, face: &[usize]) -> Result<()> {
+        if face.len() < 3 {
+            bail!("A face has only {} vertices", face.len());
+        }
+        if let Some(&i) = face.iter().find(|&&i| i >= self.vertices.len()) {
+            bail!(
+                "A face refers to vertex {i}, but there are only {}",
+                self.vertices.len()
+            );
+        }
+        self.triangles += face.len() as u64 - 2;
+        for (i, &a) in face.iter().enumerate() {
+            let b = face[(i + 1) % face.len()];
+            if a != b {
+                *self.edges.entry((a.min(b), a.max(b))).or_default() += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn stats(&self) -> MeshStats {
+        let is_real = |v: &&[f64; 3]| v.iter().all(|c| c.is_finite());
+        let bad_vertices = self.vertices.iter().filter(|v| !is_real(v)).count() as u64;
+        let points: Vec<_> = self
+            .vertices
+            .iter()
+            .filter(is_real)
+            .map(|&[x, y, z]| Point3D { x, y, z })
+            .collect();
+        let open_edges = self.edges.values().filter(|&&n| n == 1).count() as u64;
+        let non_manifold_edges = self.edges.values().filter(|&&n| n > 2).count() as u64;
+        MeshStats {
+            triangles: self.triangles,
+            vertices: self.vertices.len() as u64,
+            bounding_box: BoundingBox::from_points(&points),
+            bad_vertices,
+            open_edges,
+            non_manifold_edges,
+            closed: self.triangles > 0 && open_edges == 0 && non_manifold_edges == 0,
+        }
+    }
+}
+
+/// STL files list each triangle's corners, rather than sharing vertices between triangles,
+/// so corners in exactly the same place are treated as one vertex.
+#[derive(Default)]
+struct Welder {
+    mesh: Mesh,
+    seen: HashMap<[u64; 3], usize>,
+}
+
+impl Welder {
+    fn add_triangle(&mut self, corners: [[f64; 3]; 3]) -> Result<()> {
+        let mut face = [0; 3];
+        for (i, corner) in corners.into_iter().enumerate() {
+            let key = corner.map(f64::to_bits);
+            face[i] = match self.seen.get(&key) {
+                Some(&index) => index,
+                None => {
+                    let index = self.mesh.vertices.len();
+                    self.mesh.add_vertex(corner);
+                    self.seen.insert(key, index);
+                    index
+                }
+            };
+        }
+        self.mesh.add_face(&face)
+    }
+}
+
+/// Read an STL file, binary or ASCII.
+fn read_stl(contents: &[u8]) -> Result<Mesh> {
+    // A binary STL's header may start with "solid" too, so only its size tells them apart for sure.
+    let binary_size = |count: u32| 84 + 50 * count as usize;
+    let count = contents
+        .get(80..84)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+    match count {
+        Some(count) if contents.len() == binary_size(count) => read_binary_stl(contents, count),
+        _ if contents.starts_with(b"solid") => read_ascii_stl(contents),
+        Some(count) => bail!(
+            "The STL file says it has {count} triangles, which would take {} bytes, but it's {} bytes",
+            binary_size(count),
+            contents.len()
+        ),
+        None => bail!(
+            "The STL file is only {} bytes, too short for even an empty binary STL",
+            contents.len()
+        ),
+    }
+}
+
+fn read_binary_stl(contents: &[u8], count: u32) -> Result<Mesh> {
+    let mut welder = Welder::default();
+    for record in contents[84..].chunks_exact(50).take(count as usize) {
+        // Each record is a normal, three corners, and two spare bytes.
+        let float = |at: usize| {
+            f64::from(f32::from_le_bytes([
+                record[at],
+                record[at + 1],
+                record[at + 2],
+                record[at + 3],
+            ]))
+        };
+        let corner = |i: usize| {
+            let at = 12 + 12 * i;
+            [float(at), float(at + 4), float(at + 8)]
+        };
+        welder.add_triangle([corner(0), corner(1), corner(2)])?;
+    }
+    Ok(welder.mesh)
+}
+
+fn read_ascii_stl(contents: &[u8]) -> Result<Mesh> {
+    let text = std::str::from_utf8(contents).wrap_err("The ASCII STL file isn't valid UTF-8")?;
+    let mut welder = Welder::default();
+    let mut corners = Vec::with_capacity(3);
+    for (i, line) in text.lines().enumerate() {
+        let mut words = line.split_whitespace();
+        if words.next() != Some("vertex") {
+            continue;
+        }
+        corners
+            .push(read_point(words).wrap_err_with(|| format!("Line {} of the STL file", i + 1))?);
+        if let Ok(triangle) = <[[f64; 3]; 3]>::try_from(corners.as_slice()) {
+            welder.add_triangle(triangle)?;
+            corners.clear();
+        }
+    }
+    if !corners.is_empty() {
+        bail!("The STL file ends partway through a triangle");
+    }
+    Ok(welder.mesh)
+}
+
+/// Read an OBJ file's vertices ("v") and faces ("f"). Everything else, e.g. normals, is skipped.
+fn read_obj(contents: &[u8]) -> Result<Mesh> {
+    let text = std::str::from_utf8(contents).wrap_err("The OBJ file isn't valid UTF-8")?;
+    let mut mesh = Mesh::default();
+    for (i, line) in text.lines().enumerate() {
+        let at = || format!("Line {} of the OBJ file", i + 1);
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("v") => mesh.add_vertex(read_point(words).wrap_err_with(at)?),
+            Some("f") => {
+                // Each corner is "v", "v/vt", "v//vn" or "v/vt/vn". Indices count from 1,
+                // or back from the latest vertex if they're negative.
+                let face = words
+                    .map(|corner| {
+                        let index = corner.split('/').next().unwrap_or_default();
+                        let index: i64 = index
+                            .parse()
+                            .wrap_err_with(|| format!("{corner:?} isn't a vertex index"))?;
+                        let count = mesh.vertices.len() as i64;
+                        match index {
+                            1.. => Ok(index as usize - 1),
+                            ..=-1 if count + index >= 0 => Ok((count + index) as usize),
+                            _ => Err(eyre!("{index} isn't a vertex")),
+                        }
+                    })
+                    .collect::<Result<Vec<_>>>()
+                    .wrap_err_with(at)?;
+                mesh.add_face(&face).wrap_err_with(at)?;
+            }
+            _ => {}
+        }
+    }
+    Ok(mesh)
+}
+
+/// Read the next three words as a point.
+fn read_point<'a>(mut words: impl Iterator<Item = &'a str>) -> Result<[f64; 3]> {
+    let mut point = [0.0; 3];
+    for c in &mut point {
+        let word = words
+            .next()
+            .ok_or_else(|| eyre!("A point needs 3 coordinates"))?;
+        *c = word
+            .parse()
+            .wrap_err_with(|| format!("{word:?} isn't a number"))?;
+    }
+    Ok(point)
+}
+
+/// One of a PLY element's properties.
+enum Property {
+    Scalar {
+        kind: String,
+        name: String,
+    },
+    List {
+        count: String,
+        item: String,
+        name: String,
+    },
+}
+
+/// A kind of element in a PLY file, e.g. "vertex", and how many of them there are.
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// How a PLY file's elements are written after its header.
+enum PlyBody<'a> {
+    Ascii(std::str::SplitWhitespace<'a>),
+    Binary { data: &'a [u8], big_endian: bool },
+}
+
+impl PlyBody<'_> {
+    /// Read one number of a type the header named, e.g. "float" or "uchar".
+    fn read(&mut self, kind: &str) -> Result<f64> {
+        match self {
+            PlyBody::Ascii(words) => {
+                let word = words
+                    .next()
+                    .ok_or_else(|| eyre!("The PLY file ends early"))?;
+                word.parse()
+                    .wrap_err_with(|| format!("{word:?} in the PLY file isn't a number"))
+            }
+            PlyBody::Binary { data, big_endian } => {
+                let size = match kind {
+                    "char" | "int8" | "uchar" | "uint8" => 1,
+                    "short" | "int16" | "ushort" | "uint16" => 2,
+                    "int" | "int32" | "uint" | "uint32" | "float" | "float32" => 4,
+                    "double" | "float64" => 8,
+                    _ => bail!("The PLY file has a property of an unknown type, {kind}"),
+                };
+                if data.len() < size {
+                    bail!("The PLY file ends early");
+                }
+                let (bytes, rest) = data.split_at(size);
+                *data = rest;
+                let mut b = [0; 8];
+                b[..size].copy_from_slice(bytes);
+                if *big_endian {
+                    b[..size].reverse();
+                }
+                Ok(match kind {
+                    "char" | "int8" => f64::from(b[0] as i8),
+                    "uchar" | "uint8" => f64::from(b[0]),
+                    "short" | "int16" => f64::from(i16::from_le_bytes([b[0], b[1]])),
+                    "ushort" | "uint16" => f64::from(u16::from_le_bytes([b[0], b[1]])),
+                    "int" | "int32" => f64::from(i32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+                    "uint" | "uint32" => f64::from(u32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+                    "float" | "float32" => f64::from(f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+                    _ => f64::from_le_bytes(b),
+                })
+            }
+        }
+    }
+}
+
+/// Read a PLY file, ASCII or binary: its vertices' x, y and z, and its faces' vertex indices.
+fn read_ply(contents: &[u8]) -> Result<Mesh> {
+    const END: &[u8] = b"end_header";
+    let end = contents
+        .windows(END.len())
+        .position(|w| w == END)
+        .ok_or_else(|| eyre!("The PLY file has no end_header"))?;
+    let header = std::str::from_utf8(&contents[..end])
+        .wrap_err("The PLY file's header isn't valid UTF-8")?;
+    // The body starts on the line after end_header.
+    let body = contents[end..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map_or(&[][..], |newline| &contents[end + newline + 1..]);
+
+    let mut lines = header.lines();
+    if lines.next().map(str::trim) != Some("ply") {
+        bail!("The file doesn't start with \"ply\", so it isn't a PLY file");
+    }
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for line in lines {
+        let words: Vec<_> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["format", f, ..] => format = Some(f.to_string()),
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .wrap_err_with(|| format!("The PLY file's {name} count isn't a number"))?,
+                properties: Vec::new(),
+            }),
+            ["property", rest @ ..] => {
+                let Some(element) = elements.last_mut() else {
+                    bail!("The PLY file has a property before any element");
+                };
+                element.properties.push(match rest {
+                    ["list", count, item, name] => Property::List {
+                        count: count.to_string(),
+                        item: item.to_string(),
+                        name: name.to_string(),
+                    },
+                    [kind, name] => Property::Scalar {
+                        kind: kind.to_string(),
+                        name: name.to_string(),
+                    },
+                    _ => bail!("The PLY file has a property it can't read: {line:?}"),
+                });
+            }
+            _ => {}
+        }
+    }
+    let mut body = match format.as_deref() {
+        Some("ascii") => PlyBody::Ascii(
+            std::str::from_utf8(body)
+                .wrap_err("The ASCII PLY file isn't valid UTF-8")?
+                .split_whitespace(),
+        ),
+        Some("binary_little_endian") => PlyBody::Binary {
+            data: body,
+            big_endian: false,
+        },
+        Some("binary_big_endian") => PlyBody::Binary {
+            data: body,
+            big_endian: true,
+        },
+        Some(other) => bail!("The PLY file's format, {other}, isn't one PLY has"),
+        None => bail!("The PLY file's header doesn't say what format it's in"),
+    };
+
+    let mut mesh = Mesh::default();
+    for element in &elements {
+        for _ in 0..element.count {
+            let mut vertex = [0.0; 3];
+            let mut face = Vec::new();
+            for property in &element.properties {
+                match property {
+                    Property::Scalar { kind, name } => {
+                        let value = body.read(kind)?;
+                        match name.as_str() {
+                            "x" => vertex[0] = value,
+                            "y" => vertex[1] = value,
+                            "z" => vertex[2] = value,
+                            _ => {}
+                        }
+                    }
+                    Property::List { count, item, name } => {
+                        let n = body.read(count)? as usize;
+                        for _ in 0..n {
+                            let value = body.read(item)?;
+                            if name == "vertex_indices" || name == "vertex_index" {
+                                face.push(value as usize);
+                            }
+                        }
+                    }
+                }
+            }
+            match element.name.as_str() {
+                "vertex" => mesh.add_vertex(vertex),
+                "face" => mesh.add_face(&face)?,
+                _ => {}
+            }
+        }
+    }
+    Ok(mesh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A tetrahedron's corners, and its four faces, each wound the same way.
+    const CORNERS: [[f32; 3]; 4] = [
+        [0.0, 0.0, 0.0],
+        [1.0, 0.0, 0.0],
+        [0.0, 2.0, 0.0],
+        [0.0, 0.0, 3.0],
+    ];
+    const FACES: [[usize; 3]; 4] = [[0, 2, 1], [0, 1, 3], [0, 3, 2], [1, 2, 3]];
+
+    /// A binary STL of these triangles, which says it has `count` of them.
+    fn binary_stl(triangles: &[[usize; 3]], count: u32) -> Vec<u8> {
+        let mut stl = vec![b' '; 80];
+        stl.extend(count.to_le_bytes());
+        for triangle in triangles {
+            stl.extend([0; 12]);
+            for &corner in triangle {
+                stl.extend(CORNERS[corner].iter().flat_map(|c| c.to_le_bytes()));
+            }
+            stl.extend([0; 2]);
+        }
+        stl
+    }
+
+    fn ascii_stl(triangles: &[[usize; 3]]) -> String {
+        let mut stl = "solid tetrahedron\n".to_owned();
+        for triangle in triangles {
+            stl += "facet normal 0 0 0\nouter loop\n";
+            for &corner in triangle {
+                let [x, y, z] = CORNERS[corner];
+                stl += &format!("vertex {x} {y} {z}\n");
+            }
+            stl += "endloop\nendfacet\n";
+        }
+        stl + "endsolid tetrahedron\n"
+    }
+
+    fn ascii_ply(faces: &str) -> String {
+        let mut ply = "ply\nformat ascii 1.0\nelement vertex 4\nproperty float x\n\
+                       property float y\nproperty float z\nelement face 4\n\
+                       property list uchar int vertex_indices\nend_header\n"
+            .to_owned();
+        for [x, y, z] in CORNERS {
+            ply += &format!("{x} {y} {z}\n");
+        }
+        ply + faces
+    }
+
+    fn error(result: Result<impl std::any::Any>) -> String {
+        let Err(e) = result else {
+            panic!("Expected it to fail");
+        };
+        let reasons: Vec<_> = e.chain().map(ToString::to_string).collect();
+        reasons.join(": ")
+    }
+
+    fn assert_tetrahedron(stats: MeshStats) {
+        assert_eq!((stats.triangles, stats.vertices), (4, 4));
+        assert!(stats.closed, "{stats}");
+        assert_eq!((stats.open_edges, stats.non_manifold_edges), (0, 0));
+        let bbox = stats.bounding_box.clone().unwrap();
+        assert_eq!([bbox.max.x, bbox.max.y, bbox.max.z], [1.0, 2.0, 3.0]);
+        assert!(stats.problems().is_empty());
+    }
+
+    #[test]
+    fn reads_binary_and_ascii_stl() {
+        assert_tetrahedron(read_stl(&binary_stl(&FACES, 4)).unwrap().stats());
+        assert_tetrahedron(read_stl(ascii_stl(&FACES).as_bytes()).unwrap().stats());
+    }
+
+    #[test]
+    fn rejects_a_cut_off_binary_stl() {
+        let mut stl = binary_stl(&FACES, 4);
+        stl.truncate(stl.len() - 10);
+        assert_eq!(
+            error(read_stl(&stl)),
+            "The STL file says it has 4 triangles, which would take 284 bytes, but it's 274 bytes"
+        );
+        assert_eq!(
+            error(read_stl(&stl[..50])),
+            "The STL file is only 50 bytes, too short for even an empty binary STL"
+        );
+    }
+
+    #[test]
+    fn rejects_a_binary_stl_with_the_wrong_triangle_count() {
+        assert_eq!(
+            error(read_stl(&binary_stl(&FACES, 5))),
+            "The STL file says it has 5 triangles, which would take 334 bytes, but it's 284 bytes"
+        );
+    }
+
+    #[test]
+    fn rejects_an_ascii_stl_which_stops_partway() {
+        let mut stl = ascii_stl(&FACES);
+        stl.truncate(stl.rfind("vertex").unwrap());
+        assert_eq!(
+            error(read_stl(stl.as_bytes())),
+            "The STL file ends partway through a triangle"
+        );
+    }
+
+    #[test]
+    fn notices_an_open_mesh() {
+        let stats = read_stl(&binary_stl(&FACES[..3], 3)).unwrap().stats();
+        assert_eq!(stats.open_edges, 3);
+        assert!(!stats.closed);
+        let empty = read_stl(&binary_stl(&[], 0)).unwrap().stats();
+        assert_eq!(empty.problems(), ["it has no triangles"]);
+    }
+
+    #[test]
+    fn reads_obj_faces_however_they_refer_to_vertices() {
+        let obj = "# A tetrahedron\nv 0 0 0\nv 1 0 0\nv 0 2 0\nv 0 0 3\nvn 0 0 1\n\
+                   f 1 3 2\nf 1/1 2/2 4/4\nf -4//1 -1//1 -2//1\nf 2/1/1 3/1/1 4/1/1\n";
+        assert_tetrahedron(read_obj(obj.as_bytes()).unwrap().stats());
+    }
+
+    #[test]
+    fn counts_an_obj_polygon_as_triangles() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\n";
+        let stats = read_obj(obj.as_bytes()).unwrap().stats();
+        assert_eq!((stats.triangles, stats.open_edges), (2, 4));
+    }
+
+    #[test]
+    fn rejects_obj_faces_of_missing_vertices() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 9\n";
+        assert_eq!(
+            error(read_obj(obj.as_bytes())),
+            "Line 4 of the OBJ file: A face refers to vertex 8, but there are only 3"
+        );
+        let obj = "v 0 0 0\nf 1 -2 0\n";
+        assert_eq!(
+            error(read_obj(obj.as_bytes())),
+            "Line 2 of the OBJ file: -2 isn't a vertex"
+        );
+        let obj = "v 0 0\n";
+        assert_eq!(
+            error(read_obj(obj.as_bytes())),
+            "Line 1 of the OBJ file: A point needs 3 coordinates"
+        );
+    }
+
+    #[test]
+    fn reads_ascii_and_binary_ply() {
+        let faces = "3 0 2 1\n3 0 1 3\n3 0 3 2\n3 1 2 3\n";
+        assert_tetrahedron(read_ply(ascii_ply(faces).as_bytes()).unwrap().stats());
+
+        let mut ply = b"ply\nformat binary_big_endian 1.0\nelement vertex 4\nproperty float x\n\
+                        property float y\nproperty float z\nelement face 4\n\
+                        property list uchar ushort vertex_indices\nend_header\n"
+            .to_vec();
+        for corner in CORNERS {
+            ply.extend(corner.iter().flat_map(|c| c.to_be_bytes()));
+        }
+        for face in FACES {
+            ply.push(3);
+            ply.extend(face.iter().flat_map(|&i| (i as u16).to_be_bytes()));
+        }
+        assert_tetrahedron(read_ply(&ply).unwrap().stats());
+    }
+
+    #[test]
+    fn rejects_a_ply_face_with_a_short_list() {
+        // The last face says it has 3 vertices, but the file ends after 2.
+        let ply = ascii_ply("3 0 2 1\n3 0 1 3\n3 0 3 2\n3 1 2\n");
+        assert_eq!(error(read_ply(ply.as_bytes())), "The PLY file ends early");
+        let ply = ascii_ply("3 0 2 1\n3 0 1 3\n3 0 3 2\n2 1 2\n");
+        assert_eq!(
+            error(read_ply(ply.as_bytes())),
+            "A face has only 2 vertices"
+        );
+    }
+
+    #[test]
+    fn rejects_a_ply_without_a_header() {
+        assert_eq!(
+            error(read_ply(b"ply\nformat ascii 1.0\n")),
+            "The PLY file has no end_header"
+        );
+        assert_eq!(
+            error(read_ply(b"plywood\nend_header\n")),
+            "The file doesn't start with \"ply\", so it isn't a PLY file"
+        );
+        assert_eq!(
+            error(read_ply(b"ply\nformat ebcdic 1.0\nend_header\n")),
+            "The PLY file's format, ebcdic, isn't one PLY has"
+        );
+        assert_eq!(
+            error(read_ply(b"ply\nend_header\n")),
+            "The PLY file's header doesn't say what format it's in"
+        );
+    }
+
+    #[test]
+    fn counts_vertices_which_arent_points() {
+        let obj = "v 0 0 0\nv nan 0 0\nv 0 inf 0\nf 1 2 3\n";
+        let stats = read_obj(obj.as_bytes()).unwrap().stats();
+        assert_eq!(stats.bad_vertices, 2);
+        assert_eq!(stats.problems(), ["2 of its vertices are NaN or infinite"]);
+    }
+
+    #[test]
+    fn only_reads_meshes() {
+        assert!(stats("step", b"ISO-10303-21;").unwrap().is_none());
+        assert_eq!(
+            stats("stl", &binary_stl(&FACES, 4))
+                .unwrap()
+                .unwrap()
+                .triangles,
+            4
+        );
+    }
+}
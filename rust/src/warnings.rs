@@ -0,0 +1,297 @@
+use crate::events::{self, Event};
+use color_eyre::Result;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/// What kind of thing a warning is about, so scripts (and --deny-warnings) can tell them apart.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Code {
+    /// The API's TLS certificate isn't being checked.
+    InsecureTls,
+    /// A message from the API couldn't be parsed, so it was skipped.
+    UnrecognizedResponse,
+    /// The API responded to a command before one sent earlier.
+    OutOfOrder,
+    /// A snapshot was blank, so it was taken again.
+    BlankSnapshot,
+    /// A caption wasn't burned into a snapshot.
+    CaptionSkipped,
+    /// A plane's normal wasn't unit length, so it was scaled.
+    PlaneNormal,
+    /// The API may have been left in sketch mode.
+    SketchMode,
+    /// An object couldn't be removed from the scene when cleaning up.
+    CleanUp,
+    /// An exported mesh couldn't be read back, or is empty, or has vertices which aren't points.
+    ExportMalformed,
+    /// The payload of a response which couldn't be handled couldn't be kept.
+    DebugPayload,
+    /// Something optional was skipped, to finish within --time-budget.
+    OverBudget,
+    /// The --cache-dir entry for the run couldn't be used, or the run's outputs couldn't be saved in it.
+    Cache,
+    /// A stroked path's corner was too sharp to miter, so it was bevelled.
+    Bevelled,
+    /// A snapshot was asked for at a bigger size than the API took it at, so it was scaled up.
+    ScaledUp,
+    /// Which way a face points couldn't be told, so it wasn't painted.
+    UnclassifiedFace,
+    /// The run asked the API for a video stream which nothing watches.
+    UnusedStream,
+    /// An export moved by --recenter didn't end up where it should have.
+    OffCenter,
+    /// The run needs something which the API didn't support when it was probed (see --capabilities).
+    Unsupported,
+    /// Something which needs a closed solid, e.g. its volume, was asked of an extrusion without caps.
+    Uncapped,
+    /// Saving a file failed in a way which may not happen next time, so it was tried again (see --write-retries).
+    WriteRetried,
+    /// A snapshot came in a format the run didn't ask for, or couldn't be decoded, so it was saved as the API sent it.
+    SnapshotFormat,
+}
+
+impl std::fmt::Display for Code {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value = clap::ValueEnum::to_possible_value(self).expect("no codes are skipped");
+        f.write_str(value.get_name())
+    }
+}
+
+/// Something which went wrong, but not badly enough to fail the run (unless --deny-warnings says so).
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct Warning {
+    pub code: Code,
+    pub message: String,
+    /// The command it's about, if it's about one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cmd_id: Option<Uuid>,
+    /// The option or parameter it's about, if it's about one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parameter: Option<String>,
+    /// How many times it happened. Warnings with the same code, message and context are counted together.
+    pub count: usize,
+}
+
+impl Warning {
+    pub fn new(code: Code, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            cmd_id: None,
+            parameter: None,
+            count: 1,
+        }
+    }
+
+    pub fn cmd_id(self, cmd_id: Uuid) -> Self {
+        Self {
+            cmd_id: Some(cmd_id),
+            ..self
+        }
+    }
+
+    pub fn parameter(self, parameter: impl Into<String>) -> Self {
+        Self {
+            parameter: Some(parameter.into()),
+            ..self
+        }
+    }
+
+    /// Print the warning (the first time it happens) and remember it for the run's summary.
+    /// Fails if --deny-warnings turned warnings like it into errors. Where failing isn't possible,
+    /// e.g. while cleaning up, the result can be ignored: the run still fails at the end.
+    pub fn raise(self) -> Result<()> {
+        let denied = is_denied(self.code);
+        let new = record(
+            &mut WARNINGS.lock().unwrap_or_else(|e| e.into_inner()),
+            &self,
+        );
+        if new {
+            if !denied {
+                eprintln!("WARNING: {}", self.message);
+            }
+            events::emit(Event::Warning(self.clone()));
+        }
+        if denied {
+            return Err(Denied(self).into());
+        }
+        Ok(())
+    }
+}
+
+/// Add a warning to those so far, or count it again if it's the same as one of them: the same code,
+/// message and context. Returns whether it's new.
+fn record(warnings: &mut Vec<Warning>, warning: &Warning) -> bool {
+    let same = warnings.iter_mut().find(|w| {
+        w.code == warning.code
+            && w.message == warning.message
+            && w.cmd_id == warning.cmd_id
+            && w.parameter == warning.parameter
+    });
+    match same {
+        Some(same) => {
+            same.count += 1;
+            false
+        }
+        None => {
+            warnings.push(warning.clone());
+            true
+        }
+    }
+}
+
+/// Every warning so far, once each, with how many times it happened.
+static WARNINGS: Mutex<Vec<Warning>> = Mutex::new(Vec::new());
+
+/// Which warnings are errors: None if none are, or the codes which are (all of them if it's empty).
+static DENIED: Mutex<Option<Vec<Code>>> = Mutex::new(None);
+
+/// Make warnings with these codes (or all warnings, if there are none) fail the run, from --deny-warnings.
+pub fn deny(codes: Vec<Code>) {
+    *DENIED.lock().unwrap_or_else(|e| e.into_inner()) = Some(codes);
+}
+
+fn is_denied(code: Code) -> bool {
+    denies(&DENIED.lock().unwrap_or_else(|e| e.into_inner()), code)
+}
+
+/// Whether `denied`, as kept in DENIED, makes warnings with this code errors.
+fn denies(denied: &Option<Vec<Code>>, code: Code) -> bool {
+    match denied {
+        None => false,
+        Some(codes) => codes.is_empty() || codes.contains(&code),
+    }
+}
+
+/// Every warning so far, once each, with how many times it happened.
+pub fn warnings() -> Vec<Warning> {
+    WARNINGS.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Fail if any warning so far was denied by --deny-warnings, in case it happened somewhere
+/// which couldn't fail at the time.
+pub fn check() -> Result<()> {
+    match warnings().into_iter().find(|w| is_denied(w.code)) {
+        Some(warning) => Err(Denied(warning).into()),
+        None => Ok(()),
+    }
+}
+
+/// A warning which --deny-warnings turned into an error.
+#[derive(Debug)]
+pub struct Denied(pub Warning);
+
+impl std::fmt::Display for Denied {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} (a {} warning, which --deny-warnings makes an error)",
+            self.0.message, self.0.code
+        )
+    }
+}
+
+impl std::error::Error for Denied {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::ValueEnum;
+    use serde_json::json;
+
+    #[test]
+    fn counts_the_same_warning_once() {
+        let mut warnings = Vec::new();
+        let warning = Warning::new(Code::BlankSnapshot, "blank").cmd_id(Uuid::from_u128(1));
+        assert!(record(&mut warnings, &warning));
+        assert!(!record(&mut warnings, &warning.clone()));
+        assert!(!record(&mut warnings, &warning));
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].count, 3);
+    }
+
+    #[test]
+    fn counts_warnings_about_different_things_apart() {
+        let mut warnings = Vec::new();
+        let warning = Warning::new(Code::Bevelled, "bevelled");
+        for different in [
+            warning.clone(),
+            Warning::new(Code::Bevelled, "bevelled twice"),
+            Warning::new(Code::Cache, "bevelled"),
+            warning.clone().cmd_id(Uuid::from_u128(1)),
+            warning.clone().cmd_id(Uuid::from_u128(2)),
+            warning.clone().parameter("miter-limit"),
+        ] {
+            assert!(record(&mut warnings, &different), "{different:?}");
+        }
+        assert!(warnings.iter().all(|w| w.count == 1));
+    }
+
+    #[test]
+    fn denies_the_codes_asked_for() {
+        assert!(!denies(&None, Code::Cache));
+        // --deny-warnings on its own denies every code.
+        assert!(Code::value_variants()
+            .iter()
+            .all(|&code| denies(&Some(vec![]), code)));
+        let some = Some(vec![Code::Cache, Code::OutOfOrder]);
+        assert!(denies(&some, Code::Cache));
+        assert!(denies(&some, Code::OutOfOrder));
+        assert!(!denies(&some, Code::BlankSnapshot));
+    }
+
+    #[test]
+    fn serializes_warnings_with_only_the_context_they_have() {
+        let warning = Warning::new(Code::WriteRetried, "retried");
+        assert_eq!(
+            serde_json::to_value(&warning).unwrap(),
+            json!({ "code": "write-retried", "message": "retried", "count": 1 })
+        );
+        let id = Uuid::from_u128(7);
+        let warning = warning.cmd_id(id).parameter("write-retries");
+        assert_eq!(
+            serde_json::to_value(&warning).unwrap(),
+            json!({
+                "code": "write-retried",
+                "message": "retried",
+                "cmd_id": id,
+                "parameter": "write-retries",
+                "count": 1,
+            })
+        );
+    }
+
+    #[test]
+    fn names_codes_the_same_everywhere() {
+        for code in Code::value_variants() {
+            // --deny-warnings, the summary and the JSON all call it the same.
+            assert_eq!(serde_json::to_value(code).unwrap(), json!(code.to_string()));
+            assert_eq!(Code::from_str(&code.to_string(), false), Ok(*code));
+        }
+    }
+
+    #[test]
+    fn says_a_denied_warning_was_made_an_error() {
+        let denied = Denied(Warning::new(Code::Cache, "The cache is corrupt"));
+        assert_eq!(
+            denied.to_string(),
+            "The cache is corrupt (a cache warning, which --deny-warnings makes an error)"
+        );
+    }
+
+    #[test]
+    fn raises_a_warning_once_however_often_it_happens() {
+        // Other tests raise warnings too, so this one's message is its own.
+        let message = "raises_a_warning_once_however_often_it_happens";
+        for _ in 0..3 {
+            Warning::new(Code::PlaneNormal, message).raise().unwrap();
+        }
+        let raised: Vec<_> = warnings()
+            .into_iter()
+            .filter(|w| w.message == message)
+            .collect();
+        assert_eq!(raised.len(), 1);
+        assert_eq!(raised[0].count, 3);
+    }
+}
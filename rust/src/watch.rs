@@ -0,0 +1,329 @@
+use crate::{
+    client::Clients,
+    names, open_session,
+    scene::{self, Scene},
+    session::{ConnectionLost, ModelingSession},
+    sink,
+    snapshot::Output,
+    tessellation, Options,
+};
+use color_eyre::Result;
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+/// How often the scene's files are checked for changes.
+const POLL: Duration = Duration::from_millis(200);
+
+/// How long the files must stay the same after they change before the scene is rendered again,
+/// so an editor which writes a file several times for one save only causes one render.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// When a file was last modified, and how big it is, to tell when it changes.
+/// None if it can't be read, e.g. while an editor is replacing it.
+type Stamp = Option<(SystemTime, u64)>;
+
+fn stamps(files: &[PathBuf]) -> Vec<Stamp> {
+    files
+        .iter()
+        .map(|file| {
+            let meta = std::fs::metadata(file).ok()?;
+            Some((meta.modified().ok()?, meta.len()))
+        })
+        .collect()
+}
+
+/// Where the watcher finds out about the scene's files: when to look at them again, and how they stand.
+trait Source {
+    /// Wait until it's time to look at the files again, and say when that is.
+    /// None if there's nothing more to watch for.
+    async fn tick(&mut self) -> Option<Instant>;
+
+    /// The files' stamps now.
+    fn stamps(&self, files: &[PathBuf]) -> Vec<Stamp>;
+}
+
+/// The filesystem, looked at every `POLL`, for as long as the run goes on.
+struct Polling;
+
+impl Source for Polling {
+    async fn tick(&mut self) -> Option<Instant> {
+        tokio::time::sleep(POLL).await;
+        Some(Instant::now())
+    }
+
+    fn stamps(&self, files: &[PathBuf]) -> Vec<Stamp> {
+        stamps(files)
+    }
+}
+
+/// Wait until the files have changed since the scene was last read, and then stayed the same for
+/// `DEBOUNCE`. False if the source stopped first.
+async fn settled(source: &mut impl Source, debounce: &mut Debounce, files: &[PathBuf]) -> bool {
+    while let Some(now) = source.tick().await {
+        if debounce.due(source.stamps(files), now) {
+            return true;
+        }
+    }
+    false
+}
+
+/// Decides when to render the scene again, so a burst of saves only causes one render.
+struct Debounce {
+    /// The files' stamps when the scene was last read.
+    read: Vec<Stamp>,
+    /// The files' stamps when they were last checked, and when those were first seen.
+    latest: (Vec<Stamp>, Instant),
+}
+
+impl Debounce {
+    fn new(stamps: Vec<Stamp>, now: Instant) -> Self {
+        Self {
+            read: stamps.clone(),
+            latest: (stamps, now),
+        }
+    }
+
+    /// Note the files' stamps, as of `now`. Returns whether the scene should be read again:
+    /// the files have changed since it was last read, and haven't changed again for `DEBOUNCE`.
+    fn due(&mut self, stamps: Vec<Stamp>, now: Instant) -> bool {
+        if stamps != self.latest.0 {
+            self.latest = (stamps, now);
+            return false;
+        }
+        self.latest.0 != self.read && now.duration_since(self.latest.1) >= DEBOUNCE
+    }
+
+    /// The scene has been read again, with the files which had these stamps.
+    fn read(&mut self, stamps: Vec<Stamp>, now: Instant) {
+        *self = Self::new(stamps, now);
+    }
+}
+
+/// Render the scene, then render it again whenever its file (or a file it includes) changes,
+/// until the run is stopped with Ctrl-C. Outputs are replaced in one go, so stopping never leaves half of one.
+///
+/// Each render starts from an empty scene, over the same connection, or a new one if it was lost.
+/// If the scene file can't be read, or one of its parts is invalid, the error is printed, the outputs
+/// from the last render are left as they are, and watching carries on.
+pub async fn scene(
+    session: &mut ModelingSession,
+    clients: &Clients,
+    path: &Path,
+    scene: Scene,
+    output: &Output,
+    options: &Options,
+) -> Result<()> {
+    let mut files = scene.files.clone();
+    // Its jitter may have changed, but it's always the same variant.
+    let seed = scene.seed;
+    let mut connected = render(session, 1, scene, output, options).await;
+    let mut source = Polling;
+    let mut debounce = Debounce::new(source.stamps(&files), Instant::now());
+    let watched: Vec<_> = files.iter().map(|f| f.display().to_string()).collect();
+    say!(
+        "Watching {} for changes. Stop with Ctrl-C",
+        watched.join(", ")
+    );
+    for iteration in 2.. {
+        if !settled(&mut source, &mut debounce, &files).await {
+            break;
+        }
+        let scene = Scene::read(path, seed).and_then(|scene| {
+            options.groups.check(&scene)?;
+            Ok(scene)
+        });
+        // Its includes may have changed. If it can't be read, keep watching the ones it had.
+        if let Ok(scene) = &scene {
+            files = scene.files.clone();
+            tessellation::set_quality(options.tessellation.or(scene.tessellation));
+        }
+        debounce.read(source.stamps(&files), Instant::now());
+        let scene = match scene {
+            Ok(scene) => scene,
+            Err(e) => {
+                eprintln!("#{iteration}: not rendered, so the last outputs are kept: {e:?}");
+                continue;
+            }
+        };
+        if !connected {
+            let camera_preset = session.camera_preset().clone();
+            match open_session(clients, options).await {
+                Ok(new_session) => {
+                    // The names carry over, but the objects they named are checked, because the
+                    // new connection's scene may not have them.
+                    let names = std::mem::take(session.names_mut());
+                    *session = new_session;
+                    session.set_camera_preset(camera_preset);
+                    *session.names_mut() = names;
+                    match names::revalidate(session).await {
+                        Ok(lost) if !lost.is_empty() => say!(
+                            "#{iteration}: connected again, but these are gone from the scene: {}",
+                            lost.join(", ")
+                        ),
+                        Ok(_) => {}
+                        Err(e) => {
+                            eprintln!("#{iteration}: not rendered, because the API couldn't be reached: {e:?}");
+                            continue;
+                        }
+                    }
+                }
+                Err(e) => {
+                    eprintln!(
+                        "#{iteration}: not rendered, because the API couldn't be reached: {e:?}"
+                    );
+                    continue;
+                }
+            }
+        }
+        connected = render(session, iteration, scene, output, options).await;
+    }
+    Ok(())
+}
+
+/// Render the scene in an empty scene, and print how it went, on one line if it worked.
+/// Returns whether the session is still connected.
+async fn render(
+    session: &mut ModelingSession,
+    iteration: usize,
+    scene: Scene,
+    output: &Output,
+    options: &Options,
+) -> bool {
+    let started = Instant::now();
+    let saved_before = sink::written().len();
+    let parts = scene.parts.len();
+    let rendered = async {
+        scene::clear(session).await?;
+        // The outputs are named again, and their names don't clash with the last render's.
+        options.outputs.forget_claims();
+        scene::render(session, scene, output, options).await
+    }
+    .await;
+    let elapsed = started.elapsed();
+    match rendered {
+        Ok(()) => {
+            let saved = sink::written().len() - saved_before;
+            say!("#{iteration}: rendered {parts} parts in {elapsed:.2?}, and saved {saved} files");
+            true
+        }
+        Err(e) => {
+            eprintln!("#{iteration}: failed after {elapsed:.2?}: {e:?}");
+            if ConnectionLost::caused(&e) {
+                return false;
+            }
+            scene::clean_up(session).await;
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{BTreeMap, VecDeque};
+
+    /// Files which are saved on cue: before each tick, the next of `saves` is made, each a file and
+    /// the length it's saved with. Each tick is `POLL` after the last.
+    struct Script {
+        saves: VecDeque<Vec<(&'static str, u64)>>,
+        files: BTreeMap<PathBuf, (SystemTime, u64)>,
+        now: Instant,
+        ticks: u32,
+    }
+
+    impl Script {
+        fn new(saves: Vec<Vec<(&'static str, u64)>>) -> Self {
+            Self {
+                saves: saves.into(),
+                files: BTreeMap::new(),
+                now: Instant::now(),
+                ticks: 0,
+            }
+        }
+    }
+
+    impl Source for Script {
+        async fn tick(&mut self) -> Option<Instant> {
+            self.ticks += 1;
+            for (file, len) in self.saves.pop_front()? {
+                let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(self.ticks.into());
+                self.files.insert(PathBuf::from(file), (modified, len));
+            }
+            self.now += POLL;
+            Some(self.now)
+        }
+
+        fn stamps(&self, files: &[PathBuf]) -> Vec<Stamp> {
+            files
+                .iter()
+                .map(|file| self.files.get(file).copied())
+                .collect()
+        }
+    }
+
+    /// Watch `files` while `saves` are made, and count how many times the scene would be rendered again.
+    fn renders(files: &[&str], saves: Vec<Vec<(&'static str, u64)>>) -> usize {
+        let files: Vec<PathBuf> = files.iter().map(PathBuf::from).collect();
+        let mut script = Script::new(saves);
+        let mut debounce = Debounce::new(script.stamps(&files), script.now);
+        let mut renders = 0;
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(async {
+                while settled(&mut script, &mut debounce, &files).await {
+                    renders += 1;
+                    debounce.read(script.stamps(&files), script.now);
+                }
+            });
+        renders
+    }
+
+    /// Enough ticks with nothing saved for the last saves to settle.
+    fn quiet() -> Vec<Vec<(&'static str, u64)>> {
+        let ticks = DEBOUNCE.as_millis().div_ceil(POLL.as_millis()) + 1;
+        vec![vec![]; ticks as usize]
+    }
+
+    #[test]
+    fn renders_once_for_a_burst_of_saves() {
+        let saves = [
+            vec![vec![("scene.toml", 10)], vec![("scene.toml", 12)]],
+            vec![vec![("part.toml", 3), ("scene.toml", 12)]],
+            quiet(),
+        ]
+        .concat();
+        assert_eq!(renders(&["scene.toml", "part.toml"], saves), 1);
+    }
+
+    #[test]
+    fn renders_once_for_each_burst_which_settles() {
+        let saves = [
+            vec![vec![("scene.toml", 10)]],
+            quiet(),
+            vec![vec![("part.toml", 3)], vec![("part.toml", 4)]],
+            quiet(),
+        ]
+        .concat();
+        assert_eq!(renders(&["scene.toml", "part.toml"], saves), 2);
+    }
+
+    #[test]
+    fn waits_for_saves_to_stop_before_rendering() {
+        // Saved on every tick, so it never settles.
+        let saves = (1..20).map(|len| vec![("scene.toml", len)]).collect();
+        assert_eq!(renders(&["scene.toml"], saves), 0);
+    }
+
+    #[test]
+    fn ignores_files_it_isnt_watching() {
+        let saves = [
+            vec![vec![("notes.txt", 1)], vec![("scene.toml.swp", 2)]],
+            quiet(),
+        ]
+        .concat();
+        assert_eq!(renders(&["scene.toml", "part.toml"], saves), 0);
+    }
+}
@@ -0,0 +1,78 @@
+//! Plays back recordings (see --offline) of the API doing what it only does now and then, to check
+//! the run copes: failing a command twice before it succeeds, pinging, closing the connection with a
+//! reason, never answering a command, and asking for the client's metrics.
+//!
+//! The recordings are of a plain `cube` run, edited where the API behaved differently:
+//! - cube-retried.jsonl: the volume fails with an internal engine error twice, then succeeds.
+//! - cube-pinged.jsonl: the API pings after the pen's moved, and after the extrude's sent.
+//! - cube-closed.jsonl: the API closes the connection (1001) instead of answering the extrude.
+//! - cube-stuck.jsonl: the API never answers the extrude.
+//! - cube-metrics.jsonl: the API asks for the client's metrics after the path's closed, and
+//!   again after the volume's sent, and the run answers.
+
+mod common;
+
+use common::Run;
+use std::fs;
+
+/// Play back a recording, in a directory of the test's own.
+fn run(test: &str, recording: &str, args: &[&str]) -> Run {
+    let dir = common::dir(&format!("api-behavior-{test}"));
+    let offline = format!("--offline={}", common::recording(recording).display());
+    let args = [&[offline.as_str(), "--output=model.png"], args].concat();
+    common::run(&dir, &args, &[])
+}
+
+#[test]
+fn succeeds_when_a_query_fails_twice_then_succeeds() {
+    let run = run("retried", "cube-retried.jsonl", &[]);
+    assert!(run.succeeded, "The run failed:\n{}", run.printed);
+    run.assert_printed("(retry 1 of 2)");
+    run.assert_printed("(retry 2 of 2)");
+    run.assert_printed("Volume: 8 cm3");
+    assert!(run.dir.join("model.png").exists());
+}
+
+#[test]
+fn gives_up_when_a_query_fails_more_times_than_it_may_be_retried() {
+    let run = run("retried-once", "cube-retried.jsonl", &["--retries=1"]);
+    assert!(!run.succeeded);
+    run.assert_printed("even after 1 retries: internal_engine");
+}
+
+#[test]
+fn answers_pings_and_carries_on() {
+    let run = run("pinged", "cube-pinged.jsonl", &["--record=trace.jsonl"]);
+    assert!(run.succeeded, "The run failed:\n{}", run.printed);
+    assert!(run.dir.join("model.png").exists());
+    let trace = fs::read_to_string(run.dir.join("trace.jsonl")).unwrap();
+    assert_eq!(trace.matches(r#"{"ping":"heartbeat"}"#).count(), 2);
+}
+
+#[test]
+fn says_why_the_api_closed_the_connection() {
+    let run = run("closed", "cube-closed.jsonl", &[]);
+    assert!(!run.succeeded);
+    run.assert_printed(
+        "The API closed the connection (code 1001): The server is restarting for a deploy",
+    );
+    // It had already responded, so it isn't just unavailable for a moment.
+    assert!(!run.printed.contains("Connecting again"));
+}
+
+#[test]
+fn names_the_command_the_api_never_answered() {
+    let run = run("stuck", "cube-stuck.jsonl", &["--command-deadline=1"]);
+    assert!(!run.succeeded);
+    run.assert_printed("Command #8 (extrude, ");
+    run.assert_printed(
+        "has waited more than 1s for a response, so it seems stuck. 7 of the 8 commands sent had been responded to.",
+    );
+}
+
+#[test]
+fn answers_the_apis_requests_for_metrics() {
+    let run = run("metrics", "cube-metrics.jsonl", &[]);
+    assert!(run.succeeded, "The run failed:\n{}", run.printed);
+    run.assert_printed("Answered 2 requests from the API for the client's metrics");
+}
@@ -0,0 +1,43 @@
+//! Plays back cubes with --validate, to check their bounding box is measured from the edges the API
+//! says they have, not worked out from what was drawn, and that a box the wrong size fails.
+//!
+//! The cubes are 20mm on a side, centered on the origin. In cube-validate.jsonl the API's edges are
+//! where they should be; in cube-too-tall.jsonl the top edges are 2mm too high.
+
+mod common;
+
+use common::Run;
+
+/// Play back a cube from a recording, in a directory of the test's own.
+fn run(test: &str, recording: &str, args: &[&str]) -> Run {
+    let dir = common::dir(&format!("bounding-box-{test}"));
+    let offline = format!("--offline={}", common::recording(recording).display());
+    let args = [&[offline.as_str(), "--output=model.png"], args, &["cube"]].concat();
+    common::run(&dir, &args, &[])
+}
+
+#[test]
+fn measures_the_cube_from_its_edges() {
+    let run = run("measured", "cube-validate.jsonl", &["--validate"]);
+    assert!(run.succeeded, "The run failed:\n{}", run.printed);
+    run.assert_printed("Bounding box (mm, measured from its edges):");
+    run.assert_printed("x: -10 to 10 (extent 20)");
+    run.assert_printed("y: -10 to 10 (extent 20)");
+    run.assert_printed("z: -10 to 10 (extent 20)");
+}
+
+#[test]
+fn fails_if_the_cube_is_the_wrong_size() {
+    let run = run("too-tall", "cube-too-tall.jsonl", &["--validate"]);
+    assert!(!run.succeeded);
+    run.assert_printed("z: -10 to 12 (extent 22)");
+    run.assert_printed("The measured z extent (22) deviates from the expected z extent (20)");
+}
+
+#[test]
+fn says_an_unmeasured_box_is_as_drawn() {
+    let run = run("drawn", "cube.jsonl", &[]);
+    assert!(run.succeeded, "The run failed:\n{}", run.printed);
+    run.assert_printed("Bounding box (mm, as drawn, not measured):");
+    run.assert_printed("z: -10 to 10 (extent 20)");
+}
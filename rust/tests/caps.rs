@@ -0,0 +1,61 @@
+//! Plays back the measure shape's recording (see --offline), with and without --no-cap, to check
+//! its extrusions are sent capped or not, and that measuring uncapped ones warns.
+
+mod common;
+
+use serde_json::Value;
+use std::fs;
+
+/// Measure the two cubes in a directory of the test's own, with `options`, and return the run and
+/// the `cap` of each extrusion it sent.
+fn measure(test: &str, options: &[&str]) -> (common::Run, Vec<bool>) {
+    let dir = common::dir(test);
+    let offline = format!("--offline={}", common::recording("measure.jsonl").display());
+    let run = common::run(
+        &dir,
+        &[
+            &[
+                offline.as_str(),
+                "--record=sent.jsonl",
+                "--output=model.png",
+            ],
+            options,
+            &["measure"],
+        ]
+        .concat(),
+        &[],
+    );
+    assert!(run.succeeded, "The run failed:\n{}", run.printed);
+    let caps = fs::read_to_string(dir.join("sent.jsonl"))
+        .unwrap()
+        .lines()
+        .filter_map(|line| {
+            let entry: Value = serde_json::from_str(line).unwrap();
+            let cmd = entry.pointer("/sent/cmd")?;
+            (cmd["type"] == "extrude").then(|| cmd["cap"].as_bool().unwrap())
+        })
+        .collect();
+    (run, caps)
+}
+
+#[test]
+fn caps_extrusions_by_default() {
+    let (run, caps) = measure("caps-default", &[]);
+    assert_eq!(caps, [true, true]);
+    assert!(!run.printed.contains("[uncapped]"), "{}", run.printed);
+}
+
+#[test]
+fn leaves_extrusions_open_with_no_cap_and_warns_when_they_re_measured() {
+    let (run, caps) = measure("caps-none", &["--no-cap"]);
+    assert_eq!(caps, [false, false]);
+    run.assert_printed(
+        "was extruded without caps, so it isn't a closed solid, and its center of mass doesn't mean much",
+    );
+    assert_eq!(
+        run.printed.matches("  [uncapped] ").count(),
+        2,
+        "{}",
+        run.printed
+    );
+}
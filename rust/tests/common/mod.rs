@@ -0,0 +1,278 @@
+//! What the integration tests share: running the binary in a directory of the test's own, and a
+//! fake API for it to talk to, which answers with a recording's responses.
+//!
+//! Each test file only uses some of it.
+#![allow(dead_code)]
+
+use serde_json::Value;
+use std::{
+    fs,
+    io::Write,
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+use tokio_tungstenite::tungstenite::{
+    self,
+    handshake::server::{ErrorResponse, Request, Response},
+    http::StatusCode,
+    Message,
+};
+
+/// How the run went.
+pub struct Run {
+    pub succeeded: bool,
+    pub exit_code: Option<i32>,
+    /// What it printed, to stdout and stderr.
+    pub printed: String,
+    /// The directory it ran in.
+    pub dir: PathBuf,
+}
+
+impl Run {
+    pub fn assert_printed(&self, text: &str) {
+        assert!(
+            self.printed.contains(text),
+            "Expected the run to print {text:?}, but it printed:\n{}",
+            self.printed
+        );
+    }
+
+    /// The files the run left in its directory with `text` in them.
+    pub fn files_containing(&self, text: &str) -> Vec<PathBuf> {
+        let mut found = Vec::new();
+        let mut dirs = vec![self.dir.clone()];
+        while let Some(dir) = dirs.pop() {
+            for entry in fs::read_dir(&dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    dirs.push(path);
+                } else if fs::read(&path)
+                    .unwrap()
+                    .windows(text.len())
+                    .any(|window| window == text.as_bytes())
+                {
+                    found.push(path);
+                }
+            }
+        }
+        found
+    }
+}
+
+/// An empty directory of the test's own.
+pub fn dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("kittycad-{test}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A recording in tests/recordings.
+pub fn recording(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/recordings")
+        .join(name)
+}
+
+/// Run the binary in `dir`, with only `env` (and $PATH, for --token-command's shell) in its environment.
+pub fn run(dir: &Path, args: &[&str], env: &[(&str, &str)]) -> Run {
+    let binary = env!("CARGO_BIN_EXE_kittycad-modeling-api-examples");
+    run_program(Command::new(binary), dir, args, env, &[], Duration::ZERO)
+}
+
+/// Run the binary like `run` does, with `input` on its standard input.
+pub fn run_with_input(dir: &Path, args: &[&str], env: &[(&str, &str)], input: &str) -> Run {
+    let binary = env!("CARGO_BIN_EXE_kittycad-modeling-api-examples");
+    run_program(
+        Command::new(binary),
+        dir,
+        args,
+        env,
+        &[input],
+        Duration::ZERO,
+    )
+}
+
+/// Run the binary like `run` does, writing each of `chunks` to its standard input in one go,
+/// and waiting `pause` after each.
+pub fn run_with_paced_input(
+    dir: &Path,
+    args: &[&str],
+    env: &[(&str, &str)],
+    chunks: &[&str],
+    pause: Duration,
+) -> Run {
+    let binary = env!("CARGO_BIN_EXE_kittycad-modeling-api-examples");
+    run_program(Command::new(binary), dir, args, env, chunks, pause)
+}
+
+/// Run one of the examples in `dir`, like `run` runs the binary.
+/// `cargo test` builds the examples next to the tests, in target/*/examples.
+pub fn run_example(name: &str, dir: &Path, args: &[&str], env: &[(&str, &str)]) -> Run {
+    let test = std::env::current_exe().unwrap();
+    let examples = test.parent().unwrap().parent().unwrap().join("examples");
+    let example = examples
+        .join(name)
+        .with_extension(std::env::consts::EXE_EXTENSION);
+    assert!(
+        example.exists(),
+        "There's no {} to run. `cargo test` builds it, but `cargo test --test` doesn't",
+        example.display()
+    );
+    run_program(Command::new(example), dir, args, env, &[], Duration::ZERO)
+}
+
+fn run_program(
+    mut command: Command,
+    dir: &Path,
+    args: &[&str],
+    env: &[(&str, &str)],
+    chunks: &[&str],
+    pause: Duration,
+) -> Run {
+    command
+        .args(args)
+        .current_dir(dir)
+        // Keep the options the run reads from the environment out of it.
+        .env_clear()
+        .env("NO_COLOR", "1")
+        .env("RUST_BACKTRACE", "0")
+        .envs(env.iter().copied());
+    if let Some(path) = std::env::var_os("PATH") {
+        command.env("PATH", path);
+    }
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // Dropping standard input once it's written ends it, as the end of a file would.
+    // A run which stops before reading it all can't be written to, which doesn't matter.
+    let mut stdin = child.stdin.take().unwrap();
+    let chunks: Vec<String> = chunks.iter().map(|&chunk| chunk.to_owned()).collect();
+    let writer = thread::spawn(move || {
+        for chunk in chunks {
+            if stdin.write_all(chunk.as_bytes()).is_err() {
+                break;
+            }
+            thread::sleep(pause);
+        }
+    });
+    let output = child.wait_with_output().unwrap();
+    writer.join().unwrap();
+    Run {
+        succeeded: output.status.success(),
+        exit_code: output.status.code(),
+        printed: format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        ),
+        dir: dir.to_owned(),
+    }
+}
+
+/// A fake modeling API on localhost, which only lets in the tokens it accepts, and answers each
+/// command sent to it with the next response in a recording, with the command's ID in it.
+/// Point a run at it with `api=` in $KITTYCAD_INJECT.
+pub struct FakeApi {
+    pub url: String,
+    /// The tokens each connection was opened with, accepted or not.
+    tokens: Arc<Mutex<Vec<String>>>,
+    /// The query string each connection was opened with, e.g. asking for a stream.
+    queries: Arc<Mutex<Vec<String>>>,
+}
+
+impl FakeApi {
+    pub fn start(recording: &str, accepted: &[&str]) -> Self {
+        let responses: Vec<Value> = fs::read_to_string(self::recording(recording))
+            .unwrap()
+            .lines()
+            .filter_map(|line| {
+                let mut entry: Value = serde_json::from_str(line).unwrap();
+                entry.get_mut("received").map(Value::take)
+            })
+            .collect();
+        let accepted: Vec<String> = accepted.iter().map(|&token| token.to_owned()).collect();
+        let tokens = Arc::new(Mutex::new(Vec::new()));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let queries = Arc::new(Mutex::new(Vec::new()));
+        let (seen, asked) = (tokens.clone(), queries.clone());
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                serve(stream, &responses, &accepted, &seen, &asked);
+            }
+        });
+        Self {
+            url,
+            tokens,
+            queries,
+        }
+    }
+
+    pub fn tokens(&self) -> Vec<String> {
+        self.tokens.lock().unwrap().clone()
+    }
+
+    pub fn queries(&self) -> Vec<String> {
+        self.queries.lock().unwrap().clone()
+    }
+}
+
+fn serve(
+    stream: TcpStream,
+    responses: &[Value],
+    accepted: &[String],
+    seen: &Mutex<Vec<String>>,
+    asked: &Mutex<Vec<String>>,
+) {
+    // tungstenite's callback returns the refusal by value, however big it is.
+    #[allow(clippy::result_large_err)]
+    let check = |request: &Request, response: Response| {
+        let token = request
+            .headers()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap_or_default()
+            .to_owned();
+        let ok = accepted.contains(&token);
+        seen.lock().unwrap().push(token);
+        asked
+            .lock()
+            .unwrap()
+            .push(request.uri().query().unwrap_or_default().to_owned());
+        if ok {
+            return Ok(response);
+        }
+        let mut refused = ErrorResponse::new(Some("That token isn't valid".to_owned()));
+        *refused.status_mut() = StatusCode::UNAUTHORIZED;
+        Err(refused)
+    };
+    let Ok(mut ws) = tungstenite::accept_hdr(stream, check) else {
+        return;
+    };
+    let mut responses = responses.iter();
+    while let Ok(msg) = ws.read() {
+        let Message::Text(text) = msg else {
+            continue;
+        };
+        let sent: Value = serde_json::from_str(&text).unwrap();
+        if sent["type"] != "modeling_cmd_req" {
+            continue;
+        }
+        let Some(mut response) = responses.next().cloned() else {
+            break;
+        };
+        response["request_id"] = sent["cmd_id"].clone();
+        if ws.send(Message::Text(response.to_string())).is_err() {
+            break;
+        }
+    }
+}
@@ -0,0 +1,71 @@
+//! Runs against a fake API (see tests/common), to check the stream a run asks for when it connects
+//! comes from --connection-profile.
+//!
+//! The fake API is reached through a hook only in builds with the "test-hooks" feature, so run these
+//! with `cargo test --features test-hooks`.
+#![cfg(feature = "test-hooks")]
+
+mod common;
+
+use common::FakeApi;
+use std::collections::BTreeMap;
+
+/// Build a cube against a fake API with `args`, and return the query the run connected with.
+fn connected_with(test: &str, args: &[&str]) -> BTreeMap<String, String> {
+    let dir = common::dir(test);
+    let api = FakeApi::start("cube.jsonl", &["token"]);
+    let inject = format!("api={}", api.url);
+    let run = common::run(
+        &dir,
+        &[&["--output=model.png", "--no-cache"], args, &["cube"]].concat(),
+        &[
+            ("KITTYCAD_API_TOKEN", "token"),
+            ("KITTYCAD_INJECT", &inject),
+        ],
+    );
+    assert!(run.succeeded, "The run failed:\n{}", run.printed);
+    let queries = api.queries();
+    assert_eq!(queries.len(), 1, "The run connected more than once");
+    queries[0]
+        .split('&')
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap();
+            (name.to_owned(), value.to_owned())
+        })
+        .collect()
+}
+
+fn query(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+    pairs
+        .iter()
+        .map(|&(name, value)| (name.to_owned(), value.to_owned()))
+        .collect()
+}
+
+#[test]
+fn asks_for_as_little_video_as_it_can_by_default() {
+    assert_eq!(
+        connected_with("connection-default", &[]),
+        query(&[
+            ("fps", "1"),
+            ("unlocked_framerate", "false"),
+            ("video_res_height", "480"),
+            ("video_res_width", "640"),
+            ("webrtc", "false"),
+        ])
+    );
+}
+
+#[test]
+fn asks_for_a_stream_with_the_streaming_profile() {
+    assert_eq!(
+        connected_with("connection-streaming", &["--connection-profile=streaming"]),
+        query(&[
+            ("fps", "30"),
+            ("unlocked_framerate", "false"),
+            ("video_res_height", "480"),
+            ("video_res_width", "640"),
+            ("webrtc", "true"),
+        ])
+    );
+}
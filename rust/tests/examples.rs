@@ -0,0 +1,160 @@
+//! Runs each of the examples, playing back a recording of its session (see --offline) through
+//! $KITTYCAD_OFFLINE, to check it still builds and still sends what it did, and what it prints and saves.
+//!
+//! The recordings are in tests/recordings/examples, one per example, named after it. They were made
+//! with $KITTYCAD_RECORD against a stand-in for the API on localhost, so the snapshots are all the
+//! same small picture, and the exports are simple meshes of what was drawn. When an example changes
+//! what it sends, record it again against the API.
+
+mod common;
+
+use common::Run;
+use std::{fs, path::Path};
+
+/// Play an example back from its recording, in a directory of the test's own.
+fn run(example: &str, args: &[&str]) -> Run {
+    let dir = common::dir(&format!("example-{example}"));
+    let recording = common::recording(&format!("examples/{example}.jsonl"));
+    let recording = recording.to_str().unwrap();
+    let run = common::run_example(example, &dir, args, &[("KITTYCAD_OFFLINE", recording)]);
+    assert!(run.succeeded, "{example} failed:\n{}", run.printed);
+    run
+}
+
+/// Check the run saved these files.
+fn assert_saved(run: &Run, files: &[&str]) {
+    for file in files {
+        assert!(
+            run.dir.join(file).exists(),
+            "Expected it to save {file}, but it printed:\n{}",
+            run.printed
+        );
+    }
+}
+
+#[test]
+fn every_example_has_a_recording() {
+    let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+    for entry in fs::read_dir(examples).unwrap() {
+        let path = entry.unwrap().path();
+        let name = path.file_stem().unwrap().to_str().unwrap();
+        let recording = common::recording(&format!("examples/{name}.jsonl"));
+        assert!(
+            recording.exists(),
+            "examples/{name}.rs has no recording to test it with. Make one with $KITTYCAD_RECORD"
+        );
+    }
+}
+
+#[test]
+fn minimal() {
+    let run = run("minimal", &[]);
+    run.assert_printed("Saved minimal.png");
+    assert_saved(&run, &["minimal.png"]);
+}
+
+#[test]
+fn cube() {
+    let run = run("cube", &[]);
+    run.assert_printed("Saved cube.png");
+    assert_saved(&run, &["cube.png"]);
+}
+
+#[test]
+fn cylinder() {
+    let run = run("cylinder", &[]);
+    run.assert_printed("Saved cylinder.png");
+    assert_saved(&run, &["cylinder.png"]);
+}
+
+#[test]
+fn export_stl() {
+    let run = run("export_stl", &[]);
+    run.assert_printed(
+        "bracket.stl has 20 triangles, 12 vertices, from (-5.000, -5.000, 0.000) to (40.000, 40.000, 20.000), closed",
+    );
+    assert_saved(&run, &["bracket.stl"]);
+}
+
+#[test]
+fn extrude_caps() {
+    let run = run("extrude_caps", &[]);
+    run.assert_printed("extrude-capped.stl is closed, with 0 open edges");
+    run.assert_printed("extrude-open.stl is not closed, with 12 open edges");
+    assert_saved(
+        &run,
+        &[
+            "extrude-capped.png",
+            "extrude-capped.stl",
+            "extrude-open.png",
+            "extrude-open.stl",
+        ],
+    );
+}
+
+#[test]
+fn extrusion_faces() {
+    let run = run("extrusion_faces", &[]);
+    run.assert_printed(", at height 10\n");
+    run.assert_printed(", at height 0\n");
+    assert_eq!(run.printed.matches("Side: ").count(), 4, "{}", run.printed);
+    assert!(!run.printed.contains("Unexpected: "), "{}", run.printed);
+    assert_saved(&run, &["extrusion_faces.png"]);
+}
+
+#[test]
+fn face_colors() {
+    let run = run("face_colors", &[]);
+    for direction in ["+X", "-X", "+Y", "-Y", "+Z", "-Z"] {
+        run.assert_printed(&format!("{direction}: "));
+    }
+    assert!(!run.printed.contains("Unpainted: "), "{}", run.printed);
+    assert_saved(&run, &["face_colors-front.png", "face_colors-back.png"]);
+}
+
+#[test]
+fn import_convert() {
+    let obj = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/examples/cube.obj");
+    let run = run("import_convert", &[obj.to_str().unwrap(), "cube.stl"]);
+    run.assert_printed(
+        "cube.stl has 12 triangles, 8 vertices, from (-10.000, -10.000, -10.000) to (10.000, 10.000, 10.000), closed",
+    );
+    run.assert_printed("to cube.stl (684 bytes)");
+    assert_saved(&run, &["cube.stl"]);
+}
+
+#[test]
+fn import_convert_says_how_to_use_it() {
+    let dir = common::dir("example-import_convert-usage");
+    let run = common::run_example("import_convert", &dir, &["cube.obj"], &[]);
+    assert!(!run.succeeded);
+    run.assert_printed("Usage: import_convert INPUT OUTPUT");
+}
+
+#[test]
+fn mounting_rail() {
+    let run = run("mounting_rail", &[]);
+    run.assert_printed("Saved mounting_rail.png");
+    assert_saved(&run, &["mounting_rail.png"]);
+}
+
+#[test]
+fn multi_view() {
+    let run = run("multi_view", &[]);
+    assert_saved(
+        &run,
+        &[
+            "view-iso.png",
+            "view-top.png",
+            "view-front.png",
+            "view-right.png",
+        ],
+    );
+}
+
+#[test]
+fn wall() {
+    let run = run("wall", &[]);
+    run.assert_printed("Saved wall.png");
+    assert_saved(&run, &["wall.png"]);
+}
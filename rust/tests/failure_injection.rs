@@ -0,0 +1,104 @@
+//! Plays back recorded runs (see --offline) with failures injected by $KITTYCAD_INJECT, to check the
+//! error handling which the API, usually working, never exercises: retries, connecting again,
+//! cleaning up, and keeping payloads for bug reports.
+//!
+//! The hooks are only in builds with the "test-hooks" feature, so run these with
+//! `cargo test --features test-hooks`.
+//!
+//! The recordings are of a plain `cube` run. cube-clean-up.jsonl was recorded with its volume
+//! failing, so it ends with the run removing the cube again.
+#![cfg(feature = "test-hooks")]
+
+mod common;
+
+use common::Run;
+use std::fs;
+
+/// Play back a recording, in a directory of the test's own, with `inject` in $KITTYCAD_INJECT.
+fn run(test: &str, recording: &str, inject: &str, args: &[&str]) -> Run {
+    let dir = common::dir(&format!("failure-injection-{test}"));
+    let offline = format!("--offline={}", common::recording(recording).display());
+    let args = [&[offline.as_str(), "--output=model.png"], args].concat();
+    common::run(&dir, &args, &[("KITTYCAD_INJECT", inject)])
+}
+
+#[test]
+fn retries_a_failed_query() {
+    let run = run("retry", "cube.jsonl", "fail=volume:internal_engine:1", &[]);
+    run.assert_printed("so it's being sent again");
+    assert!(run.succeeded, "The run failed:\n{}", run.printed);
+    assert!(run.dir.join("model.png").exists());
+}
+
+#[test]
+fn gives_up_retrying_a_query_which_keeps_failing() {
+    let run = run(
+        "retry-exhausted",
+        "cube.jsonl",
+        "fail=volume:internal_engine",
+        &["--retries=1"],
+    );
+    assert!(!run.succeeded);
+    run.assert_printed("even after 1 retries: internal_engine");
+}
+
+#[test]
+fn connects_again_if_the_connection_drops_before_any_response() {
+    let run = run("reconnect", "cube.jsonl", "drop-after=0", &[]);
+    run.assert_printed("Connecting again in 1s (attempt 1 of 3)");
+    assert!(run.succeeded, "The run failed:\n{}", run.printed);
+    assert!(run.dir.join("model.png").exists());
+}
+
+#[test]
+fn fails_if_the_connection_drops_after_a_response() {
+    let run = run("dropped", "cube.jsonl", "drop-after=3", &[]);
+    assert!(!run.succeeded);
+    run.assert_printed("Lost the connection to the API");
+    assert!(!run.printed.contains("Connecting again"));
+    // The connection's gone, so there's nothing to clean up through.
+    assert!(!run.printed.contains("Cleaned up"));
+}
+
+#[test]
+fn cleans_up_after_a_failure() {
+    let run = run(
+        "clean-up",
+        "cube-clean-up.jsonl",
+        "fail=volume:bad_request",
+        &[],
+    );
+    assert!(!run.succeeded);
+    run.assert_printed("bad_request: Injected by $KITTYCAD_INJECT");
+    run.assert_printed("Cleaned up 1 of 1 objects");
+    assert!(!run.printed.contains("Could not clean up"));
+}
+
+#[test]
+fn keeps_a_corrupt_snapshot_for_bug_reports() {
+    // The snapshot is the 13th response.
+    let run = run("debug", "cube.jsonl", "corrupt=13", &["--debug-dir=kept"]);
+    assert!(!run.succeeded);
+    run.assert_printed("to attach to a bug report");
+    let kept: Vec<_> = fs::read_dir(run.dir.join("kept"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    assert_eq!(kept.len(), 1, "Expected one payload, but kept {kept:?}");
+    assert_eq!(fs::read(&kept[0]).unwrap(), b"corrupted");
+    assert!(!run.dir.join("model.png").exists());
+}
+
+#[test]
+fn times_out_if_the_api_goes_quiet() {
+    let run = run("idle", "cube.jsonl", "delay=2s", &["--idle-timeout=1"]);
+    assert!(!run.succeeded);
+    run.assert_printed("The API sent nothing at all for 1s");
+}
+
+#[test]
+fn rejects_an_unknown_injection() {
+    let run = run("unknown", "cube.jsonl", "explode=1", &[]);
+    assert!(!run.succeeded);
+    run.assert_printed("There's nothing called explode to inject");
+}
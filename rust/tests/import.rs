@@ -0,0 +1,58 @@
+//! Plays back a recording (see --offline) of importing tests/fixtures/import/tetrahedron.stl,
+//! import.jsonl, to check the file is sent as it is, and the camera is pointed at its vertices.
+//! The tetrahedron's corners are at (10, 20, 30), and 20mm along each axis from there.
+
+mod common;
+
+use serde_json::Value;
+use std::{fs, path::Path};
+
+#[test]
+fn imports_an_stl_and_frames_its_vertices() {
+    let dir = common::dir("import-stl");
+    let stl = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/import/tetrahedron.stl");
+    let offline = format!("--offline={}", common::recording("import.jsonl").display());
+    let file = format!("--file={}", stl.display());
+    let run = common::run(
+        &dir,
+        &[
+            &offline,
+            "--record=trace.jsonl",
+            "--output=model.png",
+            "import",
+            &file,
+        ],
+        &[],
+    );
+    assert!(run.succeeded, "The run failed:\n{}", run.printed);
+    run.assert_printed("Imported 1 file(s) as 1 object");
+    assert!(dir.join("model.png").exists());
+
+    let sent: Vec<Value> = fs::read_to_string(dir.join("trace.jsonl"))
+        .unwrap()
+        .lines()
+        .filter_map(|line| {
+            let mut entry: Value = serde_json::from_str(line).unwrap();
+            entry.pointer_mut("/sent/cmd").map(Value::take)
+        })
+        .collect();
+    let imported = sent
+        .iter()
+        .find(|cmd| cmd["type"] == "import_files")
+        .expect("Nothing was imported");
+    let files = imported["files"].as_array().unwrap();
+    assert_eq!(files.len(), 1);
+    // Just the file's name, so files which refer to each other can find each other.
+    assert_eq!(files[0]["path"], "tetrahedron.stl");
+    let data: Vec<u8> = serde_json::from_value(files[0]["data"].clone()).unwrap();
+    assert_eq!(data, fs::read(&stl).unwrap());
+
+    let look_at = sent
+        .iter()
+        .find(|cmd| cmd["type"] == "default_camera_look_at")
+        .expect("The camera wasn't pointed at the import");
+    assert_eq!(
+        look_at["center"],
+        serde_json::json!({ "x": 20.0, "y": 30.0, "z": 40.0 })
+    );
+}
@@ -0,0 +1,117 @@
+//! Keeps `examples/minimal.rs` minimal, as the rest of the crate grows around it.
+//! If one of these fails, show the new thing in another example instead.
+
+/// The example, as it is now.
+const EXAMPLE: &str = include_str!("../examples/minimal.rs");
+
+/// How long the example may be, comments included.
+const MAX_LINES: usize = 150;
+
+/// What the example may `use`. From this crate, only the session is allowed: everything else it does
+/// is spelled out, so it can be read top to bottom.
+const ALLOWED_IMPORTS: &[&str] = &[
+    "color_eyre",
+    "kittycad::types",
+    "kittycad_modeling_api_examples::session",
+    "std",
+];
+
+#[test]
+fn minimal_example_is_short() {
+    let lines = EXAMPLE.lines().count();
+    assert!(
+        lines <= MAX_LINES,
+        "examples/minimal.rs is {lines} lines long, but it should be at most {MAX_LINES}"
+    );
+}
+
+#[test]
+fn minimal_example_only_imports_what_it_may() {
+    for import in imports(EXAMPLE) {
+        assert!(
+            ALLOWED_IMPORTS
+                .iter()
+                .any(|allowed| import == *allowed || import.starts_with(&format!("{allowed}::"))),
+            "examples/minimal.rs imports {import}, but it may only import from {ALLOWED_IMPORTS:?}"
+        );
+    }
+}
+
+#[test]
+fn minimal_example_only_uses_the_session_from_this_crate() {
+    for (at, _) in EXAMPLE.match_indices("kittycad_modeling_api_examples::") {
+        let path = &EXAMPLE[at + "kittycad_modeling_api_examples::".len()..];
+        assert!(
+            path.starts_with("session::"),
+            "examples/minimal.rs uses kittycad_modeling_api_examples::{}, but it may only use the session",
+            path.lines().next().unwrap_or_default()
+        );
+    }
+}
+
+/// The paths a file's `use` statements import, with their braces expanded,
+/// e.g. `use a::{b, c::d};` is a::b and a::c::d.
+fn imports(source: &str) -> Vec<String> {
+    let code: String = source
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("//"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut imports = Vec::new();
+    let mut rest = code.as_str();
+    while let Some(at) = rest.find("use ") {
+        // Only whole words, e.g. not "reuse ".
+        let starts_word = rest[..at]
+            .chars()
+            .last()
+            .is_none_or(|c| !c.is_alphanumeric() && c != '_');
+        let after = &rest[at + "use ".len()..];
+        let Some(end) = after.find(';') else {
+            break;
+        };
+        if starts_word {
+            let tree = after[..end]
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ");
+            expand("", &tree, &mut imports);
+        }
+        rest = &after[end..];
+    }
+    imports
+}
+
+/// Expand a use tree, e.g. `a::{b,c::{d,e}}`, into the paths it names, after `prefix`.
+fn expand(prefix: &str, tree: &str, imports: &mut Vec<String>) {
+    let tree = tree.trim();
+    let Some(open) = tree.find('{') else {
+        // A trailing comma leaves nothing after it.
+        if tree.is_empty() {
+            return;
+        }
+        let path = tree.split(" as ").next().unwrap_or(tree).replace(' ', "");
+        if path == "self" {
+            imports.push(prefix.trim_end_matches("::").to_owned());
+        } else {
+            imports.push(format!("{prefix}{path}"));
+        }
+        return;
+    };
+    let prefix = format!("{prefix}{}", tree[..open].replace(' ', ""));
+    let inner = &tree[open + 1..tree.len() - 1];
+    // Split at the commas which aren't inside nested braces.
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in inner.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                expand(&prefix, &inner[start..i], imports);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    expand(&prefix, &inner[start..], imports);
+}
@@ -0,0 +1,95 @@
+//! Plays back recordings (see --offline) of the pipe subcommand, which builds a scene one line of
+//! standard input at a time:
+//! - pipe-raw.jsonl: raw commands, some of which are bad, and aren't sent.
+//! - pipe-previews.jsonl: five camera zooms, in bursts of two, two and one, with a preview after each.
+//! - pipe-previews-every.jsonl: the same, with --preview-every=3, so there's no preview after the first.
+
+mod common;
+
+use common::Run;
+use std::time::Duration;
+
+/// Pipe `input` into a run playing back a recording, in a directory of the test's own.
+fn pipe(test: &str, recording: &str, args: &[&str], input: &str) -> Run {
+    let dir = common::dir(&format!("pipe-{test}"));
+    let offline = format!("--offline={}", common::recording(recording).display());
+    let args = [&[offline.as_str(), "--output=model.png", "pipe"], args].concat();
+    common::run_with_input(&dir, &args, &[], input)
+}
+
+#[test]
+fn names_the_line_of_each_bad_raw_command_and_carries_on() {
+    let input = [
+        r#"{"type": "default_camera_zoom", "magnitude": 2}"#,
+        "# a comment",
+        r#"{"type": "default_camera_zoom", "magnitude": }"#,
+        r#"{"type": "teleport_me"}"#,
+        r#"{"type": "object_visible", "object_id": "${plate}", "hidden": false}"#,
+        r#"{"type": "default_camera_zoom", "magnitude": 0.5}"#,
+    ]
+    .join("\n");
+    let run = pipe("bad-lines", "pipe-raw.jsonl", &["--no-preview"], &input);
+    assert!(run.succeeded, "The run failed:\n{}", run.printed);
+    let failed = |line: usize, why: &str| {
+        let at = run
+            .printed
+            .find(&format!("Line {line} failed: "))
+            .unwrap_or_else(|| panic!("Line {line} didn't fail:\n{}", run.printed));
+        let rest = &run.printed[at + 1..];
+        let failure = &rest[..rest.find("Line ").unwrap_or(rest.len())];
+        assert!(
+            failure.contains(why),
+            "Line {line} didn't fail with {why:?}:\n{}",
+            run.printed
+        );
+    };
+    failed(3, "The raw command isn't valid JSON");
+    failed(
+        4,
+        "teleport_me isn't a command version 0.2.37 of the kittycad crate knows",
+    );
+    failed(
+        5,
+        "Nothing is called plate, because nothing has been named yet",
+    );
+    for line in [1, 2, 6] {
+        assert!(!run.printed.contains(&format!("Line {line} failed")));
+    }
+    assert!(run.dir.join("model.png").exists());
+}
+
+/// Pipe five zooms into a run playing back a recording, in bursts of two, two and one, each followed by
+/// a pause long enough for a preview. Returns the run, and how many previews it saved.
+fn pipe_in_bursts(test: &str, recording: &str, args: &[&str]) -> (Run, usize) {
+    let dir = common::dir(&format!("pipe-{test}"));
+    let offline = format!("--offline={}", common::recording(recording).display());
+    let args = [&[offline.as_str(), "--output=model.png", "pipe"], args].concat();
+    let zoom =
+        |magnitude| format!("{{\"type\": \"default_camera_zoom\", \"magnitude\": {magnitude}}}\n");
+    let bursts = [zoom(2.0) + &zoom(0.5), zoom(2.0) + &zoom(0.5), zoom(2.0)];
+    let bursts: Vec<&str> = bursts.iter().map(String::as_str).collect();
+    let run = common::run_with_paced_input(&dir, &args, &[], &bursts, Duration::from_millis(600));
+    let previews = run.printed.matches("Updated the preview at").count();
+    (run, previews)
+}
+
+#[test]
+fn saves_one_preview_for_each_burst_of_lines() {
+    let (run, previews) = pipe_in_bursts("bursts", "pipe-previews.jsonl", &[]);
+    assert!(run.succeeded, "The run failed:\n{}", run.printed);
+    assert_eq!(previews, 3, "{}", run.printed);
+    assert!(run.dir.join("preview.png").exists());
+    assert!(run.dir.join("model.png").exists());
+}
+
+#[test]
+fn waits_for_enough_changes_before_a_preview() {
+    let (run, previews) = pipe_in_bursts(
+        "preview-every",
+        "pipe-previews-every.jsonl",
+        &["--preview-every=3"],
+    );
+    assert!(run.succeeded, "The run failed:\n{}", run.printed);
+    // After the second burst (four changes), and at the end (one more).
+    assert_eq!(previews, 2, "{}", run.printed);
+}
@@ -0,0 +1,68 @@
+//! Runs against a fake API (see tests/common), to check a run which couldn't save what it's asked to
+//! fails saying so before it connects, rather than at the end of a long run.
+//!
+//! The fake API is reached through a hook only in builds with the "test-hooks" feature, so run these
+//! with `cargo test --features test-hooks`. Directory permissions are Unix's, so they're Unix only.
+#![cfg(all(feature = "test-hooks", unix))]
+
+mod common;
+
+use common::{FakeApi, Run};
+use std::{fs, os::unix::fs::PermissionsExt, path::Path};
+
+/// Build a cube against a fake API, and check it never connected.
+fn run(dir: &Path, args: &[&str]) -> Run {
+    let api = FakeApi::start("cube.jsonl", &["token"]);
+    let inject = format!("api={}", api.url);
+    let run = common::run(
+        dir,
+        &[&["--no-cache"], args].concat(),
+        &[
+            ("KITTYCAD_API_TOKEN", "token"),
+            ("KITTYCAD_INJECT", inject.as_str()),
+        ],
+    );
+    assert!(!run.succeeded, "The run didn't fail:\n{}", run.printed);
+    assert!(
+        api.tokens().is_empty(),
+        "The run connected before failing:\n{}",
+        run.printed
+    );
+    run.assert_printed("Not starting, because the run couldn't save everything it's asked to:");
+    run
+}
+
+#[test]
+fn fails_before_connecting_if_the_output_directory_is_a_file() {
+    let dir = common::dir("preflight-file");
+    fs::write(dir.join("renders"), "").unwrap();
+    let run = run(&dir, &["--output=renders/model.png"]);
+    run.assert_printed(
+        "the snapshot (renders/model.png) can't be saved: renders isn't a directory",
+    );
+}
+
+#[test]
+fn fails_before_connecting_if_the_output_directory_cant_be_written_to() {
+    let dir = common::dir("preflight-read-only");
+    fs::create_dir(dir.join("read-only")).unwrap();
+    fs::set_permissions(dir.join("read-only"), fs::Permissions::from_mode(0o555)).unwrap();
+    if fs::write(dir.join("read-only/probe"), "").is_ok() {
+        // Running as root, which can write anywhere.
+        return;
+    }
+    let run = run(
+        &dir,
+        &[
+            "--output=read-only/model.png",
+            "--record=read-only/missing/trace.jsonl",
+        ],
+    );
+    run.assert_printed(
+        "the snapshot (read-only/model.png) can't be saved: read-only can't be written to: ",
+    );
+    run.assert_printed(
+        "the recording (read-only/missing/trace.jsonl) can't be saved: \
+         read-only/missing doesn't exist, and can't be made in read-only: ",
+    );
+}
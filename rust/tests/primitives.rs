@@ -0,0 +1,35 @@
+//! Plays back a recording (see --offline) of verify-primitives measuring a box, verify-primitives.jsonl,
+//! and checks shapes which can't be built are refused before anything is sent.
+
+mod common;
+
+#[test]
+fn compares_a_box_s_measurements_with_its_formulas() {
+    let dir = common::dir("primitives-box");
+    let offline = format!(
+        "--offline={}",
+        common::recording("verify-primitives.jsonl").display()
+    );
+    let run = common::run(&dir, &[&offline, "verify-primitives", "--shape=box"], &[]);
+    assert!(run.succeeded, "The run failed:\n{}", run.printed);
+    run.assert_printed(
+        "box           6000.000     6000.000   0.000%     2200.000     2200.000   0.000%",
+    );
+    assert!(!run.printed.contains("skipped"), "{}", run.printed);
+}
+
+#[test]
+fn refuses_primitives_which_cant_be_built() {
+    let dir = common::dir("primitives-unsupported");
+    for shape in ["sphere", "torus", "cone"] {
+        let shape_arg = format!("--shape={shape}");
+        // No recording, so anything sent would fail differently.
+        let run = common::run(&dir, &["verify-primitives", "--shape=box", &shape_arg], &[]);
+        assert!(!run.succeeded);
+        assert_eq!(run.exit_code, Some(2));
+        run.assert_printed(&format!(
+            "invalid value '{shape}' for '--shape <SHAPE>': Unsupported primitive {shape}: \
+             spheres, tori and cones need a revolve"
+        ));
+    }
+}
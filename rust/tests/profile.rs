@@ -0,0 +1,86 @@
+//! Plays back recordings (see --offline) of the sample profile extruded 5mm, to check --centered
+//! puts the solid half below its outline's plane and half above:
+//! - profile.jsonl: extruded upwards, from z = 0.
+//! - profile-centered.jsonl: with --centered, from a copy of the outline 2.5mm lower.
+
+mod common;
+
+use serde_json::Value;
+use std::{collections::HashMap, fs, path::Path};
+
+/// Extrude the sample profile, recording what's sent, and return what the run printed and the
+/// z extent of the solid it sent, from the bottom of the outline it extruded to its top.
+fn extrude(test: &str, recording: &str, args: &[&str]) -> (common::Run, (f64, f64)) {
+    let dir = common::dir(test);
+    let offline = format!("--offline={}", common::recording(recording).display());
+    let csv = Path::new(env!("CARGO_MANIFEST_DIR")).join("samples/profile.csv");
+    let csv = format!("--profile-csv={}", csv.display());
+    let run = common::run(
+        &dir,
+        &[
+            &[
+                offline.as_str(),
+                "--record=trace.jsonl",
+                "--output=model.png",
+                "profile",
+                &csv,
+                "--extrude-height=5",
+            ],
+            args,
+        ]
+        .concat(),
+        &[],
+    );
+    assert!(run.succeeded, "The run failed:\n{}", run.printed);
+    // Where each path's points are along Z, and how far the extruded one was extruded.
+    let mut heights: HashMap<String, Vec<f64>> = HashMap::new();
+    let mut extruded = None;
+    for line in fs::read_to_string(dir.join("trace.jsonl")).unwrap().lines() {
+        let entry: Value = serde_json::from_str(line).unwrap();
+        let Some(cmd) = entry.pointer("/sent/cmd") else {
+            continue;
+        };
+        let path = cmd["path"].as_str().unwrap_or_default().to_owned();
+        match cmd["type"].as_str().unwrap() {
+            "move_path_pen" => heights
+                .entry(path)
+                .or_default()
+                .push(cmd["to"]["z"].as_f64().unwrap()),
+            "extend_path" => heights
+                .entry(path)
+                .or_default()
+                .push(cmd["segment"]["end"]["z"].as_f64().unwrap()),
+            "extrude" => {
+                extruded = Some((
+                    cmd["target"].as_str().unwrap().to_owned(),
+                    cmd["distance"].as_f64().unwrap(),
+                ))
+            }
+            _ => {}
+        }
+    }
+    let (target, distance) = extruded.expect("Nothing was extruded");
+    let bottom = heights[&target].iter().copied().fold(f64::MAX, f64::min);
+    (run, (bottom, bottom + distance))
+}
+
+#[test]
+fn extrudes_a_profile_upwards_from_its_outline() {
+    let (run, extent) = extrude("profile-upwards", "profile.jsonl", &[]);
+    assert_eq!(extent, (0.0, 5.0));
+    run.assert_printed("z: 0 to 5 (extent 5)");
+}
+
+#[test]
+fn extrudes_a_centered_profile_either_side_of_its_outline() {
+    let (run, extent) = extrude(
+        "profile-centered",
+        "profile-centered.jsonl",
+        &["--centered"],
+    );
+    assert_eq!(extent, (-2.5, 2.5));
+    run.assert_printed("z: -2.5 to 2.5 (extent 5)");
+    // Only moved along Z: the outline is where it was.
+    run.assert_printed("x: 0 to 30 (extent 30)");
+    run.assert_printed("y: 0 to 25 (extent 25)");
+}
@@ -0,0 +1,56 @@
+//! Plays back a recording (see --offline) of a cube built away from the origin, with --at=30,40,50,
+//! and exported with --recenter origin, recenter.jsonl, to check the exported STL is where it says.
+
+mod common;
+
+use std::fs;
+
+/// The corners of the box around a binary STL's triangles.
+fn bounding_box(stl: &[u8]) -> ([f32; 3], [f32; 3]) {
+    let triangles = u32::from_le_bytes(stl[80..84].try_into().unwrap()) as usize;
+    assert_eq!(stl.len(), 84 + triangles * 50, "The STL isn't binary");
+    let (mut min, mut max) = ([f32::MAX; 3], [f32::MIN; 3]);
+    for triangle in stl[84..].chunks(50) {
+        // Each triangle is its normal, then its three corners, each three floats.
+        for corner in triangle[12..48].chunks(12) {
+            for (axis, value) in corner.chunks(4).enumerate() {
+                let value = f32::from_le_bytes(value.try_into().unwrap());
+                min[axis] = min[axis].min(value);
+                max[axis] = max[axis].max(value);
+            }
+        }
+    }
+    (min, max)
+}
+
+#[test]
+fn exports_the_model_centered_on_the_origin() {
+    let dir = common::dir("recenter-origin");
+    let offline = format!(
+        "--offline={}",
+        common::recording("recenter.jsonl").display()
+    );
+    let run = common::run(
+        &dir,
+        &[
+            &offline,
+            "--output=model.png",
+            "--export=model.stl",
+            "--recenter=origin",
+            "--at=30,40,50",
+            "cube",
+        ],
+        &[],
+    );
+    assert!(run.succeeded, "The run failed:\n{}", run.printed);
+    let (min, max) = bounding_box(&fs::read(dir.join("model.stl")).unwrap());
+    for axis in 0..3 {
+        assert!(
+            (min[axis] + 10.0).abs() < 1e-3 && (max[axis] - 10.0).abs() < 1e-3,
+            "The cube goes from {min:?} to {max:?}, not -10 to 10 along each axis"
+        );
+    }
+    // It's checked when it's exported too.
+    run.assert_printed("from (-10.000, -10.000, -10.000) to (10.000, 10.000, 10.000)");
+    assert!(!run.printed.contains("should be centered on the origin"));
+}
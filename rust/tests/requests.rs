@@ -0,0 +1,280 @@
+//! Golden files of the requests each shape sends, so a new version of the kittycad crate which
+//! renames a field or changes an enum shows up as a diff in review, instead of as the API refusing
+//! commands which used to work.
+//!
+//! Each shape is played back from its recording in tests/recordings (see --offline), with
+//! --deterministic-ids so the IDs are the same every run, and what it sent (see --record) is compared
+//! with tests/fixtures/requests/<shape>.json. If a change to what's sent is meant, write the new
+//! files with `UPDATE_FIXTURES=1 cargo test --test requests`, and check their diff.
+//!
+//! The recordings' responses are only what each shape needs to get to the end, e.g. a solid with one
+//! flat face and one side, not what the API would say, so only what's sent is checked. A shape which
+//! sends different kinds of commands needs its recording brought up to date: playing it back fails,
+//! saying where the run and the recording differ.
+//!
+//! The cube is drawn with --at too, to check every point it sends is moved by it.
+
+mod common;
+
+use serde_json::Value;
+use std::{fs, path::Path};
+
+#[test]
+fn cube() {
+    check("cube", &[]);
+}
+
+#[test]
+fn l_bracket() {
+    check("l-bracket", &[]);
+}
+
+#[test]
+fn path_info() {
+    check("path-info", &[]);
+}
+
+#[test]
+fn plate() {
+    check("plate", &[]);
+}
+
+#[test]
+fn measure() {
+    check("measure", &[]);
+}
+
+#[test]
+fn transform() {
+    check("transform", &[]);
+}
+
+#[test]
+fn linear_pattern() {
+    check("linear-pattern", &[]);
+}
+
+#[test]
+fn bolt_circle() {
+    check("bolt-circle", &[]);
+}
+
+#[test]
+fn boss() {
+    check("boss", &[]);
+}
+
+#[test]
+fn cross() {
+    check("cross", &[]);
+}
+
+#[test]
+fn highlight() {
+    check("highlight", &[]);
+}
+
+#[test]
+fn remove() {
+    check("remove", &[]);
+}
+
+#[test]
+fn profile() {
+    check(
+        "profile",
+        &[
+            &format!("--profile-csv={}", sample("profile.csv")),
+            "--extrude-height=5",
+        ],
+    );
+}
+
+#[test]
+fn wall() {
+    check("wall", &[&format!("--path-csv={}", sample("wall.csv"))]);
+}
+
+#[test]
+fn svg_extrude() {
+    check("svg-extrude", &[&sample("plaque.svg")]);
+}
+
+/// A file in samples/.
+fn sample(name: &str) -> String {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("samples")
+        .join(name)
+        .display()
+        .to_string()
+}
+
+/// Draw a shape from its recording, and compare what it sent with its golden file.
+fn check(shape: &str, args: &[&str]) {
+    let sent = sent(&format!("requests-{shape}"), &[], shape, args);
+
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/requests")
+        .join(format!("{shape}.json"));
+    if std::env::var_os("UPDATE_FIXTURES").is_some_and(|update| update == "1") {
+        fs::create_dir_all(fixture.parent().unwrap()).unwrap();
+        let json = serde_json::to_string_pretty(&sent).unwrap();
+        fs::write(&fixture, json + "\n").unwrap();
+        return;
+    }
+    let Ok(expected) = fs::read_to_string(&fixture) else {
+        panic!(
+            "There's no {}. Write it with UPDATE_FIXTURES=1",
+            fixture.display()
+        );
+    };
+    let expected: Vec<Value> = serde_json::from_str(&expected).unwrap();
+    let differences = differences(&expected, &sent);
+    assert!(
+        differences.is_empty(),
+        "{shape} sent different requests from {}:\n{}\n\nIf that's meant, write the file again with UPDATE_FIXTURES=1",
+        fixture.display(),
+        differences.join("\n")
+    );
+}
+
+/// Draw a shape from its recording, in a directory of the test's own, with `options` before the
+/// shape and `args` after it, and return what it sent.
+fn sent(test: &str, options: &[&str], shape: &str, args: &[&str]) -> Vec<Value> {
+    let dir = common::dir(test);
+    let offline = format!(
+        "--offline={}",
+        common::recording(&format!("{shape}.jsonl")).display()
+    );
+    let run = common::run(
+        &dir,
+        &[
+            &[
+                offline.as_str(),
+                "--deterministic-ids",
+                "--record=sent.jsonl",
+                "--output=model.png",
+            ],
+            options,
+            &[shape],
+            args,
+        ]
+        .concat(),
+        &[],
+    );
+    assert!(run.succeeded, "{shape} failed:\n{}", run.printed);
+    fs::read_to_string(dir.join("sent.jsonl"))
+        .unwrap()
+        .lines()
+        .filter_map(|line| {
+            let mut entry: Value = serde_json::from_str(line).unwrap();
+            entry.get_mut("sent").map(Value::take)
+        })
+        .collect()
+}
+
+/// The points a shape's paths went through, in the order they were sent.
+fn path_points(sent: &[Value]) -> Vec<[f64; 3]> {
+    sent.iter()
+        .filter_map(|request| match kind(request) {
+            "move_path_pen" => request.pointer("/cmd/to"),
+            "extend_path" => request.pointer("/cmd/segment/end"),
+            _ => None,
+        })
+        .map(|point| ["x", "y", "z"].map(|axis| point[axis].as_f64().unwrap()))
+        .collect()
+}
+
+/// Check the cube's paths, built with `options`, are its paths at the origin moved by `by`.
+fn check_moved(test: &str, options: &[&str], by: [f64; 3]) {
+    let at_origin = path_points(&sent(&format!("{test}-origin"), &[], "cube", &[]));
+    let moved = path_points(&sent(test, options, "cube", &[]));
+    assert_eq!(moved.len(), at_origin.len());
+    assert!(!moved.is_empty());
+    for (moved, at_origin) in moved.iter().zip(&at_origin) {
+        let expected = [0, 1, 2].map(|axis| at_origin[axis] + by[axis]);
+        assert_eq!(
+            moved, &expected,
+            "{at_origin:?} should have moved by {by:?}"
+        );
+    }
+}
+
+#[test]
+fn moves_every_point_by_at() {
+    check_moved("requests-at", &["--at=30,40,50"], [30.0, 40.0, 50.0]);
+}
+
+#[test]
+fn moves_every_point_by_at_in_the_input_s_coordinates() {
+    // With Y up, forward is -Z, so 50 along Z is 50 back, along the API's -Y.
+    check_moved(
+        "requests-at-y-up",
+        &["--at=30,40,50", "--input-up=y"],
+        [30.0, -50.0, 40.0],
+    );
+}
+
+/// How the requests sent differ from those expected, one line per difference, e.g.
+/// "request 3 (extend_path): /cmd/segment/end/x is 5, not 10".
+fn differences(expected: &[Value], sent: &[Value]) -> Vec<String> {
+    let mut differences = Vec::new();
+    for (i, (expected, sent)) in expected.iter().zip(sent).enumerate() {
+        let mut found = Vec::new();
+        compare("", expected, sent, &mut found);
+        differences.extend(
+            found
+                .into_iter()
+                .map(|difference| format!("request {} ({}): {difference}", i + 1, kind(sent))),
+        );
+    }
+    for (i, extra) in sent.iter().enumerate().skip(expected.len()) {
+        differences.push(format!(
+            "request {} ({}) wasn't expected",
+            i + 1,
+            kind(extra)
+        ));
+    }
+    for (i, missing) in expected.iter().enumerate().skip(sent.len()) {
+        differences.push(format!("request {} ({}) wasn't sent", i + 1, kind(missing)));
+    }
+    differences
+}
+
+/// The kind of command a request is, e.g. "extend_path".
+fn kind(request: &Value) -> &str {
+    request
+        .pointer("/cmd/type")
+        .or_else(|| request.get("type"))
+        .and_then(Value::as_str)
+        .unwrap_or("unknown")
+}
+
+/// Find where two values differ, by the JSON pointer to each difference.
+fn compare(at: &str, expected: &Value, sent: &Value, found: &mut Vec<String>) {
+    match (expected, sent) {
+        (Value::Object(expected), Value::Object(sent)) => {
+            for (key, value) in expected {
+                match sent.get(key) {
+                    Some(sent) => compare(&format!("{at}/{key}"), value, sent, found),
+                    None => found.push(format!("{at}/{key} is missing (it was {value})")),
+                }
+            }
+            for (key, value) in sent {
+                if !expected.contains_key(key) {
+                    found.push(format!("{at}/{key} is new (it's {value})"));
+                }
+            }
+        }
+        (Value::Array(expected), Value::Array(sent)) if expected.len() == sent.len() => {
+            for (i, (expected, sent)) in expected.iter().zip(sent).enumerate() {
+                compare(&format!("{at}/{i}"), expected, sent, found);
+            }
+        }
+        _ if expected != sent => {
+            let at = if at.is_empty() { "/" } else { at };
+            found.push(format!("{at} is {sent}, not {expected}"));
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,102 @@
+//! Plays back a recording (see --offline) of a scene with three groups of parts, scene-groups.jsonl,
+//! to check which parts each of its snapshots shows with --hide-group and --only-group.
+//! The scene, tests/fixtures/scenes/groups.json, has a frame, a panel, and two fasteners, one of them
+//! invisible, and is exploded, so it's snapshotted twice.
+
+mod common;
+
+use serde_json::Value;
+use std::{collections::BTreeSet, fs, path::Path};
+
+/// For each snapshot a run took, from its --record trace, the objects which weren't hidden,
+/// by the order they were built in.
+fn shown_per_snapshot(trace: &Path) -> Vec<BTreeSet<usize>> {
+    let mut built = Vec::new();
+    let mut hidden = BTreeSet::new();
+    let mut removed = BTreeSet::new();
+    let mut snapshots = Vec::new();
+    for line in fs::read_to_string(trace).unwrap().lines() {
+        let entry: Value = serde_json::from_str(line).unwrap();
+        let Some(sent) = entry.get("sent") else {
+            continue;
+        };
+        let cmd = &sent["cmd"];
+        let object = |id: &Value| built.iter().position(|b| b == id.as_str().unwrap());
+        match cmd["type"].as_str().unwrap() {
+            "start_path" => built.push(sent["cmd_id"].as_str().unwrap().to_owned()),
+            "object_visible" => {
+                let object = object(&cmd["object_id"]).unwrap();
+                if cmd["hidden"].as_bool().unwrap() {
+                    hidden.insert(object);
+                } else {
+                    hidden.remove(&object);
+                }
+            }
+            "remove_scene_objects" => removed.extend(
+                cmd["object_ids"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(object)
+                    .map(Option::unwrap),
+            ),
+            "take_snapshot" => snapshots.push(
+                (0..built.len())
+                    .filter(|o| !hidden.contains(o) && !removed.contains(o))
+                    .collect(),
+            ),
+            _ => {}
+        }
+    }
+    snapshots
+}
+
+/// The objects built in the order the scene builds them: its four parts, then, to explode it,
+/// copies of the frame and the visible fastener, in their place.
+const FRAME: usize = 0;
+const PANEL: usize = 1;
+const BOLT: usize = 2;
+const SPARE: usize = 3;
+const EXPLODED_FRAME: usize = 4;
+const EXPLODED_BOLT: usize = 5;
+
+fn check_hides_the_panel(test: &str, groups: &str) {
+    let dir = common::dir(test);
+    let scene = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/scenes/groups.json");
+    let offline = format!(
+        "--offline={}",
+        common::recording("scene-groups.jsonl").display()
+    );
+    let run = common::run(
+        &dir,
+        &[
+            "--output=scene.png",
+            groups,
+            "--record=trace.jsonl",
+            &offline,
+            "scene",
+            scene.to_str().unwrap(),
+        ],
+        &[],
+    );
+    assert!(run.succeeded, "The run failed:\n{}", run.printed);
+    run.assert_printed("Hid 2 objects of parts which aren't shown");
+    assert_eq!(
+        shown_per_snapshot(&dir.join("trace.jsonl")),
+        [
+            BTreeSet::from([FRAME, BOLT]),
+            BTreeSet::from([EXPLODED_FRAME, EXPLODED_BOLT]),
+        ],
+        "The panel ({PANEL}) and the invisible fastener ({SPARE}) should be hidden from every snapshot"
+    );
+}
+
+#[test]
+fn hides_a_hidden_groups_parts_from_every_snapshot() {
+    check_hides_the_panel("scene-hide-group", "--hide-group=panels");
+}
+
+#[test]
+fn hides_the_parts_of_groups_which_arent_the_only_ones_shown() {
+    check_hides_the_panel("scene-only-group", "--only-group=frame,fasteners");
+}
@@ -0,0 +1,115 @@
+//! Runs against a fake API (see tests/common) which only accepts some tokens, to check a run fetches
+//! a new token from its --token-command when the API refuses one, and that no token ever ends up in
+//! what a run prints or the files it leaves behind: recordings, sidecars or debug payloads.
+//!
+//! The fake API is reached through a hook only in builds with the "test-hooks" feature, so run these
+//! with `cargo test --features test-hooks`. The --token-command runs in `sh`, so they're Unix only.
+#![cfg(all(feature = "test-hooks", unix))]
+
+mod common;
+
+use common::{FakeApi, Run};
+use std::{fs, path::Path};
+
+/// A --token-command which prints a different token each time it's run: `tokens[0]` the first time,
+/// and so on. It reads them from files outside `dir`, so the command itself doesn't have them in it.
+fn token_command(dir: &Path, tokens: &[&str]) -> String {
+    let tokens_dir = dir.with_extension("tokens");
+    let _ = fs::remove_dir_all(&tokens_dir);
+    fs::create_dir_all(&tokens_dir).unwrap();
+    for (i, token) in tokens.iter().enumerate() {
+        fs::write(tokens_dir.join(format!("token-{}", i + 1)), token).unwrap();
+    }
+    let count = tokens_dir.join("count");
+    format!(
+        "n=$(( $(cat '{count}' 2>/dev/null || echo 0) + 1 )); echo $n > '{count}'; cat '{dir}/token-'$n",
+        count = count.display(),
+        dir = tokens_dir.display(),
+    )
+}
+
+/// Build a cube against `api`, recording the session and keeping everything a run can keep.
+fn run(dir: &Path, api: &FakeApi, args: &[&str], env: &[(&str, &str)]) -> Run {
+    let inject = format!("api={}", api.url);
+    let args = [
+        &[
+            "--output=model.png",
+            "--record=trace.jsonl",
+            "--debug-dir=kept",
+            "--no-cache",
+        ],
+        args,
+    ]
+    .concat();
+    common::run(
+        dir,
+        &args,
+        &[env, &[("KITTYCAD_INJECT", inject.as_str())]].concat(),
+    )
+}
+
+/// Check `token` isn't anywhere the run put things.
+fn assert_kept_secret(run: &Run, token: &str) {
+    assert!(
+        !run.printed.contains(token),
+        "The run printed the token {token}:\n{}",
+        run.printed
+    );
+    let files = run.files_containing(token);
+    assert!(files.is_empty(), "The token {token} is in {files:?}");
+}
+
+#[test]
+fn fetches_a_new_token_when_the_api_refuses_one() {
+    let (stale, fresh) = ("stale-3f9a1c", "fresh-8d20b4");
+    let dir = common::dir("tokens-refresh");
+    let api = FakeApi::start("cube.jsonl", &[fresh]);
+    let command = format!("--token-command={}", token_command(&dir, &[stale, fresh]));
+    let run = run(&dir, &api, &[&command], &[]);
+    assert!(run.succeeded, "The run failed:\n{}", run.printed);
+    run.assert_printed(
+        "Fetched a new API token with the --token-command, because the last one was refused",
+    );
+    assert_eq!(api.tokens(), [stale, fresh]);
+    assert!(dir.join("model.png").exists());
+    assert!(dir.join("trace.jsonl").exists());
+    assert_kept_secret(&run, stale);
+    assert_kept_secret(&run, fresh);
+}
+
+#[test]
+fn fails_if_the_api_refuses_the_new_token_too() {
+    let (stale, staler) = ("stale-51c7e0", "staler-a4b9d2");
+    let dir = common::dir("tokens-refused");
+    let api = FakeApi::start("cube.jsonl", &[]);
+    let command = format!("--token-command={}", token_command(&dir, &[stale, staler]));
+    let run = run(&dir, &api, &[&command], &[]);
+    assert!(!run.succeeded);
+    assert_eq!(run.exit_code, Some(77));
+    run.assert_printed("The API refused the token again, after a new one was fetched");
+    assert_eq!(api.tokens(), [stale, staler]);
+    assert_kept_secret(&run, stale);
+    assert_kept_secret(&run, staler);
+}
+
+#[test]
+fn keeps_the_token_from_the_environment_secret() {
+    let token = "env-token-6e13f7";
+    let dir = common::dir("tokens-env");
+    let api = FakeApi::start("cube.jsonl", &[token]);
+    let run = run(&dir, &api, &[], &[("KITTYCAD_API_TOKEN", token)]);
+    assert!(run.succeeded, "The run failed:\n{}", run.printed);
+    assert_eq!(api.tokens(), [token]);
+    assert_kept_secret(&run, token);
+}
+
+#[test]
+fn keeps_a_refused_token_from_the_environment_secret() {
+    let token = "env-token-0b5d98";
+    let dir = common::dir("tokens-env-refused");
+    let api = FakeApi::start("cube.jsonl", &[]);
+    let run = run(&dir, &api, &[], &[("KITTYCAD_API_TOKEN", token)]);
+    assert!(!run.succeeded);
+    run.assert_printed("The API didn't accept the token");
+    assert_kept_secret(&run, token);
+}
@@ -0,0 +1,80 @@
+//! Plays back recordings (see --offline) of a four-frame turntable of a cube which fails partway,
+//! to check --resume-frames carries on from where it stopped:
+//! - turntable-failed.jsonl: the API refuses the snapshot of the third frame.
+//! - turntable-resumed.jsonl: the run after it, with --resume-frames, which only takes the last two.
+
+mod common;
+
+use serde_json::Value;
+use std::fs;
+
+/// The types of the commands a run sent, from its --record trace.
+fn sent(trace: &std::path::Path) -> Vec<String> {
+    fs::read_to_string(trace)
+        .unwrap()
+        .lines()
+        .filter_map(|line| {
+            let entry: Value = serde_json::from_str(line).unwrap();
+            Some(entry.get("sent")?["cmd"]["type"].as_str()?.to_owned())
+        })
+        .collect()
+}
+
+#[test]
+fn resumes_a_turntable_from_the_frame_it_failed_on() {
+    let dir = common::dir("turntable-resumed");
+    let offline = |recording: &str| format!("--offline={}", common::recording(recording).display());
+    let args = [
+        "--output=model.png",
+        "--turntable=4",
+        "--record=trace.jsonl",
+    ];
+
+    let failed = common::run(
+        &dir,
+        &[&args[..], &[offline("turntable-failed.jsonl").as_str()]].concat(),
+        &[],
+    );
+    assert!(
+        !failed.succeeded,
+        "The run didn't fail:\n{}",
+        failed.printed
+    );
+    failed.assert_printed("The renderer is unavailable");
+    for frame in ["model-000.png", "model-001.png", "model.png.progress.json"] {
+        assert!(dir.join(frame).exists(), "{frame} wasn't saved");
+    }
+    assert!(!dir.join("model-002.png").exists());
+    let progress: Value =
+        serde_json::from_str(&fs::read_to_string(dir.join("model.png.progress.json")).unwrap())
+            .unwrap();
+    assert_eq!(progress["frames"], 4);
+    assert_eq!(progress["completed"].as_array().unwrap().len(), 2);
+
+    let resumed = common::run(
+        &dir,
+        &[
+            &args[..],
+            &[
+                "--resume-frames",
+                offline("turntable-resumed.jsonl").as_str(),
+            ],
+        ]
+        .concat(),
+        &[],
+    );
+    assert!(resumed.succeeded, "The run failed:\n{}", resumed.printed);
+    resumed.assert_printed("Resuming the turntable from frame 3 of 4");
+    resumed.assert_printed("Saved all 4 frames of the turntable");
+    // Only the frames which weren't saved were asked for again.
+    let snapshots = sent(&dir.join("trace.jsonl"))
+        .into_iter()
+        .filter(|cmd| cmd == "take_snapshot")
+        .count();
+    assert_eq!(snapshots, 2);
+    for frame in 0..4 {
+        assert!(dir.join(format!("model-{frame:03}.png")).exists());
+    }
+    // It's finished, so the progress is gone.
+    assert!(!dir.join("model.png.progress.json").exists());
+}